@@ -0,0 +1,91 @@
+//! Looking up single outputs of a 512x512 [DummyRouter]: through the default
+//! [MatrixRouter::get_route], which copies all routes first, and through its override.
+//!
+//! Run with `cargo bench --bench get_route`.
+
+use anyhow::Result;
+use futures_core::stream::BoxStream;
+use omnimatrix::matrix::*;
+use std::{hint::black_box, time::Instant};
+
+const SIZE: u32 = 512;
+const ROUNDS: u32 = 200;
+
+/// Passes everything through, but leaves [MatrixRouter::get_route] at its default.
+struct Plain(DummyRouter);
+
+impl MatrixRouter for Plain {
+    async fn is_alive(&self) -> Result<bool> {
+        self.0.is_alive().await
+    }
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.0.get_router_info().await
+    }
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        self.0.get_matrix_info(index).await
+    }
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.0.get_input_labels(index).await
+    }
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.0.get_output_labels(index).await
+    }
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.0.update_input_labels(index, changed).await
+    }
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.0.update_output_labels(index, changed).await
+    }
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.0.get_routes(index).await
+    }
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.0.update_routes(index, changes).await
+    }
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        self.0.event_stream().await
+    }
+}
+
+fn main() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let dummy = DummyRouter::with_config(1, SIZE as usize, SIZE as usize);
+    let plain = Plain(dummy.clone());
+    rt.block_on(async {
+        let patches = (0..SIZE)
+            .map(|o| RouterPatch {
+                from_input: Some(SIZE - 1 - o),
+                to_output: o,
+            })
+            .collect();
+        dummy.update_routes(0, patches).await.unwrap();
+        for o in 0..SIZE {
+            assert_eq!(
+                plain.get_route(0, o).await.unwrap(),
+                dummy.get_route(0, o).await.unwrap(),
+                "routes differ"
+            );
+        }
+
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            for o in 0..SIZE {
+                black_box(plain.get_route(0, black_box(o)).await.unwrap());
+            }
+        }
+        let default = start.elapsed() / (ROUNDS * SIZE);
+
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            for o in 0..SIZE {
+                black_box(dummy.get_route(0, black_box(o)).await.unwrap());
+            }
+        }
+        let direct = start.elapsed() / (ROUNDS * SIZE);
+
+        println!("default get_route: {:?} per lookup", default);
+        println!("DummyRouter::get_route: {:?} per lookup", direct);
+    });
+}
@@ -0,0 +1,55 @@
+//! Serializing a 288x288 routing dump: through `core::fmt`, which formats numbers the slow
+//! way, through `std::io::Write`, and into a reused buffer.
+//!
+//! Run with `cargo bench -p videohub`.
+
+use bytes::BytesMut;
+use std::{hint::black_box, time::Instant};
+use videohub::{Route, VideohubMessage};
+
+const ROUNDS: u32 = 20_000;
+
+fn main() {
+    let msg = VideohubMessage::VideoOutputRouting(
+        (0..288)
+            .map(|o| Route {
+                from_input: 287 - o,
+                to_output: o,
+            })
+            .collect(),
+    );
+
+    let mut expected = Vec::new();
+    msg.write_serialized(&mut expected).unwrap();
+    let mut buf = BytesMut::new();
+    msg.encode_into(&mut buf);
+    assert_eq!(&buf[..], &expected[..], "output differs");
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let mut out = String::new();
+        black_box(&msg).write_serialized_fmt(&mut out).unwrap();
+        black_box(out);
+    }
+    let fmt = start.elapsed() / ROUNDS;
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let mut out = Vec::new();
+        black_box(&msg).write_serialized(&mut out).unwrap();
+        black_box(out);
+    }
+    let io = start.elapsed() / ROUNDS;
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        buf.clear();
+        black_box(&msg).encode_into(&mut buf);
+        black_box(&buf);
+    }
+    let reused = start.elapsed() / ROUNDS;
+
+    println!("write_serialized_fmt: {:?} per block", fmt);
+    println!("write_serialized: {:?} per block", io);
+    println!("encode_into:      {:?} per block", reused);
+}
@@ -0,0 +1,391 @@
+// Async client for Videohub peers.
+// Replies to commands carry no reference to them, the n-th ACK or NAK answers the n-th
+// request still waiting for one. The connection task keeps that queue.
+
+use crate::{Label, ProtocolProfile, Route, VideohubCodec, VideohubMessage};
+use futures_util::{SinkExt, Stream, StreamExt};
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpStream, ToSocketAddrs},
+    select,
+    sync::{mpsc, oneshot},
+};
+use tokio_util::codec::Framed;
+
+/// How long requests wait for their ACK or NAK, by default.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Why a request to the peer didn't succeed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClientError {
+    /// The peer refused the request.
+    Nak,
+    /// No ACK or NAK arrived in time.
+    Timeout,
+    /// The connection is gone.
+    Closed,
+    /// The peer's protocol version lacks the request, like pings before 2.7.
+    Unsupported,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Nak => f.write_str("request refused with NAK"),
+            ClientError::Timeout => f.write_str("no reply to request in time"),
+            ClientError::Closed => f.write_str("connection closed"),
+            ClientError::Unsupported => f.write_str("request not supported by the peer"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+enum Command {
+    /// Send without expecting a reply.
+    Send(VideohubMessage),
+    /// Send and report whether the next ACK or NAK is an ACK.
+    Request(VideohubMessage, oneshot::Sender<Result<(), ClientError>>),
+}
+
+/// Handle of a connection to a Videohub, or anything speaking its protocol.
+///
+/// Cloning gives another handle to the same connection, which is closed once all
+/// handles are gone. Everything the peer sends shows up in the [VideohubEvents]
+/// created along with the client.
+#[derive(Clone, Debug)]
+pub struct VideohubClient {
+    cmd_tx: mpsc::UnboundedSender<Command>,
+    timeout: Duration,
+}
+
+/// Messages received by a [VideohubClient], ACK and NAK included.
+///
+/// Ends once the connection is closed.
+#[derive(Debug)]
+pub struct VideohubEvents {
+    rx: mpsc::UnboundedReceiver<VideohubMessage>,
+}
+
+impl VideohubEvents {
+    /// Next received message, `None` once the connection is closed.
+    pub async fn recv(&mut self) -> Option<VideohubMessage> {
+        self.rx.recv().await
+    }
+}
+
+impl Stream for VideohubEvents {
+    type Item = VideohubMessage;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl VideohubClient {
+    /// Connect over TCP. The peer's initial dump arrives as events.
+    pub async fn connect(addr: impl ToSocketAddrs) -> io::Result<(Self, VideohubEvents)> {
+        let socket = TcpStream::connect(addr).await?;
+        Ok(Self::new(socket))
+    }
+
+    /// Talk over an established connection.
+    ///
+    /// Spawns the connection task, so this has to be called within a Tokio runtime.
+    pub fn new<T>(io: T) -> (Self, VideohubEvents)
+    where
+        T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        Self::from_framed(Framed::new(io, VideohubCodec::default()))
+    }
+
+    /// Like [VideohubClient::new], but carrying on where `framed` left off,
+    /// e.g. after reading the first blocks of the dump directly.
+    pub fn from_framed<T>(framed: Framed<T, VideohubCodec>) -> (Self, VideohubEvents)
+    where
+        T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        Self::spawn(framed, None)
+    }
+
+    /// Like [VideohubClient::from_framed], for when the preamble was read already.
+    /// `version` is the protocol version it announced.
+    pub fn from_framed_with_version<T>(
+        framed: Framed<T, VideohubCodec>,
+        version: &str,
+    ) -> (Self, VideohubEvents)
+    where
+        T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        Self::spawn(framed, ProtocolProfile::from_version(version))
+    }
+
+    fn spawn<T>(
+        framed: Framed<T, VideohubCodec>,
+        profile: Option<ProtocolProfile>,
+    ) -> (Self, VideohubEvents)
+    where
+        T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(framed, profile, cmd_rx, event_tx));
+        let client = Self {
+            cmd_tx,
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+        };
+        (client, VideohubEvents { rx: event_rx })
+    }
+
+    /// Set how long requests wait for their ACK or NAK, defaults to 5s.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Whether the connection is gone.
+    pub fn is_closed(&self) -> bool {
+        self.cmd_tx.is_closed()
+    }
+
+    /// Send `msg` without waiting for a reply.
+    pub fn send(&self, msg: VideohubMessage) -> Result<(), ClientError> {
+        self.cmd_tx
+            .send(Command::Send(msg))
+            .map_err(|_| ClientError::Closed)
+    }
+
+    /// Send `msg`, resolving once the peer acknowledged it.
+    ///
+    /// The message is queued right away, so requests go out in the order this is called
+    /// in, even if their replies are awaited elsewhere. A request that timed out still
+    /// takes the next reply, keeping later ones matched up.
+    pub fn request(
+        &self,
+        msg: VideohubMessage,
+    ) -> impl Future<Output = Result<(), ClientError>> + Send + 'static {
+        let (tx, rx) = oneshot::channel();
+        let queued = self.cmd_tx.send(Command::Request(msg, tx));
+        let timeout = self.timeout;
+        async move {
+            queued.map_err(|_| ClientError::Closed)?;
+            match tokio::time::timeout(timeout, rx).await {
+                Ok(Ok(res)) => res,
+                Ok(Err(_)) => Err(ClientError::Closed),
+                Err(_) => Err(ClientError::Timeout),
+            }
+        }
+    }
+
+    /// Check the peer is responsive.
+    ///
+    /// Hubs before protocol 2.7 don't answer pings, so once the peer's preamble announced
+    /// such a version, or [VideohubClient::from_framed_with_version] was given one, this
+    /// fails with [ClientError::Unsupported] without sending anything.
+    pub fn ping(&self) -> impl Future<Output = Result<(), ClientError>> + Send + 'static {
+        self.request(VideohubMessage::Ping)
+    }
+
+    /// Route `input` to `output`.
+    pub fn set_route(
+        &self,
+        output: u32,
+        input: u32,
+    ) -> impl Future<Output = Result<(), ClientError>> + Send + 'static {
+        let route = Route {
+            from_input: input,
+            to_output: output,
+        };
+        self.request(VideohubMessage::VideoOutputRouting(vec![route]))
+    }
+
+    /// Rename input `id`.
+    pub fn set_input_label(
+        &self,
+        id: u32,
+        name: &str,
+    ) -> impl Future<Output = Result<(), ClientError>> + Send + 'static {
+        self.request(VideohubMessage::InputLabels(vec![Label::from((id, name))]))
+    }
+
+    /// Rename output `id`.
+    pub fn set_output_label(
+        &self,
+        id: u32,
+        name: &str,
+    ) -> impl Future<Output = Result<(), ClientError>> + Send + 'static {
+        self.request(VideohubMessage::OutputLabels(vec![Label::from((id, name))]))
+    }
+
+    /// Ask for labels, routing and locks, the answers arrive as events.
+    pub fn request_dump(&self) -> Result<(), ClientError> {
+        for msg in [
+            VideohubMessage::InputLabels(vec![]),
+            VideohubMessage::OutputLabels(vec![]),
+            VideohubMessage::VideoOutputRouting(vec![]),
+            VideohubMessage::VideoOutputLocks(vec![]),
+        ] {
+            self.send(msg)?;
+        }
+        Ok(())
+    }
+}
+
+/// The connection task, until either side is gone.
+async fn run<T>(
+    framed: Framed<T, VideohubCodec>,
+    mut profile: Option<ProtocolProfile>,
+    mut cmd_rx: mpsc::UnboundedReceiver<Command>,
+    event_tx: mpsc::UnboundedSender<VideohubMessage>,
+) where
+    T: AsyncRead + AsyncWrite + Send + Unpin,
+{
+    let mut pending: VecDeque<oneshot::Sender<Result<(), ClientError>>> = VecDeque::new();
+    // Learned from the preamble if not known yet, until then everything is sent.
+    let (mut sink, mut stream) = framed.split();
+    loop {
+        select! {
+            cmd = cmd_rx.recv() => {
+                let msg = match cmd {
+                    Some(Command::Send(msg)) => msg,
+                    Some(Command::Request(VideohubMessage::Ping, resp))
+                        if profile.is_some_and(|p| !p.has_ping()) =>
+                    {
+                        // It would never be answered and take the reply of the next request.
+                        let _ = resp.send(Err(ClientError::Unsupported));
+                        continue;
+                    }
+                    Some(Command::Request(msg, resp)) => {
+                        pending.push_back(resp);
+                        msg
+                    }
+                    None => return,
+                };
+                if let Err(e) = sink.send(msg).await {
+                    tracing::error!(error = ?e, "Failed to send to Videohub peer");
+                    return;
+                }
+            }
+            frame = stream.next() => {
+                let msg = match frame {
+                    Some(Ok(msg)) => msg,
+                    Some(Err(e)) => {
+                        tracing::error!(error = ?e, "Videohub Codec encountered error");
+                        return;
+                    }
+                    None => return,
+                };
+                match &msg {
+                    VideohubMessage::Preamble(preamble) => {
+                        profile = ProtocolProfile::from_version(&preamble.version);
+                    }
+                    VideohubMessage::ACK | VideohubMessage::NAK => {
+                        if let Some(resp) = pending.pop_front() {
+                            let res = if msg == VideohubMessage::ACK {
+                                Ok(())
+                            } else {
+                                Err(ClientError::Nak)
+                            };
+                            let _ = resp.send(res);
+                        }
+                    }
+                    _ => {}
+                }
+                let _ = event_tx.send(msg);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, DuplexStream};
+
+    /// A client and the peer's end of its connection.
+    fn pair() -> (
+        VideohubClient,
+        VideohubEvents,
+        Framed<DuplexStream, VideohubCodec>,
+    ) {
+        let (a, b) = duplex(4096);
+        let (client, events) = VideohubClient::new(a);
+        (client, events, Framed::new(b, VideohubCodec::default()))
+    }
+
+    #[tokio::test]
+    async fn replies_in_order() {
+        let (client, mut events, mut peer) = pair();
+        let first = client.set_route(0, 1);
+        let second = client.set_input_label(2, "Cam 3");
+        let (first, second, ()) = tokio::join!(first, second, async {
+            let msg = peer.next().await.unwrap().unwrap();
+            assert!(matches!(msg, VideohubMessage::VideoOutputRouting(_)));
+            let msg = peer.next().await.unwrap().unwrap();
+            assert!(matches!(msg, VideohubMessage::InputLabels(_)));
+            peer.send(VideohubMessage::NAK).await.unwrap();
+            peer.send(VideohubMessage::ACK).await.unwrap();
+        });
+        assert_eq!(first, Err(ClientError::Nak));
+        assert_eq!(second, Ok(()));
+        assert_eq!(events.recv().await, Some(VideohubMessage::NAK));
+        assert_eq!(events.recv().await, Some(VideohubMessage::ACK));
+    }
+
+    #[tokio::test]
+    async fn timeout_keeps_matching() {
+        let (client, _events, mut peer) = pair();
+        let client = client.with_timeout(Duration::from_millis(20));
+        assert_eq!(client.ping().await, Err(ClientError::Timeout));
+
+        // The late reply goes to the timed out ping, not the next request.
+        let ping = client.ping();
+        peer.next().await.unwrap().unwrap();
+        peer.next().await.unwrap().unwrap();
+        peer.send(VideohubMessage::ACK).await.unwrap();
+        peer.send(VideohubMessage::NAK).await.unwrap();
+        assert_eq!(ping.await, Err(ClientError::Nak));
+    }
+
+    #[tokio::test]
+    async fn no_pings_before_2_7() {
+        let (client, mut events, mut peer) = pair();
+        peer.send(VideohubMessage::Preamble(crate::Preamble {
+            version: "2.4".into(),
+        }))
+        .await
+        .unwrap();
+        assert!(matches!(
+            events.recv().await,
+            Some(VideohubMessage::Preamble(_))
+        ));
+
+        // The ping isn't sent, so the ACK belongs to the route.
+        assert_eq!(client.ping().await, Err(ClientError::Unsupported));
+        let route = client.set_route(0, 1);
+        let msg = peer.next().await.unwrap().unwrap();
+        assert!(matches!(msg, VideohubMessage::VideoOutputRouting(_)));
+        peer.send(VideohubMessage::ACK).await.unwrap();
+        assert_eq!(route.await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn closed() {
+        let (client, mut events, peer) = pair();
+        let ping = client.ping();
+        drop(peer);
+        assert_eq!(ping.await, Err(ClientError::Closed));
+        assert_eq!(events.recv().await, None);
+        assert!(client.is_closed());
+        assert_eq!(client.request_dump(), Err(ClientError::Closed));
+    }
+}
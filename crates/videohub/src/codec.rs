@@ -1,11 +1,18 @@
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
-use super::VideohubMessage;
+use super::{MessageParseError, ParseOptions, VideohubMessage};
+
+/// Raw bytes of a bad block included in decode errors, at most.
+const ERROR_CONTEXT_LEN: usize = 80;
 
 /// A `tokio_util` Codec for parsing and serializing Videohub protocol messages.
+///
+/// Decodes with lossy UTF-8 handling by default, see [crate::Utf8Mode].
 #[derive(Debug, Clone, Default)]
-pub struct VideohubCodec;
+pub struct VideohubCodec {
+    pub options: ParseOptions,
+}
 
 impl Decoder for VideohubCodec {
     type Item = VideohubMessage;
@@ -14,37 +21,133 @@ impl Decoder for VideohubCodec {
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         let input = &src[..];
 
-        match VideohubMessage::parse_single_block(input) {
+        match VideohubMessage::parse_single_block_with(input, self.options) {
             Ok((remaining, msg)) => {
                 let parsed_len = input.len() - remaining.len();
                 src.advance(parsed_len); // Remove the consumed bytes from the buffer
                 Ok(Some(msg))
             }
             // Not enough data, wait for more
-            Err(nom::Err::Incomplete(_)) => Ok(None),
-            // Other error,
-            Err(_) => {
-                // Parsing error, treat as protocol error
-                Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "Invalid Videohub message",
-                ))
-            }
+            Err(MessageParseError::Incomplete) => Ok(None),
+            // Parsing error, treat as protocol error
+            Err(e) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                decode_error_message(input, &e),
+            )),
         }
     }
 }
 
+/// Describe a parse error, quoting the offending line and the (truncated) block.
+fn decode_error_message(input: &[u8], e: &MessageParseError) -> String {
+    let block_end = input
+        .windows(2)
+        .position(|w| w == b"\n\n" || w == b"\n\r")
+        .map_or(input.len(), |p| p + 1);
+    let block = &input[..block_end.min(ERROR_CONTEXT_LEN)];
+    let ellipsis = if block_end > ERROR_CONTEXT_LEN {
+        "..."
+    } else {
+        ""
+    };
+    let line = e.line_in(input).unwrap_or_default();
+    format!(
+        "Invalid Videohub message: {} in line {:?} of block {:?}{}",
+        e,
+        String::from_utf8_lossy(&input[line]),
+        String::from_utf8_lossy(block),
+        ellipsis
+    )
+}
+
 impl Encoder<VideohubMessage> for VideohubCodec {
     type Error = std::io::Error;
 
     fn encode(&mut self, item: VideohubMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let writer = dst.writer();
-        item.write_serialized(writer)?;
-
+        item.encode_into(dst);
         Ok(())
     }
 }
 
+/// A [VideohubCodec] skipping malformed blocks instead of failing.
+///
+/// The protocol resynchronizes on blank lines: a complete block that doesn't parse is
+/// dropped up to and including its terminating empty line, decoding carries on after it.
+#[derive(Debug, Clone, Default)]
+pub struct LenientCodec;
+
+impl Decoder for LenientCodec {
+    type Item = VideohubMessage;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            let input = &src[..];
+            let e = match VideohubMessage::parse_single_block_with(input, ParseOptions::default()) {
+                Ok((rest, msg)) => {
+                    let parsed_len = input.len() - rest.len();
+                    src.advance(parsed_len);
+                    return Ok(Some(msg));
+                }
+                Err(MessageParseError::Incomplete) => return Ok(None),
+                Err(e) => e,
+            };
+            // Only skip once the bad block is complete.
+            let Ok((rest, _)) = crate::helpers::take_until_empty_line(&src[..]) else {
+                return Ok(None);
+            };
+            tracing::warn!(
+                error = %decode_error_message(&src[..], &e),
+                "Skipping malformed block"
+            );
+            let skip = src.len() - rest.len();
+            src.advance(skip);
+        }
+    }
+}
+
+impl Encoder<VideohubMessage> for LenientCodec {
+    type Error = std::io::Error;
+
+    fn encode(&mut self, item: VideohubMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        VideohubCodec::default().encode(item, dst)
+    }
+}
+
+/// A [VideohubCodec] normalizing labels on encode, see [crate::Label::normalized].
+///
+/// Useful when talking to hardware, so labels arrive the way the hub would keep them.
+#[derive(Debug, Clone)]
+pub struct NormalizingCodec {
+    pub max_label_len: usize,
+}
+
+impl Default for NormalizingCodec {
+    fn default() -> Self {
+        Self {
+            max_label_len: crate::HARDWARE_LABEL_LEN,
+        }
+    }
+}
+
+impl Decoder for NormalizingCodec {
+    type Item = VideohubMessage;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        VideohubCodec::default().decode(src)
+    }
+}
+
+impl Encoder<VideohubMessage> for NormalizingCodec {
+    type Error = std::io::Error;
+
+    fn encode(&mut self, mut item: VideohubMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        item.normalize_labels(self.max_label_len);
+        VideohubCodec::default().encode(item, dst)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::{DeviceInfo, Present};
@@ -85,6 +188,49 @@ mod tests {
         assert_eq!(buf, &input[..]);
     }
 
+    #[test]
+    fn decode_every_split() {
+        let input: &[u8] = include_bytes!("./bmd_example.txt");
+        let (all, _) = VideohubMessage::parse_all_blocks_partial(input);
+        for at in 0..=input.len() {
+            let mut codec = VideohubCodec::default();
+            let mut buf = BytesMut::from(&input[..at]);
+            let mut msgs = Vec::new();
+            while let Some(m) = codec.decode(&mut buf).unwrap() {
+                msgs.push(m);
+            }
+            buf.extend_from_slice(&input[at..]);
+            while let Some(m) = codec.decode(&mut buf).unwrap() {
+                msgs.push(m);
+            }
+            assert_eq!(msgs, all, "split at {}", at);
+            assert!(buf.is_empty(), "split at {}", at);
+        }
+    }
+
+    #[test]
+    fn decode_strict_utf8() {
+        let input = &b"OUTPUT LABELS:\n0 Pr\xf0gram\n\n"[..];
+        let mut lossy = BytesMut::from(input);
+        assert!(VideohubCodec::default()
+            .decode(&mut lossy)
+            .unwrap()
+            .is_some());
+
+        let mut codec = VideohubCodec {
+            options: ParseOptions {
+                utf8: crate::Utf8Mode::Strict,
+                ..Default::default()
+            },
+        };
+        let err = codec.decode(&mut BytesMut::from(input)).unwrap_err();
+        assert!(
+            err.to_string().contains("invalid UTF-8 at byte 19"),
+            "{}",
+            err
+        );
+    }
+
     #[test]
     fn encode_simple_message() {
         let mut codec = VideohubCodec::default();
@@ -100,4 +246,94 @@ mod tests {
         assert!(output.contains("Device present: false"));
         assert!(output.ends_with("\r\n\r\n") || output.ends_with("\n\n"));
     }
+
+    #[test]
+    fn unknown_block_keeps_following() {
+        let mut codec = VideohubCodec::default();
+        let mut buf = BytesMut::from(&b"FANCY NEW BLOCK:\nfoo\n\nPING:\n\n"[..]);
+
+        let msg = codec.decode(&mut buf).unwrap().unwrap();
+        assert!(matches!(msg, VideohubMessage::UnknownMessage(..)));
+        let msg = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(msg, VideohubMessage::Ping);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn decode_error_quotes_block() {
+        let mut codec = VideohubCodec::default();
+        let mut buf = BytesMut::from(&b"VIDEO OUTPUT LOCKS:\n0 U\n1 Owned\n\nPING:\n\n"[..]);
+        let e = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(
+            e.to_string(),
+            "Invalid Videohub message: invalid block at byte 24 (Tag) in line \"1 Owned\" \
+             of block \"VIDEO OUTPUT LOCKS:\\n0 U\\n1 Owned\\n\""
+        );
+
+        // Long blocks get cut.
+        let mut body = String::from("VIDEO OUTPUT LOCKS:\n");
+        for id in 0..40 {
+            body += &format!("{} U\n", id);
+        }
+        body += "40 Owned\n\n";
+        let e = codec.decode(&mut BytesMut::from(&body[..])).unwrap_err();
+        assert!(e.to_string().contains("line \"40 Owned\""));
+        assert!(e.to_string().ends_with("..."));
+    }
+
+    #[test]
+    fn lenient_skips_bad_blocks() {
+        let mut buf =
+            BytesMut::from(&b"PING:\n\nVIDEO OUTPUT LOCKS:\r\n0 U\r\n1 Owned\r\n\r\nACK\n\n"[..]);
+        let mut strict = buf.clone();
+        assert_eq!(
+            VideohubCodec::default().decode(&mut strict).unwrap(),
+            Some(VideohubMessage::Ping)
+        );
+        assert!(VideohubCodec::default().decode(&mut strict).is_err());
+
+        let mut codec = LenientCodec;
+        let mut got = Vec::new();
+        while let Some(msg) = codec.decode(&mut buf).unwrap() {
+            got.push(msg);
+        }
+        assert_eq!(got, [VideohubMessage::Ping, VideohubMessage::ACK]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn lenient_waits_for_whole_bad_block() {
+        let mut codec = LenientCodec;
+        let mut buf = BytesMut::from(&b"VIDEO OUTPUT LOCKS:\n1 Owned\n"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        // Nothing dropped yet, the block might still go on.
+        assert_eq!(buf.len(), 28);
+
+        buf.extend_from_slice(b"\nPING:\n\n");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(VideohubMessage::Ping));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn normalizing_encode() {
+        let msg = VideohubMessage::InputLabels(vec![crate::Label {
+            id: 0,
+            name: " Camera\t1 with a rather long name ".into(),
+        }]);
+        let mut buf = BytesMut::new();
+        VideohubCodec::default()
+            .encode(msg.clone(), &mut buf)
+            .unwrap();
+        assert_eq!(
+            &buf[..],
+            b"INPUT LABELS:\n0  Camera\t1 with a rather long name \n\n"
+        );
+
+        let mut buf = BytesMut::new();
+        let mut codec = NormalizingCodec { max_label_len: 15 };
+        codec.encode(msg, &mut buf).unwrap();
+        assert_eq!(&buf[..], b"INPUT LABELS:\n0 Camera1 with a\n\n");
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+    }
 }
@@ -0,0 +1,243 @@
+// Compact, human-readable rendering of messages, meant for logs.
+// Unlike the writer, this never spans multiple lines.
+
+use super::model::*;
+use alloc::string::{String, ToString};
+use core::fmt;
+
+/// Number of entries shown before a summary gets truncated.
+const SUMMARY_ENTRIES: usize = 4;
+
+/// Write `count noun(s) (a, b, c, d, …)`, truncated after [SUMMARY_ENTRIES].
+fn entries<T>(
+    f: &mut fmt::Formatter,
+    noun: &str,
+    v: &[T],
+    mut entry: impl FnMut(&mut fmt::Formatter, &T) -> fmt::Result,
+) -> fmt::Result {
+    let plural = if v.len() == 1 { "" } else { "s" };
+    write!(f, "{} {}{}", v.len(), noun, plural)?;
+    if v.is_empty() {
+        return Ok(());
+    }
+    f.write_str(" (")?;
+    for (n, e) in v.iter().take(SUMMARY_ENTRIES).enumerate() {
+        if n > 0 {
+            f.write_str(", ")?;
+        }
+        entry(f, e)?;
+    }
+    if v.len() > SUMMARY_ENTRIES {
+        f.write_str(", …")?;
+    }
+    f.write_str(")")
+}
+
+fn labels(f: &mut fmt::Formatter, header: &str, v: &[Label]) -> fmt::Result {
+    write!(f, "{} ", header)?;
+    entries(f, "label", v, |f, l| write!(f, "{} {:?}", l.id, l.name))
+}
+
+fn routes(f: &mut fmt::Formatter, header: &str, v: &[Route]) -> fmt::Result {
+    write!(f, "{} ", header)?;
+    entries(f, "route", v, |f, r| {
+        write!(f, "out {}←in {}", r.to_output, r.from_input)
+    })
+}
+
+fn locks(f: &mut fmt::Formatter, header: &str, v: &[Lock]) -> fmt::Result {
+    write!(f, "{} ", header)?;
+    entries(f, "lock", v, |f, l| write!(f, "{} {}", l.id, l.state))
+}
+
+fn ports(f: &mut fmt::Formatter, header: &str, v: &[HardwarePort]) -> fmt::Result {
+    write!(f, "{} ", header)?;
+    entries(f, "port", v, |f, p| write!(f, "{} {}", p.id, p.port_type))
+}
+
+impl fmt::Display for VideohubMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VideohubMessage::Preamble(p) => write!(f, "PROTOCOL PREAMBLE: version {}", p.version),
+            VideohubMessage::DeviceInfo(d) => {
+                f.write_str("VIDEOHUB DEVICE:")?;
+                if let Some(present) = &d.present {
+                    write!(f, " present {}", present)?;
+                }
+                if let Some(model) = &d.model_name {
+                    write!(f, ", {:?}", model)?;
+                }
+                if let (Some(i), Some(o)) = (d.video_inputs, d.video_outputs) {
+                    write!(f, ", {}x{}", i, o)?;
+                }
+                Ok(())
+            }
+
+            VideohubMessage::InputLabels(v) => labels(f, "INPUT LABELS:", v),
+            VideohubMessage::OutputLabels(v) => labels(f, "OUTPUT LABELS:", v),
+            VideohubMessage::MonitorOutputLabels(v) => labels(f, MONITOR_OUTPUT_LABELS_HEADER, v),
+            VideohubMessage::SerialPortLabels(v) => labels(f, "SERIAL PORT LABELS:", v),
+            VideohubMessage::FrameLabels(v) => labels(f, "FRAME LABELS:", v),
+
+            VideohubMessage::VideoOutputRouting(v) => routes(f, "VIDEO OUTPUT ROUTING:", v),
+            VideohubMessage::VideoMonitoringOutputRouting(v) => {
+                routes(f, "VIDEO MONITORING OUTPUT ROUTING:", v)
+            }
+            VideohubMessage::SerialPortRouting(v) => routes(f, "SERIAL PORT ROUTING:", v),
+            VideohubMessage::ProcessingUnitRouting(v) => routes(f, "PROCESSING UNIT ROUTING:", v),
+            VideohubMessage::FrameBufferRouting(v) => routes(f, "FRAME BUFFER ROUTING:", v),
+
+            VideohubMessage::VideoOutputLocks(v) => locks(f, "VIDEO OUTPUT LOCKS:", v),
+            VideohubMessage::MonitoringOutputLocks(v) => locks(f, "MONITORING OUTPUT LOCKS:", v),
+            VideohubMessage::SerialPortLocks(v) => locks(f, "SERIAL PORT LOCKS:", v),
+            VideohubMessage::ProcessingUnitLocks(v) => locks(f, "PROCESSING UNIT LOCKS:", v),
+            VideohubMessage::FrameBufferLocks(v) => locks(f, "FRAME BUFFER LOCKS:", v),
+
+            VideohubMessage::VideoInputStatus(v) => ports(f, "VIDEO INPUT STATUS:", v),
+            VideohubMessage::VideoOutputStatus(v) => ports(f, "VIDEO OUTPUT STATUS:", v),
+            VideohubMessage::SerialPortStatus(v) => ports(f, "SERIAL PORT STATUS:", v),
+
+            VideohubMessage::AlarmStatus(v) => {
+                f.write_str("ALARM STATUS: ")?;
+                entries(f, "alarm", v, |f, a| write!(f, "{}: {}", a.name, a.status))
+            }
+            VideohubMessage::Configuration(v) => {
+                f.write_str("CONFIGURATION: ")?;
+                entries(f, "setting", v, |f, s| {
+                    write!(f, "{}: {}", s.setting, s.value)
+                })
+            }
+
+            VideohubMessage::ACK => f.write_str("ACK"),
+            VideohubMessage::NAK => f.write_str("NAK"),
+            VideohubMessage::Ping => f.write_str("PING:"),
+            VideohubMessage::EndPrelude => f.write_str("END PRELUDE:"),
+
+            VideohubMessage::UnknownMessage(h, body) => {
+                let header = String::from_utf8_lossy(h);
+                write!(f, "{} (unknown, {} bytes)", header.trim_end(), body.len())
+            }
+        }
+    }
+}
+
+impl VideohubMessage {
+    /// One-line summary of the message, suitable for logging.
+    pub fn summary(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    const BMD_EXAMPLE: &[u8] = include_bytes!("./bmd_example.txt");
+    const BMD_CLEANSWITCH: &[u8] = include_bytes!("./bmd_cleanswitch_12x12.txt");
+
+    fn routing(count: u32) -> VideohubMessage {
+        VideohubMessage::VideoOutputRouting(
+            (0..count)
+                .map(|n| Route {
+                    from_input: count - n - 1,
+                    to_output: n,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn truncates_long_blocks() {
+        assert_eq!(
+            routing(288).summary(),
+            "VIDEO OUTPUT ROUTING: 288 routes (out 0←in 287, out 1←in 286, out 2←in 285, out 3←in 284, …)"
+        );
+        assert_eq!(
+            routing(4).summary(),
+            "VIDEO OUTPUT ROUTING: 4 routes (out 0←in 3, out 1←in 2, out 2←in 1, out 3←in 0)"
+        );
+        assert_eq!(
+            routing(1).summary(),
+            "VIDEO OUTPUT ROUTING: 1 route (out 0←in 0)"
+        );
+        assert_eq!(routing(0).summary(), "VIDEO OUTPUT ROUTING: 0 routes");
+    }
+
+    #[test]
+    fn labels_are_quoted() {
+        let m = VideohubMessage::InputLabels(vec![Label {
+            id: 3,
+            name: "Camera, \"wide\"".into(),
+        }]);
+        assert_eq!(
+            m.summary(),
+            r#"INPUT LABELS: 1 label (3 "Camera, \"wide\"")"#
+        );
+    }
+
+    #[test]
+    fn every_variant_is_one_line() {
+        let mut msgs = Vec::new();
+        for input in [BMD_EXAMPLE, BMD_CLEANSWITCH] {
+            let (_, parsed) = VideohubMessage::parse_all_blocks(input).unwrap();
+            msgs.extend(parsed);
+        }
+        let port = HardwarePort {
+            id: 0,
+            port_type: HardwarePortType::BNC,
+        };
+        let lock = Lock {
+            id: 0,
+            state: LockState::Owned,
+        };
+        msgs.extend([
+            VideohubMessage::MonitorOutputLabels(vec![]),
+            VideohubMessage::SerialPortLabels(vec![]),
+            VideohubMessage::FrameLabels(vec![]),
+            VideohubMessage::VideoMonitoringOutputRouting(vec![]),
+            VideohubMessage::SerialPortRouting(vec![]),
+            VideohubMessage::ProcessingUnitRouting(vec![]),
+            VideohubMessage::FrameBufferRouting(vec![]),
+            VideohubMessage::MonitoringOutputLocks(vec![lock]),
+            VideohubMessage::SerialPortLocks(vec![lock]),
+            VideohubMessage::ProcessingUnitLocks(vec![lock]),
+            VideohubMessage::FrameBufferLocks(vec![lock]),
+            VideohubMessage::VideoInputStatus(vec![port.clone()]),
+            VideohubMessage::VideoOutputStatus(vec![port.clone()]),
+            VideohubMessage::SerialPortStatus(vec![port]),
+            VideohubMessage::AlarmStatus(vec![Alarm {
+                name: "Fan".into(),
+                status: "OK".into(),
+            }]),
+            VideohubMessage::Configuration(vec![Setting {
+                setting: "Take Mode".into(),
+                value: "true".into(),
+            }]),
+            VideohubMessage::ACK,
+            VideohubMessage::NAK,
+            VideohubMessage::Ping,
+            VideohubMessage::EndPrelude,
+            VideohubMessage::UnknownMessage(
+                BytesMut::from(&b"FANCY NEW BLOCK:"[..]),
+                BytesMut::from(&b"a\nb\n"[..]),
+            ),
+        ]);
+
+        for m in msgs {
+            let s = m.summary();
+            assert!(!s.is_empty());
+            assert!(!s.contains('\n'), "multi-line summary: {:?}", s);
+            assert!(s.len() < 200, "summary too long: {:?}", s);
+        }
+    }
+
+    #[test]
+    fn device_info() {
+        let (_, msgs) = VideohubMessage::parse_all_blocks(BMD_CLEANSWITCH).unwrap();
+        assert_eq!(
+            msgs[1].summary(),
+            "VIDEOHUB DEVICE: present true, \"Smart Videohub CleanSwitch 12x12\", 12x12"
+        );
+    }
+}
@@ -1,11 +1,32 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "client")]
+mod client;
 #[cfg(feature = "codec")]
 mod codec;
+mod display;
 mod helpers;
+mod merge;
 #[allow(dead_code)]
 mod model;
+mod normalize;
 mod parser;
+mod prelude;
+mod profile;
+mod validate;
 mod writer;
 
+#[cfg(feature = "client")]
+pub use client::{ClientError, VideohubClient, VideohubEvents, DEFAULT_REQUEST_TIMEOUT};
 #[cfg(feature = "codec")]
-pub use codec::VideohubCodec;
+pub use codec::{LenientCodec, NormalizingCodec, VideohubCodec};
 pub use model::*;
+pub use normalize::HARDWARE_LABEL_LEN;
+pub use parser::{BlockSpans, MessageParseError, ParseOptions, Utf8Mode};
+pub use prelude::{
+    build_prelude, build_prelude_for, build_state_dump, chunked, PreludeBlocks, VideohubState,
+};
+pub use profile::ProtocolProfile;
+pub use validate::{DevicePort, ValidationError};
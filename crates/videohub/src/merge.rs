@@ -0,0 +1,290 @@
+// Coalescing of messages, for senders with more updates than they care to send one by one.
+// Later entries win over earlier ones of the same port, like a receiver applying both would
+// end up with.
+
+use super::model::*;
+use alloc::vec::Vec;
+
+/// Entries of `a`, overwritten by those of `b` where `same`, followed by the rest of `b`.
+fn merge_by<T: Clone>(a: &[T], b: &[T], same: impl Fn(&T, &T) -> bool) -> Vec<T> {
+    let mut merged = a.to_vec();
+    for e in b {
+        match merged.iter_mut().find(|m| same(m, e)) {
+            Some(m) => *m = e.clone(),
+            None => merged.push(e.clone()),
+        }
+    }
+    merged
+}
+
+/// Like [merge_by], but empty blocks are requests and never merge.
+fn merge_updates<T: Clone>(a: &[T], b: &[T], same: impl Fn(&T, &T) -> bool) -> Option<Vec<T>> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+    Some(merge_by(a, b, same))
+}
+
+fn same_label(a: &Label, b: &Label) -> bool {
+    a.id == b.id
+}
+
+fn same_route(a: &Route, b: &Route) -> bool {
+    a.to_output == b.to_output
+}
+
+fn same_lock(a: &Lock, b: &Lock) -> bool {
+    a.id == b.id
+}
+
+fn same_port(a: &HardwarePort, b: &HardwarePort) -> bool {
+    a.id == b.id
+}
+
+impl VideohubMessage {
+    /// A single message doing what this one followed by `other` does, if there is one.
+    ///
+    /// Blocks of the same kind merge, with entries of `other` replacing those of the same
+    /// port. Device info merges field by field. Requests, which are empty blocks, and
+    /// protocol messages like [VideohubMessage::ACK] each expect a reply of their own and
+    /// never merge.
+    pub fn merge(&self, other: &VideohubMessage) -> Option<VideohubMessage> {
+        use VideohubMessage::*;
+        Some(match (self, other) {
+            (InputLabels(a), InputLabels(b)) => InputLabels(merge_updates(a, b, same_label)?),
+            (OutputLabels(a), OutputLabels(b)) => OutputLabels(merge_updates(a, b, same_label)?),
+            (MonitorOutputLabels(a), MonitorOutputLabels(b)) => {
+                MonitorOutputLabels(merge_updates(a, b, same_label)?)
+            }
+            (SerialPortLabels(a), SerialPortLabels(b)) => {
+                SerialPortLabels(merge_updates(a, b, same_label)?)
+            }
+            (FrameLabels(a), FrameLabels(b)) => FrameLabels(merge_updates(a, b, same_label)?),
+
+            (VideoOutputRouting(a), VideoOutputRouting(b)) => {
+                VideoOutputRouting(merge_updates(a, b, same_route)?)
+            }
+            (VideoMonitoringOutputRouting(a), VideoMonitoringOutputRouting(b)) => {
+                VideoMonitoringOutputRouting(merge_updates(a, b, same_route)?)
+            }
+            (SerialPortRouting(a), SerialPortRouting(b)) => {
+                SerialPortRouting(merge_updates(a, b, same_route)?)
+            }
+            (ProcessingUnitRouting(a), ProcessingUnitRouting(b)) => {
+                ProcessingUnitRouting(merge_updates(a, b, same_route)?)
+            }
+            (FrameBufferRouting(a), FrameBufferRouting(b)) => {
+                FrameBufferRouting(merge_updates(a, b, same_route)?)
+            }
+
+            (VideoOutputLocks(a), VideoOutputLocks(b)) => {
+                VideoOutputLocks(merge_updates(a, b, same_lock)?)
+            }
+            (MonitoringOutputLocks(a), MonitoringOutputLocks(b)) => {
+                MonitoringOutputLocks(merge_updates(a, b, same_lock)?)
+            }
+            (SerialPortLocks(a), SerialPortLocks(b)) => {
+                SerialPortLocks(merge_updates(a, b, same_lock)?)
+            }
+            (ProcessingUnitLocks(a), ProcessingUnitLocks(b)) => {
+                ProcessingUnitLocks(merge_updates(a, b, same_lock)?)
+            }
+            (FrameBufferLocks(a), FrameBufferLocks(b)) => {
+                FrameBufferLocks(merge_updates(a, b, same_lock)?)
+            }
+
+            (Configuration(a), Configuration(b)) => {
+                Configuration(merge_updates(a, b, |x, y| x.setting == y.setting)?)
+            }
+
+            (VideoInputStatus(a), VideoInputStatus(b)) => {
+                VideoInputStatus(merge_by(a, b, same_port))
+            }
+            (VideoOutputStatus(a), VideoOutputStatus(b)) => {
+                VideoOutputStatus(merge_by(a, b, same_port))
+            }
+            (SerialPortStatus(a), SerialPortStatus(b)) => {
+                SerialPortStatus(merge_by(a, b, same_port))
+            }
+            (AlarmStatus(a), AlarmStatus(b)) => {
+                AlarmStatus(merge_by(a, b, |x, y| x.name == y.name))
+            }
+
+            (DeviceInfo(a), DeviceInfo(b)) => DeviceInfo(super::DeviceInfo {
+                present: b.present.or(a.present),
+                model_name: b.model_name.clone().or_else(|| a.model_name.clone()),
+                friendly_name: b.friendly_name.clone().or_else(|| a.friendly_name.clone()),
+                unique_id: b.unique_id.clone().or_else(|| a.unique_id.clone()),
+                video_inputs: b.video_inputs.or(a.video_inputs),
+                video_processing_units: b.video_processing_units.or(a.video_processing_units),
+                video_outputs: b.video_outputs.or(a.video_outputs),
+                video_monitoring_outputs: b.video_monitoring_outputs.or(a.video_monitoring_outputs),
+                serial_ports: b.serial_ports.or(a.serial_ports),
+                unknown_fields: match (&a.unknown_fields, &b.unknown_fields) {
+                    (Some(a), Some(b)) => Some(merge_by(a, b, |x, y| x.key == y.key)),
+                    (a, b) => b.clone().or_else(|| a.clone()),
+                },
+            }),
+
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn labels() {
+        for make in [
+            VideohubMessage::InputLabels,
+            VideohubMessage::OutputLabels,
+            VideohubMessage::MonitorOutputLabels,
+            VideohubMessage::SerialPortLabels,
+            VideohubMessage::FrameLabels,
+        ] {
+            let a = make(vec![(0, "A").into(), (1, "B").into()]);
+            let b = make(vec![(1, "C").into(), (2, "D").into()]);
+            assert_eq!(
+                a.merge(&b),
+                Some(make(vec![
+                    (0, "A").into(),
+                    (1, "C").into(),
+                    (2, "D").into()
+                ]))
+            );
+        }
+    }
+
+    #[test]
+    fn routes() {
+        for make in [
+            VideohubMessage::VideoOutputRouting,
+            VideohubMessage::VideoMonitoringOutputRouting,
+            VideohubMessage::SerialPortRouting,
+            VideohubMessage::ProcessingUnitRouting,
+            VideohubMessage::FrameBufferRouting,
+        ] {
+            // From `(to_output, from_input)`.
+            let a = make(vec![(0, 3).into(), (1, 3).into()]);
+            let b = make(vec![(1, 2).into()]);
+            let c = make(vec![(0, 1).into()]);
+            let merged = a.merge(&b).and_then(|m| m.merge(&c));
+            assert_eq!(merged, Some(make(vec![(0, 1).into(), (1, 2).into()])));
+        }
+    }
+
+    #[test]
+    fn locks() {
+        for make in [
+            VideohubMessage::VideoOutputLocks,
+            VideohubMessage::MonitoringOutputLocks,
+            VideohubMessage::SerialPortLocks,
+            VideohubMessage::ProcessingUnitLocks,
+            VideohubMessage::FrameBufferLocks,
+        ] {
+            let a = make(vec![
+                (0, LockState::Owned).into(),
+                (1, LockState::Locked).into(),
+            ]);
+            let b = make(vec![(0, LockState::Unlocked).into()]);
+            assert_eq!(
+                a.merge(&b),
+                Some(make(vec![
+                    (0, LockState::Unlocked).into(),
+                    (1, LockState::Locked).into()
+                ]))
+            );
+        }
+    }
+
+    #[test]
+    fn hardware_status() {
+        let port = |id, port_type| HardwarePort { id, port_type };
+        for make in [
+            VideohubMessage::VideoInputStatus,
+            VideohubMessage::VideoOutputStatus,
+            VideohubMessage::SerialPortStatus,
+        ] {
+            let a = make(vec![port(0, HardwarePortType::BNC)]);
+            let b = make(vec![
+                port(0, HardwarePortType::None),
+                port(1, HardwarePortType::BNC),
+            ]);
+            assert_eq!(a.merge(&b), Some(b.clone()));
+            // No ports is a status like any other.
+            assert_eq!(make(vec![]).merge(&a), Some(a.clone()));
+        }
+    }
+
+    #[test]
+    fn alarms_and_configuration() {
+        let alarm = |name: &str, status: &str| Alarm {
+            name: name.into(),
+            status: status.into(),
+        };
+        let a = VideohubMessage::AlarmStatus(vec![alarm("Fan", "ok"), alarm("PSU 1", "ok")]);
+        let b = VideohubMessage::AlarmStatus(vec![alarm("Fan", "failed")]);
+        assert_eq!(
+            a.merge(&b),
+            Some(VideohubMessage::AlarmStatus(vec![
+                alarm("Fan", "failed"),
+                alarm("PSU 1", "ok")
+            ]))
+        );
+
+        let setting = |setting: &str, value: &str| Setting {
+            setting: setting.into(),
+            value: value.into(),
+        };
+        let a = VideohubMessage::Configuration(vec![setting("Take Mode", "false")]);
+        let b = VideohubMessage::Configuration(vec![setting("Take Mode", "true")]);
+        assert_eq!(a.merge(&b), Some(b.clone()));
+    }
+
+    #[test]
+    fn device_info() {
+        let a = VideohubMessage::DeviceInfo(DeviceInfo {
+            present: Some(Present::Yes),
+            model_name: Some("Smart Videohub".into()),
+            video_inputs: Some(12),
+            ..Default::default()
+        });
+        let b = VideohubMessage::DeviceInfo(DeviceInfo {
+            present: Some(Present::No),
+            ..Default::default()
+        });
+        assert_eq!(
+            a.merge(&b),
+            Some(VideohubMessage::DeviceInfo(DeviceInfo {
+                present: Some(Present::No),
+                model_name: Some("Smart Videohub".into()),
+                video_inputs: Some(12),
+                ..Default::default()
+            }))
+        );
+    }
+
+    #[test]
+    fn unmergeable() {
+        let routing = VideohubMessage::VideoOutputRouting(vec![(0, 1).into()]);
+        let labels = VideohubMessage::InputLabels(vec![(0, "A").into()]);
+        // Different kinds.
+        assert_eq!(routing.merge(&labels), None);
+        // Requests want a reply each.
+        let request = VideohubMessage::VideoOutputRouting(vec![]);
+        assert_eq!(routing.merge(&request), None);
+        assert_eq!(request.merge(&routing), None);
+        assert_eq!(request.merge(&request), None);
+        for m in [
+            VideohubMessage::ACK,
+            VideohubMessage::NAK,
+            VideohubMessage::Ping,
+            VideohubMessage::EndPrelude,
+        ] {
+            assert_eq!(m.merge(&m), None);
+        }
+    }
+}
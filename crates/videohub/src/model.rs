@@ -1,7 +1,8 @@
 // BMD Videohub Protocol Data Model
 
+use alloc::{string::String, vec::Vec};
 use bytes::BytesMut;
-use std::fmt;
+use core::fmt;
 
 /// Preamble contains version.
 /// This is only compatible with major version 2, but later minor versions should be compatible.
@@ -11,7 +12,7 @@ use std::fmt;
 /// Version: 2.4↵
 /// ↵
 /// ```
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Preamble {
     pub version: String,
 }
@@ -20,7 +21,7 @@ pub struct Preamble {
 /// - `Device present: true`
 /// - `Device present: false`
 /// - `Device present: needs_update`
-#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub enum Present {
     Yes,
     #[default]
@@ -40,7 +41,7 @@ impl fmt::Display for Present {
 }
 
 /// An unknown Key-Value pair.
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct UnknownKVPair {
     pub key: String,
     pub value: String,
@@ -55,7 +56,7 @@ pub struct UnknownKVPair {
 /// Video monitoring outputs: 0↵
 /// Serial ports: 0↵
 /// ↵
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct DeviceInfo {
     pub present: Option<Present>,
     pub model_name: Option<String>,
@@ -75,7 +76,9 @@ pub struct DeviceInfo {
 /// - `MONITORING OUTPUT LABELS:`
 /// - `SERIAL PORT LABELS:`
 /// - `FRAME LABELS:`
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+///
+/// Ordered by id, then name.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Label {
     pub id: u32,
     pub name: String,
@@ -87,19 +90,34 @@ pub struct Label {
 /// - `SERIAL PORT ROUTING:`
 /// - `PROCESSING UNIT ROUTING:`
 /// - `FRAME BUFFER ROUTING:`
-#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+///
+/// Ordered by output, then input, as listed in routing blocks.
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct Route {
     pub from_input: u32,
     pub to_output: u32,
 }
 
+impl Ord for Route {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        (self.to_output, self.from_input).cmp(&(other.to_output, other.from_input))
+    }
+}
+
+impl PartialOrd for Route {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 /// Lock State
 ///
 /// Represented by something like the following:
 /// - `x O` - x is owned by current client
 /// - `x L` - x is locked by different client
 /// - `x U` - x is not locked
-#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+/// - `x F` - force-take: override whatever lock is on x
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum LockState {
     /// Lock owned by the current Client
     Owned,
@@ -108,6 +126,10 @@ pub enum LockState {
     /// Not locked
     #[default]
     Unlocked,
+    /// Force-take a lock, even if a different Client holds it (newer firmwares)
+    Force,
+    /// Unknown single letter state, preserved as-is
+    Other(char),
 }
 
 impl fmt::Display for LockState {
@@ -116,6 +138,8 @@ impl fmt::Display for LockState {
             LockState::Owned => "O",
             LockState::Locked => "L",
             LockState::Unlocked => "U",
+            LockState::Force => "F",
+            LockState::Other(c) => return write!(f, "{}", c),
         };
         f.write_str(s)
     }
@@ -127,13 +151,65 @@ impl fmt::Display for LockState {
 /// - `SERIAL PORT LOCKS:↵`
 /// - `PROCESSING UNIT LOCKS:`
 /// - `FRAME BUFFER LOCKS:`
-#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+///
+/// Ordered by id, then state.
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Lock {
     pub id: u32,
     pub state: LockState,
 }
 
-#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+impl From<(u32, &str)> for Label {
+    /// From `(id, name)`.
+    fn from((id, name): (u32, &str)) -> Self {
+        Label {
+            id,
+            name: name.into(),
+        }
+    }
+}
+
+impl From<(u32, u32)> for Route {
+    /// From `(to_output, from_input)`, in the order of a routing line.
+    fn from((to_output, from_input): (u32, u32)) -> Self {
+        Route {
+            from_input,
+            to_output,
+        }
+    }
+}
+
+impl From<(u32, LockState)> for Lock {
+    /// From `(id, state)`.
+    fn from((id, state): (u32, LockState)) -> Self {
+        Lock { id, state }
+    }
+}
+
+/// Sorting block entries the way hubs send them, ascending by port.
+pub trait SortCanonical {
+    fn sort_canonical(&mut self);
+}
+
+impl SortCanonical for [Label] {
+    fn sort_canonical(&mut self) {
+        self.sort();
+    }
+}
+
+impl SortCanonical for [Route] {
+    fn sort_canonical(&mut self) {
+        self.sort();
+    }
+}
+
+impl SortCanonical for [Lock] {
+    fn sort_canonical(&mut self) {
+        self.sort();
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub enum SerialPortDirectionState {
     /// In (Workstation)
     Control,
@@ -163,13 +239,13 @@ impl fmt::Display for SerialPortDirectionState {
 /// 1 slave↵
 /// 2 auto↵
 /// ```
-#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct SerialPortDirection {
     pub id: u32,
     pub state: SerialPortDirectionState,
 }
 
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub enum HardwarePortType {
     #[default]
     None,
@@ -183,10 +259,15 @@ pub enum HardwarePortType {
 
 impl fmt::Display for HardwarePortType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            HardwarePortType::Other(s) => f.write_str(s),
-            _ => fmt::Debug::fmt(self, f),
-        }
+        let t = match self {
+            HardwarePortType::None => "None",
+            HardwarePortType::BNC => "BNC",
+            HardwarePortType::Optical => "Optical",
+            HardwarePortType::Thunderbolt => "Thunderbolt",
+            HardwarePortType::RS422 => "RS422",
+            HardwarePortType::Other(s) => s,
+        };
+        f.write_str(t)
     }
 }
 
@@ -194,7 +275,7 @@ impl fmt::Display for HardwarePortType {
 /// - `VIDEO INPUT STATUS:`
 /// - `VIDEO OUTPUT STATUS:`
 /// - `SERIAL PORT STATUS:`
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct HardwarePort {
     pub id: u32,
     pub port_type: HardwarePortType,
@@ -202,27 +283,71 @@ pub struct HardwarePort {
 
 /// An Alarm Status Message.
 /// More akin to sensors, really.
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct Alarm {
     pub name: String,
     pub status: String,
 }
 
 /// An Configuration Message's Setting.
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct Setting {
     pub setting: String,
     pub value: String,
 }
 
+/// Line terminator used when serializing messages.
+///
+/// The parser accepts both, real hardware sends `\r\n`.
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub enum LineEnding {
+    /// `\n`
+    #[default]
+    LF,
+    /// `\r\n`
+    CRLF,
+}
+
+impl LineEnding {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::LF => b"\n",
+            LineEnding::CRLF => b"\r\n",
+        }
+    }
+}
+
+/// Header emitted for [VideohubMessage::MonitorOutputLabels].
+///
+/// This is the spelling used by the BMD protocol documentation. Some firmware revisions
+/// send `MONITOR OUTPUT LABELS:` instead, which the parser accepts as well.
+pub const MONITOR_OUTPUT_LABELS_HEADER: &str = "MONITORING OUTPUT LABELS:";
+
 /// Unknown Message.
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct UnknownMessage {
     pub header: BytesMut,
     pub body: BytesMut,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+/// A block of the Videohub protocol.
+///
+/// Blocks the crate learns to tell apart may be added without a major release, so matching
+/// outside of it needs a wildcard arm:
+///
+/// ```
+/// use videohub::VideohubMessage;
+///
+/// fn is_reply(msg: &VideohubMessage) -> bool {
+///     match msg {
+///         VideohubMessage::ACK | VideohubMessage::NAK => true,
+///         _ => false,
+///     }
+/// }
+/// # assert!(is_reply(&VideohubMessage::ACK));
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
 pub enum VideohubMessage {
     /// `PROTOCOL PREAMBLE:`
     Preamble(Preamble),
@@ -233,7 +358,7 @@ pub enum VideohubMessage {
     InputLabels(Vec<Label>),
     /// `OUTPUT LABELS:`
     OutputLabels(Vec<Label>),
-    /// `MONITOR OUTPUT LABELS:`
+    /// `MONITORING OUTPUT LABELS:` or `MONITOR OUTPUT LABELS:`
     MonitorOutputLabels(Vec<Label>),
     /// `SERIAL PORT LABELS:`
     SerialPortLabels(Vec<Label>),
@@ -242,7 +367,7 @@ pub enum VideohubMessage {
 
     /// `VIDEO OUTPUT ROUTING:`
     VideoOutputRouting(Vec<Route>),
-    /// `VIDEO MONITORING OUTPUT ROUTING:`
+    /// `VIDEO MONITORING OUTPUT ROUTING:` or `VIDEO MONITOR OUTPUT ROUTING:`
     VideoMonitoringOutputRouting(Vec<Route>),
     /// `SERIAL PORT ROUTING:`
     SerialPortRouting(Vec<Route>),
@@ -253,7 +378,7 @@ pub enum VideohubMessage {
 
     /// `VIDEO OUTPUT LOCKS:`
     VideoOutputLocks(Vec<Lock>),
-    /// `MONITORING OUTPUT LOCKS:`
+    /// `MONITORING OUTPUT LOCKS:` or `MONITOR OUTPUT LOCKS:`
     MonitoringOutputLocks(Vec<Lock>),
     /// `SERIAL PORT LOCKS:`
     SerialPortLocks(Vec<Lock>),
@@ -286,3 +411,232 @@ pub enum VideohubMessage {
     /// Unknown Message
     UnknownMessage(BytesMut, BytesMut),
 }
+
+impl VideohubMessage {
+    /// Whether this asks the receiver to change state, like relabeling, routing or locking.
+    ///
+    /// Empty blocks are requests for the current state and don't count.
+    pub fn is_control_message(&self) -> bool {
+        match self {
+            VideohubMessage::InputLabels(v)
+            | VideohubMessage::OutputLabels(v)
+            | VideohubMessage::MonitorOutputLabels(v)
+            | VideohubMessage::SerialPortLabels(v)
+            | VideohubMessage::FrameLabels(v) => !v.is_empty(),
+            VideohubMessage::VideoOutputRouting(v)
+            | VideohubMessage::VideoMonitoringOutputRouting(v)
+            | VideohubMessage::SerialPortRouting(v)
+            | VideohubMessage::ProcessingUnitRouting(v)
+            | VideohubMessage::FrameBufferRouting(v) => !v.is_empty(),
+            VideohubMessage::VideoOutputLocks(v)
+            | VideohubMessage::MonitoringOutputLocks(v)
+            | VideohubMessage::SerialPortLocks(v)
+            | VideohubMessage::ProcessingUnitLocks(v)
+            | VideohubMessage::FrameBufferLocks(v) => !v.is_empty(),
+            VideohubMessage::Configuration(v) => !v.is_empty(),
+            VideohubMessage::Preamble(_)
+            | VideohubMessage::DeviceInfo(_)
+            | VideohubMessage::VideoInputStatus(_)
+            | VideohubMessage::VideoOutputStatus(_)
+            | VideohubMessage::SerialPortStatus(_)
+            | VideohubMessage::AlarmStatus(_)
+            | VideohubMessage::ACK
+            | VideohubMessage::NAK
+            | VideohubMessage::Ping
+            | VideohubMessage::EndPrelude
+            | VideohubMessage::UnknownMessage(..) => false,
+        }
+    }
+
+    /// Whether this only informs about the sender, like its device info or hardware status.
+    ///
+    /// Nothing is expected in return, and hubs have no way of changing any of it.
+    pub fn is_status_message(&self) -> bool {
+        match self {
+            VideohubMessage::Preamble(_)
+            | VideohubMessage::DeviceInfo(_)
+            | VideohubMessage::VideoInputStatus(_)
+            | VideohubMessage::VideoOutputStatus(_)
+            | VideohubMessage::SerialPortStatus(_)
+            | VideohubMessage::AlarmStatus(_)
+            | VideohubMessage::EndPrelude => true,
+            VideohubMessage::InputLabels(_)
+            | VideohubMessage::OutputLabels(_)
+            | VideohubMessage::MonitorOutputLabels(_)
+            | VideohubMessage::SerialPortLabels(_)
+            | VideohubMessage::FrameLabels(_)
+            | VideohubMessage::VideoOutputRouting(_)
+            | VideohubMessage::VideoMonitoringOutputRouting(_)
+            | VideohubMessage::SerialPortRouting(_)
+            | VideohubMessage::ProcessingUnitRouting(_)
+            | VideohubMessage::FrameBufferRouting(_)
+            | VideohubMessage::VideoOutputLocks(_)
+            | VideohubMessage::MonitoringOutputLocks(_)
+            | VideohubMessage::SerialPortLocks(_)
+            | VideohubMessage::ProcessingUnitLocks(_)
+            | VideohubMessage::FrameBufferLocks(_)
+            | VideohubMessage::Configuration(_)
+            | VideohubMessage::ACK
+            | VideohubMessage::NAK
+            | VideohubMessage::Ping
+            | VideohubMessage::UnknownMessage(..) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::HashSet,
+        hash::{BuildHasher, RandomState},
+    };
+
+    fn routing() -> VideohubMessage {
+        VideohubMessage::VideoOutputRouting(vec![Route {
+            from_input: 3,
+            to_output: 1,
+        }])
+    }
+
+    #[test]
+    fn equal_messages_hash_equal() {
+        let s = RandomState::new();
+        assert_eq!(s.hash_one(routing()), s.hash_one(routing().clone()));
+
+        // Unknown blocks hash by content, regardless of how the buffers were built.
+        let mut header = BytesMut::with_capacity(64);
+        header.extend_from_slice(b"FANCY NEW BLOCK:");
+        let a = VideohubMessage::UnknownMessage(header, BytesMut::from("x: 1\n"));
+        let b = VideohubMessage::UnknownMessage(
+            BytesMut::from("FANCY NEW BLOCK:"),
+            BytesMut::from("x: 1\n"),
+        );
+        assert_eq!(a, b);
+        assert_eq!(s.hash_one(&a), s.hash_one(&b));
+    }
+
+    #[test]
+    fn dedup_in_set() {
+        let set: HashSet<_> = [
+            routing(),
+            VideohubMessage::ACK,
+            routing(),
+            VideohubMessage::ACK,
+            VideohubMessage::UnknownMessage(BytesMut::from("A:"), BytesMut::new()),
+            VideohubMessage::UnknownMessage(BytesMut::from("A:"), BytesMut::new()),
+            VideohubMessage::UnknownMessage(BytesMut::from("B:"), BytesMut::new()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.len(), 4);
+        assert!(set.contains(&routing()));
+    }
+
+    #[test]
+    fn classification() {
+        let label = Label::from((0, "A"));
+        let route = Route::from((0, 1));
+        let lock = Lock::from((0, LockState::Owned));
+        let port = HardwarePort {
+            id: 0,
+            port_type: HardwarePortType::BNC,
+        };
+        let setting = Setting {
+            setting: "Take Mode".into(),
+            value: "true".into(),
+        };
+        use VideohubMessage::*;
+        let control: Vec<VideohubMessage> = vec![
+            InputLabels(vec![label.clone()]),
+            OutputLabels(vec![label.clone()]),
+            MonitorOutputLabels(vec![label.clone()]),
+            SerialPortLabels(vec![label.clone()]),
+            FrameLabels(vec![label.clone()]),
+            VideoOutputRouting(vec![route]),
+            VideoMonitoringOutputRouting(vec![route]),
+            SerialPortRouting(vec![route]),
+            ProcessingUnitRouting(vec![route]),
+            FrameBufferRouting(vec![route]),
+            VideoOutputLocks(vec![lock]),
+            MonitoringOutputLocks(vec![lock]),
+            SerialPortLocks(vec![lock]),
+            ProcessingUnitLocks(vec![lock]),
+            FrameBufferLocks(vec![lock]),
+            Configuration(vec![setting]),
+        ];
+        let status: Vec<VideohubMessage> = vec![
+            Preamble(super::Preamble {
+                version: "2.8".into(),
+            }),
+            DeviceInfo(Default::default()),
+            VideoInputStatus(vec![port.clone()]),
+            VideoOutputStatus(vec![port.clone()]),
+            SerialPortStatus(vec![port]),
+            AlarmStatus(vec![]),
+            EndPrelude,
+        ];
+        // Requests and protocol replies are neither.
+        let neither: Vec<VideohubMessage> = vec![
+            InputLabels(vec![]),
+            OutputLabels(vec![]),
+            MonitorOutputLabels(vec![]),
+            SerialPortLabels(vec![]),
+            FrameLabels(vec![]),
+            VideoOutputRouting(vec![]),
+            VideoMonitoringOutputRouting(vec![]),
+            SerialPortRouting(vec![]),
+            ProcessingUnitRouting(vec![]),
+            FrameBufferRouting(vec![]),
+            VideoOutputLocks(vec![]),
+            MonitoringOutputLocks(vec![]),
+            SerialPortLocks(vec![]),
+            ProcessingUnitLocks(vec![]),
+            FrameBufferLocks(vec![]),
+            Configuration(vec![]),
+            ACK,
+            NAK,
+            Ping,
+            UnknownMessage(BytesMut::from("A:"), BytesMut::new()),
+        ];
+        for m in &control {
+            assert!(m.is_control_message() && !m.is_status_message(), "{:?}", m);
+        }
+        for m in &status {
+            assert!(!m.is_control_message() && m.is_status_message(), "{:?}", m);
+        }
+        for m in &neither {
+            assert!(!m.is_control_message() && !m.is_status_message(), "{:?}", m);
+        }
+    }
+
+    #[test]
+    fn sort_canonical() {
+        let mut labels: Vec<Label> = vec![(2, "C").into(), (0, "B").into(), (0, "A").into()];
+        labels.sort_canonical();
+        assert_eq!(
+            labels,
+            [(0, "A").into(), (0, "B").into(), (2, "C").into()] as [Label; 3]
+        );
+
+        // By output, even though the input comes first in the struct.
+        let mut routes: Vec<Route> = vec![(1, 0).into(), (0, 5).into(), (2, 1).into()];
+        routes.sort_canonical();
+        assert_eq!(
+            routes.iter().map(|r| r.to_output).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert_eq!(
+            Route::from((3, 4)),
+            Route {
+                from_input: 4,
+                to_output: 3
+            }
+        );
+
+        let mut locks: Vec<Lock> =
+            vec![(3, LockState::Owned).into(), (1, LockState::Locked).into()];
+        locks.sort_canonical();
+        assert_eq!(locks.iter().map(|l| l.id).collect::<Vec<_>>(), [1, 3]);
+    }
+}
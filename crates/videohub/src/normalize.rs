@@ -0,0 +1,106 @@
+// Label normalization, matching what hardware hubs keep of a label.
+// Hubs cut labels somewhere around 30 to 40 bytes and drop control characters,
+// normalizing up front makes every backend agree on the result.
+
+use super::model::*;
+use alloc::string::String;
+
+/// Label length in bytes kept intact by all known hardware hubs.
+pub const HARDWARE_LABEL_LEN: usize = 30;
+
+impl Label {
+    /// Copy of the label without control characters, trimmed and cut to at most `max_len` bytes.
+    ///
+    /// Truncation never splits a multi-byte character, so the result may be shorter.
+    pub fn normalized(&self, max_len: usize) -> Label {
+        Label {
+            id: self.id,
+            name: normalize_name(&self.name, max_len),
+        }
+    }
+}
+
+fn normalize_name(name: &str, max_len: usize) -> String {
+    let cleaned: String = name.chars().filter(|c| !c.is_control()).collect();
+    let mut name = cleaned.trim();
+    if name.len() > max_len {
+        let mut end = max_len;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name = name[..end].trim_end();
+    }
+    name.into()
+}
+
+impl VideohubMessage {
+    /// Normalize all labels of a label block, see [Label::normalized].
+    /// Other messages are left alone.
+    pub fn normalize_labels(&mut self, max_len: usize) {
+        match self {
+            VideohubMessage::InputLabels(v)
+            | VideohubMessage::OutputLabels(v)
+            | VideohubMessage::MonitorOutputLabels(v)
+            | VideohubMessage::SerialPortLabels(v)
+            | VideohubMessage::FrameLabels(v) => {
+                for l in v.iter_mut() {
+                    *l = l.normalized(max_len);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(name: &str) -> Label {
+        Label {
+            id: 7,
+            name: name.into(),
+        }
+    }
+
+    #[test]
+    fn strips_and_trims() {
+        assert_eq!(label(" Cam\t1\x07 ").normalized(30), label("Cam1"));
+        assert_eq!(label("Cam\r\n2").normalized(30), label("Cam2"));
+        assert_eq!(label("   ").normalized(30), label(""));
+    }
+
+    #[test]
+    fn truncates_on_char_boundary() {
+        assert_eq!(label("Studio A").normalized(6), label("Studio"));
+        // Cutting "Studio A" after 7 bytes leaves a trailing space.
+        assert_eq!(label("Studio A").normalized(7), label("Studio"));
+        // "ü" is two bytes, "🎥" four.
+        assert_eq!(label("Über").normalized(1), label(""));
+        assert_eq!(label("Über").normalized(2), label("Ü"));
+        assert_eq!(label("A🎥B").normalized(4), label("A"));
+        assert_eq!(label("A🎥B").normalized(5), label("A🎥"));
+        let long = "é".repeat(40);
+        let n = label(&long).normalized(HARDWARE_LABEL_LEN);
+        assert_eq!(n.name, "é".repeat(15));
+    }
+
+    #[test]
+    fn only_touches_labels() {
+        let long = "x".repeat(40);
+        let mut m = VideohubMessage::OutputLabels(vec![label("Program\n"), label(&long)]);
+        m.normalize_labels(HARDWARE_LABEL_LEN);
+        assert_eq!(
+            m,
+            VideohubMessage::OutputLabels(vec![label("Program"), label(&"x".repeat(30))])
+        );
+
+        let mut alarm = VideohubMessage::AlarmStatus(vec![Alarm {
+            name: " Fan\t".into(),
+            status: "OK".into(),
+        }]);
+        let before = alarm.clone();
+        alarm.normalize_labels(1);
+        assert_eq!(alarm, before);
+    }
+}
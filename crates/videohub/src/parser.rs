@@ -2,11 +2,16 @@
 
 use crate::helpers::*;
 use crate::model::*;
-use bytes::BytesMut;
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use bytes::{Bytes, BytesMut};
+use core::ops::Range;
 use nom::{
     branch::alt,
     bytes::streaming::{tag, tag_no_case, take_until},
-    character::streaming::{multispace0, space1},
+    character::streaming::{multispace0, space0, space1},
     error::{Error, ErrorKind, ParseError},
     sequence::{preceded, terminated, tuple},
     Err, IResult,
@@ -14,16 +19,147 @@ use nom::{
 
 const COLON: &[u8] = b":";
 
-/// Parse one "Key: Value" line to (key, value) tuple
+/// Error of the string parsing conveniences, like [VideohubMessage::parse_str_all].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MessageParseError {
+    /// The input ended before the block was terminated by an empty line.
+    Incomplete,
+    /// A block is malformed, the parser gave up at byte `offset`.
+    Invalid { offset: usize, kind: String },
+    /// Input continues after the expected single block, starting at byte `offset`.
+    TrailingData { offset: usize },
+    /// A block has a header not known to the parser, only raised in strict mode.
+    UnknownBlock { header: String },
+    /// A block isn't valid UTF-8 from byte `offset` on, only raised with [Utf8Mode::Strict].
+    InvalidUtf8 { offset: usize },
+}
+
+/// How text that isn't valid UTF-8 is handled, see [ParseOptions::utf8].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Utf8Mode {
+    /// Reject blocks containing invalid UTF-8 with [MessageParseError::InvalidUtf8].
+    Strict,
+    /// Replace invalid sequences with U+FFFD.
+    #[default]
+    Lossy,
+    /// Like [Utf8Mode::Lossy], but also record where label names are in the input,
+    /// see [BlockSpans::raw_label_names].
+    Raw,
+}
+
+/// Options for [VideohubMessage::parse_single_block_with].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ParseOptions {
+    /// Reject blocks with unknown headers instead of parsing them as
+    /// [VideohubMessage::UnknownMessage].
+    pub strict: bool,
+    /// Handling of invalid UTF-8 in strings.
+    pub utf8: Utf8Mode,
+}
+
+/// Where a block came from, as byte ranges of the parsed input.
+///
+/// See [VideohubMessage::parse_single_block_spanned].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BlockSpans {
+    /// The header line, without its line ending.
+    pub header: Range<usize>,
+    /// The body lines including their line endings, empty for header-only blocks.
+    pub body: Range<usize>,
+    /// Names of the labels of a label block, only recorded with [Utf8Mode::Raw].
+    pub label_names: Vec<Range<usize>>,
+}
+
+impl BlockSpans {
+    /// Label names exactly as received, in the order of the parsed labels.
+    ///
+    /// `input` has to be what was parsed.
+    pub fn raw_label_names(&self, input: &Bytes) -> Vec<Bytes> {
+        self.label_names
+            .iter()
+            .map(|r| input.slice(r.clone()))
+            .collect()
+    }
+}
+
+impl core::fmt::Display for MessageParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            MessageParseError::Incomplete => f.write_str("incomplete block"),
+            MessageParseError::Invalid { offset, kind } => {
+                write!(f, "invalid block at byte {} ({})", offset, kind)
+            }
+            MessageParseError::TrailingData { offset } => {
+                write!(f, "trailing data after block at byte {}", offset)
+            }
+            MessageParseError::UnknownBlock { header } => write!(f, "unknown block {:?}", header),
+            MessageParseError::InvalidUtf8 { offset } => {
+                write!(f, "invalid UTF-8 at byte {}", offset)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MessageParseError {}
+
+impl MessageParseError {
+    /// Convert a nom error on (a subslice of) `input`.
+    fn from_nom(input: &[u8], e: Err<Error<&[u8]>>) -> Self {
+        match e {
+            Err::Incomplete(_) => MessageParseError::Incomplete,
+            Err::Error(e) | Err::Failure(e) => MessageParseError::Invalid {
+                offset: offset_in(input, e.input),
+                kind: e.code.description().to_string(),
+            },
+        }
+    }
+}
+
+impl MessageParseError {
+    /// Range of the line in `input` the error points at, without its line ending.
+    ///
+    /// `input` has to be what was parsed, offsets of [VideohubMessage::parse_str_all]
+    /// are relative to the whole string.
+    pub fn line_in(&self, input: &[u8]) -> Option<Range<usize>> {
+        let offset = match self {
+            MessageParseError::Invalid { offset, .. }
+            | MessageParseError::TrailingData { offset }
+            | MessageParseError::InvalidUtf8 { offset } => (*offset).min(input.len()),
+            _ => return None,
+        };
+        let start = input[..offset]
+            .iter()
+            .rposition(|&c| c == b'\n')
+            .map_or(0, |p| p + 1);
+        let end = input[offset..]
+            .iter()
+            .position(|&c| c == b'\r' || c == b'\n')
+            .map_or(input.len(), |p| offset + p);
+        Some(start..end)
+    }
+}
+
+/// Byte offset of `rest` within `input`, which it has to be a subslice of.
+fn offset_in(input: &[u8], rest: &[u8]) -> usize {
+    (rest.as_ptr() as usize)
+        .saturating_sub(input.as_ptr() as usize)
+        .min(input.len())
+}
+
+/// Raw "Key: Value" pairs of a block body.
+type KVPairs<'a> = Vec<(&'a [u8], &'a [u8])>;
+
+/// Parse one "Key: Value" line to (key, value) tuple, the space after the colon is optional
 fn parse_kv_line(i: &[u8]) -> IResult<&[u8], (&[u8], &[u8])> {
     let (i, (k, _, _, v, _)) = tuple((
         take_until(COLON),
         tag(COLON),
-        space1,
+        space0,
         take_until_newline,
         any_newline,
     ))(i)?;
-    Ok((i, (k.trim_ascii(), v.trim_ascii_end())))
+    Ok((i, (k.trim_ascii(), v.trim_ascii())))
 }
 
 /// Parse the body of a Preamble block after its header
@@ -61,7 +197,7 @@ fn parse_device_body(mut i: &[u8]) -> IResult<&[u8], VideohubMessage> {
             b"video monitoring outputs" => di.video_monitoring_outputs = Some(parse_u32(v)?.1),
             b"serial ports" => di.serial_ports = Some(parse_u32(v)?.1),
             _ => {
-                let mut unknown = di.unknown_fields.unwrap_or_else(|| Vec::new());
+                let mut unknown = di.unknown_fields.unwrap_or_else(Vec::new);
                 unknown.push(UnknownKVPair {
                     key: String::from_utf8_lossy(k).to_string(),
                     value: String::from_utf8_lossy(v).to_string(),
@@ -75,28 +211,32 @@ fn parse_device_body(mut i: &[u8]) -> IResult<&[u8], VideohubMessage> {
 }
 
 /// Parse generic "ID Name Here" label lines
-fn parse_label_body<'a>(
-    mut i: &'a [u8],
+fn parse_label_body(
+    mut i: &[u8],
     ctor: fn(Vec<Label>) -> VideohubMessage,
-) -> IResult<&'a [u8], VideohubMessage> {
+) -> IResult<&[u8], VideohubMessage> {
     let mut out = Vec::new();
-    while let Ok((i2, (id, _, nm, _))) =
-        tuple((parse_u32, space1, take_until_newline, any_newline))(i)
-    {
+    while let Ok((i2, (id, nm))) = parse_label_line(i) {
         out.push(Label {
             id,
-            name: String::from_utf8_lossy(nm.trim_ascii()).to_string(),
+            name: String::from_utf8_lossy(nm).to_string(),
         });
         i = i2;
     }
     Ok((i, ctor(out)))
 }
 
+/// Parse one "ID Name Here" line to (id, trimmed name) tuple
+fn parse_label_line(i: &[u8]) -> IResult<&[u8], (u32, &[u8])> {
+    let (i, (id, _, nm, _)) = tuple((parse_u32, space1, take_until_newline, any_newline))(i)?;
+    Ok((i, (id, nm.trim_ascii())))
+}
+
 /// Parse generic "to from" route lines
-fn parse_route_body<'a>(
-    mut i: &'a [u8],
+fn parse_route_body(
+    mut i: &[u8],
     ctor: fn(Vec<Route>) -> VideohubMessage,
-) -> IResult<&'a [u8], VideohubMessage> {
+) -> IResult<&[u8], VideohubMessage> {
     let mut out = Vec::new();
     while let Ok((i2, (t, _, f, _))) = tuple((parse_u32, space1, parse_u32, any_newline))(i) {
         out.push(Route {
@@ -108,11 +248,11 @@ fn parse_route_body<'a>(
     Ok((i, ctor(out)))
 }
 
-/// Parse generic "ID [O/L/U]" lines
-fn parse_lock_body<'a>(
-    mut i: &'a [u8],
+/// Parse generic "ID [O/L/U/F]" lines
+fn parse_lock_body(
+    mut i: &[u8],
     ctor: fn(Vec<Lock>) -> VideohubMessage,
-) -> IResult<&'a [u8], VideohubMessage> {
+) -> IResult<&[u8], VideohubMessage> {
     let mut out = Vec::new();
     while let Ok((i2, (id, _, s, _))) =
         tuple((parse_u32, space1, take_until_newline, any_newline))(i)
@@ -121,6 +261,8 @@ fn parse_lock_body<'a>(
             b"O" | b"o" => LockState::Owned,
             b"L" | b"l" => LockState::Locked,
             b"U" | b"u" => LockState::Unlocked,
+            b"F" | b"f" => LockState::Force,
+            [c] if c.is_ascii_alphabetic() => LockState::Other(*c as char),
             _ => return Err(Err::Error(Error::from_error_kind(i, ErrorKind::Tag))),
         };
         out.push(Lock { id, state });
@@ -130,10 +272,10 @@ fn parse_lock_body<'a>(
 }
 
 /// Parse generic "status" lines
-fn parse_hw_body<'a>(
-    mut i: &'a [u8],
+fn parse_hw_body(
+    mut i: &[u8],
     ctor: fn(Vec<HardwarePort>) -> VideohubMessage,
-) -> IResult<&'a [u8], VideohubMessage> {
+) -> IResult<&[u8], VideohubMessage> {
     let mut out = Vec::new();
     while let Ok((i2, (id, _, hw_type, _))) =
         tuple((parse_u32, space1, take_until_newline, any_newline))(i)
@@ -141,7 +283,7 @@ fn parse_hw_body<'a>(
         let tp = hw_type.trim_ascii_end();
         let lp = tp.to_ascii_lowercase();
         let port_type = match &lp[..] {
-            b"one" => HardwarePortType::None,
+            b"none" => HardwarePortType::None,
             b"bnc" => HardwarePortType::BNC,
             b"optical" => HardwarePortType::Optical,
             b"thunderbolt" => HardwarePortType::Thunderbolt,
@@ -157,7 +299,7 @@ fn parse_hw_body<'a>(
 /// Parse generic Key-Value lines
 fn parse_kv_body<'a>(
     mut i: &'a [u8],
-    ctor: fn(Vec<(&'a [u8], &'a [u8])>) -> VideohubMessage,
+    ctor: fn(KVPairs<'a>) -> VideohubMessage,
 ) -> IResult<&'a [u8], VideohubMessage> {
     let mut out = Vec::new();
     while let Ok((i2, (k, v))) = parse_kv_line(i) {
@@ -167,88 +309,197 @@ fn parse_kv_body<'a>(
     Ok((i, ctor(out)))
 }
 
+/// Split off one block including its trailing blank-line, returning its trimmed header and body.
+fn split_block(i: &[u8]) -> IResult<&[u8], (&[u8], &[u8])> {
+    let (i, header) = preceded(multispace0, terminated(take_until_newline, any_newline))(i)?;
+    let (i, body) = alt((any_newline, take_until_empty_line))(i)?;
+    Ok((i, (header.trim_ascii_end(), body)))
+}
+
+/// Parse the body of a block according to its header, `None` if the header is unknown.
+fn parse_body<'a>(
+    header: &[u8],
+    body: &'a [u8],
+) -> Result<Option<VideohubMessage>, Err<Error<&'a [u8]>>> {
+    let screaming_header = header.to_ascii_uppercase();
+    let (_, msg) = match &screaming_header[..] {
+        b"PROTOCOL PREAMBLE:" => parse_preamble_body(body)?,
+        b"VIDEOHUB DEVICE:" => parse_device_body(body)?,
+
+        b"INPUT LABELS:" => parse_label_body(body, VideohubMessage::InputLabels)?,
+        b"OUTPUT LABELS:" => parse_label_body(body, VideohubMessage::OutputLabels)?,
+        b"MONITORING OUTPUT LABELS:" | b"MONITOR OUTPUT LABELS:" => {
+            parse_label_body(body, VideohubMessage::MonitorOutputLabels)?
+        }
+        b"SERIAL PORT LABELS:" => parse_label_body(body, VideohubMessage::SerialPortLabels)?,
+        b"FRAME LABELS:" => parse_label_body(body, VideohubMessage::FrameLabels)?,
+
+        b"VIDEO OUTPUT ROUTING:" => parse_route_body(body, VideohubMessage::VideoOutputRouting)?,
+        b"VIDEO MONITORING OUTPUT ROUTING:" | b"VIDEO MONITOR OUTPUT ROUTING:" => {
+            parse_route_body(body, VideohubMessage::VideoMonitoringOutputRouting)?
+        }
+        b"SERIAL PORT ROUTING:" => parse_route_body(body, VideohubMessage::SerialPortRouting)?,
+        b"PROCESSING UNIT ROUTING:" => {
+            parse_route_body(body, VideohubMessage::ProcessingUnitRouting)?
+        }
+        b"FRAME BUFFER ROUTING:" => parse_route_body(body, VideohubMessage::FrameBufferRouting)?,
+
+        b"VIDEO OUTPUT LOCKS:" => parse_lock_body(body, VideohubMessage::VideoOutputLocks)?,
+        b"MONITORING OUTPUT LOCKS:" | b"MONITOR OUTPUT LOCKS:" => {
+            parse_lock_body(body, VideohubMessage::MonitoringOutputLocks)?
+        }
+        b"SERIAL PORT LOCKS:" => parse_lock_body(body, VideohubMessage::SerialPortLocks)?,
+        b"PROCESSING UNIT LOCKS:" => parse_lock_body(body, VideohubMessage::ProcessingUnitLocks)?,
+        b"FRAME BUFFER LOCKS:" => parse_lock_body(body, VideohubMessage::FrameBufferLocks)?,
+
+        b"VIDEO INPUT STATUS:" => parse_hw_body(body, VideohubMessage::VideoInputStatus)?,
+        b"VIDEO OUTPUT STATUS:" => parse_hw_body(body, VideohubMessage::VideoOutputStatus)?,
+        b"SERIAL PORT STATUS:" => parse_hw_body(body, VideohubMessage::SerialPortStatus)?,
+
+        b"ALARM STATUS:" => parse_kv_body(body, |vals| {
+            VideohubMessage::AlarmStatus(
+                vals.iter()
+                    .map(|t| Alarm {
+                        name: String::from_utf8_lossy(t.0.trim_ascii()).to_string(),
+                        status: String::from_utf8_lossy(t.1.trim_ascii()).to_string(),
+                    })
+                    .collect(),
+            )
+        })?,
+        b"CONFIGURATION:" => parse_kv_body(body, |vals| {
+            VideohubMessage::Configuration(
+                vals.iter()
+                    .map(|t| Setting {
+                        setting: String::from_utf8_lossy(t.0.trim_ascii()).to_string(),
+                        value: String::from_utf8_lossy(t.1.trim_ascii()).to_string(),
+                    })
+                    .collect(),
+            )
+        })?,
+
+        b"ACK" => (body, VideohubMessage::ACK),
+        b"NAK" => (body, VideohubMessage::NAK),
+        b"PING:" => (body, VideohubMessage::Ping),
+        b"END PRELUDE:" => (body, VideohubMessage::EndPrelude),
+
+        _ => return Ok(None),
+    };
+    Ok(Some(msg))
+}
+
 impl VideohubMessage {
     /// Parse one block including its trailing blank-line
     pub fn parse_single_block(i: &[u8]) -> IResult<&[u8], VideohubMessage> {
-        let (i, header) = preceded(multispace0, terminated(take_until_newline, any_newline))(i)?;
-        let (i, body) = alt((any_newline, take_until_empty_line))(i)?;
-        let trimmed_header = header.trim_ascii_end();
-        let screaming_header = trimmed_header.to_ascii_uppercase();
-        let (_, msg) = match &screaming_header[..] {
-            b"PROTOCOL PREAMBLE:" => parse_preamble_body(body)?,
-            b"VIDEOHUB DEVICE:" => parse_device_body(body)?,
-
-            b"INPUT LABELS:" => parse_label_body(body, VideohubMessage::InputLabels)?,
-            b"OUTPUT LABELS:" => parse_label_body(body, VideohubMessage::OutputLabels)?,
-            b"MONITOR OUTPUT LABELS:" => {
-                parse_label_body(body, VideohubMessage::MonitorOutputLabels)?
-            }
-            b"SERIAL PORT LABELS:" => parse_label_body(body, VideohubMessage::SerialPortLabels)?,
-            b"FRAME LABELS:" => parse_label_body(body, VideohubMessage::FrameLabels)?,
+        let (i, (header, body)) = split_block(i)?;
+        let msg = match parse_body(header, body)? {
+            Some(msg) => msg,
+            None => VideohubMessage::UnknownMessage(BytesMut::from(header), BytesMut::from(body)),
+        };
+        Ok((i, msg))
+    }
 
-            b"VIDEO OUTPUT ROUTING:" => {
-                parse_route_body(body, VideohubMessage::VideoOutputRouting)?
-            }
-            b"VIDEO MONITORING OUTPUT ROUTING:" => {
-                parse_route_body(body, VideohubMessage::VideoMonitoringOutputRouting)?
-            }
-            b"SERIAL PORT ROUTING:" => parse_route_body(body, VideohubMessage::SerialPortRouting)?,
-            b"PROCESSING UNIT ROUTING:" => {
-                parse_route_body(body, VideohubMessage::ProcessingUnitRouting)?
-            }
-            b"FRAME BUFFER ROUTING:" => {
-                parse_route_body(body, VideohubMessage::FrameBufferRouting)?
-            }
+    /// Parse one block including its trailing blank-line according to `opts`.
+    ///
+    /// Unlike [Self::parse_single_block], errors are reported as [MessageParseError],
+    /// with offsets relative to `i`.
+    pub fn parse_single_block_with(
+        i: &[u8],
+        opts: ParseOptions,
+    ) -> Result<(&[u8], VideohubMessage), MessageParseError> {
+        let (rest, msg, _) = Self::parse_single_block_spanned(i, opts)?;
+        Ok((rest, msg))
+    }
 
-            b"VIDEO OUTPUT LOCKS:" => parse_lock_body(body, VideohubMessage::VideoOutputLocks)?,
-            b"MONITORING OUTPUT LOCKS:" => {
-                parse_lock_body(body, VideohubMessage::MonitoringOutputLocks)?
+    /// Like [Self::parse_single_block_with], but also returning where the block's
+    /// header and body are within `i`.
+    pub fn parse_single_block_spanned(
+        i: &[u8],
+        opts: ParseOptions,
+    ) -> Result<(&[u8], VideohubMessage, BlockSpans), MessageParseError> {
+        let (rest, (header, body)) =
+            split_block(i).map_err(|e| MessageParseError::from_nom(i, e))?;
+        if opts.utf8 == Utf8Mode::Strict {
+            let block = &i[..offset_in(i, rest)];
+            if let Err(e) = core::str::from_utf8(block) {
+                return Err(MessageParseError::InvalidUtf8 {
+                    offset: e.valid_up_to(),
+                });
+            }
+        }
+        let msg = match parse_body(header, body).map_err(|e| MessageParseError::from_nom(i, e))? {
+            Some(msg) => msg,
+            None if opts.strict => {
+                return Err(MessageParseError::UnknownBlock {
+                    header: String::from_utf8_lossy(header).into_owned(),
+                })
             }
-            b"SERIAL PORT LOCKS:" => parse_lock_body(body, VideohubMessage::SerialPortLocks)?,
-            b"PROCESSING UNIT LOCKS:" => {
-                parse_lock_body(body, VideohubMessage::ProcessingUnitLocks)?
+            None => VideohubMessage::UnknownMessage(BytesMut::from(header), BytesMut::from(body)),
+        };
+        let header_start = offset_in(i, header);
+        let body_start = offset_in(i, body);
+        // Header-only blocks get the empty line as body, which isn't part of it.
+        let body_len = if body.trim_ascii().is_empty() {
+            0
+        } else {
+            body.len()
+        };
+        let mut label_names = Vec::new();
+        let is_label_block = matches!(
+            msg,
+            VideohubMessage::InputLabels(_)
+                | VideohubMessage::OutputLabels(_)
+                | VideohubMessage::MonitorOutputLabels(_)
+                | VideohubMessage::SerialPortLabels(_)
+                | VideohubMessage::FrameLabels(_)
+        );
+        if opts.utf8 == Utf8Mode::Raw && is_label_block {
+            let mut b = body;
+            while let Ok((b2, (_, nm))) = parse_label_line(b) {
+                let start = offset_in(i, nm);
+                label_names.push(start..start + nm.len());
+                b = b2;
             }
-            b"FRAME BUFFER LOCKS:" => parse_lock_body(body, VideohubMessage::FrameBufferLocks)?,
-
-            b"VIDEO INPUT STATUS:" => parse_hw_body(body, VideohubMessage::VideoInputStatus)?,
-            b"VIDEO OUTPUT STATUS:" => parse_hw_body(body, VideohubMessage::VideoOutputStatus)?,
-            b"SERIAL PORT STATUS:" => parse_hw_body(body, VideohubMessage::SerialPortStatus)?,
-
-            b"ALARM STATUS:" => parse_kv_body(body, |vals| {
-                VideohubMessage::AlarmStatus(
-                    vals.iter()
-                        .map(|t| Alarm {
-                            name: String::from_utf8_lossy(t.0.trim_ascii()).to_string(),
-                            status: String::from_utf8_lossy(t.1.trim_ascii()).to_string(),
-                        })
-                        .collect(),
-                )
-            })?,
-            b"CONFIGURATION:" => parse_kv_body(body, |vals| {
-                VideohubMessage::Configuration(
-                    vals.iter()
-                        .map(|t| Setting {
-                            setting: String::from_utf8_lossy(t.0.trim_ascii()).to_string(),
-                            value: String::from_utf8_lossy(t.1.trim_ascii()).to_string(),
-                        })
-                        .collect(),
-                )
-            })?,
-
-            b"ACK" => (i, VideohubMessage::ACK),
-            b"NAK" => (i, VideohubMessage::ACK),
-            b"PING:" => (i, VideohubMessage::Ping),
-            b"END PRELUDE:" => (i, VideohubMessage::EndPrelude),
-
-            _ => (
-                b"".as_slice(),
-                VideohubMessage::UnknownMessage(
-                    BytesMut::from(trimmed_header),
-                    BytesMut::from(body),
-                ),
-            ),
+        }
+        let spans = BlockSpans {
+            header: header_start..header_start + header.len(),
+            body: body_start..body_start + body_len,
+            label_names,
         };
-        Ok((i, msg))
+        Ok((rest, msg, spans))
+    }
+
+    /// Parse a string containing exactly one complete block.
+    ///
+    /// Trailing whitespace is fine, anything else after the block is an error.
+    pub fn parse_str(s: &str) -> Result<VideohubMessage, MessageParseError> {
+        let input = s.as_bytes();
+        let (rest, msg) = Self::parse_single_block_with(input, ParseOptions::default())?;
+        if !rest.trim_ascii().is_empty() {
+            return Err(MessageParseError::TrailingData {
+                offset: offset_in(input, rest),
+            });
+        }
+        Ok(msg)
+    }
+
+    /// Parse a string of complete blocks.
+    pub fn parse_str_all(s: &str) -> Result<Vec<VideohubMessage>, MessageParseError> {
+        let input = s.as_bytes();
+        let mut i = input;
+        let mut messages = Vec::new();
+        while !i.trim_ascii().is_empty() {
+            let (ni, msg) =
+                Self::parse_single_block_with(i, ParseOptions::default()).map_err(|e| match e {
+                    MessageParseError::Invalid { offset, kind } => MessageParseError::Invalid {
+                        offset: offset_in(input, i) + offset,
+                        kind,
+                    },
+                    e => e,
+                })?;
+            messages.push(msg);
+            i = ni;
+        }
+        Ok(messages)
     }
 
     /// Parse an entire Videohub conversation of multiple messages.
@@ -266,12 +517,48 @@ impl VideohubMessage {
     }
 }
 
+impl VideohubMessage {
+    /// Parse all complete blocks, returning them along with the unconsumed rest of `input`.
+    ///
+    /// Meant for buffers filled incrementally: the rest is empty or starts with a block that
+    /// isn't complete yet, to be parsed again once more data arrived. Parsing also stops at a
+    /// malformed block, which then starts the rest.
+    pub fn parse_all_blocks_partial(input: &[u8]) -> (Vec<VideohubMessage>, &[u8]) {
+        let mut i = input;
+        let mut messages = Vec::new();
+        while let Ok((ni, message)) = Self::parse_single_block(i) {
+            messages.push(message);
+            i = ni;
+        }
+        (messages, i)
+    }
+}
+
+impl TryFrom<&str> for VideohubMessage {
+    type Error = MessageParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::parse_str(s)
+    }
+}
+
+impl core::str::FromStr for VideohubMessage {
+    type Err = MessageParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_str(s)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     const BMD_EXAMPLE: &[u8] = include_bytes!("./bmd_example.txt");
     const BMD_CLEANSWITCH: &[u8] = include_bytes!("./bmd_cleanswitch_12x12.txt");
+    const BMD_MONITOR: &[u8] = include_bytes!("./bmd_monitor_labels.txt");
+    const BMD_MONITORING: &[u8] = include_bytes!("./bmd_monitoring_labels.txt");
+    const BMD_FRAME_BUFFERS: &[u8] = include_bytes!("./bmd_frame_buffers.txt");
 
     #[test]
     fn parse_only_preamble() {
@@ -294,6 +581,13 @@ mod tests {
         assert_eq!(msg, VideohubMessage::Ping);
     }
 
+    #[test]
+    fn parse_nak_is_nak() {
+        let (rem, msg) = VideohubMessage::parse_single_block(b"NAK\n\n").expect("should parse NAK");
+        assert!(rem.is_empty(), "remaining = {:?}", rem);
+        assert_eq!(msg, VideohubMessage::NAK);
+    }
+
     #[test]
     fn parse_only_deviceinfo() {
         let buf = b"VIDEOHUB DEVICE:\r\n\
@@ -358,6 +652,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_lock_states() {
+        let buf = b"VIDEO OUTPUT LOCKS:\n0 O\n1 l\n2 U\n3 F\n4 X\n\n";
+        let (rem, msg) = VideohubMessage::parse_single_block(buf).expect("should parse locks");
+        assert!(rem.is_empty(), "remaining = {:?}", rem);
+        let states: Vec<LockState> = match msg {
+            VideohubMessage::VideoOutputLocks(v) => v.into_iter().map(|l| l.state).collect(),
+            _ => panic!("expected VideoOutputLocks, got {:?}", msg),
+        };
+        assert_eq!(
+            states,
+            vec![
+                LockState::Owned,
+                LockState::Locked,
+                LockState::Unlocked,
+                LockState::Force,
+                LockState::Other('X'),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_lock_invalid_state() {
+        let buf = b"VIDEO OUTPUT LOCKS:\n0 Owned\n\n";
+        assert!(VideohubMessage::parse_single_block(buf).is_err());
+    }
+
     #[test]
     fn parse_partial() {
         let mut buf: Vec<u8> = Vec::from(b"INPUT ");
@@ -380,6 +701,43 @@ mod tests {
         assert_eq!(rem, b"OUTPUT LABELS:\n");
     }
 
+    #[test]
+    fn kv_separator_spacing() {
+        for sep in [":", ": ", ":    ", ":\t", ": \t "] {
+            let buf = format!(
+                "PROTOCOL PREAMBLE:\nVersion{sep}2.8\n\n\
+                 VIDEOHUB DEVICE:\nModel name{sep}Smart Videohub\nVideo inputs{sep}12\n\n\
+                 CONFIGURATION:\nTake Mode{sep}true\n\n\
+                 ALARM STATUS:\nFan{sep}ok\n\n"
+            );
+            let (rem, msgs) = VideohubMessage::parse_all_blocks(buf.as_bytes()).unwrap();
+            assert!(rem.is_empty(), "{:?}: remaining = {:?}", sep, rem);
+            assert_eq!(
+                msgs,
+                [
+                    VideohubMessage::Preamble(Preamble {
+                        version: "2.8".into()
+                    }),
+                    VideohubMessage::DeviceInfo(DeviceInfo {
+                        model_name: Some("Smart Videohub".into()),
+                        video_inputs: Some(12),
+                        ..Default::default()
+                    }),
+                    VideohubMessage::Configuration(vec![Setting {
+                        setting: "Take Mode".into(),
+                        value: "true".into()
+                    }]),
+                    VideohubMessage::AlarmStatus(vec![Alarm {
+                        name: "Fan".into(),
+                        status: "ok".into()
+                    }]),
+                ],
+                "separator {:?}",
+                sep
+            );
+        }
+    }
+
     #[test]
     fn parse_multiple_sections() {
         let buf = b"PROTOCOL PREAMBLE:\nVersion:2.4\n\nINPUT LABELS:\n0 A\n\n";
@@ -505,4 +863,278 @@ mod tests {
         }
         assert_eq!(&msgs[7], &VideohubMessage::EndPrelude);
     }
+
+    #[test]
+    fn parse_monitor_spellings() {
+        let (rem, monitor) = VideohubMessage::parse_all_blocks(BMD_MONITOR).unwrap();
+        assert!(rem.is_empty(), "remaining = {:?}", rem);
+        let (rem, monitoring) = VideohubMessage::parse_all_blocks(BMD_MONITORING).unwrap();
+        assert!(rem.is_empty(), "remaining = {:?}", rem);
+
+        // Both spellings land in the same variants.
+        assert_eq!(monitor.len(), 2);
+        assert_eq!(monitoring.len(), 3);
+        assert_eq!(monitor[0], monitoring[0]);
+        assert_eq!(monitor[1], monitoring[2]);
+        match &monitor[0] {
+            VideohubMessage::MonitorOutputLabels(v) => {
+                assert_eq!(v.len(), 2);
+                assert_eq!(&v[1].name, "Monitor 2");
+            }
+            _ => panic!("expected MonitorOutputLabels, got {:?}", monitor[0]),
+        }
+        assert!(matches!(
+            monitoring[1],
+            VideohubMessage::VideoMonitoringOutputRouting(_)
+        ));
+        assert!(matches!(
+            monitor[1],
+            VideohubMessage::MonitoringOutputLocks(_)
+        ));
+    }
+
+    #[test]
+    fn parse_frame_buffers() {
+        let (rem, msgs) = VideohubMessage::parse_all_blocks(BMD_FRAME_BUFFERS).unwrap();
+        assert!(rem.is_empty(), "remaining = {:?}", rem);
+        assert_eq!(msgs.len(), 3);
+        match &msgs[0] {
+            VideohubMessage::FrameLabels(v) => {
+                assert_eq!(v.len(), 4);
+                assert_eq!(&v[3].name, "Frame 4");
+            }
+            _ => panic!("expected FrameLabels, got {:?}", msgs[0]),
+        }
+        match &msgs[1] {
+            VideohubMessage::FrameBufferRouting(v) => {
+                assert_eq!(v.len(), 4);
+                assert_eq!(v[3].to_output, 3);
+                assert_eq!(v[3].from_input, 11);
+            }
+            _ => panic!("expected FrameBufferRouting, got {:?}", msgs[1]),
+        }
+        match &msgs[2] {
+            VideohubMessage::FrameBufferLocks(v) => assert_eq!(v[2].state, LockState::Locked),
+            _ => panic!("expected FrameBufferLocks, got {:?}", msgs[2]),
+        }
+    }
+
+    #[test]
+    fn parse_str_single() {
+        let msg: VideohubMessage = "PING:\n\n".parse().unwrap();
+        assert_eq!(msg, VideohubMessage::Ping);
+        let msg = VideohubMessage::try_from("VIDEO OUTPUT ROUTING:\r\n0 5\r\n\r\n\n").unwrap();
+        assert_eq!(
+            msg,
+            VideohubMessage::VideoOutputRouting(vec![Route {
+                from_input: 5,
+                to_output: 0,
+            }])
+        );
+    }
+
+    #[test]
+    fn parse_str_errors() {
+        assert_eq!(
+            VideohubMessage::parse_str("PING:\n"),
+            Err(MessageParseError::Incomplete)
+        );
+        assert_eq!(
+            VideohubMessage::parse_str(""),
+            Err(MessageParseError::Incomplete)
+        );
+        assert_eq!(
+            VideohubMessage::parse_str("PING:\n\nACK\n\n"),
+            Err(MessageParseError::TrailingData { offset: 7 })
+        );
+        assert_eq!(
+            VideohubMessage::parse_str("PING:\n\ngarbage"),
+            Err(MessageParseError::TrailingData { offset: 7 })
+        );
+        match VideohubMessage::parse_str("VIDEO OUTPUT LOCKS:\n0 U\n1 Owned\n\n") {
+            Err(MessageParseError::Invalid { offset, .. }) => assert_eq!(offset, 24),
+            r => panic!("expected Invalid, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn utf8_modes() {
+        let input = b"INPUT LABELS:\n0 Cam\xff 1\n1 Caf\xc3\xa9\n\n";
+        let opts = |utf8| ParseOptions {
+            utf8,
+            ..Default::default()
+        };
+        let lossy = VideohubMessage::InputLabels(vec![
+            Label {
+                id: 0,
+                name: "Cam\u{fffd} 1".into(),
+            },
+            Label {
+                id: 1,
+                name: "Café".into(),
+            },
+        ]);
+
+        let (_, msg, spans) =
+            VideohubMessage::parse_single_block_spanned(input, opts(Utf8Mode::Lossy)).unwrap();
+        assert_eq!(msg, lossy);
+        assert!(spans.label_names.is_empty());
+
+        assert_eq!(
+            VideohubMessage::parse_single_block_with(input, opts(Utf8Mode::Strict)),
+            Err(MessageParseError::InvalidUtf8 { offset: 19 })
+        );
+        assert_eq!(
+            MessageParseError::InvalidUtf8 { offset: 19 }.line_in(input),
+            Some(14..22)
+        );
+        let valid = b"INPUT LABELS:\n1 Caf\xc3\xa9\n\n";
+        assert!(VideohubMessage::parse_single_block_with(valid, opts(Utf8Mode::Strict)).is_ok());
+
+        let (_, msg, spans) =
+            VideohubMessage::parse_single_block_spanned(input, opts(Utf8Mode::Raw)).unwrap();
+        assert_eq!(msg, lossy);
+        assert_eq!(
+            spans.raw_label_names(&Bytes::from_static(input)),
+            [&b"Cam\xff 1"[..], "Café".as_bytes()]
+        );
+        // Only label blocks record names.
+        let (_, _, spans) = VideohubMessage::parse_single_block_spanned(
+            b"VIDEO OUTPUT ROUTING:\n0 1\n\n",
+            opts(Utf8Mode::Raw),
+        )
+        .unwrap();
+        assert!(spans.label_names.is_empty());
+    }
+
+    #[test]
+    fn spans() {
+        let input = b"\nPING:\n\nVIDEO OUTPUT LOCKS:\r\n0 U\r\n1 L\r\n\r\n";
+        let opts = ParseOptions::default();
+        let (rest, msg, spans) = VideohubMessage::parse_single_block_spanned(input, opts).unwrap();
+        assert_eq!(msg, VideohubMessage::Ping);
+        assert_eq!(&input[spans.header.clone()], b"PING:");
+        assert_eq!(spans.body, 7..7);
+
+        let (_, _, spans) = VideohubMessage::parse_single_block_spanned(rest, opts).unwrap();
+        assert_eq!(&rest[spans.header], b"VIDEO OUTPUT LOCKS:");
+        assert_eq!(&rest[spans.body], b"0 U\r\n1 L\r\n");
+    }
+
+    #[test]
+    fn error_line() {
+        let input = "PING:\n\nVIDEO OUTPUT LOCKS:\r\n0 U\r\n1 Owned\r\n2 L\r\n\r\n";
+        let e = VideohubMessage::parse_str_all(input).unwrap_err();
+        let line = e.line_in(input.as_bytes()).unwrap();
+        assert_eq!(&input[line], "1 Owned");
+
+        let e = VideohubMessage::parse_str("PING:\n\nACK\n\n").unwrap_err();
+        assert_eq!(e.line_in(b"PING:\n\nACK\n\n"), Some(7..10));
+        assert_eq!(MessageParseError::Incomplete.line_in(b"PING:"), None);
+    }
+
+    #[test]
+    fn parse_partial_blocks() {
+        let (msgs, rest) = VideohubMessage::parse_all_blocks_partial(b"PING:\n\nACK\n\nINPUT LA");
+        assert_eq!(msgs, [VideohubMessage::Ping, VideohubMessage::ACK]);
+        assert_eq!(rest, b"INPUT LA");
+
+        let (msgs, rest) = VideohubMessage::parse_all_blocks_partial(b"");
+        assert!(msgs.is_empty() && rest.is_empty());
+
+        // Malformed blocks aren't skipped.
+        let bad = b"PING:\n\nVIDEO OUTPUT LOCKS:\n0 Owned\n\nACK\n\n";
+        let (msgs, rest) = VideohubMessage::parse_all_blocks_partial(bad);
+        assert_eq!(msgs, [VideohubMessage::Ping]);
+        assert_eq!(rest, &bad[7..]);
+    }
+
+    #[test]
+    fn parse_partial_every_split() {
+        let (_, all) = VideohubMessage::parse_all_blocks(BMD_EXAMPLE).unwrap();
+        for at in 0..=BMD_EXAMPLE.len() {
+            let (mut msgs, rest) = VideohubMessage::parse_all_blocks_partial(&BMD_EXAMPLE[..at]);
+            let mut buf = rest.to_vec();
+            buf.extend_from_slice(&BMD_EXAMPLE[at..]);
+            let (more, rest) = VideohubMessage::parse_all_blocks_partial(&buf);
+            msgs.extend(more);
+            assert_eq!(msgs, all, "split at {}", at);
+            assert!(rest.is_empty(), "split at {}", at);
+        }
+    }
+
+    #[test]
+    fn parse_str_all_blocks() {
+        let example = std::str::from_utf8(BMD_EXAMPLE).unwrap();
+        let msgs = VideohubMessage::parse_str_all(example).unwrap();
+        assert_eq!(
+            msgs,
+            VideohubMessage::parse_all_blocks(BMD_EXAMPLE).unwrap().1
+        );
+        assert_eq!(VideohubMessage::parse_str_all("\n"), Ok(vec![]));
+        assert_eq!(
+            VideohubMessage::parse_str_all("PING:\n\nACK\n"),
+            Err(MessageParseError::Incomplete)
+        );
+        assert!(VideohubMessage::parse_str("PING:\n\nACK\n\n").is_err());
+    }
+
+    const UNKNOWN_THEN_LABELS: &[u8] =
+        b"INPTU LABELS:\n0 Camera 1\n\nINPUT LABELS:\n0 Camera 1\n\n";
+
+    #[test]
+    fn parse_unknown_lenient() {
+        let (rem, unknown) = VideohubMessage::parse_single_block(UNKNOWN_THEN_LABELS).unwrap();
+        assert_eq!(
+            unknown,
+            VideohubMessage::UnknownMessage(
+                BytesMut::from(&b"INPTU LABELS:"[..]),
+                BytesMut::from(&b"0 Camera 1\n"[..]),
+            )
+        );
+        assert_eq!(rem, b"INPUT LABELS:\n0 Camera 1\n\n");
+
+        let (rem, labels) =
+            VideohubMessage::parse_single_block_with(rem, ParseOptions::default()).unwrap();
+        assert!(rem.is_empty());
+        assert_eq!(
+            labels,
+            VideohubMessage::InputLabels(vec![Label {
+                id: 0,
+                name: "Camera 1".into(),
+            }])
+        );
+        assert_eq!(
+            VideohubMessage::parse_all_blocks(UNKNOWN_THEN_LABELS)
+                .unwrap()
+                .1
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn parse_unknown_strict() {
+        let strict = ParseOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let err = VideohubMessage::parse_single_block_with(UNKNOWN_THEN_LABELS, strict);
+        assert_eq!(
+            err,
+            Err(MessageParseError::UnknownBlock {
+                header: "INPTU LABELS:".into()
+            })
+        );
+        assert!(err.unwrap_err().to_string().contains("INPTU LABELS:"));
+
+        // Known blocks are unaffected.
+        let (rem, msg) = VideohubMessage::parse_single_block_with(b"PING:\n\n", strict).unwrap();
+        assert!(rem.is_empty());
+        assert_eq!(msg, VideohubMessage::Ping);
+        assert_eq!(
+            VideohubMessage::parse_single_block_with(b"PING:\n", strict),
+            Err(MessageParseError::Incomplete)
+        );
+    }
 }
@@ -0,0 +1,572 @@
+// Canonical initial dump of a server, in the order hardware sends it.
+
+use super::model::*;
+use super::profile::ProtocolProfile;
+use alloc::{format, string::String, vec, vec::Vec};
+
+/// Optional blocks to announce in [build_prelude].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct PreludeBlocks {
+    /// Monitoring output labels, locks and routing.
+    pub monitoring: bool,
+    /// Serial port labels, locks and routing.
+    pub serial: bool,
+    /// Configuration settings.
+    pub configuration: bool,
+}
+
+/// Everything a server announces in its initial dump.
+///
+/// Port counts come from [VideohubState::device], frame buffers aren't part of it and
+/// have their own [VideohubState::frame_count].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VideohubState {
+    /// Protocol version sent in the preamble.
+    pub version: String,
+    pub device: DeviceInfo,
+    pub frame_count: u32,
+
+    pub input_labels: Vec<Label>,
+    pub output_labels: Vec<Label>,
+    pub monitoring_output_labels: Vec<Label>,
+    pub serial_port_labels: Vec<Label>,
+    pub frame_labels: Vec<Label>,
+
+    pub video_output_locks: Vec<Lock>,
+    pub monitoring_output_locks: Vec<Lock>,
+    pub serial_port_locks: Vec<Lock>,
+    pub processing_unit_locks: Vec<Lock>,
+    pub frame_buffer_locks: Vec<Lock>,
+
+    pub video_output_routing: Vec<Route>,
+    pub monitoring_output_routing: Vec<Route>,
+    pub serial_port_routing: Vec<Route>,
+    pub processing_unit_routing: Vec<Route>,
+    pub frame_buffer_routing: Vec<Route>,
+
+    pub configuration: Vec<Setting>,
+    pub include: PreludeBlocks,
+}
+
+/// Replace entries with the same id, keeping the rest.
+fn merge<T: Clone>(into: &mut Vec<T>, changes: &[T], id: impl Fn(&T) -> u32) {
+    for c in changes {
+        match into.iter_mut().find(|e| id(e) == id(c)) {
+            Some(e) => *e = c.clone(),
+            None => into.push(c.clone()),
+        }
+    }
+}
+
+impl VideohubState {
+    /// Update the state from a message, like a client following a server would.
+    ///
+    /// Blocks only carry changes, entries not mentioned are kept.
+    pub fn apply(&mut self, msg: &VideohubMessage) {
+        let label = |l: &Label| l.id;
+        let lock = |l: &Lock| l.id;
+        let route = |r: &Route| r.to_output;
+        match msg {
+            VideohubMessage::Preamble(p) => self.version = p.version.clone(),
+            VideohubMessage::DeviceInfo(d) => self.device = d.clone(),
+
+            VideohubMessage::InputLabels(v) => merge(&mut self.input_labels, v, label),
+            VideohubMessage::OutputLabels(v) => merge(&mut self.output_labels, v, label),
+            VideohubMessage::MonitorOutputLabels(v) => {
+                merge(&mut self.monitoring_output_labels, v, label)
+            }
+            VideohubMessage::SerialPortLabels(v) => merge(&mut self.serial_port_labels, v, label),
+            VideohubMessage::FrameLabels(v) => merge(&mut self.frame_labels, v, label),
+
+            VideohubMessage::VideoOutputLocks(v) => merge(&mut self.video_output_locks, v, lock),
+            VideohubMessage::MonitoringOutputLocks(v) => {
+                merge(&mut self.monitoring_output_locks, v, lock)
+            }
+            VideohubMessage::SerialPortLocks(v) => merge(&mut self.serial_port_locks, v, lock),
+            VideohubMessage::ProcessingUnitLocks(v) => {
+                merge(&mut self.processing_unit_locks, v, lock)
+            }
+            VideohubMessage::FrameBufferLocks(v) => merge(&mut self.frame_buffer_locks, v, lock),
+
+            VideohubMessage::VideoOutputRouting(v) => {
+                merge(&mut self.video_output_routing, v, route)
+            }
+            VideohubMessage::VideoMonitoringOutputRouting(v) => {
+                merge(&mut self.monitoring_output_routing, v, route)
+            }
+            VideohubMessage::SerialPortRouting(v) => merge(&mut self.serial_port_routing, v, route),
+            VideohubMessage::ProcessingUnitRouting(v) => {
+                merge(&mut self.processing_unit_routing, v, route)
+            }
+            VideohubMessage::FrameBufferRouting(v) => {
+                merge(&mut self.frame_buffer_routing, v, route)
+            }
+
+            VideohubMessage::Configuration(v) => {
+                for s in v {
+                    match self
+                        .configuration
+                        .iter_mut()
+                        .find(|c| c.setting == s.setting)
+                    {
+                        Some(c) => c.value = s.value.clone(),
+                        None => self.configuration.push(s.clone()),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Labels `0..count`, unnamed ones called `{prefix} {id + 1}` like on a fresh hub.
+/// Without a count, the known labels are sorted by id.
+fn dense_labels(labels: &[Label], count: Option<u32>, prefix: &str) -> Vec<Label> {
+    let Some(count) = count else {
+        let mut v = labels.to_vec();
+        v.sort_canonical();
+        return v;
+    };
+    (0..count)
+        .map(|id| match labels.iter().find(|l| l.id == id) {
+            Some(l) => l.clone(),
+            None => Label {
+                id,
+                name: format!("{} {}", prefix, id + 1),
+            },
+        })
+        .collect()
+}
+
+/// Locks `0..count`, unmentioned ones unlocked.
+/// Without a count, the known locks are sorted by id.
+fn dense_locks(locks: &[Lock], count: Option<u32>) -> Vec<Lock> {
+    let Some(count) = count else {
+        let mut v = locks.to_vec();
+        v.sort_canonical();
+        return v;
+    };
+    (0..count)
+        .map(|id| match locks.iter().find(|l| l.id == id) {
+            Some(l) => *l,
+            None => Lock {
+                id,
+                state: LockState::Unlocked,
+            },
+        })
+        .collect()
+}
+
+fn sorted_routes(routes: &[Route]) -> Vec<Route> {
+    let mut v = routes.to_vec();
+    v.sort_canonical();
+    v
+}
+
+/// The state part of [build_prelude], without preamble and `END PRELUDE:`.
+///
+/// This is also what a server resends once its device comes back.
+/// A device that isn't present only gets its [DeviceInfo] announced.
+pub fn build_state_dump(state: &VideohubState) -> Vec<VideohubMessage> {
+    let d = &state.device;
+    let mut out = vec![VideohubMessage::DeviceInfo(d.clone())];
+    if d.present != Some(Present::Yes) {
+        return out;
+    }
+    let inc = state.include;
+    let frames = state.frame_count > 0;
+    let processing = d.video_processing_units.unwrap_or(0) > 0;
+
+    // Labels
+    out.push(VideohubMessage::InputLabels(dense_labels(
+        &state.input_labels,
+        d.video_inputs,
+        "Input",
+    )));
+    out.push(VideohubMessage::OutputLabels(dense_labels(
+        &state.output_labels,
+        d.video_outputs,
+        "Output",
+    )));
+    if inc.monitoring {
+        out.push(VideohubMessage::MonitorOutputLabels(dense_labels(
+            &state.monitoring_output_labels,
+            d.video_monitoring_outputs,
+            "Monitor",
+        )));
+    }
+    if inc.serial {
+        out.push(VideohubMessage::SerialPortLabels(dense_labels(
+            &state.serial_port_labels,
+            d.serial_ports,
+            "Serial",
+        )));
+    }
+    if frames {
+        out.push(VideohubMessage::FrameLabels(dense_labels(
+            &state.frame_labels,
+            Some(state.frame_count),
+            "Frame",
+        )));
+    }
+
+    // Locks
+    out.push(VideohubMessage::VideoOutputLocks(dense_locks(
+        &state.video_output_locks,
+        d.video_outputs,
+    )));
+    if inc.monitoring {
+        out.push(VideohubMessage::MonitoringOutputLocks(dense_locks(
+            &state.monitoring_output_locks,
+            d.video_monitoring_outputs,
+        )));
+    }
+    if inc.serial {
+        out.push(VideohubMessage::SerialPortLocks(dense_locks(
+            &state.serial_port_locks,
+            d.serial_ports,
+        )));
+    }
+    if processing {
+        out.push(VideohubMessage::ProcessingUnitLocks(dense_locks(
+            &state.processing_unit_locks,
+            d.video_processing_units,
+        )));
+    }
+    if frames {
+        out.push(VideohubMessage::FrameBufferLocks(dense_locks(
+            &state.frame_buffer_locks,
+            Some(state.frame_count),
+        )));
+    }
+
+    // Routing
+    out.push(VideohubMessage::VideoOutputRouting(sorted_routes(
+        &state.video_output_routing,
+    )));
+    if inc.monitoring {
+        out.push(VideohubMessage::VideoMonitoringOutputRouting(
+            sorted_routes(&state.monitoring_output_routing),
+        ));
+    }
+    if inc.serial {
+        out.push(VideohubMessage::SerialPortRouting(sorted_routes(
+            &state.serial_port_routing,
+        )));
+    }
+    // An empty block would read as a request, so leave it out if nothing is routed.
+    if processing && !state.processing_unit_routing.is_empty() {
+        out.push(VideohubMessage::ProcessingUnitRouting(sorted_routes(
+            &state.processing_unit_routing,
+        )));
+    }
+    if frames {
+        out.push(VideohubMessage::FrameBufferRouting(sorted_routes(
+            &state.frame_buffer_routing,
+        )));
+    }
+
+    if inc.configuration {
+        out.push(VideohubMessage::Configuration(state.configuration.clone()));
+    }
+    out
+}
+
+/// The initial dump a server sends to a freshly connected client, in canonical order:
+/// preamble, device, labels, locks, routing, configuration and finally `END PRELUDE:`.
+///
+/// Label and lock lists are dense over the port counts of [VideohubState::device].
+pub fn build_prelude(state: &VideohubState) -> Vec<VideohubMessage> {
+    let mut out = vec![VideohubMessage::Preamble(Preamble {
+        version: state.version.clone(),
+    })];
+    out.extend(build_state_dump(state));
+    out.push(VideohubMessage::EndPrelude);
+    out
+}
+
+/// [build_prelude] as a peer speaking `profile` expects it.
+///
+/// The preamble announces the profile's version, blocks it doesn't know are left out
+/// and so is `END PRELUDE:` before 2.7.
+pub fn build_prelude_for(state: &VideohubState, profile: ProtocolProfile) -> Vec<VideohubMessage> {
+    let mut out = vec![VideohubMessage::Preamble(Preamble {
+        version: profile.version().into(),
+    })];
+    if profile.has_configuration() {
+        out.extend(build_state_dump(state));
+    } else {
+        let mut state = state.clone();
+        state.include.configuration = false;
+        out.extend(build_state_dump(&state));
+    }
+    if profile.has_end_prelude() {
+        out.push(VideohubMessage::EndPrelude);
+    }
+    out
+}
+
+/// Split label and routing blocks of `msgs` into consecutive blocks of at most `max_lines`
+/// entries each, like hardware does for big updates. Order is kept.
+///
+/// Only `INPUT LABELS:`, `OUTPUT LABELS:` and `VIDEO OUTPUT ROUTING:` get split,
+/// empty blocks are left alone as splitting them would turn nothing into requests.
+pub fn chunked(
+    msgs: impl IntoIterator<Item = VideohubMessage>,
+    max_lines: usize,
+) -> Vec<VideohubMessage> {
+    let max_lines = max_lines.max(1);
+    let mut out = Vec::new();
+    for msg in msgs {
+        match msg {
+            VideohubMessage::InputLabels(v) if v.len() > max_lines => {
+                out.extend(
+                    v.chunks(max_lines)
+                        .map(|c| VideohubMessage::InputLabels(c.to_vec())),
+                );
+            }
+            VideohubMessage::OutputLabels(v) if v.len() > max_lines => {
+                out.extend(
+                    v.chunks(max_lines)
+                        .map(|c| VideohubMessage::OutputLabels(c.to_vec())),
+                );
+            }
+            VideohubMessage::VideoOutputRouting(v) if v.len() > max_lines => {
+                out.extend(
+                    v.chunks(max_lines)
+                        .map(|c| VideohubMessage::VideoOutputRouting(c.to_vec())),
+                );
+            }
+            msg => out.push(msg),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BMD_CLEANSWITCH: &[u8] = include_bytes!("./bmd_cleanswitch_12x12.txt");
+
+    fn headers(msgs: &[VideohubMessage]) -> Vec<String> {
+        msgs.iter()
+            .map(|m| m.summary().split(':').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn matches_cleanswitch() {
+        let (_, msgs) = VideohubMessage::parse_all_blocks(BMD_CLEANSWITCH).unwrap();
+        let mut state = VideohubState {
+            include: PreludeBlocks {
+                configuration: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for m in &msgs {
+            state.apply(m);
+        }
+        let prelude = build_prelude(&state);
+        assert_eq!(
+            headers(&prelude),
+            [
+                "PROTOCOL PREAMBLE",
+                "VIDEOHUB DEVICE",
+                "INPUT LABELS",
+                "OUTPUT LABELS",
+                "VIDEO OUTPUT LOCKS",
+                "VIDEO OUTPUT ROUTING",
+                "CONFIGURATION",
+                "END PRELUDE",
+            ]
+        );
+        assert_eq!(prelude, msgs);
+    }
+
+    #[test]
+    fn dense_and_ordered() {
+        let state = VideohubState {
+            version: "2.8".into(),
+            device: DeviceInfo {
+                present: Some(Present::Yes),
+                video_inputs: Some(2),
+                video_outputs: Some(3),
+                video_processing_units: Some(1),
+                serial_ports: Some(1),
+                ..Default::default()
+            },
+            frame_count: 1,
+            output_labels: vec![Label {
+                id: 1,
+                name: "Program".into(),
+            }],
+            video_output_locks: vec![Lock {
+                id: 2,
+                state: LockState::Locked,
+            }],
+            video_output_routing: vec![
+                Route {
+                    from_input: 1,
+                    to_output: 2,
+                },
+                Route {
+                    from_input: 0,
+                    to_output: 0,
+                },
+            ],
+            include: PreludeBlocks {
+                serial: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let prelude = build_prelude(&state);
+        assert_eq!(
+            headers(&prelude),
+            [
+                "PROTOCOL PREAMBLE",
+                "VIDEOHUB DEVICE",
+                "INPUT LABELS",
+                "OUTPUT LABELS",
+                "SERIAL PORT LABELS",
+                "FRAME LABELS",
+                "VIDEO OUTPUT LOCKS",
+                "SERIAL PORT LOCKS",
+                "PROCESSING UNIT LOCKS",
+                "FRAME BUFFER LOCKS",
+                "VIDEO OUTPUT ROUTING",
+                "SERIAL PORT ROUTING",
+                "FRAME BUFFER ROUTING",
+                "END PRELUDE",
+            ]
+        );
+        let names = |m: &VideohubMessage| match m {
+            VideohubMessage::OutputLabels(v) => v.iter().map(|l| l.name.clone()).collect(),
+            _ => Vec::new(),
+        };
+        assert_eq!(names(&prelude[3]), ["Output 1", "Program", "Output 3"]);
+        assert_eq!(
+            prelude[6],
+            VideohubMessage::VideoOutputLocks(vec![
+                Lock {
+                    id: 0,
+                    state: LockState::Unlocked
+                },
+                Lock {
+                    id: 1,
+                    state: LockState::Unlocked
+                },
+                Lock {
+                    id: 2,
+                    state: LockState::Locked
+                },
+            ])
+        );
+        assert!(matches!(
+            &prelude[10],
+            VideohubMessage::VideoOutputRouting(r) if r[0].to_output == 0 && r[1].to_output == 2
+        ));
+
+        // Absent devices announce nothing else.
+        let absent = VideohubState {
+            device: DeviceInfo {
+                present: Some(Present::No),
+                ..Default::default()
+            },
+            ..state
+        };
+        assert_eq!(
+            headers(&build_prelude(&absent)),
+            ["PROTOCOL PREAMBLE", "VIDEOHUB DEVICE", "END PRELUDE"]
+        );
+    }
+
+    #[test]
+    fn chunked_applies_the_same() {
+        let state = VideohubState {
+            version: "2.8".into(),
+            device: DeviceInfo {
+                present: Some(Present::Yes),
+                video_inputs: Some(576),
+                video_outputs: Some(576),
+                ..Default::default()
+            },
+            video_output_routing: (0..576)
+                .map(|o| Route {
+                    from_input: 575 - o,
+                    to_output: o,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let prelude = build_prelude(&state);
+        let split = chunked(prelude.clone(), 100);
+        // 576 lines make five full blocks and a partial one.
+        let count = |msgs: &[VideohubMessage], header: &str| {
+            headers(msgs).iter().filter(|h| *h == header).count()
+        };
+        for header in ["INPUT LABELS", "OUTPUT LABELS", "VIDEO OUTPUT ROUTING"] {
+            assert_eq!(count(&split, header), 6, "{}", header);
+        }
+        assert_eq!(count(&split, "VIDEO OUTPUT LOCKS"), 1);
+        assert_eq!(headers(&split).last().unwrap(), "END PRELUDE");
+
+        let mut text = String::new();
+        for m in &split {
+            m.write_serialized_fmt(&mut text).unwrap();
+        }
+        let (rest, parsed) = VideohubMessage::parse_all_blocks(text.as_bytes()).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed, split);
+
+        let apply = |msgs: &[VideohubMessage]| {
+            let mut st = VideohubState::default();
+            for m in msgs {
+                st.apply(m);
+            }
+            st
+        };
+        assert_eq!(apply(&parsed), apply(&prelude));
+        assert_eq!(build_prelude(&apply(&parsed)), prelude);
+
+        // Nothing to split.
+        assert_eq!(chunked(prelude.clone(), 576), prelude);
+    }
+
+    #[test]
+    fn old_profiles() {
+        let (_, msgs) = VideohubMessage::parse_all_blocks(BMD_CLEANSWITCH).unwrap();
+        let mut state = VideohubState {
+            include: PreludeBlocks {
+                configuration: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for m in &msgs {
+            state.apply(m);
+        }
+        assert_eq!(build_prelude_for(&state, ProtocolProfile::V2_8), msgs);
+
+        let prelude = build_prelude_for(&state, ProtocolProfile::V2_4);
+        assert_eq!(
+            prelude[0],
+            VideohubMessage::Preamble(Preamble {
+                version: "2.4".into()
+            })
+        );
+        assert_eq!(
+            headers(&prelude),
+            [
+                "PROTOCOL PREAMBLE",
+                "VIDEOHUB DEVICE",
+                "INPUT LABELS",
+                "OUTPUT LABELS",
+                "VIDEO OUTPUT LOCKS",
+                "VIDEO OUTPUT ROUTING",
+            ]
+        );
+    }
+}
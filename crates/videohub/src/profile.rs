@@ -0,0 +1,91 @@
+// Protocol versions and what they support.
+// Older hubs lack some blocks entirely, peers expecting them get confused.
+
+/// Protocol version a peer speaks, deciding which blocks and commands it knows.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ProtocolProfile {
+    V2_3,
+    V2_4,
+    V2_7,
+    V2_8,
+}
+
+impl ProtocolProfile {
+    /// Version as sent in the preamble.
+    pub fn version(self) -> &'static str {
+        match self {
+            ProtocolProfile::V2_3 => "2.3",
+            ProtocolProfile::V2_4 => "2.4",
+            ProtocolProfile::V2_7 => "2.7",
+            ProtocolProfile::V2_8 => "2.8",
+        }
+    }
+
+    /// Profile of a preamble version, the newest one not exceeding it.
+    ///
+    /// Returns `None` for unparsable versions, other major versions and ones older than 2.3.
+    pub fn from_version(version: &str) -> Option<Self> {
+        let (major, minor) = version.trim().split_once('.')?;
+        let (major, minor): (u32, u32) = (major.parse().ok()?, minor.parse().ok()?);
+        match (major, minor) {
+            (2, 8..) => Some(ProtocolProfile::V2_8),
+            (2, 7) => Some(ProtocolProfile::V2_7),
+            (2, 4..=6) => Some(ProtocolProfile::V2_4),
+            (2, 3) => Some(ProtocolProfile::V2_3),
+            _ => None,
+        }
+    }
+
+    /// Whether the initial dump ends with `END PRELUDE:`.
+    pub fn has_end_prelude(self) -> bool {
+        self >= ProtocolProfile::V2_7
+    }
+
+    /// Whether `PING:` is understood and answered with `ACK`.
+    pub fn has_ping(self) -> bool {
+        self >= ProtocolProfile::V2_7
+    }
+
+    /// Whether the `CONFIGURATION:` block exists.
+    pub fn has_configuration(self) -> bool {
+        self >= ProtocolProfile::V2_7
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities() {
+        for p in [ProtocolProfile::V2_3, ProtocolProfile::V2_4] {
+            assert!(!p.has_end_prelude() && !p.has_ping() && !p.has_configuration());
+        }
+        for p in [ProtocolProfile::V2_7, ProtocolProfile::V2_8] {
+            assert!(p.has_end_prelude() && p.has_ping() && p.has_configuration());
+        }
+    }
+
+    #[test]
+    fn from_version() {
+        for p in [
+            ProtocolProfile::V2_3,
+            ProtocolProfile::V2_4,
+            ProtocolProfile::V2_7,
+            ProtocolProfile::V2_8,
+        ] {
+            assert_eq!(ProtocolProfile::from_version(p.version()), Some(p));
+        }
+        assert_eq!(
+            ProtocolProfile::from_version("2.5"),
+            Some(ProtocolProfile::V2_4)
+        );
+        assert_eq!(
+            ProtocolProfile::from_version("2.10"),
+            Some(ProtocolProfile::V2_8)
+        );
+        assert_eq!(ProtocolProfile::from_version("2.2"), None);
+        assert_eq!(ProtocolProfile::from_version("3.0"), None);
+        assert_eq!(ProtocolProfile::from_version("two"), None);
+    }
+}
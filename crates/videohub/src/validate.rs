@@ -0,0 +1,258 @@
+// Bounds checks of messages against the port counts a device advertises.
+// Counts a device doesn't advertise aren't checked.
+
+use super::model::*;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Kind of port a [ValidationError] refers to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DevicePort {
+    VideoInput,
+    VideoOutput,
+    MonitoringOutput,
+    SerialPort,
+    ProcessingUnit,
+}
+
+impl fmt::Display for DevicePort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DevicePort::VideoInput => "video input",
+            DevicePort::VideoOutput => "video output",
+            DevicePort::MonitoringOutput => "monitoring output",
+            DevicePort::SerialPort => "serial port",
+            DevicePort::ProcessingUnit => "processing unit",
+        })
+    }
+}
+
+/// A port index outside of what the device advertises.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ValidationError {
+    pub port: DevicePort,
+    pub id: u32,
+    /// Number of ports of this kind the device has.
+    pub count: u32,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} out of range, device has {}",
+            self.port, self.id, self.count
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ValidationError {}
+
+impl DevicePort {
+    /// Number of ports of this kind advertised by `device`, if any.
+    fn count(self, device: &DeviceInfo) -> Option<u32> {
+        match self {
+            DevicePort::VideoInput => device.video_inputs,
+            DevicePort::VideoOutput => device.video_outputs,
+            DevicePort::MonitoringOutput => device.video_monitoring_outputs,
+            DevicePort::SerialPort => device.serial_ports,
+            DevicePort::ProcessingUnit => device.video_processing_units,
+        }
+    }
+}
+
+/// Collects violations while checking a single message.
+struct Checker<'a> {
+    device: &'a DeviceInfo,
+    errors: Vec<ValidationError>,
+}
+
+impl Checker<'_> {
+    fn check(&mut self, port: DevicePort, id: u32) {
+        if let Some(count) = port.count(self.device) {
+            if id >= count {
+                self.errors.push(ValidationError { port, id, count });
+            }
+        }
+    }
+
+    fn ids(&mut self, port: DevicePort, ids: impl IntoIterator<Item = u32>) {
+        for id in ids {
+            self.check(port, id);
+        }
+    }
+
+    /// Routes from `from` to `to`, `None` for sides without advertised counts.
+    fn routes(&mut self, from: Option<DevicePort>, to: Option<DevicePort>, v: &[Route]) {
+        for r in v {
+            if let Some(to) = to {
+                self.check(to, r.to_output);
+            }
+            if let Some(from) = from {
+                self.check(from, r.from_input);
+            }
+        }
+    }
+}
+
+impl VideohubMessage {
+    /// Check all port indices of the message against the counts advertised by `device`.
+    ///
+    /// Frame buffers aren't part of [DeviceInfo], so their indices are never checked.
+    pub fn validate(&self, device: &DeviceInfo) -> Result<(), Vec<ValidationError>> {
+        use DevicePort::*;
+        let mut c = Checker {
+            device,
+            errors: Vec::new(),
+        };
+        let labels = |v: &[Label]| v.iter().map(|l| l.id).collect::<Vec<_>>();
+        let locks = |v: &[Lock]| v.iter().map(|l| l.id).collect::<Vec<_>>();
+        let ports = |v: &[HardwarePort]| v.iter().map(|p| p.id).collect::<Vec<_>>();
+        match self {
+            VideohubMessage::InputLabels(v) => c.ids(VideoInput, labels(v)),
+            VideohubMessage::OutputLabels(v) => c.ids(VideoOutput, labels(v)),
+            VideohubMessage::MonitorOutputLabels(v) => c.ids(MonitoringOutput, labels(v)),
+            VideohubMessage::SerialPortLabels(v) => c.ids(SerialPort, labels(v)),
+
+            VideohubMessage::VideoOutputRouting(v) => {
+                c.routes(Some(VideoInput), Some(VideoOutput), v)
+            }
+            VideohubMessage::VideoMonitoringOutputRouting(v) => {
+                c.routes(Some(VideoInput), Some(MonitoringOutput), v)
+            }
+            VideohubMessage::SerialPortRouting(v) => {
+                c.routes(Some(SerialPort), Some(SerialPort), v)
+            }
+            VideohubMessage::ProcessingUnitRouting(v) => {
+                c.routes(Some(VideoInput), Some(ProcessingUnit), v)
+            }
+            VideohubMessage::FrameBufferRouting(v) => c.routes(Some(VideoInput), None, v),
+
+            VideohubMessage::VideoOutputLocks(v) => c.ids(VideoOutput, locks(v)),
+            VideohubMessage::MonitoringOutputLocks(v) => c.ids(MonitoringOutput, locks(v)),
+            VideohubMessage::SerialPortLocks(v) => c.ids(SerialPort, locks(v)),
+            VideohubMessage::ProcessingUnitLocks(v) => c.ids(ProcessingUnit, locks(v)),
+
+            VideohubMessage::VideoInputStatus(v) => c.ids(VideoInput, ports(v)),
+            VideohubMessage::VideoOutputStatus(v) => c.ids(VideoOutput, ports(v)),
+            VideohubMessage::SerialPortStatus(v) => c.ids(SerialPort, ports(v)),
+
+            _ => {}
+        }
+        if c.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(c.errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deliberately asymmetric, so swapped bounds show up.
+    fn device() -> DeviceInfo {
+        DeviceInfo {
+            video_inputs: Some(4),
+            video_outputs: Some(2),
+            video_monitoring_outputs: Some(1),
+            serial_ports: Some(3),
+            ..Default::default()
+        }
+    }
+
+    fn route(to_output: u32, from_input: u32) -> Route {
+        Route {
+            from_input,
+            to_output,
+        }
+    }
+
+    fn err(port: DevicePort, id: u32, count: u32) -> ValidationError {
+        ValidationError { port, id, count }
+    }
+
+    #[test]
+    fn routes_use_matching_bounds() {
+        let d = device();
+        // Input 3 exists, output 3 does not.
+        assert_eq!(
+            VideohubMessage::VideoOutputRouting(vec![route(1, 3)]).validate(&d),
+            Ok(())
+        );
+        assert_eq!(
+            VideohubMessage::VideoOutputRouting(vec![route(3, 1)]).validate(&d),
+            Err(vec![err(DevicePort::VideoOutput, 3, 2)])
+        );
+        assert_eq!(
+            VideohubMessage::VideoMonitoringOutputRouting(vec![route(0, 3), route(1, 4)])
+                .validate(&d),
+            Err(vec![
+                err(DevicePort::MonitoringOutput, 1, 1),
+                err(DevicePort::VideoInput, 4, 4),
+            ])
+        );
+    }
+
+    #[test]
+    fn collects_all_violations() {
+        let d = device();
+        let labels = (0..6)
+            .map(|id| Label {
+                id,
+                name: format!("L{}", id),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            VideohubMessage::InputLabels(labels.clone()).validate(&d),
+            Err(vec![
+                err(DevicePort::VideoInput, 4, 4),
+                err(DevicePort::VideoInput, 5, 4),
+            ])
+        );
+        assert_eq!(
+            VideohubMessage::OutputLabels(labels)
+                .validate(&d)
+                .unwrap_err()
+                .len(),
+            4
+        );
+
+        let lock = |id| Lock {
+            id,
+            state: LockState::Owned,
+        };
+        assert_eq!(
+            VideohubMessage::VideoOutputLocks(vec![lock(1), lock(2)]).validate(&d),
+            Err(vec![err(DevicePort::VideoOutput, 2, 2)])
+        );
+        assert_eq!(
+            VideohubMessage::SerialPortLocks(vec![lock(2)]).validate(&d),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn unadvertised_counts_pass() {
+        let d = device();
+        assert_eq!(
+            VideohubMessage::ProcessingUnitRouting(vec![route(7, 1)]).validate(&d),
+            Ok(())
+        );
+        assert_eq!(
+            VideohubMessage::FrameBufferRouting(vec![route(9, 4)]).validate(&d),
+            Err(vec![err(DevicePort::VideoInput, 4, 4)])
+        );
+        assert_eq!(
+            VideohubMessage::VideoOutputRouting(vec![route(9, 9)]).validate(&DeviceInfo::default()),
+            Ok(())
+        );
+        assert_eq!(VideohubMessage::Ping.validate(&d), Ok(()));
+        assert_eq!(
+            err(DevicePort::VideoOutput, 3, 2).to_string(),
+            "video output 3 out of range, device has 2"
+        );
+    }
+}
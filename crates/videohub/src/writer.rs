@@ -2,24 +2,215 @@
 // Serializes into the same output as the parser eats.
 
 use super::model::*;
-use bytes::{BufMut, BytesMut};
-use std::io::{Result, Write};
+use alloc::string::String;
+use bytes::BytesMut;
+use core::{convert::Infallible, fmt, fmt::Write};
+
+/// Destination of the serializer, either a byte stream or a string.
+trait Sink {
+    type Error;
+    fn write_args(&mut self, args: fmt::Arguments) -> Result<(), Self::Error>;
+    fn write_bytes(&mut self, b: &[u8]) -> Result<(), Self::Error>;
+
+    /// Write `n` in decimal, without going through [fmt] for byte sinks.
+    fn write_u32(&mut self, n: u32) -> Result<(), Self::Error> {
+        self.write_args(format_args!("{}", n))
+    }
+}
+
+/// Decimal digits of `n`, at the end of `buf`.
+fn u32_digits(mut n: u32, buf: &mut [u8; 10]) -> &[u8] {
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            return &buf[i..];
+        }
+    }
+}
+
+struct BytesSink<'a>(&'a mut BytesMut);
+
+impl Sink for BytesSink<'_> {
+    type Error = Infallible;
+
+    fn write_args(&mut self, args: fmt::Arguments) -> Result<(), Infallible> {
+        // BytesMut grows as needed, only Display impls could fail and ours don't.
+        let _ = self.0.write_fmt(args);
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, b: &[u8]) -> Result<(), Infallible> {
+        self.0.extend_from_slice(b);
+        Ok(())
+    }
+
+    fn write_u32(&mut self, n: u32) -> Result<(), Infallible> {
+        self.write_bytes(u32_digits(n, &mut [0; 10]))
+    }
+}
+
+#[cfg(feature = "std")]
+struct IoSink<W>(W);
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> Sink for IoSink<W> {
+    type Error = std::io::Error;
+
+    fn write_args(&mut self, args: fmt::Arguments) -> std::io::Result<()> {
+        self.0.write_fmt(args)
+    }
+
+    fn write_bytes(&mut self, b: &[u8]) -> std::io::Result<()> {
+        self.0.write_all(b)
+    }
+
+    fn write_u32(&mut self, n: u32) -> std::io::Result<()> {
+        self.write_bytes(u32_digits(n, &mut [0; 10]))
+    }
+}
+
+struct FmtSink<'a, W: ?Sized>(&'a mut W);
+
+impl<W: fmt::Write + ?Sized> Sink for FmtSink<'_, W> {
+    type Error = fmt::Error;
+
+    fn write_args(&mut self, args: fmt::Arguments) -> fmt::Result {
+        self.0.write_fmt(args)
+    }
+
+    /// Raw bytes only show up in unknown blocks, invalid UTF-8 gets replaced.
+    fn write_bytes(&mut self, b: &[u8]) -> fmt::Result {
+        self.0.write_str(&String::from_utf8_lossy(b))
+    }
+}
+
+/// Whether every line of `body` ends with `le`, so it can be written as it is.
+fn terminated_with(body: &[u8], le: LineEnding) -> bool {
+    if !body.is_empty() && !body.ends_with(b"\n") {
+        return false;
+    }
+    let mut lines = body.split(|&c| c == b'\n');
+    // Nothing follows the last line ending.
+    lines.next_back();
+    lines.all(|line| line.ends_with(b"\r") == (le == LineEnding::CRLF))
+}
 
 impl VideohubMessage {
     /// Write a serialized VideohubMessage into a std::io::Writer.
     /// It is terminated by an empty line, completing the block.
-    pub fn write_serialized(&self, mut w: impl Write) -> Result<()> {
+    ///
+    /// Entries are written in the order given, including unknown device fields,
+    /// so the output only depends on the message. Bodies of unknown blocks are passed
+    /// through as they are if their lines already end in the chosen [LineEnding],
+    /// otherwise their lines are terminated again.
+    #[cfg(feature = "std")]
+    pub fn write_serialized(&self, w: impl std::io::Write) -> std::io::Result<()> {
+        self.write_serialized_with(w, LineEnding::default())
+    }
+
+    /// Like [VideohubMessage::write_serialized], but terminating every line with `le`.
+    #[cfg(feature = "std")]
+    pub fn write_serialized_with(
+        &self,
+        w: impl std::io::Write,
+        le: LineEnding,
+    ) -> std::io::Result<()> {
+        self.serialize(&mut IoSink(w), le)
+    }
+
+    /// Like [VideohubMessage::write_serialized], but into a [core::fmt::Write].
+    /// Available without `std`, unknown blocks that aren't valid UTF-8 are written lossily.
+    pub fn write_serialized_fmt(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        self.write_serialized_fmt_with(w, LineEnding::default())
+    }
+
+    /// Like [VideohubMessage::write_serialized_fmt], but terminating every line with `le`.
+    pub fn write_serialized_fmt_with(
+        &self,
+        w: &mut impl fmt::Write,
+        le: LineEnding,
+    ) -> fmt::Result {
+        self.serialize(&mut FmtSink(w), le)
+    }
+
+    /// Append the serialized message to `dst`, reserving [Self::serialized_len_hint] up front.
+    ///
+    /// Cheaper than [Self::write_serialized] for many messages, as `dst` can be reused.
+    pub fn encode_into(&self, dst: &mut BytesMut) {
+        self.encode_into_with(dst, LineEnding::default())
+    }
+
+    /// Like [VideohubMessage::encode_into], but terminating every line with `le`.
+    pub fn encode_into_with(&self, dst: &mut BytesMut, le: LineEnding) {
+        dst.reserve(self.serialized_len_hint());
+        let Ok(()) = self.serialize(&mut BytesSink(dst), le);
+    }
+
+    /// Rough size of the serialized message in bytes, to reserve buffers with.
+    ///
+    /// Assumes `\n` line endings and ids of up to three digits.
+    pub fn serialized_len_hint(&self) -> usize {
+        // Id, space and newline.
+        const ENTRY: usize = 5;
+        let entries = match self {
+            VideohubMessage::InputLabels(v)
+            | VideohubMessage::OutputLabels(v)
+            | VideohubMessage::MonitorOutputLabels(v)
+            | VideohubMessage::SerialPortLabels(v)
+            | VideohubMessage::FrameLabels(v) => v.iter().map(|l| ENTRY + l.name.len()).sum(),
+            VideohubMessage::VideoOutputRouting(v)
+            | VideohubMessage::VideoMonitoringOutputRouting(v)
+            | VideohubMessage::SerialPortRouting(v)
+            | VideohubMessage::ProcessingUnitRouting(v)
+            | VideohubMessage::FrameBufferRouting(v) => v.len() * (ENTRY + 3),
+            VideohubMessage::VideoOutputLocks(v)
+            | VideohubMessage::MonitoringOutputLocks(v)
+            | VideohubMessage::SerialPortLocks(v)
+            | VideohubMessage::ProcessingUnitLocks(v)
+            | VideohubMessage::FrameBufferLocks(v) => v.len() * (ENTRY + 1),
+            VideohubMessage::VideoInputStatus(v)
+            | VideohubMessage::VideoOutputStatus(v)
+            | VideohubMessage::SerialPortStatus(v) => v.len() * (ENTRY + 8),
+            VideohubMessage::AlarmStatus(v) => {
+                v.iter().map(|a| a.name.len() + a.status.len() + 3).sum()
+            }
+            VideohubMessage::Configuration(v) => {
+                v.iter().map(|s| s.setting.len() + s.value.len() + 3).sum()
+            }
+            VideohubMessage::Preamble(p) => p.version.len() + 10,
+            VideohubMessage::DeviceInfo(_) => 256,
+            VideohubMessage::UnknownMessage(_, body) => body.len(),
+            VideohubMessage::ACK
+            | VideohubMessage::NAK
+            | VideohubMessage::Ping
+            | VideohubMessage::EndPrelude => 0,
+        };
+        // Longest header plus newline, and the blank line.
+        33 + entries + 1
+    }
+
+    fn serialize<S: Sink>(&self, w: &mut S, le: LineEnding) -> Result<(), S::Error> {
+        let nl = le.as_bytes();
+        macro_rules! write_line {
+            ($($arg:tt)*) => {
+                w.write_args(format_args!($($arg)*)).and_then(|_| w.write_bytes(nl))
+            };
+        }
+
         match self {
             VideohubMessage::Preamble(p) => {
-                write!(w, "PROTOCOL PREAMBLE:\n")?;
-                write!(w, "Version: {}\n", p.version)?;
+                write_line!("PROTOCOL PREAMBLE:")?;
+                write_line!("Version: {}", p.version)?;
             }
             VideohubMessage::DeviceInfo(d) => {
-                write!(w, "VIDEOHUB DEVICE:\n")?;
+                write_line!("VIDEOHUB DEVICE:")?;
                 macro_rules! opt_val {
                     ($field:expr, $label:expr) => {
                         if let Some(v) = $field {
-                            write!(w, "{}: {}\n", $label, v)?;
+                            write_line!("{}: {}", $label, v)?;
                         }
                     };
                 }
@@ -36,166 +227,200 @@ impl VideohubMessage {
 
                 if let Some(unknown) = &d.unknown_fields {
                     for kv in unknown.iter() {
-                        write!(w, "{}: {}\n", &kv.key, &kv.value)?;
+                        write_line!("{}: {}", &kv.key, &kv.value)?;
                     }
                 }
             }
             VideohubMessage::InputLabels(v) => {
-                write!(w, "INPUT LABELS:\n")?;
+                write_line!("INPUT LABELS:")?;
                 for l in v {
-                    write!(w, "{} {}\n", l.id, l.name)?;
+                    write_label(w, l, nl)?;
                 }
             }
             VideohubMessage::OutputLabels(v) => {
-                write!(w, "OUTPUT LABELS:\n")?;
+                write_line!("OUTPUT LABELS:")?;
                 for l in v {
-                    write!(w, "{} {}\n", l.id, l.name)?;
+                    write_label(w, l, nl)?;
                 }
             }
             VideohubMessage::MonitorOutputLabels(v) => {
-                write!(w, "MONITOR OUTPUT LABELS:\n")?;
+                write_line!("{}", MONITOR_OUTPUT_LABELS_HEADER)?;
                 for l in v {
-                    write!(w, "{} {}\n", l.id, l.name)?;
+                    write_label(w, l, nl)?;
                 }
             }
             VideohubMessage::SerialPortLabels(v) => {
-                write!(w, "SERIAL PORT LABELS:\n")?;
+                write_line!("SERIAL PORT LABELS:")?;
                 for l in v {
-                    write!(w, "{} {}\n", l.id, l.name)?;
+                    write_label(w, l, nl)?;
                 }
             }
             VideohubMessage::FrameLabels(v) => {
-                write!(w, "FRAME LABELS:\n")?;
+                write_line!("FRAME LABELS:")?;
                 for l in v {
-                    write!(w, "{} {}\n", l.id, l.name)?;
+                    write_label(w, l, nl)?;
                 }
             }
             VideohubMessage::VideoOutputRouting(v) => {
-                write!(w, "VIDEO OUTPUT ROUTING:\n")?;
+                write_line!("VIDEO OUTPUT ROUTING:")?;
                 for r in v {
-                    write!(w, "{} {}\n", r.to_output, r.from_input)?;
+                    write_route(w, r, nl)?;
                 }
             }
             VideohubMessage::VideoMonitoringOutputRouting(v) => {
-                write!(w, "VIDEO MONITORING OUTPUT ROUTING:\n")?;
+                write_line!("VIDEO MONITORING OUTPUT ROUTING:")?;
                 for r in v {
-                    write!(w, "{} {}\n", r.to_output, r.from_input)?;
+                    write_route(w, r, nl)?;
                 }
             }
             VideohubMessage::SerialPortRouting(v) => {
-                write!(w, "SERIAL PORT ROUTING:\n")?;
+                write_line!("SERIAL PORT ROUTING:")?;
                 for r in v {
-                    write!(w, "{} {}\n", r.to_output, r.from_input)?;
+                    write_route(w, r, nl)?;
                 }
             }
             VideohubMessage::ProcessingUnitRouting(v) => {
-                write!(w, "PROCESSING UNIT ROUTING:\n")?;
+                write_line!("PROCESSING UNIT ROUTING:")?;
                 for r in v {
-                    write!(w, "{} {}\n", r.to_output, r.from_input)?;
+                    write_route(w, r, nl)?;
                 }
             }
             VideohubMessage::FrameBufferRouting(v) => {
-                write!(w, "FRAME BUFFER ROUTING:\n")?;
+                write_line!("FRAME BUFFER ROUTING:")?;
                 for r in v {
-                    write!(w, "{} {}\n", r.to_output, r.from_input)?;
+                    write_route(w, r, nl)?;
                 }
             }
             VideohubMessage::VideoOutputLocks(v) => {
-                write!(w, "VIDEO OUTPUT LOCKS:\n")?;
+                write_line!("VIDEO OUTPUT LOCKS:")?;
                 for l in v {
-                    write!(w, "{} {}\n", l.id, l.state)?;
+                    write_line!("{} {}", l.id, l.state)?;
                 }
             }
             VideohubMessage::MonitoringOutputLocks(v) => {
-                write!(w, "MONITORING OUTPUT LOCKS:\n")?;
+                write_line!("MONITORING OUTPUT LOCKS:")?;
                 for l in v {
-                    write!(w, "{} {}\n", l.id, l.state)?;
+                    write_line!("{} {}", l.id, l.state)?;
                 }
             }
             VideohubMessage::SerialPortLocks(v) => {
-                write!(w, "SERIAL PORT LOCKS:\n")?;
+                write_line!("SERIAL PORT LOCKS:")?;
                 for l in v {
-                    write!(w, "{} {}\n", l.id, l.state)?;
+                    write_line!("{} {}", l.id, l.state)?;
                 }
             }
             VideohubMessage::ProcessingUnitLocks(v) => {
-                write!(w, "PROCESSING UNIT LOCKS:\n")?;
+                write_line!("PROCESSING UNIT LOCKS:")?;
                 for l in v {
-                    write!(w, "{} {}\n", l.id, l.state)?;
+                    write_line!("{} {}", l.id, l.state)?;
                 }
             }
             VideohubMessage::FrameBufferLocks(v) => {
-                write!(w, "FRAME BUFFER LOCKS:\n")?;
+                write_line!("FRAME BUFFER LOCKS:")?;
                 for l in v {
-                    write!(w, "{} {}\n", l.id, l.state)?;
+                    write_line!("{} {}", l.id, l.state)?;
                 }
             }
             VideohubMessage::VideoInputStatus(v) => {
-                write!(w, "VIDEO INPUT STATUS:\n")?;
+                write_line!("VIDEO INPUT STATUS:")?;
                 for p in v {
-                    write!(w, "{} {:?}\n", p.id, p.port_type)?;
+                    write_line!("{} {}", p.id, p.port_type)?;
                 }
             }
             VideohubMessage::VideoOutputStatus(v) => {
-                write!(w, "VIDEO OUTPUT STATUS:\n")?;
+                write_line!("VIDEO OUTPUT STATUS:")?;
                 for p in v {
-                    write!(w, "{} {:?}\n", p.id, p.port_type)?;
+                    write_line!("{} {}", p.id, p.port_type)?;
                 }
             }
             VideohubMessage::SerialPortStatus(v) => {
-                write!(w, "SERIAL PORT STATUS:\n")?;
+                write_line!("SERIAL PORT STATUS:")?;
                 for p in v {
-                    write!(w, "{} {:?}", p.id, p.port_type)?;
+                    write_line!("{} {}", p.id, p.port_type)?;
                 }
             }
             VideohubMessage::AlarmStatus(v) => {
-                write!(w, "ALARM STATUS:\n")?;
+                write_line!("ALARM STATUS:")?;
                 for a in v {
-                    write!(w, "{}: {}\n", a.name, a.status)?;
+                    write_line!("{}: {}", a.name, a.status)?;
                 }
             }
             VideohubMessage::Configuration(v) => {
-                write!(w, "CONFIGURATION:\n")?;
+                write_line!("CONFIGURATION:")?;
                 for s in v {
-                    write!(w, "{}: {}\n", s.setting, s.value)?;
+                    write_line!("{}: {}", s.setting, s.value)?;
                 }
             }
             VideohubMessage::ACK => {
-                write!(w, "ACK\n")?;
+                write_line!("ACK")?;
             }
             VideohubMessage::NAK => {
-                write!(w, "NAK\n")?;
+                write_line!("NAK")?;
             }
             VideohubMessage::Ping => {
-                write!(w, "PING:\n")?;
+                write_line!("PING:")?;
             }
             VideohubMessage::EndPrelude => {
-                write!(w, "END PRELUDE:\n")?;
+                write_line!("END PRELUDE:")?;
             }
             VideohubMessage::UnknownMessage(h, body) => {
-                w.write_all(&h[..])?;
-                w.write_all("\n".as_bytes())?;
-                w.write_all(&body[..])?;
+                w.write_bytes(&h[..])?;
+                w.write_bytes(nl)?;
+                if terminated_with(body, le) {
+                    w.write_bytes(&body[..])?;
+                } else {
+                    // Re-terminate the raw body lines, so endings don't mix.
+                    let body = body.strip_suffix(b"\n").unwrap_or(&body[..]);
+                    for line in body.split(|&c| c == b'\n') {
+                        w.write_bytes(line.strip_suffix(b"\r").unwrap_or(line))?;
+                        w.write_bytes(nl)?;
+                    }
+                }
             }
         }
         // trailing blank‐line
-        write!(w, "\n")?;
+        w.write_bytes(nl)?;
         Ok(())
     }
 
-    pub fn to_serialized(&self) -> Result<BytesMut> {
-        let mut w = BytesMut::new().writer();
-        self.write_serialized(&mut w)?;
-        Ok(w.into_inner())
+    #[cfg(feature = "std")]
+    pub fn to_serialized(&self) -> std::io::Result<BytesMut> {
+        self.to_serialized_with(LineEnding::default())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn to_serialized_with(&self, le: LineEnding) -> std::io::Result<BytesMut> {
+        let mut b = BytesMut::new();
+        self.encode_into_with(&mut b, le);
+        Ok(b)
     }
 }
 
-#[cfg(test)]
+/// `<id> <name>` line of a label block.
+fn write_label<S: Sink>(w: &mut S, l: &Label, nl: &[u8]) -> Result<(), S::Error> {
+    w.write_u32(l.id)?;
+    w.write_bytes(b" ")?;
+    w.write_bytes(l.name.as_bytes())?;
+    w.write_bytes(nl)
+}
+
+/// `<output> <input>` line of a routing block.
+fn write_route<S: Sink>(w: &mut S, r: &Route, nl: &[u8]) -> Result<(), S::Error> {
+    w.write_u32(r.to_output)?;
+    w.write_bytes(b" ")?;
+    w.write_u32(r.from_input)?;
+    w.write_bytes(nl)
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
     const BMD_EXAMPLE: &[u8] = include_bytes!("./bmd_example.txt");
     const BMD_CLEANSWITCH: &[u8] = include_bytes!("./bmd_cleanswitch_12x12.txt");
+    const BMD_MONITOR: &[u8] = include_bytes!("./bmd_monitor_labels.txt");
+    const BMD_MONITORING: &[u8] = include_bytes!("./bmd_monitoring_labels.txt");
+    const BMD_FRAME_BUFFERS: &[u8] = include_bytes!("./bmd_frame_buffers.txt");
 
     #[test]
     fn single_preamble() {
@@ -253,4 +478,396 @@ mod tests {
         assert!(rem2.is_empty(), "leftover after round-trip");
         assert_eq!(msgs, msgs2);
     }
+
+    #[test]
+    fn roundtrip_hardware_port_types() {
+        let types = [
+            HardwarePortType::None,
+            HardwarePortType::BNC,
+            HardwarePortType::Optical,
+            HardwarePortType::Thunderbolt,
+            HardwarePortType::RS422,
+            HardwarePortType::Other("HDMI".into()),
+        ];
+        let ports: Vec<HardwarePort> = types
+            .into_iter()
+            .enumerate()
+            .map(|(id, port_type)| HardwarePort {
+                id: id as u32,
+                port_type,
+            })
+            .collect();
+
+        for m in [
+            VideohubMessage::VideoInputStatus(ports.clone()),
+            VideohubMessage::VideoOutputStatus(ports.clone()),
+            VideohubMessage::SerialPortStatus(ports.clone()),
+        ] {
+            let b = m.to_serialized().unwrap();
+            let (r, m2) = VideohubMessage::parse_single_block(&b).unwrap();
+            assert!(r.is_empty());
+            assert_eq!(m, m2);
+        }
+    }
+
+    #[test]
+    fn roundtrip_status_blocks() {
+        let block: &[u8] = b"VIDEO INPUT STATUS:\n0 BNC\n1 Optical\n\n\
+            VIDEO OUTPUT STATUS:\n0 BNC\n\n\
+            SERIAL PORT STATUS:\n0 RS422\n1 None\n\n";
+        let (r, msgs) = VideohubMessage::parse_all_blocks(block).unwrap();
+        assert!(r.is_empty());
+        assert_eq!(msgs.len(), 3);
+        assert!(matches!(&msgs[2], VideohubMessage::SerialPortStatus(v) if v.len() == 2));
+        assert_eq!(&serialize_all(&msgs, LineEnding::LF)[..], block);
+    }
+
+    #[test]
+    fn roundtrip_lock_states() {
+        let states = [
+            LockState::Owned,
+            LockState::Locked,
+            LockState::Unlocked,
+            LockState::Force,
+            LockState::Other('Q'),
+        ];
+        let m = VideohubMessage::VideoOutputLocks(
+            states
+                .into_iter()
+                .enumerate()
+                .map(|(id, state)| Lock {
+                    id: id as u32,
+                    state,
+                })
+                .collect(),
+        );
+        let b = m.to_serialized().unwrap();
+        assert!(b.ends_with(b"3 F\n4 Q\n\n"));
+        let (_, m2) = VideohubMessage::parse_single_block(&b).unwrap();
+        assert_eq!(m, m2);
+    }
+
+    #[test]
+    fn roundtrip_monitor_spellings() {
+        // Whatever spelling came in, the documented one goes out.
+        for input in [BMD_MONITOR, BMD_MONITORING] {
+            let (_, msgs) = VideohubMessage::parse_all_blocks(input).unwrap();
+            let b = msgs[0].to_serialized().unwrap();
+            assert!(b.starts_with(MONITOR_OUTPUT_LABELS_HEADER.as_bytes()));
+            let (r, m2) = VideohubMessage::parse_single_block(&b).unwrap();
+            assert!(r.is_empty());
+            assert_eq!(msgs[0], m2);
+        }
+        let (_, msgs) = VideohubMessage::parse_all_blocks(BMD_MONITORING).unwrap();
+        assert_eq!(&serialize_all(&msgs, LineEnding::LF)[..], BMD_MONITORING);
+    }
+
+    #[test]
+    fn roundtrip_frame_buffers() {
+        let (_, msgs) = VideohubMessage::parse_all_blocks(BMD_FRAME_BUFFERS).unwrap();
+        let b = serialize_all(&msgs, LineEnding::LF);
+        assert_eq!(&b[..], BMD_FRAME_BUFFERS);
+        let (r, msgs2) = VideohubMessage::parse_all_blocks(&b).unwrap();
+        assert!(r.is_empty());
+        assert_eq!(msgs, msgs2);
+    }
+
+    fn serialize_all(msgs: &[VideohubMessage], le: LineEnding) -> BytesMut {
+        let mut out = BytesMut::new();
+        for m in msgs {
+            out.extend_from_slice(&m.to_serialized_with(le).unwrap());
+        }
+        out
+    }
+
+    #[test]
+    fn line_endings_parse_identically() {
+        for example in [BMD_EXAMPLE, BMD_CLEANSWITCH] {
+            let (_rem, msgs) = VideohubMessage::parse_all_blocks(example).unwrap();
+            for le in [LineEnding::LF, LineEnding::CRLF] {
+                let out = serialize_all(&msgs, le);
+                let (rem, msgs2) = VideohubMessage::parse_all_blocks(&out).unwrap();
+                assert!(rem.is_empty(), "leftover after round-trip");
+                assert_eq!(msgs, msgs2);
+            }
+        }
+    }
+
+    #[test]
+    fn line_endings_never_mix() {
+        let (_rem, mut msgs) = VideohubMessage::parse_all_blocks(BMD_CLEANSWITCH).unwrap();
+        msgs.push(VideohubMessage::UnknownMessage(
+            BytesMut::from(&b"SOMETHING NEW:"[..]),
+            BytesMut::from(&b"a\r\nb\n"[..]),
+        ));
+
+        let lf = serialize_all(&msgs, LineEnding::LF);
+        assert!(!lf.contains(&b'\r'));
+
+        let crlf = serialize_all(&msgs, LineEnding::CRLF);
+        for (pos, _) in crlf.iter().enumerate().filter(|(_, &c)| c == b'\n') {
+            assert_eq!(crlf[pos - 1], b'\r', "bare LF at {}", pos);
+        }
+        assert!(crlf.ends_with(b"a\r\nb\r\n\r\n"));
+    }
+
+    #[test]
+    fn unknown_bodies_pass_through() {
+        let body = &b"a: 1\n\nb: 2\n"[..];
+        let m = VideohubMessage::UnknownMessage(
+            BytesMut::from(&b"SOMETHING NEW:"[..]),
+            BytesMut::from(body),
+        );
+        let lf = serialize_all(core::slice::from_ref(&m), LineEnding::LF);
+        assert_eq!(&lf[..], &b"SOMETHING NEW:\na: 1\n\nb: 2\n\n"[..]);
+        // Only endings not matching are replaced.
+        let crlf = serialize_all(&[m], LineEnding::CRLF);
+        assert_eq!(
+            &crlf[..],
+            &b"SOMETHING NEW:\r\na: 1\r\n\r\nb: 2\r\n\r\n"[..]
+        );
+    }
+}
+
+#[cfg(test)]
+mod fmt_tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    const BMD_EXAMPLE: &[u8] = include_bytes!("./bmd_example.txt");
+    const BMD_FRAME_BUFFERS: &[u8] = include_bytes!("./bmd_frame_buffers.txt");
+
+    fn to_string(msgs: &[VideohubMessage], le: LineEnding) -> String {
+        let mut out = String::new();
+        for m in msgs {
+            m.write_serialized_fmt_with(&mut out, le).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn roundtrip_fmt() {
+        for example in [BMD_EXAMPLE, BMD_FRAME_BUFFERS] {
+            let (_, msgs) = VideohubMessage::parse_all_blocks(example).unwrap();
+            for le in [LineEnding::LF, LineEnding::CRLF] {
+                let out = to_string(&msgs, le);
+                let (rem, msgs2) = VideohubMessage::parse_all_blocks(out.as_bytes()).unwrap();
+                assert!(rem.is_empty(), "leftover after round-trip");
+                assert_eq!(msgs, msgs2);
+            }
+        }
+        let (_, msgs) = VideohubMessage::parse_all_blocks(BMD_FRAME_BUFFERS).unwrap();
+        assert_eq!(
+            to_string(&msgs, LineEnding::LF).as_bytes(),
+            BMD_FRAME_BUFFERS
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn fmt_matches_io() {
+        let (_, mut msgs) = VideohubMessage::parse_all_blocks(BMD_EXAMPLE).unwrap();
+        msgs.push(VideohubMessage::UnknownMessage(
+            BytesMut::from(&b"SOMETHING NEW:"[..]),
+            BytesMut::from(&b"a\r\nb\n"[..]),
+        ));
+        let io = msgs
+            .iter()
+            .flat_map(|m| m.to_serialized().unwrap())
+            .collect::<Vec<u8>>();
+        assert_eq!(to_string(&msgs, LineEnding::LF).as_bytes(), &io[..]);
+    }
+
+    #[test]
+    fn fmt_replaces_invalid_utf8() {
+        let m = VideohubMessage::UnknownMessage(
+            BytesMut::from(&b"SOMETHING NEW:"[..]),
+            BytesMut::from(&b"a\xff\n"[..]),
+        );
+        assert_eq!(
+            to_string(&[m], LineEnding::LF),
+            "SOMETHING NEW:\na\u{fffd}\n\n"
+        );
+    }
+}
+
+/// Byte-exact output, compared against the files in `src/golden/`.
+///
+/// After intentional changes to the output, regenerate them with
+/// `UPDATE_GOLDEN=1 cargo test -p videohub golden` and review the diff.
+#[cfg(all(test, feature = "std"))]
+mod golden_tests {
+    use super::*;
+    use bytes::BytesMut;
+    use std::{env, fs, path::PathBuf};
+
+    fn label(id: u32, name: &str) -> Label {
+        Label {
+            id,
+            name: name.into(),
+        }
+    }
+
+    fn route(to_output: u32, from_input: u32) -> Route {
+        Route {
+            from_input,
+            to_output,
+        }
+    }
+
+    fn lock(id: u32, state: LockState) -> Lock {
+        Lock { id, state }
+    }
+
+    fn port(id: u32, port_type: HardwarePortType) -> HardwarePort {
+        HardwarePort { id, port_type }
+    }
+
+    /// Every variant, with entries deliberately out of id order to show it's kept.
+    fn canonical() -> Vec<VideohubMessage> {
+        use LockState::*;
+        vec![
+            VideohubMessage::Preamble(Preamble {
+                version: "2.8".into(),
+            }),
+            VideohubMessage::DeviceInfo(DeviceInfo {
+                present: Some(Present::Yes),
+                model_name: Some("Blackmagic Smart Videohub 12x12".into()),
+                friendly_name: Some("Studio A".into()),
+                unique_id: Some("7C2E0D0A1B2C".into()),
+                video_inputs: Some(12),
+                video_processing_units: Some(0),
+                video_outputs: Some(12),
+                video_monitoring_outputs: Some(0),
+                serial_ports: Some(0),
+                unknown_fields: Some(vec![
+                    UnknownKVPair {
+                        key: "Zeta field".into(),
+                        value: "last".into(),
+                    },
+                    UnknownKVPair {
+                        key: "Alpha field".into(),
+                        value: "first".into(),
+                    },
+                ]),
+            }),
+            VideohubMessage::DeviceInfo(DeviceInfo {
+                present: Some(Present::NeedsUpdate),
+                ..Default::default()
+            }),
+            VideohubMessage::InputLabels(vec![label(1, "Camera 2"), label(0, "Camera 1")]),
+            VideohubMessage::OutputLabels(vec![label(0, "Program")]),
+            VideohubMessage::MonitorOutputLabels(vec![label(0, "Monitor 1")]),
+            VideohubMessage::SerialPortLabels(vec![label(0, "Deck")]),
+            VideohubMessage::FrameLabels(vec![label(0, "Still")]),
+            VideohubMessage::VideoOutputRouting(vec![route(2, 5), route(0, 11)]),
+            VideohubMessage::VideoMonitoringOutputRouting(vec![route(0, 1)]),
+            VideohubMessage::SerialPortRouting(vec![route(0, 1)]),
+            VideohubMessage::ProcessingUnitRouting(vec![route(0, 3)]),
+            VideohubMessage::FrameBufferRouting(vec![route(0, 4)]),
+            VideohubMessage::VideoOutputLocks(vec![
+                lock(0, Owned),
+                lock(1, Locked),
+                lock(2, Unlocked),
+                lock(3, Force),
+                lock(4, Other('X')),
+            ]),
+            VideohubMessage::MonitoringOutputLocks(vec![lock(0, Unlocked)]),
+            VideohubMessage::SerialPortLocks(vec![lock(0, Locked)]),
+            VideohubMessage::ProcessingUnitLocks(vec![lock(0, Owned)]),
+            VideohubMessage::FrameBufferLocks(vec![lock(0, Unlocked)]),
+            VideohubMessage::VideoInputStatus(vec![
+                port(0, HardwarePortType::BNC),
+                port(1, HardwarePortType::Optical),
+                port(2, HardwarePortType::Thunderbolt),
+                port(3, HardwarePortType::None),
+                port(4, HardwarePortType::Other("HDMI".into())),
+            ]),
+            VideohubMessage::VideoOutputStatus(vec![port(0, HardwarePortType::BNC)]),
+            VideohubMessage::SerialPortStatus(vec![port(0, HardwarePortType::RS422)]),
+            VideohubMessage::AlarmStatus(vec![Alarm {
+                name: "Fan".into(),
+                status: "OK".into(),
+            }]),
+            VideohubMessage::Configuration(vec![Setting {
+                setting: "Take Mode".into(),
+                value: "true".into(),
+            }]),
+            // Empty collections are requests.
+            VideohubMessage::InputLabels(vec![]),
+            VideohubMessage::VideoOutputRouting(vec![]),
+            VideohubMessage::VideoOutputLocks(vec![]),
+            VideohubMessage::Configuration(vec![]),
+            VideohubMessage::ACK,
+            VideohubMessage::NAK,
+            VideohubMessage::Ping,
+            VideohubMessage::EndPrelude,
+            VideohubMessage::UnknownMessage(
+                BytesMut::from(&b"SOMETHING NEW:"[..]),
+                BytesMut::from(&b"b: 2\r\na: 1\n"[..]),
+            ),
+            VideohubMessage::UnknownMessage(BytesMut::from(&b"EMPTY NEW:"[..]), BytesMut::new()),
+        ]
+    }
+
+    fn check(name: &str, actual: &[u8]) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src/golden")
+            .join(name);
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert!(
+            expected == actual,
+            "{} differs, got:\n{}",
+            name,
+            String::from_utf8_lossy(actual)
+        );
+    }
+
+    #[test]
+    fn golden_io() {
+        for (name, le) in [("lf.txt", LineEnding::LF), ("crlf.txt", LineEnding::CRLF)] {
+            let mut out = Vec::new();
+            for m in canonical() {
+                m.write_serialized_with(&mut out, le).unwrap();
+            }
+            check(name, &out);
+        }
+    }
+
+    #[test]
+    fn golden_fmt() {
+        for (name, le) in [("lf.txt", LineEnding::LF), ("crlf.txt", LineEnding::CRLF)] {
+            let mut out = String::new();
+            for m in canonical() {
+                m.write_serialized_fmt_with(&mut out, le).unwrap();
+            }
+            check(name, out.as_bytes());
+        }
+    }
+
+    #[test]
+    fn golden_encode_into() {
+        for (name, le) in [("lf.txt", LineEnding::LF), ("crlf.txt", LineEnding::CRLF)] {
+            let mut out = BytesMut::new();
+            for m in canonical() {
+                m.encode_into_with(&mut out, le);
+            }
+            check(name, &out);
+        }
+    }
+
+    #[test]
+    fn len_hint_covers_routing_dump() {
+        let m = VideohubMessage::VideoOutputRouting((0..288).map(|o| route(o, 287 - o)).collect());
+        let mut io = Vec::new();
+        m.write_serialized(&mut io).unwrap();
+        let mut out = BytesMut::new();
+        m.encode_into(&mut out);
+        assert_eq!(&out[..], &io[..]);
+        assert!(m.serialized_len_hint() >= out.len());
+    }
 }
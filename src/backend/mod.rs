@@ -2,4 +2,7 @@ mod ndi;
 mod videohub;
 
 pub use ndi::NDIRouter;
-pub use videohub::VideohubRouter;
+pub use videohub::{
+    ApplyOptions, ApplySummary, ChunkFailurePolicy, ChunkProgress, ConnectOptions, FailoverPolicy,
+    RawFilter, VideohubRouter,
+};
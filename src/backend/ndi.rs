@@ -3,16 +3,90 @@ use anyhow::{anyhow, Result};
 use futures_core::stream::BoxStream;
 use ndi_sdk::{FindInstance, RouteInstance, Source};
 use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::broadcast;
 use tokio_stream::{wrappers::BroadcastStream, StreamExt};
 use tracing::{debug, error};
 
+/// Decides by name whether an NDI source gets an input, see [NDIRouter::with_source_filter].
+pub type SourceFilter = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
 #[derive(Clone)]
 pub struct NDIRouter {
     group: Arc<Vec<String>>,
     state: Arc<Mutex<State>>,
     tx: broadcast::Sender<RouterEvent>,
+    persistence: Option<Arc<PathBuf>>,
+    /// Held while writing to the persistence file, so writes don't overlap.
+    writing: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// Routing state written to disk by [NDIRouter::with_persistence].
+///
+/// Inputs are stored by source name, so a restarted router can hand
+/// every source its old slot once it shows up again.
+#[cfg(feature = "serde")]
+#[derive(Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+struct PersistedState {
+    input_names: Vec<String>,
+    output_names: Vec<String>,
+    routes: Vec<(u32, Option<u32>)>,
+    #[serde(default)]
+    output_descriptions: Vec<String>,
+}
+
+#[cfg(feature = "serde")]
+impl PersistedState {
+    fn from_state(st: &State) -> Self {
+        Self {
+            input_names: st.input_labels.iter().map(|l| l.name.clone()).collect(),
+            output_names: st.output_labels.iter().map(|l| l.name.clone()).collect(),
+            routes: st
+                .routes
+                .iter()
+                .map(|p| (p.to_output, p.from_input))
+                .collect(),
+            output_descriptions: st
+                .output_descriptions
+                .iter()
+                .map(|d| d.text.clone())
+                .collect(),
+        }
+    }
+
+    /// Apply to `st`, returning the outputs renamed, which need new route instances.
+    fn apply(self, st: &mut State) -> Vec<usize> {
+        for (lbl, name) in st.input_labels.iter_mut().zip(self.input_names) {
+            lbl.name = name;
+        }
+        let mut renamed = Vec::new();
+        for (i, name) in self.output_names.into_iter().enumerate() {
+            if i >= st.output_labels.len() || st.output_labels[i].name == name {
+                continue;
+            }
+            st.output_labels[i].name = name;
+            renamed.push(i);
+        }
+        for (d, text) in st
+            .output_descriptions
+            .iter_mut()
+            .zip(self.output_descriptions)
+        {
+            d.text = text;
+        }
+        for (output, input) in self.routes {
+            let count = st.matrix_info.input_count;
+            if output as usize >= st.routes.len() || input.is_some_and(|i| i >= count) {
+                continue;
+            }
+            // Sources aren't discovered yet, the worker patches them once they are.
+            st.routes[output as usize].from_input = input;
+        }
+        renamed
+    }
 }
 
 struct State {
@@ -20,81 +94,188 @@ struct State {
     matrix_info: RouterMatrixInfo,
     input_labels: Vec<RouterLabel>,
     output_labels: Vec<RouterLabel>,
+    /// Input descriptions are the URLs of their sources, only those of outputs are stored.
+    output_descriptions: Vec<RouterDescription>,
     routes: Vec<RouterPatch>,
     source_map: HashMap<String, String>,
+    source_filter: Option<SourceFilter>,
     route_instances: Vec<RouteInstance>,
+    /// Whether the first discovery pass completed.
+    discovered: bool,
+    discovery_passes: u64,
+    poll_interval: Duration,
+    jitter: Duration,
 }
 
-impl NDIRouter {
-    pub fn new(
-        name: &str,
-        group: Vec<&str>,
-        max_inputs: usize,
-        output_count: usize,
-    ) -> Result<Self> {
-        let name = name.to_string();
-        let group: Arc<Vec<String>> = Arc::new(group.into_iter().map(String::from).collect());
-
-        let info = RouterInfo {
-            model: Some("NDIRouter".into()),
-            name: Some(name.clone()),
-            matrix_count: Some(1),
-        };
-        let matrix_info = RouterMatrixInfo {
-            input_count: max_inputs as u32,
-            output_count: output_count as u32,
-        };
-
-        let input_labels: Vec<RouterLabel> = (0..max_inputs)
+impl State {
+    /// State of a router named `name` with nothing discovered yet, and no route instances.
+    fn new(name: &str, max_inputs: usize, output_count: usize) -> Self {
+        let input_labels = (0..max_inputs)
             .map(|i| RouterLabel {
                 id: i as u32,
                 name: String::new(),
             })
             .collect();
-
-        let output_labels: Vec<RouterLabel> = (0..output_count)
+        let output_labels = (0..output_count)
             .map(|i| RouterLabel {
                 id: i as u32,
                 name: format!("{} {}", name, i + 1),
             })
             .collect();
+        Self {
+            info: RouterInfo {
+                model: Some("NDIRouter".into()),
+                name: Some(name.into()),
+                matrix_count: Some(1),
+                matrix_names: Some(vec![name.into()]),
+            },
+            matrix_info: RouterMatrixInfo {
+                input_count: max_inputs as u32,
+                output_count: output_count as u32,
+                frame_count: 0,
+                name: Some(name.into()),
+            },
+            input_labels,
+            output_labels,
+            output_descriptions: (0..output_count as u32)
+                .map(RouterDescription::empty)
+                .collect(),
+            // Fresh route instances have no source.
+            routes: (0..output_count)
+                .map(|i| RouterPatch::parked(i as u32))
+                .collect(),
+            source_map: HashMap::new(),
+            source_filter: None,
+            route_instances: Vec::with_capacity(output_count),
+            discovered: false,
+            discovery_passes: 0,
+            poll_interval: NDIRouter::DEFAULT_POLL_INTERVAL,
+            jitter: Duration::ZERO,
+        }
+    }
+}
 
-        let routes = (0..output_count)
-            .map(|i| RouterPatch {
-                from_input: 0,
-                to_output: i as u32,
-            })
-            .collect();
+impl NDIRouter {
+    /// Time between NDI source discovery passes unless configured otherwise.
+    pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+    pub fn new(
+        name: &str,
+        group: Vec<&str>,
+        max_inputs: usize,
+        output_count: usize,
+    ) -> Result<Self> {
+        let group: Arc<Vec<String>> = Arc::new(group.into_iter().map(String::from).collect());
+        let mut st = State::new(name, max_inputs, output_count);
 
-        let mut ris = Vec::with_capacity(output_count);
         let group_ref: Vec<&str> = group.iter().map(|e| e.as_ref()).collect();
-        for lbl in output_labels.iter() {
+        for lbl in st.output_labels.iter() {
             let ri = RouteInstance::create(&lbl.name, &group_ref)?;
-            ris.push(ri);
+            st.route_instances.push(ri);
         }
 
-        let state = Arc::new(Mutex::new(State {
-            info,
-            matrix_info,
-            input_labels,
-            output_labels,
-            routes,
-            source_map: HashMap::new(),
-            route_instances: ris,
-        }));
-
         let (tx, _) = broadcast::channel(16);
 
         let router = NDIRouter {
             group: group.clone(),
-            state: state.clone(),
+            state: Arc::new(Mutex::new(st)),
             tx: tx.clone(),
+            persistence: None,
+            writing: Arc::default(),
         };
 
         router.spawn_worker();
         Ok(router)
     }
 
+    /// Persist routes and labels to a JSON file at `path`.
+    ///
+    /// If the file exists, its output labels and routes are re-applied first.
+    /// Inputs keep their slots and get patched once their source is discovered again.
+    /// Every successful route, output label or output description update rewrites the file.
+    #[cfg(feature = "serde")]
+    pub fn with_persistence(mut self, path: PathBuf) -> Result<Self> {
+        if let Some(saved) = crate::persist::load::<PersistedState>(&path)? {
+            let mut st = self.state.lock().unwrap();
+            let group_ref: Vec<&str> = self.group.iter().map(|e| e.as_ref()).collect();
+            for i in saved.apply(&mut st) {
+                st.route_instances[i] =
+                    RouteInstance::create(&st.output_labels[i].name, &group_ref)?;
+            }
+            debug!(?path, "Restored persisted NDI router state");
+        }
+        self.persistence = Some(Arc::new(path));
+        Ok(self)
+    }
+
+    /// Scan for NDI sources every `interval`, [NDIRouter::DEFAULT_POLL_INTERVAL] by default.
+    ///
+    /// Shorter intervals pick up new and vanished sources sooner, at the cost of more
+    /// discovery traffic and locking the router state more often. With many sources or
+    /// routers on a network, longer intervals keep the load down.
+    pub fn with_poll_interval(self, interval: Duration) -> Self {
+        self.state.lock().unwrap().poll_interval = interval;
+        self
+    }
+
+    /// Wait up to `jitter` longer than the poll interval, picked at random for every pass.
+    ///
+    /// Keeps many routers started at once from scanning in lockstep, at the cost of
+    /// discovery being less predictable. None by default.
+    pub fn with_jitter(self, jitter: Duration) -> Self {
+        self.state.lock().unwrap().jitter = jitter;
+        self
+    }
+
+    /// Only map NDI sources to inputs whose name `filter` accepts.
+    ///
+    /// Keeps busy networks from filling up the inputs with unwanted sources. The filter is
+    /// called for every source on every discovery pass, with the router state locked.
+    pub fn with_source_filter(self, filter: SourceFilter) -> Self {
+        self.set_source_filter(filter);
+        self
+    }
+
+    /// Replace the source filter, see [NDIRouter::with_source_filter].
+    ///
+    /// Sources it rejects lose their inputs on the next discovery pass, like vanished ones.
+    pub fn set_source_filter(&self, filter: SourceFilter) {
+        self.state.lock().unwrap().source_filter = Some(filter);
+    }
+
+    /// Number of NDI sources currently discovered.
+    pub fn source_count(&self) -> usize {
+        self.state.lock().unwrap().source_map.len()
+    }
+
+    /// Names of the NDI sources currently discovered, sorted.
+    pub fn available_sources(&self) -> Vec<String> {
+        Self::source_names(&self.state.lock().unwrap())
+    }
+
+    fn source_names(st: &State) -> Vec<String> {
+        let mut names: Vec<String> = st.source_map.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Write the current state to the persistence file, if any.
+    ///
+    /// Failing to is only logged, the changes made it to the router regardless.
+    async fn persist(&self) {
+        #[cfg(feature = "serde")]
+        if let Some(path) = &self.persistence {
+            // Every write takes the state as it is by then, so the last one is up to date.
+            let _writing = self.writing.lock().await;
+            let saved = PersistedState::from_state(&self.state.lock().unwrap());
+            if let Err(e) = crate::persist::store_async(path, &saved).await {
+                error!(?path, "Failed to persist NDI router state: {:#}", e);
+            }
+        }
+        #[cfg(not(feature = "serde"))]
+        let _ = (&self.persistence, &self.writing);
+    }
+
     fn assert_matrix_zero(index: u32) -> Result<()> {
         if index != 0 {
             return Err(anyhow!("Only matrix 0 supported"));
@@ -117,9 +298,62 @@ impl NDIRouter {
             .any(|own| source.ndi_name.ends_with(&format!(" ({})", own)))
     }
 
+    /// Sources worth an input by name and URL: not our own outputs, and accepted by `filter`.
+    fn wanted_sources(
+        sources: Vec<Source>,
+        own_names: &[&str],
+        filter: Option<&SourceFilter>,
+    ) -> HashMap<String, String> {
+        sources
+            .into_iter()
+            .filter(|s| !Self::is_own(s, own_names))
+            .filter(|s| filter.is_none_or(|f| f(&s.ndi_name)))
+            .map(|s| (s.ndi_name, s.url_address))
+            .collect()
+    }
+
+    /// Labels of `after` differing from those of `before`.
+    fn changed_labels(before: &[RouterLabel], after: &[RouterLabel]) -> Vec<RouterLabel> {
+        after
+            .iter()
+            .zip(before)
+            .filter(|(a, b)| a.name != b.name)
+            .map(|(a, _)| a.clone())
+            .collect()
+    }
+
+    /// Input descriptions, the URL of the source on each input.
+    fn input_descriptions(st: &State) -> Vec<RouterDescription> {
+        st.input_labels
+            .iter()
+            .map(|l| RouterDescription {
+                id: l.id,
+                text: st.source_map.get(&l.name).cloned().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Input status, present while the source on it is discovered.
+    fn input_status(st: &State) -> Vec<RouterPortStatus> {
+        st.input_labels
+            .iter()
+            .map(|l| RouterPortStatus {
+                id: l.id,
+                kind: PortKind::Input,
+                present: Some(st.source_map.contains_key(&l.name)),
+                port_type: Some("NDI".into()),
+            })
+            .collect()
+    }
+
     /// Patch output to input, both in state as with NDI
-    fn patch_output(st: &mut State, output: u32, input: u32) -> Result<()> {
-        let name = &st.input_labels[input as usize].name;
+    ///
+    /// Parking the output clears it, just like patching an input without a source.
+    fn patch_output(st: &mut State, output: u32, input: Option<u32>) -> Result<()> {
+        let name = match input {
+            Some(input) => st.input_labels[input as usize].name.as_str(),
+            None => "",
+        };
         if name.is_empty() {
             // No label -> No Source -> Clear.
             st.route_instances[output as usize].clear()?;
@@ -130,16 +364,72 @@ impl NDIRouter {
                 .get(name)
                 .ok_or_else(|| anyhow!("No such source '{}'", name))?;
             let src = Source {
-                ndi_name: name.clone(),
+                ndi_name: name.into(),
                 url_address: url.clone(),
             };
             st.route_instances[output as usize].change(&src)?;
-            debug!("Patched NDI Output {} to Input {}", output, input);
+            debug!("Patched NDI Output {} to Input {:?}", output, input);
         }
         st.routes[output as usize].from_input = input;
         Ok(())
     }
 
+    /// Apply `changes` with `patch`, undoing those applied already if one fails.
+    ///
+    /// Fails with the error of the failing patch, or [RouterError::PartiallyApplied] if some
+    /// outputs couldn't be restored either.
+    fn apply_patches(
+        st: &mut State,
+        index: u32,
+        changes: &[RouterPatch],
+        mut patch: impl FnMut(&mut State, u32, Option<u32>) -> Result<()>,
+    ) -> Result<()> {
+        let mut undo = Vec::with_capacity(changes.len());
+        for p in changes {
+            let before = st.routes[p.to_output as usize];
+            if let Err(e) = patch(st, p.to_output, p.from_input) {
+                for before in undo.iter().rev() {
+                    let RouterPatch {
+                        from_input,
+                        to_output,
+                    } = *before;
+                    if let Err(e) = patch(st, to_output, from_input) {
+                        error!("Failed to restore NDI Output {}: {:?}", to_output, e);
+                    }
+                }
+                // The first entry per output holds its route before the update.
+                let mut seen = Vec::new();
+                let mut applied = Vec::new();
+                for before in &undo {
+                    if seen.contains(&before.to_output) {
+                        continue;
+                    }
+                    seen.push(before.to_output);
+                    if st.routes[before.to_output as usize] != *before {
+                        applied.push(before.to_output);
+                    }
+                }
+                if applied.is_empty() {
+                    return Err(e);
+                }
+                let mut failed = Vec::new();
+                for p in changes {
+                    if !applied.contains(&p.to_output) && !failed.contains(&p.to_output) {
+                        failed.push(p.to_output);
+                    }
+                }
+                return Err(RouterError::PartiallyApplied {
+                    index,
+                    applied,
+                    failed,
+                }
+                .into());
+            }
+            undo.push(before);
+        }
+        Ok(())
+    }
+
     fn spawn_worker(&self) {
         let state = self.state.clone();
         let tx = self.tx.clone();
@@ -160,14 +450,13 @@ impl NDIRouter {
                     let mut st = state.lock().unwrap();
 
                     let own_names = Self::own_output_names(&st);
-                    let mut current = HashMap::new();
-                    for s in sources {
-                        if !Self::is_own(&s, &own_names) {
-                            current.insert(s.ndi_name.clone(), s.url_address.clone());
-                        }
-                    }
+                    let current =
+                        Self::wanted_sources(sources, &own_names, st.source_filter.as_ref());
 
                     let mut actually_changed = false;
+                    let labels_before = st.input_labels.clone();
+                    let descriptions_before = Self::input_descriptions(&st);
+                    let status_before = Self::input_status(&st);
                     let old: Vec<_> = st.source_map.keys().cloned().collect();
 
                     // Removed NDI sources
@@ -178,11 +467,13 @@ impl NDIRouter {
                                 st.input_labels.iter_mut().position(|l| l.name == ndi_name)
                             {
                                 st.input_labels[pos].name.clear();
-                                // unpatch any outputs on that input
+                                // park any outputs on that input
                                 for out in 0..st.routes.len() {
-                                    if st.routes[out].from_input as usize == pos {
-                                        if let Err(e) = Self::patch_output(&mut st, out as u32, 0) {
-                                            error!("Failed to patch output {} with removed source to source 0: {:?}", out, e);
+                                    if st.routes[out].from_input == Some(pos as u32) {
+                                        if let Err(e) =
+                                            Self::patch_output(&mut st, out as u32, None)
+                                        {
+                                            error!("Failed to park output {} with removed source: {:?}", out, e);
                                         }
                                     }
                                 }
@@ -197,15 +488,38 @@ impl NDIRouter {
                     for (ndi_name, url) in current.iter() {
                         match st.source_map.get::<String>(ndi_name) {
                             None => {
-                                // New source, find blank label slot.
-                                if let Some(slot) =
-                                    st.input_labels.iter_mut().find(|l| l.name.is_empty())
-                                {
-                                    let id = slot.id;
-                                    slot.name = ndi_name.clone();
+                                // New source, prefer a slot it held before, else a blank one.
+                                let slot = st
+                                    .input_labels
+                                    .iter()
+                                    .position(|l| &l.name == ndi_name)
+                                    .or_else(|| {
+                                        st.input_labels.iter().position(|l| l.name.is_empty())
+                                    });
+                                if let Some(pos) = slot {
+                                    let reserved = !st.input_labels[pos].name.is_empty();
+                                    st.input_labels[pos].name = ndi_name.clone();
                                     st.source_map.insert(ndi_name.clone(), url.clone());
                                     actually_changed = true;
-                                    debug!(?ndi_name, input = ?id, "New NDI Source");
+                                    debug!(?ndi_name, input = ?pos, "New NDI Source");
+
+                                    // Restore outputs still routed to a reserved slot.
+                                    if reserved {
+                                        for out in 0..st.routes.len() {
+                                            if st.routes[out].from_input == Some(pos as u32) {
+                                                if let Err(e) = Self::patch_output(
+                                                    &mut st,
+                                                    out as u32,
+                                                    Some(pos as u32),
+                                                ) {
+                                                    error!(
+                                                        "Failed to restore output {}: {:?}",
+                                                        out, e
+                                                    );
+                                                }
+                                            }
+                                        }
+                                    }
                                 }
                             }
                             Some(old_url) if old_url != url => {
@@ -218,7 +532,7 @@ impl NDIRouter {
                                     .unwrap();
                                 debug!(?ndi_name, input = ?input_index, "Updated NDI Source URL");
                                 for patch in &st.routes {
-                                    if patch.from_input as usize == input_index {
+                                    if patch.from_input == Some(input_index as u32) {
                                         let out = patch.to_output as usize;
                                         let src = Source {
                                             ndi_name: ndi_name.clone(),
@@ -234,17 +548,106 @@ impl NDIRouter {
                         }
                     }
 
+                    if !st.discovered {
+                        // Sources found so far are part of the initial state.
+                        st.discovered = true;
+                        debug!("First NDI discovery pass done");
+                        let _ = tx.send(RouterEvent::Connected);
+                        let _ =
+                            tx.send(RouterEvent::InputLabelSnapshot(0, st.input_labels.clone()));
+                        let _ = tx.send(RouterEvent::OutputLabelSnapshot(
+                            0,
+                            st.output_labels.clone(),
+                        ));
+                        let _ = tx.send(RouterEvent::RouteSnapshot(0, st.routes.clone()));
+                    } else if actually_changed {
+                        let changed = Self::changed_labels(&labels_before, &st.input_labels);
+                        if !changed.is_empty() {
+                            let _ = tx.send(RouterEvent::InputLabelUpdate(0, changed));
+                        }
+                    }
+                    if st.discovered {
+                        // Covers URL changes of known sources too.
+                        let changed: Vec<_> = Self::input_descriptions(&st)
+                            .into_iter()
+                            .zip(descriptions_before)
+                            .filter(|(a, b)| a != b)
+                            .map(|(a, _)| a)
+                            .collect();
+                        if !changed.is_empty() {
+                            let ev = RouterEvent::DescriptionUpdate(0, PortKind::Input, changed);
+                            let _ = tx.send(ev);
+                        }
+                        let changed: Vec<_> = Self::input_status(&st)
+                            .into_iter()
+                            .zip(status_before)
+                            .filter(|(a, b)| a != b)
+                            .map(|(a, _)| a)
+                            .collect();
+                        if !changed.is_empty() {
+                            let _ = tx.send(RouterEvent::PortStatusUpdate(0, changed));
+                        }
+                    }
                     if actually_changed {
-                        let _ = tx.send(RouterEvent::InputLabelUpdate(0, st.input_labels.clone()));
+                        let _ = tx.send(RouterEvent::SourcesChanged(Self::source_names(&st)));
                     }
                 }
 
-                tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+                let delay = {
+                    let mut st = state.lock().unwrap();
+                    st.discovery_passes += 1;
+                    poll_delay(st.poll_interval, st.jitter)
+                };
+                tokio::time::sleep(delay).await;
             }
         });
     }
 }
 
+/// `interval` plus a random share of `jitter`.
+fn poll_delay(interval: Duration, jitter: Duration) -> Duration {
+    if jitter.is_zero() {
+        return interval;
+    }
+    // Every RandomState is seeded differently, that's random enough to spread routers out.
+    let random = RandomState::new().build_hasher().finish();
+    interval + jitter.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+impl RouterIntrospect for NDIRouter {
+    fn name(&self) -> &'static str {
+        "NDIRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        let persistence = match &self.persistence {
+            Some(path) => path.display().to_string(),
+            None => "none".into(),
+        };
+        let st = self.state.lock().unwrap();
+        vec![
+            ("group".into(), self.group.join(",")),
+            ("persistence".into(), persistence),
+            ("poll_interval".into(), format!("{:?}", st.poll_interval)),
+            ("jitter".into(), format!("{:?}", st.jitter)),
+            (
+                "source_filter".into(),
+                if st.source_filter.is_some() {
+                    "set"
+                } else {
+                    "none"
+                }
+                .into(),
+            ),
+        ]
+    }
+
+    fn counters(&self) -> Vec<(String, u64)> {
+        let passes = self.state.lock().unwrap().discovery_passes;
+        vec![("discovery_passes".into(), passes)]
+    }
+}
+
 impl MatrixRouter for NDIRouter {
     async fn is_alive(&self) -> Result<bool> {
         Ok(true)
@@ -275,30 +678,106 @@ impl MatrixRouter for NDIRouter {
 
     async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
         Self::assert_matrix_zero(index)?;
-        let mut st = self.state.lock().unwrap();
-        let mut actually_changed = false;
-        for label in changed {
-            let i = label.id as usize;
-            if i >= st.output_labels.len() {
-                return Err(anyhow!("Output {} out of range", i));
+        let updated = {
+            let mut st = self.state.lock().unwrap();
+            let count = st.output_labels.len() as u32;
+            if let Some(label) = changed.iter().find(|l| l.id >= count) {
+                return Err(RouterError::LabelOutOfRange {
+                    index,
+                    kind: LabelKind::Output,
+                    id: label.id,
+                    max: count.checked_sub(1),
+                }
+                .into());
             }
-            if st.output_labels[i].name != label.name {
+
+            // Create all new instances first, so a failure leaves the state untouched.
+            let group_ref: Vec<&str> = self.group.iter().map(|e| e.as_ref()).collect();
+            let mut renamed = Vec::new();
+            for label in changed {
                 // only recreate on actual rename
-                let group_ref: Vec<&str> = self.group.iter().map(|e| e.as_ref()).collect();
-                let ri = RouteInstance::create(&label.name, &group_ref)?;
+                if st.output_labels[label.id as usize].name != label.name {
+                    let ri = RouteInstance::create(&label.name, &group_ref)?;
+                    renamed.push((label, ri));
+                }
+            }
+            let mut applied = Vec::with_capacity(renamed.len());
+            for (label, ri) in renamed {
+                let i = label.id as usize;
                 st.route_instances[i] = ri;
                 st.output_labels[i].name = label.name.clone();
-                actually_changed = true;
+                applied.push(label);
+            }
+            if !applied.is_empty() {
+                let _ = self.tx.send(RouterEvent::OutputLabelUpdate(0, applied));
+                true
+            } else {
+                false
             }
+        };
+        if updated {
+            self.persist().await;
         }
-        if actually_changed {
+        Ok(())
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        Self::assert_matrix_zero(index)?;
+        Ok(Self::input_descriptions(&self.state.lock().unwrap()))
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        Self::assert_matrix_zero(index)?;
+        Ok(self.state.lock().unwrap().output_descriptions.clone())
+    }
+
+    async fn update_input_descriptions(&self, _: u32, _: Vec<RouterDescription>) -> Result<()> {
+        Err(anyhow!("NDI input descriptions are the source URLs"))
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        Self::assert_matrix_zero(index)?;
+        {
+            let mut st = self.state.lock().unwrap();
+            st.matrix_info
+                .check_descriptions(index, LabelKind::Output, &changed)?;
+            if changed.is_empty() {
+                return Ok(());
+            }
+            for d in &changed {
+                st.output_descriptions[d.id as usize].text = d.text.clone();
+            }
             let _ = self
                 .tx
-                .send(RouterEvent::OutputLabelUpdate(0, st.output_labels.clone()));
+                .send(RouterEvent::DescriptionUpdate(0, PortKind::Output, changed));
         }
+        self.persist().await;
         Ok(())
     }
 
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        Self::assert_matrix_zero(index)?;
+        Ok(Self::input_status(&self.state.lock().unwrap()))
+    }
+
+    /// Outputs are route instances of our own, always there.
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        Self::assert_matrix_zero(index)?;
+        let count = self.state.lock().unwrap().matrix_info.output_count;
+        Ok((0..count)
+            .map(|id| RouterPortStatus {
+                id,
+                kind: PortKind::Output,
+                present: Some(true),
+                port_type: Some("NDI".into()),
+            })
+            .collect())
+    }
+
     async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
         Self::assert_matrix_zero(index)?;
         Ok(self.state.lock().unwrap().routes.clone())
@@ -306,28 +785,443 @@ impl MatrixRouter for NDIRouter {
 
     async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
         Self::assert_matrix_zero(index)?;
-        let mut st = self.state.lock().unwrap();
-        let mut actually_changed = false;
+        let (result, updated) = {
+            let mut st = self.state.lock().unwrap();
+            for p in changes.iter() {
+                let count = st.matrix_info.input_count;
+                if p.to_output as usize >= st.routes.len()
+                    || p.from_input.is_some_and(|i| i >= count)
+                {
+                    return Err(anyhow!("Patch {:?} out of bounds", p));
+                }
+                // Parked outputs need no source.
+                let Some(input) = p.from_input else { continue };
+                let name = &st.input_labels[input as usize].name;
+                if !name.is_empty() && !st.source_map.contains_key(name) {
+                    return Err(anyhow!("No such source '{}'", name));
+                }
+            }
 
-        for p in changes {
-            let output = p.to_output;
-            let input = p.from_input;
-            if output as usize >= st.routes.len() || input >= st.matrix_info.input_count {
-                return Err(anyhow!("Patch {:?} out of bounds", p));
+            let result = Self::apply_patches(&mut st, index, &changes, Self::patch_output);
+            let applied = match &result {
+                Ok(()) => changes,
+                Err(e) => match e.downcast_ref::<RouterError>() {
+                    Some(RouterError::PartiallyApplied { applied, .. }) => applied
+                        .iter()
+                        .map(|&output| st.routes[output as usize])
+                        .collect(),
+                    _ => vec![],
+                },
+            };
+            let updated = !applied.is_empty();
+            if updated {
+                let _ = self.tx.send(RouterEvent::RouteUpdate(0, applied));
             }
-            Self::patch_output(&mut st, output, input)?;
-            actually_changed = true;
+            (result, updated)
+        };
+        if updated {
+            self.persist().await;
         }
+        result
+    }
 
-        if actually_changed {
-            let _ = self.tx.send(RouterEvent::RouteUpdate(0, st.routes.clone()));
+    /// Inputs are named after the sources found, so their labels can't be changed.
+    fn capabilities(&self) -> RouterCapabilities {
+        RouterCapabilities {
+            can_update_input_labels: false,
+            ..RouterCapabilities::default()
         }
-        Ok(())
     }
 
+    /// Starts with [RouterEvent::Connected] if the first discovery pass is done.
     async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
-        let bs = BroadcastStream::new(self.tx.subscribe());
-        let filtered = bs.filter_map(|r| r.ok());
-        Ok(futures_util::StreamExt::boxed(filtered))
+        let (rx, discovered) = {
+            let st = self.state.lock().unwrap();
+            (self.tx.subscribe(), st.discovered)
+        };
+        let initial = discovered.then_some(RouterEvent::Connected);
+        let filtered = BroadcastStream::new(rx).filter_map(|r| r.ok());
+        Ok(futures_util::StreamExt::boxed(
+            tokio_stream::iter(initial).chain(filtered),
+        ))
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persisted_state_roundtrip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ndi.json");
+        assert_eq!(crate::persist::load::<PersistedState>(&path)?, None);
+
+        let saved = PersistedState {
+            input_names: vec!["CAM (1)".into(), String::new()],
+            output_names: vec!["Out 1".into()],
+            routes: vec![(0, Some(1))],
+            output_descriptions: vec!["Projector, main hall".into()],
+        };
+        crate::persist::store(&path, &saved)?;
+        assert_eq!(crate::persist::load(&path)?, Some(saved));
+
+        // Files written before descriptions were persisted still load.
+        std::fs::write(
+            &path,
+            r#"{"input_names":[],"output_names":["Out 1"],"routes":[]}"#,
+        )?;
+        let old = crate::persist::load::<PersistedState>(&path)?.unwrap();
+        assert!(old.output_descriptions.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn state_persists_and_restores() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ndi.json");
+
+        let mut st = State::new("Test", 4, 2);
+        st.input_labels[2].name = "CAM (1)".into();
+        st.output_labels[1].name = "Renamed".into();
+        st.output_descriptions[0].text = "Projector".into();
+        st.routes[1].from_input = Some(2);
+        crate::persist::store(&path, &PersistedState::from_state(&st))?;
+
+        // A restarted router gets it all back, asking for a new route instance where renamed.
+        let mut restarted = State::new("Test", 4, 2);
+        let saved = crate::persist::load::<PersistedState>(&path)?.unwrap();
+        assert_eq!(saved.apply(&mut restarted), vec![1]);
+        assert_eq!(restarted.input_labels, st.input_labels);
+        assert_eq!(restarted.output_labels, st.output_labels);
+        assert_eq!(restarted.output_descriptions, st.output_descriptions);
+        assert_eq!(restarted.routes, st.routes);
+
+        // Routes that don't fit a smaller router anymore are dropped.
+        let mut smaller = State::new("Test", 2, 2);
+        let saved = crate::persist::load::<PersistedState>(&path)?.unwrap();
+        saved.apply(&mut smaller);
+        assert_eq!(smaller.routes[1], RouterPatch::parked(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "requires the NDI runtime"]
+    async fn state_survives_restart() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ndi.json");
+        let label = RouterLabel {
+            id: 1,
+            name: "Renamed".into(),
+        };
+        let patch = RouterPatch {
+            from_input: Some(2),
+            to_output: 1,
+        };
+
+        {
+            let router = NDIRouter::new("Test", vec![], 4, 2)?.with_persistence(path.clone())?;
+            router.update_output_labels(0, vec![label.clone()]).await?;
+            router.update_routes(0, vec![patch]).await?;
+        }
+
+        // "Restart" with the same file.
+        let router = NDIRouter::new("Test", vec![], 4, 2)?.with_persistence(path)?;
+        assert!(router.get_output_labels(0).await?.contains(&label));
+        assert!(router.get_routes(0).await?.contains(&patch));
+        Ok(())
+    }
+
+    #[test]
+    fn failed_patch_undoes_earlier_ones() {
+        let mut st = State::new("Test", 4, 3);
+        let changes = [
+            RouterPatch {
+                from_input: Some(1),
+                to_output: 0,
+            },
+            RouterPatch {
+                from_input: Some(2),
+                to_output: 1,
+            },
+            RouterPatch {
+                from_input: Some(3),
+                to_output: 2,
+            },
+        ];
+        let before = st.routes.clone();
+        let patch = |st: &mut State, output: u32, input: Option<u32>| {
+            if output == 2 {
+                return Err(anyhow!("Refused"));
+            }
+            st.routes[output as usize].from_input = input;
+            Ok(())
+        };
+        let e = NDIRouter::apply_patches(&mut st, 0, &changes, patch).unwrap_err();
+        assert_eq!(e.to_string(), "Refused");
+        assert_eq!(st.routes, before);
+
+        // Output 0 can't be parked again, so it stays patched.
+        let patch = |st: &mut State, output: u32, input: Option<u32>| {
+            if output == 2 || (output == 0 && input.is_none()) {
+                return Err(anyhow!("Refused"));
+            }
+            st.routes[output as usize].from_input = input;
+            Ok(())
+        };
+        let e = NDIRouter::apply_patches(&mut st, 0, &changes, patch).unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<RouterError>(),
+            Some(&RouterError::PartiallyApplied {
+                index: 0,
+                applied: vec![0],
+                failed: vec![1, 2],
+            })
+        );
+        assert_eq!(st.routes[0].from_input, Some(1));
+        assert_eq!(st.routes[1..], before[1..]);
+    }
+
+    #[test]
+    fn poll_delay_within_jitter() {
+        let interval = Duration::from_millis(100);
+        assert_eq!(poll_delay(interval, Duration::ZERO), interval);
+        let jitter = Duration::from_millis(50);
+        let delays: Vec<_> = (0..100).map(|_| poll_delay(interval, jitter)).collect();
+        assert!(delays
+            .iter()
+            .all(|d| (interval..=interval + jitter).contains(d)));
+        assert!(delays.iter().any(|d| *d != delays[0]));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires the NDI runtime"]
+    async fn short_poll_interval() -> Result<()> {
+        let router = NDIRouter::new("Test", vec![], 4, 2)?
+            .with_poll_interval(Duration::from_millis(10))
+            .with_jitter(Duration::from_millis(5));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let passes = router.counters()[0].1;
+        // Far more than the single pass the default interval would allow.
+        assert!(passes >= 5, "only {} discovery passes", passes);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "requires the NDI runtime"]
+    async fn sources_match_input_labels() -> Result<()> {
+        let router = NDIRouter::new("Test", vec![], 4, 2)?;
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let sources = router.available_sources();
+        assert_eq!(sources.len(), router.source_count());
+        let labels = router.get_input_labels(0).await?;
+        for name in &sources {
+            assert!(
+                labels.iter().any(|l| &l.name == name),
+                "{} has no input",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "requires the NDI runtime"]
+    async fn descriptions() -> Result<()> {
+        let router = NDIRouter::new("Test", vec![], 4, 2)?;
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        // Inputs are described by the URL of their source, blank ones by nothing.
+        let labels = router.get_input_labels(0).await?;
+        for (l, d) in labels.iter().zip(router.get_input_descriptions(0).await?) {
+            assert_eq!(l.name.is_empty(), d.text.is_empty());
+        }
+        assert!(router
+            .update_input_descriptions(0, vec![RouterDescription::empty(0)])
+            .await
+            .is_err());
+
+        let d = RouterDescription {
+            id: 1,
+            text: "Stream encoder".into(),
+        };
+        router
+            .update_output_descriptions(0, vec![d.clone()])
+            .await?;
+        assert_eq!(router.get_output_descriptions(0).await?[1], d);
+        let bad = RouterDescription { id: 2, ..d };
+        assert!(router
+            .update_output_descriptions(0, vec![bad])
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "requires the NDI runtime"]
+    async fn port_status() -> Result<()> {
+        let router = NDIRouter::new("Test", vec![], 4, 2)?;
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let sources = router.available_sources();
+        for st in router.get_input_status(0).await? {
+            let label = &router.get_input_labels(0).await?[st.id as usize];
+            assert_eq!(st.present, Some(sources.contains(&label.name)));
+        }
+        assert!(router
+            .get_output_status(0)
+            .await?
+            .iter()
+            .all(|st| st.present == Some(true)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "requires the NDI runtime"]
+    async fn parking_clears_outputs() -> Result<()> {
+        let router = NDIRouter::new("Test", vec![], 4, 2)?;
+        // Nothing is routed before it is patched.
+        assert!(router.get_routes(0).await?.iter().all(|p| p.is_parked()));
+        let patch = RouterPatch {
+            from_input: Some(0),
+            to_output: 1,
+        };
+        router.update_routes(0, vec![patch]).await?;
+        assert_eq!(router.get_route(0, 1).await?, patch);
+        router
+            .update_routes(0, vec![RouterPatch::parked(1)])
+            .await?;
+        assert!(router.get_route(0, 1).await?.is_parked());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "requires the NDI runtime"]
+    async fn transaction_rolls_back() -> Result<()> {
+        let router = NDIRouter::new("Test", vec![], 4, 2)?;
+        let before = router.get_routes(0).await?;
+        // Unlabeled inputs clear the output, so patching them needs no discovered sources.
+        let transaction = RouteTransaction::default()
+            .patch(0, 0)
+            .patch(0, 1)
+            .patch(99, 0);
+        let outcome = apply_transaction(&router, 0, transaction).await?;
+        assert!(outcome.is_rolled_back());
+        assert_eq!(outcome.applied.len(), 2);
+        assert_eq!(router.get_routes(0).await?, before);
+        Ok(())
+    }
+
+    fn source(ndi_name: &str, url_address: &str) -> Source {
+        Source {
+            ndi_name: ndi_name.into(),
+            url_address: url_address.into(),
+        }
+    }
+
+    fn no_test_sources() -> SourceFilter {
+        Arc::new(|name: &str| !name.starts_with("Test"))
+    }
+
+    #[test]
+    fn filtered_sources() {
+        let sources = vec![
+            source("CAM (1)", "10.0.0.2:5961"),
+            source("Test Pattern (1)", "10.0.0.3:5961"),
+            source("HOST (Out 1)", "127.0.0.1:5962"),
+        ];
+        let wanted = NDIRouter::wanted_sources(sources.clone(), &["Out 1"], None);
+        assert_eq!(wanted.len(), 2);
+        assert!(wanted.contains_key("Test Pattern (1)"));
+
+        let filter = no_test_sources();
+        let wanted = NDIRouter::wanted_sources(sources, &["Out 1"], Some(&filter));
+        assert_eq!(wanted.keys().collect::<Vec<_>>(), vec!["CAM (1)"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires the NDI runtime"]
+    async fn source_filter_hides_inputs() -> Result<()> {
+        let router = NDIRouter::new("Test", vec![], 16, 2)?;
+        tokio::time::sleep(Duration::from_secs(3)).await;
+
+        // Sources accepted so far go away once the filter rejects them.
+        router.set_source_filter(no_test_sources());
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let labels = router.get_input_labels(0).await?;
+        assert!(labels.iter().all(|l| !l.name.starts_with("Test")));
+        assert!(router
+            .available_sources()
+            .iter()
+            .all(|s| !s.starts_with("Test")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "requires the NDI runtime"]
+    async fn connected_after_discovery() -> Result<()> {
+        use tokio::time::timeout;
+
+        let router = NDIRouter::new("Test", vec![], 4, 2)?;
+        let mut early = router.event_stream().await?;
+        let first = timeout(Duration::from_secs(5), early.next()).await?;
+        assert_eq!(first, Some(RouterEvent::Connected));
+
+        // Once discovered, late subscribers start connected right away.
+        let mut late = router.event_stream().await?;
+        assert_eq!(late.next().await, Some(RouterEvent::Connected));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "requires the NDI runtime"]
+    async fn events_carry_changes_only() -> Result<()> {
+        use tokio::time::timeout;
+
+        let router = NDIRouter::new("Test", vec![], 4, 8)?;
+        let mut events = router.event_stream().await?;
+        let first = timeout(Duration::from_secs(5), events.next()).await?;
+        assert_eq!(first, Some(RouterEvent::Connected));
+        match events.next().await {
+            Some(RouterEvent::InputLabelSnapshot(0, labels)) => assert_eq!(labels.len(), 4),
+            ev => panic!("expected input label snapshot, got {:?}", ev),
+        }
+        match events.next().await {
+            Some(RouterEvent::OutputLabelSnapshot(0, labels)) => assert_eq!(labels.len(), 8),
+            ev => panic!("expected output label snapshot, got {:?}", ev),
+        }
+        match events.next().await {
+            Some(RouterEvent::RouteSnapshot(0, routes)) => assert_eq!(routes.len(), 8),
+            ev => panic!("expected route snapshot, got {:?}", ev),
+        }
+
+        let patch = RouterPatch {
+            from_input: Some(0),
+            to_output: 5,
+        };
+        router.update_routes(0, vec![patch]).await?;
+        loop {
+            match events.next().await {
+                Some(RouterEvent::RouteUpdate(0, routes)) => {
+                    assert_eq!(routes, vec![patch]);
+                    break;
+                }
+                Some(_) => continue,
+                None => panic!("event stream ended"),
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "requires the NDI runtime"]
+    async fn input_labels_are_fixed() -> Result<()> {
+        let router = NDIRouter::new("Test", vec![], 4, 2)?;
+        let caps = router.capabilities();
+        assert!(!caps.can_update_input_labels);
+        assert!(caps.can_update_output_labels);
+        assert_eq!(caps.matrix_count, 1);
+        // Wrappers pass it on.
+        let router = CachingRouter::new(router, Duration::from_secs(1)).into_dyn();
+        assert!(!router.capabilities().can_update_input_labels);
+        Ok(())
     }
 }
@@ -5,49 +5,256 @@
 use crate::matrix::*;
 use anyhow::{anyhow, Result};
 use futures_core::stream::BoxStream;
-use futures_util::{SinkExt, StreamExt};
-use std::{collections::VecDeque, net::SocketAddr, sync::Arc};
+use futures_util::StreamExt;
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(feature = "serde")]
+use std::path::PathBuf;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio::{
+    io::{AsyncRead, AsyncWrite},
     net::TcpStream,
     select,
     sync::{broadcast, mpsc, oneshot, RwLock},
 };
-use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{wrappers::BroadcastStream, Stream};
 use tokio_util::codec::Framed;
 use tracing::{error, info};
-use videohub::{VideohubCodec, VideohubMessage};
+use videohub::{ProtocolProfile, VideohubClient, VideohubCodec, VideohubMessage};
 
 /// Which part of the cache changed?
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+///
+/// Labels and routes carry the event announcing the change, a snapshot for the first block
+/// since connecting, else just the entries of the block. None if the block was refused.
+#[derive(Clone, Debug, PartialEq, Eq)]
 enum CacheEvent {
-    InputLabels,
-    OutputLabels,
-    Routes,
+    InputLabels(Option<RouterEvent>),
+    OutputLabels(Option<RouterEvent>),
+    Routes(Option<RouterEvent>),
+    Locks,
+    FrameLabels,
+    FrameRoutes,
+    Alarms,
+    /// Port status that changed.
+    PortStatus(Vec<RouterPortStatus>),
+    MatrixInfo,
+    Connected,
     Disconnected,
 }
 
 /// In‐memory cache of last‐seen state.
 #[derive(Default)]
 struct Cache {
+    /// Whether a peer is currently connected, only changed together with the matching event.
+    connected: bool,
     info: RouterInfo,
     matrix_info: RouterMatrixInfo,
     input_labels: Option<Vec<RouterLabel>>,
     output_labels: Option<Vec<RouterLabel>>,
     routes: Option<Vec<RouterPatch>>,
+    locks: Option<Vec<RouterLock>>,
+    frame_labels: Option<Vec<RouterLabel>>,
+    frame_routes: Option<Vec<RouterPatch>>,
+    /// Alarms in the order the peer first reported them.
+    alarms: Vec<RouterAlarm>,
+    /// Port status as reported by the peer, ports it never mentioned are unknown.
+    input_status: Vec<RouterPortStatus>,
+    output_status: Vec<RouterPortStatus>,
+    /// Whether the peer reported `Take Mode: true` in its configuration.
+    take_mode: bool,
+    /// Protocol version from the peer's preamble.
+    protocol_version: String,
 }
 
 /// Commands sent into the single reader loop.
 enum Command {
-    /// Send msg and capture next ACK/NAK in resp.
+    /// Send msg and report in resp whether it was ACKed in time.
     Ack {
         msg: VideohubMessage,
         resp: oneshot::Sender<bool>,
     },
     /// Just send msg.
     Send { msg: VideohubMessage },
+    /// Close the connection, failing everything still waiting, and report in done.
+    Disconnect { done: oneshot::Sender<()> },
+}
+
+/// Subscribers of raw incoming messages, each with its filter.
+type RawSubscribers = Arc<Mutex<Vec<(RawFilter, broadcast::Sender<VideohubMessage>)>>>;
+
+/// Predicate selecting raw messages for [VideohubRouter::subscribe_raw].
+pub type RawFilter = fn(&VideohubMessage) -> bool;
+
+/// Where messages exchanged with the peer go besides the cache, shared with the reader loop.
+#[derive(Clone, Default)]
+struct Taps {
+    /// subscribers of raw incoming messages
+    raw: RawSubscribers,
+    /// trace log of all messages, once enabled
+    #[cfg(feature = "serde")]
+    trace: Arc<Mutex<Option<TraceLog>>>,
+}
+
+impl Taps {
+    /// Pass on `msg` about to be sent to the peer.
+    fn sent(&self, msg: &VideohubMessage) {
+        #[cfg(feature = "serde")]
+        self.trace(TraceDirection::Outbound, msg);
+        #[cfg(not(feature = "serde"))]
+        let _ = msg;
+    }
+
+    /// Pass on `msg` received from the peer.
+    fn received(&self, msg: &VideohubMessage) {
+        VideohubRouter::publish_raw(&self.raw, msg);
+        #[cfg(feature = "serde")]
+        self.trace(TraceDirection::Inbound, msg);
+    }
+
+    #[cfg(feature = "serde")]
+    fn trace(&self, direction: TraceDirection, msg: &VideohubMessage) {
+        if let Some(log) = self.trace.lock().unwrap().as_ref() {
+            log.record(direction, msg);
+        }
+    }
+}
+
+/// Number of raw messages buffered per subscriber before it starts missing some.
+const RAW_SUBSCRIBER_CAPACITY: usize = 64;
+
+/// How long to wait before retrying once all failover peers are unreachable.
+const FAILOVER_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Which peer [VideohubRouter::connect_with_failover] promotes on disconnect.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FailoverPolicy {
+    /// The first healthy peer in the given order, preferring the primary.
+    #[default]
+    Active,
+    /// The next healthy peer after the one that went away.
+    RoundRobin,
+}
+
+/// Routing blocks with more entries than this are split up by default.
+const DEFAULT_MAX_BLOCK_ENTRIES: usize = 128;
+
+/// How long connecting waits for answers to prefetch requests, see [ConnectOptions].
+const PREFETCH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Options for [VideohubRouter::connect_with].
+#[derive(Clone, Copy, Debug)]
+pub struct ConnectOptions {
+    prefetch: bool,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self { prefetch: true }
+    }
+}
+
+impl ConnectOptions {
+    /// Request labels and routes right after the handshake and wait for them, so the first
+    /// reads are served from the cache rather than costing a round-trip each. On by default.
+    ///
+    /// Peers not answering in time are left alone, reads fetch what's missing on demand.
+    pub fn with_prefetch(mut self, prefetch: bool) -> Self {
+        self.prefetch = prefetch;
+        self
+    }
+}
+
+/// What to do when the peer refuses a chunk of a chunked apply.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChunkFailurePolicy {
+    /// Stop and skip all remaining chunks.
+    #[default]
+    Abort,
+    /// Keep going with the next chunk.
+    Continue,
+}
+
+/// Options for [VideohubRouter::apply_routes].
+#[derive(Clone, Debug)]
+pub struct ApplyOptions {
+    /// Maximum number of routes per `VIDEO OUTPUT ROUTING:` block.
+    pub chunk_size: usize,
+    /// Delay between two consecutive chunks.
+    pub chunk_delay: Duration,
+    /// Behaviour once a chunk got NAK'd.
+    pub on_failure: ChunkFailurePolicy,
+}
+
+impl Default for ApplyOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: DEFAULT_MAX_BLOCK_ENTRIES,
+            chunk_delay: Duration::ZERO,
+            on_failure: ChunkFailurePolicy::default(),
+        }
+    }
+}
+
+/// Result of a single chunk, reported while applying.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkProgress {
+    /// Zero-based index of this chunk.
+    pub chunk: usize,
+    /// Total number of chunks in this apply.
+    pub chunk_count: usize,
+    /// Outputs contained in this chunk.
+    pub outputs: Vec<u32>,
+    /// Whether the peer ACK'd the chunk.
+    pub acked: bool,
+}
+
+/// Summary of a chunked apply, listing outputs by outcome.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ApplySummary {
+    /// Outputs in chunks the peer ACK'd.
+    pub applied: Vec<u32>,
+    /// Outputs in chunks the peer NAK'd.
+    pub failed: Vec<u32>,
+    /// Outputs in chunks never sent due to [ChunkFailurePolicy::Abort].
+    pub skipped: Vec<u32>,
+}
+
+impl ApplySummary {
+    /// Whether every output got applied.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.skipped.is_empty()
+    }
+
+    /// Result of an update of matrix `index` with this outcome.
+    fn into_result(self, index: u32) -> Result<()> {
+        if self.is_complete() {
+            Ok(())
+        } else if self.applied.is_empty() {
+            Err(RouterError::Refused { index }.into())
+        } else {
+            let mut failed = self.failed;
+            failed.extend(self.skipped);
+            Err(RouterError::PartiallyApplied {
+                index,
+                applied: self.applied,
+                failed,
+            }
+            .into())
+        }
+    }
 }
 
-/// A MatrixRouter speaking Videohub over TCP with caching.
+/// A MatrixRouter speaking Videohub over TCP or UNIX sockets, with caching.
+///
+/// Videohub routes always have an input, so patches parking an output are refused before
+/// anything is sent.
+#[derive(Clone)]
 pub struct VideohubRouter {
     /// send commands into the reader loop
     cmd_tx: mpsc::UnboundedSender<Command>,
@@ -55,18 +262,34 @@ pub struct VideohubRouter {
     cache: Arc<RwLock<Cache>>,
     /// broadcast cache updates
     cache_tx: broadcast::Sender<CacheEvent>,
+    /// routing blocks larger than this get chunked, `None` to never chunk them
+    max_block_entries: Option<usize>,
+    /// follow routing blocks with a take, `None` to go by the peer's configuration
+    take_mode: Option<bool>,
+    /// raw subscribers and trace log
+    taps: Taps,
+}
+
+/// Setting of the `CONFIGURATION:` block holding whether routes need to be taken.
+const TAKE_MODE: &str = "Take Mode";
+
+/// Block taking staged routes on a device in take mode.
+fn take_message() -> VideohubMessage {
+    VideohubMessage::Configuration(vec![videohub::Setting {
+        setting: TAKE_MODE.into(),
+        value: "true".into(),
+    }])
 }
 
 fn update_labels(
     opt: &mut Option<Vec<RouterLabel>>,
     changes: Vec<RouterLabel>,
-    max_idx: u32,
+    kind: LabelKind,
+    mi: &RouterMatrixInfo,
 ) -> Result<()> {
-    let mut current = opt.replace(vec![]).unwrap_or_default();
+    mi.check_labels(0, kind, &changes)?;
+    let mut current = opt.take().unwrap_or_default();
     for new in changes {
-        if new.id >= max_idx {
-            return Err(anyhow!("Label is out of index!"));
-        }
         if let Some(idx) = current.iter().position(|l| l.id == new.id) {
             current[idx].name = new.name;
         } else {
@@ -83,11 +306,14 @@ fn update_routes(
     max_input_idx: u32,
     max_output_idx: u32,
 ) -> Result<()> {
-    let mut current = opt.replace(vec![]).unwrap_or_default();
+    if let Some(&patch) = changes
+        .iter()
+        .find(|p| p.to_output >= max_output_idx || p.from_input.is_some_and(|i| i >= max_input_idx))
+    {
+        return Err(RouterError::OutOfRange { index: 0, patch }.into());
+    }
+    let mut current = opt.take().unwrap_or_default();
     for new in changes {
-        if new.to_output >= max_output_idx || new.from_input >= max_input_idx {
-            return Err(anyhow!("Patch is out of index!"));
-        }
         if let Some(idx) = current.iter().position(|p| p.to_output == new.to_output) {
             current[idx].from_input = new.from_input;
         } else {
@@ -98,45 +324,312 @@ fn update_routes(
     Ok(())
 }
 
+/// Merge reported port status into the cache, returning the entries that changed.
+fn update_port_status(
+    current: &mut Vec<RouterPortStatus>,
+    reported: Vec<RouterPortStatus>,
+) -> Vec<RouterPortStatus> {
+    let mut changed = Vec::new();
+    for new in reported {
+        match current.iter_mut().find(|s| s.id == new.id) {
+            Some(s) if *s == new => {}
+            Some(s) => {
+                *s = new.clone();
+                changed.push(new);
+            }
+            None => {
+                current.push(new.clone());
+                changed.push(new);
+            }
+        }
+    }
+    changed
+}
+
+/// Status of all `count` ports of `kind`, unknown unless reported.
+fn port_status(reported: &[RouterPortStatus], kind: PortKind, count: u32) -> Vec<RouterPortStatus> {
+    (0..count)
+        .map(|id| {
+            reported
+                .iter()
+                .find(|s| s.id == id)
+                .cloned()
+                .unwrap_or_else(|| RouterPortStatus::unknown(kind, id))
+        })
+        .collect()
+}
+
+/// Merge lock changes into the cache, refusing all of them if any is out of range.
+fn update_locks(
+    opt: &mut Option<Vec<RouterLock>>,
+    changes: Vec<RouterLock>,
+    max_output_idx: u32,
+) -> Result<()> {
+    if let Some(l) = changes.iter().find(|l| l.id >= max_output_idx) {
+        let patch = RouterPatch::parked(l.id);
+        return Err(RouterError::OutOfRange { index: 0, patch }.into());
+    }
+    let mut current = opt.take().unwrap_or_default();
+    for new in changes {
+        match current.iter_mut().find(|l| l.id == new.id) {
+            Some(l) => l.locked = new.locked,
+            None => current.push(new),
+        }
+    }
+    opt.replace(current);
+    Ok(())
+}
+
+/// The device block doesn't carry a frame buffer count, so grow it to fit the frames seen.
+fn grow_frame_count(mi: &mut RouterMatrixInfo, ids: impl Iterator<Item = u32>) {
+    if let Some(max) = ids.max() {
+        mi.frame_count = mi.frame_count.max(max + 1);
+    }
+}
+
+/// Collapse patches to one per output, the last one winning.
+/// Outputs keep the order of their first occurrence.
+fn dedup_patches(changes: Vec<RouterPatch>) -> Vec<RouterPatch> {
+    let mut out: Vec<RouterPatch> = Vec::with_capacity(changes.len());
+    for new in changes {
+        if let Some(p) = out.iter_mut().find(|p| p.to_output == new.to_output) {
+            p.from_input = new.from_input;
+        } else {
+            out.push(new);
+        }
+    }
+    out
+}
+
 impl VideohubRouter {
-    /// Connect, consume only Preamble + DeviceInfo, spawn the reader loop.
-    #[tracing::instrument]
+    /// Connect, consume only Preamble + DeviceInfo, spawn the reader loop and prefetch labels
+    /// and routes, see [ConnectOptions::with_prefetch].
+    ///
+    /// Event streams created afterwards start with [RouterEvent::Connected].
     pub async fn connect(addr: SocketAddr) -> Result<Self> {
+        Self::connect_with(addr, ConnectOptions::default()).await
+    }
+
+    /// Like [VideohubRouter::connect], with `options`.
+    #[tracing::instrument]
+    pub async fn connect_with(addr: SocketAddr, options: ConnectOptions) -> Result<Self> {
         info!("Connecting to Videohub Router");
-        let socket = TcpStream::connect(addr).await?;
-        let mut framed = Framed::new(socket, VideohubCodec::default());
+        Self::connect_socket(TcpStream::connect(addr).await?, options).await
+    }
+
+    /// Like [VideohubRouter::connect], but over the UNIX socket at `path`.
+    #[cfg(unix)]
+    pub async fn connect_unix(path: &Path) -> Result<Self> {
+        Self::connect_unix_with(path, ConnectOptions::default()).await
+    }
+
+    /// Like [VideohubRouter::connect_unix], with `options`.
+    #[cfg(unix)]
+    #[tracing::instrument]
+    pub async fn connect_unix_with(path: &Path, options: ConnectOptions) -> Result<Self> {
+        info!("Connecting to Videohub Router");
+        Self::connect_socket(UnixStream::connect(path).await?, options).await
+    }
 
+    /// Set up a client on an established connection to the peer.
+    async fn connect_socket<T>(socket: T, options: ConnectOptions) -> Result<Self>
+    where
+        T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
         // Channels and cache.
-        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let (cmd_tx, mut cmd_rx) = mpsc::unbounded_channel();
+        let cache = Arc::new(RwLock::new(Cache::default()));
+        let (tx_cache, _) = broadcast::channel(32);
+
+        let framed = Self::handshake_on(socket, &cache).await?;
+        Self::mark_connected(&cache, &tx_cache).await;
+
+        // 4) build client + spawn loop
+        let taps = Taps::default();
+        let client = Self {
+            cmd_tx,
+            cache: cache.clone(),
+            cache_tx: tx_cache.clone(),
+            max_block_entries: None,
+            take_mode: None,
+            taps: taps.clone(),
+        };
+        tokio::spawn(async move {
+            Self::event_loop(&mut cmd_rx, framed, cache, tx_cache, &taps).await;
+        });
+        if options.prefetch {
+            client.prefetch().await;
+        }
+        Ok(client)
+    }
+
+    /// Request labels and routes not cached yet all at once, waiting up to
+    /// [PREFETCH_TIMEOUT] for the answers.
+    async fn prefetch(&self) {
+        // Subscribe first, answers may come quick.
+        let mut rx = self.cache_tx.subscribe();
+        let mut missing = {
+            let c = self.cache.read().await;
+            [
+                c.input_labels.is_none(),
+                c.output_labels.is_none(),
+                c.routes.is_none(),
+            ]
+        };
+        let requests = [
+            VideohubMessage::InputLabels(vec![]),
+            VideohubMessage::OutputLabels(vec![]),
+            VideohubMessage::VideoOutputRouting(vec![]),
+        ];
+        for (msg, _) in requests.into_iter().zip(missing).filter(|(_, m)| *m) {
+            if self.cmd_tx.send(Command::Send { msg }).is_err() {
+                return;
+            }
+        }
+        let answers = async {
+            while missing.contains(&true) {
+                match rx.recv().await {
+                    Ok(CacheEvent::InputLabels(_)) => missing[0] = false,
+                    Ok(CacheEvent::OutputLabels(_)) => missing[1] = false,
+                    Ok(CacheEvent::Routes(_)) => missing[2] = false,
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+        };
+        if tokio::time::timeout(PREFETCH_TIMEOUT, answers)
+            .await
+            .is_err()
+        {
+            info!("Peer didn't answer prefetch requests in time, fetching on demand");
+        }
+    }
+
+    /// Connect to the first reachable of several redundant peers.
+    ///
+    /// Commands always go to the active peer. Once it disconnects, the next healthy one
+    /// according to `policy` gets promoted, emitting [RouterEvent::Disconnected] and
+    /// [RouterEvent::Connected]. Commands issued in between are sent after promotion.
+    #[tracing::instrument]
+    pub async fn connect_with_failover(
+        addrs: Vec<SocketAddr>,
+        policy: FailoverPolicy,
+    ) -> Result<Self> {
+        if addrs.is_empty() {
+            return Err(anyhow!("No peers given"));
+        }
+
+        let (cmd_tx, mut cmd_rx) = mpsc::unbounded_channel();
         let cache = Arc::new(RwLock::new(Cache::default()));
         let (tx_cache, _) = broadcast::channel(32);
 
+        let (mut active, mut framed) = Self::handshake_any(&addrs, 0, &cache)
+            .await
+            .ok_or_else(|| anyhow!("None of the peers {:?} are reachable", addrs))?;
+        Self::mark_connected(&cache, &tx_cache).await;
+
+        let taps = Taps::default();
+        let client = Self {
+            cmd_tx,
+            cache: cache.clone(),
+            cache_tx: tx_cache.clone(),
+            max_block_entries: None,
+            take_mode: None,
+            taps: taps.clone(),
+        };
+        tokio::spawn(async move {
+            loop {
+                info!(peer = ?addrs[active], "Using Videohub peer");
+                if Self::event_loop(&mut cmd_rx, framed, cache.clone(), tx_cache.clone(), &taps)
+                    .await
+                {
+                    break;
+                }
+
+                let start = match policy {
+                    FailoverPolicy::Active => 0,
+                    FailoverPolicy::RoundRobin => (active + 1) % addrs.len(),
+                };
+                loop {
+                    if let Some(next) = Self::handshake_any(&addrs, start, &cache).await {
+                        (active, framed) = next;
+                        break;
+                    }
+                    if cmd_rx.is_closed() {
+                        return;
+                    }
+                    tokio::time::sleep(FAILOVER_RETRY_INTERVAL).await;
+                }
+                Self::mark_connected(&cache, &tx_cache).await;
+            }
+        });
+        Ok(client)
+    }
+
+    /// Try all peers in order, starting at `start`, returning the first successful one.
+    async fn handshake_any(
+        addrs: &[SocketAddr],
+        start: usize,
+        cache: &RwLock<Cache>,
+    ) -> Option<(usize, Framed<TcpStream, VideohubCodec>)> {
+        for n in 0..addrs.len() {
+            let idx = (start + n) % addrs.len();
+            match Self::handshake(addrs[idx], cache).await {
+                Ok(framed) => return Some((idx, framed)),
+                Err(e) => error!(peer = ?addrs[idx], error = ?e, "Failed to connect to peer"),
+            }
+        }
+        None
+    }
+
+    /// Connect and consume Preamble + DeviceInfo, resetting the cache to the new peer.
+    async fn handshake(
+        addr: SocketAddr,
+        cache: &RwLock<Cache>,
+    ) -> Result<Framed<TcpStream, VideohubCodec>> {
+        Self::handshake_on(TcpStream::connect(addr).await?, cache).await
+    }
+
+    /// Like [VideohubRouter::handshake], on an established connection.
+    async fn handshake_on<T>(socket: T, cache: &RwLock<Cache>) -> Result<Framed<T, VideohubCodec>>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut framed = Framed::new(socket, VideohubCodec::default());
+
         // Read initial Preamble and DeviceInfo.
-        let mut seen_pre = false;
+        let mut version = None;
         let mut seen_di = false;
-        while !(seen_pre && seen_di) {
+        while !(version.is_some() && seen_di) {
             let msg = framed
                 .next()
                 .await
                 .ok_or_else(|| anyhow!("EOF during connect"))??;
-            if let VideohubMessage::Preamble(_) = msg {
-                seen_pre = true;
+            if let VideohubMessage::Preamble(p) = &msg {
+                version = Some(p.version.clone());
             }
             if let VideohubMessage::DeviceInfo(di) = msg.clone() {
                 seen_di = true;
                 let mut c = cache.write().await;
-                c.info = RouterInfo {
-                    model: di.model_name.clone(),
-                    name: di.friendly_name.clone(),
-                    matrix_count: Some(1),
-                };
-                c.matrix_info = RouterMatrixInfo {
-                    input_count: di.video_inputs.ok_or_else(|| {
-                        anyhow!("Videohub Device does not contain video input count")
-                    })?,
-                    output_count: di.video_outputs.ok_or_else(|| {
-                        anyhow!("Videohub Device does not contain video output count")
-                    })?,
+                *c = Cache {
+                    info: RouterInfo {
+                        model: di.model_name.clone(),
+                        name: di.friendly_name.clone(),
+                        matrix_count: Some(1),
+                        matrix_names: None,
+                    },
+                    matrix_info: RouterMatrixInfo {
+                        input_count: di.video_inputs.ok_or_else(|| {
+                            anyhow!("Videohub Device does not contain video input count")
+                        })?,
+                        output_count: di.video_outputs.ok_or_else(|| {
+                            anyhow!("Videohub Device does not contain video output count")
+                        })?,
+                        // Not part of the device block, learned from the frame blocks.
+                        frame_count: 0,
+                        name: None,
+                    },
+                    ..Default::default()
                 };
                 info!(
                     "Found {}x{} Router",
@@ -144,27 +637,150 @@ impl VideohubRouter {
                 );
             }
         }
+        // Set afterwards, the device block resets the cache.
+        cache.write().await.protocol_version = version.unwrap_or_default();
+        Ok(framed)
+    }
 
-        // 4) build client + spawn loop
-        let client = Self {
-            cmd_tx,
-            cache: cache.clone(),
-            cache_tx: tx_cache.clone(),
-        };
-        tokio::spawn(Self::event_loop(cmd_rx, framed, cache, tx_cache));
-        Ok(client)
+    /// Flag the cache as connected and tell subscribers.
+    /// Done under the cache lock, so [MatrixRouter::event_stream] can't miss or double it.
+    async fn mark_connected(cache: &RwLock<Cache>, cache_tx: &broadcast::Sender<CacheEvent>) {
+        let mut c = cache.write().await;
+        c.connected = true;
+        let _ = cache_tx.send(CacheEvent::Connected);
+    }
+
+    /// Counterpart to [VideohubRouter::mark_connected].
+    async fn mark_disconnected(cache: &RwLock<Cache>, cache_tx: &broadcast::Sender<CacheEvent>) {
+        let mut c = cache.write().await;
+        c.connected = false;
+        let _ = cache_tx.send(CacheEvent::Disconnected);
+    }
+
+    /// Split routing blocks sent by [MatrixRouter::update_routes] above `max` entries, for
+    /// devices refusing big blocks. Chunks go out in order.
+    ///
+    /// Chunked updates aren't all-or-nothing: when the peer refuses a chunk after taking
+    /// earlier ones, the update fails with [RouterError::PartiallyApplied] listing both.
+    /// That's why this is off by default, so updates go out as a single block the peer takes
+    /// or refuses as a whole, as [MatrixRouter] promises. Big salvos can be chunked on their
+    /// own with [VideohubRouter::apply_routes] instead.
+    pub fn with_max_block_entries(mut self, max: usize) -> Self {
+        self.max_block_entries = Some(max.max(1));
+        self
+    }
+
+    /// Follow every routing block with a `Take Mode: true` configuration block, or never.
+    ///
+    /// By default this goes by the peer, which reports the setting in its initial dump.
+    /// Devices in take mode only apply staged routes once taken.
+    pub fn with_take_mode(mut self, take_mode: bool) -> Self {
+        self.take_mode = Some(take_mode);
+        self
+    }
+
+    /// Whether routing blocks need to be taken, see [VideohubRouter::with_take_mode].
+    async fn take_mode(&self) -> bool {
+        match self.take_mode {
+            Some(take_mode) => take_mode,
+            None => self.cache.read().await.take_mode,
+        }
+    }
+
+    /// Apply routes in chunks according to `opts`.
+    ///
+    /// Patches are deduplicated (last one wins) across the whole set before splitting.
+    /// Refused chunks don't produce an error, they are listed in the returned summary.
+    /// Parked outputs can't be sent at all and fail the whole apply.
+    pub async fn apply_routes(
+        &self,
+        changes: Vec<RouterPatch>,
+        opts: &ApplyOptions,
+    ) -> Result<ApplySummary> {
+        self.apply_routes_with_progress(changes, opts, |_| {}).await
+    }
+
+    /// Like [VideohubRouter::apply_routes], calling `progress` after each chunk.
+    pub async fn apply_routes_with_progress(
+        &self,
+        changes: Vec<RouterPatch>,
+        opts: &ApplyOptions,
+        mut progress: impl FnMut(&ChunkProgress) + Send,
+    ) -> Result<ApplySummary> {
+        let changes = dedup_patches(changes);
+        self.cache
+            .read()
+            .await
+            .matrix_info
+            .check_patches(0, &changes)?;
+        let routes = changes
+            .iter()
+            .map(|&p| videohub::Route::try_from(p))
+            .collect::<Result<Vec<_>>>()?;
+
+        let chunk_size = opts.chunk_size.max(1);
+        let chunks: Vec<&[RouterPatch]> = changes.chunks(chunk_size).collect();
+        let chunk_count = chunks.len();
+        let mut summary = ApplySummary::default();
+        for (n, (chunk, rs)) in chunks
+            .into_iter()
+            .zip(routes.chunks(chunk_size))
+            .enumerate()
+        {
+            let outputs: Vec<u32> = chunk.iter().map(|p| p.to_output).collect();
+            if n > 0 && !summary.failed.is_empty() && opts.on_failure == ChunkFailurePolicy::Abort {
+                summary.skipped.extend(outputs);
+                continue;
+            }
+            if n > 0 && !opts.chunk_delay.is_zero() {
+                tokio::time::sleep(opts.chunk_delay).await;
+            }
+
+            let mut acked = self
+                .request_acked(VideohubMessage::VideoOutputRouting(rs.to_vec()))
+                .await?;
+            if acked && self.take_mode().await {
+                acked = self.request_acked(take_message()).await?;
+            }
+            if acked {
+                let mut c = self.cache.write().await;
+                let in_count = c.matrix_info.input_count;
+                let out_count = c.matrix_info.output_count;
+                update_routes(&mut c.routes, chunk.to_vec(), in_count, out_count)?;
+            }
+
+            progress(&ChunkProgress {
+                chunk: n,
+                chunk_count,
+                outputs: outputs.clone(),
+                acked,
+            });
+            if acked {
+                summary.applied.extend(outputs);
+            } else {
+                summary.failed.extend(outputs);
+            }
+        }
+        Ok(summary)
     }
 
     /// The single reader/select loop.
-    #[tracing::instrument(skip(cmd_rx, framed, cache, cache_tx))]
-    async fn event_loop(
-        mut cmd_rx: mpsc::UnboundedReceiver<Command>,
-        framed: Framed<TcpStream, VideohubCodec>,
+    /// Returns true once the router itself is gone, false if the peer went away.
+    #[tracing::instrument(skip(cmd_rx, framed, cache, cache_tx, taps))]
+    async fn event_loop<T>(
+        cmd_rx: &mut mpsc::UnboundedReceiver<Command>,
+        framed: Framed<T, VideohubCodec>,
         cache: Arc<RwLock<Cache>>,
         cache_tx: broadcast::Sender<CacheEvent>,
-    ) {
-        let mut pending_commands: VecDeque<oneshot::Sender<bool>> = VecDeque::new();
-        let (mut sink, mut stream) = framed.split();
+        taps: &Taps,
+    ) -> bool
+    where
+        T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        // The client does the protocol I/O and ACK matching, this only keeps the cache.
+        // It needs the version to hold back pings the peer won't answer.
+        let version = cache.read().await.protocol_version.clone();
+        let (client, mut events) = VideohubClient::from_framed_with_version(framed, &version);
 
         loop {
             select! {
@@ -172,39 +788,52 @@ impl VideohubRouter {
                 cmd = cmd_rx.recv() => {
                     match cmd {
                         Some(Command::Send { msg }) => {
-                            let _ = sink.send(msg).await;
+                            taps.sent(&msg);
+                            let _ = client.send(msg);
                         },
                         Some(Command::Ack { msg, resp }) => {
-                            // Queue the responder, then actually send the command.
-                            pending_commands.push_back(resp);
-                            let _ = sink.send(msg).await;
+                            // Queued right away, so commands keep their order.
+                            taps.sent(&msg);
+                            let reply = client.request(msg);
+                            tokio::spawn(async move {
+                                let _ = resp.send(reply.await.is_ok());
+                            });
                         },
+                        Some(Command::Disconnect { done }) => {
+                            info!("Disconnecting from peer");
+                            // Refuse new commands, fail those queued behind this one.
+                            cmd_rx.close();
+                            while let Ok(cmd) = cmd_rx.try_recv() {
+                                if let Command::Ack { resp, .. } = cmd {
+                                    let _ = resp.send(false);
+                                }
+                            }
+                            // The connection closes once what was sent before is out,
+                            // requests still waiting for their ACK fail then.
+                            drop(client);
+                            Self::mark_disconnected(&cache, &cache_tx).await;
+                            let _ = done.send(());
+                            return true;
+                        }
                         None => {
                             info!("Command receiver closed, stopping");
-                            let _ = cache_tx.send(CacheEvent::Disconnected);
-                            break;
+                            Self::mark_disconnected(&cache, &cache_tx).await;
+                            return true;
                         }
                      }
                 }
 
-                // Incoming frames
-                frame = stream.next() => {
-                    let Some(msg) = frame else {
-                        info!("Peer closed connection, stopping");
-                        let _ = cache_tx.send(CacheEvent::Disconnected);
-                        break;
-                    };
-                    let Ok(msg) = msg else {
-                        error!(error = ?msg.unwrap_err(), "Videohub Codec encountered error");
-                        break;
+                // Incoming messages
+                msg = events.next() => {
+                    let Some(msg) = msg else {
+                        info!("Connection to peer closed, stopping");
+                        Self::mark_disconnected(&cache, &cache_tx).await;
+                        return false;
                     };
+                    taps.received(&msg);
 
-                    // First handle ACK/NAK if any pending
+                    // Replies were already matched to their requests by the client.
                     if matches!(msg, VideohubMessage::ACK | VideohubMessage::NAK) {
-                        if let Some(tx) = pending_commands.pop_front() {
-                            let ok = msg == VideohubMessage::ACK;
-                            let _ = tx.send(ok);
-                        }
                         continue;
                     }
 
@@ -219,46 +848,142 @@ impl VideohubRouter {
                                 c.info.name = Some(name);
                             };
 
+                            // Hubs with hot-swappable modules resend this once they change.
+                            let before = c.matrix_info.clone();
                             if let Some(in_count) = di.video_inputs {
                                 c.matrix_info.input_count = in_count;
                             };
                             if let Some(out_count) = di.video_outputs {
                                 c.matrix_info.output_count = out_count;
                             };
+                            if c.matrix_info != before {
+                                info!(
+                                    "Router changed to {}x{}",
+                                    c.matrix_info.input_count, c.matrix_info.output_count
+                                );
+                                let _ = cache_tx.send(CacheEvent::MatrixInfo);
+                            }
                         }
                         VideohubMessage::InputLabels(ls) => {
-                            let updates = ls.into_iter()
+                            let updates: Vec<RouterLabel> = ls.into_iter()
                                   .map(|l| l.into())
                                   .collect();
 
-                            let count = c.matrix_info.input_count;
-                            if let Err(e) = update_labels(&mut c.input_labels, updates, count) {
-                                error!(error = ?e, "Failed to update labels from received InputLabels message");
+                            let mi = c.matrix_info.clone();
+                            let first = c.input_labels.is_none();
+                            let ev = match update_labels(&mut c.input_labels, updates.clone(), LabelKind::Input, &mi) {
+                                Ok(()) if first => Some(RouterEvent::InputLabelSnapshot(0, c.input_labels.clone().unwrap_or_default())),
+                                Ok(()) => Some(RouterEvent::InputLabelUpdate(0, updates)),
+                                Err(e) => {
+                                    error!(error = ?e, "Failed to update labels from received InputLabels message");
+                                    None
+                                }
                             };
-                            let _ = cache_tx.send(CacheEvent::InputLabels);
+                            let _ = cache_tx.send(CacheEvent::InputLabels(ev));
                         }
                         VideohubMessage::OutputLabels(ls) => {
-                            let updates = ls.into_iter()
+                            let updates: Vec<RouterLabel> = ls.into_iter()
                                   .map(|l| l.into())
                                   .collect();
 
-                            let count = c.matrix_info.output_count;
-                            if let Err(e) = update_labels(&mut c.output_labels, updates, count) {
-                                error!(error = ?e, "Failed to update labels from received OutputLabels message");
+                            let mi = c.matrix_info.clone();
+                            let first = c.output_labels.is_none();
+                            let ev = match update_labels(&mut c.output_labels, updates.clone(), LabelKind::Output, &mi) {
+                                Ok(()) if first => Some(RouterEvent::OutputLabelSnapshot(0, c.output_labels.clone().unwrap_or_default())),
+                                Ok(()) => Some(RouterEvent::OutputLabelUpdate(0, updates)),
+                                Err(e) => {
+                                    error!(error = ?e, "Failed to update labels from received OutputLabels message");
+                                    None
+                                }
                             };
-                            let _ = cache_tx.send(CacheEvent::OutputLabels);
+                            let _ = cache_tx.send(CacheEvent::OutputLabels(ev));
                         }
                         VideohubMessage::VideoOutputRouting(rs) => {
-                            let updates = rs.into_iter()
+                            let updates: Vec<RouterPatch> = rs.into_iter()
+                                  .map(|p| p.into())
+                                  .collect();
+
+                            let in_count = c.matrix_info.input_count;
+                            let out_count = c.matrix_info.output_count;
+                            let first = c.routes.is_none();
+                            let ev = match update_routes(&mut c.routes, updates.clone(), in_count, out_count) {
+                                Ok(()) if first => Some(RouterEvent::RouteSnapshot(0, c.routes.clone().unwrap_or_default())),
+                                Ok(()) => Some(RouterEvent::RouteUpdate(0, updates)),
+                                Err(e) => {
+                                    error!(error = ?e, "Failed to update routes from received VideoOutputRouting message");
+                                    None
+                                }
+                            };
+                            let _ = cache_tx.send(CacheEvent::Routes(ev));
+                        }
+                        VideohubMessage::VideoOutputLocks(ls) => {
+                            let updates = ls.into_iter()
+                                  .map(|l| l.into())
+                                  .collect();
+
+                            let out_count = c.matrix_info.output_count;
+                            if let Err(e) = update_locks(&mut c.locks, updates, out_count) {
+                                error!(error = ?e, "Failed to update locks from received VideoOutputLocks message");
+                            };
+                            let _ = cache_tx.send(CacheEvent::Locks);
+                        }
+                        VideohubMessage::FrameLabels(ls) => {
+                            let updates: Vec<RouterLabel> = ls.into_iter()
+                                  .map(|l| l.into())
+                                  .collect();
+
+                            grow_frame_count(&mut c.matrix_info, updates.iter().map(|l| l.id));
+                            let mi = c.matrix_info.clone();
+                            if let Err(e) = update_labels(&mut c.frame_labels, updates, LabelKind::Frame, &mi) {
+                                error!(error = ?e, "Failed to update labels from received FrameLabels message");
+                            };
+                            let _ = cache_tx.send(CacheEvent::FrameLabels);
+                        }
+                        VideohubMessage::AlarmStatus(alarms) => {
+                            // Blocks may only carry the alarms that changed.
+                            for alarm in alarms {
+                                match c.alarms.iter_mut().find(|a| a.name == alarm.name) {
+                                    Some(a) => a.status = alarm.status,
+                                    None => c.alarms.push(alarm.into()),
+                                }
+                            }
+                            let _ = cache_tx.send(CacheEvent::Alarms);
+                        }
+                        VideohubMessage::VideoInputStatus(ports) => {
+                            let reported = ports.into_iter()
+                                  .map(|p| RouterPortStatus::from_hardware(PortKind::Input, p))
+                                  .collect();
+                            let changed = update_port_status(&mut c.input_status, reported);
+                            if !changed.is_empty() {
+                                let _ = cache_tx.send(CacheEvent::PortStatus(changed));
+                            }
+                        }
+                        VideohubMessage::VideoOutputStatus(ports) => {
+                            let reported = ports.into_iter()
+                                  .map(|p| RouterPortStatus::from_hardware(PortKind::Output, p))
+                                  .collect();
+                            let changed = update_port_status(&mut c.output_status, reported);
+                            if !changed.is_empty() {
+                                let _ = cache_tx.send(CacheEvent::PortStatus(changed));
+                            }
+                        }
+                        VideohubMessage::Configuration(settings) => {
+                            if let Some(s) = settings.iter().find(|s| s.setting.eq_ignore_ascii_case(TAKE_MODE)) {
+                                c.take_mode = s.value.eq_ignore_ascii_case("true");
+                            }
+                        }
+                        VideohubMessage::FrameBufferRouting(rs) => {
+                            let updates: Vec<RouterPatch> = rs.into_iter()
                                   .map(|p| p.into())
                                   .collect();
 
+                            grow_frame_count(&mut c.matrix_info, updates.iter().map(|p| p.to_output));
                             let in_count = c.matrix_info.input_count;
-                            let out_count = c.matrix_info.input_count;
-                            if let Err(e) = update_routes(&mut c.routes, updates, in_count, out_count) {
-                                error!(error = ?e, "Failed to update routes from received VideoOutputRouting message");
+                            let frame_count = c.matrix_info.frame_count;
+                            if let Err(e) = update_routes(&mut c.frame_routes, updates, in_count, frame_count) {
+                                error!(error = ?e, "Failed to update routes from received FrameBufferRouting message");
                             };
-                            let _ = cache_tx.send(CacheEvent::Routes);
+                            let _ = cache_tx.send(CacheEvent::FrameRoutes);
                         }
                         _ => {}
                     }
@@ -267,6 +992,77 @@ impl VideohubRouter {
         }
     }
 
+    /// Subscribe to raw incoming messages matching `filter`, as received from the peer.
+    ///
+    /// Messages are passed on regardless of whether they also get handled internally, the
+    /// handshake of a (re)connection excluded. Slow subscribers miss messages.
+    pub fn subscribe_raw(&self, filter: RawFilter) -> impl Stream<Item = VideohubMessage> {
+        let (tx, rx) = broadcast::channel(RAW_SUBSCRIBER_CAPACITY);
+        self.taps.raw.lock().unwrap().push((filter, tx));
+        BroadcastStream::new(rx).filter_map(|r| async move { r.ok() })
+    }
+
+    /// Trace all messages exchanged with the peer from now on into the file at `path`, as
+    /// JSON Lines of [TraceEntry]s, replacing the file and any trace log enabled before.
+    ///
+    /// Like raw subscriptions, the handshake of a (re)connection is not traced.
+    #[cfg(feature = "serde")]
+    pub async fn enable_trace_log(&self, path: PathBuf) -> Result<()> {
+        let log = TraceLog::create(&path).await?;
+        let previous = self.taps.trace.lock().unwrap().replace(log);
+        if let Some(previous) = previous {
+            previous.flush().await;
+        }
+        Ok(())
+    }
+
+    /// Stop tracing, returning once everything traced so far is written.
+    #[cfg(feature = "serde")]
+    pub async fn disable_trace_log(&self) {
+        let log = self.taps.trace.lock().unwrap().take();
+        if let Some(log) = log {
+            log.flush().await;
+        }
+    }
+
+    /// Pass `msg` on to all interested raw subscribers, forgetting the ones gone.
+    fn publish_raw(raw: &RawSubscribers, msg: &VideohubMessage) {
+        let mut subs = raw.lock().unwrap();
+        subs.retain(|(filter, tx)| {
+            if tx.receiver_count() == 0 {
+                return false;
+            }
+            if filter(msg) {
+                let _ = tx.send(msg.clone());
+            }
+            true
+        });
+    }
+
+    /// Close the connection to the peer for a clean shutdown, unlike merely dropping.
+    ///
+    /// Requests still waiting for their ACK fail, as do all later ones of this router and its
+    /// clones, while cached state stays readable. Event streams get a final
+    /// [RouterEvent::Disconnected].
+    /// Disconnecting again does nothing. With failover, this waits for a peer to be promoted.
+    pub async fn disconnect(&self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        if self.cmd_tx.send(Command::Disconnect { done: tx }).is_err() {
+            // Gone already.
+            return Ok(());
+        }
+        let _ = rx.await;
+        Ok(())
+    }
+
+    /// A Videohub only has a single matrix, refuse all others.
+    fn check_index(idx: u32) -> Result<()> {
+        if idx != 0 {
+            return Err(anyhow!("Matrix index {} out of range", idx));
+        }
+        Ok(())
+    }
+
     /// Send a message expecting ACK/NAK.
     async fn request_acked(&self, msg: VideohubMessage) -> Result<bool> {
         let (tx, rx) = oneshot::channel();
@@ -276,24 +1072,58 @@ impl VideohubRouter {
         Ok(rx.await.unwrap_or(false))
     }
 
-    /// Send a message and wait for a specific cache event.
-    async fn request_and_wait_cache(&self, msg: VideohubMessage, want: CacheEvent) -> Result<()> {
+    /// Send a message and wait for a cache event matching `want`.
+    async fn request_and_wait_cache(
+        &self,
+        msg: VideohubMessage,
+        want: impl Fn(&CacheEvent) -> bool,
+    ) -> Result<()> {
         self.cmd_tx
             .send(Command::Send { msg })
             .map_err(|_| anyhow!("request channel closed"))?;
         let mut rx = self.cache_tx.subscribe();
         while let Ok(ev) = rx.recv().await {
-            if ev == want {
+            if want(&ev) {
                 return Ok(());
             }
         }
-        Err(anyhow!("no cache event {:?}", want))
+        Err(anyhow!("no matching cache event"))
+    }
+}
+
+impl RouterIntrospect for VideohubRouter {
+    fn name(&self) -> &'static str {
+        "VideohubRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        let take_mode = match self.take_mode {
+            Some(take_mode) => take_mode.to_string(),
+            None => "auto".into(),
+        };
+        let max_block_entries = match self.max_block_entries {
+            Some(max) => max.to_string(),
+            None => "unlimited".into(),
+        };
+        vec![
+            ("max_block_entries".into(), max_block_entries),
+            ("take_mode".into(), take_mode),
+        ]
     }
 }
 
 impl MatrixRouter for VideohubRouter {
+    /// Pings the peer, or for peers before protocol 2.7 not answering pings, tells whether
+    /// it's connected.
     async fn is_alive(&self) -> Result<bool> {
-        Ok(self.request_acked(VideohubMessage::Ping).await?)
+        {
+            let c = self.cache.read().await;
+            let profile = ProtocolProfile::from_version(&c.protocol_version);
+            if profile.is_some_and(|p| !p.has_ping()) {
+                return Ok(c.connected);
+            }
+        }
+        self.request_acked(VideohubMessage::Ping).await
     }
 
     async fn get_router_info(&self) -> Result<RouterInfo> {
@@ -301,136 +1131,341 @@ impl MatrixRouter for VideohubRouter {
         Ok(c.info.clone())
     }
 
-    async fn get_matrix_info(&self, _idx: u32) -> Result<RouterMatrixInfo> {
+    async fn get_matrix_info(&self, idx: u32) -> Result<RouterMatrixInfo> {
+        Self::check_index(idx)?;
         let c = self.cache.read().await;
         Ok(c.matrix_info.clone())
     }
 
-    async fn get_input_labels(&self, _idx: u32) -> Result<Vec<RouterLabel>> {
+    async fn get_input_labels(&self, idx: u32) -> Result<Vec<RouterLabel>> {
+        Self::check_index(idx)?;
         {
             let c = self.cache.read().await;
             if let Some(ls) = &c.input_labels {
                 return Ok(ls.clone());
             }
         }
-        self.request_and_wait_cache(
-            VideohubMessage::InputLabels(vec![]),
-            CacheEvent::InputLabels,
-        )
+        self.request_and_wait_cache(VideohubMessage::InputLabels(vec![]), |ev| {
+            matches!(ev, CacheEvent::InputLabels(_))
+        })
         .await?;
         let c = self.cache.read().await;
-        Ok(c.input_labels.clone().unwrap())
+        // The peer's answer may have been unusable, e.g. out of range.
+        c.input_labels
+            .clone()
+            .ok_or_else(|| anyhow!("Peer sent no usable input labels"))
     }
 
-    async fn get_output_labels(&self, _idx: u32) -> Result<Vec<RouterLabel>> {
+    async fn get_output_labels(&self, idx: u32) -> Result<Vec<RouterLabel>> {
+        Self::check_index(idx)?;
         {
             let c = self.cache.read().await;
             if let Some(ls) = &c.output_labels {
                 return Ok(ls.clone());
             }
         }
-        self.request_and_wait_cache(
-            VideohubMessage::OutputLabels(vec![]),
-            CacheEvent::OutputLabels,
-        )
+        self.request_and_wait_cache(VideohubMessage::OutputLabels(vec![]), |ev| {
+            matches!(ev, CacheEvent::OutputLabels(_))
+        })
         .await?;
         let c = self.cache.read().await;
-        Ok(c.output_labels.clone().unwrap())
+        c.output_labels
+            .clone()
+            .ok_or_else(|| anyhow!("Peer sent no usable output labels"))
     }
 
-    async fn update_input_labels(&self, _idx: u32, changed: Vec<RouterLabel>) -> Result<()> {
+    async fn update_input_labels(&self, idx: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        Self::check_index(idx)?;
+        let mi = self.cache.read().await.matrix_info.clone();
+        mi.check_labels(idx, LabelKind::Input, &changed)?;
         let lbs = changed.clone().into_iter().map(|l| l.into()).collect();
         let ok = self
             .request_acked(VideohubMessage::InputLabels(lbs))
             .await?;
         if ok {
             let mut c = self.cache.write().await;
-            let count = c.matrix_info.input_count;
-            update_labels(&mut c.input_labels, changed, count)?;
+            let mi = c.matrix_info.clone();
+            update_labels(&mut c.input_labels, changed, LabelKind::Input, &mi)?;
             Ok(())
         } else {
-            Err(anyhow!("NAK"))
+            Err(RouterError::Refused { index: idx }.into())
         }
     }
 
-    async fn update_output_labels(&self, _idx: u32, changed: Vec<RouterLabel>) -> Result<()> {
+    async fn update_output_labels(&self, idx: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        Self::check_index(idx)?;
+        let mi = self.cache.read().await.matrix_info.clone();
+        mi.check_labels(idx, LabelKind::Output, &changed)?;
         let lbs = changed.clone().into_iter().map(|l| l.into()).collect();
         let ok = self
             .request_acked(VideohubMessage::OutputLabels(lbs))
             .await?;
         if ok {
             let mut c = self.cache.write().await;
-            let count = c.matrix_info.input_count;
-            update_labels(&mut c.input_labels, changed, count)?;
+            let mi = c.matrix_info.clone();
+            update_labels(&mut c.output_labels, changed, LabelKind::Output, &mi)?;
             Ok(())
         } else {
-            Err(anyhow!("NAK"))
+            Err(RouterError::Refused { index: idx }.into())
         }
     }
 
-    async fn get_routes(&self, _idx: u32) -> Result<Vec<RouterPatch>> {
+    async fn get_routes(&self, idx: u32) -> Result<Vec<RouterPatch>> {
+        Self::check_index(idx)?;
         {
             let c = self.cache.read().await;
             if let Some(r) = &c.routes {
                 return Ok(r.clone());
             }
         }
-        self.request_and_wait_cache(
-            VideohubMessage::VideoOutputRouting(vec![]),
-            CacheEvent::Routes,
-        )
+        self.request_and_wait_cache(VideohubMessage::VideoOutputRouting(vec![]), |ev| {
+            matches!(ev, CacheEvent::Routes(_))
+        })
         .await?;
         let c = self.cache.read().await;
-        Ok(c.routes.clone().unwrap())
+        c.routes
+            .clone()
+            .ok_or_else(|| anyhow!("Peer sent no usable routes"))
+    }
+
+    async fn get_route_for_output(&self, idx: u32, output: u32) -> Result<Option<RouterPatch>> {
+        Self::check_index(idx)?;
+        let cached = {
+            let c = self.cache.read().await;
+            c.routes
+                .as_ref()
+                .map(|r| r.iter().find(|p| p.to_output == output).copied())
+        };
+        let found = match cached {
+            Some(found) => found,
+            // Not cached yet, fetching all routes fills the cache.
+            None => self
+                .get_routes(idx)
+                .await?
+                .into_iter()
+                .find(|p| p.to_output == output),
+        };
+        Ok(found)
+    }
+
+    async fn get_route_for_input(&self, idx: u32, input: u32) -> Result<Vec<u32>> {
+        Self::check_index(idx)?;
+        let cached = {
+            let c = self.cache.read().await;
+            c.routes.as_ref().map(|r| {
+                r.iter()
+                    .filter(|p| p.from_input == Some(input))
+                    .map(|p| p.to_output)
+                    .collect::<Vec<_>>()
+            })
+        };
+        let mut outputs = match cached {
+            Some(outputs) => outputs,
+            // Not cached yet, fetching all routes fills the cache.
+            None => self
+                .get_routes(idx)
+                .await?
+                .into_iter()
+                .filter(|p| p.from_input == Some(input))
+                .map(|p| p.to_output)
+                .collect(),
+        };
+        // Blocks may have arrived in any order.
+        outputs.sort_unstable();
+        Ok(outputs)
     }
 
-    async fn update_routes(&self, _idx: u32, changed: Vec<RouterPatch>) -> Result<()> {
-        let rs = changed.clone().into_iter().map(|p| p.into()).collect();
+    /// Sends all patches as a single block, unless chunked with
+    /// [VideohubRouter::with_max_block_entries].
+    async fn update_routes(&self, idx: u32, changed: Vec<RouterPatch>) -> Result<()> {
+        Self::check_index(idx)?;
+        let opts = ApplyOptions {
+            chunk_size: self.max_block_entries.unwrap_or(usize::MAX),
+            ..Default::default()
+        };
+        self.apply_routes(changed, &opts).await?.into_result(idx)
+    }
+
+    /// Sends all patches as a single block, regardless of [VideohubRouter::with_max_block_entries].
+    async fn update_routes_atomic(&self, idx: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        Self::check_index(idx)?;
+        {
+            let c = self.cache.read().await;
+            c.matrix_info.check_patches(idx, &changes)?;
+        }
+        let opts = ApplyOptions {
+            chunk_size: usize::MAX,
+            ..Default::default()
+        };
+        self.apply_routes(changes, &opts).await?.into_result(idx)
+    }
+
+    /// Locks as last reported by the peer, which hubs do as part of their initial dump.
+    /// None before that, so frontends don't wait on peers never sending any.
+    async fn get_locks(&self, idx: u32) -> Result<Vec<RouterLock>> {
+        Self::check_index(idx)?;
+        let c = self.cache.read().await;
+        Ok(c.locks.clone().unwrap_or_default())
+    }
+
+    async fn update_locks(&self, idx: u32, changed: Vec<RouterLock>) -> Result<()> {
+        Self::check_index(idx)?;
+        let out_count = self.cache.read().await.matrix_info.output_count;
+        if let Some(l) = changed.iter().find(|l| l.id >= out_count) {
+            let patch = RouterPatch::parked(l.id);
+            return Err(RouterError::OutOfRange { index: idx, patch }.into());
+        }
+        let ls = changed.iter().map(|&l| l.into()).collect();
         let ok = self
-            .request_acked(VideohubMessage::VideoOutputRouting(rs))
+            .request_acked(VideohubMessage::VideoOutputLocks(ls))
+            .await?;
+        if ok {
+            let mut c = self.cache.write().await;
+            update_locks(&mut c.locks, changed, out_count)?;
+            Ok(())
+        } else {
+            Err(RouterError::Refused { index: idx }.into())
+        }
+    }
+
+    async fn get_frame_labels(&self, idx: u32) -> Result<Vec<RouterLabel>> {
+        Self::check_index(idx)?;
+        let c = self.cache.read().await;
+        Ok(c.frame_labels.clone().unwrap_or_default())
+    }
+
+    async fn update_frame_labels(&self, idx: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        Self::check_index(idx)?;
+        let mi = self.cache.read().await.matrix_info.clone();
+        mi.check_labels(idx, LabelKind::Frame, &changed)?;
+        let lbs = changed.clone().into_iter().map(|l| l.into()).collect();
+        let ok = self
+            .request_acked(VideohubMessage::FrameLabels(lbs))
+            .await?;
+        if ok {
+            let mut c = self.cache.write().await;
+            let mi = c.matrix_info.clone();
+            update_labels(&mut c.frame_labels, changed, LabelKind::Frame, &mi)?;
+            Ok(())
+        } else {
+            Err(RouterError::Refused { index: idx }.into())
+        }
+    }
+
+    async fn get_frame_routes(&self, idx: u32) -> Result<Vec<RouterPatch>> {
+        Self::check_index(idx)?;
+        let c = self.cache.read().await;
+        Ok(c.frame_routes.clone().unwrap_or_default())
+    }
+
+    async fn update_frame_routes(&self, idx: u32, changed: Vec<RouterPatch>) -> Result<()> {
+        Self::check_index(idx)?;
+        {
+            let c = self.cache.read().await;
+            let mi = &c.matrix_info;
+            if let Some(&patch) = changed.iter().find(|p| {
+                p.to_output >= mi.frame_count || p.from_input.is_some_and(|i| i >= mi.input_count)
+            }) {
+                return Err(RouterError::OutOfRange { index: idx, patch }.into());
+            }
+        }
+        let rs = changed
+            .iter()
+            .map(|&p| videohub::Route::try_from(p))
+            .collect::<Result<_>>()?;
+        let ok = self
+            .request_acked(VideohubMessage::FrameBufferRouting(rs))
             .await?;
         if ok {
             let mut c = self.cache.write().await;
             let in_count = c.matrix_info.input_count;
-            let out_count = c.matrix_info.output_count;
-            update_routes(&mut c.routes, changed, in_count, out_count)?;
+            let frame_count = c.matrix_info.frame_count;
+            update_routes(&mut c.frame_routes, changed, in_count, frame_count)?;
             Ok(())
         } else {
-            Err(anyhow!("NAK"))
+            Err(RouterError::Refused { index: idx }.into())
         }
     }
 
+    /// Alarms the peer reported so far, hubs only send them when something changes.
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        Ok(self.cache.read().await.alarms.clone())
+    }
+
+    /// As reported by the peer in its initial dump and later, unknown if it didn't.
+    async fn get_input_status(&self, idx: u32) -> Result<Vec<RouterPortStatus>> {
+        Self::check_index(idx)?;
+        let c = self.cache.read().await;
+        let count = c.matrix_info.input_count;
+        Ok(port_status(&c.input_status, PortKind::Input, count))
+    }
+
+    async fn get_output_status(&self, idx: u32) -> Result<Vec<RouterPortStatus>> {
+        Self::check_index(idx)?;
+        let c = self.cache.read().await;
+        let count = c.matrix_info.output_count;
+        Ok(port_status(&c.output_status, PortKind::Output, count))
+    }
+
+    /// Whether the peer has frame buffers is up to its matrix info.
+    fn capabilities(&self) -> RouterCapabilities {
+        RouterCapabilities {
+            matrix_count: 1,
+            can_update_input_labels: true,
+            can_update_output_labels: true,
+            can_lock_outputs: true,
+            has_frame_buffers: true,
+            has_processing_units: false,
+            has_alarm_status: true,
+            has_port_metadata: false,
+        }
+    }
+
+    /// Starts with [RouterEvent::Connected] if a peer is connected at the time of subscribing.
     async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
-        let rx = self.cache_tx.subscribe();
+        let (rx, connected) = {
+            let c = self.cache.read().await;
+            (self.cache_tx.subscribe(), c.connected)
+        };
+        let initial = connected.then_some(RouterEvent::Connected);
         let cache = Arc::clone(&self.cache);
-        let bs = BroadcastStream::new(rx)
-            .filter_map(move |res| {
-                let cache = cache.clone();
-                async move {
-                    if let Ok(ev) = res {
-                        let guard = cache.read().await;
-                        match ev {
-                            CacheEvent::InputLabels => {
-                                let input_labels = guard.input_labels.clone().unwrap_or_default();
-                                Some(RouterEvent::InputLabelUpdate(0, input_labels))
-                            }
-                            CacheEvent::OutputLabels => {
-                                let output_labels = guard.output_labels.clone().unwrap_or_default();
-                                Some(RouterEvent::OutputLabelUpdate(0, output_labels))
-                            }
-                            CacheEvent::Routes => {
-                                let routes = guard.routes.clone().unwrap_or_default();
-                                Some(RouterEvent::RouteUpdate(0, routes))
-                            }
-                            CacheEvent::Disconnected => Some(RouterEvent::Disconnected),
+        let bs = BroadcastStream::new(rx).filter_map(move |res| {
+            let cache = cache.clone();
+            async move {
+                if let Ok(ev) = res {
+                    let guard = cache.read().await;
+                    match ev {
+                        CacheEvent::InputLabels(ev)
+                        | CacheEvent::OutputLabels(ev)
+                        | CacheEvent::Routes(ev) => ev,
+                        CacheEvent::Locks => {
+                            let locks = guard.locks.clone().unwrap_or_default();
+                            Some(RouterEvent::LockUpdate(0, locks))
                         }
-                    } else {
-                        None
+                        CacheEvent::FrameLabels => {
+                            let frame_labels = guard.frame_labels.clone().unwrap_or_default();
+                            Some(RouterEvent::FrameLabelUpdate(0, frame_labels))
+                        }
+                        CacheEvent::FrameRoutes => {
+                            let frame_routes = guard.frame_routes.clone().unwrap_or_default();
+                            Some(RouterEvent::FrameRouteUpdate(0, frame_routes))
+                        }
+                        CacheEvent::Alarms => Some(RouterEvent::AlarmUpdate(guard.alarms.clone())),
+                        CacheEvent::PortStatus(statuses) => {
+                            Some(RouterEvent::PortStatusUpdate(0, statuses))
+                        }
+                        CacheEvent::MatrixInfo => {
+                            Some(RouterEvent::MatrixInfoUpdate(0, guard.matrix_info.clone()))
+                        }
+                        CacheEvent::Connected => Some(RouterEvent::Connected),
+                        CacheEvent::Disconnected => Some(RouterEvent::Disconnected),
                     }
+                } else {
+                    None
                 }
-            })
-            .boxed();
-        Ok(bs)
+            }
+        });
+        Ok(futures_util::stream::iter(initial).chain(bs).boxed())
     }
 }
 
@@ -438,15 +1473,26 @@ impl MatrixRouter for VideohubRouter {
 mod tests {
     use super::*;
     use crate::frontend::VideohubFrontend;
-    use crate::matrix::{DummyRouter, RouterEvent, RouterLabel, RouterPatch};
+    use crate::matrix::{
+        DummyRouter, LabelKind, RouterError, RouterEvent, RouterLabel, RouterLock, RouterPatch,
+    };
+    #[cfg(feature = "serde")]
+    use crate::matrix::{TraceDirection, TraceEntry};
+    use crate::test_utils::MockVideohubServer;
     use anyhow::Result;
-    use futures_util::StreamExt;
+    use futures_util::{SinkExt, StreamExt};
     use std::net::SocketAddr;
     use std::sync::Arc;
     use tokio::net::TcpListener;
     use tokio::spawn;
     use tokio::time::{timeout, Duration};
 
+    /// Connect without prefetching, for peers not answering requests or counting them.
+    async fn connect_lazy(addr: SocketAddr) -> Result<VideohubRouter> {
+        let options = ConnectOptions::default().with_prefetch(false);
+        VideohubRouter::connect_with(addr, options).await
+    }
+
     /// Start a frontend with DummyRouter on an ephemeral port, return its address and router.
     async fn spawn_frontend() -> Result<(SocketAddr, DummyRouter)> {
         let dummy = DummyRouter::with_config(1, 3, 3);
@@ -473,6 +1519,174 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn connected_is_first_event() -> Result<()> {
+        let (addr, dummy) = spawn_frontend().await?;
+        let client = VideohubRouter::connect(addr).await?;
+        let mut es = client.event_stream().await?;
+        let p = RouterPatch {
+            from_input: Some(1),
+            to_output: 0,
+        };
+        dummy.update_routes(0, vec![p]).await?;
+
+        let first = timeout(Duration::from_secs(1), es.next()).await?;
+        assert_eq!(first, Some(RouterEvent::Connected));
+        // Exactly once, the rest of the prelude doesn't repeat it.
+        loop {
+            match timeout(Duration::from_secs(1), es.next()).await? {
+                Some(RouterEvent::RouteUpdate(0, r) | RouterEvent::RouteSnapshot(0, r))
+                    if r.contains(&p) =>
+                {
+                    break
+                }
+                ev => assert_ne!(ev, Some(RouterEvent::Connected)),
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn snapshot_then_changes() -> Result<()> {
+        let (addr, dummy) = spawn_frontend().await?;
+        // Prefetching would have the snapshot before subscribing.
+        let client = connect_lazy(addr).await?;
+        let mut es = client.event_stream().await?;
+        assert_eq!(es.next().await, Some(RouterEvent::Connected));
+        // The prelude dump makes the snapshot, carrying every output.
+        loop {
+            match timeout(Duration::from_secs(1), es.next()).await? {
+                Some(RouterEvent::RouteSnapshot(0, r)) => {
+                    assert_eq!(r.len(), dummy.get_routes(0).await?.len());
+                    break;
+                }
+                Some(RouterEvent::RouteUpdate(..)) => panic!("update before the snapshot"),
+                _ => {}
+            }
+        }
+
+        let p = RouterPatch {
+            from_input: Some(1),
+            to_output: 2,
+        };
+        dummy.update_routes(0, vec![p]).await?;
+        loop {
+            match timeout(Duration::from_secs(1), es.next()).await? {
+                Some(RouterEvent::RouteUpdate(0, r)) => {
+                    assert_eq!(r, vec![p]);
+                    break;
+                }
+                Some(RouterEvent::RouteSnapshot(..)) => panic!("second snapshot"),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn out_of_range_is_typed() -> Result<()> {
+        let (addr, _dummy) = spawn_frontend().await?;
+        let client = VideohubRouter::connect(addr).await?;
+        let out_of_range = |res: Result<_>| {
+            matches!(
+                res.unwrap_err().downcast_ref::<RouterError>(),
+                Some(RouterError::OutOfRange { .. })
+            )
+        };
+        let patch = RouterPatch {
+            from_input: Some(0),
+            to_output: 3,
+        };
+
+        let lock = RouterLock {
+            id: 3,
+            locked: true,
+        };
+        assert!(out_of_range(client.update_locks(0, vec![lock]).await));
+        // The dummy has no frame buffers.
+        assert!(out_of_range(
+            client.update_frame_routes(0, vec![patch]).await
+        ));
+        let opts = ApplyOptions::default();
+        assert!(out_of_range(
+            client.apply_routes(vec![patch], &opts).await.map(|_| ())
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn atomic_routes_single_block() -> Result<()> {
+        let (addr, dummy) = spawn_frontend().await?;
+        let client = VideohubRouter::connect(addr)
+            .await?
+            .with_max_block_entries(1);
+        let mut es = dummy.event_stream().await?;
+        assert_eq!(es.next().await, Some(RouterEvent::Connected));
+        let before = dummy.get_routes(0).await?;
+        let patch = |from_input, to_output| RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        };
+
+        let err = client
+            .update_routes_atomic(0, vec![patch(1, 0), patch(1, 3)])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RouterError>(),
+            Some(RouterError::OutOfRange { .. })
+        ));
+        assert_eq!(dummy.get_routes(0).await?, before);
+
+        // Despite the block limit, the dummy sees a single update.
+        let changes = vec![patch(1, 0), patch(2, 1), patch(2, 2)];
+        client.update_routes_atomic(0, changes.clone()).await?;
+        assert_eq!(
+            timeout(Duration::from_secs(1), es.next()).await?,
+            Some(RouterEvent::RouteUpdate(0, changes.clone()))
+        );
+        assert!(timeout(Duration::from_millis(100), es.next())
+            .await
+            .is_err());
+        assert_eq!(client.get_routes(0).await?, changes);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn only_matrix_zero() -> Result<()> {
+        let (addr, _dummy) = spawn_frontend().await?;
+        let client = VideohubRouter::connect(addr).await?;
+        assert!(client.get_matrix_info(1).await.is_err());
+        assert!(client.get_routes(1).await.is_err());
+        let p = RouterPatch {
+            from_input: Some(0),
+            to_output: 0,
+        };
+        assert!(client.update_routes(1, vec![p]).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn connect_to_second_matrix() -> Result<()> {
+        let dummy = DummyRouter::with_matrices(&[(2, 0), (4, 3)]);
+        let fe = VideohubFrontend::new(Arc::new(dummy.clone()), 1);
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        spawn(fe.serve(listener));
+        let client = VideohubRouter::connect(addr).await?;
+
+        let mi = client.get_matrix_info(0).await?;
+        assert_eq!((mi.input_count, mi.output_count), (4, 3));
+        let p = RouterPatch {
+            from_input: Some(3),
+            to_output: 2,
+        };
+        client.update_routes(0, vec![p]).await?;
+        assert!(dummy.get_routes(1).await?.contains(&p));
+        assert!(dummy.get_routes(0).await?.is_empty());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn labels_roundtrip() -> Result<()> {
         let (addr, dummy) = spawn_frontend().await?;
@@ -497,6 +1711,108 @@ mod tests {
         let dlabels = dummy.get_input_labels(0).await?;
         assert!(dlabels.contains(&new));
 
+        // Out of range labels are refused before asking the peer.
+        let bad = RouterLabel {
+            id: 3,
+            name: "Y".into(),
+        };
+        let err = client.update_output_labels(0, vec![bad]).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::LabelOutOfRange {
+                index: 0,
+                kind: LabelKind::Output,
+                id: 3,
+                max: Some(2)
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn frame_buffers_roundtrip() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 3, 3).with_frame_count(2);
+        let fe = VideohubFrontend::new(Arc::new(dummy.clone()), 0);
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        spawn(fe.serve(listener));
+        let client = VideohubRouter::connect(addr).await?;
+
+        // The frame count is learned from the initial dump.
+        timeout(Duration::from_secs(1), async {
+            while client.get_matrix_info(0).await.unwrap().frame_count < 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+        assert_eq!(client.get_frame_labels(0).await?.len(), 2);
+
+        let p = RouterPatch {
+            from_input: Some(2),
+            to_output: 1,
+        };
+        client.update_frame_routes(0, vec![p]).await?;
+        assert!(client.get_frame_routes(0).await?.contains(&p));
+        assert!(dummy.get_frame_routes(0).await?.contains(&p));
+
+        let bad = RouterPatch {
+            from_input: Some(0),
+            to_output: 2,
+        };
+        assert!(client.update_frame_routes(0, vec![bad]).await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rejected_label_update_keeps_cache() {
+        let labels = vec![RouterLabel {
+            id: 0,
+            name: "A".into(),
+        }];
+        let mut cache = Some(labels.clone());
+        let changes = vec![
+            RouterLabel {
+                id: 0,
+                name: "B".into(),
+            },
+            RouterLabel {
+                id: 9,
+                name: "C".into(),
+            },
+        ];
+        let mi = RouterMatrixInfo {
+            input_count: 2,
+            ..Default::default()
+        };
+        let err = update_labels(&mut cache, changes, LabelKind::Input, &mi).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::LabelOutOfRange {
+                index: 0,
+                kind: LabelKind::Input,
+                id: 9,
+                max: Some(1)
+            })
+        );
+        assert_eq!(cache, Some(labels));
+    }
+
+    #[tokio::test]
+    async fn output_labels_roundtrip() -> Result<()> {
+        let (addr, dummy) = spawn_frontend().await?;
+        let client = VideohubRouter::connect(addr).await?;
+        let inputs = client.get_input_labels(0).await?;
+        let _ = client.get_output_labels(0).await?;
+
+        let new = RouterLabel {
+            id: 2,
+            name: "Y".into(),
+        };
+        client.update_output_labels(0, vec![new.clone()]).await?;
+        assert!(client.get_output_labels(0).await?.contains(&new));
+        assert!(dummy.get_output_labels(0).await?.contains(&new));
+        // Input labels are left alone.
+        assert_eq!(client.get_input_labels(0).await?, inputs);
         Ok(())
     }
 
@@ -509,10 +1825,10 @@ mod tests {
 
         // update one route
         let p = RouterPatch {
-            from_input: 2,
+            from_input: Some(2),
             to_output: 1,
         };
-        client.update_routes(0, vec![p.clone()]).await?;
+        client.update_routes(0, vec![p]).await?;
 
         // dummy sees it
         let dr = dummy.get_routes(0).await?;
@@ -524,13 +1840,316 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn locks_roundtrip() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 3, 3).with_output_locks();
+        let fe = VideohubFrontend::new(Arc::new(dummy.clone()), 0);
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        spawn(fe.serve(listener));
+        let client = VideohubRouter::connect(addr).await?;
+        let mut es = client.event_stream().await?;
+
+        let lock = RouterLock {
+            id: 2,
+            locked: true,
+        };
+        client.update_locks(0, vec![lock]).await?;
+        assert!(dummy.get_locks(0).await?.contains(&lock));
+        assert!(client.get_locks(0).await?.contains(&lock));
+        let ev = timeout(Duration::from_secs(1), async {
+            loop {
+                match es.next().await {
+                    Some(RouterEvent::LockUpdate(0, locks)) if locks.contains(&lock) => {
+                        return locks
+                    }
+                    Some(_) => continue,
+                    None => panic!("event stream ended"),
+                }
+            }
+        })
+        .await?;
+        assert_eq!(ev.len(), 3);
+
+        // Out of range locks never reach the peer.
+        let bad = RouterLock { id: 3, ..lock };
+        assert!(client.update_locks(0, vec![bad]).await.is_err());
+        assert!(client.update_locks(1, vec![lock]).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn single_route() -> Result<()> {
+        let (addr, _dummy) = spawn_frontend().await?;
+        let client = VideohubRouter::connect(addr).await?;
+        // The dump may still be underway, so this might have to ask.
+        assert_eq!(
+            client.get_route(0, 2).await?,
+            RouterPatch {
+                from_input: Some(0),
+                to_output: 2
+            }
+        );
+
+        let p = RouterPatch {
+            from_input: Some(2),
+            to_output: 1,
+        };
+        client.update_routes(0, vec![p]).await?;
+        assert_eq!(client.get_route(0, 1).await?, p);
+        assert!(client.get_route(0, 3).await.is_err());
+        assert_eq!(client.get_route_for_output(0, 3).await?, None);
+        assert!(client.get_route(1, 0).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disconnect() -> Result<()> {
+        // A peer never answering, telling when a routing block arrives and when it's left.
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(socket, VideohubCodec::default());
+            framed
+                .send(VideohubMessage::Preamble(videohub::Preamble {
+                    version: "2.7".into(),
+                }))
+                .await
+                .unwrap();
+            framed
+                .send(VideohubMessage::DeviceInfo(videohub::DeviceInfo {
+                    present: Some(videohub::Present::Yes),
+                    video_inputs: Some(3),
+                    video_outputs: Some(3),
+                    ..Default::default()
+                }))
+                .await
+                .unwrap();
+            while let Some(Ok(msg)) = framed.next().await {
+                if matches!(msg, VideohubMessage::VideoOutputRouting(_)) {
+                    tx.send(true).unwrap();
+                }
+            }
+            tx.send(false).unwrap();
+        });
+        let client = connect_lazy(addr).await?;
+        let mut es = client.event_stream().await?;
+        assert_eq!(es.next().await, Some(RouterEvent::Connected));
+
+        let p = RouterPatch {
+            from_input: Some(1),
+            to_output: 0,
+        };
+        let pending = spawn({
+            let client = client.clone();
+            async move { client.update_routes(0, vec![p]).await }
+        });
+        assert_eq!(
+            timeout(Duration::from_secs(1), rx.recv()).await?,
+            Some(true)
+        );
+        timeout(Duration::from_secs(1), client.disconnect()).await??;
+
+        // The waiting request fails right away rather than timing out.
+        assert!(timeout(Duration::from_secs(1), pending).await??.is_err());
+        assert_eq!(
+            timeout(Duration::from_secs(1), rx.recv()).await?,
+            Some(false)
+        );
+        assert_eq!(
+            timeout(Duration::from_secs(1), es.next()).await?,
+            Some(RouterEvent::Disconnected)
+        );
+        assert!(timeout(Duration::from_millis(100), es.next())
+            .await
+            .is_err());
+
+        assert!(client.is_alive().await.is_err());
+        assert!(client.update_routes(0, vec![p]).await.is_err());
+        assert_eq!(client.get_matrix_info(0).await?.input_count, 3);
+        client.disconnect().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn parked_routes_refused() -> Result<()> {
+        let (addr, dummy) = spawn_frontend().await?;
+        let client = VideohubRouter::connect(addr).await?;
+        let p = RouterPatch {
+            from_input: Some(2),
+            to_output: 0,
+        };
+        let before = dummy.get_routes(0).await?;
+        assert!(client
+            .update_routes(0, vec![p, RouterPatch::parked(1)])
+            .await
+            .is_err());
+        assert_eq!(dummy.get_routes(0).await?, before);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prefetch_on_connect() -> Result<()> {
+        let mut mock = MockVideohubServer::start(MockVideohubServer::handshake(2, 2)).await;
+        let connect = spawn(VideohubRouter::connect(mock.addr()));
+        mock.expect_received(VideohubMessage::InputLabels(vec![]))
+            .await;
+        mock.expect_received(VideohubMessage::OutputLabels(vec![]))
+            .await;
+        mock.expect_received(VideohubMessage::VideoOutputRouting(vec![]))
+            .await;
+
+        let label = |id, name: &str| videohub::Label {
+            id,
+            name: name.into(),
+        };
+        let route = |to_output, from_input| videohub::Route {
+            from_input,
+            to_output,
+        };
+        mock.send(VideohubMessage::InputLabels(vec![
+            label(0, "Cam 1"),
+            label(1, "Cam 2"),
+        ]));
+        mock.send(VideohubMessage::OutputLabels(vec![
+            label(0, "PGM"),
+            label(1, "PVW"),
+        ]));
+        mock.send(VideohubMessage::VideoOutputRouting(vec![
+            route(0, 1),
+            route(1, 0),
+        ]));
+        let client = timeout(Duration::from_secs(1), connect).await???;
+
+        // All cache hits, the mock wouldn't answer again.
+        let quick = Duration::from_millis(100);
+        assert_eq!(timeout(quick, client.get_input_labels(0)).await??.len(), 2);
+        assert_eq!(timeout(quick, client.get_output_labels(0)).await??.len(), 2);
+        assert_eq!(
+            timeout(quick, client.get_routes(0)).await??,
+            vec![
+                RouterPatch {
+                    from_input: Some(1),
+                    to_output: 0
+                },
+                RouterPatch {
+                    from_input: Some(0),
+                    to_output: 1
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn more_outputs_than_inputs() -> Result<()> {
+        let mut mock = MockVideohubServer::start(MockVideohubServer::handshake(4, 8)).await;
+        let client = connect_lazy(mock.addr()).await?;
+
+        let routes = client.get_routes(0);
+        let peer = async {
+            mock.expect_received(VideohubMessage::VideoOutputRouting(vec![]))
+                .await;
+            let rs = (0..8).map(|o| (o, o % 4).into()).collect();
+            mock.send(VideohubMessage::VideoOutputRouting(rs));
+        };
+        let (routes, ()) = tokio::join!(routes, peer);
+        let routes = routes?;
+        assert_eq!(routes.len(), 8);
+        assert_eq!(routes[7].from_input, Some(3));
+
+        // Unusable answers fail the request rather than the client.
+        let labels = client.get_output_labels(0);
+        let peer = async {
+            mock.expect_received(VideohubMessage::OutputLabels(vec![]))
+                .await;
+            mock.send(VideohubMessage::OutputLabels(vec![(8, "Nowhere").into()]));
+        };
+        let (labels, ()) = tokio::join!(labels, peer);
+        assert!(labels.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn no_pings_before_2_7() -> Result<()> {
+        let mut script = MockVideohubServer::handshake(2, 2);
+        script[0] = VideohubMessage::Preamble(videohub::Preamble {
+            version: "2.4".into(),
+        });
+        let mut mock = MockVideohubServer::start(script).await;
+        let client = connect_lazy(mock.addr()).await?;
+        assert!(client.is_alive().await?);
+
+        // No ping went out to take the ACK of the update.
+        let patch = RouterPatch {
+            from_input: Some(1),
+            to_output: 0,
+        };
+        let update = client.update_routes(0, vec![patch]);
+        let peer = async {
+            assert_eq!(
+                mock.next_received().await,
+                VideohubMessage::VideoOutputRouting(vec![(0, 1).into()])
+            );
+            mock.send(VideohubMessage::NAK);
+        };
+        let (updated, ()) = tokio::join!(update, peer);
+        assert!(matches!(
+            updated.unwrap_err().downcast_ref(),
+            Some(RouterError::Refused { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn routes_for_input() -> Result<()> {
+        let (addr, dummy) = spawn_frontend().await?;
+        let client = VideohubRouter::connect(addr).await?;
+        // Asks if the dump is still underway.
+        assert_eq!(client.get_route_for_input(0, 0).await?, vec![0, 1, 2]);
+
+        let patch = |from_input, to_output| RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        };
+        client
+            .update_routes(0, vec![patch(1, 2), patch(1, 0)])
+            .await?;
+        assert_eq!(client.get_route_for_input(0, 1).await?, vec![0, 2]);
+        assert!(client.get_route_for_input(0, 2).await?.is_empty());
+        assert_eq!(
+            client.get_route_for_input(0, 1).await?,
+            dummy.get_route_for_input(0, 1).await?
+        );
+        assert!(client.get_route_for_input(1, 0).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn staging_applies_right_away() -> Result<()> {
+        let (addr, dummy) = spawn_frontend().await?;
+        let client = VideohubRouter::connect(addr).await?;
+        let p = RouterPatch {
+            from_input: Some(2),
+            to_output: 0,
+        };
+        let handle = client.stage_routes(0, vec![p]).await?;
+        assert_eq!(dummy.get_route(0, 0).await?, p);
+        assert_eq!(client.get_staged_routes(0).await?, vec![]);
+        client.commit(handle).await?;
+        assert!(client.discard(handle).await.is_err());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn event_stream_routes() -> Result<()> {
         let (addr, dummy) = spawn_frontend().await?;
         let client = VideohubRouter::connect(addr).await?;
         // cause a route change in dummy
         let p = RouterPatch {
-            from_input: 1,
+            from_input: Some(1),
             to_output: 0,
         };
 
@@ -538,9 +2157,10 @@ mod tests {
         let _ = dummy.get_routes(0).await?;
         let mut es = client.event_stream().await?;
 
-        dummy.push_event(RouterEvent::RouteUpdate(0, vec![p.clone()]));
+        dummy.push_event(RouterEvent::RouteUpdate(0, vec![p]));
         let mut found = false;
-        for _ in 0..5 {
+        // Skip whatever is left of the initial dump, locks included.
+        for _ in 0..8 {
             let ev = timeout(Duration::from_secs(1), es.next())
                 .await?
                 .expect("Expecting an event!");
@@ -554,4 +2174,736 @@ mod tests {
         assert!(found);
         Ok(())
     }
+
+    /// Start a fake 3x6 Videohub peer NAK'ing the routing blocks whose
+    /// (zero-based) position is listed in `nak`, returning its address and
+    /// a receiver of all routing blocks it got.
+    async fn spawn_nak_peer(
+        nak: Vec<usize>,
+    ) -> Result<(SocketAddr, mpsc::UnboundedReceiver<Vec<videohub::Route>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(socket, VideohubCodec::default());
+            framed
+                .send(VideohubMessage::Preamble(videohub::Preamble {
+                    version: "2.7".into(),
+                }))
+                .await
+                .unwrap();
+            framed
+                .send(VideohubMessage::DeviceInfo(videohub::DeviceInfo {
+                    present: Some(videohub::Present::Yes),
+                    video_inputs: Some(3),
+                    video_outputs: Some(6),
+                    ..Default::default()
+                }))
+                .await
+                .unwrap();
+            let mut n = 0;
+            while let Some(Ok(msg)) = framed.next().await {
+                if let VideohubMessage::VideoOutputRouting(rs) = msg {
+                    let reply = if nak.contains(&n) {
+                        VideohubMessage::NAK
+                    } else {
+                        VideohubMessage::ACK
+                    };
+                    n += 1;
+                    tx.send(rs).unwrap();
+                    framed.send(reply).await.unwrap();
+                }
+            }
+        });
+        Ok((addr, rx))
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn trace_log() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("trace.jsonl");
+        let mut mock = MockVideohubServer::start(MockVideohubServer::handshake(2, 2)).await;
+        let client = connect_lazy(mock.addr()).await?;
+        let mut es = client.event_stream().await?;
+        client.enable_trace_log(path.clone()).await?;
+
+        let patch = RouterPatch {
+            from_input: Some(1),
+            to_output: 1,
+        };
+        let update = client.update_routes(0, vec![patch]);
+        let peer = async {
+            mock.expect_received(VideohubMessage::VideoOutputRouting(vec![(1, 1).into()]))
+                .await;
+            mock.send(VideohubMessage::ACK);
+        };
+        let (updated, ()) = tokio::join!(update, peer);
+        updated?;
+        // The first labels since connecting make a snapshot, later ones updates.
+        mock.send(VideohubMessage::InputLabels(vec![(0, "Traced").into()]));
+        while !matches!(es.next().await, Some(RouterEvent::InputLabelSnapshot(..))) {}
+        client.disable_trace_log().await;
+        // Not traced anymore.
+        mock.send(VideohubMessage::InputLabels(vec![(0, "Untraced").into()]));
+        while !matches!(es.next().await, Some(RouterEvent::InputLabelUpdate(..))) {}
+
+        let entries: Vec<TraceEntry> = std::fs::read_to_string(&path)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        // The end of the prelude may have arrived after tracing started.
+        let traced: Vec<_> = entries
+            .iter()
+            .filter(|e| e.message != "END PRELUDE:\n\n")
+            .map(|e| (e.direction, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            traced,
+            vec![
+                (TraceDirection::Outbound, "VIDEO OUTPUT ROUTING:\n1 1\n\n"),
+                (TraceDirection::Inbound, "ACK\n\n"),
+                (TraceDirection::Inbound, "INPUT LABELS:\n0 Traced\n\n"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn raw_subscription() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(socket, VideohubCodec::default());
+            framed
+                .send(VideohubMessage::Preamble(videohub::Preamble {
+                    version: "2.7".into(),
+                }))
+                .await
+                .unwrap();
+            framed
+                .send(VideohubMessage::DeviceInfo(videohub::DeviceInfo {
+                    present: Some(videohub::Present::Yes),
+                    video_inputs: Some(2),
+                    video_outputs: Some(2),
+                    ..Default::default()
+                }))
+                .await
+                .unwrap();
+            // Only start talking once the client pinged, so it had time to subscribe.
+            while let Some(Ok(msg)) = framed.next().await {
+                if msg != VideohubMessage::Ping {
+                    continue;
+                }
+                for msg in [
+                    VideohubMessage::ACK,
+                    VideohubMessage::AlarmStatus(vec![videohub::Alarm {
+                        name: "Fan".into(),
+                        status: "Failed".into(),
+                    }]),
+                    VideohubMessage::InputLabels(vec![videohub::Label {
+                        id: 1,
+                        name: "Raw".into(),
+                    }]),
+                    VideohubMessage::Configuration(vec![videohub::Setting {
+                        setting: "Take Mode".into(),
+                        value: "true".into(),
+                    }]),
+                ] {
+                    framed.send(msg).await.unwrap();
+                }
+            }
+        });
+
+        let client = connect_lazy(addr).await?;
+        let raw = client.subscribe_raw(|m| {
+            matches!(
+                m,
+                VideohubMessage::AlarmStatus(_) | VideohubMessage::Configuration(_)
+            )
+        });
+        let everything = client.subscribe_raw(|_| true);
+        futures_util::pin_mut!(raw, everything);
+        assert!(client.is_alive().await?);
+
+        let first = timeout(Duration::from_secs(1), raw.next()).await?;
+        assert!(matches!(first, Some(VideohubMessage::AlarmStatus(a)) if a[0].status == "Failed"));
+        let second = timeout(Duration::from_secs(1), raw.next()).await?;
+        assert!(matches!(second, Some(VideohubMessage::Configuration(_))));
+
+        // ACKs and handled messages show up as well, and still get handled.
+        let first = timeout(Duration::from_secs(1), everything.next()).await?;
+        assert_eq!(first, Some(VideohubMessage::ACK));
+        let labels = client.get_input_labels(0).await?;
+        assert!(labels.contains(&RouterLabel {
+            id: 1,
+            name: "Raw".into(),
+        }));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn hot_swap_updates_matrix_info() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let device = |inputs, outputs| {
+            VideohubMessage::DeviceInfo(videohub::DeviceInfo {
+                present: Some(videohub::Present::Yes),
+                video_inputs: Some(inputs),
+                video_outputs: Some(outputs),
+                ..Default::default()
+            })
+        };
+        let grown = device(4, 6);
+        spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(socket, VideohubCodec::default());
+            let preamble = VideohubMessage::Preamble(videohub::Preamble {
+                version: "2.7".into(),
+            });
+            for msg in [preamble, device(2, 2), VideohubMessage::EndPrelude] {
+                framed.send(msg).await.unwrap();
+            }
+            // Modules get swapped once the client pinged, so it had time to subscribe.
+            while let Some(Ok(msg)) = framed.next().await {
+                match msg {
+                    VideohubMessage::Ping => {
+                        framed.send(VideohubMessage::ACK).await.unwrap();
+                        framed.send(grown.clone()).await.unwrap();
+                    }
+                    // Requests are answered with empty blocks, it knows nothing.
+                    VideohubMessage::InputLabels(ref v) | VideohubMessage::OutputLabels(ref v)
+                        if v.is_empty() =>
+                    {
+                        framed.send(msg).await.unwrap();
+                    }
+                    VideohubMessage::VideoOutputRouting(ref v) if v.is_empty() => {
+                        framed.send(msg).await.unwrap();
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        let client = Arc::new(connect_lazy(addr).await?);
+        let mut es = client.event_stream().await?;
+        assert_eq!(es.next().await, Some(RouterEvent::Connected));
+
+        // A frontend on top tells its clients about the new size.
+        let fe = VideohubFrontend::new(Arc::clone(&client), 0);
+        let fe_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let fe_addr = fe_listener.local_addr()?;
+        spawn(fe.serve(fe_listener));
+        let mut fe_client =
+            Framed::new(TcpStream::connect(fe_addr).await?, VideohubCodec::default());
+        async fn next_device(
+            framed: &mut Framed<TcpStream, VideohubCodec>,
+        ) -> videohub::DeviceInfo {
+            loop {
+                let msg = timeout(Duration::from_secs(1), framed.next())
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap();
+                if let VideohubMessage::DeviceInfo(di) = msg {
+                    return di;
+                }
+            }
+        }
+        assert_eq!(next_device(&mut fe_client).await.video_inputs, Some(2));
+
+        assert!(client.is_alive().await?);
+        let ev = timeout(Duration::from_secs(1), es.next()).await?;
+        assert_eq!(
+            ev,
+            Some(RouterEvent::MatrixInfoUpdate(
+                0,
+                RouterMatrixInfo {
+                    input_count: 4,
+                    output_count: 6,
+                    frame_count: 0,
+                    name: None,
+                }
+            ))
+        );
+        let di = next_device(&mut fe_client).await;
+        assert_eq!((di.video_inputs, di.video_outputs), (Some(4), Some(6)));
+        Ok(())
+    }
+
+    fn patches(pairs: &[(u32, u32)]) -> Vec<RouterPatch> {
+        pairs
+            .iter()
+            .map(|&(to_output, from_input)| RouterPatch {
+                from_input: Some(from_input),
+                to_output,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn chunked_apply_abort_on_nak() -> Result<()> {
+        let (addr, mut blocks) = spawn_nak_peer(vec![1]).await?;
+        let client = connect_lazy(addr).await?;
+        let opts = ApplyOptions {
+            chunk_size: 2,
+            chunk_delay: Duration::from_millis(1),
+            on_failure: ChunkFailurePolicy::Abort,
+        };
+
+        let mut reports = Vec::new();
+        let summary = client
+            .apply_routes_with_progress(
+                patches(&[(0, 1), (1, 1), (2, 1), (3, 1), (4, 1), (5, 1)]),
+                &opts,
+                |p| reports.push(p.clone()),
+            )
+            .await?;
+
+        assert_eq!(reports.len(), 2);
+        assert!(reports[0].acked);
+        assert_eq!(reports[0].outputs, vec![0, 1]);
+        assert!(!reports[1].acked);
+        assert_eq!(reports[1].outputs, vec![2, 3]);
+        assert!(reports.iter().all(|r| r.chunk_count == 3));
+
+        assert_eq!(summary.applied, vec![0, 1]);
+        assert_eq!(summary.failed, vec![2, 3]);
+        assert_eq!(summary.skipped, vec![4, 5]);
+        assert!(!summary.is_complete());
+
+        // Only two blocks made it to the peer.
+        assert_eq!(blocks.recv().await.unwrap().len(), 2);
+        assert_eq!(blocks.recv().await.unwrap().len(), 2);
+        assert!(blocks.try_recv().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chunked_apply_continue_on_nak() -> Result<()> {
+        let (addr, _blocks) = spawn_nak_peer(vec![1]).await?;
+        let client = connect_lazy(addr).await?;
+        let opts = ApplyOptions {
+            chunk_size: 2,
+            chunk_delay: Duration::ZERO,
+            on_failure: ChunkFailurePolicy::Continue,
+        };
+
+        let mut reports = Vec::new();
+        let summary = client
+            .apply_routes_with_progress(
+                patches(&[(0, 1), (1, 1), (2, 1), (3, 1), (4, 1), (5, 1)]),
+                &opts,
+                |p| reports.push(p.clone()),
+            )
+            .await?;
+
+        let acked: Vec<bool> = reports.iter().map(|r| r.acked).collect();
+        assert_eq!(acked, vec![true, false, true]);
+        assert_eq!(summary.applied, vec![0, 1, 4, 5]);
+        assert_eq!(summary.failed, vec![2, 3]);
+        assert!(summary.skipped.is_empty());
+
+        // Only ACK'd chunks end up in the cache.
+        let routes = client.get_routes(0).await?;
+        assert!(routes.contains(&patches(&[(4, 1)])[0]));
+        assert!(!routes.iter().any(|p| p.to_output == 2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_routes_auto_chunks_with_dedup() -> Result<()> {
+        let (addr, mut blocks) = spawn_nak_peer(vec![]).await?;
+        let client = connect_lazy(addr).await?.with_max_block_entries(2);
+
+        // Output 0 is set twice, the last one wins and only counts once.
+        client
+            .update_routes(0, patches(&[(0, 1), (1, 1), (2, 1), (0, 2)]))
+            .await?;
+
+        let first = blocks.recv().await.unwrap();
+        let second = blocks.recv().await.unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(second.len(), 1);
+        assert_eq!(first[0].to_output, 0);
+        assert_eq!(first[0].from_input, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_routes_fails_on_nak() -> Result<()> {
+        let (addr, mut blocks) = spawn_nak_peer(vec![0]).await?;
+        let client = connect_lazy(addr).await?;
+
+        // All patches go out as one block, refused as a whole.
+        let err = client
+            .update_routes(0, patches(&[(0, 1), (1, 1), (2, 1)]))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::Refused { index: 0 })
+        );
+        assert_eq!(blocks.recv().await.unwrap().len(), 3);
+        assert!(blocks.try_recv().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chunked_update_reports_partial_apply() -> Result<()> {
+        let (addr, _blocks) = spawn_nak_peer(vec![1]).await?;
+        let client = connect_lazy(addr).await?.with_max_block_entries(1);
+        let err = client
+            .update_routes(0, patches(&[(0, 1), (1, 1), (2, 1)]))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::PartiallyApplied {
+                index: 0,
+                applied: vec![0],
+                failed: vec![1, 2],
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn malformed_block_disconnects() -> Result<()> {
+        let mock = MockVideohubServer::start(MockVideohubServer::handshake(2, 2)).await;
+        let client = connect_lazy(mock.addr()).await?;
+        let mut es = client.event_stream().await?;
+        assert_eq!(es.next().await, Some(RouterEvent::Connected));
+
+        mock.send_raw(b"VIDEO OUTPUT LOCKS:\n0 Owned\n\n");
+        let ev = timeout(Duration::from_secs(1), es.next()).await?;
+        assert_eq!(ev, Some(RouterEvent::Disconnected));
+        assert!(!matches!(client.is_alive().await, Ok(true)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn port_status() -> Result<()> {
+        let port = |id, port_type| videohub::HardwarePort { id, port_type };
+        let mut script = MockVideohubServer::handshake(2, 2);
+        script.push(VideohubMessage::VideoInputStatus(vec![
+            port(0, videohub::HardwarePortType::BNC),
+            port(1, videohub::HardwarePortType::None),
+        ]));
+        let mock = MockVideohubServer::start(script).await;
+        let client = connect_lazy(mock.addr()).await?;
+        timeout(Duration::from_secs(1), async {
+            while client.get_input_status(0).await.unwrap()[0]
+                .present
+                .is_none()
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+        let inputs = client.get_input_status(0).await?;
+        assert_eq!(inputs[0].port_type.as_deref(), Some("BNC"));
+        assert_eq!(inputs[1].present, Some(false));
+        assert_eq!(inputs[1].port_type, None);
+        // Outputs weren't reported at all.
+        assert_eq!(
+            client.get_output_status(0).await?,
+            vec![
+                RouterPortStatus::unknown(PortKind::Output, 0),
+                RouterPortStatus::unknown(PortKind::Output, 1)
+            ]
+        );
+        assert!(client.get_input_status(1).await.is_err());
+
+        // Only changes are announced.
+        let mut es = client.event_stream().await?;
+        assert_eq!(es.next().await, Some(RouterEvent::Connected));
+        mock.send(VideohubMessage::VideoInputStatus(vec![
+            port(0, videohub::HardwarePortType::BNC),
+            port(1, videohub::HardwarePortType::Optical),
+        ]));
+        let optical = RouterPortStatus {
+            id: 1,
+            kind: PortKind::Input,
+            present: Some(true),
+            port_type: Some("Optical".into()),
+        };
+        assert_eq!(
+            timeout(Duration::from_secs(1), es.next()).await?,
+            Some(RouterEvent::PortStatusUpdate(0, vec![optical.clone()]))
+        );
+        assert_eq!(client.get_input_status(0).await?[1], optical);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn alarms_reach_frontend_clients() -> Result<()> {
+        // A full dump, so the frontend can greet its client from the cache.
+        let mut script = MockVideohubServer::handshake(2, 2);
+        let dump = [
+            VideohubMessage::InputLabels(vec![(0, "In 1").into(), (1, "In 2").into()]),
+            VideohubMessage::OutputLabels(vec![(0, "Out 1").into(), (1, "Out 2").into()]),
+            VideohubMessage::VideoOutputLocks(vec![]),
+            VideohubMessage::VideoOutputRouting(vec![(0, 0).into(), (1, 0).into()]),
+        ];
+        script.splice(2..2, dump);
+        let mut mock = MockVideohubServer::start(script).await;
+        let router = Arc::new(connect_lazy(mock.addr()).await?);
+        sync(&router, &mut mock).await;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        spawn(VideohubFrontend::new(router.clone(), 0).serve(listener));
+        let (_client, mut events) = videohub::VideohubClient::connect(addr).await?;
+        // The frontend checks on the hub before greeting its client.
+        mock.expect_received(VideohubMessage::Ping).await;
+        mock.send(VideohubMessage::ACK);
+        timeout(Duration::from_secs(1), async {
+            while events.recv().await != Some(VideohubMessage::EndPrelude) {}
+        })
+        .await?;
+
+        let fan = |status: &str| videohub::Alarm {
+            name: "Fan".into(),
+            status: status.into(),
+        };
+        mock.send(VideohubMessage::AlarmStatus(vec![
+            fan("ok"),
+            videohub::Alarm {
+                name: "PSU".into(),
+                status: "ok".into(),
+            },
+        ]));
+        mock.send(VideohubMessage::AlarmStatus(vec![fan("Failed")]));
+        let received = timeout(Duration::from_secs(1), async {
+            loop {
+                match events.recv().await {
+                    Some(VideohubMessage::AlarmStatus(a)) if a.contains(&fan("Failed")) => {
+                        return a
+                    }
+                    Some(_) => {}
+                    None => panic!("connection closed"),
+                }
+            }
+        })
+        .await?;
+        // Changes are merged into what was known before.
+        assert_eq!(received.len(), 2);
+        let alarms = router.get_alarms().await?;
+        assert_eq!(alarms[0].status, "Failed");
+        assert_eq!(alarms[1].name, "PSU");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unexpected_nak() -> Result<()> {
+        let mut mock = MockVideohubServer::start(MockVideohubServer::handshake(2, 2)).await;
+        let client = Arc::new(connect_lazy(mock.addr()).await?);
+        let mut es = client.event_stream().await?;
+        assert_eq!(es.next().await, Some(RouterEvent::Connected));
+
+        // A NAK nobody waits for gets dropped, not held against the next request.
+        mock.send(VideohubMessage::NAK);
+        mock.send(VideohubMessage::InputLabels(vec![videohub::Label {
+            id: 0,
+            name: "After NAK".into(),
+        }]));
+        let ev = timeout(Duration::from_secs(1), es.next()).await?;
+        assert!(matches!(ev, Some(RouterEvent::InputLabelSnapshot(0, _))));
+        let alive = spawn({
+            let client = Arc::clone(&client);
+            async move { client.is_alive().await }
+        });
+        mock.expect_received(VideohubMessage::Ping).await;
+        mock.send(VideohubMessage::ACK);
+        assert!(alive.await??);
+
+        // A refused change fails.
+        let update = spawn({
+            let client = Arc::clone(&client);
+            async move { client.update_routes(0, patches(&[(1, 1)])).await }
+        });
+        mock.expect_received(VideohubMessage::VideoOutputRouting(vec![videohub::Route {
+            from_input: 1,
+            to_output: 1,
+        }]))
+        .await;
+        mock.send(VideohubMessage::NAK);
+        assert!(update.await?.is_err());
+        Ok(())
+    }
+
+    /// Script of a hub reporting `take_mode` in its configuration.
+    fn take_mode_script(take_mode: bool) -> Vec<VideohubMessage> {
+        let mut script = MockVideohubServer::handshake(2, 2);
+        script.insert(
+            2,
+            VideohubMessage::Configuration(vec![videohub::Setting {
+                setting: "Take Mode".into(),
+                value: take_mode.to_string(),
+            }]),
+        );
+        script
+    }
+
+    /// Ping through `mock`, so everything it sent before has been handled.
+    /// The ping has to be the next thing the client sends.
+    async fn sync(client: &Arc<VideohubRouter>, mock: &mut MockVideohubServer) {
+        let alive = spawn({
+            let client = Arc::clone(client);
+            async move { client.is_alive().await }
+        });
+        assert_eq!(mock.next_received().await, VideohubMessage::Ping);
+        mock.send(VideohubMessage::ACK);
+        assert!(alive.await.unwrap().unwrap());
+    }
+
+    /// Route input 1 to output 0, answering everything with ACK.
+    /// Returns what the client sent.
+    async fn route_acked(
+        client: &Arc<VideohubRouter>,
+        mock: &mut MockVideohubServer,
+        blocks: usize,
+    ) -> Vec<VideohubMessage> {
+        let update = spawn({
+            let client = Arc::clone(client);
+            async move { client.update_routes(0, patches(&[(0, 1)])).await }
+        });
+        let mut sent = Vec::new();
+        for _ in 0..blocks {
+            sent.push(mock.next_received().await);
+            mock.send(VideohubMessage::ACK);
+        }
+        update.await.unwrap().unwrap();
+        sent
+    }
+
+    #[tokio::test]
+    async fn take_mode_detected() -> Result<()> {
+        let mut mock = MockVideohubServer::start(take_mode_script(true)).await;
+        let client = Arc::new(connect_lazy(mock.addr()).await?);
+        sync(&client, &mut mock).await;
+
+        let sent = route_acked(&client, &mut mock, 2).await;
+        assert_eq!(
+            sent,
+            [
+                VideohubMessage::VideoOutputRouting(vec![videohub::Route {
+                    from_input: 1,
+                    to_output: 0
+                }]),
+                take_message(),
+            ]
+        );
+        assert_eq!(client.get_routes(0).await?[0], patches(&[(0, 1)])[0]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn take_mode_overridden() -> Result<()> {
+        // Never taking, even though the device wants it.
+        let mut mock = MockVideohubServer::start(take_mode_script(true)).await;
+        let client = Arc::new(connect_lazy(mock.addr()).await?.with_take_mode(false));
+        sync(&client, &mut mock).await;
+        let sent = route_acked(&client, &mut mock, 1).await;
+        assert!(matches!(sent[0], VideohubMessage::VideoOutputRouting(_)));
+        sync(&client, &mut mock).await;
+
+        // Always taking, even though the device doesn't.
+        let mut mock = MockVideohubServer::start(take_mode_script(false)).await;
+        let client = Arc::new(connect_lazy(mock.addr()).await?.with_take_mode(true));
+        let sent = route_acked(&client, &mut mock, 2).await;
+        assert_eq!(sent[1], take_message());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn take_refused() -> Result<()> {
+        let mut mock = MockVideohubServer::start(take_mode_script(true)).await;
+        let client = Arc::new(connect_lazy(mock.addr()).await?);
+        sync(&client, &mut mock).await;
+        let update = spawn({
+            let client = Arc::clone(&client);
+            async move { client.update_routes(0, patches(&[(0, 1)])).await }
+        });
+        assert!(matches!(
+            mock.next_received().await,
+            VideohubMessage::VideoOutputRouting(_)
+        ));
+        mock.send(VideohubMessage::ACK);
+        assert_eq!(mock.next_received().await, take_message());
+        mock.send(VideohubMessage::NAK);
+        assert!(update.await?.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn eof_disconnects() -> Result<()> {
+        let mock = MockVideohubServer::start(MockVideohubServer::handshake(2, 2)).await;
+        let client = connect_lazy(mock.addr()).await?;
+        let mut es = client.event_stream().await?;
+        assert_eq!(es.next().await, Some(RouterEvent::Connected));
+
+        mock.close();
+        let ev = timeout(Duration::from_secs(1), es.next()).await?;
+        assert_eq!(ev, Some(RouterEvent::Disconnected));
+        Ok(())
+    }
+
+    /// How long failover may take in tests.
+    const FAILOVER_WINDOW: Duration = Duration::from_secs(5);
+
+    /// Start a frontend on its own runtime, so shutting that runtime down
+    /// kills the listener and all of its connections.
+    fn spawn_killable_frontend() -> Result<(SocketAddr, DummyRouter, tokio::runtime::Runtime)> {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let dummy = DummyRouter::with_config(1, 3, 3);
+        let fe = VideohubFrontend::new(Arc::new(dummy.clone()), 0);
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        rt.spawn(async move {
+            let listener = TcpListener::from_std(listener).unwrap();
+            fe.serve(listener).await.unwrap();
+        });
+        Ok((addr, dummy, rt))
+    }
+
+    #[tokio::test]
+    async fn failover_to_second_peer() -> Result<()> {
+        for policy in [FailoverPolicy::Active, FailoverPolicy::RoundRobin] {
+            let (addr1, _dummy1, rt1) = spawn_killable_frontend()?;
+            let (addr2, dummy2, rt2) = spawn_killable_frontend()?;
+            let client = VideohubRouter::connect_with_failover(vec![addr1, addr2], policy).await?;
+            let mut es = client.event_stream().await?;
+            assert_eq!(es.next().await, Some(RouterEvent::Connected));
+            assert!(client.is_alive().await?);
+
+            // Kill the primary.
+            rt1.shutdown_background();
+            let mut events = Vec::new();
+            timeout(FAILOVER_WINDOW, async {
+                while !events.contains(&RouterEvent::Connected) {
+                    events.push(es.next().await.expect("Expecting an event!"));
+                }
+            })
+            .await?;
+            assert!(events.contains(&RouterEvent::Disconnected));
+
+            // Commands now end up at the second peer.
+            let p = RouterPatch {
+                from_input: Some(2),
+                to_output: 1,
+            };
+            timeout(FAILOVER_WINDOW, client.update_routes(0, vec![p])).await??;
+            assert!(dummy2.get_routes(0).await?.contains(&p));
+            rt2.shutdown_background();
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,140 @@
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use futures_util::StreamExt;
+use omnimatrix::{
+    backend::VideohubRouter,
+    matrix::{MatrixRouter, RouterEvent, RouterLabel, RouterPatch},
+};
+use std::{io::Write, net::SocketAddr};
+use tracing_subscriber::{
+    filter::{EnvFilter, LevelFilter},
+    fmt,
+    prelude::*,
+};
+
+/// Query and control routers from the command line.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Connect to a Videohub router, showing its info without further command.
+    Connect {
+        addr: SocketAddr,
+        /// Matrix to operate on.
+        #[arg(short, long, default_value_t = 0)]
+        matrix: u32,
+        #[command(subcommand)]
+        action: Option<Action>,
+    },
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Print input labels, one `id<TAB>name` per line.
+    ListInputs,
+    /// Print output labels, one `id<TAB>name` per line.
+    ListOutputs,
+    /// Print routes, one `output<TAB>input` per line.
+    ListRoutes,
+    /// Route an input to an output.
+    Patch { output: u32, input: u32 },
+    /// Change the label of an input.
+    RenameInput { id: u32, name: String },
+    /// Change the label of an output.
+    RenameOutput { id: u32, name: String },
+    /// Print every router event as a line of JSON until the router disconnects.
+    Watch,
+}
+
+fn print_labels(labels: &[RouterLabel]) {
+    for l in labels {
+        println!("{}\t{}", l.id, l.name);
+    }
+}
+
+async fn run(router: &VideohubRouter, idx: u32, action: Option<Action>) -> Result<()> {
+    match action {
+        None => {
+            let info = router.get_router_info().await?;
+            let matrix = router.get_matrix_info(idx).await?;
+            println!("model\t{}", info.model.unwrap_or_default());
+            println!("name\t{}", info.name.unwrap_or_default());
+            println!("inputs\t{}", matrix.input_count);
+            println!("outputs\t{}", matrix.output_count);
+        }
+        Some(Action::ListInputs) => print_labels(&router.get_input_labels(idx).await?),
+        Some(Action::ListOutputs) => print_labels(&router.get_output_labels(idx).await?),
+        Some(Action::ListRoutes) => {
+            // Videohubs have no parked outputs.
+            for r in router.get_routes(idx).await? {
+                if let Some(input) = r.from_input {
+                    println!("{}\t{}", r.to_output, input);
+                }
+            }
+        }
+        Some(Action::Patch { output, input }) => {
+            let patch = RouterPatch {
+                from_input: Some(input),
+                to_output: output,
+            };
+            router
+                .update_routes(idx, vec![patch])
+                .await
+                .with_context(|| format!("Patching input {} to output {}", input, output))?;
+        }
+        Some(Action::RenameInput { id, name }) => {
+            router
+                .update_input_labels(idx, vec![RouterLabel { id, name }])
+                .await
+                .with_context(|| format!("Renaming input {}", id))?;
+        }
+        Some(Action::RenameOutput { id, name }) => {
+            router
+                .update_output_labels(idx, vec![RouterLabel { id, name }])
+                .await
+                .with_context(|| format!("Renaming output {}", id))?;
+        }
+        Some(Action::Watch) => {
+            let mut events = router.event_stream().await?;
+            let mut stdout = std::io::stdout().lock();
+            while let Some(ev) = events.next().await {
+                serde_json::to_writer(&mut stdout, &ev)?;
+                writeln!(stdout)?;
+                stdout.flush()?;
+                // Plain connections don't come back.
+                if ev == RouterEvent::Disconnected {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Logs go to stderr, stdout is for output only.
+    tracing_subscriber::registry()
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::WARN.into())
+                .from_env_lossy(),
+        )
+        .init();
+
+    let Command::Connect {
+        addr,
+        matrix,
+        action,
+    } = Cli::parse().command;
+    let router = VideohubRouter::connect(addr)
+        .await
+        .with_context(|| format!("Connecting to {}", addr))?;
+    run(&router, matrix, action).await
+}
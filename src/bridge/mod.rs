@@ -0,0 +1,3 @@
+mod videohub;
+
+pub(crate) use videohub::{canonical_labels, canonical_routes};
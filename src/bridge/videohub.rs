@@ -0,0 +1,308 @@
+//! Conversions between Videohub protocol blocks and [RouterEvent]s
+//!
+//! A Videohub has a single matrix, so events converted from messages are for matrix 0 and
+//! the matrix index of converted events is dropped. Picking the right matrix is up to the
+//! caller. Empty blocks are requests rather than changes, neither side converts them.
+//! Videohub routes always have an input, so parked outputs are left out of routing blocks.
+
+use crate::matrix::{RouterEvent, RouterLabel, RouterLock, RouterPatch};
+use anyhow::{anyhow, Error};
+use videohub::{Label, Lock, Route, SortCanonical, VideohubMessage};
+
+/// Labels as sent to clients, ascending by id.
+pub(crate) fn canonical_labels(labels: Vec<RouterLabel>) -> Vec<Label> {
+    let mut labels: Vec<Label> = labels.into_iter().map(Label::from).collect();
+    labels.sort_canonical();
+    labels
+}
+
+/// Routes as sent to clients, ascending by output. Parked outputs have no route.
+pub(crate) fn canonical_routes(routes: Vec<RouterPatch>) -> Vec<Route> {
+    let mut routes: Vec<Route> = routes
+        .into_iter()
+        .filter_map(|p| Route::try_from(p).ok())
+        .collect();
+    routes.sort_canonical();
+    routes
+}
+
+/// Locks as sent to clients, ascending by id.
+fn canonical_locks(locks: Vec<RouterLock>) -> Vec<Lock> {
+    let mut locks: Vec<Lock> = locks.into_iter().map(Lock::from).collect();
+    locks.sort_canonical();
+    locks
+}
+
+fn convert<T, U: From<T>>(items: Vec<T>) -> Vec<U> {
+    items.into_iter().map(U::from).collect()
+}
+
+impl TryFrom<VideohubMessage> for RouterEvent {
+    type Error = Error;
+
+    /// The change a label, routing, lock or alarm block announces, as an event of matrix 0.
+    fn try_from(msg: VideohubMessage) -> Result<Self, Self::Error> {
+        // The only status that is a change of its own.
+        if let VideohubMessage::AlarmStatus(v) = msg {
+            return Ok(RouterEvent::AlarmUpdate(convert(v)));
+        }
+        if !msg.is_control_message() {
+            return Err(anyhow!("{} announces no change", msg.summary()));
+        }
+        Ok(match msg {
+            VideohubMessage::InputLabels(v) => RouterEvent::InputLabelUpdate(0, convert(v)),
+            VideohubMessage::OutputLabels(v) => RouterEvent::OutputLabelUpdate(0, convert(v)),
+            VideohubMessage::FrameLabels(v) => RouterEvent::FrameLabelUpdate(0, convert(v)),
+            VideohubMessage::VideoOutputRouting(v) => RouterEvent::RouteUpdate(0, convert(v)),
+            VideohubMessage::FrameBufferRouting(v) => RouterEvent::FrameRouteUpdate(0, convert(v)),
+            VideohubMessage::VideoOutputLocks(v) => RouterEvent::LockUpdate(0, convert(v)),
+            VideohubMessage::FrameBufferLocks(v) => RouterEvent::FrameLockUpdate(0, convert(v)),
+            VideohubMessage::ProcessingUnitLocks(v) => {
+                RouterEvent::ProcessingUnitLockUpdate(0, convert(v))
+            }
+            msg => return Err(anyhow!("No event for {}", msg.summary())),
+        })
+    }
+}
+
+impl TryFrom<RouterEvent> for VideohubMessage {
+    type Error = Error;
+
+    /// The block announcing a label, route, lock or alarm change, entries sorted by id.
+    /// Snapshots make the same block as updates, carrying all entries.
+    ///
+    /// Locked ports come out as [videohub::LockState::Owned], telling owners apart is up to
+    /// whoever knows them.
+    fn try_from(ev: RouterEvent) -> Result<Self, Self::Error> {
+        let msg = match ev.into_update() {
+            RouterEvent::InputLabelUpdate(_, v) => {
+                VideohubMessage::InputLabels(canonical_labels(v))
+            }
+            RouterEvent::OutputLabelUpdate(_, v) => {
+                VideohubMessage::OutputLabels(canonical_labels(v))
+            }
+            RouterEvent::FrameLabelUpdate(_, v) => {
+                VideohubMessage::FrameLabels(canonical_labels(v))
+            }
+            RouterEvent::RouteUpdate(_, v) => {
+                VideohubMessage::VideoOutputRouting(canonical_routes(v))
+            }
+            RouterEvent::FrameRouteUpdate(_, v) => {
+                VideohubMessage::FrameBufferRouting(canonical_routes(v))
+            }
+            RouterEvent::LockUpdate(_, v) => VideohubMessage::VideoOutputLocks(canonical_locks(v)),
+            RouterEvent::FrameLockUpdate(_, v) => {
+                VideohubMessage::FrameBufferLocks(canonical_locks(v))
+            }
+            RouterEvent::ProcessingUnitLockUpdate(_, v) => {
+                VideohubMessage::ProcessingUnitLocks(canonical_locks(v))
+            }
+            RouterEvent::AlarmUpdate(v) => return Ok(VideohubMessage::AlarmStatus(convert(v))),
+            ev => return Err(anyhow!("No Videohub message for {:?}", ev)),
+        };
+        // Empty blocks are all that isn't a change here.
+        if !msg.is_control_message() {
+            return Err(anyhow!("Empty {} would be a request", msg.summary()));
+        }
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::RouterAlarm;
+    use videohub::{Alarm, LockState};
+
+    fn alarm(name: &str, status: &str) -> RouterAlarm {
+        RouterAlarm {
+            name: name.into(),
+            status: status.into(),
+        }
+    }
+
+    fn label(id: u32, name: &str) -> RouterLabel {
+        RouterLabel {
+            id,
+            name: name.into(),
+        }
+    }
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    #[test]
+    fn message_to_event() -> anyhow::Result<()> {
+        let cases = [
+            (
+                VideohubMessage::InputLabels(vec![Label::from((1, "Cam 2"))]),
+                RouterEvent::InputLabelUpdate(0, vec![label(1, "Cam 2")]),
+            ),
+            (
+                VideohubMessage::OutputLabels(vec![Label::from((0, "PGM"))]),
+                RouterEvent::OutputLabelUpdate(0, vec![label(0, "PGM")]),
+            ),
+            (
+                VideohubMessage::FrameLabels(vec![Label::from((2, "Still"))]),
+                RouterEvent::FrameLabelUpdate(0, vec![label(2, "Still")]),
+            ),
+            (
+                VideohubMessage::VideoOutputRouting(vec![Route::from((3, 1))]),
+                RouterEvent::RouteUpdate(0, vec![patch(1, 3)]),
+            ),
+            (
+                VideohubMessage::FrameBufferRouting(vec![Route::from((0, 4))]),
+                RouterEvent::FrameRouteUpdate(0, vec![patch(4, 0)]),
+            ),
+        ];
+        for (msg, ev) in cases {
+            assert_eq!(RouterEvent::try_from(msg)?, ev);
+        }
+
+        let locks = vec![
+            Lock {
+                id: 0,
+                state: LockState::Owned,
+            },
+            Lock {
+                id: 1,
+                state: LockState::Locked,
+            },
+            Lock {
+                id: 2,
+                state: LockState::Unlocked,
+            },
+        ];
+        let router_locks = vec![
+            RouterLock {
+                id: 0,
+                locked: true,
+            },
+            RouterLock {
+                id: 1,
+                locked: true,
+            },
+            RouterLock {
+                id: 2,
+                locked: false,
+            },
+        ];
+        assert_eq!(
+            RouterEvent::try_from(VideohubMessage::VideoOutputLocks(locks.clone()))?,
+            RouterEvent::LockUpdate(0, router_locks.clone())
+        );
+        assert_eq!(
+            RouterEvent::try_from(VideohubMessage::FrameBufferLocks(locks.clone()))?,
+            RouterEvent::FrameLockUpdate(0, router_locks.clone())
+        );
+        assert_eq!(
+            RouterEvent::try_from(VideohubMessage::ProcessingUnitLocks(locks))?,
+            RouterEvent::ProcessingUnitLockUpdate(0, router_locks)
+        );
+
+        assert_eq!(
+            RouterEvent::try_from(VideohubMessage::AlarmStatus(vec![Alarm {
+                name: "Fan".into(),
+                status: "Failed".into(),
+            }]))?,
+            RouterEvent::AlarmUpdate(vec![alarm("Fan", "Failed")])
+        );
+
+        // Requests and everything else have no event.
+        for msg in [
+            VideohubMessage::InputLabels(vec![]),
+            VideohubMessage::VideoOutputRouting(vec![]),
+            VideohubMessage::Ping,
+            VideohubMessage::ACK,
+            VideohubMessage::EndPrelude,
+        ] {
+            assert!(RouterEvent::try_from(msg.clone()).is_err(), "{:?}", msg);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn event_to_message() -> anyhow::Result<()> {
+        assert_eq!(
+            VideohubMessage::try_from(RouterEvent::InputLabelUpdate(
+                3,
+                vec![label(2, "Cam 3"), label(0, "Cam 1")]
+            ))?,
+            VideohubMessage::InputLabels(vec![
+                Label::from((0, "Cam 1")),
+                Label::from((2, "Cam 3"))
+            ])
+        );
+        assert_eq!(
+            VideohubMessage::try_from(RouterEvent::OutputLabelUpdate(0, vec![label(0, "PGM")]))?,
+            VideohubMessage::OutputLabels(vec![Label::from((0, "PGM"))])
+        );
+        assert_eq!(
+            VideohubMessage::try_from(RouterEvent::FrameLabelUpdate(0, vec![label(1, "Still")]))?,
+            VideohubMessage::FrameLabels(vec![Label::from((1, "Still"))])
+        );
+        assert_eq!(
+            VideohubMessage::try_from(RouterEvent::RouteUpdate(1, vec![patch(0, 2), patch(3, 1)]))?,
+            VideohubMessage::VideoOutputRouting(vec![Route::from((1, 3)), Route::from((2, 0))])
+        );
+        assert_eq!(
+            VideohubMessage::try_from(RouterEvent::FrameRouteUpdate(0, vec![patch(1, 0)]))?,
+            VideohubMessage::FrameBufferRouting(vec![Route::from((0, 1))])
+        );
+
+        let router_locks = vec![
+            RouterLock {
+                id: 1,
+                locked: false,
+            },
+            RouterLock {
+                id: 0,
+                locked: true,
+            },
+        ];
+        let locks = vec![
+            Lock {
+                id: 0,
+                state: LockState::Owned,
+            },
+            Lock {
+                id: 1,
+                state: LockState::Unlocked,
+            },
+        ];
+        assert_eq!(
+            VideohubMessage::try_from(RouterEvent::LockUpdate(0, router_locks.clone()))?,
+            VideohubMessage::VideoOutputLocks(locks.clone())
+        );
+        assert_eq!(
+            VideohubMessage::try_from(RouterEvent::FrameLockUpdate(0, router_locks.clone()))?,
+            VideohubMessage::FrameBufferLocks(locks.clone())
+        );
+        assert_eq!(
+            VideohubMessage::try_from(RouterEvent::ProcessingUnitLockUpdate(0, router_locks))?,
+            VideohubMessage::ProcessingUnitLocks(locks)
+        );
+
+        assert_eq!(
+            VideohubMessage::try_from(RouterEvent::AlarmUpdate(vec![alarm("PSU 2", "ok")]))?,
+            VideohubMessage::AlarmStatus(vec![Alarm {
+                name: "PSU 2".into(),
+                status: "ok".into(),
+            }])
+        );
+
+        // An empty block would be a request.
+        for ev in [
+            RouterEvent::RouteUpdate(0, vec![]),
+            RouterEvent::Connected,
+            RouterEvent::SalvoRecalled(0, "Show".into()),
+        ] {
+            assert!(VideohubMessage::try_from(ev.clone()).is_err(), "{:?}", ev);
+        }
+        Ok(())
+    }
+}
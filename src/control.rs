@@ -0,0 +1,943 @@
+//! Local Control Socket
+//!
+//! Runtime administration over a Unix domain socket, speaking newline-delimited JSON.
+//! Every line is a request like `{"cmd": "route", "params": {"output": 1, "input": 2}}`,
+//! answered by a single line `{"ok": true, "result": ...}` or `{"ok": false, "error": "..."}`.
+//!
+//! Commands live in a [ControlRegistry], `help` lists them along with a JSON schema of their
+//! parameters. Access is restricted by the socket file permissions and an optional token.
+//!
+//! Destructive commands can require a second step, see [ControlRegistry::with_confirmation].
+//! Their first request only returns a summary of what would change along with a `confirm`
+//! token, repeating the request with that token added to its parameters carries it out.
+
+use crate::matrix::{describe_router, label_csv, MatrixRouter, RouterIntrospect, RouterPatch};
+use anyhow::{anyhow, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{net::UnixStream, time::Instant};
+use tokio_util::codec::{Framed, LinesCodec};
+use tracing::{debug, error, info};
+
+/// Longest request or response line, in bytes. Anything longer closes the connection.
+pub const MAX_LINE_LENGTH: usize = 4 << 20;
+
+/// A single control request.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ControlRequest {
+    pub cmd: String,
+    #[serde(default)]
+    pub params: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// The answer to a [ControlRequest].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ControlResponse {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ControlResponse {
+    fn from_result(res: Result<Value>) -> Self {
+        match res {
+            Ok(v) => Self {
+                ok: true,
+                result: Some(v),
+                error: None,
+            },
+            Err(e) => Self {
+                ok: false,
+                result: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Future returned by command handlers.
+pub type CommandFuture = Pin<Box<dyn Future<Output = Result<Value>> + Send>>;
+
+type Handler<S> = Arc<dyn Fn(Arc<S>, Value) -> CommandFuture + Send + Sync>;
+
+struct Command<S> {
+    description: String,
+    params: Value,
+    handler: Handler<S>,
+    confirm: Option<Confirm<S>>,
+}
+
+/// How a destructive command gets confirmed.
+struct Confirm<S> {
+    /// Whether a request changes anything, and thus needs confirming.
+    needed: Arc<dyn Fn(&Value) -> bool + Send + Sync>,
+    /// Summary of what a request would change, without changing it.
+    prepare: Handler<S>,
+}
+
+/// A prepared change, waiting for its token to come back.
+struct Prepared {
+    cmd: String,
+    digest: u64,
+    expires: Instant,
+}
+
+/// Outstanding confirmation tokens.
+struct Confirmations {
+    ttl: Duration,
+    prepared: Mutex<HashMap<String, Prepared>>,
+}
+
+impl Confirmations {
+    /// Hand out a token for `digest`, dropping expired ones while at it.
+    fn issue(&self, cmd: &str, digest: u64) -> Result<String> {
+        let mut raw = [0u8; 16];
+        getrandom::fill(&mut raw).map_err(|e| anyhow!("Failed to create token: {}", e))?;
+        let token: String = raw.iter().map(|b| format!("{:02x}", b)).collect();
+        let now = Instant::now();
+        let mut prepared = self.prepared.lock().unwrap();
+        prepared.retain(|_, p| p.expires > now);
+        prepared.insert(
+            token.clone(),
+            Prepared {
+                cmd: cmd.to_string(),
+                digest,
+                expires: now + self.ttl,
+            },
+        );
+        Ok(token)
+    }
+
+    /// Use up `token`, which has to be for the same change.
+    fn redeem(&self, token: &str, cmd: &str, digest: u64) -> Result<()> {
+        let p = self
+            .prepared
+            .lock()
+            .unwrap()
+            .remove(token)
+            .ok_or_else(|| anyhow!("Unknown or already used confirmation token"))?;
+        if p.expires <= Instant::now() {
+            return Err(anyhow!("Confirmation token expired, prepare again"));
+        }
+        if p.cmd != cmd {
+            return Err(anyhow!("Confirmation token is for '{}'", p.cmd));
+        }
+        if p.digest != digest {
+            return Err(anyhow!("State changed since preparing, prepare again"));
+        }
+        Ok(())
+    }
+}
+
+/// Identifies a prepared change: the request along with what it would do.
+fn change_digest(cmd: &str, params: &Value, summary: &Value) -> u64 {
+    let mut h = DefaultHasher::new();
+    cmd.hash(&mut h);
+    params.to_string().hash(&mut h);
+    summary.to_string().hash(&mut h);
+    h.finish()
+}
+
+/// Named commands operating on a router.
+pub struct ControlRegistry<S> {
+    commands: BTreeMap<String, Command<S>>,
+    confirmations: Option<Confirmations>,
+}
+
+impl<S> ControlRegistry<S>
+where
+    S: MatrixRouter + 'static,
+{
+    /// An empty registry, only knowing `help`.
+    pub fn new() -> Self {
+        Self {
+            commands: BTreeMap::new(),
+            confirmations: None,
+        }
+    }
+
+    /// Require destructive commands to be confirmed with a token, valid for `ttl` and only once.
+    ///
+    /// Off by default, leave it that way for automation.
+    pub fn with_confirmation(mut self, ttl: Duration) -> Self {
+        self.confirmations = Some(Confirmations {
+            ttl,
+            prepared: Mutex::new(HashMap::new()),
+        });
+        self
+    }
+
+    /// A registry with the basic router commands: `info`, `routes` and `route`.
+    pub fn with_router_commands() -> Self {
+        let mut reg = Self::new();
+        let index_schema = json!({
+            "type": "object",
+            "properties": { "index": { "type": "integer", "minimum": 0, "default": 0 } },
+        });
+
+        reg.register(
+            "info",
+            "Router and matrix information",
+            index_schema.clone(),
+            |router: Arc<S>, params| {
+                Box::pin(async move {
+                    let index = index_param(&params)?;
+                    let alive = router.is_alive().await?;
+                    let info = router.get_router_info().await?;
+                    let mi = router.get_matrix_info(index).await?;
+                    Ok(json!({
+                        "alive": alive,
+                        "model": info.model,
+                        "name": info.name,
+                        "matrix_count": info.matrix_count,
+                        "matrix_names": info.matrix_names,
+                        "matrix_name": mi.name,
+                        "input_count": mi.input_count,
+                        "output_count": mi.output_count,
+                    }))
+                })
+            },
+        );
+        reg.register(
+            "routes",
+            "Currently patched routes as [output, input] pairs, input null for parked outputs",
+            index_schema,
+            |router: Arc<S>, params| {
+                Box::pin(async move {
+                    let index = index_param(&params)?;
+                    let mut routes = router.get_routes(index).await?;
+                    routes.sort();
+                    let pairs: Vec<(u32, Option<u32>)> = routes
+                        .into_iter()
+                        .map(|p| (p.to_output, p.from_input))
+                        .collect();
+                    Ok(json!(pairs))
+                })
+            },
+        );
+        reg.register(
+            "route",
+            "Patch a single output to an input, or park it with a null input",
+            json!({
+                "type": "object",
+                "properties": {
+                    "index": { "type": "integer", "minimum": 0, "default": 0 },
+                    "output": { "type": "integer", "minimum": 0 },
+                    "input": { "type": ["integer", "null"], "minimum": 0 },
+                },
+                "required": ["output", "input"],
+            }),
+            |router: Arc<S>, params| {
+                Box::pin(async move {
+                    let index = index_param(&params)?;
+                    let from_input = match params.get("input") {
+                        Some(Value::Null) => None,
+                        _ => Some(u32_param(&params, "input")?),
+                    };
+                    let patch = RouterPatch {
+                        from_input,
+                        to_output: u32_param(&params, "output")?,
+                    };
+                    router.update_routes(index, vec![patch]).await?;
+                    Ok(Value::Null)
+                })
+            },
+        );
+        reg
+    }
+
+    /// Add `export-labels` and `import-labels`, moving labels in and out as CSV.
+    ///
+    /// Imports only show the diff unless `apply` is set.
+    pub fn with_label_commands(mut self) -> Self {
+        self.register(
+            "export-labels",
+            "All labels as CSV of matrix, direction, id and name",
+            json!({ "type": "object" }),
+            |router: Arc<S>, _params| {
+                Box::pin(async move { Ok(json!(label_csv::export(&*router).await?)) })
+            },
+        );
+        self.register_confirmed(
+            "import-labels",
+            "Diff labels against CSV as exported, applying the changes if asked to",
+            json!({
+                "type": "object",
+                "properties": {
+                    "csv": { "type": "string" },
+                    "apply": { "type": "boolean", "default": false },
+                    "batch_size": { "type": "integer", "minimum": 1, "default": 32 },
+                },
+                "required": ["csv"],
+            }),
+            |params| params.get("apply").and_then(Value::as_bool) == Some(true),
+            |router: Arc<S>, params| {
+                Box::pin(async move { import_labels(&*router, &params, false).await })
+            },
+            |router: Arc<S>, params| {
+                Box::pin(async move {
+                    let apply = params
+                        .get("apply")
+                        .and_then(Value::as_bool)
+                        .unwrap_or(false);
+                    import_labels(&*router, &params, apply).await
+                })
+            },
+        );
+        self
+    }
+
+    /// Register a command, replacing any previous one of the same name.
+    /// `params` is a JSON schema describing the accepted parameters.
+    pub fn register<F>(&mut self, name: &str, description: &str, params: Value, handler: F)
+    where
+        F: Fn(Arc<S>, Value) -> CommandFuture + Send + Sync + 'static,
+    {
+        self.commands.insert(
+            name.to_string(),
+            Command {
+                description: description.to_string(),
+                params,
+                handler: Arc::new(handler),
+                confirm: None,
+            },
+        );
+    }
+
+    /// Register a destructive command, confirmed if enabled by [Self::with_confirmation].
+    ///
+    /// Requests `needed` deems destructive get the result of `prepare` as `summary` first,
+    /// along with a `confirm` token. `handler` only runs once that token is sent back as
+    /// parameter and `prepare` still gives the same summary.
+    pub fn register_confirmed<N, P, F>(
+        &mut self,
+        name: &str,
+        description: &str,
+        mut params: Value,
+        needed: N,
+        prepare: P,
+        handler: F,
+    ) where
+        N: Fn(&Value) -> bool + Send + Sync + 'static,
+        P: Fn(Arc<S>, Value) -> CommandFuture + Send + Sync + 'static,
+        F: Fn(Arc<S>, Value) -> CommandFuture + Send + Sync + 'static,
+    {
+        if let Some(props) = params.get_mut("properties").and_then(Value::as_object_mut) {
+            props.insert("confirm".into(), json!({ "type": "string" }));
+        }
+        self.commands.insert(
+            name.to_string(),
+            Command {
+                description: description.to_string(),
+                params,
+                handler: Arc::new(handler),
+                confirm: Some(Confirm {
+                    needed: Arc::new(needed),
+                    prepare: Arc::new(prepare),
+                }),
+            },
+        );
+    }
+
+    /// Describe all known commands.
+    fn help(&self) -> Value {
+        let mut cmds: Vec<Value> = self
+            .commands
+            .iter()
+            .map(|(name, c)| {
+                json!({ "name": name, "description": c.description, "params": c.params })
+            })
+            .collect();
+        cmds.push(json!({
+            "name": "help",
+            "description": "List available commands",
+            "params": { "type": "object" },
+        }));
+        Value::Array(cmds)
+    }
+
+    /// Run a request against `router`.
+    pub async fn dispatch(&self, router: Arc<S>, req: ControlRequest) -> ControlResponse {
+        if req.cmd == "help" {
+            return ControlResponse::from_result(Ok(self.help()));
+        }
+        let res = match self.commands.get(&req.cmd) {
+            Some(c) => match (&self.confirmations, &c.confirm) {
+                (Some(confs), Some(confirm)) if (confirm.needed)(&req.params) => {
+                    self.run_confirmed(confs, c, confirm, router, req).await
+                }
+                _ => (c.handler)(router, req.params).await,
+            },
+            None => Err(anyhow!("Unknown command '{}'", req.cmd)),
+        };
+        ControlResponse::from_result(res)
+    }
+
+    /// Prepare a destructive request, or carry it out if it brings a valid token.
+    async fn run_confirmed(
+        &self,
+        confs: &Confirmations,
+        c: &Command<S>,
+        confirm: &Confirm<S>,
+        router: Arc<S>,
+        req: ControlRequest,
+    ) -> Result<Value> {
+        let mut params = req.params;
+        let token = params.as_object_mut().and_then(|o| o.remove("confirm"));
+        // Prepared again when confirming, so changes in between get noticed.
+        let summary = (confirm.prepare)(Arc::clone(&router), params.clone()).await?;
+        let digest = change_digest(&req.cmd, &params, &summary);
+        match token {
+            None => {
+                let token = confs.issue(&req.cmd, digest)?;
+                debug!(cmd = ?req.cmd, "Prepared destructive command");
+                Ok(json!({
+                    "summary": summary,
+                    "confirm": token,
+                    "expires_in_ms": confs.ttl.as_millis() as u64,
+                }))
+            }
+            Some(token) => {
+                let token = token
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Invalid parameter 'confirm'"))?;
+                confs.redeem(token, &req.cmd, digest)?;
+                info!(cmd = ?req.cmd, "Running confirmed command");
+                (c.handler)(router, params).await
+            }
+        }
+    }
+}
+
+impl<S> ControlRegistry<S>
+where
+    S: MatrixRouter + RouterIntrospect + 'static,
+{
+    /// Add `describe-router`, listing the layers of a composed router.
+    pub fn with_describe_command(mut self) -> Self {
+        self.register(
+            "describe-router",
+            "Layers of the router, outermost first, with their configuration and counters",
+            json!({ "type": "object" }),
+            |router: Arc<S>, _params| {
+                let layers: Vec<Value> = describe_router(&*router)
+                    .into_iter()
+                    .map(|l| {
+                        let config: serde_json::Map<String, Value> =
+                            l.config.into_iter().map(|(k, v)| (k, json!(v))).collect();
+                        let counters: serde_json::Map<String, Value> =
+                            l.counters.into_iter().map(|(k, v)| (k, json!(v))).collect();
+                        json!({ "name": l.name, "config": config, "counters": counters })
+                    })
+                    .collect();
+                Box::pin(async move { Ok(Value::Array(layers)) })
+            },
+        );
+        self
+    }
+}
+
+impl<S> Default for ControlRegistry<S>
+where
+    S: MatrixRouter + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Diff labels against the CSV in `params`, applying the changes if `apply` is set.
+async fn import_labels<S: MatrixRouter>(router: &S, params: &Value, apply: bool) -> Result<Value> {
+    let csv = params
+        .get("csv")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("Missing or invalid parameter 'csv'"))?;
+    let batch_size = match params.get("batch_size") {
+        None | Some(Value::Null) => 32,
+        Some(_) => u32_param(params, "batch_size")? as usize,
+    };
+
+    let (rows, mut errors) = label_csv::parse(csv);
+    let (changes, diff_errors) = label_csv::diff(router, rows).await?;
+    errors.extend(diff_errors);
+    let mut applied = Vec::new();
+    if apply {
+        let (ok, apply_errors) = label_csv::apply(router, &changes, batch_size).await;
+        applied = ok;
+        errors.extend(apply_errors);
+    }
+    errors.sort_by_key(|e| e.line);
+    Ok(json!({
+        "changes": changes.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+        "applied": applied,
+        "errors": errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+    }))
+}
+
+fn u32_param(params: &Value, name: &str) -> Result<u32> {
+    params
+        .get(name)
+        .and_then(Value::as_u64)
+        .and_then(|v| u32::try_from(v).ok())
+        .ok_or_else(|| anyhow!("Missing or invalid parameter '{}'", name))
+}
+
+fn index_param(params: &Value) -> Result<u32> {
+    match params.get("index") {
+        None | Some(Value::Null) => Ok(0),
+        Some(_) => u32_param(params, "index"),
+    }
+}
+
+/// Control socket serving a [ControlRegistry] for one router.
+pub struct ControlSocket<S> {
+    router: Arc<S>,
+    registry: Arc<ControlRegistry<S>>,
+    token: Option<String>,
+}
+
+impl<S> Clone for ControlSocket<S> {
+    fn clone(&self) -> Self {
+        Self {
+            router: Arc::clone(&self.router),
+            registry: Arc::clone(&self.registry),
+            token: self.token.clone(),
+        }
+    }
+}
+
+impl<S> ControlSocket<S>
+where
+    S: MatrixRouter + 'static,
+{
+    pub fn new(router: Arc<S>, registry: ControlRegistry<S>) -> Self {
+        Self {
+            router,
+            registry: Arc::new(registry),
+            token: None,
+        }
+    }
+
+    /// Require every request to carry `token`.
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Bind the socket at `path`, only accessible by the current user, and serve clients.
+    /// A stale socket at `path` is replaced, any other file there fails the bind.
+    #[tracing::instrument(skip(self, path), fields(path = ?path.as_ref()))]
+    pub async fn listen(self, path: impl AsRef<Path>) -> Result<()> {
+        let listener = crate::unix_socket::bind_with_mode(path.as_ref(), 0o600)?;
+        info!("Control socket bound successfully");
+        loop {
+            let (socket, _) = listener.accept().await?;
+            let ctl = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = ctl.handle_connection(socket).await {
+                    error!(error = ?e, "Control connection returned error");
+                }
+            });
+        }
+    }
+
+    async fn handle_connection(self, socket: UnixStream) -> Result<()> {
+        let mut framed = Framed::new(socket, LinesCodec::new_with_max_length(MAX_LINE_LENGTH));
+        while let Some(line) = framed.next().await {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let resp = match serde_json::from_str::<ControlRequest>(&line) {
+                Ok(req) if !self.token_matches(req.token.as_deref()) => {
+                    ControlResponse::from_result(Err(anyhow!("Invalid token")))
+                }
+                Ok(req) => {
+                    debug!(cmd = ?req.cmd, "Got control request");
+                    self.registry.dispatch(self.router.clone(), req).await
+                }
+                Err(e) => ControlResponse::from_result(Err(anyhow!("Invalid request: {}", e))),
+            };
+            framed.send(serde_json::to_string(&resp)?).await?;
+        }
+        Ok(())
+    }
+
+    /// Check a request's token without giving away how much of it was right.
+    fn token_matches(&self, token: Option<&str>) -> bool {
+        let Some(expected) = &self.token else {
+            return true;
+        };
+        let given = token.unwrap_or_default().as_bytes();
+        let expected = expected.as_bytes();
+        let diff = given
+            .iter()
+            .zip(expected)
+            .fold(given.len() ^ expected.len(), |acc, (a, b)| {
+                acc | (a ^ b) as usize
+            });
+        diff == 0
+    }
+}
+
+/// Send a single request to the control socket at `path` and return its result.
+pub async fn request(path: impl AsRef<Path>, req: &ControlRequest) -> Result<Value> {
+    let socket = UnixStream::connect(path).await?;
+    let mut framed = Framed::new(socket, LinesCodec::new_with_max_length(MAX_LINE_LENGTH));
+    framed.send(serde_json::to_string(req)?).await?;
+    let line = framed
+        .next()
+        .await
+        .ok_or_else(|| anyhow!("Control socket closed without response"))??;
+    let resp: ControlResponse = serde_json::from_str(&line)?;
+    if resp.ok {
+        Ok(resp.result.unwrap_or(Value::Null))
+    } else {
+        Err(anyhow!(resp.error.unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::{DummyRouter, MetadataRouter, RouterLabel};
+    use std::{os::unix::fs::PermissionsExt, path::PathBuf};
+
+    async fn spawn_control(
+        token: Option<&str>,
+    ) -> Result<(tempfile::TempDir, PathBuf, DummyRouter)> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("control.sock");
+        let dummy = DummyRouter::with_config(1, 4, 4).with_matrix_names(&["Main"]);
+        let mut ctl = ControlSocket::new(
+            Arc::new(dummy.clone()),
+            ControlRegistry::with_router_commands(),
+        );
+        if let Some(token) = token {
+            ctl = ctl.with_token(token);
+        }
+        let listen_path = path.clone();
+        tokio::spawn(async move { ctl.listen(listen_path).await.unwrap() });
+        while !path.exists() {
+            tokio::task::yield_now().await;
+        }
+        Ok((dir, path, dummy))
+    }
+
+    fn req(cmd: &str, params: Value) -> ControlRequest {
+        ControlRequest {
+            cmd: cmd.to_string(),
+            params,
+            token: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn help_lists_commands() -> Result<()> {
+        let (_dir, path, _dummy) = spawn_control(None).await?;
+        let help = request(&path, &req("help", Value::Null)).await?;
+        let names: Vec<&str> = help
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["info", "route", "routes", "help"]);
+        assert_eq!(help[1]["params"]["required"], json!(["output", "input"]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn route_and_query() -> Result<()> {
+        let (_dir, path, dummy) = spawn_control(None).await?;
+
+        let info = request(&path, &req("info", Value::Null)).await?;
+        assert_eq!(info["input_count"], 4);
+        assert_eq!(info["matrix_name"], "Main");
+        assert_eq!(info["matrix_names"], json!(["Main"]));
+
+        request(&path, &req("route", json!({ "output": 2, "input": 3 }))).await?;
+        let p = RouterPatch {
+            from_input: Some(3),
+            to_output: 2,
+        };
+        assert!(dummy.get_routes(0).await?.contains(&p));
+
+        let routes = request(&path, &req("routes", json!({}))).await?;
+        assert_eq!(routes[2], json!([2, 3]));
+
+        request(&path, &req("route", json!({ "output": 1, "input": null }))).await?;
+        let routes = request(&path, &req("routes", json!({}))).await?;
+        assert_eq!(routes[1], json!([1, null]));
+
+        // Errors are reported, not fatal.
+        assert!(
+            request(&path, &req("route", json!({ "output": 9, "input": 0 })))
+                .await
+                .is_err()
+        );
+        assert!(request(&path, &req("nope", Value::Null)).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn describe_composed_router() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("control.sock");
+        let router = MetadataRouter::new(MetadataRouter::new(DummyRouter::with_config(1, 4, 2)));
+        let ctl = ControlSocket::new(
+            Arc::new(router),
+            ControlRegistry::with_router_commands().with_describe_command(),
+        );
+        let listen_path = path.clone();
+        tokio::spawn(async move { ctl.listen(listen_path).await.unwrap() });
+        while !path.exists() {
+            tokio::task::yield_now().await;
+        }
+
+        let layers = request(&path, &req("describe-router", Value::Null)).await?;
+        let names: Vec<&str> = layers
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|l| l["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["MetadataRouter", "MetadataRouter", "DummyRouter"]
+        );
+        assert_eq!(layers[0]["counters"]["rejected"], 0);
+        assert_eq!(layers[2]["config"]["matrices"], "4x2");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn label_import() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("control.sock");
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let ctl = ControlSocket::new(
+            Arc::new(dummy.clone()),
+            ControlRegistry::new().with_label_commands(),
+        );
+        let listen_path = path.clone();
+        tokio::spawn(async move { ctl.listen(listen_path).await.unwrap() });
+        while !path.exists() {
+            tokio::task::yield_now().await;
+        }
+
+        let csv = request(&path, &req("export-labels", Value::Null)).await?;
+        let csv = csv.as_str().unwrap().replace("Output 2", "Monitor") + "0,output,7,Nope\n";
+
+        // Dry-run by default.
+        let res = request(&path, &req("import-labels", json!({ "csv": csv }))).await?;
+        assert_eq!(
+            res["changes"],
+            json!(["matrix 0 output 1: \"Output 2\" -> \"Monitor\""])
+        );
+        assert_eq!(
+            res["errors"],
+            json!(["line 6: output 7 out of range for matrix 0"])
+        );
+        assert_eq!(dummy.get_output_labels(0).await?[1].name, "Output 2");
+
+        let res = request(
+            &path,
+            &req("import-labels", json!({ "csv": csv, "apply": true })),
+        )
+        .await?;
+        assert_eq!(res["applied"], json!([5]));
+        assert_eq!(dummy.get_output_labels(0).await?[1].name, "Monitor");
+        Ok(())
+    }
+
+    /// A registry confirming label imports, along with CSV renaming output 2.
+    async fn confirming(
+        ttl: Duration,
+    ) -> Result<(ControlRegistry<DummyRouter>, Arc<DummyRouter>, String)> {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        let reg = ControlRegistry::new()
+            .with_label_commands()
+            .with_confirmation(ttl);
+        let csv = label_csv::export(&*dummy)
+            .await?
+            .replace("Output 2", "Monitor");
+        Ok((reg, dummy, csv))
+    }
+
+    async fn dispatch(
+        reg: &ControlRegistry<DummyRouter>,
+        dummy: &Arc<DummyRouter>,
+        params: Value,
+    ) -> ControlResponse {
+        reg.dispatch(Arc::clone(dummy), req("import-labels", params))
+            .await
+    }
+
+    #[tokio::test]
+    async fn confirm_import() -> Result<()> {
+        let (reg, dummy, csv) = confirming(Duration::from_secs(60)).await?;
+
+        // Dry-runs don't need confirming.
+        let res = dispatch(&reg, &dummy, json!({ "csv": csv })).await;
+        assert!(res.result.unwrap().get("confirm").is_none());
+
+        let res = dispatch(&reg, &dummy, json!({ "csv": csv, "apply": true })).await;
+        let prepared = res.result.unwrap();
+        assert_eq!(
+            prepared["summary"]["changes"],
+            json!(["matrix 0 output 1: \"Output 2\" -> \"Monitor\""])
+        );
+        assert_eq!(prepared["expires_in_ms"], 60_000);
+        assert_eq!(dummy.get_output_labels(0).await?[1].name, "Output 2");
+
+        let token = prepared["confirm"].clone();
+        let res = dispatch(
+            &reg,
+            &dummy,
+            json!({ "csv": csv, "apply": true, "confirm": token }),
+        )
+        .await;
+        assert!(res.ok, "{:?}", res.error);
+        assert_eq!(res.result.unwrap()["applied"], json!([5]));
+        assert_eq!(dummy.get_output_labels(0).await?[1].name, "Monitor");
+
+        // Tokens are single-use.
+        let res = dispatch(
+            &reg,
+            &dummy,
+            json!({ "csv": csv, "apply": true, "confirm": token }),
+        )
+        .await;
+        assert_eq!(
+            res.error.as_deref(),
+            Some("Unknown or already used confirmation token")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn confirm_expires() -> Result<()> {
+        let (reg, dummy, csv) = confirming(Duration::from_millis(20)).await?;
+        let res = dispatch(&reg, &dummy, json!({ "csv": csv, "apply": true })).await;
+        let token = res.result.unwrap()["confirm"].clone();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let res = dispatch(
+            &reg,
+            &dummy,
+            json!({ "csv": csv, "apply": true, "confirm": token }),
+        )
+        .await;
+        assert_eq!(
+            res.error.as_deref(),
+            Some("Confirmation token expired, prepare again")
+        );
+        assert_eq!(dummy.get_output_labels(0).await?[1].name, "Output 2");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn confirm_bound_to_change() -> Result<()> {
+        let (reg, dummy, csv) = confirming(Duration::from_secs(60)).await?;
+        let res = dispatch(&reg, &dummy, json!({ "csv": csv, "apply": true })).await;
+        let token = res.result.unwrap()["confirm"].clone();
+
+        // Somebody else renames the output in between, so the import would do something else.
+        let label = RouterLabel {
+            id: 1,
+            name: "Program".into(),
+        };
+        dummy.update_output_labels(0, vec![label]).await?;
+        let res = dispatch(
+            &reg,
+            &dummy,
+            json!({ "csv": csv, "apply": true, "confirm": token }),
+        )
+        .await;
+        assert_eq!(
+            res.error.as_deref(),
+            Some("State changed since preparing, prepare again")
+        );
+        assert_eq!(dummy.get_output_labels(0).await?[1].name, "Program");
+
+        // A token only confirms the request it was prepared for.
+        let res = dispatch(&reg, &dummy, json!({ "csv": csv, "apply": true })).await;
+        let token = res.result.unwrap()["confirm"].clone();
+        let other = csv.replace("Monitor", "Monitor 2");
+        let res = dispatch(
+            &reg,
+            &dummy,
+            json!({ "csv": other, "apply": true, "confirm": token }),
+        )
+        .await;
+        assert!(!res.ok);
+        assert_eq!(dummy.get_output_labels(0).await?[1].name, "Program");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn token_required() -> Result<()> {
+        let (_dir, path, _dummy) = spawn_control(Some("secret")).await?;
+        let mut r = req("info", Value::Null);
+        assert!(request(&path, &r).await.is_err());
+
+        r.token = Some("secret".into());
+        assert!(request(&path, &r).await.is_ok());
+
+        r.token = Some("secreT".into());
+        assert!(request(&path, &r).await.is_err());
+        r.token = Some("secret2".into());
+        assert!(request(&path, &r).await.is_err());
+
+        let mode = std::fs::metadata(&path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_to_replace_other_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("control.sock");
+        std::fs::write(&path, "not a socket")?;
+        let ctl = ControlSocket::new(
+            Arc::new(DummyRouter::with_config(1, 4, 4)),
+            ControlRegistry::with_router_commands(),
+        );
+        assert!(ctl.listen(&path).await.is_err());
+        assert_eq!(std::fs::read_to_string(&path)?, "not a socket");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn overlong_line_closes_connection() -> Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (_dir, path, _dummy) = spawn_control(None).await?;
+        let mut socket = UnixStream::connect(&path).await?;
+        let line = vec![b'x'; MAX_LINE_LENGTH + 1];
+        // The server may hang up before taking all of it.
+        let _ = socket.write_all(&line).await;
+        let mut buf = Vec::new();
+        let _ = socket.read_to_end(&mut buf).await;
+        assert!(buf.is_empty());
+
+        // Others are still served.
+        assert!(request(&path, &req("info", Value::Null)).await.is_ok());
+        Ok(())
+    }
+}
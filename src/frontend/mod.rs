@@ -1,3 +1,3 @@
 mod videohub;
 
-pub use videohub::VideohubFrontend;
+pub use videohub::{bind_dual_stack, VideohubFrontend};
@@ -1,36 +1,245 @@
-use crate::matrix::{MatrixRouter, RouterEvent};
+use crate::bridge::{canonical_labels, canonical_routes};
+use crate::matrix::{MatrixRouter, RouterError, RouterEvent, RouterLabel, RouterLock, RouterPatch};
 use anyhow::Result;
 use async_stream::try_stream;
+use futures_core::stream::BoxStream;
 use futures_util::pin_mut;
 use futures_util::SinkExt;
-use std::{net::SocketAddr, sync::Arc};
-use tokio::sync::Mutex;
-use tokio::{
-    net::{TcpListener, TcpStream},
-    select,
-};
-use tokio_stream::{Stream, StreamExt};
+use socket2::{Domain, Protocol, Socket, Type};
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, mpsc, Mutex, Notify};
+use tokio::time::Instant;
+use tokio::{net::TcpListener, select};
+use tokio_stream::{adapters::Peekable, Stream, StreamExt};
 use tokio_util::codec::Framed;
 use tracing::{debug, error, info};
 use videohub::*;
 
-/// Holds the router and any cached protocol state
+/// Bind a listener on `addr`, accepting IPv4 clients as well if it's an IPv6 address.
+///
+/// IPv4 clients show up as IPv4-mapped addresses like `::ffff:127.0.0.1`. Whether an IPv6
+/// socket accepts them by default depends on the OS, this makes it explicit.
+pub fn bind_dual_stack(addr: SocketAddr) -> Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(false)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(TcpListener::from_std(socket.into())?)
+}
+
+/// How long clients wait for a reply to a request, by default.
+const DEFAULT_REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long to wait for further events after one, to send what they amount to at once.
+const EVENT_BATCH_WINDOW: Duration = Duration::from_millis(1);
+
+/// Identifies a single client connection.
+type SessionId = u64;
+
+/// Kinds of ports clients can lock.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum LockTarget {
+    /// Held at the router if it has output locks, see [MatrixRouter::update_locks].
+    /// Otherwise only known to the frontend.
+    VideoOutput,
+    /// Held at the router, see [MatrixRouter::update_frame_locks].
+    FrameBuffer,
+    /// Held at the router, see [MatrixRouter::update_processing_unit_locks].
+    ProcessingUnit,
+}
+
+/// Locks and the sessions owning them.
+#[derive(Debug, Default)]
+struct LockRegistry {
+    owners: HashMap<(LockTarget, u32), SessionId>,
+}
+
+impl LockRegistry {
+    /// Lock state of port `id` as seen by `session`.
+    fn state_for(&self, target: LockTarget, id: u32, session: SessionId) -> LockState {
+        match self.owners.get(&(target, id)) {
+            None => LockState::Unlocked,
+            Some(&owner) if owner == session => LockState::Owned,
+            Some(_) => LockState::Locked,
+        }
+    }
+
+    fn locked_by_other(&self, target: LockTarget, id: u32, session: SessionId) -> bool {
+        self.state_for(target, id, session) == LockState::Locked
+    }
+
+    /// Whether [Self::apply] would accept the lock requests.
+    fn acceptable(
+        &self,
+        session: SessionId,
+        target: LockTarget,
+        changes: &[Lock],
+        count: u32,
+    ) -> bool {
+        changes.iter().all(|l| {
+            l.id < count
+                && match l.state {
+                    LockState::Owned | LockState::Unlocked => {
+                        !self.locked_by_other(target, l.id, session)
+                    }
+                    LockState::Force => true,
+                    LockState::Locked | LockState::Other(_) => false,
+                }
+        })
+    }
+
+    /// Apply lock requests of `session`, either all of them or none.
+    ///
+    /// `O` takes a free lock, `U` releases an owned one and `F` takes it over regardless.
+    /// Returns whether the requests were accepted.
+    fn apply(
+        &mut self,
+        session: SessionId,
+        target: LockTarget,
+        changes: &[Lock],
+        count: u32,
+    ) -> bool {
+        if !self.acceptable(session, target, changes, count) {
+            return false;
+        }
+        for l in changes {
+            match l.state {
+                LockState::Owned | LockState::Force => {
+                    self.owners.insert((target, l.id), session);
+                }
+                _ => {
+                    self.owners.remove(&(target, l.id));
+                }
+            }
+        }
+        true
+    }
+
+    /// Forget owners of locks the router reports as free, they got released elsewhere.
+    fn reconcile(&mut self, target: LockTarget, locks: &[RouterLock]) {
+        for l in locks.iter().filter(|l| !l.locked) {
+            self.owners.remove(&(target, l.id));
+        }
+    }
+
+    /// Drop all locks of `session`, returning the ones it held.
+    fn release(&mut self, session: SessionId) -> Vec<(LockTarget, u32)> {
+        let mut released = Vec::new();
+        self.owners.retain(|&key, owner| {
+            if *owner == session {
+                released.push(key);
+            }
+            *owner != session
+        });
+        released
+    }
+}
+
+/// Protocol state shared between all connections of a frontend.
 struct VideohubFrontendState {
-    // add other cached state here
+    next_session: SessionId,
+    locks: LockRegistry,
 }
 
 impl VideohubFrontendState {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            next_session: 1,
+            locks: LockRegistry::default(),
+        }
+    }
+}
+
+/// Outputs with route changes on their way to the router.
+///
+/// A route change reserves its outputs once it passed the lock check, other sessions can't
+/// take their locks until the router is done with it.
+#[derive(Debug, Default)]
+struct RouteReservations {
+    outputs: std::sync::Mutex<HashMap<u32, SessionId>>,
+    released: Notify,
+}
+
+impl RouteReservations {
+    /// Whether any of `outputs` is reserved by a session other than `session`.
+    fn held_by_other(&self, outputs: impl IntoIterator<Item = u32>, session: SessionId) -> bool {
+        let reserved = self.outputs.lock().unwrap();
+        outputs
+            .into_iter()
+            .any(|o| reserved.get(&o).is_some_and(|&s| s != session))
+    }
+
+    /// Reserve `outputs` for `session` if none of them is yet.
+    fn try_reserve(&self, outputs: &[u32], session: SessionId) -> Option<RouteReservation<'_>> {
+        let mut reserved = self.outputs.lock().unwrap();
+        if outputs.iter().any(|o| reserved.contains_key(o)) {
+            return None;
+        }
+        reserved.extend(outputs.iter().map(|&o| (o, session)));
+        Some(RouteReservation {
+            reservations: self,
+            outputs: outputs.to_vec(),
+        })
+    }
+}
+
+/// Outputs reserved by a route change, released on drop.
+struct RouteReservation<'a> {
+    reservations: &'a RouteReservations,
+    outputs: Vec<u32>,
+}
+
+impl Drop for RouteReservation<'_> {
+    fn drop(&mut self) {
+        let mut reserved = self.reservations.outputs.lock().unwrap();
+        for o in &self.outputs {
+            reserved.remove(o);
+        }
+        drop(reserved);
+        self.reservations.released.notify_waiters();
     }
 }
 
 /// Frontend bridging TCP‐Videohub clients to a MatrixRouter
+///
+/// The Videohub protocol has no routing levels, clients see and patch level 0 of the matrix.
+/// Other levels are left alone and their changes aren't announced.
 pub struct VideohubFrontend<S> {
     pub router: Arc<S>,
     index: u32,
     state: Arc<Mutex<VideohubFrontendState>>,
+    /// Outputs with route changes in flight, by the session making them.
+    reservations: Arc<RouteReservations>,
+    /// Notifies all connections once locks changed.
+    locks_tx: broadcast::Sender<()>,
     peer: Option<SocketAddr>,
+    session: SessionId,
+    /// Upper bound for answering requests whose data the router is still fetching.
+    reply_timeout: Duration,
+    /// Messages completing late replies, sent to the client unsolicited.
+    deferred_tx: Option<mpsc::UnboundedSender<VideohubMessage>>,
+    /// Maximum label length in bytes, labels from clients get normalized to it if set.
+    label_limit: Option<usize>,
+    /// Protocol version spoken to clients.
+    profile: ProtocolProfile,
+    /// Maximum entries per label or routing block sent, bigger ones get split if set.
+    max_block_lines: Option<usize>,
+    /// Input parked outputs are shown routed from, they are left out of routing blocks if unset.
+    park_input: Option<u32>,
+    /// Number of clients currently connected, shared by all connections.
+    connections: Arc<AtomicUsize>,
+    /// Called with the peer of every accepted TCP client.
+    on_connect: Option<fn(SocketAddr)>,
+    /// Called once a client is gone, with the error ending the connection if any.
+    on_disconnect: Option<fn(SocketAddr, Option<anyhow::Error>)>,
 }
 
 impl<S> VideohubFrontend<S>
@@ -42,57 +251,307 @@ where
             router,
             index,
             state: Arc::new(Mutex::new(VideohubFrontendState::new())),
+            reservations: Arc::default(),
+            locks_tx: broadcast::channel(16).0,
+            peer: None,
+            session: 0,
+            reply_timeout: DEFAULT_REPLY_TIMEOUT,
+            deferred_tx: None,
+            label_limit: None,
+            profile: ProtocolProfile::V2_7,
+            max_block_lines: None,
+            park_input: None,
+            connections: Arc::new(AtomicUsize::new(0)),
+            on_connect: None,
+            on_disconnect: None,
+        }
+    }
+
+    /// A frontend serving matrix `new_index` of the same router, configured like this one.
+    ///
+    /// Locks and the connection count belong to a matrix, so the new frontend starts out
+    /// with its own.
+    pub fn clone_with_index(&self, new_index: u32) -> Self {
+        Self {
+            index: new_index,
+            state: Arc::new(Mutex::new(VideohubFrontendState::new())),
+            reservations: Arc::default(),
+            locks_tx: broadcast::channel(16).0,
             peer: None,
+            session: 0,
+            deferred_tx: None,
+            connections: Arc::new(AtomicUsize::new(0)),
+            ..self.clone()
+        }
+    }
+
+    /// Set how long a request may wait on the router before getting a partial reply.
+    ///
+    /// Defaults to 500ms.
+    pub fn with_reply_timeout(mut self, timeout: Duration) -> Self {
+        self.reply_timeout = timeout;
+        self
+    }
+
+    /// Normalize labels set by clients before forwarding them to the router,
+    /// see [RouterLabel::normalized] and [videohub::HARDWARE_LABEL_LEN].
+    pub fn with_label_limit(mut self, max_len: usize) -> Self {
+        self.label_limit = Some(max_len);
+        self
+    }
+
+    /// Speak an older or newer protocol version to clients, defaults to 2.7.
+    ///
+    /// Before 2.7 the initial dump has no `END PRELUDE:` and `PING:` goes unanswered.
+    pub fn with_profile(mut self, profile: ProtocolProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Split label and routing blocks sent to clients above `max` entries,
+    /// for clients choking on big blocks. See [videohub::chunked].
+    pub fn with_max_block_lines(mut self, max: usize) -> Self {
+        self.max_block_lines = Some(max);
+        self
+    }
+
+    /// Show parked outputs to clients as routed from `input`.
+    ///
+    /// The protocol can't tell that an output is fed by nothing, so by default parked
+    /// outputs are left out of routing blocks and clients keep showing their last route.
+    pub fn with_park_input(mut self, input: u32) -> Self {
+        self.park_input = Some(input);
+        self
+    }
+
+    /// Call `hook` with the address of every client connecting.
+    ///
+    /// Clients on UNIX sockets have no address and don't get reported.
+    pub fn with_on_connect(mut self, hook: fn(SocketAddr)) -> Self {
+        self.on_connect = Some(hook);
+        self
+    }
+
+    /// Call `hook` with the address of every client disconnecting, along with the error
+    /// that ended the connection, if any.
+    pub fn with_on_disconnect(mut self, hook: fn(SocketAddr, Option<anyhow::Error>)) -> Self {
+        self.on_disconnect = Some(hook);
+        self
+    }
+
+    /// Number of clients currently connected through [Self::serve], [Self::listen] and the
+    /// like.
+    pub fn connection_count(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Split messages for sending, if configured.
+    fn chunk(&self, msgs: Vec<VideohubMessage>) -> Vec<VideohubMessage> {
+        match self.max_block_lines {
+            Some(max) => chunked(msgs, max),
+            None => msgs,
+        }
+    }
+
+    /// Route parked outputs from the park input, if configured.
+    fn unpark(&self, routes: Vec<RouterPatch>) -> Vec<RouterPatch> {
+        match self.park_input {
+            Some(input) => routes
+                .into_iter()
+                .map(|p| RouterPatch {
+                    from_input: p.from_input.or(Some(input)),
+                    ..p
+                })
+                .collect(),
+            None => routes,
+        }
+    }
+
+    /// Normalize label changes from a client, if configured.
+    fn label_changes(&self, labels: Vec<RouterLabel>) -> Vec<RouterLabel> {
+        labels
+            .into_iter()
+            .map(|l| match self.label_limit {
+                Some(max_len) => l.normalized(max_len),
+                None => l,
+            })
+            .collect()
+    }
+
+    /// Spawn a task handling a freshly accepted client as a new session.
+    ///
+    /// `peer` is `None` for clients without an address, like those on UNIX sockets.
+    async fn spawn_connection<T>(&self, socket: T, peer: Option<SocketAddr>)
+    where
+        T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let mut frontend = self.clone();
+        frontend.peer = peer;
+        // Routers telling lock owners apart get to know who is locking.
+        if let Some(owner) = peer.and_then(|p| self.router.with_lock_owner(&p.to_string())) {
+            frontend.router = Arc::new(owner);
         }
+        {
+            let mut st = self.state.lock().await;
+            frontend.session = st.next_session;
+            st.next_session += 1;
+        }
+        self.connections.fetch_add(1, Ordering::SeqCst);
+        if let (Some(hook), Some(peer)) = (self.on_connect, peer) {
+            hook(peer);
+        }
+        let connections = self.connections.clone();
+        let on_disconnect = self.on_disconnect;
+        tokio::spawn(async move {
+            let result = frontend.handle_connection(socket).await;
+            connections.fetch_sub(1, Ordering::SeqCst);
+            if let Err(e) = &result {
+                error!(?peer, error = ?e, "handle_connection returned error");
+            }
+            if let (Some(hook), Some(peer)) = (on_disconnect, peer) {
+                hook(peer, result.err());
+            }
+        });
     }
 
     /// Accept connections on existing TcpListener, spawning tasks per client
-    #[tracing::instrument(skip(self, listener), fields(addr = ?listener.local_addr()?))]
+    #[tracing::instrument(
+        skip(self, listener),
+        fields(addr = ?listener.local_addr()?, matrix_idx = self.index)
+    )]
     pub async fn serve(self, listener: TcpListener) -> Result<()> {
         info!("Serving on existing Listener");
         loop {
             let (socket, peer) = listener.accept().await?;
             info!(?peer, "Got connection");
-            let mut frontend = self.clone();
-            frontend.peer = Some(peer);
-            tokio::spawn(async move {
-                if let Err(e) = frontend.handle_connection(socket).await {
-                    error!(?peer, error = ?e, "handle_connection returned error");
-                }
-            });
+            self.spawn_connection(socket, Some(peer)).await;
         }
     }
 
     /// Bind and accept connections, spawning tasks per client
-    #[tracing::instrument(skip(self))]
+    #[tracing::instrument(skip(self), fields(matrix_idx = self.index))]
     pub async fn listen(self, addr: SocketAddr) -> Result<()> {
         let listener = TcpListener::bind(addr).await?;
         info!("Listener bound successfully");
         loop {
             let (socket, peer) = listener.accept().await?;
             info!(?peer, "Got connection");
-            let mut frontend = self.clone();
-            frontend.peer = Some(peer);
-            tokio::spawn(async move {
-                if let Err(e) = frontend.handle_connection(socket).await {
-                    error!(?peer, error = ?e, "handle_connection returned error");
-                }
-            });
+            self.spawn_connection(socket, Some(peer)).await;
+        }
+    }
+
+    /// Like [Self::listen], but binding dual-stack, see [bind_dual_stack].
+    ///
+    /// Pass `[::]:9990` to serve IPv6 and IPv4 clients on all interfaces.
+    #[tracing::instrument(skip(self), fields(matrix_idx = self.index))]
+    pub async fn listen_v6(self, addr: SocketAddr) -> Result<()> {
+        let listener = bind_dual_stack(addr)?;
+        info!("Dual-stack listener bound successfully");
+        self.serve(listener).await
+    }
+
+    /// Bind a UNIX socket at `path` and accept connections, spawning tasks per client.
+    ///
+    /// Meant for processes on the same host, only the current user may connect, see
+    /// [Self::listen_unix_with_mode] to let others in. A stale socket at `path` is replaced,
+    /// any other file there fails the bind.
+    #[cfg(unix)]
+    pub async fn listen_unix(self, path: &Path) -> Result<()> {
+        self.listen_unix_with_mode(path, 0o600).await
+    }
+
+    /// Like [Self::listen_unix], with the socket's permissions set to `mode`, like `0o660`
+    /// for the owning group too.
+    ///
+    /// The permissions are in place before the socket shows up at `path`.
+    #[cfg(unix)]
+    #[tracing::instrument(skip(self), fields(matrix_idx = self.index))]
+    pub async fn listen_unix_with_mode(self, path: &Path, mode: u32) -> Result<()> {
+        let listener = crate::unix_socket::bind_with_mode(path, mode)?;
+        info!("UNIX listener bound successfully");
+        loop {
+            let (socket, _) = listener.accept().await?;
+            info!("Got connection");
+            self.spawn_connection(socket, None).await;
+        }
+    }
+
+    /// Serve a client until it is gone.
+    ///
+    /// The matrix size is recorded on the span by [Self::gather_state] once known.
+    #[tracing::instrument(
+        skip(self, socket),
+        fields(
+            peer.addr = ?self.peer,
+            session = self.session,
+            matrix_idx = self.index,
+            router.input_count = tracing::field::Empty,
+            router.output_count = tracing::field::Empty,
+        )
+    )]
+    async fn handle_connection<T>(mut self, socket: T) -> Result<()>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (deferred_tx, deferred_rx) = mpsc::unbounded_channel();
+        self.deferred_tx = Some(deferred_tx);
+        let res = self.run_connection(socket, deferred_rx).await;
+        // Whatever happened, the session is gone and so are its locks.
+        self.end_session().await;
+        res
+    }
+
+    /// Release all locks of this session, at the router as well.
+    async fn end_session(&self) {
+        let released = self.state.lock().await.locks.release(self.session);
+        if released.iter().any(|(t, _)| *t == LockTarget::VideoOutput) {
+            let _ = self.locks_tx.send(());
+        }
+        let targets = [
+            LockTarget::VideoOutput,
+            LockTarget::FrameBuffer,
+            LockTarget::ProcessingUnit,
+        ];
+        for target in targets {
+            if target == LockTarget::VideoOutput && !self.router_has_output_locks().await {
+                continue;
+            }
+            let unlocks: Vec<RouterLock> = released
+                .iter()
+                .filter(|(t, _)| *t == target)
+                .map(|&(_, id)| RouterLock { id, locked: false })
+                .collect();
+            if unlocks.is_empty() {
+                continue;
+            }
+            if let Err(e) = self.update_router_locks(target, unlocks).await {
+                error!(?target, error = ?e, "Failed to release locks at the router");
+            }
         }
     }
 
-    #[tracing::instrument(skip(self, socket), fields(?peer = self.peer.unwrap()))]
-    async fn handle_connection(self, socket: TcpStream) -> Result<()> {
+    async fn run_connection<T>(
+        &self,
+        socket: T,
+        mut deferred_rx: mpsc::UnboundedReceiver<VideohubMessage>,
+    ) -> Result<()>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
         let mut framed = Framed::new(socket, VideohubCodec::default());
 
-        let mut ev_stream = self.router.event_stream().await?;
+        // Events of other matrices don't even make it here.
+        let mut ev_stream = self
+            .router
+            .events()
+            .matrix(self.index)
+            .subscribe()
+            .await?
+            .peekable();
+        let mut locks_rx = self.locks_tx.subscribe();
 
         debug!("Sending initial dump");
-        let dump = self.create_initial_dump();
-        pin_mut!(dump);
-        while let Some(msg) = dump.next().await {
-            framed.send(msg?).await?;
-        }
+        let mut present = Self::send_dump(&mut framed, self.create_initial_dump()).await?;
         debug!("Dump done");
 
         loop {
@@ -100,10 +559,12 @@ where
                 // Client sent a message to us, expecting the response of a router.
                 maybe = framed.next() => match maybe {
                     Some(Ok(msg)) => {
-                        debug!(?msg, "Got message");
-                        if let Some(reply) = self.handle_message(msg).await? {
-                            debug!(?reply, "Replying");
-                            framed.send(reply).await?;
+                        debug!(msg = %msg.summary(), "Got message");
+                        if let Some(reply) = self.handle_message(msg, present).await? {
+                            debug!(reply = %reply.summary(), "Replying");
+                            for msg in self.chunk(vec![reply]) {
+                                framed.send(msg).await?;
+                            }
                         }
                     }
                     Some(Err(e)) => return Err(e.into()),
@@ -113,10 +574,50 @@ where
                 // Router (Backend) sent an event to us, translate and forward to client.
                 Some(ev) = ev_stream.next() => {
                     debug!(?ev, "Got event");
-                    if let Some(reply) = self.handle_event(ev).await? {
-                        debug!(?reply, "Sending converted event");
-                        framed.send(reply).await?;
+                    match ev {
+                        // The client only saw an absent router so far, tell it everything.
+                        RouterEvent::Connected if !present => {
+                            debug!("Router came back, sending state again");
+                            present = Self::send_dump(&mut framed, self.create_state_dump()).await?;
+                        }
+                        // Ports came or went, start over with the new size.
+                        RouterEvent::MatrixInfoUpdate(idx, _) if idx == self.index && present => {
+                            debug!("Matrix changed, sending state again");
+                            present = Self::send_dump(&mut framed, self.create_state_dump()).await?;
+                        }
+                        RouterEvent::Disconnected if present => {
+                            present = false;
+                            framed.send(VideohubMessage::DeviceInfo(DeviceInfo {
+                                present: Some(Present::No),
+                                ..Default::default()
+                            })).await?;
+                        }
+                        ev => if let Some(reply) = self.handle_event(ev).await? {
+                            let batch = self.batch_events(reply, &mut ev_stream, present).await?;
+                            for reply in batch {
+                                debug!(reply = %reply.summary(), "Sending converted events");
+                                for msg in self.chunk(vec![reply]) {
+                                    framed.send(msg).await?;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // A reply we couldn't give in time finally completed.
+                Some(msg) = deferred_rx.recv() => {
+                    debug!(msg = %msg.summary(), "Sending deferred reply");
+                    framed.send(msg).await?;
+                }
+
+                // Some session changed locks, send them from our point of view.
+                res = locks_rx.recv() => {
+                    if let Err(broadcast::error::RecvError::Closed) = res {
+                        continue;
                     }
+                    let reply = self.gen_locks().await?;
+                    debug!(reply = %reply.summary(), "Sending lock update");
+                    framed.send(reply).await?;
                 }
             }
         }
@@ -124,157 +625,588 @@ where
         Ok(())
     }
 
+    /// Translate events following the one translated to `first` within [EVENT_BATCH_WINDOW],
+    /// merging messages of the same kind, see [VideohubMessage::merge].
+    ///
+    /// Events calling for a dump end the batch early and are left in `events`.
+    async fn batch_events(
+        &self,
+        first: VideohubMessage,
+        events: &mut Peekable<BoxStream<'_, RouterEvent>>,
+        present: bool,
+    ) -> Result<Vec<VideohubMessage>> {
+        let mut batch = vec![first];
+        let deadline = Instant::now() + EVENT_BATCH_WINDOW;
+        while let Ok(Some(ev)) = tokio::time::timeout_at(deadline, events.peek()).await {
+            if self.calls_for_dump(ev, present) {
+                break;
+            }
+            let Some(ev) = events.next().await else {
+                break;
+            };
+            let Some(msg) = self.handle_event(ev).await? else {
+                continue;
+            };
+            let merged = batch
+                .iter_mut()
+                .rev()
+                .find_map(|m| m.merge(&msg).map(|merged| (m, merged)));
+            match merged {
+                Some((m, merged)) => *m = merged,
+                None => batch.push(msg),
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Whether the connection answers `ev` with a dump or presence change, rather than
+    /// translating it.
+    fn calls_for_dump(&self, ev: &RouterEvent, present: bool) -> bool {
+        match ev {
+            RouterEvent::Connected => !present,
+            RouterEvent::MatrixInfoUpdate(idx, _) => *idx == self.index && present,
+            RouterEvent::Disconnected => present,
+            _ => false,
+        }
+    }
+
+    /// Send a dump to the client, returning whether it announced the router as present.
+    async fn send_dump<T>(
+        framed: &mut Framed<T, VideohubCodec>,
+        dump: impl Stream<Item = Result<VideohubMessage>>,
+    ) -> Result<bool>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        pin_mut!(dump);
+        let mut present = false;
+        while let Some(msg) = dump.next().await {
+            let msg = msg?;
+            if let VideohubMessage::DeviceInfo(di) = &msg {
+                present = di.present == Some(Present::Yes);
+            }
+            framed.send(msg).await?;
+        }
+        Ok(present)
+    }
+
     /// Create the initial dump expected by the client.
     fn create_initial_dump(&self) -> impl Stream<Item = Result<VideohubMessage>> + use<'_, S> {
         try_stream! {
+            let state = self.gather_state().await?;
+            for msg in self.chunk(build_prelude_for(&state, self.profile)) {
+                yield msg;
+            }
+        }
+    }
+
+    /// Create the state part of the initial dump, also sent once the router comes back.
+    fn create_state_dump(&self) -> impl Stream<Item = Result<VideohubMessage>> + use<'_, S> {
+        try_stream! {
+            for msg in self.chunk(build_state_dump(&self.gather_state().await?)) {
+                yield msg;
+            }
+        }
+    }
 
-            // 1) Say hello, send some version that should be appropriate to what we're doing.
-            yield VideohubMessage::Preamble(Preamble {
-                version: "2.7".into(),
-            });
+    /// Collect what the dumps announce, as seen by this session.
+    async fn gather_state(&self) -> Result<VideohubState> {
+        let mut state = VideohubState {
+            version: self.profile.version().into(),
+            ..Default::default()
+        };
 
-            // 2) Identify as a VIDEOHUB device.
-            let mut di = DeviceInfo::default();
-            let mut output_count = 0;
-            let alive = self.router.is_alive().await?;
-            di.present = Some(if alive { Present::Yes } else { Present::No });
-            if alive {
-                let si = self.router.get_router_info().await?;
-                di.model_name = si.model;
-                di.friendly_name = si.name;
+        let alive = self.router.is_alive().await?;
+        state.device.present = Some(if alive { Present::Yes } else { Present::No });
+        if !alive {
+            return Ok(state);
+        }
 
-                let mi = self.router.get_matrix_info(self.index).await?;
-                output_count = mi.output_count;
-                di.video_inputs = Some(mi.input_count);
-                di.video_outputs = Some(output_count);
+        // Identify as a VIDEOHUB device.
+        let (si, mi) = tokio::try_join!(
+            self.router.get_router_info(),
+            self.router.get_matrix_info(self.index)
+        )?;
+        state.device.model_name = si.model;
+        // Each session shows a single matrix, which may well have a name of its own.
+        state.device.friendly_name = mi.name.clone().or(si.name);
+        state.device.video_inputs = Some(mi.input_count);
+        state.device.video_outputs = Some(mi.output_count);
+        // Fill in the connection span, see [Self::handle_connection].
+        let span = tracing::Span::current();
+        span.record("router.input_count", mi.input_count);
+        span.record("router.output_count", mi.output_count);
 
-                // TODO: Is sending more fields necessary?
+        // Ask for everything at once, the router may take a while for each.
+        // Sections the router has no support for are left out without asking.
+        let caps = self.router.capabilities();
+        let locks = self.gen_locks_for(mi.output_count);
+        // Frame Buffers, if there are any.
+        let frames = async {
+            if !caps.has_frame_buffers || mi.frame_count == 0 {
+                return Ok(None);
             }
-            yield VideohubMessage::DeviceInfo(di);
-
-            if alive {
-                // 3) Input Labels
-                yield self.gen_inputlabels().await?;
+            tokio::try_join!(
+                self.gen_framelabels(),
+                self.gen_framerouting(),
+                self.gen_router_locks(LockTarget::FrameBuffer)
+            )
+            .map(Some)
+        };
+        let (input_labels, output_labels, locks, routing, frames, pu_locks) = tokio::try_join!(
+            self.gen_inputlabels(),
+            self.gen_outputlabels(),
+            locks,
+            // The juicy bits!
+            self.gen_routing_bounded(),
+            frames,
+            async {
+                if !caps.has_processing_units {
+                    return Ok(vec![]);
+                }
+                self.router.get_processing_unit_locks(self.index).await
+            }
+        )?;
 
-                // 4) Output Labels
-                yield self.gen_outputlabels().await?;
+        // The dump's order doesn't depend on which answer came first.
+        state.apply(&input_labels);
+        state.apply(&output_labels);
+        state.apply(&locks);
+        state.apply(&routing);
+        if let Some((frame_labels, frame_routing, frame_locks)) = frames {
+            state.frame_count = mi.frame_count;
+            state.apply(&frame_labels);
+            state.apply(&frame_routing);
+            state.apply(&frame_locks);
+        }
 
-                // 5) Output Locks - stub for now.
-                let mut locks = Vec::new();
-                for id in 0..output_count {
-                    locks.push(Lock {
-                        id,
-                        state: LockState::Unlocked,
-                    })
-                }
-                // 6) Video Output Routing - the juicy bits!
-                yield self.gen_routing().await?;
-           }
-            // 7) That's all!
-            yield VideohubMessage::EndPrelude;
+        // Processing Units, likewise.
+        if !pu_locks.is_empty() {
+            state.device.video_processing_units = Some(pu_locks.len() as u32);
+            state.apply(
+                &self
+                    .router_lock_view(LockTarget::ProcessingUnit, pu_locks)
+                    .await,
+            );
         }
+        Ok(state)
     }
 
     /// Generate InputLabels Message
     async fn gen_inputlabels(&self) -> Result<VideohubMessage> {
-        let mut input_labels = self.router.get_input_labels(self.index).await?;
-        input_labels.sort_by(|a, b| a.id.cmp(&b.id)); // Enforce 0 to X
-        return Ok(VideohubMessage::InputLabels(
-            input_labels.into_iter().map(|l| l.into()).collect(),
-        ));
+        let input_labels = self.router.get_input_labels(self.index).await?;
+        Ok(VideohubMessage::InputLabels(canonical_labels(input_labels)))
     }
 
     /// Generate OutputLabels Message
     async fn gen_outputlabels(&self) -> Result<VideohubMessage> {
-        let mut output_labels = self.router.get_output_labels(self.index).await?;
-        output_labels.sort_by(|a, b| a.id.cmp(&b.id)); // Enforce 0 to X
-        return Ok(VideohubMessage::OutputLabels(
-            output_labels.into_iter().map(|l| l.into()).collect(),
-        ));
+        let output_labels = self.router.get_output_labels(self.index).await?;
+        Ok(VideohubMessage::OutputLabels(canonical_labels(
+            output_labels,
+        )))
     }
 
     /// Generate VideoOutputRouting Message
     async fn gen_routing(&self) -> Result<VideohubMessage> {
-        let mut routes = self.router.get_routes(self.index).await?;
-        routes.sort_by(|a, b| a.to_output.cmp(&b.to_output)); // Enforce 0 to X
-        return Ok(VideohubMessage::VideoOutputRouting(
-            routes.into_iter().map(|r| r.into()).collect(),
-        ));
+        let routes = self.router.get_routes(self.index).await?;
+        Ok(VideohubMessage::VideoOutputRouting(canonical_routes(
+            self.unpark(routes),
+        )))
+    }
+
+    /// Generate VideoOutputRouting Message within [Self::with_reply_timeout].
+    ///
+    /// If the router takes longer, an empty block is returned right away and the full one
+    /// gets sent to the client unsolicited once available, like a route change would be.
+    async fn gen_routing_bounded(&self) -> Result<VideohubMessage> {
+        let fe = self.clone();
+        let mut fetch = tokio::spawn(async move { fe.gen_routing().await });
+        match tokio::time::timeout(self.reply_timeout, &mut fetch).await {
+            Ok(res) => res?,
+            Err(_) => {
+                debug!(timeout = ?self.reply_timeout, "Routing not available in time, deferring");
+                match self.deferred_tx.clone() {
+                    Some(tx) => {
+                        tokio::spawn(async move {
+                            match fetch.await {
+                                Ok(Ok(msg)) => {
+                                    let _ = tx.send(msg);
+                                }
+                                Ok(Err(e)) => debug!(error = ?e, "Deferred routing fetch failed"),
+                                Err(e) => debug!(error = ?e, "Deferred routing fetch panicked"),
+                            }
+                        });
+                    }
+                    // Nobody to complete the reply for.
+                    None => fetch.abort(),
+                }
+                Ok(VideohubMessage::VideoOutputRouting(vec![]))
+            }
+        }
+    }
+
+    /// Generate VideoOutputLocks Message, as seen by this session
+    async fn gen_locks(&self) -> Result<VideohubMessage> {
+        let mi = self.router.get_matrix_info(self.index).await?;
+        self.gen_locks_for(mi.output_count).await
+    }
+
+    /// Locks held at the router if it has output locks,
+    /// otherwise the ones taken through this frontend.
+    async fn gen_locks_for(&self, output_count: u32) -> Result<VideohubMessage> {
+        let held = self.router.get_locks(self.index).await?;
+        if !held.is_empty() {
+            return Ok(self.router_lock_view(LockTarget::VideoOutput, held).await);
+        }
+        Ok(self.gen_session_locks(output_count).await)
+    }
+
+    /// Locks taken through this frontend only.
+    async fn gen_session_locks(&self, output_count: u32) -> VideohubMessage {
+        let st = self.state.lock().await;
+        VideohubMessage::VideoOutputLocks(
+            (0..output_count)
+                .map(|id| Lock {
+                    id,
+                    state: st
+                        .locks
+                        .state_for(LockTarget::VideoOutput, id, self.session),
+                })
+                .collect(),
+        )
+    }
+
+    /// Whether the router holds output locks, rather than leaving them to this frontend.
+    async fn router_has_output_locks(&self) -> bool {
+        self.router
+            .get_locks(self.index)
+            .await
+            .is_ok_and(|l| !l.is_empty())
+    }
+
+    /// Locks held at the router, from the point of view of this session.
+    async fn get_router_locks(&self, target: LockTarget) -> Result<Vec<RouterLock>> {
+        match target {
+            LockTarget::FrameBuffer => self.router.get_frame_locks(self.index).await,
+            LockTarget::ProcessingUnit => self.router.get_processing_unit_locks(self.index).await,
+            LockTarget::VideoOutput => self.router.get_locks(self.index).await,
+        }
+    }
+
+    async fn update_router_locks(
+        &self,
+        target: LockTarget,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        match target {
+            LockTarget::FrameBuffer => self.router.update_frame_locks(self.index, changes).await,
+            LockTarget::ProcessingUnit => {
+                self.router
+                    .update_processing_unit_locks(self.index, changes)
+                    .await
+            }
+            LockTarget::VideoOutput => self.router.update_locks(self.index, changes).await,
+        }
+    }
+
+    /// Translate router-held locks to the view of this session.
+    ///
+    /// Locks taken outside of this frontend show up as locked by someone else.
+    async fn router_lock_view(
+        &self,
+        target: LockTarget,
+        mut locks: Vec<RouterLock>,
+    ) -> VideohubMessage {
+        locks.sort_by_key(|l| l.id); // Enforce 0 to X
+        let mut st = self.state.lock().await;
+        st.locks.reconcile(target, &locks);
+        let locks = locks
+            .into_iter()
+            .map(|l| Lock {
+                id: l.id,
+                state: match st.locks.state_for(target, l.id, self.session) {
+                    LockState::Unlocked if l.locked => LockState::Locked,
+                    state => state,
+                },
+            })
+            .collect();
+        match target {
+            LockTarget::VideoOutput => VideohubMessage::VideoOutputLocks(locks),
+            LockTarget::ProcessingUnit => VideohubMessage::ProcessingUnitLocks(locks),
+            LockTarget::FrameBuffer => VideohubMessage::FrameBufferLocks(locks),
+        }
+    }
+
+    /// Generate VideoOutputLocks, FrameBufferLocks or ProcessingUnitLocks Message
+    async fn gen_router_locks(&self, target: LockTarget) -> Result<VideohubMessage> {
+        let locks = self.get_router_locks(target).await?;
+        Ok(self.router_lock_view(target, locks).await)
+    }
+
+    /// Handle lock requests for locks held at the router.
+    async fn request_router_locks(
+        &self,
+        target: LockTarget,
+        locks: Vec<Lock>,
+    ) -> Result<VideohubMessage> {
+        let current = self.get_router_locks(target).await?;
+        let count = current.len() as u32;
+        let mut st = self.state.lock().await;
+        // Locks taken outside of this frontend can only be forced.
+        let held_elsewhere = locks.iter().any(|l| {
+            l.state != LockState::Force
+                && current.iter().any(|c| c.id == l.id && c.locked)
+                && st.locks.state_for(target, l.id, self.session) == LockState::Unlocked
+        });
+        if held_elsewhere || !st.locks.acceptable(self.session, target, &locks, count) {
+            return Ok(VideohubMessage::NAK);
+        }
+        let changes = locks
+            .iter()
+            .map(|l| RouterLock {
+                id: l.id,
+                locked: l.state != LockState::Unlocked,
+            })
+            .collect();
+        // Keep holding the registry, so nobody can take them over in between.
+        if let Err(e) = self.update_router_locks(target, changes).await {
+            debug!(?target, error = ?e, "Router refused locks");
+            return Ok(VideohubMessage::NAK);
+        }
+        st.locks.apply(self.session, target, &locks, count);
+        Ok(VideohubMessage::ACK)
+    }
+
+    /// Generate FrameLabels Message
+    async fn gen_framelabels(&self) -> Result<VideohubMessage> {
+        let frame_labels = self.router.get_frame_labels(self.index).await?;
+        Ok(VideohubMessage::FrameLabels(canonical_labels(frame_labels)))
+    }
+
+    /// Generate FrameBufferRouting Message
+    async fn gen_framerouting(&self) -> Result<VideohubMessage> {
+        let routes = self.router.get_frame_routes(self.index).await?;
+        Ok(VideohubMessage::FrameBufferRouting(canonical_routes(
+            self.unpark(routes),
+        )))
     }
 
     /// Message handler: update state, optionally call router
-    async fn handle_message(&self, msg: VideohubMessage) -> Result<Option<VideohubMessage>> {
-        // TODO: handle PING locally, call self.router.get_routes() and such if needed
+    ///
+    /// `present` is whether the router was last seen connected, as tracked from its events.
+    /// The router refusing anything is no reason to drop the client, only failing to talk to
+    /// it is, so [RouterError]s are answered with NAK.
+    async fn handle_message(
+        &self,
+        msg: VideohubMessage,
+        present: bool,
+    ) -> Result<Option<VideohubMessage>> {
+        match self.dispatch_message(msg, present).await {
+            Err(e) if e.downcast_ref::<RouterError>().is_some() => {
+                debug!(error = ?e, "Router refused request");
+                Ok(Some(VideohubMessage::NAK))
+            }
+            res => res,
+        }
+    }
+
+    /// [Self::handle_message], passing router errors on.
+    async fn dispatch_message(
+        &self,
+        msg: VideohubMessage,
+        present: bool,
+    ) -> Result<Option<VideohubMessage>> {
+        // Status is the hub's to report, there is nothing to do with a client's.
+        if msg.is_status_message() {
+            debug!(msg = %msg.summary(), "Ignoring status message");
+            return Ok(None);
+        }
+        // Nothing but pings can be served without a router.
+        if msg != VideohubMessage::Ping && !present {
+            return Ok(Some(VideohubMessage::NAK));
+        }
+        if msg != VideohubMessage::Ping {
+            let mi = self.router.get_matrix_info(self.index).await?;
+            let device = DeviceInfo {
+                video_inputs: Some(mi.input_count),
+                video_outputs: Some(mi.output_count),
+                ..Default::default()
+            };
+            if let Err(errors) = msg.validate(&device) {
+                debug!(?errors, "Refusing out of range request");
+                return Ok(Some(VideohubMessage::NAK));
+            }
+            // Frame buffers aren't part of the device info, check them here.
+            let frame_ids: Vec<u32> = match &msg {
+                VideohubMessage::FrameLabels(v) => v.iter().map(|l| l.id).collect(),
+                VideohubMessage::FrameBufferRouting(v) => v.iter().map(|r| r.to_output).collect(),
+                VideohubMessage::FrameBufferLocks(v) => v.iter().map(|l| l.id).collect(),
+                _ => Vec::new(),
+            };
+            if frame_ids.iter().any(|&id| id >= mi.frame_count) {
+                debug!(?frame_ids, "Refusing out of range frame buffer request");
+                return Ok(Some(VideohubMessage::NAK));
+            }
+        }
         Ok(match msg {
+            // Older hubs don't know pings, and don't answer them either.
+            VideohubMessage::Ping if !self.profile.has_ping() => None,
             VideohubMessage::Ping => Some(VideohubMessage::ACK),
-            VideohubMessage::InputLabels(labels) => {
-                if labels.is_empty() {
-                    Some(self.gen_inputlabels().await?)
+            VideohubMessage::InputLabels(v) if v.is_empty() => Some(self.gen_inputlabels().await?),
+            VideohubMessage::OutputLabels(v) if v.is_empty() => {
+                Some(self.gen_outputlabels().await?)
+            }
+            VideohubMessage::VideoOutputRouting(v) if v.is_empty() => {
+                Some(self.gen_routing_bounded().await?)
+            }
+            VideohubMessage::FrameLabels(v) if v.is_empty() => Some(self.gen_framelabels().await?),
+            VideohubMessage::FrameBufferRouting(v) if v.is_empty() => {
+                Some(self.gen_framerouting().await?)
+            }
+            VideohubMessage::VideoOutputLocks(locks) => {
+                if locks.is_empty() {
+                    Some(self.gen_locks().await?)
+                } else if self.router_has_output_locks().await {
+                    // Other sessions learn about the change from the router's event.
+                    Some(
+                        self.request_router_locks(LockTarget::VideoOutput, locks)
+                            .await?,
+                    )
                 } else {
-                    let changed = labels.into_iter().map(|l| l.into()).collect();
-                    self.router.update_input_labels(self.index, changed).await?;
-                    Some(VideohubMessage::ACK)
+                    let mi = self.router.get_matrix_info(self.index).await?;
+                    let mut st = self.state.lock().await;
+                    // Outputs being routed by others keep their owners until that's done.
+                    let accepted = !self
+                        .reservations
+                        .held_by_other(locks.iter().map(|l| l.id), self.session)
+                        && st.locks.apply(
+                            self.session,
+                            LockTarget::VideoOutput,
+                            &locks,
+                            mi.output_count,
+                        );
+                    drop(st);
+                    if accepted {
+                        let _ = self.locks_tx.send(());
+                        Some(VideohubMessage::ACK)
+                    } else {
+                        Some(VideohubMessage::NAK)
+                    }
                 }
             }
-            VideohubMessage::OutputLabels(labels) => {
-                if labels.is_empty() {
-                    Some(self.gen_outputlabels().await?)
+            VideohubMessage::FrameBufferLocks(locks) => {
+                if locks.is_empty() {
+                    Some(self.gen_router_locks(LockTarget::FrameBuffer).await?)
                 } else {
-                    let changed = labels.into_iter().map(|l| l.into()).collect();
-                    self.router
-                        .update_output_labels(self.index, changed)
-                        .await?;
-                    Some(VideohubMessage::ACK)
+                    Some(
+                        self.request_router_locks(LockTarget::FrameBuffer, locks)
+                            .await?,
+                    )
                 }
             }
-            VideohubMessage::VideoOutputRouting(routes) => {
-                if routes.is_empty() {
-                    Some(self.gen_routing().await?)
+            VideohubMessage::ProcessingUnitLocks(locks) => {
+                if locks.is_empty() {
+                    Some(self.gen_router_locks(LockTarget::ProcessingUnit).await?)
                 } else {
-                    let changed = routes.into_iter().map(|r| r.into()).collect();
-                    self.router.update_routes(self.index, changed).await?;
-                    Some(VideohubMessage::ACK)
+                    Some(
+                        self.request_router_locks(LockTarget::ProcessingUnit, locks)
+                            .await?,
+                    )
                 }
             }
-            _ => Some(VideohubMessage::NAK),
+            msg => match RouterEvent::try_from(msg) {
+                Ok(change) => Some(self.apply_change(change).await?),
+                // Control messages for things the router doesn't have, requests we can't
+                // answer and unknown blocks.
+                Err(_) => Some(VideohubMessage::NAK),
+            },
         })
     }
 
+    /// Apply a change requested by a client, as converted from its message.
+    async fn apply_change(&self, change: RouterEvent) -> Result<VideohubMessage> {
+        match change {
+            RouterEvent::InputLabelUpdate(_, labels) => {
+                let changed = self.label_changes(labels);
+                self.router.update_input_labels(self.index, changed).await?;
+            }
+            RouterEvent::OutputLabelUpdate(_, labels) => {
+                let changed = self.label_changes(labels);
+                self.router
+                    .update_output_labels(self.index, changed)
+                    .await?;
+            }
+            RouterEvent::RouteUpdate(_, changed) => {
+                let held = self.router.get_locks(self.index).await?;
+                let outputs: Vec<u32> = changed.iter().map(|p| p.to_output).collect();
+                let _reservation = loop {
+                    let released = self.reservations.released.notified();
+                    {
+                        let st = self.state.lock().await;
+                        // Locks taken outside of this frontend only show up at the router.
+                        if outputs.iter().any(|&o| {
+                            match st.locks.state_for(LockTarget::VideoOutput, o, self.session) {
+                                LockState::Locked => true,
+                                LockState::Unlocked => held.iter().any(|l| l.id == o && l.locked),
+                                _ => false,
+                            }
+                        }) {
+                            return Ok(VideohubMessage::NAK);
+                        }
+                        // Nobody can take the locks over until the router is done.
+                        if let Some(r) = self.reservations.try_reserve(&outputs, self.session) {
+                            break r;
+                        }
+                    }
+                    // Another change to the same outputs is in flight, check again after it.
+                    released.await;
+                };
+                self.router.update_routes(self.index, changed).await?;
+            }
+            RouterEvent::FrameLabelUpdate(_, labels) => {
+                let changed = self.label_changes(labels);
+                self.router.update_frame_labels(self.index, changed).await?;
+            }
+            RouterEvent::FrameRouteUpdate(_, changed) => {
+                self.router.update_frame_routes(self.index, changed).await?;
+            }
+            _ => return Ok(VideohubMessage::NAK),
+        }
+        Ok(VideohubMessage::ACK)
+    }
+
     /// Event handler: update state, produce protocol message if desired
     /// Luckily, we don't need to filter out changes we did on our own, cause the Videohub protocol
     /// does the same on original devices.
     async fn handle_event(&self, event: RouterEvent) -> Result<Option<VideohubMessage>> {
-        // TODO: translate stuff like route-change events
         Ok(match event {
-            RouterEvent::InputLabelUpdate(idx, mut updates) => {
-                if idx != self.index {
-                    None
-                } else {
-                    updates.sort_by(|a, b| a.id.cmp(&b.id)); // Enforce 0 to X
-                    Some(VideohubMessage::InputLabels(
-                        updates.into_iter().map(|r| r.into()).collect(),
-                    ))
-                }
+            RouterEvent::LockUpdate(idx, locks) if idx == self.index => {
+                Some(self.router_lock_view(LockTarget::VideoOutput, locks).await)
             }
-            RouterEvent::OutputLabelUpdate(idx, mut updates) => {
-                if idx != self.index {
-                    None
-                } else {
-                    updates.sort_by(|a, b| a.id.cmp(&b.id)); // Enforce 0 to X
-                    Some(VideohubMessage::InputLabels(
-                        updates.into_iter().map(|r| r.into()).collect(),
-                    ))
-                }
+            RouterEvent::FrameLockUpdate(idx, locks) if idx == self.index => {
+                Some(self.router_lock_view(LockTarget::FrameBuffer, locks).await)
             }
-            RouterEvent::RouteUpdate(idx, mut updates) => {
-                if idx != self.index {
-                    None
-                } else {
-                    updates.sort_by(|a, b| a.to_output.cmp(&b.to_output)); // Enforce 0 to X
-                    Some(VideohubMessage::VideoOutputRouting(
-                        updates.into_iter().map(|r| r.into()).collect(),
-                    ))
-                }
+            RouterEvent::ProcessingUnitLockUpdate(idx, locks) if idx == self.index => Some(
+                self.router_lock_view(LockTarget::ProcessingUnit, locks)
+                    .await,
+            ),
+            // Alarms are the whole hub's, whichever matrix is served.
+            RouterEvent::AlarmUpdate(_) => VideohubMessage::try_from(event).ok(),
+            // Only the changed entries go out, as hardware hubs do. Snapshots follow
+            // Connected, which has everything dumped already.
+            RouterEvent::RouteUpdate(idx, routes) if idx == self.index => {
+                VideohubMessage::try_from(RouterEvent::RouteUpdate(idx, self.unpark(routes))).ok()
+            }
+            RouterEvent::FrameRouteUpdate(idx, routes) if idx == self.index => {
+                let routes = self.unpark(routes);
+                VideohubMessage::try_from(RouterEvent::FrameRouteUpdate(idx, routes)).ok()
+            }
+            RouterEvent::InputLabelUpdate(idx, _)
+            | RouterEvent::OutputLabelUpdate(idx, _)
+            | RouterEvent::FrameLabelUpdate(idx, _)
+                if idx == self.index =>
+            {
+                VideohubMessage::try_from(event).ok()
             }
             _ => None,
         })
@@ -290,7 +1222,19 @@ where
             router: Arc::clone(&self.router),
             index: self.index,
             state: self.state.clone(),
-            peer: self.peer.clone(),
+            reservations: self.reservations.clone(),
+            locks_tx: self.locks_tx.clone(),
+            peer: self.peer,
+            session: self.session,
+            reply_timeout: self.reply_timeout,
+            deferred_tx: self.deferred_tx.clone(),
+            label_limit: self.label_limit,
+            profile: self.profile,
+            max_block_lines: self.max_block_lines,
+            park_input: self.park_input,
+            connections: self.connections.clone(),
+            on_connect: self.on_connect,
+            on_disconnect: self.on_disconnect,
         }
     }
 }
@@ -298,9 +1242,12 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::matrix::{DummyRouter, RouterPatch};
+    use crate::matrix::{
+        DummyRouter, LockingRouter, RateLimit, RateLimitPolicy, RateLimitedRouter, ReadOnlyRouter,
+    };
+    use tokio::net::TcpStream;
     use tokio_stream::StreamExt;
-    use videohub::{Label, VideohubMessage};
+    use videohub::{Label, Route, VideohubMessage};
 
     const IDX: u32 = 0;
 
@@ -320,36 +1267,369 @@ mod tests {
         assert!(matches!(items[1], VideohubMessage::DeviceInfo(..)));
         assert!(matches!(items[2], VideohubMessage::InputLabels(..)));
         assert!(matches!(items[3], VideohubMessage::OutputLabels(..)));
-        assert!(matches!(items[4], VideohubMessage::VideoOutputRouting(..)));
-        assert_eq!(items[5], VideohubMessage::EndPrelude);
+        assert!(matches!(items[4], VideohubMessage::VideoOutputLocks(..)));
+        assert!(matches!(items[5], VideohubMessage::VideoOutputRouting(..)));
+        assert_eq!(items[6], VideohubMessage::EndPrelude);
     }
 
-    #[tokio::test]
-    async fn ping_and_label_update() {
-        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
-        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+    /// A [DummyRouter] taking `latency` to answer label and routing requests,
+    /// listing everything backwards if `reversed`.
+    #[derive(Clone)]
+    struct LatencyRouter {
+        inner: Arc<DummyRouter>,
+        latency: Duration,
+        reversed: bool,
+    }
 
-        // Ping!
-        let resp = frontend
-            .handle_message(VideohubMessage::Ping)
-            .await
-            .unwrap();
-        assert_eq!(resp, Some(VideohubMessage::ACK));
+    impl LatencyRouter {
+        fn order<T>(&self, mut v: Vec<T>) -> Vec<T> {
+            if self.reversed {
+                v.reverse();
+            }
+            v
+        }
+    }
 
-        // Request labels.
-        let resp = frontend
-            .handle_message(VideohubMessage::InputLabels(vec![]))
-            .await
-            .unwrap();
-        assert!(matches!(resp, Some(VideohubMessage::InputLabels(_))));
+    impl MatrixRouter for LatencyRouter {
+        async fn is_alive(&self) -> Result<bool> {
+            self.inner.is_alive().await
+        }
+        async fn get_router_info(&self) -> Result<crate::matrix::RouterInfo> {
+            self.inner.get_router_info().await
+        }
+        async fn get_matrix_info(&self, index: u32) -> Result<crate::matrix::RouterMatrixInfo> {
+            self.inner.get_matrix_info(index).await
+        }
+        async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+            tokio::time::sleep(self.latency).await;
+            Ok(self.order(self.inner.get_input_labels(index).await?))
+        }
+        async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+            tokio::time::sleep(self.latency).await;
+            Ok(self.order(self.inner.get_output_labels(index).await?))
+        }
+        async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+            self.inner.update_input_labels(index, changed).await
+        }
+        async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+            self.inner.update_output_labels(index, changed).await
+        }
+        async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+            tokio::time::sleep(self.latency).await;
+            Ok(self.order(self.inner.get_routes(index).await?))
+        }
+        async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+            tokio::time::sleep(self.latency).await;
+            self.inner.update_routes(index, changes).await
+        }
+        async fn event_stream<'a>(
+            &'a self,
+        ) -> Result<futures_core::stream::BoxStream<'a, RouterEvent>> {
+            self.inner.event_stream().await
+        }
+    }
 
-        // Update one label.
+    #[tokio::test]
+    async fn initial_dump_concurrent() {
+        let router = Arc::new(LatencyRouter {
+            inner: Arc::new(DummyRouter::with_config(1, 2, 2)),
+            latency: Duration::from_millis(50),
+            reversed: false,
+        });
+        let frontend = VideohubFrontend::new(router, IDX);
+        let started = std::time::Instant::now();
+        let dump = frontend.create_initial_dump();
+        pin_mut!(dump);
+        let mut items = Vec::new();
+        while let Some(item) = dump.next().await {
+            items.push(item.unwrap());
+        }
+        // One after the other, this would take 150ms.
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        assert!(matches!(items[2], VideohubMessage::InputLabels(..)));
+        assert!(matches!(items[3], VideohubMessage::OutputLabels(..)));
+        assert!(matches!(items[4], VideohubMessage::VideoOutputLocks(..)));
+        match &items[5] {
+            VideohubMessage::VideoOutputRouting(rs) => assert_eq!(rs.len(), 2),
+            m => panic!("expected VideoOutputRouting, got {:?}", m),
+        }
+    }
+
+    #[tokio::test]
+    async fn initial_dump_slow_router() {
+        let latency = Duration::from_millis(20);
+        let router = Arc::new(DummyRouter::with_config(1, 2, 2).with_latency(latency));
+        let frontend = VideohubFrontend::new(router, IDX);
+        let started = std::time::Instant::now();
+        let dump = frontend.create_initial_dump();
+        pin_mut!(dump);
+        while let Some(item) = dump.next().await {
+            item.unwrap();
+        }
+        // Liveness, then the router and matrix info, then all the fetches at once:
+        // each round trip takes less than twice the latency, where asking for
+        // everything in turn would take eight.
+        assert!(started.elapsed() < 3 * 2 * latency);
+    }
+
+    #[tokio::test]
+    async fn dumps_ascending() {
+        let router = Arc::new(LatencyRouter {
+            inner: Arc::new(DummyRouter::with_config(1, 4, 3)),
+            latency: Duration::ZERO,
+            reversed: true,
+        });
+        let frontend = VideohubFrontend::new(router, IDX);
+        fn check(msg: &VideohubMessage) {
+            let ids: Vec<u32> = match msg {
+                VideohubMessage::InputLabels(v) | VideohubMessage::OutputLabels(v) => {
+                    v.iter().map(|l| l.id).collect()
+                }
+                VideohubMessage::VideoOutputRouting(v) => v.iter().map(|r| r.to_output).collect(),
+                VideohubMessage::VideoOutputLocks(v) => v.iter().map(|l| l.id).collect(),
+                _ => return,
+            };
+            assert!(ids.windows(2).all(|w| w[0] < w[1]), "{:?}", msg);
+        }
+
+        let dump = frontend.create_initial_dump();
+        pin_mut!(dump);
+        while let Some(item) = dump.next().await {
+            check(&item.unwrap());
+        }
+        for req in [
+            VideohubMessage::InputLabels(vec![]),
+            VideohubMessage::OutputLabels(vec![]),
+            VideohubMessage::VideoOutputRouting(vec![]),
+        ] {
+            let reply = frontend.handle_message(req, true).await.unwrap().unwrap();
+            check(&reply);
+        }
+
+        let labels = vec![(2, "C"), (0, "A"), (1, "B")]
+            .into_iter()
+            .map(|l| RouterLabel::from(Label::from(l)))
+            .collect();
+        let reply = frontend
+            .handle_event(RouterEvent::OutputLabelUpdate(IDX, labels))
+            .await
+            .unwrap()
+            .unwrap();
+        check(&reply);
+        let routes = vec![(2, 0), (0, 1)]
+            .into_iter()
+            .map(|r| RouterPatch::from(Route::from(r)))
+            .collect();
+        let reply = frontend
+            .handle_event(RouterEvent::RouteUpdate(IDX, routes))
+            .await
+            .unwrap()
+            .unwrap();
+        check(&reply);
+    }
+
+    #[tokio::test]
+    async fn status_ignored_unknown_control_refused() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        let frontend = VideohubFrontend::new(dummy, IDX);
+        let port = HardwarePort {
+            id: 0,
+            port_type: HardwarePortType::BNC,
+        };
+        for msg in [
+            VideohubMessage::DeviceInfo(DeviceInfo::default()),
+            VideohubMessage::VideoInputStatus(vec![port]),
+            VideohubMessage::EndPrelude,
+        ] {
+            assert_eq!(frontend.handle_message(msg, true).await.unwrap(), None);
+        }
+        let serial = VideohubMessage::SerialPortRouting(vec![Route {
+            from_input: 0,
+            to_output: 0,
+        }]);
+        assert!(serial.is_control_message());
+        assert_eq!(
+            frontend.handle_message(serial, true).await.unwrap(),
+            Some(VideohubMessage::NAK)
+        );
+    }
+
+    #[tokio::test]
+    async fn routes_level_zero() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2).with_level_count(2));
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+        let reply = frontend
+            .handle_message(
+                VideohubMessage::VideoOutputRouting(vec![Route {
+                    from_input: 1,
+                    to_output: 0,
+                }]),
+                true,
+            )
+            .await
+            .unwrap();
+        assert_eq!(reply, Some(VideohubMessage::ACK));
+        assert_eq!(dummy.get_routes(IDX).await.unwrap()[0].from_input, Some(1));
+        let audio = dummy.get_level_routes(IDX, 1).await.unwrap();
+        assert_eq!(audio[0].from_input, Some(0));
+
+        let ev = RouterEvent::LevelRouteUpdate(IDX, 1, audio);
+        assert_eq!(frontend.handle_event(ev).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn initial_dump_old_profile() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        let frontend = VideohubFrontend::new(dummy, IDX).with_profile(ProtocolProfile::V2_4);
+        let dump = frontend.create_initial_dump();
+        pin_mut!(dump);
+        let mut items = Vec::new();
+        while let Some(item) = dump.next().await {
+            items.push(item.unwrap());
+        }
+
+        assert_eq!(
+            items[0],
+            VideohubMessage::Preamble(Preamble {
+                version: "2.4".into()
+            })
+        );
+        assert_eq!(items.len(), 6);
+        assert!(!items.iter().any(|m| matches!(
+            m,
+            VideohubMessage::EndPrelude | VideohubMessage::Configuration(_)
+        )));
+
+        // Pings go unanswered, like on an old hub.
+        let resp = frontend
+            .handle_message(VideohubMessage::Ping, true)
+            .await
+            .unwrap();
+        assert_eq!(resp, None);
+    }
+
+    #[tokio::test]
+    async fn initial_dump_with_frames() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2).with_frame_count(2));
+        let frontend = VideohubFrontend::new(dummy, IDX);
+        let dump = frontend.create_initial_dump();
+        pin_mut!(dump);
+        let mut items = Vec::new();
+        while let Some(item) = dump.next().await {
+            items.push(item.unwrap());
+        }
+
+        // Canonical order: all labels, all locks, then all routing.
+        match &items[4] {
+            VideohubMessage::FrameLabels(ls) => assert_eq!(ls.len(), 2),
+            m => panic!("expected FrameLabels, got {:?}", m),
+        }
+        assert!(matches!(items[5], VideohubMessage::VideoOutputLocks(..)));
+        match &items[6] {
+            VideohubMessage::FrameBufferLocks(ls) => assert_eq!(ls.len(), 2),
+            m => panic!("expected FrameBufferLocks, got {:?}", m),
+        }
+        assert!(matches!(items[7], VideohubMessage::VideoOutputRouting(..)));
+        match &items[8] {
+            VideohubMessage::FrameBufferRouting(rs) => assert_eq!(rs.len(), 2),
+            m => panic!("expected FrameBufferRouting, got {:?}", m),
+        }
+        assert_eq!(items[9], VideohubMessage::EndPrelude);
+    }
+
+    #[tokio::test]
+    async fn initial_dump_skips_unsupported() {
+        // Frame buffers in its matrix info, but not among its capabilities.
+        let router = Arc::new(LatencyRouter {
+            inner: Arc::new(DummyRouter::with_config(1, 2, 2).with_frame_count(2)),
+            latency: Duration::ZERO,
+            reversed: false,
+        });
+        assert!(!router.capabilities().has_frame_buffers);
+        let frontend = VideohubFrontend::new(router, IDX);
+        let dump = frontend.create_initial_dump();
+        pin_mut!(dump);
+        let mut items = Vec::new();
+        while let Some(item) = dump.next().await {
+            items.push(item.unwrap());
+        }
+
+        assert_eq!(items.len(), 7);
+        assert!(!items.iter().any(|m| matches!(
+            m,
+            VideohubMessage::FrameLabels(_)
+                | VideohubMessage::FrameBufferLocks(_)
+                | VideohubMessage::FrameBufferRouting(_)
+        )));
+    }
+
+    #[tokio::test]
+    async fn frame_route_update() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2).with_frame_count(2));
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+
+        let route = Route {
+            from_input: 1,
+            to_output: 1,
+        };
+        let resp = frontend
+            .handle_message(VideohubMessage::FrameBufferRouting(vec![route]), true)
+            .await
+            .unwrap();
+        assert_eq!(resp, Some(VideohubMessage::ACK));
+        let actual = dummy.get_frame_routes(IDX).await.unwrap();
+        assert!(actual.contains(&route.into()));
+
+        let resp = frontend
+            .handle_message(VideohubMessage::FrameBufferRouting(vec![]), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            resp,
+            Some(VideohubMessage::FrameBufferRouting(vec![
+                Route {
+                    from_input: 0,
+                    to_output: 0,
+                },
+                route,
+            ]))
+        );
+
+        let ev = RouterEvent::FrameRouteUpdate(IDX, actual);
+        let maybe = frontend.handle_event(ev).await.unwrap();
+        assert!(matches!(
+            maybe,
+            Some(VideohubMessage::FrameBufferRouting(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn ping_and_label_update() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+
+        // Ping!
+        let resp = frontend
+            .handle_message(VideohubMessage::Ping, true)
+            .await
+            .unwrap();
+        assert_eq!(resp, Some(VideohubMessage::ACK));
+
+        // Request labels.
+        let resp = frontend
+            .handle_message(VideohubMessage::InputLabels(vec![]), true)
+            .await
+            .unwrap();
+        assert!(matches!(resp, Some(VideohubMessage::InputLabels(_))));
+
+        // Update one label.
         let test_label = Label {
             id: 1,
             name: "Test Label".to_owned(),
         };
         let resp = frontend
-            .handle_message(VideohubMessage::InputLabels(vec![test_label.clone()]))
+            .handle_message(VideohubMessage::InputLabels(vec![test_label.clone()]), true)
             .await
             .unwrap();
         assert_eq!(resp, Some(VideohubMessage::ACK));
@@ -366,7 +1646,7 @@ mod tests {
 
         // Simulate a route update event.
         let patches = vec![RouterPatch {
-            from_input: 1,
+            from_input: Some(1),
             to_output: 0,
         }];
         let ev = RouterEvent::RouteUpdate(IDX, patches.clone());
@@ -380,4 +1660,1607 @@ mod tests {
             panic!("expected VideoOutputRouting");
         }
     }
+
+    #[tokio::test]
+    async fn forwards_changed_lines_only() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 288, 288));
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+
+        let socket = TcpStream::connect(addr).await.unwrap();
+        let mut client = Framed::new(socket, VideohubCodec::default());
+        next_matching(&mut client, |m| *m == VideohubMessage::EndPrelude).await;
+
+        let patch = RouterPatch {
+            from_input: Some(17),
+            to_output: 250,
+        };
+        dummy.update_routes(IDX, vec![patch]).await.unwrap();
+        match next_matching(&mut client, |_| true).await {
+            VideohubMessage::VideoOutputRouting(rs) => {
+                assert_eq!(rs, vec![patch.try_into().unwrap()])
+            }
+            m => panic!("expected VideoOutputRouting, got {:?}", m),
+        }
+
+        // Snapshots are no news to clients, the dump had all of it.
+        let routes = dummy.get_routes(IDX).await.unwrap();
+        dummy.push_event(RouterEvent::RouteSnapshot(IDX, routes));
+        dummy
+            .update_input_labels(
+                IDX,
+                vec![RouterLabel {
+                    id: 3,
+                    name: "Cam 4".into(),
+                }],
+            )
+            .await
+            .unwrap();
+        match next_matching(&mut client, |_| true).await {
+            VideohubMessage::InputLabels(ls) => assert_eq!(
+                ls,
+                vec![Label {
+                    id: 3,
+                    name: "Cam 4".into()
+                }]
+            ),
+            m => panic!("expected InputLabels, got {:?}", m),
+        }
+    }
+
+    /// Collect the initial dump of a frontend.
+    async fn collect_dump<S>(frontend: &VideohubFrontend<S>) -> Vec<VideohubMessage>
+    where
+        S: MatrixRouter + Send + Sync + Clone + 'static,
+    {
+        let dump = frontend.create_initial_dump();
+        pin_mut!(dump);
+        let mut items = Vec::new();
+        while let Some(item) = dump.next().await {
+            items.push(item.unwrap());
+        }
+        items
+    }
+
+    #[tokio::test]
+    async fn multi_matrix_dumps() {
+        // Matrix 0 has no outputs at all, matrix 1 is populated.
+        let dummy = Arc::new(
+            DummyRouter::with_matrices(&[(2, 0), (4, 3)])
+                .with_matrix_names(&["Studio A", "Studio B"]),
+        );
+        let fe0 = VideohubFrontend::new(Arc::clone(&dummy), 0);
+        let fe1 = VideohubFrontend::new(Arc::clone(&dummy), 1);
+
+        let dump0 = collect_dump(&fe0).await;
+        let dump1 = collect_dump(&fe1).await;
+        match (&dump0[1], &dump1[1]) {
+            (VideohubMessage::DeviceInfo(d0), VideohubMessage::DeviceInfo(d1)) => {
+                assert_eq!((d0.video_inputs, d0.video_outputs), (Some(2), Some(0)));
+                assert_eq!((d1.video_inputs, d1.video_outputs), (Some(4), Some(3)));
+                assert_eq!(d0.friendly_name.as_deref(), Some("Studio A"));
+                assert_eq!(d1.friendly_name.as_deref(), Some("Studio B"));
+            }
+            _ => panic!("expected DeviceInfo"),
+        }
+        assert_eq!(dump0[3], VideohubMessage::OutputLabels(vec![]));
+        assert_eq!(dump0[4], VideohubMessage::VideoOutputLocks(vec![]));
+        assert_eq!(dump0[5], VideohubMessage::VideoOutputRouting(vec![]));
+        match (&dump1[2], &dump1[3], &dump1[5]) {
+            (
+                VideohubMessage::InputLabels(i),
+                VideohubMessage::OutputLabels(o),
+                VideohubMessage::VideoOutputRouting(r),
+            ) => assert_eq!((i.len(), o.len(), r.len()), (4, 3, 3)),
+            _ => panic!("unexpected dump {:?}", dump1),
+        }
+    }
+
+    #[tokio::test]
+    async fn clone_with_index() -> Result<()> {
+        let dummy = Arc::new(DummyRouter::with_config(3, 2, 2));
+        for index in 0..3 {
+            let label = RouterLabel {
+                id: 0,
+                name: format!("Matrix {}", index),
+            };
+            dummy.update_input_labels(index, vec![label]).await?;
+        }
+        let fe = VideohubFrontend::new(Arc::clone(&dummy), 0).with_max_block_lines(1);
+        let frontends: Vec<_> = (0..3).map(|index| fe.clone_with_index(index)).collect();
+        for (index, fe) in frontends.iter().enumerate() {
+            assert!(Arc::ptr_eq(&fe.router, &dummy));
+            let dump = collect_dump(fe).await;
+            // Still split into single lines.
+            assert_eq!(
+                dump[2],
+                VideohubMessage::InputLabels(vec![Label {
+                    id: 0,
+                    name: format!("Matrix {}", index),
+                }])
+            );
+        }
+
+        // Locks on one matrix leave the others alone.
+        let lock = |state| Lock { id: 1, state };
+        let resp = frontends[1]
+            .handle_message(
+                VideohubMessage::VideoOutputLocks(vec![lock(LockState::Owned)]),
+                true,
+            )
+            .await?;
+        assert_eq!(resp, Some(VideohubMessage::ACK));
+        let mut other = frontends[2].clone();
+        other.session = 1;
+        let resp = other
+            .handle_message(
+                VideohubMessage::VideoOutputLocks(vec![lock(LockState::Owned)]),
+                true,
+            )
+            .await?;
+        assert_eq!(resp, Some(VideohubMessage::ACK));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn parked_outputs() -> Result<()> {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        dummy
+            .update_routes(IDX, vec![RouterPatch::parked(1)])
+            .await?;
+        let routed = VideohubMessage::VideoOutputRouting(vec![Route::from((0, 0))]);
+        let parked = RouterEvent::RouteUpdate(IDX, vec![RouterPatch::parked(1)]);
+
+        // Left out by default, a block of nothing but parked outputs isn't sent at all.
+        let fe = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+        assert_eq!(collect_dump(&fe).await[5], routed);
+        assert_eq!(fe.handle_event(parked.clone()).await?, None);
+
+        let fe = fe.with_park_input(1);
+        assert_eq!(
+            collect_dump(&fe).await[5],
+            VideohubMessage::VideoOutputRouting(vec![Route::from((0, 0)), Route::from((1, 1))])
+        );
+        assert_eq!(
+            fe.handle_event(parked).await?,
+            Some(VideohubMessage::VideoOutputRouting(vec![Route::from((
+                1, 1
+            ))]))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn multi_matrix_isolation() {
+        let dummy = Arc::new(DummyRouter::with_matrices(&[(2, 0), (4, 3)]));
+        let fe0 = VideohubFrontend::new(Arc::clone(&dummy), 0);
+        let fe1 = VideohubFrontend::new(Arc::clone(&dummy), 1);
+        let mut stream = dummy.event_stream().await.unwrap();
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+
+        // Output 2 only exists on matrix 1.
+        let route = Route {
+            from_input: 3,
+            to_output: 2,
+        };
+        let resp = fe0
+            .handle_message(VideohubMessage::VideoOutputRouting(vec![route]), true)
+            .await
+            .unwrap();
+        assert_eq!(resp, Some(VideohubMessage::NAK));
+        let resp = fe1
+            .handle_message(VideohubMessage::VideoOutputRouting(vec![route]), true)
+            .await
+            .unwrap();
+        assert_eq!(resp, Some(VideohubMessage::ACK));
+        assert!(dummy.get_routes(0).await.unwrap().is_empty());
+        assert!(dummy.get_routes(1).await.unwrap().contains(&route.into()));
+
+        // Input 3 only exists on matrix 1, too.
+        let label = Label {
+            id: 3,
+            name: "Only on 1".to_string(),
+        };
+        let resp = fe0
+            .handle_message(VideohubMessage::InputLabels(vec![label.clone()]), true)
+            .await
+            .unwrap();
+        assert_eq!(resp, Some(VideohubMessage::NAK));
+        fe1.handle_message(VideohubMessage::InputLabels(vec![label.clone()]), true)
+            .await
+            .unwrap();
+        assert_eq!(dummy.get_input_labels(0).await.unwrap().len(), 2);
+
+        // Events only reach the frontend bound to their matrix.
+        let ev = stream.next().await.unwrap();
+        assert!(matches!(ev, RouterEvent::RouteUpdate(1, _)));
+        assert_eq!(fe0.handle_event(ev.clone()).await.unwrap(), None);
+        match fe1.handle_event(ev).await.unwrap() {
+            Some(VideohubMessage::VideoOutputRouting(rs)) => assert_eq!(rs, vec![route]),
+            m => panic!("expected VideoOutputRouting, got {:?}", m),
+        }
+        let ev = stream.next().await.unwrap();
+        assert!(matches!(ev, RouterEvent::InputLabelUpdate(1, _)));
+        assert_eq!(fe0.handle_event(ev.clone()).await.unwrap(), None);
+        assert!(matches!(
+            fe1.handle_event(ev).await.unwrap(),
+            Some(VideohubMessage::InputLabels(ls)) if ls == vec![label]
+        ));
+
+        // And the other way around.
+        let patch = RouterPatch {
+            from_input: Some(1),
+            to_output: 0,
+        };
+        let ev = RouterEvent::RouteUpdate(0, vec![patch]);
+        assert_eq!(fe1.handle_event(ev.clone()).await.unwrap(), None);
+        assert!(fe0.handle_event(ev).await.unwrap().is_some());
+    }
+
+    #[test]
+    fn lock_registry() {
+        let mut reg = LockRegistry::default();
+        let lock = |id, state| Lock { id, state };
+        const OUT: LockTarget = LockTarget::VideoOutput;
+
+        assert!(reg.apply(1, OUT, &[lock(0, LockState::Owned)], 2));
+        assert_eq!(reg.state_for(OUT, 0, 1), LockState::Owned);
+        assert_eq!(reg.state_for(OUT, 0, 2), LockState::Locked);
+        assert_eq!(reg.state_for(OUT, 1, 2), LockState::Unlocked);
+
+        // Neither taking nor releasing someone else's lock works, and nothing applies.
+        assert!(!reg.apply(
+            2,
+            OUT,
+            &[lock(1, LockState::Owned), lock(0, LockState::Owned)],
+            2
+        ));
+        assert!(!reg.apply(2, OUT, &[lock(0, LockState::Unlocked)], 2));
+        assert_eq!(reg.state_for(OUT, 1, 2), LockState::Unlocked);
+        // Out of range or nonsensical requests are refused.
+        assert!(!reg.apply(2, OUT, &[lock(2, LockState::Owned)], 2));
+        assert!(!reg.apply(2, OUT, &[lock(1, LockState::Locked)], 2));
+
+        // Forcing takes over.
+        assert!(reg.apply(2, OUT, &[lock(0, LockState::Force)], 2));
+        assert_eq!(reg.state_for(OUT, 0, 2), LockState::Owned);
+        assert_eq!(reg.state_for(OUT, 0, 1), LockState::Locked);
+
+        assert!(reg.release(1).is_empty());
+        assert_eq!(reg.release(2), vec![(OUT, 0)]);
+        assert_eq!(reg.state_for(OUT, 0, 1), LockState::Unlocked);
+    }
+
+    /// Read messages until one matches `pred`.
+    async fn next_matching(
+        framed: &mut Framed<TcpStream, VideohubCodec>,
+        pred: impl Fn(&VideohubMessage) -> bool,
+    ) -> VideohubMessage {
+        let read = async {
+            loop {
+                let msg = framed.next().await.expect("connection closed").unwrap();
+                if pred(&msg) {
+                    return msg;
+                }
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(1), read)
+            .await
+            .expect("timed out waiting for message")
+    }
+
+    #[tokio::test]
+    async fn locks_across_sessions() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+
+        let connect = || async {
+            let socket = TcpStream::connect(addr).await.unwrap();
+            let mut framed = Framed::new(socket, VideohubCodec::default());
+            next_matching(&mut framed, |m| *m == VideohubMessage::EndPrelude).await;
+            framed
+        };
+        let mut a = connect().await;
+        let mut b = connect().await;
+        let is_locks = |m: &VideohubMessage| matches!(m, VideohubMessage::VideoOutputLocks(_));
+        let states = |m: VideohubMessage| match m {
+            VideohubMessage::VideoOutputLocks(ls) => {
+                ls.into_iter().map(|l| l.state).collect::<Vec<_>>()
+            }
+            _ => unreachable!(),
+        };
+
+        // A locks output 1.
+        let take = vec![Lock {
+            id: 1,
+            state: LockState::Owned,
+        }];
+        a.send(VideohubMessage::VideoOutputLocks(take.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_matching(&mut a, |m| *m == VideohubMessage::ACK).await,
+            VideohubMessage::ACK
+        );
+        let seen_a = states(next_matching(&mut a, is_locks).await);
+        let seen_b = states(next_matching(&mut b, is_locks).await);
+        assert_eq!(seen_a, vec![LockState::Unlocked, LockState::Owned]);
+        assert_eq!(seen_b, vec![LockState::Unlocked, LockState::Locked]);
+
+        // B can neither take the lock, nor route to the locked output.
+        b.send(VideohubMessage::VideoOutputLocks(take))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_matching(&mut b, |m| *m == VideohubMessage::NAK).await,
+            VideohubMessage::NAK
+        );
+        let route = Route {
+            from_input: 1,
+            to_output: 1,
+        };
+        b.send(VideohubMessage::VideoOutputRouting(vec![route]))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_matching(&mut b, |m| *m == VideohubMessage::NAK).await,
+            VideohubMessage::NAK
+        );
+        assert!(!dummy.get_routes(IDX).await.unwrap().contains(&route.into()));
+
+        // Once A is gone, the lock is released.
+        drop(a);
+        let released_1 = |m: &VideohubMessage| matches!(m, VideohubMessage::VideoOutputLocks(ls) if ls[1].state == LockState::Unlocked);
+        let seen_b = states(next_matching(&mut b, released_1).await);
+        assert_eq!(seen_b, vec![LockState::Unlocked, LockState::Unlocked]);
+    }
+
+    #[tokio::test]
+    async fn routing_reserves_only_its_outputs() {
+        let router = Arc::new(LatencyRouter {
+            inner: Arc::new(DummyRouter::with_config(1, 2, 2)),
+            latency: Duration::from_millis(300),
+            reversed: false,
+        });
+        let frontend = VideohubFrontend::new(router, IDX);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+
+        let connect = || async {
+            let socket = TcpStream::connect(addr).await.unwrap();
+            let mut framed = Framed::new(socket, VideohubCodec::default());
+            next_matching(&mut framed, |m| *m == VideohubMessage::EndPrelude).await;
+            framed
+        };
+        let mut a = connect().await;
+        let mut b = connect().await;
+        let lock = |id, state| VideohubMessage::VideoOutputLocks(vec![Lock { id, state }]);
+        let is_reply =
+            |m: &VideohubMessage| matches!(m, VideohubMessage::ACK | VideohubMessage::NAK);
+
+        // A routes output 0, which takes a while.
+        a.send(VideohubMessage::VideoOutputRouting(vec![Route {
+            from_input: 1,
+            to_output: 0,
+        }]))
+        .await
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Meanwhile B gets to lock other outputs, but can't take output 0 over.
+        let started = std::time::Instant::now();
+        b.send(lock(1, LockState::Owned)).await.unwrap();
+        assert_eq!(next_matching(&mut b, is_reply).await, VideohubMessage::ACK);
+        assert!(started.elapsed() < Duration::from_millis(200));
+        b.send(lock(0, LockState::Force)).await.unwrap();
+        assert_eq!(next_matching(&mut b, is_reply).await, VideohubMessage::NAK);
+
+        // Once A is done, it can.
+        assert_eq!(next_matching(&mut a, is_reply).await, VideohubMessage::ACK);
+        b.send(lock(0, LockState::Force)).await.unwrap();
+        assert_eq!(next_matching(&mut b, is_reply).await, VideohubMessage::ACK);
+    }
+
+    #[tokio::test]
+    async fn router_locks_across_sessions() {
+        let dummy = Arc::new(
+            DummyRouter::with_config(1, 2, 2)
+                .with_frame_count(2)
+                .with_processing_units(2),
+        );
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+
+        let connect = || async {
+            let socket = TcpStream::connect(addr).await.unwrap();
+            let mut framed = Framed::new(socket, VideohubCodec::default());
+            let mut seen = Vec::new();
+            loop {
+                let msg = next_matching(&mut framed, |_| true).await;
+                if msg == VideohubMessage::EndPrelude {
+                    break;
+                }
+                seen.push(msg);
+            }
+            (framed, seen)
+        };
+        let (mut a, dump) = connect().await;
+        let unlocked = |n| {
+            (0..n)
+                .map(|id| Lock {
+                    id,
+                    state: LockState::Unlocked,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(dump.contains(&VideohubMessage::FrameBufferLocks(unlocked(2))));
+        assert!(dump.contains(&VideohubMessage::ProcessingUnitLocks(unlocked(2))));
+        let (mut b, _) = connect().await;
+
+        let is_frame_locks =
+            |m: &VideohubMessage| matches!(m, VideohubMessage::FrameBufferLocks(_));
+        let states = |m: VideohubMessage| match m {
+            VideohubMessage::FrameBufferLocks(ls) | VideohubMessage::ProcessingUnitLocks(ls) => {
+                ls.into_iter().map(|l| l.state).collect::<Vec<_>>()
+            }
+            _ => unreachable!(),
+        };
+
+        // A takes frame buffer 1, which the router now holds.
+        let take = vec![Lock {
+            id: 1,
+            state: LockState::Owned,
+        }];
+        a.send(VideohubMessage::FrameBufferLocks(take.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_matching(&mut a, |m| *m == VideohubMessage::ACK).await,
+            VideohubMessage::ACK
+        );
+        assert!(dummy.get_frame_locks(IDX).await.unwrap()[1].locked);
+        let seen_b = states(next_matching(&mut b, is_frame_locks).await);
+        assert_eq!(seen_b, vec![LockState::Unlocked, LockState::Locked]);
+        a.send(VideohubMessage::FrameBufferLocks(vec![]))
+            .await
+            .unwrap();
+        let seen_a = states(next_matching(&mut a, is_frame_locks).await);
+        assert_eq!(seen_a, vec![LockState::Unlocked, LockState::Owned]);
+
+        // B can't take it, but force it.
+        b.send(VideohubMessage::FrameBufferLocks(take))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_matching(&mut b, |m| *m == VideohubMessage::NAK).await,
+            VideohubMessage::NAK
+        );
+        let force = vec![Lock {
+            id: 1,
+            state: LockState::Force,
+        }];
+        b.send(VideohubMessage::FrameBufferLocks(force))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_matching(&mut b, |m| *m == VideohubMessage::ACK).await,
+            VideohubMessage::ACK
+        );
+
+        // Processing units work alike, and get released along with the session.
+        let take = vec![Lock {
+            id: 0,
+            state: LockState::Owned,
+        }];
+        a.send(VideohubMessage::ProcessingUnitLocks(take))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_matching(&mut a, |m| *m == VideohubMessage::ACK).await,
+            VideohubMessage::ACK
+        );
+        assert!(dummy.get_processing_unit_locks(IDX).await.unwrap()[0].locked);
+        drop(a);
+        let seen_b = states(
+            next_matching(&mut b, |m| {
+                matches!(m, VideohubMessage::ProcessingUnitLocks(ls) if ls[0].state == LockState::Unlocked)
+            })
+            .await,
+        );
+        assert_eq!(seen_b, vec![LockState::Unlocked, LockState::Unlocked]);
+        assert!(!dummy.get_processing_unit_locks(IDX).await.unwrap()[0].locked);
+        // B's forced frame buffer lock stays.
+        assert!(dummy.get_frame_locks(IDX).await.unwrap()[1].locked);
+    }
+
+    #[tokio::test]
+    async fn offline_dump() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        dummy.set_alive(false);
+        let frontend = VideohubFrontend::new(dummy, IDX);
+        let items = collect_dump(&frontend).await;
+
+        assert_eq!(items.len(), 3);
+        assert!(matches!(items[0], VideohubMessage::Preamble(..)));
+        match &items[1] {
+            VideohubMessage::DeviceInfo(di) => {
+                assert_eq!(di.present, Some(Present::No));
+                assert_eq!(di.video_outputs, None);
+            }
+            m => panic!("expected DeviceInfo, got {:?}", m),
+        }
+        assert_eq!(items[2], VideohubMessage::EndPrelude);
+    }
+
+    #[tokio::test]
+    async fn offline_naks() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        dummy.set_alive(false);
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+        let before = dummy.get_routes(IDX).await.unwrap();
+
+        let route = Route {
+            from_input: 1,
+            to_output: 0,
+        };
+        let label = Label {
+            id: 0,
+            name: "Nope".into(),
+        };
+        for msg in [
+            VideohubMessage::InputLabels(vec![]),
+            VideohubMessage::OutputLabels(vec![label]),
+            VideohubMessage::VideoOutputRouting(vec![]),
+            VideohubMessage::VideoOutputRouting(vec![route]),
+        ] {
+            let resp = frontend.handle_message(msg, false).await.unwrap();
+            assert_eq!(resp, Some(VideohubMessage::NAK));
+        }
+        assert_eq!(dummy.get_routes(IDX).await.unwrap(), before);
+
+        // Pings still work.
+        let resp = frontend
+            .handle_message(VideohubMessage::Ping, false)
+            .await
+            .unwrap();
+        assert_eq!(resp, Some(VideohubMessage::ACK));
+    }
+
+    #[tokio::test]
+    async fn redump_once_connected() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        dummy.set_alive(false);
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+
+        let socket = TcpStream::connect(addr).await.unwrap();
+        let mut client = Framed::new(socket, VideohubCodec::default());
+        next_matching(&mut client, |m| *m == VideohubMessage::EndPrelude).await;
+
+        dummy.set_alive(true);
+        match next_matching(&mut client, |_| true).await {
+            VideohubMessage::DeviceInfo(di) => {
+                assert_eq!(di.present, Some(Present::Yes));
+                assert_eq!(di.video_outputs, Some(2));
+            }
+            m => panic!("expected DeviceInfo, got {:?}", m),
+        }
+        for expected in ["INPUT LABELS:", "OUTPUT LABELS:", "VIDEO OUTPUT LOCKS:"] {
+            let msg = next_matching(&mut client, |_| true).await;
+            assert!(msg.summary().starts_with(expected), "got {:?}", msg);
+        }
+        assert!(matches!(
+            next_matching(&mut client, |_| true).await,
+            VideohubMessage::VideoOutputRouting(_)
+        ));
+
+        // Going away again is announced as well.
+        dummy.set_alive(false);
+        match next_matching(&mut client, |_| true).await {
+            VideohubMessage::DeviceInfo(di) => assert_eq!(di.present, Some(Present::No)),
+            m => panic!("expected DeviceInfo, got {:?}", m),
+        }
+    }
+
+    /// A [DummyRouter] counting how often it's asked whether it's alive.
+    #[derive(Clone)]
+    struct AliveCountingRouter {
+        inner: Arc<DummyRouter>,
+        asked: Arc<AtomicUsize>,
+    }
+
+    impl MatrixRouter for AliveCountingRouter {
+        async fn is_alive(&self) -> Result<bool> {
+            self.asked.fetch_add(1, Ordering::SeqCst);
+            self.inner.is_alive().await
+        }
+        async fn get_router_info(&self) -> Result<crate::matrix::RouterInfo> {
+            self.inner.get_router_info().await
+        }
+        async fn get_matrix_info(&self, index: u32) -> Result<crate::matrix::RouterMatrixInfo> {
+            self.inner.get_matrix_info(index).await
+        }
+        async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+            self.inner.get_input_labels(index).await
+        }
+        async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+            self.inner.get_output_labels(index).await
+        }
+        async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+            self.inner.update_input_labels(index, changed).await
+        }
+        async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+            self.inner.update_output_labels(index, changed).await
+        }
+        async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+            self.inner.get_routes(index).await
+        }
+        async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+            self.inner.update_routes(index, changes).await
+        }
+        async fn event_stream<'a>(
+            &'a self,
+        ) -> Result<futures_core::stream::BoxStream<'a, RouterEvent>> {
+            self.inner.event_stream().await
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_trust_tracked_presence() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        let router = Arc::new(AliveCountingRouter {
+            inner: Arc::clone(&dummy),
+            asked: Arc::default(),
+        });
+        let frontend = VideohubFrontend::new(Arc::clone(&router), IDX);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+
+        let socket = TcpStream::connect(addr).await.unwrap();
+        let mut client = Framed::new(socket, VideohubCodec::default());
+        next_matching(&mut client, |m| *m == VideohubMessage::EndPrelude).await;
+        let asked = router.asked.load(Ordering::SeqCst);
+
+        // Requests don't ask the router again, that would be a round trip each.
+        let route = Route {
+            from_input: 1,
+            to_output: 0,
+        };
+        for _ in 0..3 {
+            client
+                .send(VideohubMessage::VideoOutputRouting(vec![route]))
+                .await
+                .unwrap();
+            next_matching(&mut client, |m| *m == VideohubMessage::ACK).await;
+        }
+        assert_eq!(router.asked.load(Ordering::SeqCst), asked);
+
+        // Once it's gone, requests are refused all the same.
+        dummy.set_alive(false);
+        next_matching(&mut client, |m| matches!(m, VideohubMessage::DeviceInfo(_))).await;
+        client
+            .send(VideohubMessage::VideoOutputRouting(vec![route]))
+            .await
+            .unwrap();
+        next_matching(&mut client, |m| *m == VideohubMessage::NAK).await;
+        assert_eq!(router.asked.load(Ordering::SeqCst), asked);
+    }
+
+    #[tokio::test]
+    async fn label_limit() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX).with_label_limit(10);
+        let labels = vec![Label {
+            id: 1,
+            name: " Kamera\t2 Süd ".into(),
+        }];
+        let resp = frontend
+            .handle_message(VideohubMessage::InputLabels(labels.clone()), true)
+            .await
+            .unwrap();
+        assert_eq!(resp, Some(VideohubMessage::ACK));
+        // The "ü" doesn't fit in whole, it's dropped rather than split.
+        assert_eq!(
+            dummy.get_input_labels(IDX).await.unwrap()[1].name,
+            "Kamera2 S"
+        );
+
+        // Without a limit, labels go through as sent.
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+        frontend
+            .handle_message(VideohubMessage::InputLabels(labels.clone()), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            dummy.get_input_labels(IDX).await.unwrap()[1].name,
+            labels[0].name
+        );
+    }
+
+    #[tokio::test]
+    async fn max_block_lines() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 5, 5));
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX).with_max_block_lines(2);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+
+        let socket = TcpStream::connect(addr).await.unwrap();
+        let mut client = Framed::new(socket, VideohubCodec::default());
+        let mut state = VideohubState::default();
+        let mut routing_blocks = 0;
+        loop {
+            let msg = next_matching(&mut client, |_| true).await;
+            if msg == VideohubMessage::EndPrelude {
+                break;
+            }
+            if let VideohubMessage::VideoOutputRouting(rs) = &msg {
+                assert!(rs.len() <= 2);
+                routing_blocks += 1;
+            }
+            state.apply(&msg);
+        }
+        assert_eq!(routing_blocks, 3);
+        assert_eq!(state.input_labels.len(), 5);
+        assert_eq!(state.video_output_routing.len(), 5);
+
+        // Replies get split as well.
+        client
+            .send(VideohubMessage::OutputLabels(vec![]))
+            .await
+            .unwrap();
+        for ids in [[0, 1].as_slice(), &[2, 3], &[4]] {
+            match next_matching(&mut client, |_| true).await {
+                VideohubMessage::OutputLabels(ls) => {
+                    assert_eq!(ls.iter().map(|l| l.id).collect::<Vec<_>>(), ids)
+                }
+                m => panic!("expected OutputLabels, got {:?}", m),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn out_of_range_naks() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 4, 2));
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+        let before = dummy.get_routes(IDX).await.unwrap();
+
+        // Output 3 doesn't exist, even though input 3 would.
+        let swapped = Route {
+            from_input: 1,
+            to_output: 3,
+        };
+        let resp = frontend
+            .handle_message(VideohubMessage::VideoOutputRouting(vec![swapped]), true)
+            .await
+            .unwrap();
+        assert_eq!(resp, Some(VideohubMessage::NAK));
+        assert_eq!(dummy.get_routes(IDX).await.unwrap(), before);
+
+        let label = Label {
+            id: 2,
+            name: "Nope".into(),
+        };
+        let resp = frontend
+            .handle_message(VideohubMessage::OutputLabels(vec![label.clone()]), true)
+            .await
+            .unwrap();
+        assert_eq!(resp, Some(VideohubMessage::NAK));
+        let resp = frontend
+            .handle_message(VideohubMessage::InputLabels(vec![label]), true)
+            .await
+            .unwrap();
+        assert_eq!(resp, Some(VideohubMessage::ACK));
+
+        let valid = Route {
+            from_input: 3,
+            to_output: 1,
+        };
+        let resp = frontend
+            .handle_message(VideohubMessage::VideoOutputRouting(vec![valid]), true)
+            .await
+            .unwrap();
+        assert_eq!(resp, Some(VideohubMessage::ACK));
+    }
+
+    #[tokio::test]
+    async fn rate_limited_naks() {
+        let limit = RateLimit {
+            max_updates_per_second: 1,
+            burst: 1,
+            policy: RateLimitPolicy::Reject,
+            ..Default::default()
+        };
+        let router = RateLimitedRouter::new(DummyRouter::with_config(1, 2, 2), limit).unwrap();
+        let frontend = VideohubFrontend::new(Arc::new(router), IDX);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+
+        let socket = TcpStream::connect(addr).await.unwrap();
+        let mut client = Framed::new(socket, VideohubCodec::default());
+        next_matching(&mut client, |m| *m == VideohubMessage::EndPrelude).await;
+        let route = |from_input| {
+            VideohubMessage::VideoOutputRouting(vec![Route {
+                from_input,
+                to_output: 0,
+            }])
+        };
+        client.send(route(1)).await.unwrap();
+        next_matching(&mut client, |m| *m == VideohubMessage::ACK).await;
+        // Over budget, refused like any other router refusal.
+        client.send(route(0)).await.unwrap();
+        next_matching(&mut client, |m| *m == VideohubMessage::NAK).await;
+
+        // Still connected.
+        client.send(VideohubMessage::Ping).await.unwrap();
+        next_matching(&mut client, |m| *m == VideohubMessage::ACK).await;
+    }
+
+    #[tokio::test]
+    async fn read_only_naks() {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let router = Arc::new(ReadOnlyRouter::new(dummy.clone()));
+        let frontend = VideohubFrontend::new(router, IDX);
+        let route = Route {
+            from_input: 1,
+            to_output: 0,
+        };
+        for msg in [
+            VideohubMessage::VideoOutputRouting(vec![route]),
+            VideohubMessage::InputLabels(vec![Label::from((0, "Cam 1"))]),
+        ] {
+            let resp = frontend.handle_message(msg, true).await.unwrap();
+            assert_eq!(resp, Some(VideohubMessage::NAK));
+        }
+
+        // Requests are still answered, with nothing changed.
+        let resp = frontend
+            .handle_message(VideohubMessage::VideoOutputRouting(vec![]), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            resp,
+            Some(VideohubMessage::VideoOutputRouting(vec![
+                Route::from((0, 0)),
+                Route::from((1, 0))
+            ]))
+        );
+        assert_eq!(
+            dummy.get_input_labels(IDX).await.unwrap()[0].name,
+            "Input 1"
+        );
+    }
+
+    /// Start a fake 2x2 Videohub peer answering routing requests only after `delay`,
+    /// returning its address.
+    async fn spawn_slow_peer(delay: Duration) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(socket, VideohubCodec::default());
+            let labels = |prefix: &str| {
+                (0..2)
+                    .map(|id| Label {
+                        id,
+                        name: format!("{} {}", prefix, id + 1),
+                    })
+                    .collect()
+            };
+            for msg in [
+                VideohubMessage::Preamble(Preamble {
+                    version: "2.7".into(),
+                }),
+                VideohubMessage::DeviceInfo(DeviceInfo {
+                    present: Some(Present::Yes),
+                    video_inputs: Some(2),
+                    video_outputs: Some(2),
+                    ..Default::default()
+                }),
+                VideohubMessage::InputLabels(labels("Input")),
+                VideohubMessage::OutputLabels(labels("Output")),
+                VideohubMessage::EndPrelude,
+            ] {
+                framed.send(msg).await.unwrap();
+            }
+
+            // Routing requests are answered late, everything else right away.
+            let mut due = None;
+            loop {
+                let wait = async {
+                    match due {
+                        Some(at) => tokio::time::sleep_until(at).await,
+                        None => std::future::pending().await,
+                    }
+                };
+                select! {
+                    msg = framed.next() => match msg {
+                        Some(Ok(VideohubMessage::Ping)) => {
+                            framed.send(VideohubMessage::ACK).await.unwrap();
+                        }
+                        Some(Ok(VideohubMessage::VideoOutputRouting(rs))) if rs.is_empty() => {
+                            due.get_or_insert(tokio::time::Instant::now() + delay);
+                        }
+                        Some(Ok(_)) => framed.send(VideohubMessage::NAK).await.unwrap(),
+                        _ => break,
+                    },
+                    _ = wait => {
+                        due = None;
+                        let routes = (0..2)
+                            .map(|n| Route {
+                                from_input: 1 - n,
+                                to_output: n,
+                            })
+                            .collect();
+                        framed
+                            .send(VideohubMessage::VideoOutputRouting(routes))
+                            .await
+                            .unwrap();
+                    }
+                }
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn slow_routing_is_deferred() {
+        let peer = spawn_slow_peer(Duration::from_millis(300)).await;
+        // Prefetching would have the routes cached before any client asks.
+        let options = crate::backend::ConnectOptions::default().with_prefetch(false);
+        let proxy = Arc::new(
+            crate::backend::VideohubRouter::connect_with(peer, options)
+                .await
+                .unwrap(),
+        );
+        let frontend =
+            VideohubFrontend::new(proxy, IDX).with_reply_timeout(Duration::from_millis(50));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+
+        let socket = TcpStream::connect(addr).await.unwrap();
+        let mut client = Framed::new(socket, VideohubCodec::default());
+        let started = std::time::Instant::now();
+        next_matching(&mut client, |m| *m == VideohubMessage::EndPrelude).await;
+
+        // Asking again is answered promptly as well, with what's there.
+        client
+            .send(VideohubMessage::VideoOutputRouting(vec![]))
+            .await
+            .unwrap();
+        let reply = next_matching(&mut client, |_| true).await;
+        assert_eq!(reply, VideohubMessage::VideoOutputRouting(vec![]));
+        assert!(started.elapsed() < Duration::from_millis(300));
+
+        // The full block follows once the peer answered.
+        let full = next_matching(
+            &mut client,
+            |m| matches!(m, VideohubMessage::VideoOutputRouting(rs) if !rs.is_empty()),
+        )
+        .await;
+        let expected = (0..2)
+            .map(|n| Route {
+                from_input: 1 - n,
+                to_output: n,
+            })
+            .collect();
+        assert_eq!(full, VideohubMessage::VideoOutputRouting(expected));
+    }
+
+    #[tokio::test]
+    async fn fast_routing_single_reply() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        let frontend = VideohubFrontend::new(dummy, IDX);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+
+        let socket = TcpStream::connect(addr).await.unwrap();
+        let mut client = Framed::new(socket, VideohubCodec::default());
+        next_matching(&mut client, |m| *m == VideohubMessage::EndPrelude).await;
+
+        client
+            .send(VideohubMessage::VideoOutputRouting(vec![]))
+            .await
+            .unwrap();
+        client.send(VideohubMessage::Ping).await.unwrap();
+        match next_matching(&mut client, |_| true).await {
+            VideohubMessage::VideoOutputRouting(rs) => assert_eq!(rs.len(), 2),
+            m => panic!("expected VideoOutputRouting, got {:?}", m),
+        }
+        // Nothing else sneaks in before the ping's reply.
+        assert_eq!(
+            next_matching(&mut client, |_| true).await,
+            VideohubMessage::ACK
+        );
+    }
+
+    #[tokio::test]
+    async fn frame_requests_out_of_range() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2).with_frame_count(2));
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+        let requests = [
+            VideohubMessage::FrameLabels(vec![Label {
+                id: 2,
+                name: "Still".into(),
+            }]),
+            VideohubMessage::FrameBufferRouting(vec![Route {
+                from_input: 0,
+                to_output: 2,
+            }]),
+            VideohubMessage::FrameBufferLocks(vec![Lock {
+                id: 2,
+                state: LockState::Owned,
+            }]),
+        ];
+        // Refused, rather than dropping the connection.
+        for req in requests {
+            let resp = frontend.handle_message(req, true).await.unwrap();
+            assert_eq!(resp, Some(VideohubMessage::NAK));
+        }
+    }
+
+    #[tokio::test]
+    async fn output_locks_held_at_router() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2).with_output_locks());
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+
+        let connect = || async {
+            let socket = TcpStream::connect(addr).await.unwrap();
+            let mut framed = Framed::new(socket, VideohubCodec::default());
+            next_matching(&mut framed, |m| *m == VideohubMessage::EndPrelude).await;
+            framed
+        };
+        let mut a = connect().await;
+        let mut b = connect().await;
+        let is_locks = |m: &VideohubMessage| matches!(m, VideohubMessage::VideoOutputLocks(_));
+        let states = |m: VideohubMessage| match m {
+            VideohubMessage::VideoOutputLocks(ls) => {
+                ls.into_iter().map(|l| l.state).collect::<Vec<_>>()
+            }
+            _ => unreachable!(),
+        };
+
+        // A locks output 1 at the router, B hears about it from the router's event.
+        a.send(VideohubMessage::VideoOutputLocks(vec![Lock {
+            id: 1,
+            state: LockState::Owned,
+        }]))
+        .await
+        .unwrap();
+        assert_eq!(
+            next_matching(&mut a, |m| *m == VideohubMessage::ACK).await,
+            VideohubMessage::ACK
+        );
+        assert!(dummy.get_locks(IDX).await.unwrap()[1].locked);
+        let seen_b = states(next_matching(&mut b, is_locks).await);
+        assert_eq!(seen_b, vec![LockState::Unlocked, LockState::Locked]);
+
+        // Locks taken at the router directly show up as someone else's, even to A.
+        let external = RouterLock {
+            id: 0,
+            locked: true,
+        };
+        dummy.update_locks(IDX, vec![external]).await.unwrap();
+        let locked_0 = |m: &VideohubMessage| matches!(m, VideohubMessage::VideoOutputLocks(ls) if ls[0].state == LockState::Locked);
+        let seen_a = states(next_matching(&mut a, locked_0).await);
+        assert_eq!(seen_a, vec![LockState::Locked, LockState::Owned]);
+        a.send(VideohubMessage::VideoOutputRouting(vec![Route {
+            from_input: 1,
+            to_output: 0,
+        }]))
+        .await
+        .unwrap();
+        assert_eq!(
+            next_matching(&mut a, |m| *m == VideohubMessage::NAK).await,
+            VideohubMessage::NAK
+        );
+
+        // Ending A's session releases its lock at the router.
+        drop(a);
+        let released_1 = |m: &VideohubMessage| matches!(m, VideohubMessage::VideoOutputLocks(ls) if ls[1].state == LockState::Unlocked);
+        let seen_b = states(next_matching(&mut b, released_1).await);
+        assert_eq!(seen_b, vec![LockState::Locked, LockState::Unlocked]);
+        assert!(!dummy.get_locks(IDX).await.unwrap()[1].locked);
+    }
+
+    #[tokio::test]
+    async fn merges_event_bursts() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 4, 4));
+        let frontend = VideohubFrontend::new(dummy.clone(), IDX);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+        let socket = TcpStream::connect(addr).await.unwrap();
+        let mut framed = Framed::new(socket, VideohubCodec::default());
+        next_matching(&mut framed, |m| *m == VideohubMessage::EndPrelude).await;
+
+        for output in 0..4 {
+            let patch = RouterPatch {
+                from_input: Some(3),
+                to_output: output,
+            };
+            dummy.update_routes(IDX, vec![patch]).await.unwrap();
+        }
+        let label = RouterLabel {
+            id: 0,
+            name: "Camera 1".into(),
+        };
+        dummy.update_input_labels(IDX, vec![label]).await.unwrap();
+
+        // All four route changes in a single block, along with the labels.
+        let all_from_3 = |m: &VideohubMessage| match m {
+            VideohubMessage::VideoOutputRouting(rs) => rs.iter().all(|r| r.from_input == 3),
+            _ => false,
+        };
+        let mut blocks = Vec::new();
+        while blocks.len() < 2 {
+            let msg = framed.next().await.unwrap().unwrap();
+            if matches!(
+                msg,
+                VideohubMessage::VideoOutputRouting(_) | VideohubMessage::InputLabels(_)
+            ) {
+                blocks.push(msg);
+            }
+        }
+        assert!(all_from_3(&blocks[0]), "{:?}", blocks[0]);
+        assert!(matches!(&blocks[1], VideohubMessage::InputLabels(ls) if ls[0].name == "Camera 1"));
+    }
+
+    #[tokio::test]
+    async fn locks_owned_by_peer() {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let router = LockingRouter::new(dummy.clone());
+        let frontend = VideohubFrontend::new(Arc::new(router.clone()), IDX);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.serve(listener));
+
+        let connect = || async {
+            let socket = TcpStream::connect(addr).await.unwrap();
+            let mut framed = Framed::new(socket, VideohubCodec::default());
+            next_matching(&mut framed, |m| *m == VideohubMessage::EndPrelude).await;
+            framed
+        };
+        let mut a = connect().await;
+        let mut b = connect().await;
+        let a_addr = a.get_ref().local_addr().unwrap().to_string();
+        let take = vec![Lock {
+            id: 1,
+            state: LockState::Owned,
+        }];
+        let locked_1 = |m: &VideohubMessage| matches!(m, VideohubMessage::VideoOutputLocks(ls) if ls[1].state == LockState::Locked);
+        let released_1 = |m: &VideohubMessage| matches!(m, VideohubMessage::VideoOutputLocks(ls) if ls[1].state == LockState::Unlocked);
+
+        // A's lock is held at the router, on behalf of A's address.
+        a.send(VideohubMessage::VideoOutputLocks(take.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_matching(&mut a, |m| *m == VideohubMessage::ACK).await,
+            VideohubMessage::ACK
+        );
+        next_matching(&mut b, locked_1).await;
+        let patch = RouterPatch {
+            from_input: Some(1),
+            to_output: 1,
+        };
+        assert!(router.update_routes(IDX, vec![patch]).await.is_err());
+        router
+            .update_routes_as(&a_addr, IDX, vec![patch])
+            .await
+            .unwrap();
+        b.send(VideohubMessage::VideoOutputLocks(take.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_matching(&mut b, |m| *m == VideohubMessage::NAK).await,
+            VideohubMessage::NAK
+        );
+
+        // Forcing it open frees it for B.
+        router.force_unlock(IDX, &[1]).await.unwrap();
+        next_matching(&mut b, released_1).await;
+        b.send(VideohubMessage::VideoOutputLocks(take))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_matching(&mut b, |m| *m == VideohubMessage::ACK).await,
+            VideohubMessage::ACK
+        );
+        next_matching(&mut a, locked_1).await;
+
+        // Once B is gone, so is its lock.
+        drop(b);
+        next_matching(&mut a, released_1).await;
+        assert!(!router.get_locks(IDX).await.unwrap()[1].locked);
+        assert_eq!(dummy.get_routes(IDX).await.unwrap()[1], patch);
+    }
+
+    #[tokio::test]
+    async fn frame_lock_released_at_router() {
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2).with_frame_count(2));
+        let frontend = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+        let take = vec![Lock {
+            id: 1,
+            state: LockState::Force,
+        }];
+        let resp = frontend
+            .handle_message(VideohubMessage::FrameBufferLocks(take), true)
+            .await
+            .unwrap();
+        assert_eq!(resp, Some(VideohubMessage::ACK));
+
+        let release = RouterLock {
+            id: 1,
+            locked: false,
+        };
+        dummy.update_frame_locks(IDX, vec![release]).await.unwrap();
+        let locks = dummy.get_frame_locks(IDX).await.unwrap();
+        let view = frontend
+            .handle_event(RouterEvent::FrameLockUpdate(IDX, locks))
+            .await
+            .unwrap();
+        let unlocked = |id| Lock {
+            id,
+            state: LockState::Unlocked,
+        };
+        assert_eq!(
+            view,
+            Some(VideohubMessage::FrameBufferLocks(vec![
+                unlocked(0),
+                unlocked(1)
+            ]))
+        );
+    }
+
+    #[tokio::test]
+    async fn connection_count() {
+        static CONNECTS: AtomicUsize = AtomicUsize::new(0);
+        static DISCONNECTS: AtomicUsize = AtomicUsize::new(0);
+
+        let dummy = Arc::new(DummyRouter::with_config(1, 2, 2));
+        let frontend = VideohubFrontend::new(dummy, IDX)
+            .with_on_connect(|_| {
+                CONNECTS.fetch_add(1, Ordering::SeqCst);
+            })
+            .with_on_disconnect(|_, _| {
+                DISCONNECTS.fetch_add(1, Ordering::SeqCst);
+            });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(frontend.clone().serve(listener));
+        assert_eq!(frontend.connection_count(), 0);
+
+        let wait_for = |count: usize| {
+            let frontend = frontend.clone();
+            async move {
+                tokio::time::timeout(Duration::from_secs(5), async {
+                    while frontend.connection_count() != count {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                })
+                .await
+                .unwrap_or_else(|_| panic!("never reached {} connections", count));
+            }
+        };
+
+        let mut clients = Vec::new();
+        for _ in 0..3 {
+            clients.push(TcpStream::connect(addr).await.unwrap());
+        }
+        wait_for(3).await;
+        drop(clients.pop());
+        drop(clients.pop());
+        wait_for(1).await;
+        clients.push(TcpStream::connect(addr).await.unwrap());
+        wait_for(2).await;
+        drop(clients);
+        wait_for(0).await;
+        assert_eq!(CONNECTS.load(Ordering::SeqCst), 4);
+        assert_eq!(DISCONNECTS.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn connection_span_fields() -> Result<()> {
+        let dummy = Arc::new(DummyRouter::with_config(1, 3, 2));
+        let mut frontend = VideohubFrontend::new(dummy, IDX);
+        frontend.peer = Some("192.0.2.1:9990".parse()?);
+        // Served right here rather than spawned, so the logs stay within this test.
+        let (client, server) = tokio::io::duplex(1 << 16);
+        let client = async move {
+            let mut client = Framed::new(client, VideohubCodec::default());
+            while let Some(msg) = client.next().await {
+                if msg.unwrap() == VideohubMessage::EndPrelude {
+                    break;
+                }
+            }
+        };
+        let (res, ()) = tokio::join!(frontend.handle_connection(server), client);
+        res?;
+        assert!(logs_contain("peer.addr=Some(192.0.2.1:9990)"));
+        assert!(logs_contain("matrix_idx=0"));
+        assert!(logs_contain("router.input_count=3 router.output_count=2"));
+        Ok(())
+    }
+
+    /// Model-based checks: arbitrary message sequences from several sessions, interleaved
+    /// with changes at the router, with invariants checked after every step.
+    mod sequences {
+        use super::*;
+        use crate::matrix::RouterLock;
+        use proptest::prelude::*;
+
+        const INPUTS: u32 = 3;
+        const OUTPUTS: u32 = 2;
+        const FRAMES: u32 = 2;
+        const UNITS: u32 = 1;
+        const SESSIONS: usize = 3;
+
+        #[derive(Clone, Debug)]
+        enum Step {
+            /// A session sends a message.
+            Client(usize, VideohubMessage),
+            /// A session disconnects, a fresh one takes its place.
+            Reconnect(usize),
+            SetAlive(bool),
+            /// Routing changed at the router, outside of the frontend.
+            ExternalRoute(RouterPatch),
+            /// A frame buffer lock changed at the router, outside of the frontend.
+            ExternalFrameLock(RouterLock),
+        }
+
+        /// Ids reach one past the end, so out of range requests show up too.
+        fn id(count: u32) -> impl Strategy<Value = u32> {
+            0..=count
+        }
+
+        fn labels(count: u32) -> impl Strategy<Value = Vec<Label>> {
+            prop::collection::vec(
+                (id(count), "[a-z ]{0,5}").prop_map(|(id, name)| Label { id, name }),
+                0..3,
+            )
+        }
+
+        fn routes(outputs: u32) -> impl Strategy<Value = Vec<Route>> {
+            prop::collection::vec(
+                (id(outputs), id(INPUTS)).prop_map(|(to_output, from_input)| Route {
+                    from_input,
+                    to_output,
+                }),
+                0..3,
+            )
+        }
+
+        fn locks(count: u32) -> impl Strategy<Value = Vec<Lock>> {
+            let state = prop_oneof![
+                Just(LockState::Owned),
+                Just(LockState::Unlocked),
+                Just(LockState::Force),
+                Just(LockState::Locked),
+            ];
+            prop::collection::vec(
+                (id(count), state).prop_map(|(id, state)| Lock { id, state }),
+                0..3,
+            )
+        }
+
+        fn message() -> impl Strategy<Value = VideohubMessage> {
+            prop_oneof![
+                Just(VideohubMessage::Ping),
+                labels(INPUTS).prop_map(VideohubMessage::InputLabels),
+                labels(OUTPUTS).prop_map(VideohubMessage::OutputLabels),
+                labels(FRAMES).prop_map(VideohubMessage::FrameLabels),
+                routes(OUTPUTS).prop_map(VideohubMessage::VideoOutputRouting),
+                routes(FRAMES).prop_map(VideohubMessage::FrameBufferRouting),
+                locks(OUTPUTS).prop_map(VideohubMessage::VideoOutputLocks),
+                locks(FRAMES).prop_map(VideohubMessage::FrameBufferLocks),
+                locks(UNITS).prop_map(VideohubMessage::ProcessingUnitLocks),
+                // Not supported by the frontend.
+                routes(1).prop_map(VideohubMessage::SerialPortRouting),
+            ]
+        }
+
+        fn step() -> impl Strategy<Value = Step> {
+            prop_oneof![
+                8 => (0..SESSIONS, message()).prop_map(|(s, m)| Step::Client(s, m)),
+                1 => (0..SESSIONS).prop_map(Step::Reconnect),
+                1 => any::<bool>().prop_map(Step::SetAlive),
+                1 => (0..OUTPUTS, 0..INPUTS).prop_map(|(to_output, from_input)| {
+                    Step::ExternalRoute(RouterPatch { from_input: Some(from_input), to_output })
+                }),
+                1 => (0..FRAMES, any::<bool>())
+                    .prop_map(|(id, locked)| Step::ExternalFrameLock(RouterLock { id, locked })),
+            ]
+        }
+
+        /// Whether a message asks for state rather than changing it.
+        fn is_query(msg: &VideohubMessage) -> bool {
+            match msg {
+                VideohubMessage::InputLabels(v)
+                | VideohubMessage::OutputLabels(v)
+                | VideohubMessage::FrameLabels(v) => v.is_empty(),
+                VideohubMessage::VideoOutputRouting(v)
+                | VideohubMessage::FrameBufferRouting(v)
+                | VideohubMessage::SerialPortRouting(v) => v.is_empty(),
+                VideohubMessage::VideoOutputLocks(v)
+                | VideohubMessage::FrameBufferLocks(v)
+                | VideohubMessage::ProcessingUnitLocks(v) => v.is_empty(),
+                _ => false,
+            }
+        }
+
+        /// Each session sees a lock as owned by at most one session, and everybody else
+        /// agrees on who holds it.
+        fn check_lock_views(views: &[LockState]) -> Result<(), TestCaseError> {
+            let owned = views.iter().filter(|&&s| s == LockState::Owned).count();
+            prop_assert!(owned <= 1, "owned by several sessions: {:?}", views);
+            if owned == 1 {
+                prop_assert!(
+                    views
+                        .iter()
+                        .all(|&s| s == LockState::Owned || s == LockState::Locked),
+                    "owner not visible to everyone: {:?}",
+                    views
+                );
+            }
+            Ok(())
+        }
+
+        async fn check_invariants(
+            dummy: &DummyRouter,
+            sessions: &[VideohubFrontend<DummyRouter>],
+        ) -> Result<(), TestCaseError> {
+            // Video output locks only live in the registry.
+            for id in 0..OUTPUTS {
+                let mut views = Vec::new();
+                for fe in sessions {
+                    match fe.gen_locks_for(OUTPUTS).await.unwrap() {
+                        VideohubMessage::VideoOutputLocks(v) => views.push(v[id as usize].state),
+                        m => prop_assert!(false, "expected locks, got {:?}", m),
+                    }
+                }
+                check_lock_views(&views)?;
+            }
+
+            // Frame buffer locks are held at the router, owners must have them locked there.
+            let router_locks = dummy.get_frame_locks(IDX).await.unwrap();
+            for l in &router_locks {
+                let mut views = Vec::new();
+                for fe in sessions {
+                    match fe
+                        .router_lock_view(LockTarget::FrameBuffer, router_locks.clone())
+                        .await
+                    {
+                        VideohubMessage::FrameBufferLocks(v) => views.push(v[l.id as usize].state),
+                        m => prop_assert!(false, "expected locks, got {:?}", m),
+                    }
+                }
+                check_lock_views(&views)?;
+                if views.contains(&LockState::Owned) {
+                    prop_assert!(l.locked, "frame {} owned but unlocked at the router", l.id);
+                }
+            }
+            Ok(())
+        }
+
+        async fn run(steps: Vec<Step>) -> Result<(), TestCaseError> {
+            let dummy = Arc::new(
+                DummyRouter::with_config(1, INPUTS as usize, OUTPUTS as usize)
+                    .with_frame_count(FRAMES as usize)
+                    .with_processing_units(UNITS as usize),
+            );
+            let base = VideohubFrontend::new(Arc::clone(&dummy), IDX);
+            let mut next_session = 1;
+            let mut session = || {
+                let mut fe = base.clone();
+                fe.session = next_session;
+                next_session += 1;
+                fe
+            };
+            let mut sessions: Vec<_> = (0..SESSIONS).map(|_| session()).collect();
+
+            for step in steps {
+                match step {
+                    Step::Client(n, msg) => {
+                        let alive = dummy.is_alive().await.unwrap();
+                        let reply = sessions[n]
+                            .handle_message(msg.clone(), alive)
+                            .await
+                            // An error ends the whole connection.
+                            .map_err(|e| {
+                                TestCaseError::fail(format!(
+                                    "{:?} dropped the session: {:?}",
+                                    msg, e
+                                ))
+                            })?;
+                        let reply = reply.expect("every message gets a reply");
+                        if msg == VideohubMessage::Ping {
+                            prop_assert_eq!(&reply, &VideohubMessage::ACK);
+                        } else if !alive {
+                            prop_assert_eq!(&reply, &VideohubMessage::NAK);
+                        } else if is_query(&msg)
+                            && !matches!(msg, VideohubMessage::SerialPortRouting(_))
+                        {
+                            prop_assert_eq!(
+                                std::mem::discriminant(&reply),
+                                std::mem::discriminant(&msg),
+                                "query {:?} answered with {:?}",
+                                msg,
+                                reply
+                            );
+                        } else {
+                            prop_assert!(
+                                reply == VideohubMessage::ACK || reply == VideohubMessage::NAK,
+                                "{:?} answered with {:?}",
+                                msg,
+                                reply
+                            );
+                        }
+
+                        // Acknowledged routing changed at the router, last patch winning.
+                        if let (VideohubMessage::VideoOutputRouting(rs), VideohubMessage::ACK) =
+                            (&msg, &reply)
+                        {
+                            let actual = dummy.get_routes(IDX).await.unwrap();
+                            for r in rs {
+                                let last = rs.iter().rev().find(|o| o.to_output == r.to_output);
+                                prop_assert_eq!(
+                                    actual[r.to_output as usize].from_input,
+                                    Some(last.unwrap().from_input)
+                                );
+                            }
+                        }
+                    }
+                    Step::Reconnect(n) => {
+                        sessions[n].end_session().await;
+                        sessions[n] = session();
+                    }
+                    Step::SetAlive(alive) => dummy.set_alive(alive),
+                    Step::ExternalRoute(p) => dummy.update_routes(IDX, vec![p]).await.unwrap(),
+                    Step::ExternalFrameLock(l) => {
+                        dummy.update_frame_locks(IDX, vec![l]).await.unwrap();
+                        // The event as every connection would get it.
+                        let ev = RouterEvent::FrameLockUpdate(
+                            IDX,
+                            dummy.get_frame_locks(IDX).await.unwrap(),
+                        );
+                        for fe in &sessions {
+                            fe.handle_event(ev.clone()).await.unwrap();
+                        }
+                    }
+                }
+                check_invariants(&dummy, &sessions).await?;
+            }
+            Ok(())
+        }
+
+        proptest! {
+            #[test]
+            fn arbitrary_sequences(steps in prop::collection::vec(step(), 1..40)) {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap()
+                    .block_on(run(steps))?;
+            }
+        }
+    }
 }
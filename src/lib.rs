@@ -1,3 +1,12 @@
 pub mod backend;
+pub mod bridge;
+#[cfg(all(unix, feature = "control"))]
+pub mod control;
 pub mod frontend;
 pub mod matrix;
+#[cfg(feature = "serde")]
+mod persist;
+#[cfg(test)]
+pub(crate) mod test_utils;
+#[cfg(unix)]
+mod unix_socket;
@@ -23,8 +23,17 @@ async fn main() {
     let router = Arc::new(NDIRouter::new("OmniRouter", vec!["Public"], 32, 4).unwrap());
     let videohub = VideohubFrontend::new(router, 0);
 
-    videohub
-        .listen("0.0.0.0:9990".parse().unwrap())
-        .await
-        .unwrap();
+    // Set OMNIMATRIX_IPV6 to serve IPv6 clients too.
+    let is_ipv6 = std::env::var_os("OMNIMATRIX_IPV6").is_some();
+    if is_ipv6 {
+        videohub
+            .listen_v6("[::]:9990".parse().unwrap())
+            .await
+            .unwrap();
+    } else {
+        videohub
+            .listen("0.0.0.0:9990".parse().unwrap())
+            .await
+            .unwrap();
+    }
 }
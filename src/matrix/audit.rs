@@ -0,0 +1,844 @@
+//! Change log of everything happening to any router
+//!
+//! Wraps a [MatrixRouter], appending an [AuditEntry] as a line of JSON for every route, label
+//! and lock change made through it, as well as for changes its events announce from elsewhere.
+//! Routes of levels other than 0 are not audited, neither are staged routes until committed.
+//! Everything passes through.
+
+use super::*;
+use anyhow::{Context, Result};
+use futures_core::stream::BoxStream;
+use std::collections::{hash_map::Entry, HashMap};
+use std::future::Future;
+use std::mem::{discriminant, Discriminant};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+use tracing::{debug, error};
+
+/// How long to wait before subscribing to events again once the stream ended.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+/// Where an audited change came from.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditSource {
+    /// Made through the [AuditRouter].
+    Api,
+    /// Only seen in the events of the wrapped router, made by someone else.
+    External,
+}
+
+/// Entries of a matrix that changed, by kind.
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", content = "entries", rename_all = "snake_case")]
+pub enum AuditChange {
+    Routes(Vec<RouterPatch>),
+    FrameRoutes(Vec<RouterPatch>),
+    InputLabels(Vec<RouterLabel>),
+    OutputLabels(Vec<RouterLabel>),
+    FrameLabels(Vec<RouterLabel>),
+    Locks(Vec<RouterLock>),
+    FrameLocks(Vec<RouterLock>),
+    ProcessingUnitLocks(Vec<RouterLock>),
+}
+
+/// Entries of a table, identified by the port they are about.
+trait Keyed: Clone + PartialEq {
+    fn key(&self) -> u32;
+}
+
+impl Keyed for RouterPatch {
+    fn key(&self) -> u32 {
+        self.to_output
+    }
+}
+
+impl Keyed for RouterLabel {
+    fn key(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Keyed for RouterLock {
+    fn key(&self) -> u32 {
+        self.id
+    }
+}
+
+/// Merge `update` into `known`, returning the entries that differ.
+fn merge<T: Keyed>(known: &mut Vec<T>, update: Vec<T>) -> Vec<T> {
+    update
+        .into_iter()
+        .filter(|e| match known.iter_mut().find(|k| k.key() == e.key()) {
+            Some(k) if k == e => false,
+            Some(k) => {
+                *k = e.clone();
+                true
+            }
+            None => {
+                known.push(e.clone());
+                true
+            }
+        })
+        .collect()
+}
+
+/// Drop entries of `diff` that were `expected`, which aren't anymore then. Expectations of
+/// ports that changed otherwise are dropped as well.
+fn unexpected<T: Keyed>(diff: Vec<T>, expected: &mut Vec<T>) -> Vec<T> {
+    diff.into_iter()
+        .filter(|e| match expected.iter().position(|x| x.key() == e.key()) {
+            Some(i) => expected.swap_remove(i) != *e,
+            None => true,
+        })
+        .collect()
+}
+
+impl AuditChange {
+    /// The change an event announces, along with the matrix it happened on.
+    fn from_event(ev: RouterEvent) -> Option<(u32, Self)> {
+        // Snapshots only change what differs from what is known.
+        Some(match ev.into_update() {
+            RouterEvent::RouteUpdate(index, ps) => (index, Self::Routes(ps)),
+            RouterEvent::FrameRouteUpdate(index, ps) => (index, Self::FrameRoutes(ps)),
+            RouterEvent::InputLabelUpdate(index, ls) => (index, Self::InputLabels(ls)),
+            RouterEvent::OutputLabelUpdate(index, ls) => (index, Self::OutputLabels(ls)),
+            RouterEvent::FrameLabelUpdate(index, ls) => (index, Self::FrameLabels(ls)),
+            RouterEvent::LockUpdate(index, ls) => (index, Self::Locks(ls)),
+            RouterEvent::FrameLockUpdate(index, ls) => (index, Self::FrameLocks(ls)),
+            RouterEvent::ProcessingUnitLockUpdate(index, ls) => {
+                (index, Self::ProcessingUnitLocks(ls))
+            }
+            _ => return None,
+        })
+    }
+
+    /// Merge `update` of the same kind into this, returning the entries that differ, if any.
+    fn merge(&mut self, update: Self) -> Option<Self> {
+        use AuditChange::*;
+        let diff = match (self, update) {
+            (Routes(k), Routes(u)) => Routes(merge(k, u)),
+            (FrameRoutes(k), FrameRoutes(u)) => FrameRoutes(merge(k, u)),
+            (InputLabels(k), InputLabels(u)) => InputLabels(merge(k, u)),
+            (OutputLabels(k), OutputLabels(u)) => OutputLabels(merge(k, u)),
+            (FrameLabels(k), FrameLabels(u)) => FrameLabels(merge(k, u)),
+            (Locks(k), Locks(u)) => Locks(merge(k, u)),
+            (FrameLocks(k), FrameLocks(u)) => FrameLocks(merge(k, u)),
+            (ProcessingUnitLocks(k), ProcessingUnitLocks(u)) => ProcessingUnitLocks(merge(k, u)),
+            _ => return None,
+        };
+        (!diff.is_empty()).then_some(diff)
+    }
+
+    /// Drop what was `expected` of the same kind from this diff, returning the rest, if any.
+    fn unexpected(self, expected: &mut Self) -> Option<Self> {
+        use AuditChange::*;
+        let rest = match (self, expected) {
+            (Routes(d), Routes(e)) => Routes(unexpected(d, e)),
+            (FrameRoutes(d), FrameRoutes(e)) => FrameRoutes(unexpected(d, e)),
+            (InputLabels(d), InputLabels(e)) => InputLabels(unexpected(d, e)),
+            (OutputLabels(d), OutputLabels(e)) => OutputLabels(unexpected(d, e)),
+            (FrameLabels(d), FrameLabels(e)) => FrameLabels(unexpected(d, e)),
+            (Locks(d), Locks(e)) => Locks(unexpected(d, e)),
+            (FrameLocks(d), FrameLocks(e)) => FrameLocks(unexpected(d, e)),
+            (ProcessingUnitLocks(d), ProcessingUnitLocks(e)) => {
+                ProcessingUnitLocks(unexpected(d, e))
+            }
+            (d, _) => d,
+        };
+        (!rest.is_empty()).then_some(rest)
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::Routes(ps) | Self::FrameRoutes(ps) => ps.is_empty(),
+            Self::InputLabels(ls) | Self::OutputLabels(ls) | Self::FrameLabels(ls) => ls.is_empty(),
+            Self::Locks(ls) | Self::FrameLocks(ls) | Self::ProcessingUnitLocks(ls) => ls.is_empty(),
+        }
+    }
+}
+
+/// A line of the audit log.
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AuditEntry {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub matrix: u32,
+    pub change: AuditChange,
+    /// Who made the change, if the [AuditRouter] it went through knew.
+    pub actor: Option<String>,
+    pub source: AuditSource,
+}
+
+impl AuditEntry {
+    fn now(matrix: u32, change: AuditChange, actor: Option<&str>, source: AuditSource) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        Self {
+            timestamp,
+            matrix,
+            change,
+            actor: actor.map(Into::into),
+            source,
+        }
+    }
+}
+
+/// Entries of every matrix, by kind.
+type Tables = HashMap<(u32, Discriminant<AuditChange>), AuditChange>;
+
+/// Merge `change` into `tables` for matrix `index`, returning what differs.
+///
+/// Nothing differs the first time a kind of change is seen, it is only learned.
+fn learn(tables: &mut Tables, index: u32, change: AuditChange) -> Option<AuditChange> {
+    match tables.entry((index, discriminant(&change))) {
+        Entry::Vacant(v) => {
+            v.insert(change);
+            None
+        }
+        Entry::Occupied(mut o) => o.get_mut().merge(change),
+    }
+}
+
+/// Where entries go, along with what is known of the router to tell what events changed.
+struct Log {
+    writer: Box<dyn AsyncWrite + Send + Sync + Unpin>,
+    /// Last seen entries, as of the events so far.
+    known: Tables,
+    /// Changes made through the wrapper, until their events show up.
+    expected: Tables,
+    /// Number of entries written.
+    written: Arc<AtomicU64>,
+}
+
+impl Log {
+    /// Remember `change` to matrix `index` was made through the wrapper.
+    fn expect(&mut self, index: u32, change: AuditChange) {
+        learn(&mut self.expected, index, change);
+    }
+
+    /// What `change` to matrix `index` changes compared to what is known, without learning it.
+    fn compare(&self, index: u32, change: AuditChange) -> Option<AuditChange> {
+        let mut known = self.known.get(&(index, discriminant(&change)))?.clone();
+        known.merge(change)
+    }
+
+    /// Learn of `change` to matrix `index` from an event, returning what differs and wasn't
+    /// made through the wrapper.
+    fn observe(&mut self, index: u32, change: AuditChange) -> Option<AuditChange> {
+        let key = (index, discriminant(&change));
+        let diff = learn(&mut self.known, index, change)?;
+        match self.expected.get_mut(&key) {
+            Some(expected) => diff.unexpected(expected),
+            None => Some(diff),
+        }
+    }
+
+    /// Append `entry` as a line, flushing right away.
+    async fn write(&mut self, entry: &AuditEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await?;
+        self.writer.flush().await?;
+        self.written.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Learn everything audited about every matrix of `router`, as far as it tells.
+async fn seed(router: &impl MatrixRouter, log: &mut Log) {
+    let matrix_count = match router.get_router_info().await {
+        Ok(info) => info.matrix_count.unwrap_or(1),
+        Err(e) => {
+            debug!(
+                "Router info unavailable, learning from events only: {:#}",
+                e
+            );
+            return;
+        }
+    };
+    for index in 0..matrix_count {
+        let (routes, frame_routes, ins, outs, frames, locks, frame_locks, pu_locks) = tokio::join!(
+            router.get_routes(index),
+            router.get_frame_routes(index),
+            router.get_input_labels(index),
+            router.get_output_labels(index),
+            router.get_frame_labels(index),
+            router.get_locks(index),
+            router.get_frame_locks(index),
+            router.get_processing_unit_locks(index)
+        );
+        let tables = [
+            routes.map(AuditChange::Routes),
+            frame_routes.map(AuditChange::FrameRoutes),
+            ins.map(AuditChange::InputLabels),
+            outs.map(AuditChange::OutputLabels),
+            frames.map(AuditChange::FrameLabels),
+            locks.map(AuditChange::Locks),
+            frame_locks.map(AuditChange::FrameLocks),
+            pu_locks.map(AuditChange::ProcessingUnitLocks),
+        ];
+        for change in tables.into_iter().flatten() {
+            learn(&mut log.known, index, change);
+        }
+    }
+}
+
+/// Audit what events of `inner` announce beyond what is known, signalling `ready` once
+/// subscribed for the first time.
+async fn listen(inner: impl MatrixRouter, log: Arc<Mutex<Log>>, ready: oneshot::Sender<()>) {
+    let mut ready = Some(ready);
+    loop {
+        match inner.event_stream().await {
+            Ok(mut events) => {
+                if let Some(ready) = ready.take() {
+                    seed(&inner, &mut *log.lock().await).await;
+                    let _ = ready.send(());
+                }
+                while let Some(ev) = events.next().await {
+                    let Some((index, change)) = AuditChange::from_event(ev) else {
+                        continue;
+                    };
+                    let mut log = log.lock().await;
+                    let Some(diff) = log.observe(index, change) else {
+                        continue;
+                    };
+                    let entry = AuditEntry::now(index, diff, None, AuditSource::External);
+                    if let Err(e) = log.write(&entry).await {
+                        error!("Failed to audit external change: {:#}", e);
+                    }
+                }
+                debug!("Event stream ended, external changes go unaudited until it is back");
+            }
+            Err(e) => error!("No event stream to audit external changes from: {:#}", e),
+        }
+        // Don't hold up construction for a router without events.
+        if let Some(ready) = ready.take() {
+            let _ = ready.send(());
+        }
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+/// Aborts the listener once the last clone of an [AuditRouter] is gone.
+struct Listener(JoinHandle<()>);
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Router wrapper auditing changes to `R`, see the module docs.
+#[derive(Clone)]
+pub struct AuditRouter<R> {
+    inner: R,
+    actor: Option<Arc<str>>,
+    log: Arc<Mutex<Log>>,
+    written: Arc<AtomicU64>,
+    _listener: Arc<Listener>,
+}
+
+impl<R> AuditRouter<R>
+where
+    R: MatrixRouter + Clone + 'static,
+{
+    /// Audit changes to `inner` into `writer`.
+    ///
+    /// Changes its events announce are told apart from those made through this wrapper by
+    /// what they change compared to what was known before. Listening happens in a task of
+    /// its own, so this must be called within a Tokio runtime.
+    pub async fn new(inner: R, writer: impl AsyncWrite + Send + Sync + Unpin + 'static) -> Self {
+        let written = Arc::new(AtomicU64::new(0));
+        let log = Arc::new(Mutex::new(Log {
+            writer: Box::new(writer),
+            known: HashMap::new(),
+            expected: HashMap::new(),
+            written: Arc::clone(&written),
+        }));
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let listener = tokio::spawn(listen(inner.clone(), Arc::clone(&log), ready_tx));
+        let _ = ready_rx.await;
+        Self {
+            inner,
+            actor: None,
+            log,
+            written,
+            _listener: Arc::new(Listener(listener)),
+        }
+    }
+
+    /// Audit changes to `inner` into the file at `path`, appending to it if it exists.
+    pub async fn open(inner: R, path: &Path) -> Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+        Ok(Self::new(inner, file).await)
+    }
+}
+
+impl<R: MatrixRouter> AuditRouter<R> {
+    /// The wrapped router.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// A handle sharing the log of this one, attributing changes made through it to `actor`.
+    ///
+    /// Cheap enough to make one per call: `router.with_actor("alice").update_routes(..)`.
+    pub fn with_actor(&self, actor: &str) -> Self
+    where
+        R: Clone,
+    {
+        Self {
+            actor: Some(actor.into()),
+            ..self.clone()
+        }
+    }
+
+    /// Who changes made through this handle are attributed to.
+    pub fn actor(&self) -> Option<&str> {
+        self.actor.as_deref()
+    }
+
+    /// Apply `update`, auditing `change` to matrix `index` once it succeeded.
+    async fn audited(
+        &self,
+        index: u32,
+        change: AuditChange,
+        update: impl Future<Output = Result<()>>,
+    ) -> Result<()> {
+        // Held throughout, so the change is expected by the time its events are looked at.
+        let mut log = self.log.lock().await;
+        update.await?;
+        log.expect(index, change.clone());
+        let entry = AuditEntry::now(index, change, self.actor(), AuditSource::Api);
+        log.write(&entry)
+            .await
+            .context("Change was applied, but could not be audited")
+    }
+}
+
+impl<R: MatrixRouter> MatrixRouter for AuditRouter<R> {
+    async fn is_alive(&self) -> Result<bool> {
+        self.inner.is_alive().await
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.inner.get_router_info().await
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        self.inner.get_matrix_info(index).await
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_input_labels(index).await
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_output_labels(index).await
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        let change = AuditChange::InputLabels(changed.clone());
+        self.audited(
+            index,
+            change,
+            self.inner.update_input_labels(index, changed),
+        )
+        .await
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        let change = AuditChange::OutputLabels(changed.clone());
+        self.audited(
+            index,
+            change,
+            self.inner.update_output_labels(index, changed),
+        )
+        .await
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_input_descriptions(index).await
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_output_descriptions(index).await
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_input_descriptions(index, changed).await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_output_descriptions(index, changed).await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_input_status(index).await
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_output_status(index).await
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_routes(index).await
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        self.inner.get_route(index, output).await
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        self.inner.get_route_for_input(index, input).await
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let change = AuditChange::Routes(changes.clone());
+        self.audited(index, change, self.inner.update_routes(index, changes))
+            .await
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let change = AuditChange::Routes(changes.clone());
+        self.audited(
+            index,
+            change,
+            self.inner.update_routes_atomic(index, changes),
+        )
+        .await
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.inner.get_levels(index).await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_level_routes(index, level).await
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        self.inner.update_level_routes(index, level, changes).await
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        self.inner.stage_routes(index, changes).await
+    }
+
+    /// Audits the routes that changed, a take doesn't tell what it will change by itself.
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        let mut log = self.log.lock().await;
+        self.inner.commit(handle).await?;
+        let routes = self.inner.get_routes(handle.index).await?;
+        if let Some(diff) = log.compare(handle.index, AuditChange::Routes(routes)) {
+            log.expect(handle.index, diff.clone());
+            let entry = AuditEntry::now(handle.index, diff, self.actor(), AuditSource::Api);
+            log.write(&entry)
+                .await
+                .context("Take was committed, but could not be audited")?;
+        }
+        Ok(())
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.discard(handle).await
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_staged_routes(index).await
+    }
+
+    async fn get_frame_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_frame_labels(index).await
+    }
+
+    async fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        let change = AuditChange::FrameLabels(changed.clone());
+        self.audited(
+            index,
+            change,
+            self.inner.update_frame_labels(index, changed),
+        )
+        .await
+    }
+
+    async fn get_frame_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_frame_routes(index).await
+    }
+
+    async fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let change = AuditChange::FrameRoutes(changes.clone());
+        self.audited(
+            index,
+            change,
+            self.inner.update_frame_routes(index, changes),
+        )
+        .await
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_locks(index).await
+    }
+
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        let change = AuditChange::Locks(changes.clone());
+        self.audited(index, change, self.inner.update_locks(index, changes))
+            .await
+    }
+
+    /// Audited into the same log, if `R` takes locks on behalf of owners.
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        Some(Self {
+            inner: self.inner.with_lock_owner(owner)?,
+            actor: self.actor.clone(),
+            log: self.log.clone(),
+            written: self.written.clone(),
+            _listener: self._listener.clone(),
+        })
+    }
+
+    async fn get_frame_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_frame_locks(index).await
+    }
+
+    async fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        let change = AuditChange::FrameLocks(changes.clone());
+        self.audited(index, change, self.inner.update_frame_locks(index, changes))
+            .await
+    }
+
+    async fn get_processing_unit_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_processing_unit_locks(index).await
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        let change = AuditChange::ProcessingUnitLocks(changes.clone());
+        let update = self.inner.update_processing_unit_locks(index, changes);
+        self.audited(index, change, update).await
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        self.inner.get_port_metadata(index, kind, id).await
+    }
+
+    async fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> Result<()> {
+        self.inner
+            .set_port_metadata(index, kind, id, metadata)
+            .await
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        self.inner.get_alarms().await
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        self.inner.event_stream().await
+    }
+}
+
+impl<R: RouterIntrospect> RouterIntrospect for AuditRouter<R> {
+    fn name(&self) -> &'static str {
+        "AuditRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        let actor = self.actor.as_deref().unwrap_or("none");
+        vec![("actor".into(), actor.into())]
+    }
+
+    fn counters(&self) -> Vec<(String, u64)> {
+        vec![("written".into(), self.written.load(Ordering::Relaxed))]
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        Some(&self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(id: u32, name: &str) -> RouterLabel {
+        RouterLabel {
+            id,
+            name: name.into(),
+        }
+    }
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    fn entries(path: &Path) -> Result<Vec<AuditEntry>> {
+        std::fs::read_to_string(path)?
+            .lines()
+            .map(|l| Ok(serde_json::from_str(l)?))
+            .collect()
+    }
+
+    /// Wait for the log at `path` to hold `count` entries.
+    async fn written(path: &Path, count: usize) -> Result<Vec<AuditEntry>> {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let entries = entries(path)?;
+                if entries.len() >= count {
+                    return Ok(entries);
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?
+    }
+
+    #[tokio::test]
+    async fn logs_changes_made_through_it() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("audit.jsonl");
+        let router =
+            AuditRouter::open(DummyRouter::with_config(1, 4, 4).with_output_locks(), &path).await?;
+        router
+            .with_actor("alice")
+            .update_routes(0, vec![patch(3, 1)])
+            .await?;
+        router
+            .update_output_labels(0, vec![label(2, "Program")])
+            .await?;
+        let lock = RouterLock {
+            id: 1,
+            locked: true,
+        };
+        router.with_actor("bob").update_locks(0, vec![lock]).await?;
+        // Refused changes didn't happen.
+        assert!(router.update_routes(0, vec![patch(9, 0)]).await.is_err());
+
+        // Give the events of these changes a chance to be mistaken for external ones.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let log = entries(&path)?;
+        let summary: Vec<_> = log
+            .iter()
+            .map(|e| (e.matrix, e.change.clone(), e.actor.as_deref(), e.source))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    0,
+                    AuditChange::Routes(vec![patch(3, 1)]),
+                    Some("alice"),
+                    AuditSource::Api
+                ),
+                (
+                    0,
+                    AuditChange::OutputLabels(vec![label(2, "Program")]),
+                    None,
+                    AuditSource::Api
+                ),
+                (
+                    0,
+                    AuditChange::Locks(vec![lock]),
+                    Some("bob"),
+                    AuditSource::Api
+                ),
+            ]
+        );
+        assert!(log.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+        assert_eq!(
+            describe_router(&router)[0].counters,
+            vec![("written".to_string(), 3)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn logs_external_changes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("audit.jsonl");
+        let dummy = DummyRouter::with_config(2, 4, 4);
+        let router = AuditRouter::open(dummy.clone(), &path).await?;
+
+        // Changed behind its back, only the events tell.
+        dummy.update_routes(1, vec![patch(2, 3)]).await?;
+        dummy
+            .update_input_labels(0, vec![label(0, "Camera 1")])
+            .await?;
+        // Made through it while those events may still be underway.
+        router
+            .with_actor("alice")
+            .update_routes(1, vec![patch(1, 0)])
+            .await?;
+
+        written(&path, 3).await?;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let log = entries(&path)?;
+        assert_eq!(log.len(), 3);
+        let find = |change: AuditChange| {
+            log.iter()
+                .find(|e| e.change == change)
+                .map(|e| (e.matrix, e.actor.as_deref(), e.source))
+        };
+        // Only what changed, not everything the events carry.
+        assert_eq!(
+            find(AuditChange::Routes(vec![patch(2, 3)])),
+            Some((1, None, AuditSource::External))
+        );
+        assert_eq!(
+            find(AuditChange::InputLabels(vec![label(0, "Camera 1")])),
+            Some((0, None, AuditSource::External))
+        );
+        assert_eq!(
+            find(AuditChange::Routes(vec![patch(1, 0)])),
+            Some((1, Some("alice"), AuditSource::Api))
+        );
+
+        // The log is appended to by whoever opens it next.
+        drop(router);
+        let router = AuditRouter::open(dummy, &path).await?;
+        router.update_routes(0, vec![patch(1, 1)]).await?;
+        assert_eq!(written(&path, 4).await?.len(), 4);
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+//! Operation budgets
+//!
+//! Lets chatty consumers of a [super::MatrixRouter] issue operations at a bounded rate,
+//! see [super::RateLimitedRouter].
+
+use anyhow::{anyhow, Result};
+use std::time::{Duration, Instant};
+
+/// Token bucket granting `rate` operations per second, with bursts of up to `burst`.
+#[derive(Clone, Debug)]
+pub struct Budget {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    /// Last refill, unset until first use.
+    last: Option<Instant>,
+}
+
+impl Budget {
+    /// Create a full bucket, `rate` has to be positive.
+    pub fn new(rate: f64, burst: u32) -> Result<Self> {
+        if !(rate.is_finite() && rate > 0.0) {
+            return Err(anyhow!("Budget rate has to be positive, got {}", rate));
+        }
+        let burst = f64::from(burst.max(1));
+        Ok(Self {
+            rate,
+            burst,
+            tokens: burst,
+            last: None,
+        })
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let last = *self.last.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = Some(last.max(now));
+    }
+
+    /// Take a token if one is available at `now`.
+    pub fn try_take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Time from `now` until the next token is available.
+    pub fn wait_time(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_burst_and_refill() {
+        let start = Instant::now();
+        let mut b = Budget::new(10.0, 3).unwrap();
+        for _ in 0..3 {
+            assert!(b.try_take(start));
+        }
+        assert!(!b.try_take(start));
+        assert_eq!(b.wait_time(start), Duration::from_millis(100));
+
+        assert!(b.try_take(start + Duration::from_millis(100)));
+        assert!(!b.try_take(start + Duration::from_millis(100)));
+
+        // Refilling never exceeds the burst.
+        let later = start + Duration::from_secs(10);
+        for _ in 0..3 {
+            assert!(b.try_take(later));
+        }
+        assert!(!b.try_take(later));
+    }
+
+    #[test]
+    fn rejects_nonpositive_rates() {
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(Budget::new(rate, 1).is_err());
+        }
+    }
+}
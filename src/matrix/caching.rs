@@ -0,0 +1,633 @@
+//! Read caching for any router
+//!
+//! Wraps a [MatrixRouter], keeping router info, matrix info, labels and routes around for a
+//! while so chatty clients don't hit slow backends for every request. Entries are dropped once
+//! they expire, when events announce a change, or when changes go through the wrapper.
+//! Everything else passes through.
+
+use super::*;
+use anyhow::Result;
+use futures_core::stream::BoxStream;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+use tracing::{debug, error};
+
+/// What a cache entry holds.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum Key {
+    RouterInfo,
+    MatrixInfo(u32),
+    InputLabels(u32),
+    OutputLabels(u32),
+    Routes(u32),
+}
+
+impl Key {
+    fn index(&self) -> Option<u32> {
+        match *self {
+            Key::RouterInfo => None,
+            Key::MatrixInfo(index)
+            | Key::InputLabels(index)
+            | Key::OutputLabels(index)
+            | Key::Routes(index) => Some(index),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Cached {
+    RouterInfo(RouterInfo),
+    MatrixInfo(RouterMatrixInfo),
+    Labels(Vec<RouterLabel>),
+    Routes(Vec<RouterPatch>),
+}
+
+/// A cache entry, filled by the first reader and shared with everyone asking meanwhile.
+/// Holds the time it was filled at.
+type Slot = Arc<OnceCell<(Instant, Cached)>>;
+
+/// Aborts the event listener once the last clone of a [CachingRouter] is gone.
+struct Listener(JoinHandle<()>);
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Router wrapper caching reads from `R`, see the module docs.
+///
+/// Failed reads aren't cached. Concurrent reads of an entry not cached yet are served by a
+/// single call to `R`.
+#[derive(Clone)]
+pub struct CachingRouter<R> {
+    inner: R,
+    ttl: Duration,
+    slots: Arc<Mutex<HashMap<Key, Slot>>>,
+    /// Number of reads served from the cache and passed on to `R`, respectively.
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+    _listener: Arc<Listener>,
+}
+
+impl<R> CachingRouter<R>
+where
+    R: MatrixRouter + Clone + 'static,
+{
+    /// Cache reads from `inner` for up to `ttl`.
+    ///
+    /// Listens to the events of `inner` in a task of its own, so this must be called within a
+    /// Tokio runtime.
+    pub fn new(inner: R, ttl: Duration) -> Self {
+        let slots: Arc<Mutex<HashMap<Key, Slot>>> = Arc::default();
+        let listener = tokio::spawn(listen(inner.clone(), ttl, Arc::clone(&slots)));
+        Self {
+            inner,
+            ttl,
+            slots,
+            hits: Arc::new(AtomicU64::new(0)),
+            misses: Arc::new(AtomicU64::new(0)),
+            _listener: Arc::new(Listener(listener)),
+        }
+    }
+}
+
+/// Drop entries as the events of `inner` announce changes, everything if it comes or goes.
+async fn listen(inner: impl MatrixRouter, retry: Duration, slots: Arc<Mutex<HashMap<Key, Slot>>>) {
+    loop {
+        match inner.event_stream().await {
+            Ok(mut events) => {
+                while let Some(ev) = events.next().await {
+                    let mut slots = slots.lock().unwrap();
+                    match ev {
+                        RouterEvent::InputLabelUpdate(index, _)
+                        | RouterEvent::InputLabelSnapshot(index, _) => {
+                            slots.remove(&Key::InputLabels(index));
+                        }
+                        RouterEvent::OutputLabelUpdate(index, _)
+                        | RouterEvent::OutputLabelSnapshot(index, _) => {
+                            slots.remove(&Key::OutputLabels(index));
+                        }
+                        RouterEvent::RouteUpdate(index, _)
+                        | RouterEvent::RouteSnapshot(index, _) => {
+                            slots.remove(&Key::Routes(index));
+                        }
+                        // Ports came or went, labels and routes with them.
+                        RouterEvent::MatrixInfoUpdate(index, _) => {
+                            slots.retain(|key, _| key.index() != Some(index));
+                        }
+                        RouterEvent::Connected | RouterEvent::Disconnected => slots.clear(),
+                        _ => {}
+                    }
+                }
+                debug!("Event stream ended, dropping cache");
+            }
+            Err(e) => error!("No event stream to keep the cache fresh with: {:#}", e),
+        }
+        // Changes while not listening go unnoticed.
+        slots.lock().unwrap().clear();
+        tokio::time::sleep(retry).await;
+    }
+}
+
+impl<R: MatrixRouter> CachingRouter<R> {
+    /// The wrapped router.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Drop everything cached, the next reads go to the router.
+    pub fn invalidate_all(&self) {
+        self.slots.lock().unwrap().clear();
+    }
+
+    fn invalidate(&self, key: Key) {
+        self.slots.lock().unwrap().remove(&key);
+    }
+
+    /// Entry `key`, fetched with `fetch` unless cached and not expired.
+    async fn cached<F>(&self, key: Key, fetch: F) -> Result<Cached>
+    where
+        F: Future<Output = Result<Cached>> + Send,
+    {
+        let slot = {
+            let mut slots = self.slots.lock().unwrap();
+            let slot = slots.entry(key).or_default();
+            if slot.get().is_some_and(|(at, _)| at.elapsed() >= self.ttl) {
+                *slot = Slot::default();
+            }
+            Arc::clone(slot)
+        };
+        let mut fetched = false;
+        let fetching = &mut fetched;
+        let (_, cached) = slot
+            .get_or_try_init(|| async move {
+                *fetching = true;
+                Ok::<_, anyhow::Error>((Instant::now(), fetch.await?))
+            })
+            .await?;
+        // Waiting for someone else's fetch counts as a hit, too.
+        let counter = if fetched { &self.misses } else { &self.hits };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(cached.clone())
+    }
+
+    async fn cached_labels(&self, key: Key) -> Result<Vec<RouterLabel>> {
+        let fetch = async {
+            Ok(Cached::Labels(match key {
+                Key::InputLabels(index) => self.inner.get_input_labels(index).await?,
+                Key::OutputLabels(index) => self.inner.get_output_labels(index).await?,
+                _ => unreachable!(),
+            }))
+        };
+        match self.cached(key, fetch).await? {
+            Cached::Labels(labels) => Ok(labels),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<R: MatrixRouter> MatrixRouter for CachingRouter<R> {
+    async fn is_alive(&self) -> Result<bool> {
+        self.inner.is_alive().await
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        let fetch = async { Ok(Cached::RouterInfo(self.inner.get_router_info().await?)) };
+        match self.cached(Key::RouterInfo, fetch).await? {
+            Cached::RouterInfo(info) => Ok(info),
+            _ => unreachable!(),
+        }
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        let fetch = async { Ok(Cached::MatrixInfo(self.inner.get_matrix_info(index).await?)) };
+        match self.cached(Key::MatrixInfo(index), fetch).await? {
+            Cached::MatrixInfo(mi) => Ok(mi),
+            _ => unreachable!(),
+        }
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.cached_labels(Key::InputLabels(index)).await
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.cached_labels(Key::OutputLabels(index)).await
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        let result = self.inner.update_input_labels(index, changed).await;
+        self.invalidate(Key::InputLabels(index));
+        result
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        let result = self.inner.update_output_labels(index, changed).await;
+        self.invalidate(Key::OutputLabels(index));
+        result
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_input_descriptions(index).await
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_output_descriptions(index).await
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_input_descriptions(index, changed).await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_output_descriptions(index, changed).await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_input_status(index).await
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_output_status(index).await
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        let fetch = async { Ok(Cached::Routes(self.inner.get_routes(index).await?)) };
+        match self.cached(Key::Routes(index), fetch).await? {
+            Cached::Routes(routes) => Ok(routes),
+            _ => unreachable!(),
+        }
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        self.inner.get_route(index, output).await
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        self.inner.get_route_for_input(index, input).await
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let result = self.inner.update_routes(index, changes).await;
+        self.invalidate(Key::Routes(index));
+        result
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let result = self.inner.update_routes_atomic(index, changes).await;
+        self.invalidate(Key::Routes(index));
+        result
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.inner.get_levels(index).await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_level_routes(index, level).await
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        let result = self.inner.update_level_routes(index, level, changes).await;
+        // Level 0 are the cached routes.
+        self.invalidate(Key::Routes(index));
+        result
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        let result = self.inner.stage_routes(index, changes).await;
+        // Routers without staging apply right away.
+        self.invalidate(Key::Routes(index));
+        result
+    }
+
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        let result = self.inner.commit(handle).await;
+        self.invalidate(Key::Routes(handle.index));
+        result
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.discard(handle).await
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_staged_routes(index).await
+    }
+
+    async fn get_frame_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_frame_labels(index).await
+    }
+
+    async fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_frame_labels(index, changed).await
+    }
+
+    async fn get_frame_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_frame_routes(index).await
+    }
+
+    async fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.inner.update_frame_routes(index, changes).await
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_locks(index).await
+    }
+
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.inner.update_locks(index, changes).await
+    }
+
+    /// Sharing the cache, if `R` takes locks on behalf of owners.
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        Some(Self {
+            inner: self.inner.with_lock_owner(owner)?,
+            ttl: self.ttl,
+            slots: self.slots.clone(),
+            hits: self.hits.clone(),
+            misses: self.misses.clone(),
+            _listener: self._listener.clone(),
+        })
+    }
+
+    async fn get_frame_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_frame_locks(index).await
+    }
+
+    async fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.inner.update_frame_locks(index, changes).await
+    }
+
+    async fn get_processing_unit_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_processing_unit_locks(index).await
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        self.inner
+            .update_processing_unit_locks(index, changes)
+            .await
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        self.inner.get_port_metadata(index, kind, id).await
+    }
+
+    async fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> Result<()> {
+        self.inner
+            .set_port_metadata(index, kind, id, metadata)
+            .await
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        self.inner.get_alarms().await
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        self.inner.event_stream().await
+    }
+}
+
+impl<R: RouterIntrospect> RouterIntrospect for CachingRouter<R> {
+    fn name(&self) -> &'static str {
+        "CachingRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        vec![("ttl".into(), format!("{:?}", self.ttl))]
+    }
+
+    fn counters(&self) -> Vec<(String, u64)> {
+        vec![
+            ("hits".into(), self.hits.load(Ordering::Relaxed)),
+            ("misses".into(), self.misses.load(Ordering::Relaxed)),
+        ]
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        Some(&self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+
+    /// A [DummyRouter] counting reads of cached data in `calls`, each taking `latency`.
+    /// Reads fail while `failing` is set.
+    #[derive(Clone)]
+    struct CountingRouter {
+        inner: DummyRouter,
+        calls: Arc<AtomicUsize>,
+        latency: Duration,
+        failing: Arc<AtomicBool>,
+    }
+
+    impl CountingRouter {
+        async fn count(&self) -> Result<()> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.latency).await;
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(anyhow!("Router failed"));
+            }
+            Ok(())
+        }
+    }
+
+    impl MatrixRouter for CountingRouter {
+        async fn is_alive(&self) -> Result<bool> {
+            self.inner.is_alive().await
+        }
+        async fn get_router_info(&self) -> Result<RouterInfo> {
+            self.count().await?;
+            self.inner.get_router_info().await
+        }
+        async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+            self.count().await?;
+            self.inner.get_matrix_info(index).await
+        }
+        async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+            self.count().await?;
+            self.inner.get_input_labels(index).await
+        }
+        async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+            self.count().await?;
+            self.inner.get_output_labels(index).await
+        }
+        async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+            self.inner.update_input_labels(index, changed).await
+        }
+        async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+            self.inner.update_output_labels(index, changed).await
+        }
+        async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+            self.count().await?;
+            self.inner.get_routes(index).await
+        }
+        async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+            self.inner.get_route(index, output).await
+        }
+        async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+            self.inner.update_routes(index, changes).await
+        }
+        async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+            self.inner.event_stream().await
+        }
+    }
+
+    impl RouterIntrospect for CountingRouter {
+        fn name(&self) -> &'static str {
+            "CountingRouter"
+        }
+    }
+
+    fn counting(latency: Duration) -> (DummyRouter, Arc<AtomicUsize>, CountingRouter) {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let router = CountingRouter {
+            inner: dummy.clone(),
+            calls: Arc::clone(&calls),
+            latency,
+            failing: Arc::default(),
+        };
+        (dummy, calls, router)
+    }
+
+    /// Cache `counting`, giving the listener time to subscribe so it won't drop entries
+    /// mid-test when seeing [RouterEvent::Connected].
+    async fn caching(counting: CountingRouter, ttl: Duration) -> CachingRouter<CountingRouter> {
+        let router = CachingRouter::new(counting, ttl);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        router
+    }
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    #[tokio::test]
+    async fn coalesces_reads() -> Result<()> {
+        let (_, calls, counting) = counting(Duration::from_millis(50));
+        let router = caching(counting, Duration::from_secs(60)).await;
+        let reads: Vec<_> = (0..10).map(|_| router.get_routes(0)).collect();
+        for routes in futures_util::future::join_all(reads).await {
+            assert_eq!(routes?, vec![patch(0, 0), patch(0, 1)]);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        router.get_routes(0).await?;
+        router.get_router_info().await?;
+        router.get_router_info().await?;
+        router.get_matrix_info(0).await?;
+        router.get_input_labels(0).await?;
+        router.get_output_labels(0).await?;
+        router.get_output_labels(0).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+        router.invalidate_all();
+        router.get_routes(0).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 6);
+        assert_eq!(
+            describe_router(&router)[0].counters,
+            vec![("hits".to_string(), 12), ("misses".to_string(), 6)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expires() -> Result<()> {
+        let (_, calls, counting) = counting(Duration::ZERO);
+        let router = caching(counting, Duration::from_millis(50)).await;
+        router.get_input_labels(0).await?;
+        router.get_input_labels(0).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        router.get_input_labels(0).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failures_not_cached() -> Result<()> {
+        let (_, calls, counting) = counting(Duration::ZERO);
+        let failing = Arc::clone(&counting.failing);
+        let router = caching(counting, Duration::from_secs(60)).await;
+        failing.store(true, Ordering::SeqCst);
+        assert!(router.get_routes(0).await.is_err());
+        failing.store(false, Ordering::SeqCst);
+        router.get_routes(0).await?;
+        router.get_routes(0).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn events_invalidate() -> Result<()> {
+        let (dummy, calls, counting) = counting(Duration::ZERO);
+        let router = caching(counting, Duration::from_secs(60)).await;
+        // Changes going through the wrapper show up right away.
+        router.get_routes(0).await?;
+        router.update_routes(0, vec![patch(1, 0)]).await?;
+        assert_eq!(router.get_routes(0).await?[0], patch(1, 0));
+
+        // Changes elsewhere once their event arrived.
+        router.get_output_labels(0).await?;
+        let calls_before = calls.load(Ordering::SeqCst);
+        dummy
+            .update_output_labels(
+                0,
+                vec![RouterLabel {
+                    id: 1,
+                    name: "Program".into(),
+                }],
+            )
+            .await?;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while router.get_output_labels(0).await.unwrap()[1].name != "Program" {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+        assert!(calls.load(Ordering::SeqCst) > calls_before);
+        Ok(())
+    }
+}
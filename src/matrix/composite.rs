@@ -0,0 +1,805 @@
+//! Several routers as one
+//!
+//! Concatenates the inputs and outputs of its children, in order, into one larger matrix per
+//! index. Every port belongs to exactly one child, so patches can't cross from one child to
+//! another, there are no tie-lines between them.
+
+use super::*;
+use anyhow::{anyhow, Result};
+use async_stream::stream;
+use futures_core::stream::BoxStream;
+use futures_util::stream::select_all;
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use tracing::error;
+
+/// Router combining the matrices of its children, see the module docs.
+///
+/// Only labels, descriptions, port status and routes are combined, alarms of all children
+/// are reported together.
+/// Frame buffers, levels, locks and staging are left to the children, the combined matrix
+/// has none of them.
+#[derive(Clone)]
+pub struct CompositeRouter {
+    children: Vec<Arc<dyn dynamic::DynMatrixRouter>>,
+}
+
+/// Where the ports of every child of a matrix start in the combined numbering.
+struct Layout {
+    infos: Vec<RouterMatrixInfo>,
+    input_offsets: Vec<u32>,
+    output_offsets: Vec<u32>,
+}
+
+impl Layout {
+    fn new(infos: Vec<RouterMatrixInfo>) -> Self {
+        let offsets = |count: fn(&RouterMatrixInfo) -> u32| {
+            infos
+                .iter()
+                .scan(0, |next, mi| {
+                    let start = *next;
+                    *next += count(mi);
+                    Some(start)
+                })
+                .collect()
+        };
+        Self {
+            input_offsets: offsets(|mi| mi.input_count),
+            output_offsets: offsets(|mi| mi.output_count),
+            infos,
+        }
+    }
+
+    fn combined(&self) -> RouterMatrixInfo {
+        RouterMatrixInfo {
+            input_count: self.infos.iter().map(|mi| mi.input_count).sum(),
+            output_count: self.infos.iter().map(|mi| mi.output_count).sum(),
+            frame_count: 0,
+            name: None,
+        }
+    }
+
+    /// Child owning combined port `id` of `kind`, and its id there.
+    fn locate(&self, kind: LabelKind, id: u32) -> Option<(usize, u32)> {
+        (0..self.infos.len()).rev().find_map(|child| {
+            let (offset, count) = self.port_range(kind, child);
+            (id >= offset && id - offset < count).then(|| (child, id - offset))
+        })
+    }
+
+    fn port_range(&self, kind: LabelKind, child: usize) -> (u32, u32) {
+        let mi = &self.infos[child];
+        match kind {
+            LabelKind::Input => (self.input_offsets[child], mi.input_count),
+            LabelKind::Output => (self.output_offsets[child], mi.output_count),
+            LabelKind::Frame => (0, 0),
+        }
+    }
+
+    fn to_combined_labels(
+        &self,
+        kind: LabelKind,
+        child: usize,
+        labels: Vec<RouterLabel>,
+    ) -> Vec<RouterLabel> {
+        let (offset, _) = self.port_range(kind, child);
+        labels
+            .into_iter()
+            .map(|l| RouterLabel {
+                id: l.id + offset,
+                name: l.name,
+            })
+            .collect()
+    }
+
+    fn to_combined_descriptions(
+        &self,
+        kind: LabelKind,
+        child: usize,
+        descriptions: Vec<RouterDescription>,
+    ) -> Vec<RouterDescription> {
+        let (offset, _) = self.port_range(kind, child);
+        descriptions
+            .into_iter()
+            .map(|d| RouterDescription {
+                id: d.id + offset,
+                text: d.text,
+            })
+            .collect()
+    }
+
+    fn to_combined_statuses(
+        &self,
+        child: usize,
+        statuses: Vec<RouterPortStatus>,
+    ) -> Vec<RouterPortStatus> {
+        statuses
+            .into_iter()
+            .map(|st| {
+                let kind = match st.kind {
+                    PortKind::Input => LabelKind::Input,
+                    PortKind::Output => LabelKind::Output,
+                };
+                let (offset, _) = self.port_range(kind, child);
+                RouterPortStatus {
+                    id: st.id + offset,
+                    ..st
+                }
+            })
+            .collect()
+    }
+
+    fn to_combined_routes(&self, child: usize, patches: Vec<RouterPatch>) -> Vec<RouterPatch> {
+        patches
+            .into_iter()
+            .map(|p| RouterPatch {
+                from_input: p.from_input.map(|i| i + self.input_offsets[child]),
+                to_output: p.to_output + self.output_offsets[child],
+            })
+            .collect()
+    }
+}
+
+impl CompositeRouter {
+    /// Combine `children`, their ports numbered in the given order.
+    pub fn new(children: Vec<Arc<dyn dynamic::DynMatrixRouter>>) -> Self {
+        Self { children }
+    }
+
+    async fn layout(&self, index: u32) -> Result<Layout> {
+        let mut infos = Vec::with_capacity(self.children.len());
+        for child in &self.children {
+            infos.push(child.get_matrix_info(index).await?);
+        }
+        Ok(Layout::new(infos))
+    }
+
+    async fn get_labels(&self, index: u32, kind: LabelKind) -> Result<Vec<RouterLabel>> {
+        let layout = self.layout(index).await?;
+        let mut labels = Vec::new();
+        for (n, child) in self.children.iter().enumerate() {
+            let child_labels = match kind {
+                LabelKind::Input => child.get_input_labels(index).await?,
+                _ => child.get_output_labels(index).await?,
+            };
+            labels.extend(layout.to_combined_labels(kind, n, child_labels));
+        }
+        Ok(labels)
+    }
+
+    async fn update_labels(
+        &self,
+        index: u32,
+        kind: LabelKind,
+        changed: Vec<RouterLabel>,
+    ) -> Result<()> {
+        let layout = self.layout(index).await?;
+        layout.combined().check_labels(index, kind, &changed)?;
+        let mut per_child = vec![Vec::new(); self.children.len()];
+        for l in changed {
+            // Checked above, every label has an owner.
+            let (child, id) = layout.locate(kind, l.id).unwrap();
+            per_child[child].push(RouterLabel { id, name: l.name });
+        }
+        for (child, labels) in self.children.iter().zip(per_child) {
+            if labels.is_empty() {
+                continue;
+            }
+            match kind {
+                LabelKind::Input => child.update_input_labels(index, labels).await?,
+                _ => child.update_output_labels(index, labels).await?,
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_descriptions(
+        &self,
+        index: u32,
+        kind: LabelKind,
+    ) -> Result<Vec<RouterDescription>> {
+        let layout = self.layout(index).await?;
+        let mut descriptions = Vec::new();
+        for (n, child) in self.children.iter().enumerate() {
+            let child_descriptions = match kind {
+                LabelKind::Input => child.get_input_descriptions(index).await?,
+                _ => child.get_output_descriptions(index).await?,
+            };
+            descriptions.extend(layout.to_combined_descriptions(kind, n, child_descriptions));
+        }
+        Ok(descriptions)
+    }
+
+    async fn update_descriptions(
+        &self,
+        index: u32,
+        kind: LabelKind,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        let layout = self.layout(index).await?;
+        layout
+            .combined()
+            .check_descriptions(index, kind, &changed)?;
+        let mut per_child = vec![Vec::new(); self.children.len()];
+        for d in changed {
+            // Checked above, every description has an owner.
+            let (child, id) = layout.locate(kind, d.id).unwrap();
+            per_child[child].push(RouterDescription { id, text: d.text });
+        }
+        for (child, descriptions) in self.children.iter().zip(per_child) {
+            if descriptions.is_empty() {
+                continue;
+            }
+            match kind {
+                LabelKind::Input => child.update_input_descriptions(index, descriptions).await?,
+                _ => {
+                    child
+                        .update_output_descriptions(index, descriptions)
+                        .await?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_status(&self, index: u32, kind: PortKind) -> Result<Vec<RouterPortStatus>> {
+        let layout = self.layout(index).await?;
+        let mut statuses = Vec::new();
+        for (n, child) in self.children.iter().enumerate() {
+            let child_statuses = match kind {
+                PortKind::Input => child.get_input_status(index).await?,
+                PortKind::Output => child.get_output_status(index).await?,
+            };
+            statuses.extend(layout.to_combined_statuses(n, child_statuses));
+        }
+        Ok(statuses)
+    }
+
+    /// Split combined patches up by child, refusing any crossing from one child to another.
+    fn split_patches(
+        &self,
+        layout: &Layout,
+        index: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<Vec<Vec<RouterPatch>>> {
+        layout.combined().check_patches(index, &changes)?;
+        let mut per_child = vec![Vec::new(); self.children.len()];
+        for patch in changes {
+            let (child, to_output) = layout.locate(LabelKind::Output, patch.to_output).unwrap();
+            // Parked outputs stay on their own child.
+            let from_input = match patch.from_input {
+                Some(input) => {
+                    let (input_child, from_input) = layout.locate(LabelKind::Input, input).unwrap();
+                    if input_child != child {
+                        return Err(RouterError::CrossDevice { index, patch }.into());
+                    }
+                    Some(from_input)
+                }
+                None => None,
+            };
+            per_child[child].push(RouterPatch {
+                from_input,
+                to_output,
+            });
+        }
+        Ok(per_child)
+    }
+
+    /// Translate an event of child `n` to the combined numbering.
+    ///
+    /// Lifecycle events are handled by [CompositeRouter::event_stream], anything not combined
+    /// gets dropped. Snapshots of a child only cover part of the combined matrix, so they
+    /// become updates.
+    async fn translate(&self, n: usize, ev: RouterEvent) -> Option<RouterEvent> {
+        let ev = ev.into_update();
+        let index = match &ev {
+            RouterEvent::InputLabelUpdate(index, _)
+            | RouterEvent::OutputLabelUpdate(index, _)
+            | RouterEvent::RouteUpdate(index, _)
+            | RouterEvent::DescriptionUpdate(index, ..)
+            | RouterEvent::PortStatusUpdate(index, _)
+            | RouterEvent::MatrixInfoUpdate(index, _) => *index,
+            RouterEvent::AlarmUpdate(_) => {
+                return self.get_alarms().await.ok().map(RouterEvent::AlarmUpdate)
+            }
+            _ => return None,
+        };
+        let layout = self.layout(index).await.ok()?;
+        Some(match ev {
+            RouterEvent::InputLabelUpdate(index, labels) => RouterEvent::InputLabelUpdate(
+                index,
+                layout.to_combined_labels(LabelKind::Input, n, labels),
+            ),
+            RouterEvent::OutputLabelUpdate(index, labels) => RouterEvent::OutputLabelUpdate(
+                index,
+                layout.to_combined_labels(LabelKind::Output, n, labels),
+            ),
+            RouterEvent::RouteUpdate(index, patches) => {
+                RouterEvent::RouteUpdate(index, layout.to_combined_routes(n, patches))
+            }
+            RouterEvent::DescriptionUpdate(index, kind, descriptions) => {
+                let label_kind = match kind {
+                    PortKind::Input => LabelKind::Input,
+                    PortKind::Output => LabelKind::Output,
+                };
+                RouterEvent::DescriptionUpdate(
+                    index,
+                    kind,
+                    layout.to_combined_descriptions(label_kind, n, descriptions),
+                )
+            }
+            RouterEvent::PortStatusUpdate(index, statuses) => {
+                RouterEvent::PortStatusUpdate(index, layout.to_combined_statuses(n, statuses))
+            }
+            RouterEvent::MatrixInfoUpdate(index, _) => {
+                RouterEvent::MatrixInfoUpdate(index, layout.combined())
+            }
+            _ => unreachable!(),
+        })
+    }
+}
+
+impl MatrixRouter for CompositeRouter {
+    /// Alive only while all children are.
+    async fn is_alive(&self) -> Result<bool> {
+        for child in &self.children {
+            if !child.is_alive().await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        let mut matrix_count = None;
+        for child in &self.children {
+            let count = child.get_router_info().await?.matrix_count.unwrap_or(1);
+            matrix_count = Some(matrix_count.map_or(count, |c: u32| c.min(count)));
+        }
+        Ok(RouterInfo {
+            model: Some("CompositeRouter".into()),
+            name: None,
+            matrix_count,
+            matrix_names: None,
+        })
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        Ok(self.layout(index).await?.combined())
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.get_labels(index, LabelKind::Input).await
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.get_labels(index, LabelKind::Output).await
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.update_labels(index, LabelKind::Input, changed).await
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.update_labels(index, LabelKind::Output, changed).await
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.get_descriptions(index, LabelKind::Input).await
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.get_descriptions(index, LabelKind::Output).await
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.update_descriptions(index, LabelKind::Input, changed)
+            .await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.update_descriptions(index, LabelKind::Output, changed)
+            .await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.get_status(index, PortKind::Input).await
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.get_status(index, PortKind::Output).await
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        let layout = self.layout(index).await?;
+        let mut routes = Vec::new();
+        for (n, child) in self.children.iter().enumerate() {
+            routes.extend(layout.to_combined_routes(n, child.get_routes(index).await?));
+        }
+        Ok(routes)
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        let layout = self.layout(index).await?;
+        let (n, local) = layout
+            .locate(LabelKind::Output, output)
+            .ok_or_else(|| anyhow!("No route for output {} in matrix {}", output, index))?;
+        let patch = self.children[n].get_route(index, local).await?;
+        Ok(layout.to_combined_routes(n, vec![patch])[0])
+    }
+
+    /// Every patch is checked before any child sees one, then children are updated one after
+    /// another. A child failing has the ones before it restored, where that fails too the
+    /// update fails with [RouterError::PartiallyApplied].
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let layout = self.layout(index).await?;
+        let outputs: Vec<u32> = changes.iter().map(|p| p.to_output).collect();
+        let per_child = self.split_patches(&layout, index, changes)?;
+        // Routes of the updated children before, to restore them with.
+        let mut updated: Vec<(usize, Vec<RouterPatch>)> = Vec::new();
+        for (n, (child, patches)) in self.children.iter().zip(per_child).enumerate() {
+            if patches.is_empty() {
+                continue;
+            }
+            let before = child.get_routes(index).await?;
+            let restore: Vec<_> = patches
+                .iter()
+                .map(|p| {
+                    before
+                        .iter()
+                        .find(|b| b.to_output == p.to_output)
+                        .copied()
+                        .unwrap_or(RouterPatch::parked(p.to_output))
+                })
+                .collect();
+            if let Err(e) = child.update_routes(index, patches).await {
+                let mut applied = Vec::new();
+                for (n, restore) in updated.into_iter().rev() {
+                    if let Err(e) = self.children[n].update_routes(index, restore.clone()).await {
+                        error!("Failed to restore routes of child {}: {:?}", n, e);
+                        applied.extend(
+                            layout
+                                .to_combined_routes(n, restore)
+                                .iter()
+                                .map(|p| p.to_output),
+                        );
+                    }
+                }
+                if applied.is_empty() {
+                    return Err(e);
+                }
+                applied.sort_unstable();
+                applied.dedup();
+                let mut failed: Vec<u32> = outputs
+                    .into_iter()
+                    .filter(|o| !applied.contains(o))
+                    .collect();
+                failed.sort_unstable();
+                failed.dedup();
+                return Err(RouterError::PartiallyApplied {
+                    index,
+                    applied,
+                    failed,
+                }
+                .into());
+            }
+            updated.push((n, restore));
+        }
+        Ok(())
+    }
+
+    /// Children telling lock owners apart route on behalf of `owner`, the others are shared.
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        let handles: Vec<_> = self
+            .children
+            .iter()
+            .map(|child| child.with_lock_owner(owner))
+            .collect();
+        if handles.iter().all(Option::is_none) {
+            return None;
+        }
+        let children = handles
+            .into_iter()
+            .zip(&self.children)
+            .map(|(handle, child)| handle.unwrap_or_else(|| Arc::clone(child)))
+            .collect();
+        Some(Self { children })
+    }
+
+    /// Alarms of all children, in order.
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        let mut alarms = Vec::new();
+        for child in &self.children {
+            alarms.extend(child.get_alarms().await?);
+        }
+        Ok(alarms)
+    }
+
+    /// Labels can be changed where every child allows it, alarms are reported if any child
+    /// reports them.
+    fn capabilities(&self) -> RouterCapabilities {
+        let children: Vec<_> = self.children.iter().map(|c| c.capabilities()).collect();
+        RouterCapabilities {
+            matrix_count: children.iter().map(|c| c.matrix_count).min().unwrap_or(0),
+            can_update_input_labels: children.iter().all(|c| c.can_update_input_labels),
+            can_update_output_labels: children.iter().all(|c| c.can_update_output_labels),
+            can_lock_outputs: false,
+            has_frame_buffers: false,
+            has_processing_units: false,
+            has_alarm_status: children.iter().any(|c| c.has_alarm_status),
+            has_port_metadata: false,
+        }
+    }
+
+    /// Starts with [RouterEvent::Connected] if all children are connected.
+    ///
+    /// The combined router is connected while all children are, a single one going away
+    /// disconnects it. A child whose events can't be subscribed to counts as disconnected.
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        Ok(futures_util::StreamExt::boxed(stream! {
+            // Subscribed in here, the streams would keep the returned future from being Sync.
+            let mut streams = Vec::with_capacity(self.children.len());
+            for (n, child) in self.children.iter().enumerate() {
+                match child.event_stream().await {
+                    Ok(s) => streams.push(s.map(move |ev| (n, ev))),
+                    Err(e) => error!("Child {} has no event stream: {:#}", n, e),
+                }
+            }
+            let mut merged = select_all(streams);
+            let mut connected = vec![false; self.children.len()];
+            while let Some((n, ev)) = merged.next().await {
+                let was_connected = connected.iter().all(|&c| c);
+                match ev {
+                    RouterEvent::Connected => connected[n] = true,
+                    RouterEvent::Disconnected => connected[n] = false,
+                    ev => {
+                        if let Some(ev) = self.translate(n, ev).await {
+                            yield ev;
+                        }
+                        continue;
+                    }
+                }
+                match (was_connected, connected.iter().all(|&c| c)) {
+                    (false, true) => yield RouterEvent::Connected,
+                    (true, false) => yield RouterEvent::Disconnected,
+                    _ => {}
+                }
+            }
+        }))
+    }
+}
+
+impl RouterIntrospect for CompositeRouter {
+    fn name(&self) -> &'static str {
+        "CompositeRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        vec![("children".into(), self.children.len().to_string())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    /// A 3x2 and a 4x3 dummy, making a 7x5 matrix.
+    fn composite() -> (DummyRouter, DummyRouter, CompositeRouter) {
+        let a = DummyRouter::with_config(1, 3, 2);
+        let b = DummyRouter::with_config(1, 4, 3);
+        let c = CompositeRouter::new(vec![a.clone().into_dyn(), b.clone().into_dyn()]);
+        (a, b, c)
+    }
+
+    #[tokio::test]
+    async fn offsets() -> Result<()> {
+        let (a, b, c) = composite();
+        let mi = c.get_matrix_info(0).await?;
+        assert_eq!((mi.input_count, mi.output_count), (7, 5));
+
+        let inputs = c.get_input_labels(0).await?;
+        let ids: Vec<_> = inputs.iter().map(|l| l.id).collect();
+        assert_eq!(ids, (0..7).collect::<Vec<_>>());
+        assert_eq!(inputs[3].name, "Input 1");
+        assert_eq!(c.get_output_labels(0).await?[2].name, "Output 1");
+
+        c.update_output_labels(
+            0,
+            vec![RouterLabel {
+                id: 4,
+                name: "Program B".into(),
+            }],
+        )
+        .await?;
+        assert_eq!(b.get_output_labels(0).await?[2].name, "Program B");
+
+        // Output 3 is output 1 of b, input 5 its input 2.
+        c.update_routes(0, vec![patch(5, 3), patch(1, 0)]).await?;
+        assert_eq!(b.get_route(0, 1).await?, patch(2, 1));
+        assert_eq!(a.get_route(0, 0).await?, patch(1, 0));
+        assert_eq!(c.get_route(0, 3).await?, patch(5, 3));
+        assert_eq!(c.get_route_for_input(0, 5).await?, vec![3]);
+        let routes = c.get_routes(0).await?;
+        assert_eq!(
+            routes,
+            vec![
+                patch(1, 0),
+                patch(0, 1),
+                patch(3, 2),
+                patch(5, 3),
+                patch(3, 4)
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_cross_device() -> Result<()> {
+        let (a, b, c) = composite();
+        let before = (a.get_routes(0).await?, b.get_routes(0).await?);
+        let err = c
+            .update_routes(0, vec![patch(4, 2), patch(4, 0)])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::CrossDevice {
+                index: 0,
+                patch: patch(4, 0)
+            })
+        );
+        assert!(err.to_string().contains("spans"));
+        // Nothing applied, not even the valid patch before it.
+        assert_eq!(before, (a.get_routes(0).await?, b.get_routes(0).await?));
+
+        let err = c.update_routes(0, vec![patch(7, 0)]).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RouterError>(),
+            Some(RouterError::OutOfRange { .. })
+        ));
+        let label = RouterLabel {
+            id: 7,
+            name: "Nope".into(),
+        };
+        assert!(c.update_input_labels(0, vec![label]).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failing_child_restores_others() -> Result<()> {
+        let a = DummyRouter::with_config(1, 3, 2);
+        let b = DummyRouter::with_config(1, 4, 3);
+        let c = CompositeRouter::new(vec![
+            a.clone().into_dyn(),
+            ReadOnlyRouter::new(b.clone()).into_dyn(),
+        ]);
+        let before = a.get_routes(0).await?;
+        let mut events = a.event_stream().await?;
+        assert_eq!(events.next().await, Some(RouterEvent::Connected));
+
+        assert!(c
+            .update_routes(0, vec![patch(2, 0), patch(4, 3)])
+            .await
+            .is_err());
+        assert_eq!(a.get_routes(0).await?, before);
+        // Output 0 of a was changed and restored.
+        assert_eq!(
+            events.next().await,
+            Some(RouterEvent::RouteUpdate(0, vec![patch(2, 0)]))
+        );
+        assert_eq!(
+            events.next().await,
+            Some(RouterEvent::RouteUpdate(0, vec![before[0]]))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn descriptions() -> Result<()> {
+        let (_, b, c) = composite();
+        let mut stream = c.event_stream().await?;
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        assert_eq!(c.get_input_descriptions(0).await?.len(), 7);
+
+        // Output 3 is output 1 of b.
+        let d = RouterDescription {
+            id: 3,
+            text: "Stream encoder".into(),
+        };
+        c.update_output_descriptions(0, vec![d.clone()]).await?;
+        assert_eq!(b.get_output_descriptions(0).await?[1].text, d.text);
+        assert_eq!(c.get_output_descriptions(0).await?[3], d);
+        assert_eq!(
+            stream.next().await,
+            Some(RouterEvent::DescriptionUpdate(0, PortKind::Output, vec![d]))
+        );
+        let bad = RouterDescription {
+            id: 5,
+            text: "Nope".into(),
+        };
+        assert!(c.update_output_descriptions(0, vec![bad]).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn port_status() -> Result<()> {
+        let (_, b, c) = composite();
+        let mut stream = c.event_stream().await?;
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        let ids: Vec<_> = c
+            .get_output_status(0)
+            .await?
+            .iter()
+            .map(|st| st.id)
+            .collect();
+        assert_eq!(ids, (0..5).collect::<Vec<_>>());
+
+        // Input 1 of b is input 4.
+        let status = RouterPortStatus {
+            present: Some(false),
+            ..RouterPortStatus::unknown(PortKind::Input, 1)
+        };
+        b.push_event(RouterEvent::PortStatusUpdate(0, vec![status.clone()]));
+        assert_eq!(
+            stream.next().await,
+            Some(RouterEvent::PortStatusUpdate(
+                0,
+                vec![RouterPortStatus { id: 4, ..status }]
+            ))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn events() -> Result<()> {
+        let (a, b, c) = composite();
+        let mut stream = c.event_stream().await?;
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+
+        b.update_routes(0, vec![patch(3, 2)]).await?;
+        assert_eq!(
+            stream.next().await,
+            Some(RouterEvent::RouteUpdate(0, vec![patch(6, 4)]))
+        );
+        b.update_input_labels(
+            0,
+            vec![RouterLabel {
+                id: 0,
+                name: "Cam B".into(),
+            }],
+        )
+        .await?;
+        match stream.next().await {
+            Some(RouterEvent::InputLabelUpdate(0, labels)) => {
+                assert_eq!(labels[0].id, 3);
+                assert_eq!(labels[0].name, "Cam B");
+            }
+            ev => panic!("unexpected {:?}", ev),
+        }
+
+        // One child going away disconnects all of it.
+        a.set_alive(false);
+        assert_eq!(stream.next().await, Some(RouterEvent::Disconnected));
+        assert!(!c.is_alive().await?);
+        b.set_alive(false);
+        a.set_alive(true);
+        b.set_alive(true);
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        Ok(())
+    }
+}
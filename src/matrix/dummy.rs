@@ -1,7 +1,10 @@
 use super::*;
 use anyhow::{anyhow, Result};
 use futures_core::stream::BoxStream;
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tokio::sync::broadcast;
 use tokio_stream::{wrappers::BroadcastStream, StreamExt};
 use tracing::error;
@@ -11,6 +14,7 @@ use tracing::error;
 pub struct DummyRouter {
     state: Arc<Mutex<State>>,
     tx: broadcast::Sender<RouterEvent>,
+    latency: Option<Duration>,
 }
 
 struct State {
@@ -19,43 +23,101 @@ struct State {
     matrix_info: Vec<RouterMatrixInfo>,
     input_labels: Vec<Vec<RouterLabel>>,
     output_labels: Vec<Vec<RouterLabel>>,
+    input_descriptions: Vec<Vec<RouterDescription>>,
+    output_descriptions: Vec<Vec<RouterDescription>>,
     routes: Vec<Vec<RouterPatch>>,
+    /// Levels shared by all matrices, level 0 being `routes`.
+    levels: Vec<RouterLevel>,
+    /// Routes of every level above 0, per matrix.
+    level_routes: Vec<Vec<Vec<RouterPatch>>>,
+    frame_labels: Vec<Vec<RouterLabel>>,
+    frame_routes: Vec<Vec<RouterPatch>>,
+    locks: Vec<Vec<RouterLock>>,
+    frame_locks: Vec<Vec<RouterLock>>,
+    processing_unit_locks: Vec<Vec<RouterLock>>,
+    /// Staged routes in staging order.
+    staged: Vec<(TakeHandle, Vec<RouterPatch>)>,
+    last_take_id: u64,
 }
 
 impl DummyRouter {
     /// Create a dummy with given matrix_count, uniform input_count and output_count per matrix.
     pub fn with_config(matrix_count: usize, input_count: usize, output_count: usize) -> Self {
+        let dummy = Self::with_matrices(&vec![(input_count, output_count); matrix_count]);
+        dummy.state.lock().unwrap().info.model =
+            Some(format!("DummyRouter {}x{}", input_count, output_count));
+        dummy
+    }
+
+    /// Create a dummy with one matrix per `(input_count, output_count)` entry.
+    pub fn with_matrices(dimensions: &[(usize, usize)]) -> Self {
         let info = RouterInfo {
-            model: Some(format!("DummyRouter {}x{}", input_count, output_count)),
+            model: Some("DummyRouter".to_string()),
             name: None,
-            matrix_count: Some(matrix_count as u32),
+            matrix_count: Some(dimensions.len() as u32),
+            matrix_names: None,
         };
-        let matrix_info = vec![
-            RouterMatrixInfo {
+        let matrix_info = dimensions
+            .iter()
+            .map(|&(input_count, output_count)| RouterMatrixInfo {
                 input_count: input_count as u32,
                 output_count: output_count as u32,
-            };
-            matrix_count
-        ];
+                frame_count: 0,
+                name: None,
+            })
+            .collect();
 
-        let input_labels: Vec<RouterLabel> = (0..input_count)
-            .map(|n| RouterLabel {
-                id: n as u32,
-                name: format!("Input {}", n + 1),
+        let input_labels = dimensions
+            .iter()
+            .map(|&(input_count, _)| {
+                (0..input_count)
+                    .map(|n| RouterLabel {
+                        id: n as u32,
+                        name: format!("Input {}", n + 1),
+                    })
+                    .collect()
             })
             .collect();
 
-        let output_labels: Vec<RouterLabel> = (0..output_count)
-            .map(|n| RouterLabel {
-                id: n as u32,
-                name: format!("Output {}", n + 1),
+        let output_labels = dimensions
+            .iter()
+            .map(|&(_, output_count)| {
+                (0..output_count)
+                    .map(|n| RouterLabel {
+                        id: n as u32,
+                        name: format!("Output {}", n + 1),
+                    })
+                    .collect()
             })
             .collect();
 
-        let patches: Vec<RouterPatch> = (0..output_count)
-            .map(|n| RouterPatch {
-                from_input: 0,
-                to_output: n as u32,
+        let input_descriptions = dimensions
+            .iter()
+            .map(|&(input_count, _)| {
+                (0..input_count as u32)
+                    .map(RouterDescription::empty)
+                    .collect()
+            })
+            .collect();
+
+        let output_descriptions = dimensions
+            .iter()
+            .map(|&(_, output_count)| {
+                (0..output_count as u32)
+                    .map(RouterDescription::empty)
+                    .collect()
+            })
+            .collect();
+
+        let routes = dimensions
+            .iter()
+            .map(|&(_, output_count)| {
+                (0..output_count)
+                    .map(|n| RouterPatch {
+                        from_input: Some(0),
+                        to_output: n as u32,
+                    })
+                    .collect()
             })
             .collect();
 
@@ -63,14 +125,29 @@ impl DummyRouter {
             is_alive: true,
             info,
             matrix_info,
-            input_labels: vec![input_labels; matrix_count],
-            output_labels: vec![output_labels; matrix_count],
-            routes: vec![patches; matrix_count],
+            input_labels,
+            output_labels,
+            input_descriptions,
+            output_descriptions,
+            routes,
+            levels: vec![RouterLevel {
+                id: 0,
+                name: "Video".into(),
+            }],
+            level_routes: vec![vec![]; dimensions.len()],
+            frame_labels: vec![vec![]; dimensions.len()],
+            frame_routes: vec![vec![]; dimensions.len()],
+            frame_locks: vec![vec![]; dimensions.len()],
+            locks: vec![vec![]; dimensions.len()],
+            processing_unit_locks: vec![vec![]; dimensions.len()],
+            staged: vec![],
+            last_take_id: 0,
         };
         let (tx, _) = broadcast::channel(16);
         DummyRouter {
             state: Arc::new(Mutex::new(state)),
             tx,
+            latency: None,
         }
     }
 
@@ -79,6 +156,142 @@ impl DummyRouter {
         Self::with_config(1, 16, 16)
     }
 
+    /// Take `latency` to answer every call, like a router at the other end of a slow link.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    /// Give every matrix `frame_count` frame buffers, all recording input 0.
+    pub fn with_frame_count(self, frame_count: usize) -> Self {
+        {
+            let mut st = self.state.lock().unwrap();
+            let labels: Vec<RouterLabel> = (0..frame_count)
+                .map(|n| RouterLabel {
+                    id: n as u32,
+                    name: format!("Frame {}", n + 1),
+                })
+                .collect();
+            let patches: Vec<RouterPatch> = (0..frame_count)
+                .map(|n| RouterPatch {
+                    from_input: Some(0),
+                    to_output: n as u32,
+                })
+                .collect();
+            let st = &mut *st;
+            for mi in st.matrix_info.iter_mut() {
+                mi.frame_count = frame_count as u32;
+            }
+            for l in st.frame_labels.iter_mut() {
+                *l = labels.clone();
+            }
+            for r in st.frame_routes.iter_mut() {
+                *r = patches.clone();
+            }
+            for l in st.frame_locks.iter_mut() {
+                *l = unlocked(frame_count);
+            }
+        }
+        self
+    }
+
+    /// Give every matrix `count` routing levels, a video level followed by audio levels.
+    ///
+    /// Every level starts out like level 0 did, with all outputs routed from input 0.
+    pub fn with_level_count(self, count: usize) -> Self {
+        {
+            let mut st = self.state.lock().unwrap();
+            st.levels = (0..count.max(1))
+                .map(|n| RouterLevel {
+                    id: n as u32,
+                    name: match n {
+                        0 => "Video".into(),
+                        n => format!("Audio {}", n),
+                    },
+                })
+                .collect();
+            let st = &mut *st;
+            for (levels, mi) in st.level_routes.iter_mut().zip(&st.matrix_info) {
+                let routes: Vec<RouterPatch> = (0..mi.output_count)
+                    .map(|n| RouterPatch {
+                        from_input: Some(0),
+                        to_output: n,
+                    })
+                    .collect();
+                *levels = vec![routes; count.saturating_sub(1)];
+            }
+        }
+        self
+    }
+
+    /// Hold output locks at the router, all unlocked at first.
+    pub fn with_output_locks(self) -> Self {
+        {
+            let mut st = self.state.lock().unwrap();
+            let st = &mut *st;
+            for (l, mi) in st.locks.iter_mut().zip(&st.matrix_info) {
+                *l = unlocked(mi.output_count as usize);
+            }
+        }
+        self
+    }
+
+    /// Give every matrix `count` processing units, all unlocked.
+    pub fn with_processing_units(self, count: usize) -> Self {
+        for l in self.state.lock().unwrap().processing_unit_locks.iter_mut() {
+            *l = unlocked(count);
+        }
+        self
+    }
+
+    /// Name the matrices in index order, those beyond `names` stay unnamed.
+    pub fn with_matrix_names(self, names: &[&str]) -> Self {
+        {
+            let mut st = self.state.lock().unwrap();
+            for (mi, name) in st.matrix_info.iter_mut().zip(names) {
+                mi.name = Some(name.to_string());
+            }
+            st.info.matrix_names = Some(
+                st.matrix_info
+                    .iter()
+                    .map(|mi| mi.name.clone().unwrap_or_default())
+                    .collect(),
+            );
+        }
+        self
+    }
+
+    /// Set whether the router claims to be alive.
+    ///
+    /// Changes are announced as [RouterEvent::Connected] or [RouterEvent::Disconnected].
+    /// Coming back is followed by snapshots of the labels and routes of every matrix.
+    pub fn set_alive(&self, alive: bool) {
+        let mut st = self.state.lock().unwrap();
+        if st.is_alive == alive {
+            return;
+        }
+        st.is_alive = alive;
+        if !alive {
+            let _ = self.tx.send(RouterEvent::Disconnected);
+            return;
+        }
+        let _ = self.tx.send(RouterEvent::Connected);
+        for index in 0..st.matrix_info.len() {
+            let idx = index as u32;
+            let _ = self.tx.send(RouterEvent::InputLabelSnapshot(
+                idx,
+                st.input_labels[index].clone(),
+            ));
+            let _ = self.tx.send(RouterEvent::OutputLabelSnapshot(
+                idx,
+                st.output_labels[index].clone(),
+            ));
+            let _ = self
+                .tx
+                .send(RouterEvent::RouteSnapshot(idx, st.routes[index].clone()));
+        }
+    }
+
     /// Update the static info.
     pub fn set_info(&self, info: RouterInfo) {
         self.state.lock().unwrap().info = info;
@@ -89,6 +302,13 @@ impl DummyRouter {
         let _ = self.tx.send(ev);
     }
 
+    /// Wait out the configured latency, if any.
+    async fn delay(&self) {
+        if let Some(latency) = self.latency {
+            tokio::time::sleep(latency).await;
+        }
+    }
+
     /// Validate that matrix index is in range
     fn validate_index(st: &State, index: u32) -> Result<()> {
         if (index as usize) < st.matrix_info.len() {
@@ -99,132 +319,622 @@ impl DummyRouter {
     }
 }
 
+impl State {
+    /// Staged patches of matrix `index`, later stagings overriding earlier ones per output.
+    fn staged_routes(&self, index: u32) -> Vec<RouterPatch> {
+        let mut by_output = std::collections::BTreeMap::new();
+        for (_, patches) in self.staged.iter().filter(|(h, _)| h.index == index) {
+            for p in patches {
+                by_output.insert(p.to_output, *p);
+            }
+        }
+        by_output.into_values().collect()
+    }
+
+    /// Remove a staging, failing for unknown or already committed or discarded handles.
+    fn take_staged(&mut self, handle: TakeHandle) -> Result<Vec<RouterPatch>> {
+        let pos = self
+            .staged
+            .iter()
+            .position(|(h, _)| *h == handle)
+            .ok_or_else(|| anyhow!("No routes staged as {:?}", handle))?;
+        Ok(self.staged.remove(pos).1)
+    }
+}
+
+fn unlocked(count: usize) -> Vec<RouterLock> {
+    (0..count)
+        .map(|n| RouterLock {
+            id: n as u32,
+            locked: false,
+        })
+        .collect()
+}
+
+/// Apply lock changes, refusing all of them if any is out of range.
+fn update_locks(locks: &mut [RouterLock], changes: &[RouterLock]) -> Result<()> {
+    if let Some(l) = changes.iter().find(|l| l.id as usize >= locks.len()) {
+        return Err(anyhow!("Lock {} out of range", l.id));
+    }
+    for l in changes {
+        locks[l.id as usize].locked = l.locked;
+    }
+    Ok(())
+}
+
+impl Default for DummyRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RouterIntrospect for DummyRouter {
+    fn name(&self) -> &'static str {
+        "DummyRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        let st = self.state.lock().unwrap();
+        let matrices = st
+            .matrix_info
+            .iter()
+            .map(|mi| format!("{}x{}", mi.input_count, mi.output_count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        vec![
+            ("matrices".into(), matrices),
+            ("alive".into(), st.is_alive.to_string()),
+        ]
+    }
+}
+
 impl MatrixRouter for DummyRouter {
     async fn is_alive(&self) -> Result<bool> {
+        self.delay().await;
         Ok(self.state.lock().unwrap().is_alive)
     }
 
     async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.delay().await;
         Ok(self.state.lock().unwrap().info.clone())
     }
 
     async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        self.delay().await;
         let st = self.state.lock().unwrap();
         Self::validate_index(&st, index)?;
         Ok(st.matrix_info[index as usize].clone())
     }
 
     async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.delay().await;
         let st = self.state.lock().unwrap();
         Self::validate_index(&st, index)?;
         Ok(st.input_labels[index as usize].clone())
     }
     async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.delay().await;
         let st = self.state.lock().unwrap();
         Self::validate_index(&st, index)?;
         Ok(st.output_labels[index as usize].clone())
     }
 
     async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.delay().await;
         let mut st = self.state.lock().unwrap();
         Self::validate_index(&st, index)?;
         let idx = index as usize;
         let mi = st.matrix_info[idx].clone();
-        let mut changes_happened = false;
-        for change in changed {
-            if change.id >= mi.input_count {
-                return Err(anyhow!("Can't update an input label outside of range!"));
-            }
-            st.input_labels[idx][change.id as usize].name = change.name;
-            changes_happened = true;
+        // Validate everything first, so a failing update doesn't apply partially.
+        mi.check_labels(index, LabelKind::Input, &changed)?;
+        if changed.is_empty() {
+            return Ok(());
+        }
+        for change in &changed {
+            st.input_labels[idx][change.id as usize].name = change.name.clone();
         }
 
-        // Broadcast the current labels if any changes occured.
-        if changes_happened {
-            if self
-                .tx
-                .send(RouterEvent::InputLabelUpdate(
-                    index,
-                    st.input_labels[idx].clone(),
-                ))
-                .is_err()
-            {
-                error!("InputLabelUpdate Event happened, but channel closed!")
-            }
+        // Broadcast only what changed.
+        if self
+            .tx
+            .send(RouterEvent::InputLabelUpdate(index, changed))
+            .is_err()
+        {
+            error!("InputLabelUpdate Event happened, but channel closed!")
         }
         Ok(())
     }
     async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.delay().await;
+        let mut st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        let idx = index as usize;
+        let mi = st.matrix_info[idx].clone();
+        // Validate everything first, so a failing update doesn't apply partially.
+        mi.check_labels(index, LabelKind::Output, &changed)?;
+        if changed.is_empty() {
+            return Ok(());
+        }
+        for change in &changed {
+            st.output_labels[idx][change.id as usize].name = change.name.clone();
+        }
+
+        // Broadcast only what changed.
+        if self
+            .tx
+            .send(RouterEvent::OutputLabelUpdate(index, changed))
+            .is_err()
+        {
+            error!("OutputLabelUpdate Event happened, but channel closed!")
+        }
+        Ok(())
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.delay().await;
+        let st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        Ok(st.input_descriptions[index as usize].clone())
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.delay().await;
+        let st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        Ok(st.output_descriptions[index as usize].clone())
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.delay().await;
+        let mut st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        let idx = index as usize;
+        let mi = st.matrix_info[idx].clone();
+        mi.check_descriptions(index, LabelKind::Input, &changed)?;
+        if changed.is_empty() {
+            return Ok(());
+        }
+        for change in &changed {
+            st.input_descriptions[idx][change.id as usize].text = change.text.clone();
+        }
+
+        let ev = RouterEvent::DescriptionUpdate(index, PortKind::Input, changed);
+        if self.tx.send(ev).is_err() {
+            error!("DescriptionUpdate Event happened, but channel closed!")
+        }
+        Ok(())
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.delay().await;
+        let mut st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        let idx = index as usize;
+        let mi = st.matrix_info[idx].clone();
+        mi.check_descriptions(index, LabelKind::Output, &changed)?;
+        if changed.is_empty() {
+            return Ok(());
+        }
+        for change in &changed {
+            st.output_descriptions[idx][change.id as usize].text = change.text.clone();
+        }
+
+        let ev = RouterEvent::DescriptionUpdate(index, PortKind::Output, changed);
+        if self.tx.send(ev).is_err() {
+            error!("DescriptionUpdate Event happened, but channel closed!")
+        }
+        Ok(())
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.delay().await;
+        let st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        let row = &st.routes[index as usize];
+        Ok(row.clone())
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        self.delay().await;
+        let st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        st.routes[index as usize]
+            .get(output as usize)
+            .copied()
+            .ok_or_else(|| anyhow!("No route for output {} in matrix {}", output, index))
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        self.delay().await;
+        let st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        // Routes are kept in output order.
+        Ok(st.routes[index as usize]
+            .iter()
+            .filter(|p| p.from_input == Some(input))
+            .map(|p| p.to_output)
+            .collect())
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.delay().await;
+        let mut st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        let idx = index as usize;
+        let outputs = st.matrix_info[idx].output_count as usize;
+        let inputs = st.matrix_info[idx].input_count as usize;
+        if let Some(p) = changes.iter().find(|p| {
+            p.from_input.is_some_and(|i| i as usize >= inputs) || p.to_output as usize >= outputs
+        }) {
+            return Err(anyhow!("Patch {:?} out of bounds for matrix {}", p, index));
+        }
+        if changes.is_empty() {
+            return Ok(());
+        }
+        for p in &changes {
+            st.routes[idx][p.to_output as usize].from_input = p.from_input;
+        }
+
+        // Broadcast only what changed.
+        if self
+            .tx
+            .send(RouterEvent::RouteUpdate(index, changes))
+            .is_err()
+        {
+            error!("RouteUpdate event happened, but channel closed!")
+        }
+        Ok(())
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.delay().await;
+        let mut st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        let idx = index as usize;
+        let info = st.matrix_info[idx].clone();
+
+        // Stage into a copy, the routes only get replaced once every patch applied.
+        let mut staged = st.routes[idx].clone();
+        for p in &changes {
+            info.check_patches(index, std::slice::from_ref(p))?;
+            staged[p.to_output as usize].from_input = p.from_input;
+        }
+        if changes.is_empty() {
+            return Ok(());
+        }
+        st.routes[idx] = staged;
+
+        if self
+            .tx
+            .send(RouterEvent::RouteUpdate(index, changes))
+            .is_err()
+        {
+            error!("RouteUpdate event happened, but channel closed!")
+        }
+        Ok(())
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.delay().await;
+        let st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        Ok(st.levels.clone())
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        self.delay().await;
+        if level == 0 {
+            return self.get_routes(index).await;
+        }
+        let st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        st.level_routes[index as usize]
+            .get(level as usize - 1)
+            .cloned()
+            .ok_or_else(|| RouterError::NoSuchLevel { index, level }.into())
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        self.delay().await;
+        if level == 0 {
+            return self.update_routes(index, changes).await;
+        }
         let mut st = self.state.lock().unwrap();
         Self::validate_index(&st, index)?;
         let idx = index as usize;
         let mi = st.matrix_info[idx].clone();
+        let routes = st.level_routes[idx]
+            .get_mut(level as usize - 1)
+            .ok_or(RouterError::NoSuchLevel { index, level })?;
+        mi.check_patches(index, &changes)?;
+        if changes.is_empty() {
+            return Ok(());
+        }
+        for p in changes {
+            routes[p.to_output as usize].from_input = p.from_input;
+        }
+
+        if self
+            .tx
+            .send(RouterEvent::LevelRouteUpdate(index, level, routes.clone()))
+            .is_err()
+        {
+            error!("LevelRouteUpdate event happened, but channel closed!")
+        }
+        Ok(())
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        self.delay().await;
+        let mut st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        st.matrix_info[index as usize].check_patches(index, &changes)?;
+        st.last_take_id += 1;
+        let handle = TakeHandle {
+            index,
+            id: st.last_take_id,
+        };
+        st.staged.push((handle, changes));
+
+        if self
+            .tx
+            .send(RouterEvent::StagedRouteUpdate(
+                index,
+                st.staged_routes(index),
+            ))
+            .is_err()
+        {
+            error!("StagedRouteUpdate event happened, but channel closed!")
+        }
+        Ok(handle)
+    }
+
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        self.delay().await;
+        let mut st = self.state.lock().unwrap();
+        let changes = st.take_staged(handle)?;
+        let idx = handle.index as usize;
+        // Checked when staging, matrices never change size.
+        for p in &changes {
+            st.routes[idx][p.to_output as usize].from_input = p.from_input;
+        }
+
+        if self
+            .tx
+            .send(RouterEvent::RouteUpdate(handle.index, changes))
+            .is_err()
+        {
+            error!("RouteUpdate event happened, but channel closed!")
+        }
+        if self
+            .tx
+            .send(RouterEvent::StagedRouteUpdate(
+                handle.index,
+                st.staged_routes(handle.index),
+            ))
+            .is_err()
+        {
+            error!("StagedRouteUpdate event happened, but channel closed!")
+        }
+        Ok(())
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        self.delay().await;
+        let mut st = self.state.lock().unwrap();
+        st.take_staged(handle)?;
+        if self
+            .tx
+            .send(RouterEvent::StagedRouteUpdate(
+                handle.index,
+                st.staged_routes(handle.index),
+            ))
+            .is_err()
+        {
+            error!("StagedRouteUpdate event happened, but channel closed!")
+        }
+        Ok(())
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.delay().await;
+        let st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        Ok(st.staged_routes(index))
+    }
+
+    async fn get_frame_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.delay().await;
+        let st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        Ok(st.frame_labels[index as usize].clone())
+    }
+
+    async fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.delay().await;
+        let mut st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        let idx = index as usize;
+        st.matrix_info[idx].check_labels(index, LabelKind::Frame, &changed)?;
         let mut changes_happened = false;
         for change in changed {
-            if change.id >= mi.output_count {
-                return Err(anyhow!("Can't update an output label outside of range!"));
-            }
-            st.output_labels[idx][change.id as usize].name = change.name;
+            st.frame_labels[idx][change.id as usize].name = change.name;
             changes_happened = true;
         }
 
-        // Broadcast the current labels if any changes occured.
-        if changes_happened {
-            if self
+        if changes_happened
+            && self
                 .tx
-                .send(RouterEvent::OutputLabelUpdate(
+                .send(RouterEvent::FrameLabelUpdate(
                     index,
-                    st.output_labels[idx].clone(),
+                    st.frame_labels[idx].clone(),
                 ))
                 .is_err()
-            {
-                error!("OutputLabelUpdate Event happened, but channel closed!")
-            }
+        {
+            error!("FrameLabelUpdate Event happened, but channel closed!")
         }
         Ok(())
     }
 
-    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+    async fn get_frame_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.delay().await;
         let st = self.state.lock().unwrap();
         Self::validate_index(&st, index)?;
-        let row = &st.routes[index as usize];
-        Ok(row.clone())
+        Ok(st.frame_routes[index as usize].clone())
     }
 
-    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+    async fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.delay().await;
         let mut st = self.state.lock().unwrap();
         Self::validate_index(&st, index)?;
         let idx = index as usize;
-        let outputs = st.matrix_info[idx].output_count as usize;
+        let frames = st.matrix_info[idx].frame_count as usize;
         let inputs = st.matrix_info[idx].input_count as usize;
+        if let Some(&patch) = changes.iter().find(|p| {
+            p.from_input.is_some_and(|i| i as usize >= inputs) || p.to_output as usize >= frames
+        }) {
+            return Err(RouterError::OutOfRange { index, patch }.into());
+        }
         let mut changes_happened = false;
         for p in changes {
-            let out = p.to_output as usize;
-            let inp = p.from_input as usize;
-            if inp >= inputs || out >= outputs {
-                return Err(anyhow!("Patch {:?} out of bounds for matrix {}", p, index));
-            }
-            st.routes[idx][out].from_input = p.from_input;
+            st.frame_routes[idx][p.to_output as usize].from_input = p.from_input;
             changes_happened = true;
         }
 
-        // Broadcast
-        if changes_happened {
-            if self
+        if changes_happened
+            && self
                 .tx
-                .send(RouterEvent::RouteUpdate(index, st.routes[idx].clone()))
+                .send(RouterEvent::FrameRouteUpdate(
+                    index,
+                    st.frame_routes[idx].clone(),
+                ))
                 .is_err()
-            {
-                error!("RouteUpdate event happened, but channel closed!")
-            }
+        {
+            error!("FrameRouteUpdate event happened, but channel closed!")
+        }
+        Ok(())
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.delay().await;
+        let st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        Ok(st.locks[index as usize].clone())
+    }
+
+    /// Only supported once enabled with [DummyRouter::with_output_locks].
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.delay().await;
+        let mut st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        let idx = index as usize;
+        update_locks(&mut st.locks[idx], &changes)?;
+
+        if !changes.is_empty()
+            && self
+                .tx
+                .send(RouterEvent::LockUpdate(index, st.locks[idx].clone()))
+                .is_err()
+        {
+            error!("LockUpdate event happened, but channel closed!")
         }
         Ok(())
     }
 
+    async fn get_frame_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.delay().await;
+        let st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        Ok(st.frame_locks[index as usize].clone())
+    }
+
+    async fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.delay().await;
+        let mut st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        let idx = index as usize;
+        update_locks(&mut st.frame_locks[idx], &changes)?;
+
+        if !changes.is_empty()
+            && self
+                .tx
+                .send(RouterEvent::FrameLockUpdate(
+                    index,
+                    st.frame_locks[idx].clone(),
+                ))
+                .is_err()
+        {
+            error!("FrameLockUpdate event happened, but channel closed!")
+        }
+        Ok(())
+    }
+
+    async fn get_processing_unit_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.delay().await;
+        let st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        Ok(st.processing_unit_locks[index as usize].clone())
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        self.delay().await;
+        let mut st = self.state.lock().unwrap();
+        Self::validate_index(&st, index)?;
+        let idx = index as usize;
+        update_locks(&mut st.processing_unit_locks[idx], &changes)?;
+
+        if !changes.is_empty()
+            && self
+                .tx
+                .send(RouterEvent::ProcessingUnitLockUpdate(
+                    index,
+                    st.processing_unit_locks[idx].clone(),
+                ))
+                .is_err()
+        {
+            error!("ProcessingUnitLockUpdate event happened, but channel closed!")
+        }
+        Ok(())
+    }
+
+    /// Everything but port metadata, which is left to [super::MetadataRouter].
+    fn capabilities(&self) -> RouterCapabilities {
+        let matrix_count = self.state.lock().unwrap().matrix_info.len() as u32;
+        RouterCapabilities {
+            has_port_metadata: false,
+            ..RouterCapabilities::all(matrix_count)
+        }
+    }
+
+    /// Starts with [RouterEvent::Connected] if the dummy is alive at the time of subscribing.
     async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
-        let bs = BroadcastStream::new(self.tx.subscribe());
-        let simple = bs.filter_map(|r| r.ok());
-        Ok(futures_util::StreamExt::boxed(simple))
+        self.delay().await;
+        let (rx, alive) = {
+            let st = self.state.lock().unwrap();
+            (self.tx.subscribe(), st.is_alive)
+        };
+        let initial = alive.then_some(RouterEvent::Connected);
+        let simple = BroadcastStream::new(rx).filter_map(|r| r.ok());
+        Ok(futures_util::StreamExt::boxed(
+            tokio_stream::iter(initial).chain(simple),
+        ))
     }
 }
 
@@ -233,6 +943,15 @@ mod tests {
     use super::*;
     use tokio_stream::StreamExt;
 
+    #[tokio::test]
+    async fn latency() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 2, 2).with_latency(Duration::from_millis(20));
+        let started = std::time::Instant::now();
+        dummy.get_routes(0).await?;
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn constructor_and_bounds() {
         let dummy = DummyRouter::with_config(2, 3, 4);
@@ -243,12 +962,40 @@ mod tests {
         assert!(dummy.get_matrix_info(5).await.is_err());
     }
 
+    #[test]
+    fn capabilities() {
+        let caps = DummyRouter::with_config(2, 3, 4).capabilities();
+        assert_eq!(caps.matrix_count, 2);
+        assert!(caps.can_update_input_labels && caps.can_lock_outputs && caps.has_frame_buffers);
+    }
+
+    #[tokio::test]
+    async fn differently_sized_matrices() {
+        let dummy = DummyRouter::with_matrices(&[(2, 0), (4, 3)]);
+        let mi0 = dummy.get_matrix_info(0).await.unwrap();
+        let mi1 = dummy.get_matrix_info(1).await.unwrap();
+        assert_eq!((mi0.input_count, mi0.output_count), (2, 0));
+        assert_eq!((mi1.input_count, mi1.output_count), (4, 3));
+        assert!(dummy.get_routes(0).await.unwrap().is_empty());
+        assert_eq!(dummy.get_routes(1).await.unwrap().len(), 3);
+        assert_eq!(dummy.get_input_labels(1).await.unwrap().len(), 4);
+
+        let p = RouterPatch {
+            from_input: Some(3),
+            to_output: 2,
+        };
+        assert!(dummy.update_routes(0, vec![p]).await.is_err());
+        dummy.update_routes(1, vec![p]).await.unwrap();
+        assert!(dummy.get_routes(1).await.unwrap().contains(&p));
+    }
+
     #[tokio::test]
     async fn patch_bounds_and_routing() {
         let dummy = DummyRouter::with_config(1, 2, 2);
         let mut stream = dummy.event_stream().await.unwrap();
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
         let p = RouterPatch {
-            from_input: 1,
+            from_input: Some(1),
             to_output: 1,
         };
         dummy.update_routes(0, vec![p]).await.unwrap();
@@ -264,22 +1011,200 @@ mod tests {
             RouterEvent::RouteUpdate(0, routes) => routes,
             _ => panic!("RouterEvent wasn't RouteUpdate!"),
         };
-        assert!(
-            route_update.contains(&p),
-            "RouteUpdate doesn't contain patch"
+        assert_eq!(
+            route_update,
+            vec![p],
+            "RouteUpdate carries more than the patch"
         );
 
         let bad = RouterPatch {
-            from_input: 5,
+            from_input: Some(5),
             to_output: 0,
         };
         assert!(dummy.update_routes(0, vec![bad]).await.is_err());
     }
 
+    #[tokio::test]
+    async fn single_change_payloads() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 288, 288);
+        let mut stream = dummy.event_stream().await?;
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        let p = RouterPatch {
+            from_input: Some(200),
+            to_output: 287,
+        };
+        dummy.update_routes(0, vec![p]).await?;
+        dummy.update_routes_atomic(0, vec![p]).await?;
+        let handle = dummy.stage_routes(0, vec![p]).await?;
+        dummy.commit(handle).await?;
+        let mut updates = Vec::new();
+        while let Ok(Some(ev)) =
+            tokio::time::timeout(Duration::from_millis(50), stream.next()).await
+        {
+            if let RouterEvent::RouteUpdate(0, routes) = ev {
+                updates.push(routes);
+            }
+        }
+        assert_eq!(updates, vec![vec![p]; 3]);
+
+        // Only a reconnect brings the whole table.
+        dummy.set_alive(false);
+        dummy.set_alive(true);
+        assert_eq!(stream.next().await, Some(RouterEvent::Disconnected));
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        assert!(matches!(
+            stream.next().await,
+            Some(RouterEvent::InputLabelSnapshot(0, ls)) if ls.len() == 288
+        ));
+        assert!(matches!(
+            stream.next().await,
+            Some(RouterEvent::OutputLabelSnapshot(0, ls)) if ls.len() == 288
+        ));
+        assert!(matches!(
+            stream.next().await,
+            Some(RouterEvent::RouteSnapshot(0, rs)) if rs.len() == 288 && rs.contains(&p)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn single_route() {
+        let dummy = DummyRouter::with_config(1, 3, 3);
+        let p = RouterPatch {
+            from_input: Some(2),
+            to_output: 1,
+        };
+        dummy.update_routes(0, vec![p]).await.unwrap();
+        assert_eq!(dummy.get_route(0, 1).await.unwrap(), p);
+        for r in dummy.get_routes(0).await.unwrap() {
+            assert_eq!(dummy.get_route(0, r.to_output).await.unwrap(), r);
+        }
+        assert!(dummy.get_route(0, 3).await.is_err());
+        assert!(dummy.get_route(1, 0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn routes_for_input() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 4, 4);
+        // Everything starts out on input 0.
+        assert_eq!(dummy.get_route_for_input(0, 0).await?, vec![0, 1, 2, 3]);
+        let patch = |from_input, to_output| RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        };
+        dummy
+            .update_routes(0, vec![patch(2, 3), patch(2, 1)])
+            .await?;
+        assert_eq!(dummy.get_route_for_input(0, 2).await?, vec![1, 3]);
+        assert_eq!(dummy.get_route_for_input(0, 0).await?, vec![0, 2]);
+        assert!(dummy.get_route_for_input(0, 3).await?.is_empty());
+        assert!(dummy.get_route_for_input(1, 0).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn parked_outputs() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let mut stream = dummy.event_stream().await?;
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        let parked = RouterPatch::parked(1);
+        dummy.update_routes(0, vec![parked]).await?;
+        assert_eq!(dummy.get_route_for_output(0, 1).await?, Some(parked));
+        assert_eq!(dummy.get_route_for_input(0, 0).await?, vec![0]);
+        assert_eq!(
+            stream.next().await,
+            Some(RouterEvent::RouteUpdate(0, vec![parked]))
+        );
+        assert!(dummy
+            .update_routes(0, vec![RouterPatch::parked(2)])
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn single_routes() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 4, 4);
+        dummy.set_route(0, 3, 2).await?;
+        let patch = RouterPatch {
+            from_input: Some(2),
+            to_output: 3,
+        };
+        assert_eq!(dummy.get_route_for_output(0, 3).await?, Some(patch));
+        assert_eq!(dummy.get_route(0, 3).await?, patch);
+        // Unknown outputs have no route, unknown matrices fail.
+        assert_eq!(dummy.get_route_for_output(0, 4).await?, None);
+        assert!(dummy.get_route(0, 4).await.is_err());
+        assert!(dummy.get_route_for_output(1, 0).await.is_err());
+        assert!(dummy.set_route(0, 4, 0).await.is_err());
+        assert!(dummy.set_route(0, 0, 4).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn find_by_label() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 3, 3);
+        assert_eq!(dummy.find_input_by_label(0, "Input 2").await?, Some(1));
+        assert_eq!(dummy.find_output_by_label(0, "Output 3").await?, Some(2));
+        assert_eq!(dummy.find_input_by_label(0, "Output 1").await?, None);
+        assert_eq!(dummy.find_output_by_label(0, "output 1").await?, None);
+        assert!(dummy.find_input_by_label(1, "Input 1").await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn descriptions() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 2, 3);
+        let mut stream = dummy.event_stream().await?;
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        assert_eq!(
+            dummy.get_output_descriptions(0).await?,
+            (0..3).map(RouterDescription::empty).collect::<Vec<_>>()
+        );
+        let d = RouterDescription {
+            id: 1,
+            text: "Stage left, behind the lectern".into(),
+        };
+        dummy.update_input_descriptions(0, vec![d.clone()]).await?;
+        assert_eq!(
+            dummy.get_input_descriptions(0).await?,
+            vec![RouterDescription::empty(0), d.clone()]
+        );
+        assert_eq!(
+            stream.next().await,
+            Some(RouterEvent::DescriptionUpdate(
+                0,
+                PortKind::Input,
+                vec![d.clone()]
+            ))
+        );
+        // Labels are left alone.
+        assert_eq!(dummy.get_input_labels(0).await?[1].name, "Input 2");
+
+        let bad = RouterDescription {
+            id: 3,
+            text: "Nowhere".into(),
+        };
+        assert!(dummy
+            .update_input_descriptions(0, vec![bad.clone()])
+            .await
+            .is_err());
+        assert!(dummy
+            .update_output_descriptions(0, vec![d, bad])
+            .await
+            .is_err());
+        assert_eq!(
+            dummy.get_output_descriptions(0).await?[1],
+            RouterDescription::empty(1)
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn input_labels() {
         let dummy = DummyRouter::with_config(1, 2, 2);
         let mut stream = dummy.event_stream().await.unwrap();
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
         let l = RouterLabel {
             id: 0,
             name: "Test Case".to_owned(),
@@ -297,21 +1222,32 @@ mod tests {
             RouterEvent::InputLabelUpdate(0, labels) => labels,
             _ => panic!("RouterEvent wasn't InputLabelUpdate!"),
         };
-        assert!(
-            label_update.contains(&l),
-            "InputLabelUpdate doesn't contain label"
+        assert_eq!(
+            label_update,
+            vec![l],
+            "InputLabelUpdate carries more than the label"
         );
 
         let bad = RouterLabel {
             id: 5,
             name: "Bad".to_string(),
         };
-        assert!(dummy.update_input_labels(0, vec![bad]).await.is_err());
+        let err = dummy.update_input_labels(0, vec![bad]).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::LabelOutOfRange {
+                index: 0,
+                kind: LabelKind::Input,
+                id: 5,
+                max: Some(1)
+            })
+        );
     }
     #[tokio::test]
     async fn output_labels() {
         let dummy = DummyRouter::with_config(1, 2, 2);
         let mut stream = dummy.event_stream().await.unwrap();
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
         let l = RouterLabel {
             id: 0,
             name: "Test Case".to_owned(),
@@ -332,25 +1268,448 @@ mod tests {
             RouterEvent::OutputLabelUpdate(0, labels) => labels,
             _ => panic!("RouterEvent wasn't OutputLabelUpdate!"),
         };
-        assert!(
-            label_update.contains(&l),
-            "OutputLabelUpdate doesn't contain label"
+        assert_eq!(
+            label_update,
+            vec![l],
+            "OutputLabelUpdate carries more than the label"
         );
 
         let bad = RouterLabel {
             id: 5,
             name: "Bad".to_string(),
         };
-        assert!(dummy.update_output_labels(0, vec![bad]).await.is_err());
+        let err = dummy.update_output_labels(0, vec![bad]).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::LabelOutOfRange {
+                index: 0,
+                kind: LabelKind::Output,
+                id: 5,
+                max: Some(1)
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn frame_buffers() {
+        let plain = DummyRouter::with_config(1, 2, 2);
+        assert_eq!(plain.get_matrix_info(0).await.unwrap().frame_count, 0);
+        assert!(plain.get_frame_routes(0).await.unwrap().is_empty());
+
+        let dummy = DummyRouter::with_config(1, 2, 2).with_frame_count(3);
+        assert_eq!(dummy.get_matrix_info(0).await.unwrap().frame_count, 3);
+        assert_eq!(dummy.get_frame_labels(0).await.unwrap().len(), 3);
+        let mut stream = dummy.event_stream().await.unwrap();
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+
+        let p = RouterPatch {
+            from_input: Some(1),
+            to_output: 2,
+        };
+        dummy.update_frame_routes(0, vec![p]).await.unwrap();
+        assert!(dummy.get_frame_routes(0).await.unwrap().contains(&p));
+        match stream.next().await {
+            Some(RouterEvent::FrameRouteUpdate(0, routes)) => assert!(routes.contains(&p)),
+            ev => panic!("expected FrameRouteUpdate, got {:?}", ev),
+        }
+
+        let l = RouterLabel {
+            id: 2,
+            name: "Still".to_string(),
+        };
+        dummy.update_frame_labels(0, vec![l.clone()]).await.unwrap();
+        assert!(dummy.get_frame_labels(0).await.unwrap().contains(&l));
+        match stream.next().await {
+            Some(RouterEvent::FrameLabelUpdate(0, labels)) => assert!(labels.contains(&l)),
+            ev => panic!("expected FrameLabelUpdate, got {:?}", ev),
+        }
+
+        // Frame 3 doesn't exist, and neither does input 2.
+        let bad = RouterPatch {
+            from_input: Some(0),
+            to_output: 3,
+        };
+        assert!(dummy.update_frame_routes(0, vec![bad]).await.is_err());
+        let bad = RouterPatch {
+            from_input: Some(2),
+            to_output: 0,
+        };
+        assert!(dummy.update_frame_routes(0, vec![bad]).await.is_err());
+
+        // Without frame buffers, there's no label to change at all.
+        let err = plain.update_frame_labels(0, vec![l]).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::LabelOutOfRange {
+                index: 0,
+                kind: LabelKind::Frame,
+                id: 2,
+                max: None
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn independent_levels() -> Result<()> {
+        let dummy = DummyRouter::with_config(2, 3, 2).with_level_count(3);
+        let mut stream = dummy.event_stream().await?;
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        let names: Vec<_> = dummy
+            .get_levels(1)
+            .await?
+            .into_iter()
+            .map(|l| l.name)
+            .collect();
+        assert_eq!(names, vec!["Video", "Audio 1", "Audio 2"]);
+
+        // Breakaway: audio 2 follows another input than video.
+        let p = RouterPatch {
+            from_input: Some(2),
+            to_output: 1,
+        };
+        dummy.update_level_routes(1, 2, vec![p]).await?;
+        let audio = dummy.get_level_routes(1, 2).await?;
+        assert_eq!(audio[1], p);
+        assert_eq!(
+            stream.next().await,
+            Some(RouterEvent::LevelRouteUpdate(1, 2, audio))
+        );
+        assert_eq!(dummy.get_level_routes(1, 1).await?[1].from_input, Some(0));
+        assert_eq!(dummy.get_routes(1).await?[1].from_input, Some(0));
+        assert_eq!(dummy.get_level_routes(0, 2).await?[1].from_input, Some(0));
+
+        // Level 0 is the plain routing.
+        dummy.update_level_routes(1, 0, vec![p]).await?;
+        assert_eq!(dummy.get_routes(1).await?[1], p);
+        assert!(matches!(
+            stream.next().await,
+            Some(RouterEvent::RouteUpdate(1, _))
+        ));
+
+        let err = dummy.update_level_routes(0, 3, vec![p]).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::NoSuchLevel { index: 0, level: 3 })
+        );
+        let bad = RouterPatch {
+            from_input: Some(3),
+            to_output: 0,
+        };
+        assert!(dummy.update_level_routes(0, 1, vec![bad]).await.is_err());
+
+        // Without levels configured, there's only level 0.
+        let plain = DummyRouter::with_config(1, 2, 2);
+        assert_eq!(plain.get_levels(0).await?.len(), 1);
+        assert!(plain.get_level_routes(0, 1).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn staged_routes() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 4, 3);
+        let mut stream = dummy.event_stream().await?;
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        let patch = |from_input, to_output| RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        };
+        let before = dummy.get_routes(0).await?;
+
+        // Staging doesn't touch the routes.
+        let a = dummy
+            .stage_routes(0, vec![patch(1, 0), patch(1, 1)])
+            .await?;
+        assert_eq!(dummy.get_routes(0).await?, before);
+        assert_eq!(
+            stream.next().await,
+            Some(RouterEvent::StagedRouteUpdate(
+                0,
+                vec![patch(1, 0), patch(1, 1)]
+            ))
+        );
+
+        // Overlapping stagings show the latest patch per output, but keep their own.
+        let b = dummy
+            .stage_routes(0, vec![patch(2, 1), patch(2, 2)])
+            .await?;
+        assert_ne!(a, b);
+        let staged = vec![patch(1, 0), patch(2, 1), patch(2, 2)];
+        assert_eq!(dummy.get_staged_routes(0).await?, staged);
+        assert_eq!(
+            stream.next().await,
+            Some(RouterEvent::StagedRouteUpdate(0, staged))
+        );
+
+        dummy.commit(a).await?;
+        let committed = vec![patch(1, 0), patch(1, 1), patch(0, 2)];
+        assert_eq!(dummy.get_routes(0).await?, committed);
+        assert_eq!(
+            stream.next().await,
+            Some(RouterEvent::RouteUpdate(0, vec![patch(1, 0), patch(1, 1)]))
+        );
+        assert_eq!(
+            stream.next().await,
+            Some(RouterEvent::StagedRouteUpdate(
+                0,
+                vec![patch(2, 1), patch(2, 2)]
+            ))
+        );
+
+        // Handles are single use.
+        assert!(dummy.commit(a).await.is_err());
+        assert!(dummy.discard(a).await.is_err());
+        dummy.discard(b).await?;
+        assert!(dummy.commit(b).await.is_err());
+        assert_eq!(dummy.get_routes(0).await?, committed);
+        assert_eq!(dummy.get_staged_routes(0).await?, vec![]);
+        assert_eq!(
+            stream.next().await,
+            Some(RouterEvent::StagedRouteUpdate(0, vec![]))
+        );
+
+        // Invalid patches are refused when staging.
+        let err = dummy.stage_routes(0, vec![patch(4, 0)]).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::OutOfRange {
+                index: 0,
+                patch: patch(4, 0)
+            })
+        );
+        assert!(dummy.stage_routes(1, vec![]).await.is_err());
+        Ok(())
     }
 
     #[tokio::test]
     async fn event_stream() {
         let dummy = DummyRouter::new();
         let mut stream = dummy.event_stream().await.unwrap();
-        dummy.push_event(RouterEvent::Connected);
+        // Alive from construction on, so subscribers start connected.
         assert_eq!(stream.next().await, Some(RouterEvent::Connected));
-        dummy.push_event(RouterEvent::Disconnected);
+        dummy.set_alive(false);
+        dummy.set_alive(false);
         assert_eq!(stream.next().await, Some(RouterEvent::Disconnected));
+
+        // Subscribers of a dead router only see Connected once it's back, followed by
+        // snapshots of everything.
+        let mut late = dummy.event_stream().await.unwrap();
+        dummy.set_alive(true);
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        assert_eq!(late.next().await, Some(RouterEvent::Connected));
+        assert_eq!(
+            late.next().await,
+            Some(RouterEvent::InputLabelSnapshot(
+                0,
+                dummy.get_input_labels(0).await.unwrap()
+            ))
+        );
+        assert_eq!(
+            late.next().await,
+            Some(RouterEvent::OutputLabelSnapshot(
+                0,
+                dummy.get_output_labels(0).await.unwrap()
+            ))
+        );
+        match late.next().await {
+            Some(RouterEvent::RouteSnapshot(0, routes)) => assert_eq!(routes.len(), 16),
+            ev => panic!("expected RouteSnapshot, got {:?}", ev),
+        }
+        dummy.push_event(RouterEvent::InfoUpdate(RouterInfo::default()));
+        assert_eq!(
+            late.next().await,
+            Some(RouterEvent::InfoUpdate(RouterInfo::default()))
+        );
+    }
+
+    #[tokio::test]
+    async fn atomic_routes_roll_back() {
+        let dummy = DummyRouter::with_config(1, 3, 3);
+        let mut stream = dummy.event_stream().await.unwrap();
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        let before = dummy.get_routes(0).await.unwrap();
+        let patch = |from_input, to_output| RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        };
+
+        // The bad patch comes last, the ones before it must not stick.
+        let changes = vec![patch(1, 0), patch(2, 1), patch(3, 2)];
+        let err = dummy.update_routes_atomic(0, changes).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::OutOfRange {
+                index: 0,
+                patch: patch(3, 2)
+            })
+        );
+        assert_eq!(dummy.get_routes(0).await.unwrap(), before);
+
+        let changes = vec![patch(1, 0), patch(2, 1), patch(2, 2)];
+        dummy
+            .update_routes_atomic(0, changes.clone())
+            .await
+            .unwrap();
+        assert_eq!(dummy.get_routes(0).await.unwrap(), changes);
+        // One event for the whole batch, none for the failed one.
+        assert_eq!(
+            stream.next().await,
+            Some(RouterEvent::RouteUpdate(0, changes))
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_update_applies_nothing() {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let before = dummy.get_input_labels(0).await.unwrap();
+        let changes = vec![
+            RouterLabel {
+                id: 0,
+                name: "Valid".to_string(),
+            },
+            RouterLabel {
+                id: 5,
+                name: "Bad".to_string(),
+            },
+        ];
+        assert!(dummy.update_input_labels(0, changes).await.is_err());
+        assert_eq!(dummy.get_input_labels(0).await.unwrap(), before);
+
+        let routes = dummy.get_routes(0).await.unwrap();
+        let changes = vec![
+            RouterPatch {
+                from_input: Some(1),
+                to_output: 0,
+            },
+            RouterPatch {
+                from_input: Some(1),
+                to_output: 5,
+            },
+        ];
+        assert!(dummy.update_routes(0, changes).await.is_err());
+        assert_eq!(dummy.get_routes(0).await.unwrap(), routes);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn no_torn_label_reads() {
+        let dummy = DummyRouter::with_config(1, 16, 16);
+        let generation = |n: usize| -> Vec<RouterLabel> {
+            (0..16)
+                .map(|id| RouterLabel {
+                    id,
+                    name: format!("Gen {}", n),
+                })
+                .collect()
+        };
+        dummy.update_input_labels(0, generation(0)).await.unwrap();
+
+        let writer = {
+            let dummy = dummy.clone();
+            tokio::spawn(async move {
+                for n in 1..200 {
+                    dummy.update_input_labels(0, generation(n)).await.unwrap();
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let dummy = dummy.clone();
+                tokio::spawn(async move {
+                    for _ in 0..200 {
+                        let labels = dummy.get_input_labels(0).await.unwrap();
+                        assert!(
+                            labels.iter().all(|l| l.name == labels[0].name),
+                            "torn read: {:?}",
+                            labels
+                        );
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+
+        writer.await.unwrap();
+        for r in readers {
+            r.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn frame_and_processing_unit_locks() {
+        let plain = DummyRouter::with_config(1, 2, 2);
+        assert!(plain.get_frame_locks(0).await.unwrap().is_empty());
+        assert!(plain.get_processing_unit_locks(0).await.unwrap().is_empty());
+
+        let dummy = DummyRouter::with_config(1, 2, 2)
+            .with_frame_count(2)
+            .with_processing_units(3);
+        let mut stream = dummy.event_stream().await.unwrap();
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        let lock = RouterLock {
+            id: 1,
+            locked: true,
+        };
+        dummy.update_frame_locks(0, vec![lock]).await.unwrap();
+        assert_eq!(
+            dummy.get_frame_locks(0).await.unwrap(),
+            vec![
+                RouterLock {
+                    id: 0,
+                    locked: false
+                },
+                lock
+            ]
+        );
+        assert!(matches!(
+            stream.next().await,
+            Some(RouterEvent::FrameLockUpdate(0, _))
+        ));
+
+        let lock = RouterLock { id: 2, ..lock };
+        dummy
+            .update_processing_unit_locks(0, vec![lock])
+            .await
+            .unwrap();
+        assert!(dummy
+            .get_processing_unit_locks(0)
+            .await
+            .unwrap()
+            .contains(&lock));
+        assert!(matches!(
+            stream.next().await,
+            Some(RouterEvent::ProcessingUnitLockUpdate(0, _))
+        ));
+
+        // Frame 2 doesn't exist, so nothing changes.
+        assert!(dummy.update_frame_locks(0, vec![lock]).await.is_err());
+        assert_eq!(dummy.get_frame_locks(0).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn output_locks() {
+        let plain = DummyRouter::with_config(1, 2, 3);
+        assert!(plain.get_locks(0).await.unwrap().is_empty());
+
+        let dummy = DummyRouter::with_config(1, 2, 3).with_output_locks();
+        let mut stream = dummy.event_stream().await.unwrap();
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+        assert_eq!(dummy.get_locks(0).await.unwrap(), unlocked(3));
+
+        let lock = RouterLock {
+            id: 2,
+            locked: true,
+        };
+        dummy.update_locks(0, vec![lock]).await.unwrap();
+        assert_eq!(dummy.get_locks(0).await.unwrap()[2], lock);
+        match stream.next().await {
+            Some(RouterEvent::LockUpdate(0, locks)) => assert!(locks.contains(&lock)),
+            ev => panic!("expected LockUpdate, got {:?}", ev),
+        }
+
+        // Output 3 doesn't exist, so nothing changes, not even output 0.
+        let changes = vec![RouterLock { id: 0, ..lock }, RouterLock { id: 3, ..lock }];
+        assert!(dummy.update_locks(0, changes).await.is_err());
+        assert!(!dummy.get_locks(0).await.unwrap()[0].locked);
+        assert!(dummy.update_locks(1, vec![lock]).await.is_err());
     }
 }
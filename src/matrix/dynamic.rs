@@ -0,0 +1,582 @@
+//! Type-erased routers
+//!
+//! [MatrixRouter] returns `impl Future`, which keeps it from being used as a trait object.
+//! [DynMatrixRouter] mirrors it with boxed futures, so routers picked at runtime can be held
+//! as `Arc<dyn DynMatrixRouter>`. Every [MatrixRouter] is a [DynMatrixRouter], and
+//! `Arc<dyn DynMatrixRouter>` is a [MatrixRouter] again, so frontends take either.
+//!
+//! Both traits have the same method names, so only one of them should be in scope where
+//! methods get called. That's why this module isn't part of the `matrix::*` re-exports.
+
+use super::*;
+use anyhow::Result;
+use futures_core::stream::BoxStream;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Boxed future returned by [DynMatrixRouter].
+pub type DynFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + Sync + 'a>>;
+
+/// Object safe form of [MatrixRouter], see there for what the methods do.
+pub trait DynMatrixRouter: Send + Sync {
+    fn is_alive(&self) -> DynFuture<'_, bool>;
+    fn get_router_info(&self) -> DynFuture<'_, RouterInfo>;
+    fn get_matrix_info(&self, index: u32) -> DynFuture<'_, RouterMatrixInfo>;
+    fn get_input_labels(&self, index: u32) -> DynFuture<'_, Vec<RouterLabel>>;
+    fn get_output_labels(&self, index: u32) -> DynFuture<'_, Vec<RouterLabel>>;
+    fn find_input_by_label<'a>(&'a self, index: u32, name: &'a str) -> DynFuture<'a, Option<u32>>;
+    fn find_output_by_label<'a>(&'a self, index: u32, name: &'a str) -> DynFuture<'a, Option<u32>>;
+    fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> DynFuture<'_, ()>;
+    fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> DynFuture<'_, ()>;
+    fn get_input_descriptions(&self, index: u32) -> DynFuture<'_, Vec<RouterDescription>>;
+    fn get_output_descriptions(&self, index: u32) -> DynFuture<'_, Vec<RouterDescription>>;
+    fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> DynFuture<'_, ()>;
+    fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> DynFuture<'_, ()>;
+    fn get_input_status(&self, index: u32) -> DynFuture<'_, Vec<RouterPortStatus>>;
+    fn get_output_status(&self, index: u32) -> DynFuture<'_, Vec<RouterPortStatus>>;
+    fn get_routes(&self, index: u32) -> DynFuture<'_, Vec<RouterPatch>>;
+    fn get_route_for_output(&self, index: u32, output: u32) -> DynFuture<'_, Option<RouterPatch>>;
+    fn get_route(&self, index: u32, output: u32) -> DynFuture<'_, RouterPatch>;
+    fn get_route_for_input(&self, index: u32, input: u32) -> DynFuture<'_, Vec<u32>>;
+    fn set_route(&self, index: u32, output: u32, input: u32) -> DynFuture<'_, ()>;
+    fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> DynFuture<'_, ()>;
+    fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> DynFuture<'_, ()>;
+    fn get_levels(&self, index: u32) -> DynFuture<'_, Vec<RouterLevel>>;
+    fn get_level_routes(&self, index: u32, level: u32) -> DynFuture<'_, Vec<RouterPatch>>;
+    fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> DynFuture<'_, ()>;
+    fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> DynFuture<'_, TakeHandle>;
+    fn commit(&self, handle: TakeHandle) -> DynFuture<'_, ()>;
+    fn discard(&self, handle: TakeHandle) -> DynFuture<'_, ()>;
+    fn get_staged_routes(&self, index: u32) -> DynFuture<'_, Vec<RouterPatch>>;
+    fn get_locks(&self, index: u32) -> DynFuture<'_, Vec<RouterLock>>;
+    fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> DynFuture<'_, ()>;
+    fn with_lock_owner(&self, owner: &str) -> Option<Arc<dyn DynMatrixRouter>>;
+    fn get_frame_labels(&self, index: u32) -> DynFuture<'_, Vec<RouterLabel>>;
+    fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> DynFuture<'_, ()>;
+    fn get_frame_routes(&self, index: u32) -> DynFuture<'_, Vec<RouterPatch>>;
+    fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> DynFuture<'_, ()>;
+    fn get_frame_locks(&self, index: u32) -> DynFuture<'_, Vec<RouterLock>>;
+    fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> DynFuture<'_, ()>;
+    fn get_processing_unit_locks(&self, index: u32) -> DynFuture<'_, Vec<RouterLock>>;
+    fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> DynFuture<'_, ()>;
+    fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32)
+        -> DynFuture<'_, PortMetadata>;
+    fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> DynFuture<'_, ()>;
+    fn get_alarms(&self) -> DynFuture<'_, Vec<RouterAlarm>>;
+    fn capabilities(&self) -> RouterCapabilities;
+    fn event_stream(&self) -> DynFuture<'_, BoxStream<'_, RouterEvent>>;
+}
+
+impl<R: MatrixRouter + 'static> DynMatrixRouter for R {
+    fn is_alive(&self) -> DynFuture<'_, bool> {
+        Box::pin(MatrixRouter::is_alive(self))
+    }
+
+    fn get_router_info(&self) -> DynFuture<'_, RouterInfo> {
+        Box::pin(MatrixRouter::get_router_info(self))
+    }
+
+    fn get_matrix_info(&self, index: u32) -> DynFuture<'_, RouterMatrixInfo> {
+        Box::pin(MatrixRouter::get_matrix_info(self, index))
+    }
+
+    fn get_input_labels(&self, index: u32) -> DynFuture<'_, Vec<RouterLabel>> {
+        Box::pin(MatrixRouter::get_input_labels(self, index))
+    }
+
+    fn get_output_labels(&self, index: u32) -> DynFuture<'_, Vec<RouterLabel>> {
+        Box::pin(MatrixRouter::get_output_labels(self, index))
+    }
+
+    fn find_input_by_label<'a>(&'a self, index: u32, name: &'a str) -> DynFuture<'a, Option<u32>> {
+        Box::pin(MatrixRouter::find_input_by_label(self, index, name))
+    }
+
+    fn find_output_by_label<'a>(&'a self, index: u32, name: &'a str) -> DynFuture<'a, Option<u32>> {
+        Box::pin(MatrixRouter::find_output_by_label(self, index, name))
+    }
+
+    fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::update_input_labels(self, index, changed))
+    }
+
+    fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::update_output_labels(self, index, changed))
+    }
+
+    fn get_input_descriptions(&self, index: u32) -> DynFuture<'_, Vec<RouterDescription>> {
+        Box::pin(MatrixRouter::get_input_descriptions(self, index))
+    }
+
+    fn get_output_descriptions(&self, index: u32) -> DynFuture<'_, Vec<RouterDescription>> {
+        Box::pin(MatrixRouter::get_output_descriptions(self, index))
+    }
+
+    fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::update_input_descriptions(
+            self, index, changed,
+        ))
+    }
+
+    fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::update_output_descriptions(
+            self, index, changed,
+        ))
+    }
+
+    fn get_input_status(&self, index: u32) -> DynFuture<'_, Vec<RouterPortStatus>> {
+        Box::pin(MatrixRouter::get_input_status(self, index))
+    }
+
+    fn get_output_status(&self, index: u32) -> DynFuture<'_, Vec<RouterPortStatus>> {
+        Box::pin(MatrixRouter::get_output_status(self, index))
+    }
+
+    fn get_routes(&self, index: u32) -> DynFuture<'_, Vec<RouterPatch>> {
+        Box::pin(MatrixRouter::get_routes(self, index))
+    }
+
+    fn get_route_for_output(&self, index: u32, output: u32) -> DynFuture<'_, Option<RouterPatch>> {
+        Box::pin(MatrixRouter::get_route_for_output(self, index, output))
+    }
+
+    fn get_route(&self, index: u32, output: u32) -> DynFuture<'_, RouterPatch> {
+        Box::pin(MatrixRouter::get_route(self, index, output))
+    }
+
+    fn get_route_for_input(&self, index: u32, input: u32) -> DynFuture<'_, Vec<u32>> {
+        Box::pin(MatrixRouter::get_route_for_input(self, index, input))
+    }
+
+    fn set_route(&self, index: u32, output: u32, input: u32) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::set_route(self, index, output, input))
+    }
+
+    fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::update_routes(self, index, changes))
+    }
+
+    fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::update_routes_atomic(self, index, changes))
+    }
+
+    fn get_levels(&self, index: u32) -> DynFuture<'_, Vec<RouterLevel>> {
+        Box::pin(MatrixRouter::get_levels(self, index))
+    }
+
+    fn get_level_routes(&self, index: u32, level: u32) -> DynFuture<'_, Vec<RouterPatch>> {
+        Box::pin(MatrixRouter::get_level_routes(self, index, level))
+    }
+
+    fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::update_level_routes(
+            self, index, level, changes,
+        ))
+    }
+
+    fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> DynFuture<'_, TakeHandle> {
+        Box::pin(MatrixRouter::stage_routes(self, index, changes))
+    }
+
+    fn commit(&self, handle: TakeHandle) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::commit(self, handle))
+    }
+
+    fn discard(&self, handle: TakeHandle) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::discard(self, handle))
+    }
+
+    fn get_staged_routes(&self, index: u32) -> DynFuture<'_, Vec<RouterPatch>> {
+        Box::pin(MatrixRouter::get_staged_routes(self, index))
+    }
+
+    fn get_locks(&self, index: u32) -> DynFuture<'_, Vec<RouterLock>> {
+        Box::pin(MatrixRouter::get_locks(self, index))
+    }
+
+    fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::update_locks(self, index, changes))
+    }
+
+    fn with_lock_owner(&self, owner: &str) -> Option<Arc<dyn DynMatrixRouter>> {
+        MatrixRouter::with_lock_owner(self, owner).map(|r| Arc::new(r) as Arc<dyn DynMatrixRouter>)
+    }
+
+    fn get_frame_labels(&self, index: u32) -> DynFuture<'_, Vec<RouterLabel>> {
+        Box::pin(MatrixRouter::get_frame_labels(self, index))
+    }
+
+    fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::update_frame_labels(self, index, changed))
+    }
+
+    fn get_frame_routes(&self, index: u32) -> DynFuture<'_, Vec<RouterPatch>> {
+        Box::pin(MatrixRouter::get_frame_routes(self, index))
+    }
+
+    fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::update_frame_routes(self, index, changes))
+    }
+
+    fn get_frame_locks(&self, index: u32) -> DynFuture<'_, Vec<RouterLock>> {
+        Box::pin(MatrixRouter::get_frame_locks(self, index))
+    }
+
+    fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::update_frame_locks(self, index, changes))
+    }
+
+    fn get_processing_unit_locks(&self, index: u32) -> DynFuture<'_, Vec<RouterLock>> {
+        Box::pin(MatrixRouter::get_processing_unit_locks(self, index))
+    }
+
+    fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::update_processing_unit_locks(
+            self, index, changes,
+        ))
+    }
+
+    fn get_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+    ) -> DynFuture<'_, PortMetadata> {
+        Box::pin(MatrixRouter::get_port_metadata(self, index, kind, id))
+    }
+
+    fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> DynFuture<'_, ()> {
+        Box::pin(MatrixRouter::set_port_metadata(
+            self, index, kind, id, metadata,
+        ))
+    }
+
+    fn get_alarms(&self) -> DynFuture<'_, Vec<RouterAlarm>> {
+        Box::pin(MatrixRouter::get_alarms(self))
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        MatrixRouter::capabilities(self)
+    }
+
+    fn event_stream(&self) -> DynFuture<'_, BoxStream<'_, RouterEvent>> {
+        Box::pin(MatrixRouter::event_stream(self))
+    }
+}
+
+impl MatrixRouter for Arc<dyn DynMatrixRouter> {
+    async fn is_alive(&self) -> Result<bool> {
+        DynMatrixRouter::is_alive(&**self).await
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        DynMatrixRouter::get_router_info(&**self).await
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        DynMatrixRouter::get_matrix_info(&**self, index).await
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        DynMatrixRouter::get_input_labels(&**self, index).await
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        DynMatrixRouter::get_output_labels(&**self, index).await
+    }
+
+    async fn find_input_by_label(&self, index: u32, name: &str) -> Result<Option<u32>> {
+        DynMatrixRouter::find_input_by_label(&**self, index, name).await
+    }
+
+    async fn find_output_by_label(&self, index: u32, name: &str) -> Result<Option<u32>> {
+        DynMatrixRouter::find_output_by_label(&**self, index, name).await
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        DynMatrixRouter::update_input_labels(&**self, index, changed).await
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        DynMatrixRouter::update_output_labels(&**self, index, changed).await
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        DynMatrixRouter::get_input_descriptions(&**self, index).await
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        DynMatrixRouter::get_output_descriptions(&**self, index).await
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        DynMatrixRouter::update_input_descriptions(&**self, index, changed).await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        DynMatrixRouter::update_output_descriptions(&**self, index, changed).await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        DynMatrixRouter::get_input_status(&**self, index).await
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        DynMatrixRouter::get_output_status(&**self, index).await
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        DynMatrixRouter::get_routes(&**self, index).await
+    }
+
+    async fn get_route_for_output(&self, index: u32, output: u32) -> Result<Option<RouterPatch>> {
+        DynMatrixRouter::get_route_for_output(&**self, index, output).await
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        DynMatrixRouter::get_route(&**self, index, output).await
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        DynMatrixRouter::get_route_for_input(&**self, index, input).await
+    }
+
+    async fn set_route(&self, index: u32, output: u32, input: u32) -> Result<()> {
+        DynMatrixRouter::set_route(&**self, index, output, input).await
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        DynMatrixRouter::update_routes(&**self, index, changes).await
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        DynMatrixRouter::update_routes_atomic(&**self, index, changes).await
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        DynMatrixRouter::get_levels(&**self, index).await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        DynMatrixRouter::get_level_routes(&**self, index, level).await
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        DynMatrixRouter::update_level_routes(&**self, index, level, changes).await
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        DynMatrixRouter::stage_routes(&**self, index, changes).await
+    }
+
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        DynMatrixRouter::commit(&**self, handle).await
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        DynMatrixRouter::discard(&**self, handle).await
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        DynMatrixRouter::get_staged_routes(&**self, index).await
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        DynMatrixRouter::get_locks(&**self, index).await
+    }
+
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        DynMatrixRouter::update_locks(&**self, index, changes).await
+    }
+
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        DynMatrixRouter::with_lock_owner(&**self, owner)
+    }
+
+    async fn get_frame_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        DynMatrixRouter::get_frame_labels(&**self, index).await
+    }
+
+    async fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        DynMatrixRouter::update_frame_labels(&**self, index, changed).await
+    }
+
+    async fn get_frame_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        DynMatrixRouter::get_frame_routes(&**self, index).await
+    }
+
+    async fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        DynMatrixRouter::update_frame_routes(&**self, index, changes).await
+    }
+
+    async fn get_frame_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        DynMatrixRouter::get_frame_locks(&**self, index).await
+    }
+
+    async fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        DynMatrixRouter::update_frame_locks(&**self, index, changes).await
+    }
+
+    async fn get_processing_unit_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        DynMatrixRouter::get_processing_unit_locks(&**self, index).await
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        DynMatrixRouter::update_processing_unit_locks(&**self, index, changes).await
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        DynMatrixRouter::get_port_metadata(&**self, index, kind, id).await
+    }
+
+    async fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> Result<()> {
+        DynMatrixRouter::set_port_metadata(&**self, index, kind, id, metadata).await
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        DynMatrixRouter::get_alarms(&**self).await
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        DynMatrixRouter::capabilities(&**self)
+    }
+
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        DynMatrixRouter::event_stream(&**self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frontend::VideohubFrontend;
+    use crate::matrix::{DummyRouter, MatrixRouter, MetadataRouter, PortKind, RouterPatch};
+    use anyhow::Result;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use videohub::VideohubClient;
+
+    type Routers = Vec<Arc<dyn super::DynMatrixRouter>>;
+
+    /// Patch output 1 to input 1 and read it back, the same way for every router.
+    async fn patch(router: &impl MatrixRouter) -> Result<RouterPatch> {
+        let p = RouterPatch {
+            from_input: Some(1),
+            to_output: 1,
+        };
+        router.update_routes(0, vec![p]).await?;
+        router.get_route(0, 1).await
+    }
+
+    #[tokio::test]
+    async fn mixed_routers() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let metadata = MetadataRouter::new(DummyRouter::with_config(1, 3, 3));
+        let routers: Routers = vec![dummy.clone().into_dyn(), metadata.clone().into_dyn()];
+        for router in &routers {
+            assert_eq!(patch(router).await?.from_input, Some(1));
+        }
+        assert_eq!(dummy.get_routes(0).await?[1].from_input, Some(1));
+        assert_eq!(metadata.inner().get_routes(0).await?[1].from_input, Some(1));
+
+        // Whatever the router supports stays the same behind the box.
+        let infos = [
+            routers[0].get_matrix_info(0).await?,
+            routers[1].get_matrix_info(0).await?,
+        ];
+        assert_eq!((infos[0].input_count, infos[1].input_count), (2, 3));
+        assert!(routers[0]
+            .get_port_metadata(0, PortKind::Input, 0)
+            .await
+            .is_err());
+        assert!(routers[1]
+            .get_port_metadata(0, PortKind::Input, 0)
+            .await
+            .is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serve_dyn_router() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 4, 2);
+        let routers: Routers = vec![dummy.clone().into_dyn()];
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let frontend = VideohubFrontend::new(Arc::new(routers[0].clone()), 0);
+        tokio::spawn(frontend.serve(listener));
+
+        let (client, _events) = VideohubClient::connect(addr).await?;
+        client.set_route(1, 3).await?;
+        assert_eq!(dummy.get_routes(0).await?[1].from_input, Some(3));
+        Ok(())
+    }
+}
@@ -1,7 +1,9 @@
 use super::model::*;
-use anyhow::Result;
+use super::EventSubscription;
+use anyhow::{anyhow, Result};
 use futures_core::stream::BoxStream;
 use std::future::Future;
+use std::sync::Arc;
 
 /// Matrix Router Abstraction.
 ///
@@ -11,6 +13,17 @@ use std::future::Future;
 /// Some information might be wise to cache, but it's the implementation's choice whether to do so.
 /// Caching some information might result in outdated information being returned if the router is
 /// being controlled outside of this instance. A setting might be wise.
+///
+/// # Consistency
+///
+/// Every single update call must appear atomic to readers: a concurrent `get_*` call observes
+/// either the complete state before or the complete state after it, never a partially applied
+/// update. Likewise, an update that fails must not leave any of its changes applied.
+///
+/// The exception are routers applying changes step by step, where a later step can fail
+/// after earlier ones took effect and can't be undone, like a hub refusing the second of
+/// several blocks. Those fail with [RouterError::PartiallyApplied] listing which outputs
+/// changed, and announce them like any other change.
 pub trait MatrixRouter: Send + Sync {
     /// Return whether or not the Router is assumed connected.
     ///
@@ -51,6 +64,30 @@ pub trait MatrixRouter: Send + Sync {
         index: u32,
     ) -> impl Future<Output = Result<Vec<RouterLabel>>> + Send + Sync;
 
+    /// Find the first input labelled `name`, `None` if no input is.
+    fn find_input_by_label(
+        &self,
+        index: u32,
+        name: &str,
+    ) -> impl Future<Output = Result<Option<u32>>> + Send + Sync {
+        async move {
+            let labels = self.get_input_labels(index).await?;
+            Ok(labels.into_iter().find(|l| l.name == name).map(|l| l.id))
+        }
+    }
+
+    /// Find the first output labelled `name`, `None` if no output is.
+    fn find_output_by_label(
+        &self,
+        index: u32,
+        name: &str,
+    ) -> impl Future<Output = Result<Option<u32>>> + Send + Sync {
+        async move {
+            let labels = self.get_output_labels(index).await?;
+            Ok(labels.into_iter().find(|l| l.name == name).map(|l| l.id))
+        }
+    }
+
     /// Update Input Labels.
     ///
     /// The provided changed labels will be merged with the existing labels.
@@ -69,12 +106,147 @@ pub trait MatrixRouter: Send + Sync {
         changed: Vec<RouterLabel>,
     ) -> impl Future<Output = Result<()>> + Send + Sync;
 
+    /// Get long descriptions of the inputs, one per input.
+    ///
+    /// Routers without descriptions return an empty one for every input.
+    fn get_input_descriptions(
+        &self,
+        index: u32,
+    ) -> impl Future<Output = Result<Vec<RouterDescription>>> + Send + Sync {
+        async move {
+            let mi = self.get_matrix_info(index).await?;
+            Ok((0..mi.input_count).map(RouterDescription::empty).collect())
+        }
+    }
+
+    /// Get long descriptions of the outputs, one per output.
+    ///
+    /// Routers without descriptions return an empty one for every output.
+    fn get_output_descriptions(
+        &self,
+        index: u32,
+    ) -> impl Future<Output = Result<Vec<RouterDescription>>> + Send + Sync {
+        async move {
+            let mi = self.get_matrix_info(index).await?;
+            Ok((0..mi.output_count).map(RouterDescription::empty).collect())
+        }
+    }
+
+    /// Update input descriptions, merging the changed ones with the existing ones.
+    ///
+    /// Changes are announced as [RouterEvent::DescriptionUpdate].
+    fn update_input_descriptions(
+        &self,
+        _index: u32,
+        _changed: Vec<RouterDescription>,
+    ) -> impl Future<Output = Result<()>> + Send + Sync {
+        async { Err(anyhow!("Router has no port descriptions")) }
+    }
+
+    /// Update output descriptions, merging the changed ones with the existing ones.
+    ///
+    /// Changes are announced as [RouterEvent::DescriptionUpdate].
+    fn update_output_descriptions(
+        &self,
+        _index: u32,
+        _changed: Vec<RouterDescription>,
+    ) -> impl Future<Output = Result<()>> + Send + Sync {
+        async { Err(anyhow!("Router has no port descriptions")) }
+    }
+
+    /// Get the status of the inputs, one per input.
+    ///
+    /// Changes are announced as [RouterEvent::PortStatusUpdate]. Routers that can't tell
+    /// report every input as unknown.
+    fn get_input_status(
+        &self,
+        index: u32,
+    ) -> impl Future<Output = Result<Vec<RouterPortStatus>>> + Send + Sync {
+        async move {
+            let mi = self.get_matrix_info(index).await?;
+            Ok((0..mi.input_count)
+                .map(|id| RouterPortStatus::unknown(PortKind::Input, id))
+                .collect())
+        }
+    }
+
+    /// Get the status of the outputs, one per output.
+    ///
+    /// Changes are announced as [RouterEvent::PortStatusUpdate]. Routers that can't tell
+    /// report every output as unknown.
+    fn get_output_status(
+        &self,
+        index: u32,
+    ) -> impl Future<Output = Result<Vec<RouterPortStatus>>> + Send + Sync {
+        async move {
+            let mi = self.get_matrix_info(index).await?;
+            Ok((0..mi.output_count)
+                .map(|id| RouterPortStatus::unknown(PortKind::Output, id))
+                .collect())
+        }
+    }
+
     /// Get currently patched routes.
     fn get_routes(
         &self,
         index: u32,
     ) -> impl Future<Output = Result<Vec<RouterPatch>>> + Send + Sync;
 
+    /// Get the patch of a single output, `None` for outputs without one.
+    ///
+    /// The default searches through [MatrixRouter::get_routes], routers with direct access
+    /// to their routes should override it to skip copying all of them.
+    fn get_route_for_output(
+        &self,
+        index: u32,
+        output: u32,
+    ) -> impl Future<Output = Result<Option<RouterPatch>>> + Send + Sync {
+        async move {
+            Ok(self
+                .get_routes(index)
+                .await?
+                .into_iter()
+                .find(|p| p.to_output == output))
+        }
+    }
+
+    /// Get the patch of a single output, failing for outputs without one.
+    ///
+    /// See [MatrixRouter::get_route_for_output].
+    fn get_route(
+        &self,
+        index: u32,
+        output: u32,
+    ) -> impl Future<Output = Result<RouterPatch>> + Send + Sync {
+        async move {
+            self.get_route_for_output(index, output)
+                .await?
+                .ok_or_else(|| anyhow!("No route for output {} in matrix {}", output, index))
+        }
+    }
+
+    /// Get the outputs currently fed by `input`, sorted by id.
+    ///
+    /// The default scans [MatrixRouter::get_routes], routers with direct access to their
+    /// routes should override it to skip copying all of them.
+    fn get_route_for_input(
+        &self,
+        index: u32,
+        input: u32,
+    ) -> impl Future<Output = Result<Vec<u32>>> + Send + Sync {
+        async move {
+            let mut outputs: Vec<u32> = self
+                .get_routes(index)
+                .await?
+                .into_iter()
+                .filter(|p| p.from_input == Some(input))
+                .map(|p| p.to_output)
+                .collect();
+            outputs.sort_unstable();
+            Ok(outputs)
+        }
+    }
+
     /// Update patched routes.
     ///
     /// The provided patches will update the existing patched routes.
@@ -84,8 +256,290 @@ pub trait MatrixRouter: Send + Sync {
         changes: Vec<RouterPatch>,
     ) -> impl Future<Output = Result<()>> + Send + Sync;
 
-    // TODO: get/update locks?
-    // TODO: alarms? settings?
+    /// Patch `input` to `output`.
+    ///
+    /// The default is a single patch [MatrixRouter::update_routes].
+    fn set_route(
+        &self,
+        index: u32,
+        output: u32,
+        input: u32,
+    ) -> impl Future<Output = Result<()>> + Send + Sync {
+        self.update_routes(
+            index,
+            vec![RouterPatch {
+                from_input: Some(input),
+                to_output: output,
+            }],
+        )
+    }
+
+    /// Update patched routes all at once or not at all.
+    ///
+    /// Every patch is checked against the matrix first, a single one out of range fails the
+    /// whole update with [RouterError::OutOfRange] without changing anything.
+    /// The default relies on [MatrixRouter::update_routes] applying a valid batch in one go,
+    /// routers splitting updates up should send them as one here.
+    fn update_routes_atomic(
+        &self,
+        index: u32,
+        changes: Vec<RouterPatch>,
+    ) -> impl Future<Output = Result<()>> + Send + Sync {
+        async move {
+            let mi = self.get_matrix_info(index).await?;
+            mi.check_patches(index, &changes)?;
+            self.update_routes(index, changes).await
+        }
+    }
+
+    /// Get the routing levels of a matrix, like video and audio for breakaway routing.
+    ///
+    /// Level 0 is what the level-less route calls work on. Routers without levels only
+    /// have that one, leveled calls for any other level fail with [RouterError::NoSuchLevel]
+    /// rather than being applied to level 0.
+    fn get_levels(
+        &self,
+        index: u32,
+    ) -> impl Future<Output = Result<Vec<RouterLevel>>> + Send + Sync {
+        async move {
+            self.get_matrix_info(index).await?;
+            Ok(vec![RouterLevel {
+                id: 0,
+                name: "Video".into(),
+            }])
+        }
+    }
+
+    /// Get patched routes of a level, see [MatrixRouter::get_levels].
+    fn get_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+    ) -> impl Future<Output = Result<Vec<RouterPatch>>> + Send + Sync {
+        async move {
+            match level {
+                0 => self.get_routes(index).await,
+                _ => Err(RouterError::NoSuchLevel { index, level }.into()),
+            }
+        }
+    }
+
+    /// Update patched routes of a level, independently of all other levels.
+    fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> impl Future<Output = Result<()>> + Send + Sync {
+        async move {
+            match level {
+                0 => self.update_routes(index, changes).await,
+                _ => Err(RouterError::NoSuchLevel { index, level }.into()),
+            }
+        }
+    }
+
+    /// Stage patches to apply later with [MatrixRouter::commit], or drop with
+    /// [MatrixRouter::discard].
+    ///
+    /// Patches are checked like [MatrixRouter::update_routes_atomic] when staging.
+    /// The default applies them right away for routers without native staging,
+    /// committing the handle then does nothing and discarding it fails.
+    fn stage_routes(
+        &self,
+        index: u32,
+        changes: Vec<RouterPatch>,
+    ) -> impl Future<Output = Result<TakeHandle>> + Send + Sync {
+        async move {
+            self.update_routes_atomic(index, changes).await?;
+            Ok(TakeHandle { index, id: 0 })
+        }
+    }
+
+    /// Apply staged routes all at once.
+    ///
+    /// Every handle can be committed or discarded once.
+    fn commit(&self, _handle: TakeHandle) -> impl Future<Output = Result<()>> + Send + Sync {
+        async { Ok(()) }
+    }
+
+    /// Drop staged routes without applying them.
+    fn discard(&self, handle: TakeHandle) -> impl Future<Output = Result<()>> + Send + Sync {
+        async move {
+            Err(anyhow!(
+                "Routes staged on matrix {} were already applied",
+                handle.index
+            ))
+        }
+    }
+
+    /// Get routes currently staged, at most one patch per output.
+    ///
+    /// Where several stagings patch the same output, the latest one is returned.
+    fn get_staged_routes(
+        &self,
+        _index: u32,
+    ) -> impl Future<Output = Result<Vec<RouterPatch>>> + Send + Sync {
+        async { Ok(vec![]) }
+    }
+
+    /// Get output locks, one per output.
+    ///
+    /// Routers without output locks return no locks, leaving it to frontends
+    /// to arbitrate between their clients.
+    fn get_locks(
+        &self,
+        _index: u32,
+    ) -> impl Future<Output = Result<Vec<RouterLock>>> + Send + Sync {
+        async { Ok(vec![]) }
+    }
+
+    /// Lock or unlock outputs.
+    fn update_locks(
+        &self,
+        _index: u32,
+        _changes: Vec<RouterLock>,
+    ) -> impl Future<Output = Result<()>> + Send + Sync {
+        async { Err(anyhow!("Router has no output locks")) }
+    }
+
+    /// A handle to this router taking output locks on behalf of `owner`, like a client address.
+    ///
+    /// Routers telling lock owners apart return one sharing their state, others `None`.
+    fn with_lock_owner(&self, _owner: &str) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    /// Get Frame Buffer Labels.
+    ///
+    /// Routers without frame buffers return no labels.
+    fn get_frame_labels(
+        &self,
+        _index: u32,
+    ) -> impl Future<Output = Result<Vec<RouterLabel>>> + Send + Sync {
+        async { Ok(vec![]) }
+    }
+
+    /// Update Frame Buffer Labels.
+    ///
+    /// The provided changed labels will be merged with the existing labels.
+    fn update_frame_labels(
+        &self,
+        _index: u32,
+        _changed: Vec<RouterLabel>,
+    ) -> impl Future<Output = Result<()>> + Send + Sync {
+        async { Err(anyhow!("Router has no frame buffers")) }
+    }
+
+    /// Get currently patched frame buffer routes.
+    ///
+    /// `to_output` is the frame buffer, `from_input` the input recorded into it.
+    fn get_frame_routes(
+        &self,
+        _index: u32,
+    ) -> impl Future<Output = Result<Vec<RouterPatch>>> + Send + Sync {
+        async { Ok(vec![]) }
+    }
+
+    /// Update patched frame buffer routes.
+    ///
+    /// The provided patches will update the existing patched frame buffer routes.
+    fn update_frame_routes(
+        &self,
+        _index: u32,
+        _changes: Vec<RouterPatch>,
+    ) -> impl Future<Output = Result<()>> + Send + Sync {
+        async { Err(anyhow!("Router has no frame buffers")) }
+    }
+
+    /// Get frame buffer locks.
+    ///
+    /// Routers without frame buffers return no locks.
+    fn get_frame_locks(
+        &self,
+        _index: u32,
+    ) -> impl Future<Output = Result<Vec<RouterLock>>> + Send + Sync {
+        async { Ok(vec![]) }
+    }
+
+    /// Lock or unlock frame buffers.
+    fn update_frame_locks(
+        &self,
+        _index: u32,
+        _changes: Vec<RouterLock>,
+    ) -> impl Future<Output = Result<()>> + Send + Sync {
+        async { Err(anyhow!("Router has no frame buffers")) }
+    }
+
+    /// Get processing unit locks, one per processing unit.
+    ///
+    /// Routers without processing units return no locks.
+    fn get_processing_unit_locks(
+        &self,
+        _index: u32,
+    ) -> impl Future<Output = Result<Vec<RouterLock>>> + Send + Sync {
+        async { Ok(vec![]) }
+    }
+
+    /// Lock or unlock processing units.
+    fn update_processing_unit_locks(
+        &self,
+        _index: u32,
+        _changes: Vec<RouterLock>,
+    ) -> impl Future<Output = Result<()>> + Send + Sync {
+        async { Err(anyhow!("Router has no processing units")) }
+    }
+
+    /// Get descriptive metadata of a port.
+    ///
+    /// Most routers can't store this, see [super::MetadataRouter] for a generic store.
+    fn get_port_metadata(
+        &self,
+        _index: u32,
+        _kind: PortKind,
+        _id: u32,
+    ) -> impl Future<Output = Result<PortMetadata>> + Send + Sync {
+        async { Err(anyhow!("Port metadata is unsupported")) }
+    }
+
+    /// Replace the descriptive metadata of a port.
+    fn set_port_metadata(
+        &self,
+        _index: u32,
+        _kind: PortKind,
+        _id: u32,
+        _metadata: PortMetadata,
+    ) -> impl Future<Output = Result<()>> + Send + Sync {
+        async { Err(anyhow!("Port metadata is unsupported")) }
+    }
+
+    /// Get the alarms of the router, like fan or power supply failures.
+    ///
+    /// Changes are announced as [RouterEvent::AlarmUpdate]. Routers without alarms
+    /// return none.
+    fn get_alarms(&self) -> impl Future<Output = Result<Vec<RouterAlarm>>> + Send + Sync {
+        async { Ok(vec![]) }
+    }
+
+    /// What this router supports, so callers can leave out what it doesn't.
+    ///
+    /// Backends only learning this once connected report what they know so far.
+    fn capabilities(&self) -> RouterCapabilities {
+        RouterCapabilities::default()
+    }
+
+    // TODO: settings?
+
+    /// Type-erase this router, for holding routers picked at runtime side by side.
+    fn into_dyn(self) -> Arc<dyn super::dynamic::DynMatrixRouter>
+    where
+        Self: Sized + 'static,
+    {
+        Arc::new(self)
+    }
 
     /// Subscribe to Events, creating a [futures_core::Stream].
     /// There is no explicit guarantee to get all events.
@@ -95,4 +549,12 @@ pub trait MatrixRouter: Send + Sync {
     fn event_stream<'a>(
         &'a self,
     ) -> impl Future<Output = Result<BoxStream<'a, RouterEvent>>> + Send + Sync;
+
+    /// Start building a subscription to just some of the events, see [EventSubscription].
+    fn events(&self) -> EventSubscription<'_, Self>
+    where
+        Self: Sized,
+    {
+        EventSubscription::new(self)
+    }
 }
@@ -0,0 +1,66 @@
+//! Router introspection
+//!
+//! Composed routers wrap each other, [RouterIntrospect] lets a running one describe its layers
+//! from the outermost wrapper down to the backend.
+
+/// A layer of a (possibly composed) router describing itself.
+pub trait RouterIntrospect {
+    /// Short name of this layer, usually its type name.
+    fn name(&self) -> &'static str;
+
+    /// Key configuration values as `(key, value)` pairs.
+    fn config_summary(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+
+    /// Counters of this layer, like rejected requests, if it tracks any.
+    fn counters(&self) -> Vec<(String, u64)> {
+        vec![]
+    }
+
+    /// The wrapped router, `None` for backends.
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        None
+    }
+}
+
+/// Description of a single layer, see [describe_router].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LayerDescription {
+    pub name: &'static str,
+    pub config: Vec<(String, String)>,
+    pub counters: Vec<(String, u64)>,
+}
+
+/// Walk a composed router, outermost layer first.
+pub fn describe_router(router: &dyn RouterIntrospect) -> Vec<LayerDescription> {
+    let mut layers = Vec::new();
+    let mut current = Some(router);
+    while let Some(layer) = current {
+        layers.push(LayerDescription {
+            name: layer.name(),
+            config: layer.config_summary(),
+            counters: layer.counters(),
+        });
+        current = layer.inner();
+    }
+    layers
+}
+
+impl<T: RouterIntrospect + ?Sized> RouterIntrospect for std::sync::Arc<T> {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        (**self).config_summary()
+    }
+
+    fn counters(&self) -> Vec<(String, u64)> {
+        (**self).counters()
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        (**self).inner()
+    }
+}
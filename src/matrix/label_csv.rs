@@ -0,0 +1,456 @@
+//! Label import and export as CSV
+//!
+//! Rows are `matrix,direction,id,name`, with direction being `input` or `output`, preceded
+//! by a header line of the same names. Importing first computes a diff against the current
+//! labels of a router, which can then be applied in batches.
+
+use super::*;
+use anyhow::{anyhow, Result};
+use std::collections::{btree_map::Entry, BTreeMap};
+use std::fmt;
+
+/// Header line of exported CSV.
+pub const HEADER: &str = "matrix,direction,id,name";
+
+/// Longest label accepted on import, in characters.
+pub const MAX_LABEL_LEN: usize = 255;
+
+/// A single parsed row.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LabelRow {
+    /// Line the row starts at, counting from 1.
+    pub line: usize,
+    pub matrix: u32,
+    pub kind: PortKind,
+    pub id: u32,
+    pub name: String,
+}
+
+/// A row that couldn't be parsed, validated or applied.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RowError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// A label that differs from what the router currently has.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LabelChange {
+    pub line: usize,
+    pub matrix: u32,
+    pub kind: PortKind,
+    pub id: u32,
+    pub old: String,
+    pub new: String,
+}
+
+impl fmt::Display for LabelChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "matrix {} {} {}: {:?} -> {:?}",
+            self.matrix,
+            direction(self.kind),
+            self.id,
+            self.old,
+            self.new
+        )
+    }
+}
+
+fn direction(kind: PortKind) -> &'static str {
+    match kind {
+        PortKind::Input => "input",
+        PortKind::Output => "output",
+    }
+}
+
+/// Quote a field if needed, doubling contained quotes.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Split CSV into records of fields, each with the line it starts at.
+///
+/// Quoted fields may contain commas, doubled quotes and line breaks.
+fn records(text: &str) -> Result<Vec<(usize, Vec<String>)>, RowError> {
+    let mut out = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        loop {
+            match chars.next() {
+                None if quoted => {
+                    return Err(RowError {
+                        line: start,
+                        message: "unterminated quoted field".into(),
+                    })
+                }
+                None => break,
+                Some('"') if quoted => {
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                        field.push('"');
+                    } else {
+                        quoted = false;
+                    }
+                }
+                Some('"') if field.is_empty() => quoted = true,
+                Some(c) if quoted => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+                Some(',') => fields.push(std::mem::take(&mut field)),
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') => {
+                    line += 1;
+                    break;
+                }
+                Some(c) => field.push(c),
+            }
+        }
+        fields.push(field);
+        // Skip blank lines.
+        if fields.len() > 1 || !fields[0].trim().is_empty() {
+            out.push((start, fields));
+        }
+    }
+    Ok(out)
+}
+
+fn parse_row(line: usize, fields: &[String]) -> Result<LabelRow, String> {
+    let [matrix, dir, id, name] = fields else {
+        return Err(format!("expected 4 fields, got {}", fields.len()));
+    };
+    let matrix = matrix
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid matrix {:?}", matrix))?;
+    let kind = match dir.trim().to_ascii_lowercase().as_str() {
+        "input" => PortKind::Input,
+        "output" => PortKind::Output,
+        _ => return Err(format!("invalid direction {:?}", dir)),
+    };
+    let id = id
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid id {:?}", id))?;
+    if name.contains(['\r', '\n']) {
+        return Err("label contains a line break".into());
+    }
+    if name.chars().count() > MAX_LABEL_LEN {
+        return Err(format!("label longer than {} characters", MAX_LABEL_LEN));
+    }
+    Ok(LabelRow {
+        line,
+        matrix,
+        kind,
+        id,
+        name: name.clone(),
+    })
+}
+
+/// Parse label CSV, collecting all malformed rows instead of stopping at the first.
+///
+/// A leading byte order mark and a header line are skipped, CRLF line endings are fine.
+pub fn parse(text: &str) -> (Vec<LabelRow>, Vec<RowError>) {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let records = match records(text) {
+        Ok(r) => r,
+        Err(e) => return (vec![], vec![e]),
+    };
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (n, (line, fields)) in records.into_iter().enumerate() {
+        if n == 0 && fields.join(",").eq_ignore_ascii_case(HEADER) {
+            continue;
+        }
+        match parse_row(line, &fields) {
+            Ok(row) => rows.push(row),
+            Err(message) => errors.push(RowError { line, message }),
+        }
+    }
+    (rows, errors)
+}
+
+async fn labels_of<R: MatrixRouter>(
+    router: &R,
+    matrix: u32,
+    kind: PortKind,
+) -> Result<Vec<RouterLabel>> {
+    let mut labels = match kind {
+        PortKind::Input => router.get_input_labels(matrix).await?,
+        PortKind::Output => router.get_output_labels(matrix).await?,
+    };
+    labels.sort();
+    Ok(labels)
+}
+
+/// Export all labels of all matrices of `router`.
+pub async fn export<R: MatrixRouter>(router: &R) -> Result<String> {
+    let matrix_count = router.get_router_info().await?.matrix_count.unwrap_or(1);
+    let mut out = String::from(HEADER);
+    out.push('\n');
+    for matrix in 0..matrix_count {
+        for kind in [PortKind::Input, PortKind::Output] {
+            for l in labels_of(router, matrix, kind).await? {
+                out.push_str(&format!(
+                    "{},{},{},{}\n",
+                    matrix,
+                    direction(kind),
+                    l.id,
+                    escape(&l.name)
+                ));
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Compare `rows` to the current labels of `router`.
+///
+/// Rows out of range for their matrix are reported as errors, unchanged ones are dropped.
+pub async fn diff<R: MatrixRouter>(
+    router: &R,
+    rows: Vec<LabelRow>,
+) -> Result<(Vec<LabelChange>, Vec<RowError>)> {
+    let mut current: BTreeMap<(u32, PortKind), Option<Vec<RouterLabel>>> = BTreeMap::new();
+    let mut changes = Vec::new();
+    let mut errors = Vec::new();
+    for row in rows {
+        let key = (row.matrix, row.kind);
+        if let Entry::Vacant(e) = current.entry(key) {
+            // Unknown matrices are only an error of the rows referring to them.
+            let labels = match router.get_matrix_info(row.matrix).await {
+                Ok(_) => Some(labels_of(router, row.matrix, row.kind).await?),
+                Err(_) => None,
+            };
+            e.insert(labels);
+        }
+        let Some(labels) = &current[&key] else {
+            errors.push(RowError {
+                line: row.line,
+                message: format!("matrix {} does not exist", row.matrix),
+            });
+            continue;
+        };
+        let Some(old) = labels.iter().find(|l| l.id == row.id) else {
+            errors.push(RowError {
+                line: row.line,
+                message: format!(
+                    "{} {} out of range for matrix {}",
+                    direction(row.kind),
+                    row.id,
+                    row.matrix
+                ),
+            });
+            continue;
+        };
+        if old.name != row.name {
+            changes.push(LabelChange {
+                line: row.line,
+                matrix: row.matrix,
+                kind: row.kind,
+                id: row.id,
+                old: old.name.clone(),
+                new: row.name,
+            });
+        }
+    }
+    Ok((changes, errors))
+}
+
+/// Apply `changes`, at most `batch_size` labels per router call.
+///
+/// Returns the lines of all changes that got applied and the errors of the rest.
+pub async fn apply<R: MatrixRouter>(
+    router: &R,
+    changes: &[LabelChange],
+    batch_size: usize,
+) -> (Vec<usize>, Vec<RowError>) {
+    let mut groups: BTreeMap<(u32, PortKind), Vec<&LabelChange>> = BTreeMap::new();
+    for c in changes {
+        groups.entry((c.matrix, c.kind)).or_default().push(c);
+    }
+
+    let mut applied = Vec::new();
+    let mut errors = Vec::new();
+    for ((matrix, kind), group) in groups {
+        for batch in group.chunks(batch_size.max(1)) {
+            let labels = batch
+                .iter()
+                .map(|c| RouterLabel {
+                    id: c.id,
+                    name: c.new.clone(),
+                })
+                .collect();
+            let res = match kind {
+                PortKind::Input => router.update_input_labels(matrix, labels).await,
+                PortKind::Output => router.update_output_labels(matrix, labels).await,
+            };
+            match res {
+                Ok(()) => applied.extend(batch.iter().map(|c| c.line)),
+                Err(e) => errors.extend(batch.iter().map(|c| RowError {
+                    line: c.line,
+                    message: e.to_string(),
+                })),
+            }
+        }
+    }
+    applied.sort();
+    errors.sort_by_key(|e| e.line);
+    (applied, errors)
+}
+
+/// Parse and diff in one go, failing on the first malformed row.
+pub async fn diff_str<R: MatrixRouter>(router: &R, text: &str) -> Result<Vec<LabelChange>> {
+    let (rows, errors) = parse(text);
+    if let Some(e) = errors.first() {
+        return Err(anyhow!("{}", e));
+    }
+    let (changes, errors) = diff(router, rows).await?;
+    if let Some(e) = errors.first() {
+        return Err(anyhow!("{}", e));
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(line: usize, matrix: u32, kind: PortKind, id: u32, name: &str) -> LabelRow {
+        LabelRow {
+            line,
+            matrix,
+            kind,
+            id,
+            name: name.into(),
+        }
+    }
+
+    #[test]
+    fn parse_edge_cases() {
+        let text = "\u{feff}matrix,direction,id,name\r\n\
+                    0,input,0,\"Camera, wide\"\r\n\
+                    0,Output,1,\"Say \"\"cheese\"\"\"\r\n\
+                    \r\n\
+                    1,input,2,plain\n";
+        let (rows, errors) = parse(text);
+        assert_eq!(errors, vec![]);
+        assert_eq!(
+            rows,
+            vec![
+                row(2, 0, PortKind::Input, 0, "Camera, wide"),
+                row(3, 0, PortKind::Output, 1, "Say \"cheese\""),
+                row(5, 1, PortKind::Input, 2, "plain"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_collects_errors() {
+        let long = "x".repeat(MAX_LABEL_LEN + 1);
+        let text = format!(
+            "0,input,0,ok\n0,sideways,1,a\n0,input,x,b\n0,input,1\n0,output,0,\"two\nlines\"\n0,input,2,{}\n0,output,3,fine\n",
+            long
+        );
+        let (rows, errors) = parse(&text);
+        assert_eq!(
+            rows,
+            vec![
+                row(1, 0, PortKind::Input, 0, "ok"),
+                row(8, 0, PortKind::Output, 3, "fine")
+            ]
+        );
+        let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![2, 3, 4, 5, 7]);
+
+        let (rows, errors) = parse("0,input,0,\"open");
+        assert!(rows.is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn export_roundtrip() -> Result<()> {
+        let dummy = DummyRouter::with_matrices(&[(2, 1), (1, 1)]);
+        let quoted = RouterLabel {
+            id: 1,
+            name: "Camera, \"wide\"".into(),
+        };
+        dummy.update_input_labels(0, vec![quoted]).await?;
+
+        let csv = export(&dummy).await?;
+        assert_eq!(
+            csv,
+            "matrix,direction,id,name\n\
+             0,input,0,Input 1\n\
+             0,input,1,\"Camera, \"\"wide\"\"\"\n\
+             0,output,0,Output 1\n\
+             1,input,0,Input 1\n\
+             1,output,0,Output 1\n"
+        );
+        // Nothing to do when importing what was just exported.
+        assert!(diff_str(&dummy, &csv).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dry_run_diff() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let (rows, _) = parse("0,input,0,Input 1\n0,input,1,Guest\n0,output,0,Monitor\n");
+        let (changes, errors) = diff(&dummy, rows).await?;
+        assert!(errors.is_empty());
+        let lines: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "matrix 0 input 1: \"Input 2\" -> \"Guest\"",
+                "matrix 0 output 0: \"Output 1\" -> \"Monitor\"",
+            ]
+        );
+        // Diffing alone changes nothing.
+        assert_eq!(dummy.get_input_labels(0).await?[1].name, "Input 2");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn partial_apply() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let (rows, parse_errors) =
+            parse("0,input,0,A\n0,input,5,Nope\n0,output,1,B\n3,output,0,Gone\n0,input,1,C\n");
+        assert!(parse_errors.is_empty());
+        let (changes, errors) = diff(&dummy, rows).await?;
+        let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![2, 4]);
+
+        let (applied, errors) = apply(&dummy, &changes, 1).await;
+        assert_eq!(applied, vec![1, 3, 5]);
+        assert!(errors.is_empty());
+        let names: Vec<String> = dummy
+            .get_input_labels(0)
+            .await?
+            .into_iter()
+            .map(|l| l.name)
+            .collect();
+        assert_eq!(names, vec!["A", "C"]);
+        assert_eq!(dummy.get_output_labels(0).await?[1].name, "B");
+        Ok(())
+    }
+}
@@ -0,0 +1,717 @@
+//! Software output locks for any router
+//!
+//! Wraps a [MatrixRouter] without output locks of its own, like the NDI or dummy one, holding
+//! locks in memory on behalf of owners like client addresses. Routes to outputs locked by
+//! someone else are refused with [RouterError::Locked]. Everything else passes through.
+
+use super::*;
+use anyhow::{anyhow, Result};
+use futures_core::stream::BoxStream;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as SyncMutex};
+use tokio::sync::{broadcast, Mutex};
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+
+/// Owners of locked outputs, by matrix and output.
+type Owners = HashMap<(u32, u32), Arc<str>>;
+
+/// Owners with changes to an output on their way to the router, by matrix and output.
+type Reservations = HashMap<(u32, u32), Vec<Arc<str>>>;
+
+/// Outputs reserved for a change, until dropped.
+struct Reservation {
+    reserved: Arc<SyncMutex<Reservations>>,
+    owner: Arc<str>,
+    keys: Vec<(u32, u32)>,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut reserved = self.reserved.lock().unwrap();
+        for key in &self.keys {
+            if let Some(owners) = reserved.get_mut(key) {
+                if let Some(pos) = owners.iter().position(|o| *o == self.owner) {
+                    owners.swap_remove(pos);
+                }
+                if owners.is_empty() {
+                    reserved.remove(key);
+                }
+            }
+        }
+    }
+}
+
+/// Router wrapper holding output locks of `R` in memory.
+///
+/// Every handle locks on behalf of an owner, see [LockingRouter::with_owner]. Handles share
+/// their locks, so owners contend with each other. Staging routes counts as changing them,
+/// committing doesn't.
+pub struct LockingRouter<R> {
+    inner: Arc<R>,
+    /// Owner of locks taken through this handle.
+    owner: Arc<str>,
+    owners: Arc<Mutex<Owners>>,
+    /// Outputs with route changes in flight, their locks can't change hands meanwhile.
+    reserved: Arc<SyncMutex<Reservations>>,
+    tx: broadcast::Sender<RouterEvent>,
+    /// Number of refused changes.
+    refused: Arc<AtomicU64>,
+}
+
+impl<R> Clone for LockingRouter<R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            owner: Arc::clone(&self.owner),
+            owners: Arc::clone(&self.owners),
+            reserved: Arc::clone(&self.reserved),
+            tx: self.tx.clone(),
+            refused: Arc::clone(&self.refused),
+        }
+    }
+}
+
+impl<R: MatrixRouter> LockingRouter<R> {
+    /// Hold locks of `inner`, taking them as the anonymous owner `""` through this handle.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            owner: "".into(),
+            owners: Arc::default(),
+            reserved: Arc::default(),
+            tx: broadcast::channel(16).0,
+            refused: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// The wrapped router.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// A handle sharing the locks of this one, taking them on behalf of `owner`.
+    pub fn with_owner(&self, owner: &str) -> Self {
+        Self {
+            owner: owner.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Owner of locks taken through this handle.
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// Lock or unlock outputs of matrix `index` on behalf of `owner`.
+    ///
+    /// Outputs locked by someone else, or with someone else's routes on their way to the
+    /// router, can't be locked or unlocked, refusing all changes with [RouterError::Locked].
+    pub async fn update_locks_as(
+        &self,
+        owner: &str,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        let mi = self.inner.get_matrix_info(index).await?;
+        if let Some(l) = changes.iter().find(|l| l.id >= mi.output_count) {
+            return Err(anyhow!("Lock {} out of range for matrix {}", l.id, index));
+        }
+        let mut owners = self.owners.lock().await;
+        self.check(&owners, owner, index, changes.iter().map(|l| l.id))?;
+        self.check_reserved(owner, index, changes.iter().map(|l| l.id))?;
+        for l in &changes {
+            if l.locked {
+                owners.insert((index, l.id), owner.into());
+            } else {
+                owners.remove(&(index, l.id));
+            }
+        }
+        if !changes.is_empty() {
+            self.announce(&owners, index, mi.output_count);
+        }
+        Ok(())
+    }
+
+    /// Patch routes of matrix `index` on behalf of `owner`.
+    ///
+    /// Patches to outputs locked by someone else refuse the whole update with
+    /// [RouterError::Locked].
+    pub async fn update_routes_as(
+        &self,
+        owner: &str,
+        index: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        let _reservation = self.reserve(owner, index, &changes).await?;
+        self.inner.update_routes(index, changes).await
+    }
+
+    /// Unlock outputs of matrix `index`, whoever holds them.
+    pub async fn force_unlock(&self, index: u32, outputs: &[u32]) -> Result<()> {
+        let mi = self.inner.get_matrix_info(index).await?;
+        let mut owners = self.owners.lock().await;
+        let released = outputs
+            .iter()
+            .filter(|&&id| owners.remove(&(index, id)).is_some())
+            .count();
+        if released > 0 {
+            self.announce(&owners, index, mi.output_count);
+        }
+        Ok(())
+    }
+
+    /// Unlock every output held by `owner`, like once it disconnected.
+    pub async fn release(&self, owner: &str) -> Result<()> {
+        let mut matrices: Vec<u32> = {
+            let mut owners = self.owners.lock().await;
+            let matrices = owners
+                .iter()
+                .filter(|(_, o)| &***o == owner)
+                .map(|(&(index, _), _)| index)
+                .collect();
+            owners.retain(|_, o| &**o != owner);
+            matrices
+        };
+        matrices.sort_unstable();
+        matrices.dedup();
+        for index in matrices {
+            let mi = self.inner.get_matrix_info(index).await?;
+            let owners = self.owners.lock().await;
+            self.announce(&owners, index, mi.output_count);
+        }
+        Ok(())
+    }
+
+    /// Refuse changes to `outputs` of matrix `index` if any is locked by someone but `owner`.
+    fn check(
+        &self,
+        owners: &Owners,
+        owner: &str,
+        index: u32,
+        mut outputs: impl Iterator<Item = u32>,
+    ) -> Result<(), RouterError> {
+        match outputs.find(|&id| owners.get(&(index, id)).is_some_and(|o| &**o != owner)) {
+            Some(output) => {
+                self.refused.fetch_add(1, Ordering::Relaxed);
+                Err(RouterError::Locked { index, output })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Refuse lock changes to `outputs` of matrix `index` if someone but `owner` is routing them.
+    fn check_reserved(
+        &self,
+        owner: &str,
+        index: u32,
+        mut outputs: impl Iterator<Item = u32>,
+    ) -> Result<(), RouterError> {
+        let reserved = self.reserved.lock().unwrap();
+        let busy = |id| {
+            reserved
+                .get(&(index, id))
+                .is_some_and(|os| os.iter().any(|o| &**o != owner))
+        };
+        match outputs.find(|&id| busy(id)) {
+            Some(output) => {
+                self.refused.fetch_add(1, Ordering::Relaxed);
+                Err(RouterError::Locked { index, output })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Refuse patches on behalf of `owner` to outputs locked by someone else, or reserve the
+    /// outputs so their locks stay put until the returned [Reservation] is dropped.
+    async fn reserve(
+        &self,
+        owner: &str,
+        index: u32,
+        patches: &[RouterPatch],
+    ) -> Result<Reservation, RouterError> {
+        let owners = self.owners.lock().await;
+        self.check(&owners, owner, index, patches.iter().map(|p| p.to_output))?;
+        let owner: Arc<str> = owner.into();
+        let keys: Vec<(u32, u32)> = patches.iter().map(|p| (index, p.to_output)).collect();
+        let mut reserved = self.reserved.lock().unwrap();
+        for key in &keys {
+            reserved.entry(*key).or_default().push(Arc::clone(&owner));
+        }
+        Ok(Reservation {
+            reserved: Arc::clone(&self.reserved),
+            owner,
+            keys,
+        })
+    }
+
+    /// Locks of matrix `index`, one per output.
+    fn locks(owners: &Owners, index: u32, output_count: u32) -> Vec<RouterLock> {
+        (0..output_count)
+            .map(|id| RouterLock {
+                id,
+                locked: owners.contains_key(&(index, id)),
+            })
+            .collect()
+    }
+
+    fn announce(&self, owners: &Owners, index: u32, output_count: u32) {
+        let locks = Self::locks(owners, index, output_count);
+        let _ = self.tx.send(RouterEvent::LockUpdate(index, locks));
+    }
+}
+
+impl<R: MatrixRouter> MatrixRouter for LockingRouter<R> {
+    async fn is_alive(&self) -> Result<bool> {
+        self.inner.is_alive().await
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.inner.get_router_info().await
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        self.inner.get_matrix_info(index).await
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_input_labels(index).await
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_output_labels(index).await
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_input_labels(index, changed).await
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_output_labels(index, changed).await
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_input_descriptions(index).await
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_output_descriptions(index).await
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_input_descriptions(index, changed).await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_output_descriptions(index, changed).await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_input_status(index).await
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_output_status(index).await
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_routes(index).await
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        self.inner.get_route(index, output).await
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        self.inner.get_route_for_input(index, input).await
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.update_routes_as(&self.owner, index, changes).await
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let _reservation = self.reserve(&self.owner, index, &changes).await?;
+        self.inner.update_routes_atomic(index, changes).await
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.inner.get_levels(index).await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_level_routes(index, level).await
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        let _reservation = self.reserve(&self.owner, index, &changes).await?;
+        self.inner.update_level_routes(index, level, changes).await
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        let _reservation = self.reserve(&self.owner, index, &changes).await?;
+        self.inner.stage_routes(index, changes).await
+    }
+
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.commit(handle).await
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.discard(handle).await
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_staged_routes(index).await
+    }
+
+    async fn get_frame_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_frame_labels(index).await
+    }
+
+    async fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_frame_labels(index, changed).await
+    }
+
+    async fn get_frame_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_frame_routes(index).await
+    }
+
+    async fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.inner.update_frame_routes(index, changes).await
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        let mi = self.inner.get_matrix_info(index).await?;
+        let owners = self.owners.lock().await;
+        Ok(Self::locks(&owners, index, mi.output_count))
+    }
+
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.update_locks_as(&self.owner, index, changes).await
+    }
+
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        Some(self.with_owner(owner))
+    }
+
+    async fn get_frame_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_frame_locks(index).await
+    }
+
+    async fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.inner.update_frame_locks(index, changes).await
+    }
+
+    async fn get_processing_unit_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_processing_unit_locks(index).await
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        self.inner
+            .update_processing_unit_locks(index, changes)
+            .await
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        self.inner.get_port_metadata(index, kind, id).await
+    }
+
+    async fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> Result<()> {
+        self.inner
+            .set_port_metadata(index, kind, id, metadata)
+            .await
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        self.inner.get_alarms().await
+    }
+
+    /// Output locks are held here, whether `R` has any or not.
+    fn capabilities(&self) -> RouterCapabilities {
+        RouterCapabilities {
+            can_lock_outputs: true,
+            ..self.inner.capabilities()
+        }
+    }
+
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        let rx = self.tx.subscribe();
+        // Locks of the router itself are hidden behind ours.
+        let events = self
+            .inner
+            .event_stream()
+            .await?
+            .filter(|ev| !matches!(ev, RouterEvent::LockUpdate(..)));
+        let locks = BroadcastStream::new(rx).filter_map(|r| r.ok());
+        Ok(futures_util::StreamExt::boxed(events.merge(locks)))
+    }
+}
+
+impl<R: RouterIntrospect> RouterIntrospect for LockingRouter<R> {
+    fn name(&self) -> &'static str {
+        "LockingRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        vec![("owner".into(), self.owner.to_string())]
+    }
+
+    fn counters(&self) -> Vec<(String, u64)> {
+        vec![("refused".into(), self.refused.load(Ordering::Relaxed))]
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        Some(&*self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock(id: u32, locked: bool) -> RouterLock {
+        RouterLock { id, locked }
+    }
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    fn locked_output(err: &anyhow::Error) -> Option<u32> {
+        match err.downcast_ref::<RouterError>() {
+            Some(&RouterError::Locked { output, .. }) => Some(output),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn lock_and_contend() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 4, 4);
+        let router = LockingRouter::new(dummy.clone());
+        let alice = router.with_owner("alice");
+        let bob = router.with_owner("bob");
+        let mut events = router.event_stream().await?;
+        assert_eq!(events.next().await, Some(RouterEvent::Connected));
+
+        alice.update_locks(0, vec![lock(1, true)]).await?;
+        assert_eq!(
+            events.next().await,
+            Some(RouterEvent::LockUpdate(
+                0,
+                vec![
+                    lock(0, false),
+                    lock(1, true),
+                    lock(2, false),
+                    lock(3, false)
+                ]
+            ))
+        );
+        assert_eq!(bob.get_locks(0).await?[1], lock(1, true));
+
+        // Bob can neither take nor release the lock, nor route to the output.
+        let err = bob.update_locks(0, vec![lock(1, true)]).await.unwrap_err();
+        assert_eq!(locked_output(&err), Some(1));
+        assert!(bob.update_locks(0, vec![lock(1, false)]).await.is_err());
+        let err = bob
+            .update_routes(0, vec![patch(2, 0), patch(2, 1)])
+            .await
+            .unwrap_err();
+        assert_eq!(locked_output(&err), Some(1));
+        assert!(bob.stage_routes(0, vec![patch(2, 1)]).await.is_err());
+        // Refused updates apply nothing.
+        assert_eq!(dummy.get_routes(0).await?[0], patch(0, 0));
+
+        // Alice may, and so may everyone once she let go.
+        alice.update_routes(0, vec![patch(3, 1)]).await?;
+        assert_eq!(dummy.get_routes(0).await?[1], patch(3, 1));
+        bob.update_routes_as("alice", 0, vec![patch(2, 1)]).await?;
+        alice.update_locks(0, vec![lock(1, false)]).await?;
+        bob.update_routes(0, vec![patch(1, 1)]).await?;
+        assert_eq!(dummy.get_routes(0).await?[1], patch(1, 1));
+
+        assert!(alice.update_locks(0, vec![lock(4, true)]).await.is_err());
+        assert_eq!(
+            describe_router(&router)[0].counters,
+            vec![("refused".to_string(), 4)]
+        );
+        Ok(())
+    }
+
+    /// Router holding route updates until let through by `gate`.
+    struct GatedRouter {
+        inner: DummyRouter,
+        gate: Arc<tokio::sync::Semaphore>,
+    }
+
+    impl MatrixRouter for GatedRouter {
+        async fn is_alive(&self) -> Result<bool> {
+            self.inner.is_alive().await
+        }
+        async fn get_router_info(&self) -> Result<RouterInfo> {
+            self.inner.get_router_info().await
+        }
+        async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+            self.inner.get_matrix_info(index).await
+        }
+        async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+            self.inner.get_input_labels(index).await
+        }
+        async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+            self.inner.get_output_labels(index).await
+        }
+        async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+            self.inner.update_input_labels(index, changed).await
+        }
+        async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+            self.inner.update_output_labels(index, changed).await
+        }
+        async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+            self.inner.get_routes(index).await
+        }
+        async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+            self.gate.acquire().await?.forget();
+            self.inner.update_routes(index, changes).await
+        }
+        async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+            self.inner.event_stream().await
+        }
+    }
+
+    #[tokio::test]
+    async fn routing_keeps_locks_in_place() -> Result<()> {
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let router = LockingRouter::new(GatedRouter {
+            inner: DummyRouter::with_config(1, 4, 4),
+            gate: Arc::clone(&gate),
+        });
+        let alice = router.with_owner("alice");
+        let bob = router.with_owner("bob");
+        let routing = tokio::spawn({
+            let alice = alice.clone();
+            async move { alice.update_routes(0, vec![patch(2, 1)]).await }
+        });
+        while router.reserved.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        // While alice's route is on its way, others can't lock its output, but any other.
+        bob.update_locks(0, vec![lock(2, true)]).await?;
+        let err = bob.update_locks(0, vec![lock(1, true)]).await.unwrap_err();
+        assert_eq!(locked_output(&err), Some(1));
+        alice.update_locks(0, vec![lock(3, true)]).await?;
+
+        gate.add_permits(1);
+        routing.await??;
+        assert!(router.reserved.lock().unwrap().is_empty());
+        bob.update_locks(0, vec![lock(1, true)]).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn owners_through_wrappers() -> Result<()> {
+        let locking = LockingRouter::new(DummyRouter::with_config(1, 4, 4));
+        let remap = RemapRouter::new(locking.clone(), vec![0, 1, 2, 3], vec![3, 2, 1, 0]).await?;
+        let limit = RateLimit {
+            max_updates_per_second: 100,
+            ..Default::default()
+        };
+        let router = Watchdog::new(
+            CachingRouter::new(
+                MetadataRouter::new(RateLimitedRouter::new(
+                    SliceRouter::new(remap, 0..4, 1..4),
+                    limit,
+                )?),
+                std::time::Duration::from_secs(1),
+            ),
+            std::time::Duration::from_secs(1),
+            3,
+        );
+        let alice = router.with_lock_owner("alice").unwrap();
+        let bob = router.with_lock_owner("bob").unwrap();
+
+        // Output 0 of the slice is output 2 underneath, once remapped.
+        alice.update_locks(0, vec![lock(0, true)]).await?;
+        assert!(locking.get_locks(0).await?[2].locked);
+        let err = bob.update_routes(0, vec![patch(1, 0)]).await.unwrap_err();
+        assert_eq!(locked_output(&err), Some(2));
+        alice.update_routes(0, vec![patch(1, 0)]).await?;
+
+        // Composites hand owners on to the children telling them apart.
+        let composite = CompositeRouter::new(vec![
+            Arc::new(DummyRouter::with_config(1, 2, 2)),
+            Arc::new(router),
+        ]);
+        let bob = composite.with_lock_owner("bob").unwrap();
+        assert!(bob.update_routes(0, vec![patch(3, 2)]).await.is_err());
+        bob.update_routes(0, vec![patch(3, 3)]).await?;
+        let plain = CompositeRouter::new(vec![Arc::new(DummyRouter::with_config(1, 2, 2))]);
+        assert!(plain.with_lock_owner("bob").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn force_unlock_and_release() -> Result<()> {
+        let router = LockingRouter::new(DummyRouter::with_config(2, 2, 2));
+        let alice = router.with_owner("alice");
+        alice
+            .update_locks(0, vec![lock(0, true), lock(1, true)])
+            .await?;
+        alice.update_locks(1, vec![lock(0, true)]).await?;
+
+        router.force_unlock(0, &[1]).await?;
+        assert_eq!(
+            router.get_locks(0).await?,
+            vec![lock(0, true), lock(1, false)]
+        );
+        router.update_routes(0, vec![patch(1, 1)]).await?;
+        assert!(router.update_routes(0, vec![patch(1, 0)]).await.is_err());
+
+        // Once alice is gone, so are her locks.
+        let mut events = router.event_stream().await?;
+        assert_eq!(events.next().await, Some(RouterEvent::Connected));
+        router.release("alice").await?;
+        assert_eq!(
+            events.next().await,
+            Some(RouterEvent::LockUpdate(
+                0,
+                vec![lock(0, false), lock(1, false)]
+            ))
+        );
+        assert_eq!(
+            events.next().await,
+            Some(RouterEvent::LockUpdate(
+                1,
+                vec![lock(0, false), lock(1, false)]
+            ))
+        );
+        router.update_routes(0, vec![patch(1, 0)]).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,496 @@
+//! Port metadata for any router
+//!
+//! Wraps a [MatrixRouter], keeping [PortMetadata] next to it and passing everything else through.
+
+use super::*;
+use anyhow::{anyhow, Result};
+use futures_core::stream::BoxStream;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "serde")]
+use tracing::debug;
+
+type Store = HashMap<(u32, PortKind, u32), PortMetadata>;
+
+/// Router wrapper adding a generic [PortMetadata] store to `R`.
+#[derive(Clone)]
+pub struct MetadataRouter<R> {
+    inner: R,
+    store: Arc<Mutex<Store>>,
+    persistence: Option<Arc<PathBuf>>,
+    /// Number of refused metadata updates.
+    rejected: Arc<AtomicU64>,
+}
+
+/// Metadata of a single port, as written to disk by [MetadataRouter::with_persistence].
+#[cfg(feature = "serde")]
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct PersistedEntry {
+    matrix: u32,
+    kind: PortKind,
+    id: u32,
+    metadata: PortMetadata,
+}
+
+/// Write the entries of `store` to `path`.
+#[cfg(feature = "serde")]
+fn store(path: &std::path::Path, store: &Store) -> Result<()> {
+    let mut entries: Vec<PersistedEntry> = store
+        .iter()
+        .map(|(&(matrix, kind, id), metadata)| PersistedEntry {
+            matrix,
+            kind,
+            id,
+            metadata: metadata.clone(),
+        })
+        .collect();
+    entries.sort_by_key(|e| (e.matrix, e.kind == PortKind::Output, e.id));
+    crate::persist::store(path, &entries)
+}
+
+impl<R: MatrixRouter> MetadataRouter<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            store: Arc::new(Mutex::new(HashMap::new())),
+            persistence: None,
+            rejected: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// The wrapped router.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Load metadata from `path` if it exists and keep it up to date there.
+    #[cfg(feature = "serde")]
+    pub fn with_persistence(mut self, path: PathBuf) -> Result<Self> {
+        if let Some(saved) = crate::persist::load::<Vec<PersistedEntry>>(&path)? {
+            let mut st = self.store.lock().unwrap();
+            for e in saved {
+                st.insert((e.matrix, e.kind, e.id), e.metadata);
+            }
+            debug!(?path, "Restored persisted port metadata");
+        }
+        self.persistence = Some(Arc::new(path));
+        Ok(self)
+    }
+
+    /// Write the store to the persistence file, if any.
+    fn persist(&self, st: &Store) -> Result<()> {
+        #[cfg(feature = "serde")]
+        if let Some(path) = &self.persistence {
+            store(path, st)?;
+        }
+        #[cfg(not(feature = "serde"))]
+        let _ = (&self.persistence, st);
+        Ok(())
+    }
+}
+
+impl<R: MatrixRouter> MatrixRouter for MetadataRouter<R> {
+    async fn is_alive(&self) -> Result<bool> {
+        self.inner.is_alive().await
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.inner.get_router_info().await
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        self.inner.get_matrix_info(index).await
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_input_labels(index).await
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_output_labels(index).await
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_input_labels(index, changed).await
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_output_labels(index, changed).await
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_input_descriptions(index).await
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_output_descriptions(index).await
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_input_descriptions(index, changed).await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_output_descriptions(index, changed).await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_input_status(index).await
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_output_status(index).await
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_routes(index).await
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        self.inner.get_route(index, output).await
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        self.inner.get_route_for_input(index, input).await
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.inner.update_routes(index, changes).await
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.inner.update_routes_atomic(index, changes).await
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.inner.get_levels(index).await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_level_routes(index, level).await
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        self.inner.update_level_routes(index, level, changes).await
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        self.inner.stage_routes(index, changes).await
+    }
+
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.commit(handle).await
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.discard(handle).await
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_staged_routes(index).await
+    }
+
+    async fn get_frame_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_frame_labels(index).await
+    }
+
+    async fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_frame_labels(index, changed).await
+    }
+
+    async fn get_frame_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_frame_routes(index).await
+    }
+
+    async fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.inner.update_frame_routes(index, changes).await
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_locks(index).await
+    }
+
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.inner.update_locks(index, changes).await
+    }
+
+    /// Sharing the metadata, if `R` takes locks on behalf of owners.
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        Some(Self {
+            inner: self.inner.with_lock_owner(owner)?,
+            store: self.store.clone(),
+            persistence: self.persistence.clone(),
+            rejected: self.rejected.clone(),
+        })
+    }
+
+    async fn get_frame_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_frame_locks(index).await
+    }
+
+    async fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.inner.update_frame_locks(index, changes).await
+    }
+
+    async fn get_processing_unit_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_processing_unit_locks(index).await
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        self.inner
+            .update_processing_unit_locks(index, changes)
+            .await
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        let st = self.store.lock().unwrap();
+        Ok(st.get(&(index, kind, id)).cloned().unwrap_or_default())
+    }
+
+    async fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> Result<()> {
+        if let Err(e) = metadata.validate() {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(e);
+        }
+        let mi = self.inner.get_matrix_info(index).await?;
+        let count = match kind {
+            PortKind::Input => mi.input_count,
+            PortKind::Output => mi.output_count,
+        };
+        if id >= count {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(anyhow!(
+                "{:?} {} out of range for matrix {}",
+                kind,
+                id,
+                index
+            ));
+        }
+
+        let mut st = self.store.lock().unwrap();
+        if metadata == PortMetadata::default() {
+            st.remove(&(index, kind, id));
+        } else {
+            st.insert((index, kind, id), metadata);
+        }
+        self.persist(&st)
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        self.inner.get_alarms().await
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        RouterCapabilities {
+            has_port_metadata: true,
+            ..self.inner.capabilities()
+        }
+    }
+
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        self.inner.event_stream().await
+    }
+}
+
+impl<R: RouterIntrospect> RouterIntrospect for MetadataRouter<R> {
+    fn name(&self) -> &'static str {
+        "MetadataRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        let persistence = match &self.persistence {
+            Some(path) => path.display().to_string(),
+            None => "none".into(),
+        };
+        let entries = self.store.lock().unwrap().len();
+        vec![
+            ("persistence".into(), persistence),
+            ("entries".into(), entries.to_string()),
+        ]
+    }
+
+    fn counters(&self) -> Vec<(String, u64)> {
+        vec![("rejected".into(), self.rejected.load(Ordering::Relaxed))]
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        Some(&self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::StreamExt;
+
+    fn notes() -> PortMetadata {
+        PortMetadata {
+            description: "Feeds the lobby display, contact facilities before changing".into(),
+            tags: vec!["lobby".into(), "public-display".into()],
+            color: Some("#ff8800".into()),
+        }
+    }
+
+    #[tokio::test]
+    async fn delegates_untouched() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let router = MetadataRouter::new(dummy.clone());
+        let mut stream = router.event_stream().await?;
+        // Lifecycle events pass through unchanged.
+        assert_eq!(stream.next().await, Some(RouterEvent::Connected));
+
+        let p = RouterPatch {
+            from_input: Some(1),
+            to_output: 0,
+        };
+        router.update_routes(0, vec![p]).await?;
+        assert!(dummy.get_routes(0).await?.contains(&p));
+        assert_eq!(router.get_routes(0).await?, dummy.get_routes(0).await?);
+        assert_eq!(
+            router.get_matrix_info(0).await?,
+            dummy.get_matrix_info(0).await?
+        );
+        assert!(matches!(
+            stream.next().await,
+            Some(RouterEvent::RouteUpdate(0, _))
+        ));
+
+        // The bare dummy doesn't do metadata, the wrapper does.
+        assert!(dummy
+            .get_port_metadata(0, PortKind::Output, 0)
+            .await
+            .is_err());
+        router
+            .set_port_metadata(0, PortKind::Output, 0, notes())
+            .await?;
+        assert_eq!(
+            router.get_port_metadata(0, PortKind::Output, 0).await?,
+            notes()
+        );
+        assert_eq!(
+            router.get_port_metadata(0, PortKind::Input, 0).await?,
+            PortMetadata::default()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid() -> Result<()> {
+        let router = MetadataRouter::new(DummyRouter::with_config(1, 2, 2));
+        assert!(router
+            .set_port_metadata(0, PortKind::Input, 2, notes())
+            .await
+            .is_err());
+        for color in ["ff8800", "#ff880", "#gg8800"] {
+            let bad = PortMetadata {
+                color: Some(color.into()),
+                ..notes()
+            };
+            assert!(bad.validate().is_err(), "{} passed", color);
+        }
+        for tag in ["", "Lobby", "with space"] {
+            let bad = PortMetadata {
+                tags: vec![tag.into()],
+                ..notes()
+            };
+            assert!(bad.validate().is_err(), "{:?} passed", tag);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn persistence_roundtrip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("metadata.json");
+        {
+            let router = MetadataRouter::new(DummyRouter::with_config(2, 2, 2))
+                .with_persistence(path.clone())?;
+            router
+                .set_port_metadata(1, PortKind::Output, 1, notes())
+                .await?;
+            router
+                .set_port_metadata(0, PortKind::Input, 0, notes())
+                .await?;
+            // Clearing removes the entry again.
+            router
+                .set_port_metadata(0, PortKind::Input, 0, PortMetadata::default())
+                .await?;
+        }
+
+        let router =
+            MetadataRouter::new(DummyRouter::with_config(2, 2, 2)).with_persistence(path)?;
+        assert_eq!(
+            router.get_port_metadata(1, PortKind::Output, 1).await?,
+            notes()
+        );
+        assert_eq!(
+            router.get_port_metadata(0, PortKind::Input, 0).await?,
+            PortMetadata::default()
+        );
+        assert_eq!(router.store.lock().unwrap().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn describe_layers() -> Result<()> {
+        let router = MetadataRouter::new(MetadataRouter::new(MetadataRouter::new(
+            DummyRouter::with_config(2, 4, 2),
+        )));
+        assert!(router
+            .set_port_metadata(0, PortKind::Output, 2, notes())
+            .await
+            .is_err());
+        router
+            .inner()
+            .set_port_metadata(1, PortKind::Input, 3, notes())
+            .await?;
+
+        let layers = describe_router(&router);
+        let names: Vec<_> = layers.iter().map(|l| l.name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "MetadataRouter",
+                "MetadataRouter",
+                "MetadataRouter",
+                "DummyRouter"
+            ]
+        );
+        // Only the outermost layer saw the rejection, only the middle one stores anything.
+        assert_eq!(layers[0].counters, vec![("rejected".to_string(), 1)]);
+        assert_eq!(layers[1].counters, vec![("rejected".to_string(), 0)]);
+        assert!(layers[1]
+            .config
+            .contains(&("entries".to_string(), "1".to_string())));
+        assert!(layers[3]
+            .config
+            .contains(&("matrices".to_string(), "4x2, 4x2".to_string())));
+        Ok(())
+    }
+}
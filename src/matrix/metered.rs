@@ -0,0 +1,397 @@
+//! Metrics for any router
+//!
+//! Wraps a [MatrixRouter], recording calls, failures and call durations of every method
+//! through the [metrics] facade, labeled by `method`, so any exporter can pick them up.
+//! Whether the router is connected is kept as a gauge, as announced by its events.
+//! Without a recorder installed, recording does nothing. Everything passes through.
+
+use super::*;
+use anyhow::Result;
+use futures_core::stream::BoxStream;
+use metrics::{counter, gauge, histogram};
+use std::future::Future;
+use std::time::Instant;
+use tokio_stream::StreamExt;
+
+/// Counter of calls, by `method`.
+pub const CALLS: &str = "omnimatrix_router_calls_total";
+/// Counter of calls that failed, by `method`.
+pub const FAILURES: &str = "omnimatrix_router_failures_total";
+/// Histogram of call durations in seconds, by `method`.
+pub const DURATION: &str = "omnimatrix_router_call_duration_seconds";
+/// Gauge of whether the router is connected, 1 if so, 0 if not.
+pub const CONNECTED: &str = "omnimatrix_router_connected";
+
+/// Router wrapper recording metrics of `R`, see the module docs.
+#[derive(Clone)]
+pub struct MeteredRouter<R> {
+    inner: R,
+}
+
+impl<R: MatrixRouter> MeteredRouter<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+
+    /// The wrapped router.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Await `call` of `method`, recording how it went.
+    async fn metered<T>(
+        &self,
+        method: &'static str,
+        call: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let started = Instant::now();
+        let res = call.await;
+        counter!(CALLS, "method" => method).increment(1);
+        if res.is_err() {
+            counter!(FAILURES, "method" => method).increment(1);
+        }
+        histogram!(DURATION, "method" => method).record(started.elapsed().as_secs_f64());
+        res
+    }
+}
+
+impl<R: MatrixRouter> MatrixRouter for MeteredRouter<R> {
+    async fn is_alive(&self) -> Result<bool> {
+        self.metered("is_alive", self.inner.is_alive()).await
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.metered("get_router_info", self.inner.get_router_info())
+            .await
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        self.metered("get_matrix_info", self.inner.get_matrix_info(index))
+            .await
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.metered("get_input_labels", self.inner.get_input_labels(index))
+            .await
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.metered("get_output_labels", self.inner.get_output_labels(index))
+            .await
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        let call = self.inner.update_input_labels(index, changed);
+        self.metered("update_input_labels", call).await
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        let call = self.inner.update_output_labels(index, changed);
+        self.metered("update_output_labels", call).await
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        let call = self.inner.get_input_descriptions(index);
+        self.metered("get_input_descriptions", call).await
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        let call = self.inner.get_output_descriptions(index);
+        self.metered("get_output_descriptions", call).await
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        let call = self.inner.update_input_descriptions(index, changed);
+        self.metered("update_input_descriptions", call).await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        let call = self.inner.update_output_descriptions(index, changed);
+        self.metered("update_output_descriptions", call).await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        let call = self.inner.get_input_status(index);
+        self.metered("get_input_status", call).await
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        let call = self.inner.get_output_status(index);
+        self.metered("get_output_status", call).await
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.metered("get_routes", self.inner.get_routes(index))
+            .await
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        self.metered("get_route", self.inner.get_route(index, output))
+            .await
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        self.metered(
+            "get_route_for_input",
+            self.inner.get_route_for_input(index, input),
+        )
+        .await
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.metered("update_routes", self.inner.update_routes(index, changes))
+            .await
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let call = self.inner.update_routes_atomic(index, changes);
+        self.metered("update_routes_atomic", call).await
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.metered("get_levels", self.inner.get_levels(index))
+            .await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        let call = self.inner.get_level_routes(index, level);
+        self.metered("get_level_routes", call).await
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        let call = self.inner.update_level_routes(index, level, changes);
+        self.metered("update_level_routes", call).await
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        self.metered("stage_routes", self.inner.stage_routes(index, changes))
+            .await
+    }
+
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        self.metered("commit", self.inner.commit(handle)).await
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        self.metered("discard", self.inner.discard(handle)).await
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.metered("get_staged_routes", self.inner.get_staged_routes(index))
+            .await
+    }
+
+    async fn get_frame_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.metered("get_frame_labels", self.inner.get_frame_labels(index))
+            .await
+    }
+
+    async fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        let call = self.inner.update_frame_labels(index, changed);
+        self.metered("update_frame_labels", call).await
+    }
+
+    async fn get_frame_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.metered("get_frame_routes", self.inner.get_frame_routes(index))
+            .await
+    }
+
+    async fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let call = self.inner.update_frame_routes(index, changes);
+        self.metered("update_frame_routes", call).await
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.metered("get_locks", self.inner.get_locks(index)).await
+    }
+
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.metered("update_locks", self.inner.update_locks(index, changes))
+            .await
+    }
+
+    /// Metered as well, if `R` takes locks on behalf of owners.
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        Some(Self::new(self.inner.with_lock_owner(owner)?))
+    }
+
+    async fn get_frame_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.metered("get_frame_locks", self.inner.get_frame_locks(index))
+            .await
+    }
+
+    async fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        let call = self.inner.update_frame_locks(index, changes);
+        self.metered("update_frame_locks", call).await
+    }
+
+    async fn get_processing_unit_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        let call = self.inner.get_processing_unit_locks(index);
+        self.metered("get_processing_unit_locks", call).await
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        let call = self.inner.update_processing_unit_locks(index, changes);
+        self.metered("update_processing_unit_locks", call).await
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        let call = self.inner.get_port_metadata(index, kind, id);
+        self.metered("get_port_metadata", call).await
+    }
+
+    async fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> Result<()> {
+        let call = self.inner.set_port_metadata(index, kind, id, metadata);
+        self.metered("set_port_metadata", call).await
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        self.metered("get_alarms", self.inner.get_alarms()).await
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        let events = self
+            .metered("event_stream", self.inner.event_stream())
+            .await?;
+        Ok(Box::pin(events.map(|ev| {
+            match ev {
+                RouterEvent::Connected => gauge!(CONNECTED).set(1.0),
+                RouterEvent::Disconnected => gauge!(CONNECTED).set(0.0),
+                _ => {}
+            }
+            ev
+        })))
+    }
+}
+
+impl<R: RouterIntrospect> RouterIntrospect for MeteredRouter<R> {
+    fn name(&self) -> &'static str {
+        "MeteredRouter"
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        Some(&self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics::{SharedString, Unit};
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::{CompositeKey, MetricKind};
+
+    /// Metrics taken from the recorder once, taking them drains histograms.
+    type Recorded = Vec<(CompositeKey, Option<Unit>, Option<SharedString>, DebugValue)>;
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    /// Run `test` with a recorder of its own, returning what it recorded.
+    fn recorded<F: Future<Output = Result<()>>>(test: impl FnOnce() -> F) -> Result<Recorded> {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        // The recorder is local to this thread, so is the runtime.
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        metrics::with_local_recorder(&recorder, || rt.block_on(test()))?;
+        Ok(snapshotter.snapshot().into_vec())
+    }
+
+    /// Value of metric `name` of `kind` for `method`, if it was recorded.
+    fn value<'a>(
+        recorded: &'a Recorded,
+        kind: MetricKind,
+        name: &str,
+        method: &str,
+    ) -> Option<&'a DebugValue> {
+        recorded
+            .iter()
+            .find(|(key, ..)| {
+                key.kind() == kind
+                    && key.key().name() == name
+                    && key
+                        .key()
+                        .labels()
+                        .any(|l| l.key() == "method" && l.value() == method)
+            })
+            .map(|(.., value)| value)
+    }
+
+    #[test]
+    fn counts_calls() -> Result<()> {
+        let recorded = recorded(|| async {
+            let router = MeteredRouter::new(DummyRouter::with_config(1, 4, 4));
+            router.get_routes(0).await?;
+            router.get_routes(0).await?;
+            Ok(())
+        })?;
+        assert_eq!(
+            value(&recorded, MetricKind::Counter, CALLS, "get_routes"),
+            Some(&DebugValue::Counter(2))
+        );
+        assert_eq!(
+            value(&recorded, MetricKind::Counter, FAILURES, "get_routes"),
+            None
+        );
+        match value(&recorded, MetricKind::Histogram, DURATION, "get_routes") {
+            Some(DebugValue::Histogram(durations)) => assert_eq!(durations.len(), 2),
+            v => panic!("expected durations, got {:?}", v),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn counts_failures() -> Result<()> {
+        let recorded = recorded(|| async {
+            let dummy = DummyRouter::with_config(1, 4, 4);
+            let router = MeteredRouter::new(dummy.clone());
+            // Passed through as is.
+            assert!(router.update_routes(0, vec![patch(9, 0)]).await.is_err());
+            router.update_routes(0, vec![patch(3, 0)]).await?;
+            assert_eq!(dummy.get_routes(0).await?[0], patch(3, 0));
+            Ok(())
+        })?;
+        assert_eq!(
+            value(&recorded, MetricKind::Counter, CALLS, "update_routes"),
+            Some(&DebugValue::Counter(2))
+        );
+        assert_eq!(
+            value(&recorded, MetricKind::Counter, FAILURES, "update_routes"),
+            Some(&DebugValue::Counter(1))
+        );
+        Ok(())
+    }
+}
@@ -1,7 +1,53 @@
+#[cfg(feature = "serde")]
+mod audit;
+mod budget;
+mod caching;
+mod composite;
 mod dummy;
+pub mod dynamic;
 mod interface;
+mod introspect;
+pub mod label_csv;
+mod locking;
+mod metadata;
+#[cfg(feature = "metrics")]
+pub mod metered;
 mod model;
+#[cfg(feature = "serde")]
+mod persistent;
+mod rate_limit;
+mod read_only;
+mod remap;
+mod salvo;
+mod slice;
+mod subscription;
+#[cfg(feature = "serde")]
+mod tracing_router;
+mod transaction;
+mod watchdog;
 
+#[cfg(feature = "serde")]
+pub use audit::{AuditChange, AuditEntry, AuditRouter, AuditSource};
+pub use budget::Budget;
+pub use caching::CachingRouter;
+pub use composite::CompositeRouter;
 pub use dummy::DummyRouter;
 pub use interface::MatrixRouter;
+pub use introspect::{describe_router, LayerDescription, RouterIntrospect};
+pub use locking::LockingRouter;
+pub use metadata::MetadataRouter;
+#[cfg(feature = "metrics")]
+pub use metered::MeteredRouter;
 pub use model::*;
+#[cfg(feature = "serde")]
+pub use persistent::PersistentRouter;
+pub use rate_limit::{RateLimit, RateLimitPolicy, RateLimitedRouter};
+pub use read_only::ReadOnlyRouter;
+pub use remap::{RemapBuilder, RemapRouter};
+pub use salvo::{SalvoRecall, SalvoStore};
+pub use slice::SliceRouter;
+pub use subscription::{EventSubscription, TypedEvent, TypedSubscription};
+#[cfg(feature = "serde")]
+pub use tracing_router::{TraceDirection, TraceEntry, TraceLog, TracingRouter};
+pub use transaction::{apply_transaction, RouteTransaction, TransactionOutcome};
+pub use watchdog::Watchdog;
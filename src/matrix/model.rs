@@ -1,38 +1,735 @@
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct RouterInfo {
     pub model: Option<String>,
     pub name: Option<String>,
     pub matrix_count: Option<u32>,
+    /// Human-readable name of every matrix, by index.
+    pub matrix_names: Option<Vec<String>>,
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct RouterMatrixInfo {
     pub input_count: u32,
     pub output_count: u32,
+    /// Number of frame buffers, zero if the matrix has none.
+    pub frame_count: u32,
+    /// Human-readable name, telling matrices of one router apart.
+    pub name: Option<String>,
 }
 
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+/// What a router supports, see [super::MatrixRouter::capabilities].
+///
+/// The default is a single matrix with changeable labels and nothing else.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RouterCapabilities {
+    pub matrix_count: u32,
+    pub can_update_input_labels: bool,
+    pub can_update_output_labels: bool,
+    /// Whether the router holds output locks itself.
+    pub can_lock_outputs: bool,
+    pub has_frame_buffers: bool,
+    pub has_processing_units: bool,
+    /// Whether the router reports alarms, see [RouterEvent::AlarmUpdate].
+    pub has_alarm_status: bool,
+    /// Whether the router stores [PortMetadata].
+    pub has_port_metadata: bool,
+}
+
+impl Default for RouterCapabilities {
+    fn default() -> Self {
+        Self {
+            matrix_count: 1,
+            can_update_input_labels: true,
+            can_update_output_labels: true,
+            can_lock_outputs: false,
+            has_frame_buffers: false,
+            has_processing_units: false,
+            has_alarm_status: false,
+            has_port_metadata: false,
+        }
+    }
+}
+
+impl RouterCapabilities {
+    /// Everything the interface has to offer, on `matrix_count` matrices.
+    pub fn all(matrix_count: u32) -> Self {
+        Self {
+            matrix_count,
+            can_update_input_labels: true,
+            can_update_output_labels: true,
+            can_lock_outputs: true,
+            has_frame_buffers: true,
+            has_processing_units: true,
+            has_alarm_status: true,
+            has_port_metadata: true,
+        }
+    }
+}
+
+/// Ordered by id, then name.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
 pub struct RouterLabel {
     pub id: u32,
     pub name: String,
 }
 
+/// Long description of a port, going beyond its label, like what it carries or where from.
+///
+/// Ordered by id, then text.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub struct RouterDescription {
+    pub id: u32,
+    pub text: String,
+}
+
+impl RouterDescription {
+    /// Blank description of port `id`.
+    pub fn empty(id: u32) -> Self {
+        Self {
+            id,
+            text: String::new(),
+        }
+    }
+}
+
+/// Ordered by output, then input, parked outputs first.
+///
+/// `from_input` is `None` for a parked output, fed by no input at all.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub struct RouterPatch {
-    pub from_input: u32,
+    pub from_input: Option<u32>,
     pub to_output: u32,
 }
 
+impl RouterPatch {
+    /// Patch parking `output`.
+    pub fn parked(output: u32) -> Self {
+        Self {
+            from_input: None,
+            to_output: output,
+        }
+    }
+
+    pub fn is_parked(&self) -> bool {
+        self.from_input.is_none()
+    }
+}
+
+impl std::fmt::Display for RouterPatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.from_input {
+            Some(input) => write!(f, "input {} to output {}", input, self.to_output),
+            None => write!(f, "no input to output {}", self.to_output),
+        }
+    }
+}
+
+impl Ord for RouterPatch {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.to_output, self.from_input).cmp(&(other.to_output, other.from_input))
+    }
+}
+
+impl PartialOrd for RouterPatch {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl RouterMatrixInfo {
+    /// Check that every patch refers to an existing input and output of matrix `index`.
+    ///
+    /// Parking an existing output is fine.
+    pub fn check_patches(&self, index: u32, patches: &[RouterPatch]) -> Result<(), RouterError> {
+        match patches.iter().find(|p| {
+            p.from_input.is_some_and(|i| i >= self.input_count) || p.to_output >= self.output_count
+        }) {
+            Some(&patch) => Err(RouterError::OutOfRange { index, patch }),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that every label refers to an existing port of `kind` in matrix `index`.
+    pub fn check_labels(
+        &self,
+        index: u32,
+        kind: LabelKind,
+        labels: &[RouterLabel],
+    ) -> Result<(), RouterError> {
+        let count = kind.count(self);
+        match labels.iter().find(|l| l.id >= count) {
+            Some(l) => Err(RouterError::LabelOutOfRange {
+                index,
+                kind,
+                id: l.id,
+                max: count.checked_sub(1),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that every description refers to an existing port of `kind` in matrix `index`.
+    pub fn check_descriptions(
+        &self,
+        index: u32,
+        kind: LabelKind,
+        descriptions: &[RouterDescription],
+    ) -> Result<(), RouterError> {
+        let count = kind.count(self);
+        match descriptions.iter().find(|d| d.id >= count) {
+            Some(d) => Err(RouterError::LabelOutOfRange {
+                index,
+                kind,
+                id: d.id,
+                max: count.checked_sub(1),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What a label names.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum LabelKind {
+    Input,
+    Output,
+    Frame,
+}
+
+impl LabelKind {
+    /// Number of ports of this kind in a matrix.
+    pub fn count(self, mi: &RouterMatrixInfo) -> u32 {
+        match self {
+            LabelKind::Input => mi.input_count,
+            LabelKind::Output => mi.output_count,
+            LabelKind::Frame => mi.frame_count,
+        }
+    }
+}
+
+impl std::fmt::Display for LabelKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            LabelKind::Input => "Input",
+            LabelKind::Output => "Output",
+            LabelKind::Frame => "Frame",
+        })
+    }
+}
+
+/// Errors callers may want to tell apart, carried inside [anyhow::Error].
 #[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RouterError {
+    /// A patch refers to an input or output matrix `index` doesn't have. Frame buffer
+    /// patches have frame buffers as outputs, locks of missing outputs are parked patches.
+    OutOfRange { index: u32, patch: RouterPatch },
+    /// A label or description refers to port `id` matrix `index` doesn't have.
+    /// `max` is the highest existing id, `None` if there are no such ports at all.
+    LabelOutOfRange {
+        index: u32,
+        kind: LabelKind,
+        id: u32,
+        max: Option<u32>,
+    },
+    /// A leveled call refers to a level matrix `index` doesn't have.
+    NoSuchLevel { index: u32, level: u32 },
+    /// An update was refused for exceeding a rate limit, it may be retried after `retry_after`.
+    RateLimited { retry_after: std::time::Duration },
+    /// A patch connects ports of two different devices behind a composite matrix `index`.
+    CrossDevice { index: u32, patch: RouterPatch },
+    /// A change was refused because the router may only be read.
+    PermissionDenied,
+    /// A change was refused because `output` of matrix `index` is locked by someone else.
+    Locked { index: u32, output: u32 },
+    /// The router refused a change to matrix `index`, like a Videohub answering NAK.
+    Refused { index: u32 },
+    /// The router refused part of a change to matrix `index` after applying the rest.
+    /// `applied` lists the outputs it changed, `failed` those it didn't.
+    PartiallyApplied {
+        index: u32,
+        applied: Vec<u32>,
+        failed: Vec<u32>,
+    },
+}
+
+impl std::fmt::Display for RouterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RouterError::OutOfRange { index, patch } => {
+                write!(f, "Patch of {} out of range for matrix {}", patch, index)
+            }
+            RouterError::LabelOutOfRange {
+                index,
+                kind,
+                id,
+                max: Some(max),
+            } => write!(
+                f,
+                "{} {} does not exist in matrix {} (max: {})",
+                kind, id, index, max
+            ),
+            RouterError::LabelOutOfRange {
+                index,
+                kind,
+                id,
+                max: None,
+            } => write!(
+                f,
+                "{} {} does not exist in matrix {}, it has none",
+                kind, id, index
+            ),
+            RouterError::NoSuchLevel { index, level } => {
+                write!(f, "Matrix {} has no level {}", index, level)
+            }
+            RouterError::RateLimited { retry_after } => write!(
+                f,
+                "Rate limited, retry after {} ms",
+                retry_after.as_millis()
+            ),
+            RouterError::CrossDevice { index, patch } => write!(
+                f,
+                "Patch of {} in matrix {} spans two devices",
+                patch, index
+            ),
+            RouterError::PermissionDenied => write!(f, "Permission denied, router is read-only"),
+            RouterError::Locked { index, output } => write!(
+                f,
+                "Output {} of matrix {} is locked by someone else",
+                output, index
+            ),
+            RouterError::Refused { index } => {
+                write!(f, "Router refused the change to matrix {}", index)
+            }
+            RouterError::PartiallyApplied {
+                index,
+                applied,
+                failed,
+            } => write!(
+                f,
+                "Router applied only part of the change to matrix {}: outputs {:?} changed, {:?} did not",
+                index, applied, failed
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RouterError {}
+
+/// State of a monitored part of the router, like a fan or power supply.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RouterAlarm {
+    pub name: String,
+    /// As reported by the router, e.g. `ok` or `Failed`.
+    pub status: String,
+}
+
+/// Lock of a single port, as held at the router.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RouterLock {
+    pub id: u32,
+    pub locked: bool,
+}
+
+/// Routing level of a matrix, like video or an audio channel pair.
+///
+/// Every matrix has at least level 0, the routes of [crate::matrix::MatrixRouter::get_routes].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RouterLevel {
+    pub id: u32,
+    pub name: String,
+}
+
+/// Routing of a matrix stored under a name, see [crate::matrix::SalvoStore].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Salvo {
+    pub name: String,
+    pub patches: Vec<RouterPatch>,
+}
+
+/// Routes staged with [crate::matrix::MatrixRouter::stage_routes], awaiting a commit or discard.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TakeHandle {
+    /// Matrix the routes were staged on.
+    pub index: u32,
+    /// Router assigned id, 0 for routes that were applied right away.
+    pub id: u64,
+}
+
+/// Which side of a matrix a port is on.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum PortKind {
+    Input,
+    Output,
+}
+
+/// Whether a physical port is there, and what kind of connector it is.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RouterPortStatus {
+    pub id: u32,
+    pub kind: PortKind,
+    /// Whether the port is there to be used, `None` if the router can't tell.
+    pub present: Option<bool>,
+    /// Connector as named by the router, like `BNC` or `Optical`, `None` if unknown.
+    pub port_type: Option<String>,
+}
+
+impl RouterPortStatus {
+    /// Status of port `id`, as far as nothing is known about it.
+    pub fn unknown(kind: PortKind, id: u32) -> Self {
+        Self {
+            id,
+            kind,
+            present: None,
+            port_type: None,
+        }
+    }
+}
+
+/// Descriptive notes on a port, going beyond its label.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PortMetadata {
+    /// Free-form description, e.g. what the port feeds.
+    pub description: String,
+    /// Short tags made of lowercase ASCII letters, digits, `-` and `_`.
+    pub tags: Vec<String>,
+    /// Color hint as `#rrggbb`.
+    pub color: Option<String>,
+}
+
+impl PortMetadata {
+    /// Check tag and color formats.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for tag in &self.tags {
+            let valid = !tag.is_empty()
+                && tag.bytes().all(|c| {
+                    c.is_ascii_lowercase() || c.is_ascii_digit() || c == b'-' || c == b'_'
+                });
+            if !valid {
+                anyhow::bail!("Invalid tag {:?}", tag);
+            }
+        }
+        if let Some(color) = &self.color {
+            let valid = color.len() == 7
+                && color.starts_with('#')
+                && color[1..].bytes().all(|c| c.is_ascii_hexdigit());
+            if !valid {
+                anyhow::bail!("Invalid color {:?}, expected #rrggbb", color);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Change at a router, see [crate::matrix::MatrixRouter::event_stream].
+///
+/// Every event stream follows the same lifecycle: if the router is ready when subscribing,
+/// the stream starts with [RouterEvent::Connected]. Afterwards, [RouterEvent::Disconnected]
+/// and [RouterEvent::Connected] alternate as the router goes away and comes back.
+/// Wrapping routers pass both through unchanged.
+///
+/// Label and route changes come as deltas, carrying only the entries that changed. Right
+/// after [RouterEvent::Connected], routers may follow up with snapshots carrying all entries,
+/// for subscribers to (re)build their view from.
+///
+/// Routers may learn to announce more without a major release, so matching outside of this
+/// crate needs a wildcard arm:
+///
+/// ```
+/// use omnimatrix::matrix::RouterEvent;
+///
+/// fn is_lifecycle(ev: &RouterEvent) -> bool {
+///     match ev {
+///         RouterEvent::Connected | RouterEvent::Disconnected => true,
+///         _ => false,
+///     }
+/// }
+/// # assert!(is_lifecycle(&RouterEvent::Connected));
+/// ```
+///
+/// With the `serde` feature, events are tagged by `type` with named fields, like
+/// `{"type":"RouteUpdate","matrix":0,"patches":[...]}`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "TaggedEvent", from = "TaggedEvent"))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum RouterEvent {
+    /// The router is ready, its state can be queried.
     Connected,
+    /// The router went away, queries may fail until the next [RouterEvent::Connected].
     Disconnected,
 
     InfoUpdate(RouterInfo),
     MatrixInfoUpdate(u32, RouterMatrixInfo),
+    /// Input labels of a matrix changed, carrying only the changed labels.
     InputLabelUpdate(u32, Vec<RouterLabel>),
+    /// Output labels of a matrix changed, carrying only the changed labels.
     OutputLabelUpdate(u32, Vec<RouterLabel>),
+    /// Routes of a matrix changed, carrying only the changed patches.
     RouteUpdate(u32, Vec<RouterPatch>),
+    /// All input labels of a matrix, as known on (re)connect.
+    InputLabelSnapshot(u32, Vec<RouterLabel>),
+    /// All output labels of a matrix, as known on (re)connect.
+    OutputLabelSnapshot(u32, Vec<RouterLabel>),
+    /// All routes of a matrix, as known on (re)connect.
+    RouteSnapshot(u32, Vec<RouterPatch>),
+    /// Staged routes of a matrix changed, carrying all still staged patches.
+    /// Committed ones show up as [RouterEvent::RouteUpdate] as usual.
+    StagedRouteUpdate(u32, Vec<RouterPatch>),
+    /// Routes of a level other than 0 changed, carrying matrix, level and all its routes.
+    /// Level 0 changes are [RouterEvent::RouteUpdate]s.
+    LevelRouteUpdate(u32, u32, Vec<RouterPatch>),
+    LockUpdate(u32, Vec<RouterLock>),
+    FrameLabelUpdate(u32, Vec<RouterLabel>),
+    FrameRouteUpdate(u32, Vec<RouterPatch>),
+    FrameLockUpdate(u32, Vec<RouterLock>),
+    ProcessingUnitLockUpdate(u32, Vec<RouterLock>),
+    /// A salvo was recalled onto a matrix, the routes changed show up as
+    /// [RouterEvent::RouteUpdate] as usual.
+    SalvoRecalled(u32, String),
+    /// The sources an NDI router discovered changed, carrying all available source names.
+    /// Inputs they occupy show up as [RouterEvent::InputLabelUpdate] as usual.
+    SourcesChanged(Vec<String>),
+    /// Alarms of the router changed, carrying all of them.
+    AlarmUpdate(Vec<RouterAlarm>),
+    /// Input or output descriptions of a matrix changed, carrying only the changed ones.
+    DescriptionUpdate(u32, PortKind, Vec<RouterDescription>),
+    /// Ports of a matrix came or went, carrying only the changed statuses.
+    PortStatusUpdate(u32, Vec<RouterPortStatus>),
+}
+
+impl RouterEvent {
+    /// The matrix this is about, None for events about the whole router.
+    pub fn matrix(&self) -> Option<u32> {
+        match self {
+            RouterEvent::MatrixInfoUpdate(i, _)
+            | RouterEvent::InputLabelUpdate(i, _)
+            | RouterEvent::OutputLabelUpdate(i, _)
+            | RouterEvent::RouteUpdate(i, _)
+            | RouterEvent::InputLabelSnapshot(i, _)
+            | RouterEvent::OutputLabelSnapshot(i, _)
+            | RouterEvent::RouteSnapshot(i, _)
+            | RouterEvent::StagedRouteUpdate(i, _)
+            | RouterEvent::LevelRouteUpdate(i, _, _)
+            | RouterEvent::LockUpdate(i, _)
+            | RouterEvent::FrameLabelUpdate(i, _)
+            | RouterEvent::FrameRouteUpdate(i, _)
+            | RouterEvent::FrameLockUpdate(i, _)
+            | RouterEvent::ProcessingUnitLockUpdate(i, _)
+            | RouterEvent::SalvoRecalled(i, _)
+            | RouterEvent::DescriptionUpdate(i, _, _)
+            | RouterEvent::PortStatusUpdate(i, _) => Some(*i),
+            RouterEvent::Connected
+            | RouterEvent::Disconnected
+            | RouterEvent::InfoUpdate(_)
+            | RouterEvent::SourcesChanged(_)
+            | RouterEvent::AlarmUpdate(_) => None,
+        }
+    }
+
+    /// Whether this carries all labels or routes of a matrix, rather than changes.
+    pub fn is_snapshot(&self) -> bool {
+        matches!(
+            self,
+            RouterEvent::InputLabelSnapshot(..)
+                | RouterEvent::OutputLabelSnapshot(..)
+                | RouterEvent::RouteSnapshot(..)
+        )
+    }
+
+    /// Turn a snapshot into the update of the same kind, for consumers which merely apply
+    /// entries. Other events stay as they are.
+    pub fn into_update(self) -> RouterEvent {
+        match self {
+            RouterEvent::InputLabelSnapshot(i, ls) => RouterEvent::InputLabelUpdate(i, ls),
+            RouterEvent::OutputLabelSnapshot(i, ls) => RouterEvent::OutputLabelUpdate(i, ls),
+            RouterEvent::RouteSnapshot(i, ps) => RouterEvent::RouteUpdate(i, ps),
+            ev => ev,
+        }
+    }
+}
+
+/// [RouterEvent] as (de)serialized, with named fields for JSON consumers.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+enum TaggedEvent {
+    Connected,
+    Disconnected,
+    InfoUpdate {
+        info: RouterInfo,
+    },
+    MatrixInfoUpdate {
+        matrix: u32,
+        info: RouterMatrixInfo,
+    },
+    InputLabelUpdate {
+        matrix: u32,
+        labels: Vec<RouterLabel>,
+    },
+    OutputLabelUpdate {
+        matrix: u32,
+        labels: Vec<RouterLabel>,
+    },
+    RouteUpdate {
+        matrix: u32,
+        patches: Vec<RouterPatch>,
+    },
+    InputLabelSnapshot {
+        matrix: u32,
+        labels: Vec<RouterLabel>,
+    },
+    OutputLabelSnapshot {
+        matrix: u32,
+        labels: Vec<RouterLabel>,
+    },
+    RouteSnapshot {
+        matrix: u32,
+        patches: Vec<RouterPatch>,
+    },
+    StagedRouteUpdate {
+        matrix: u32,
+        patches: Vec<RouterPatch>,
+    },
+    LevelRouteUpdate {
+        matrix: u32,
+        level: u32,
+        patches: Vec<RouterPatch>,
+    },
+    LockUpdate {
+        matrix: u32,
+        locks: Vec<RouterLock>,
+    },
+    FrameLabelUpdate {
+        matrix: u32,
+        labels: Vec<RouterLabel>,
+    },
+    FrameRouteUpdate {
+        matrix: u32,
+        patches: Vec<RouterPatch>,
+    },
+    FrameLockUpdate {
+        matrix: u32,
+        locks: Vec<RouterLock>,
+    },
+    ProcessingUnitLockUpdate {
+        matrix: u32,
+        locks: Vec<RouterLock>,
+    },
+    SalvoRecalled {
+        matrix: u32,
+        salvo: String,
+    },
+    SourcesChanged {
+        sources: Vec<String>,
+    },
+    AlarmUpdate {
+        alarms: Vec<RouterAlarm>,
+    },
+    DescriptionUpdate {
+        matrix: u32,
+        kind: PortKind,
+        descriptions: Vec<RouterDescription>,
+    },
+    PortStatusUpdate {
+        matrix: u32,
+        statuses: Vec<RouterPortStatus>,
+    },
+}
+
+#[cfg(feature = "serde")]
+impl From<RouterEvent> for TaggedEvent {
+    fn from(ev: RouterEvent) -> Self {
+        use RouterEvent as E;
+        match ev {
+            E::Connected => Self::Connected,
+            E::Disconnected => Self::Disconnected,
+            E::InfoUpdate(info) => Self::InfoUpdate { info },
+            E::MatrixInfoUpdate(matrix, info) => Self::MatrixInfoUpdate { matrix, info },
+            E::InputLabelUpdate(matrix, labels) => Self::InputLabelUpdate { matrix, labels },
+            E::OutputLabelUpdate(matrix, labels) => Self::OutputLabelUpdate { matrix, labels },
+            E::RouteUpdate(matrix, patches) => Self::RouteUpdate { matrix, patches },
+            E::InputLabelSnapshot(matrix, labels) => Self::InputLabelSnapshot { matrix, labels },
+            E::OutputLabelSnapshot(matrix, labels) => Self::OutputLabelSnapshot { matrix, labels },
+            E::RouteSnapshot(matrix, patches) => Self::RouteSnapshot { matrix, patches },
+            E::StagedRouteUpdate(matrix, patches) => Self::StagedRouteUpdate { matrix, patches },
+            E::LevelRouteUpdate(matrix, level, patches) => Self::LevelRouteUpdate {
+                matrix,
+                level,
+                patches,
+            },
+            E::LockUpdate(matrix, locks) => Self::LockUpdate { matrix, locks },
+            E::FrameLabelUpdate(matrix, labels) => Self::FrameLabelUpdate { matrix, labels },
+            E::FrameRouteUpdate(matrix, patches) => Self::FrameRouteUpdate { matrix, patches },
+            E::FrameLockUpdate(matrix, locks) => Self::FrameLockUpdate { matrix, locks },
+            E::ProcessingUnitLockUpdate(matrix, locks) => {
+                Self::ProcessingUnitLockUpdate { matrix, locks }
+            }
+            E::SalvoRecalled(matrix, salvo) => Self::SalvoRecalled { matrix, salvo },
+            E::SourcesChanged(sources) => Self::SourcesChanged { sources },
+            E::AlarmUpdate(alarms) => Self::AlarmUpdate { alarms },
+            E::DescriptionUpdate(matrix, kind, descriptions) => Self::DescriptionUpdate {
+                matrix,
+                kind,
+                descriptions,
+            },
+            E::PortStatusUpdate(matrix, statuses) => Self::PortStatusUpdate { matrix, statuses },
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<TaggedEvent> for RouterEvent {
+    fn from(ev: TaggedEvent) -> Self {
+        use TaggedEvent as T;
+        match ev {
+            T::Connected => Self::Connected,
+            T::Disconnected => Self::Disconnected,
+            T::InfoUpdate { info } => Self::InfoUpdate(info),
+            T::MatrixInfoUpdate { matrix, info } => Self::MatrixInfoUpdate(matrix, info),
+            T::InputLabelUpdate { matrix, labels } => Self::InputLabelUpdate(matrix, labels),
+            T::OutputLabelUpdate { matrix, labels } => Self::OutputLabelUpdate(matrix, labels),
+            T::RouteUpdate { matrix, patches } => Self::RouteUpdate(matrix, patches),
+            T::InputLabelSnapshot { matrix, labels } => Self::InputLabelSnapshot(matrix, labels),
+            T::OutputLabelSnapshot { matrix, labels } => Self::OutputLabelSnapshot(matrix, labels),
+            T::RouteSnapshot { matrix, patches } => Self::RouteSnapshot(matrix, patches),
+            T::StagedRouteUpdate { matrix, patches } => Self::StagedRouteUpdate(matrix, patches),
+            T::LevelRouteUpdate {
+                matrix,
+                level,
+                patches,
+            } => Self::LevelRouteUpdate(matrix, level, patches),
+            T::LockUpdate { matrix, locks } => Self::LockUpdate(matrix, locks),
+            T::FrameLabelUpdate { matrix, labels } => Self::FrameLabelUpdate(matrix, labels),
+            T::FrameRouteUpdate { matrix, patches } => Self::FrameRouteUpdate(matrix, patches),
+            T::FrameLockUpdate { matrix, locks } => Self::FrameLockUpdate(matrix, locks),
+            T::ProcessingUnitLockUpdate { matrix, locks } => {
+                Self::ProcessingUnitLockUpdate(matrix, locks)
+            }
+            T::SalvoRecalled { matrix, salvo } => Self::SalvoRecalled(matrix, salvo),
+            T::SourcesChanged { sources } => Self::SourcesChanged(sources),
+            T::AlarmUpdate { alarms } => Self::AlarmUpdate(alarms),
+            T::DescriptionUpdate {
+                matrix,
+                kind,
+                descriptions,
+            } => Self::DescriptionUpdate(matrix, kind, descriptions),
+            T::PortStatusUpdate { matrix, statuses } => Self::PortStatusUpdate(matrix, statuses),
+        }
+    }
 }
 
 impl From<videohub::Label> for RouterLabel {
@@ -43,28 +740,396 @@ impl From<videohub::Label> for RouterLabel {
         }
     }
 }
-impl Into<videohub::Label> for RouterLabel {
-    fn into(self) -> videohub::Label {
+impl From<RouterLabel> for videohub::Label {
+    fn from(val: RouterLabel) -> Self {
         videohub::Label {
-            id: self.id,
-            name: self.name,
+            id: val.id,
+            name: val.name,
         }
     }
 }
 
+impl RouterLabel {
+    /// Normalize the name the way hardware hubs would, see [videohub::Label::normalized].
+    pub fn normalized(&self, max_len: usize) -> RouterLabel {
+        videohub::Label::from(self.clone())
+            .normalized(max_len)
+            .into()
+    }
+}
+
+impl std::str::FromStr for RouterLabel {
+    type Err = anyhow::Error;
+
+    /// Parse a label in Videohub `<id> <name>` format, e.g. `0 Camera 1`.
+    ///
+    /// Everything after the first space is the name, including further leading spaces.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (id, name) = s
+            .split_once(' ')
+            .ok_or_else(|| anyhow::anyhow!("Invalid label {:?}, expected \"<id> <name>\"", s))?;
+        let id = id
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid label id {:?}", id))?;
+        Ok(RouterLabel {
+            id,
+            name: name.into(),
+        })
+    }
+}
+
+impl TryFrom<&str> for RouterLabel {
+    type Error = anyhow::Error;
+
+    fn try_from(s: &str) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<RouterLabel> for String {
+    /// Videohub `<id> <name>` format, as parsed by its [`FromStr`](std::str::FromStr) impl.
+    fn from(val: RouterLabel) -> Self {
+        format!("{} {}", val.id, val.name)
+    }
+}
+
 impl From<videohub::Route> for RouterPatch {
     fn from(item: videohub::Route) -> Self {
         Self {
-            from_input: item.from_input,
+            from_input: Some(item.from_input),
             to_output: item.to_output,
         }
     }
 }
-impl Into<videohub::Route> for RouterPatch {
-    fn into(self) -> videohub::Route {
-        videohub::Route {
-            from_input: self.from_input,
-            to_output: self.to_output,
+impl TryFrom<RouterPatch> for videohub::Route {
+    type Error = anyhow::Error;
+
+    /// Videohub routes always have an input, parked outputs have no route.
+    fn try_from(val: RouterPatch) -> anyhow::Result<Self> {
+        let from_input = val.from_input.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Output {} is parked, which Videohubs can't express",
+                val.to_output
+            )
+        })?;
+        Ok(videohub::Route {
+            from_input,
+            to_output: val.to_output,
+        })
+    }
+}
+
+impl From<videohub::Alarm> for RouterAlarm {
+    fn from(item: videohub::Alarm) -> Self {
+        Self {
+            name: item.name,
+            status: item.status,
+        }
+    }
+}
+impl From<RouterAlarm> for videohub::Alarm {
+    fn from(val: RouterAlarm) -> Self {
+        videohub::Alarm {
+            name: val.name,
+            status: val.status,
+        }
+    }
+}
+
+impl RouterPortStatus {
+    /// Status of a port as reported in a `VIDEO INPUT STATUS:` or `VIDEO OUTPUT STATUS:`
+    /// block, where a port type of `None` tells the port is missing.
+    pub fn from_hardware(kind: PortKind, port: videohub::HardwarePort) -> Self {
+        let present = port.port_type != videohub::HardwarePortType::None;
+        Self {
+            id: port.id,
+            kind,
+            present: Some(present),
+            port_type: present.then(|| port.port_type.to_string()),
+        }
+    }
+}
+
+impl From<videohub::Lock> for RouterLock {
+    /// Held by anyone counts as locked, the router doesn't tell owners apart.
+    fn from(item: videohub::Lock) -> Self {
+        Self {
+            id: item.id,
+            locked: item.state != videohub::LockState::Unlocked,
+        }
+    }
+}
+impl From<RouterLock> for videohub::Lock {
+    /// Locking takes ownership, unlocking releases it.
+    fn from(val: RouterLock) -> Self {
+        videohub::Lock {
+            id: val.id,
+            state: if val.locked {
+                videohub::LockState::Owned
+            } else {
+                videohub::LockState::Unlocked
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn label(id: u32, name: &str) -> RouterLabel {
+        RouterLabel {
+            id,
+            name: name.into(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn events_roundtrip_through_json() {
+        let patches = vec![RouterPatch {
+            from_input: Some(2),
+            to_output: 1,
+        }];
+        let labels = vec![label(0, "Cam 1")];
+        let locks = vec![RouterLock {
+            id: 3,
+            locked: true,
+        }];
+        let info = RouterInfo {
+            model: Some("Smart Videohub 12x12".into()),
+            name: None,
+            matrix_count: Some(1),
+            matrix_names: Some(vec!["Main".into()]),
+        };
+        let matrix_info = RouterMatrixInfo {
+            input_count: 12,
+            output_count: 12,
+            frame_count: 0,
+            name: None,
+        };
+        let alarm = RouterAlarm {
+            name: "Fan".into(),
+            status: "Failed".into(),
+        };
+        let events = vec![
+            RouterEvent::Connected,
+            RouterEvent::Disconnected,
+            RouterEvent::InfoUpdate(info),
+            RouterEvent::InfoUpdate(RouterInfo::default()),
+            RouterEvent::MatrixInfoUpdate(0, matrix_info),
+            RouterEvent::InputLabelUpdate(0, labels.clone()),
+            RouterEvent::InputLabelUpdate(1, vec![]),
+            RouterEvent::OutputLabelUpdate(0, labels.clone()),
+            RouterEvent::OutputLabelUpdate(0, vec![]),
+            RouterEvent::RouteUpdate(0, patches.clone()),
+            RouterEvent::RouteUpdate(0, vec![]),
+            RouterEvent::InputLabelSnapshot(0, labels.clone()),
+            RouterEvent::InputLabelSnapshot(0, vec![]),
+            RouterEvent::OutputLabelSnapshot(0, labels.clone()),
+            RouterEvent::OutputLabelSnapshot(0, vec![]),
+            RouterEvent::RouteSnapshot(0, patches.clone()),
+            RouterEvent::RouteSnapshot(0, vec![]),
+            RouterEvent::StagedRouteUpdate(0, patches.clone()),
+            RouterEvent::StagedRouteUpdate(0, vec![]),
+            RouterEvent::LevelRouteUpdate(0, 2, patches.clone()),
+            RouterEvent::LevelRouteUpdate(0, 2, vec![]),
+            RouterEvent::LockUpdate(0, locks.clone()),
+            RouterEvent::LockUpdate(0, vec![]),
+            RouterEvent::FrameLabelUpdate(0, labels),
+            RouterEvent::FrameLabelUpdate(0, vec![]),
+            RouterEvent::FrameRouteUpdate(0, patches.clone()),
+            RouterEvent::FrameRouteUpdate(0, vec![]),
+            RouterEvent::FrameLockUpdate(0, locks.clone()),
+            RouterEvent::FrameLockUpdate(0, vec![]),
+            RouterEvent::ProcessingUnitLockUpdate(0, locks),
+            RouterEvent::ProcessingUnitLockUpdate(0, vec![]),
+            RouterEvent::SalvoRecalled(0, "Show".into()),
+            RouterEvent::SourcesChanged(vec!["CAM (1)".into()]),
+            RouterEvent::SourcesChanged(vec![]),
+            RouterEvent::AlarmUpdate(vec![alarm]),
+            RouterEvent::AlarmUpdate(vec![]),
+            RouterEvent::DescriptionUpdate(
+                0,
+                PortKind::Output,
+                vec![RouterDescription {
+                    id: 3,
+                    text: "Projector".into(),
+                }],
+            ),
+            RouterEvent::DescriptionUpdate(0, PortKind::Input, vec![]),
+            RouterEvent::PortStatusUpdate(
+                0,
+                vec![
+                    RouterPortStatus::unknown(PortKind::Input, 2),
+                    RouterPortStatus {
+                        id: 0,
+                        kind: PortKind::Output,
+                        present: Some(true),
+                        port_type: Some("BNC".into()),
+                    },
+                ],
+            ),
+            RouterEvent::PortStatusUpdate(0, vec![]),
+        ];
+        for ev in events {
+            let json = serde_json::to_string(&ev).unwrap();
+            assert_eq!(
+                serde_json::from_str::<RouterEvent>(&json).unwrap(),
+                ev,
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn events_tagged_by_type() {
+        let ev = RouterEvent::RouteUpdate(
+            0,
+            vec![RouterPatch {
+                from_input: Some(2),
+                to_output: 1,
+            }],
+        );
+        assert_eq!(
+            serde_json::to_value(&ev).unwrap(),
+            serde_json::json!({
+                "type": "RouteUpdate",
+                "matrix": 0,
+                "patches": [{"from_input": 2, "to_output": 1}],
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(RouterEvent::Connected).unwrap(),
+            serde_json::json!({"type": "Connected"})
+        );
+    }
+
+    #[test]
+    fn sorted_by_port() {
+        let mut labels = vec![label(2, "C"), label(0, "B"), label(1, "A"), label(0, "A")];
+        labels.sort();
+        assert_eq!(
+            labels,
+            vec![label(0, "A"), label(0, "B"), label(1, "A"), label(2, "C")]
+        );
+
+        let patch = |from_input, to_output| RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        };
+        let mut patches = vec![
+            patch(0, 3),
+            patch(5, 1),
+            RouterPatch::parked(1),
+            patch(2, 1),
+            patch(9, 0),
+        ];
+        patches.sort();
+        assert_eq!(
+            patches,
+            vec![
+                patch(9, 0),
+                RouterPatch::parked(1),
+                patch(2, 1),
+                patch(5, 1),
+                patch(0, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn parked_outputs() {
+        let mi = RouterMatrixInfo {
+            input_count: 2,
+            output_count: 2,
+            frame_count: 0,
+            name: None,
+        };
+        assert!(mi.check_patches(0, &[RouterPatch::parked(1)]).is_ok());
+        let err = mi.check_patches(0, &[RouterPatch::parked(2)]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Patch of no input to output 2 out of range for matrix 0"
+        );
+        assert!(videohub::Route::try_from(RouterPatch::parked(1)).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn parked_outputs_in_json() {
+        let parked: RouterPatch =
+            serde_json::from_value(serde_json::json!({"from_input": null, "to_output": 1}))
+                .unwrap();
+        assert!(parked.is_parked());
+        assert_eq!(
+            serde_json::to_value(parked).unwrap(),
+            serde_json::json!({"from_input": null, "to_output": 1})
+        );
+    }
+
+    #[test]
+    fn label_out_of_range_message() {
+        let mi = RouterMatrixInfo {
+            input_count: 16,
+            output_count: 4,
+            frame_count: 0,
+            name: None,
+        };
+        let err = mi
+            .check_labels(0, LabelKind::Input, &[label(3, "A"), label(17, "B")])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            RouterError::LabelOutOfRange {
+                index: 0,
+                kind: LabelKind::Input,
+                id: 17,
+                max: Some(15)
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Input 17 does not exist in matrix 0 (max: 15)"
+        );
+        let err = mi
+            .check_labels(0, LabelKind::Frame, &[label(0, "A")])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Frame 0 does not exist in matrix 0, it has none"
+        );
+        assert!(mi
+            .check_labels(0, LabelKind::Output, &[label(3, "A")])
+            .is_ok());
+    }
+
+    #[test]
+    fn label_from_str() {
+        assert_eq!(
+            RouterLabel::try_from("0 Camera 1").unwrap(),
+            label(0, "Camera 1")
+        );
+        assert_eq!(RouterLabel::try_from("7  Cam").unwrap(), label(7, " Cam"));
+        assert_eq!(
+            RouterLabel::try_from("3 Studio: A").unwrap(),
+            label(3, "Studio: A")
+        );
+        assert_eq!(RouterLabel::try_from("4 ").unwrap(), label(4, ""));
+        for bad in ["", "0", "Camera 1", "-1 Cam", " 0 Cam", "4294967296 Cam"] {
+            assert!(RouterLabel::try_from(bad).is_err(), "{:?}", bad);
+        }
+        assert_eq!(String::from(label(0, "Camera 1")), "0 Camera 1");
+    }
+
+    proptest! {
+        #[test]
+        fn label_round_trip(id: u32, name in "[^\r\n]*") {
+            let l = RouterLabel { id, name };
+            prop_assert_eq!(RouterLabel::try_from(String::from(l.clone()).as_str()).unwrap(), l);
         }
     }
 }
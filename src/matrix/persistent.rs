@@ -0,0 +1,617 @@
+//! Routing state surviving restarts, for any router
+//!
+//! Wraps a [MatrixRouter], writing its labels and routes to a JSON snapshot whenever events
+//! announce a change, and replaying that snapshot into it on startup. Meant for routers which
+//! forget everything when restarted, like the NDI one. Everything passes through.
+
+use super::*;
+use anyhow::Result;
+use futures_core::stream::BoxStream;
+use futures_util::FutureExt;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{oneshot, Mutex};
+use tokio_stream::StreamExt;
+use tracing::{debug, error, warn};
+
+/// Labels and routes of a single matrix, as written to disk by [PersistentRouter].
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct MatrixSnapshot {
+    index: u32,
+    input_labels: Vec<RouterLabel>,
+    output_labels: Vec<RouterLabel>,
+    routes: Vec<RouterPatch>,
+}
+
+/// Labels and routes of every matrix of `router`.
+async fn snapshot(router: &impl MatrixRouter) -> Result<Vec<MatrixSnapshot>> {
+    let matrix_count = router.get_router_info().await?.matrix_count.unwrap_or(1);
+    let mut matrices = Vec::new();
+    for index in 0..matrix_count {
+        let (input_labels, output_labels, routes) = tokio::try_join!(
+            router.get_input_labels(index),
+            router.get_output_labels(index),
+            router.get_routes(index)
+        )?;
+        matrices.push(MatrixSnapshot {
+            index,
+            input_labels,
+            output_labels,
+            routes,
+        });
+    }
+    Ok(matrices)
+}
+
+/// Apply `entries` through `update`, one at a time if the router refuses them all at once.
+/// Returns how many it refused.
+async fn replay<T, F, Fut>(entries: Vec<T>, update: F) -> u64
+where
+    T: Clone,
+    F: Fn(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    if entries.is_empty() || update(entries.clone()).await.is_ok() {
+        return 0;
+    }
+    let mut refused = 0;
+    for entry in entries {
+        if let Err(e) = update(vec![entry]).await {
+            debug!("Router refused persisted entry: {:#}", e);
+            refused += 1;
+        }
+    }
+    refused
+}
+
+/// Replay `matrices` into `router`, returning how many entries it refused.
+async fn restore(router: &impl MatrixRouter, matrices: Vec<MatrixSnapshot>) -> u64 {
+    let mut refused = 0;
+    for m in matrices {
+        let index = m.index;
+        refused += replay(m.input_labels, move |ls| {
+            router.update_input_labels(index, ls)
+        })
+        .await;
+        refused += replay(m.output_labels, move |ls| {
+            router.update_output_labels(index, ls)
+        })
+        .await;
+        refused += replay(m.routes, move |ps| router.update_routes(index, ps)).await;
+    }
+    refused
+}
+
+/// Where snapshots get written, shared by the writer and all clones of a [PersistentRouter].
+#[derive(Clone)]
+struct Store {
+    path: Arc<PathBuf>,
+    /// Number of snapshots written.
+    written: Arc<AtomicU64>,
+    /// Held while writing, so snapshots go out one after the other.
+    writing: Arc<Mutex<()>>,
+}
+
+impl Store {
+    /// Write a snapshot of `inner`.
+    async fn write(&self, inner: &impl MatrixRouter) -> Result<()> {
+        let _writing = self.writing.lock().await;
+        let matrices = snapshot(inner).await?;
+        crate::persist::store_async(&self.path, &matrices).await?;
+        self.written.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Write a snapshot of `inner`, logging failure.
+    async fn write_logged(&self, inner: &impl MatrixRouter) {
+        if let Err(e) = self.write(inner).await {
+            error!(path = ?self.path, "Failed to persist routing state: {:#}", e);
+        }
+    }
+}
+
+/// Whether `ev` announces a change to persist.
+fn is_change(ev: RouterEvent) -> bool {
+    matches!(
+        ev.into_update(),
+        RouterEvent::InputLabelUpdate(..)
+            | RouterEvent::OutputLabelUpdate(..)
+            | RouterEvent::RouteUpdate(..)
+    )
+}
+
+/// Write a snapshot of `inner` once events announce changes, at most once every `debounce`,
+/// signalling `ready` once subscribed for the first time.
+///
+/// Once `closed` fires, changes waiting for the debounce or already announced get written
+/// right away before returning.
+async fn write_back(
+    inner: impl MatrixRouter,
+    store: Store,
+    debounce: Duration,
+    ready: oneshot::Sender<()>,
+    mut closed: oneshot::Receiver<()>,
+) {
+    let mut ready = Some(ready);
+    loop {
+        match inner.event_stream().await {
+            Ok(mut events) => {
+                if let Some(ready) = ready.take() {
+                    let _ = ready.send(());
+                }
+                loop {
+                    let ev = tokio::select! {
+                        ev = events.next() => ev,
+                        _ = &mut closed => {
+                            // Pick up changes announced just before.
+                            let mut pending = false;
+                            while let Some(Some(ev)) = events.next().now_or_never() {
+                                pending |= is_change(ev);
+                            }
+                            if pending {
+                                store.write_logged(&inner).await;
+                            }
+                            return;
+                        }
+                    };
+                    let Some(ev) = ev else { break };
+                    if !is_change(ev) {
+                        continue;
+                    }
+                    // Let a burst of changes settle, they all end up in the same snapshot.
+                    let settle = async { while events.next().await.is_some() {} };
+                    let closing = tokio::select! {
+                        _ = tokio::time::timeout(debounce, settle) => false,
+                        _ = &mut closed => true,
+                    };
+                    store.write_logged(&inner).await;
+                    if closing {
+                        return;
+                    }
+                }
+                debug!("Event stream ended, changes go unpersisted until it is back");
+            }
+            Err(e) => error!("No event stream to persist changes from: {:#}", e),
+        }
+        // Don't hold up construction for a router without events.
+        if let Some(ready) = ready.take() {
+            let _ = ready.send(());
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(debounce) => {}
+            _ = &mut closed => return,
+        }
+    }
+}
+
+/// Tells the writer to finish up once the last clone of a [PersistentRouter] is gone.
+struct Writer {
+    _closed: oneshot::Sender<()>,
+}
+
+/// Router wrapper persisting the labels and routes of `R`, see the module docs.
+#[derive(Clone)]
+pub struct PersistentRouter<R> {
+    inner: R,
+    store: Store,
+    debounce: Duration,
+    /// Number of persisted entries `R` refused on startup.
+    refused: Arc<AtomicU64>,
+    _writer: Arc<Writer>,
+}
+
+impl<R> PersistentRouter<R>
+where
+    R: MatrixRouter + Clone + 'static,
+{
+    /// Replay the snapshot at `path` into `inner`, if there is one, then keep it up to date.
+    ///
+    /// Entries `inner` refuses, like labels of ports it doesn't have anymore, are skipped.
+    /// Changes are written `debounce` after the first of them, along with any that followed
+    /// meanwhile. Writes happen in a task of their own, so this must be called within a Tokio
+    /// runtime. Once the last clone is dropped, that task writes what's left and ends. To be
+    /// sure everything is on disk before the runtime goes away, call [Self::flush].
+    pub async fn new(inner: R, path: PathBuf, debounce: Duration) -> Result<Self> {
+        let mut refused = 0;
+        if let Some(matrices) = crate::persist::load::<Vec<MatrixSnapshot>>(&path)? {
+            refused = restore(&inner, matrices).await;
+            if refused > 0 {
+                warn!(?path, refused, "Router refused some persisted entries");
+            }
+            debug!(?path, "Restored persisted routing state");
+        }
+        let store = Store {
+            path: Arc::new(path),
+            written: Arc::new(AtomicU64::new(0)),
+            writing: Arc::new(Mutex::new(())),
+        };
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let (closed_tx, closed_rx) = oneshot::channel();
+        tokio::spawn(write_back(
+            inner.clone(),
+            store.clone(),
+            debounce,
+            ready_tx,
+            closed_rx,
+        ));
+        let _ = ready_rx.await;
+        Ok(Self {
+            inner,
+            store,
+            debounce,
+            refused: Arc::new(AtomicU64::new(refused)),
+            _writer: Arc::new(Writer { _closed: closed_tx }),
+        })
+    }
+}
+
+impl<R: MatrixRouter> PersistentRouter<R> {
+    /// The wrapped router.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Write a snapshot right away, without waiting for the debounce.
+    ///
+    /// Meant for shutting down, so no change gets lost.
+    pub async fn flush(&self) -> Result<()> {
+        self.store.write(&self.inner).await
+    }
+}
+
+impl<R: MatrixRouter> MatrixRouter for PersistentRouter<R> {
+    async fn is_alive(&self) -> Result<bool> {
+        self.inner.is_alive().await
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.inner.get_router_info().await
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        self.inner.get_matrix_info(index).await
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_input_labels(index).await
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_output_labels(index).await
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_input_labels(index, changed).await
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_output_labels(index, changed).await
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_input_descriptions(index).await
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_output_descriptions(index).await
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_input_descriptions(index, changed).await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_output_descriptions(index, changed).await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_input_status(index).await
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_output_status(index).await
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_routes(index).await
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        self.inner.get_route(index, output).await
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        self.inner.get_route_for_input(index, input).await
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.inner.update_routes(index, changes).await
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.inner.update_routes_atomic(index, changes).await
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.inner.get_levels(index).await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_level_routes(index, level).await
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        self.inner.update_level_routes(index, level, changes).await
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        self.inner.stage_routes(index, changes).await
+    }
+
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.commit(handle).await
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.discard(handle).await
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_staged_routes(index).await
+    }
+
+    async fn get_frame_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_frame_labels(index).await
+    }
+
+    async fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_frame_labels(index, changed).await
+    }
+
+    async fn get_frame_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_frame_routes(index).await
+    }
+
+    async fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.inner.update_frame_routes(index, changes).await
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_locks(index).await
+    }
+
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.inner.update_locks(index, changes).await
+    }
+
+    /// Persisted along with this handle, if `R` takes locks on behalf of owners.
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        Some(Self {
+            inner: self.inner.with_lock_owner(owner)?,
+            store: self.store.clone(),
+            debounce: self.debounce,
+            refused: self.refused.clone(),
+            _writer: self._writer.clone(),
+        })
+    }
+
+    async fn get_frame_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_frame_locks(index).await
+    }
+
+    async fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.inner.update_frame_locks(index, changes).await
+    }
+
+    async fn get_processing_unit_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_processing_unit_locks(index).await
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        self.inner
+            .update_processing_unit_locks(index, changes)
+            .await
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        self.inner.get_port_metadata(index, kind, id).await
+    }
+
+    async fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> Result<()> {
+        self.inner
+            .set_port_metadata(index, kind, id, metadata)
+            .await
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        self.inner.get_alarms().await
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        self.inner.event_stream().await
+    }
+}
+
+impl<R: RouterIntrospect> RouterIntrospect for PersistentRouter<R> {
+    fn name(&self) -> &'static str {
+        "PersistentRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        vec![
+            ("path".into(), self.store.path.display().to_string()),
+            ("debounce".into(), format!("{:?}", self.debounce)),
+        ]
+    }
+
+    fn counters(&self) -> Vec<(String, u64)> {
+        vec![
+            ("written".into(), self.store.written.load(Ordering::Relaxed)),
+            ("refused".into(), self.refused.load(Ordering::Relaxed)),
+        ]
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        Some(&self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEBOUNCE: Duration = Duration::from_millis(20);
+
+    fn label(id: u32, name: &str) -> RouterLabel {
+        RouterLabel {
+            id,
+            name: name.into(),
+        }
+    }
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    fn load(path: &std::path::Path) -> Result<Option<Vec<MatrixSnapshot>>> {
+        crate::persist::load(path)
+    }
+
+    /// Wait for the snapshot at `path` to satisfy `done`.
+    async fn written(
+        path: &std::path::Path,
+        done: impl Fn(&[MatrixSnapshot]) -> bool,
+    ) -> Result<()> {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !load(path).ok().flatten().is_some_and(|m| done(&m)) {
+                tokio::time::sleep(DEBOUNCE).await;
+            }
+        })
+        .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn restores_after_restart() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("routing.json");
+        {
+            let router =
+                PersistentRouter::new(DummyRouter::with_config(2, 4, 4), path.clone(), DEBOUNCE)
+                    .await?;
+            router.update_routes(1, vec![patch(3, 2)]).await?;
+            router
+                .update_output_labels(1, vec![label(2, "Program")])
+                .await?;
+            router
+                .update_input_labels(0, vec![label(0, "Camera 1")])
+                .await?;
+            written(&path, |m| {
+                m[1].routes.contains(&patch(3, 2))
+                    && m[1].output_labels[2].name == "Program"
+                    && m[0].input_labels[0].name == "Camera 1"
+            })
+            .await?;
+        }
+
+        let dummy = DummyRouter::with_config(2, 4, 4);
+        let router = PersistentRouter::new(dummy.clone(), path.clone(), DEBOUNCE).await?;
+        assert!(dummy.get_routes(1).await?.contains(&patch(3, 2)));
+        assert_eq!(dummy.get_output_labels(1).await?[2].name, "Program");
+        assert_eq!(router.get_input_labels(0).await?[0].name, "Camera 1");
+        assert_eq!(router.refused.load(Ordering::Relaxed), 0);
+        // Nothing left behind from writing.
+        assert!(!dir.path().join("routing.json.tmp").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skips_refused_entries() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("routing.json");
+        {
+            let router =
+                PersistentRouter::new(DummyRouter::with_config(1, 4, 4), path.clone(), DEBOUNCE)
+                    .await?;
+            router
+                .update_routes(0, vec![patch(1, 0), patch(3, 3)])
+                .await?;
+            written(&path, |m| m[0].routes.contains(&patch(3, 3))).await?;
+        }
+
+        // The router shrank meanwhile, what still fits is restored.
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let router = PersistentRouter::new(dummy.clone(), path, DEBOUNCE).await?;
+        assert!(dummy.get_routes(0).await?.contains(&patch(1, 0)));
+        // Labels of inputs and outputs 2 and 3, and the routes to outputs 2 and 3.
+        assert_eq!(
+            describe_router(&router)[0].counters,
+            vec![("written".to_string(), 0), ("refused".to_string(), 6)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn writes_pending_changes_once_dropped() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("routing.json");
+        // Far longer than the test may take.
+        let debounce = Duration::from_secs(60);
+        let router =
+            PersistentRouter::new(DummyRouter::with_config(1, 4, 4), path.clone(), debounce)
+                .await?;
+        router.update_routes(0, vec![patch(3, 2)]).await?;
+        drop(router);
+        written(&path, |m| m[0].routes.contains(&patch(3, 2))).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn flush_writes_right_away() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("routing.json");
+        let router = PersistentRouter::new(
+            DummyRouter::with_config(1, 4, 4),
+            path.clone(),
+            Duration::from_secs(60),
+        )
+        .await?;
+        router.update_routes(0, vec![patch(3, 2)]).await?;
+        router.flush().await?;
+        assert!(load(&path)?.unwrap()[0].routes.contains(&patch(3, 2)));
+        Ok(())
+    }
+}
@@ -0,0 +1,641 @@
+//! Update rate limiting for any router
+//!
+//! Wraps a [MatrixRouter], holding route and label updates to a configured rate so a
+//! misbehaving client can't flood slow hardware with them. Everything else passes through.
+
+use super::*;
+use anyhow::{anyhow, Result};
+use futures_core::stream::BoxStream;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// What a [RateLimitedRouter] does with updates beyond its [RateLimit].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RateLimitPolicy {
+    /// Refuse them with [RouterError::RateLimited], clients are expected to retry.
+    #[default]
+    Reject,
+    /// Wait until the budget allows them.
+    Delay,
+    /// Wait as well, merging route updates of a matrix meanwhile. Patches to the same output
+    /// replace earlier ones, so only the latest reaches the router. Other updates are delayed.
+    Coalesce,
+}
+
+/// Rate limit of a [RateLimitedRouter].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RateLimit {
+    /// Updates allowed per second, at least one.
+    pub max_updates_per_second: u32,
+    /// Updates allowed at once, `0` for as many as per second.
+    pub burst: u32,
+    /// Whether every matrix gets its own budget instead of sharing one.
+    pub per_matrix: bool,
+    pub policy: RateLimitPolicy,
+}
+
+/// Outcome of applying a [Batch], shared with everyone whose patches it holds.
+type Outcome = Option<Result<(), Arc<anyhow::Error>>>;
+
+/// Route patches of a matrix waiting for budget under [RateLimitPolicy::Coalesce].
+struct Batch {
+    patches: Vec<RouterPatch>,
+    outcome: watch::Receiver<Outcome>,
+}
+
+/// What a coalesced route update turns out to be.
+enum Role {
+    /// Within budget, applied right away.
+    Direct(Vec<RouterPatch>),
+    /// First over budget, applying the batch once budget allows.
+    Leader(watch::Sender<Outcome>),
+    /// Merged into a waiting batch.
+    Follower(watch::Receiver<Outcome>),
+}
+
+/// Replace patches in `patches` to the same outputs as `changes`, adding the rest.
+fn merge_patches(patches: &mut Vec<RouterPatch>, changes: Vec<RouterPatch>) {
+    for c in changes {
+        match patches.iter_mut().find(|p| p.to_output == c.to_output) {
+            Some(p) => *p = c,
+            None => patches.push(c),
+        }
+    }
+}
+
+/// A copy of a shared error, still telling [RouterError]s apart.
+fn shared_error(e: &anyhow::Error) -> anyhow::Error {
+    match e.downcast_ref::<RouterError>() {
+        Some(re) => re.clone().into(),
+        None => anyhow!("{:#}", e),
+    }
+}
+
+/// Router wrapper holding route and label updates to `R` to a [RateLimit].
+///
+/// Staging routes counts as an update, committing them doesn't.
+#[derive(Clone)]
+pub struct RateLimitedRouter<R> {
+    inner: R,
+    limit: RateLimit,
+    /// Full budget handed to every matrix, or to all of them together.
+    budget: Budget,
+    /// Budgets by matrix index, all under `None` unless limiting per matrix.
+    budgets: Arc<Mutex<HashMap<Option<u32>, Budget>>>,
+    /// Route updates waiting to be coalesced, by matrix index.
+    batches: Arc<Mutex<HashMap<u32, Batch>>>,
+    /// Number of updates beyond the limit, refused or not, and of those merged into others.
+    limited: Arc<AtomicU64>,
+    coalesced: Arc<AtomicU64>,
+}
+
+impl<R: MatrixRouter> RateLimitedRouter<R> {
+    /// Limit updates to `inner`, failing if `limit` doesn't allow any.
+    pub fn new(inner: R, limit: RateLimit) -> Result<Self> {
+        let burst = match limit.burst {
+            0 => limit.max_updates_per_second,
+            burst => burst,
+        };
+        let budget = Budget::new(f64::from(limit.max_updates_per_second), burst)?;
+        Ok(Self {
+            inner,
+            limit,
+            budget,
+            budgets: Arc::new(Mutex::new(HashMap::new())),
+            batches: Arc::new(Mutex::new(HashMap::new())),
+            limited: Arc::new(AtomicU64::new(0)),
+            coalesced: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// The wrapped router.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Take an update of matrix `index` out of its budget, or tell how long until it allows one.
+    fn try_take(&self, index: u32) -> Result<(), Duration> {
+        let key = self.limit.per_matrix.then_some(index);
+        let now = Instant::now();
+        let mut budgets = self.budgets.lock().unwrap();
+        let budget = budgets.entry(key).or_insert_with(|| self.budget.clone());
+        if budget.try_take(now) {
+            return Ok(());
+        }
+        Err(budget.wait_time(now))
+    }
+
+    /// Wait until the budget of matrix `index` allows another update.
+    async fn wait_for_budget(&self, index: u32) {
+        while let Err(wait) = self.try_take(index) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take an update of matrix `index` out of its budget, refusing or waiting as configured.
+    async fn take(&self, index: u32) -> Result<()> {
+        let Err(retry_after) = self.try_take(index) else {
+            return Ok(());
+        };
+        self.limited.fetch_add(1, Ordering::Relaxed);
+        if self.limit.policy == RateLimitPolicy::Reject {
+            return Err(RouterError::RateLimited { retry_after }.into());
+        }
+        tokio::time::sleep(retry_after).await;
+        self.wait_for_budget(index).await;
+        Ok(())
+    }
+
+    /// Apply `changes` to matrix `index` right away if the budget allows, or along with
+    /// others once it does, see [RateLimitPolicy::Coalesce].
+    ///
+    /// Patches are checked before joining a batch, so an invalid one fails only its caller
+    /// instead of the whole batch.
+    async fn update_routes_coalesced(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let mi = self.inner.get_matrix_info(index).await?;
+        mi.check_patches(index, &changes)?;
+        let role = {
+            let mut batches = self.batches.lock().unwrap();
+            // A batch whose leader gave up waiting is never going to be applied.
+            batches.retain(|_, b| b.outcome.has_changed().is_ok());
+            if let Some(batch) = batches.get_mut(&index) {
+                merge_patches(&mut batch.patches, changes);
+                Role::Follower(batch.outcome.clone())
+            } else if self.try_take(index).is_ok() {
+                Role::Direct(changes)
+            } else {
+                let (tx, outcome) = watch::channel(None);
+                batches.insert(
+                    index,
+                    Batch {
+                        patches: changes,
+                        outcome,
+                    },
+                );
+                Role::Leader(tx)
+            }
+        };
+
+        match role {
+            Role::Direct(changes) => self.inner.update_routes(index, changes).await,
+            Role::Follower(mut outcome) => {
+                self.limited.fetch_add(1, Ordering::Relaxed);
+                self.coalesced.fetch_add(1, Ordering::Relaxed);
+                let outcome = outcome.wait_for(Option::is_some).await.map(|o| o.clone());
+                match outcome {
+                    Ok(Some(Ok(()))) => Ok(()),
+                    Ok(Some(Err(e))) => Err(shared_error(&e)),
+                    _ => Err(anyhow!("Coalesced route update was abandoned")),
+                }
+            }
+            Role::Leader(tx) => {
+                self.limited.fetch_add(1, Ordering::Relaxed);
+                self.wait_for_budget(index).await;
+                let patches = self
+                    .batches
+                    .lock()
+                    .unwrap()
+                    .remove(&index)
+                    .map(|b| b.patches);
+                let res = self
+                    .inner
+                    .update_routes(index, patches.unwrap_or_default())
+                    .await
+                    .map_err(Arc::new);
+                let _ = tx.send(Some(res.clone()));
+                res.map_err(|e| shared_error(&e))
+            }
+        }
+    }
+}
+
+impl<R: MatrixRouter> MatrixRouter for RateLimitedRouter<R> {
+    async fn is_alive(&self) -> Result<bool> {
+        self.inner.is_alive().await
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.inner.get_router_info().await
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        self.inner.get_matrix_info(index).await
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_input_labels(index).await
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_output_labels(index).await
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.take(index).await?;
+        self.inner.update_input_labels(index, changed).await
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.take(index).await?;
+        self.inner.update_output_labels(index, changed).await
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_input_descriptions(index).await
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_output_descriptions(index).await
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.take(index).await?;
+        self.inner.update_input_descriptions(index, changed).await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.take(index).await?;
+        self.inner.update_output_descriptions(index, changed).await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_input_status(index).await
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_output_status(index).await
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_routes(index).await
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        self.inner.get_route(index, output).await
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        self.inner.get_route_for_input(index, input).await
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        if self.limit.policy == RateLimitPolicy::Coalesce {
+            return self.update_routes_coalesced(index, changes).await;
+        }
+        self.take(index).await?;
+        self.inner.update_routes(index, changes).await
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.take(index).await?;
+        self.inner.update_routes_atomic(index, changes).await
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.inner.get_levels(index).await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_level_routes(index, level).await
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        self.take(index).await?;
+        self.inner.update_level_routes(index, level, changes).await
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        self.take(index).await?;
+        self.inner.stage_routes(index, changes).await
+    }
+
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.commit(handle).await
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.discard(handle).await
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_staged_routes(index).await
+    }
+
+    async fn get_frame_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_frame_labels(index).await
+    }
+
+    async fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_frame_labels(index, changed).await
+    }
+
+    async fn get_frame_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_frame_routes(index).await
+    }
+
+    async fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.inner.update_frame_routes(index, changes).await
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_locks(index).await
+    }
+
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.inner.update_locks(index, changes).await
+    }
+
+    /// Sharing the budgets, if `R` takes locks on behalf of owners.
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        Some(Self {
+            inner: self.inner.with_lock_owner(owner)?,
+            limit: self.limit,
+            budget: self.budget.clone(),
+            budgets: self.budgets.clone(),
+            batches: self.batches.clone(),
+            limited: self.limited.clone(),
+            coalesced: self.coalesced.clone(),
+        })
+    }
+
+    async fn get_frame_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_frame_locks(index).await
+    }
+
+    async fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.inner.update_frame_locks(index, changes).await
+    }
+
+    async fn get_processing_unit_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_processing_unit_locks(index).await
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        self.inner
+            .update_processing_unit_locks(index, changes)
+            .await
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        self.inner.get_port_metadata(index, kind, id).await
+    }
+
+    async fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> Result<()> {
+        self.inner
+            .set_port_metadata(index, kind, id, metadata)
+            .await
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        self.inner.get_alarms().await
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        self.inner.event_stream().await
+    }
+}
+
+impl<R: RouterIntrospect> RouterIntrospect for RateLimitedRouter<R> {
+    fn name(&self) -> &'static str {
+        "RateLimitedRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        vec![
+            (
+                "max_updates_per_second".into(),
+                self.limit.max_updates_per_second.to_string(),
+            ),
+            ("burst".into(), self.limit.burst.to_string()),
+            ("per_matrix".into(), self.limit.per_matrix.to_string()),
+            ("policy".into(), format!("{:?}", self.limit.policy)),
+        ]
+    }
+
+    fn counters(&self) -> Vec<(String, u64)> {
+        vec![
+            ("limited".into(), self.limited.load(Ordering::Relaxed)),
+            ("coalesced".into(), self.coalesced.load(Ordering::Relaxed)),
+        ]
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        Some(&self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::StreamExt;
+
+    fn patch(from_input: u32) -> Vec<RouterPatch> {
+        vec![RouterPatch {
+            from_input: Some(from_input),
+            to_output: 0,
+        }]
+    }
+
+    fn limit(per_matrix: bool) -> RateLimit {
+        RateLimit {
+            max_updates_per_second: 10,
+            per_matrix,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn requires_positive_rate() {
+        let router = RateLimitedRouter::new(DummyRouter::new(), RateLimit::default());
+        assert!(router.is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_over_limit() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let router = RateLimitedRouter::new(dummy.clone(), limit(false))?;
+        let mut results = Vec::new();
+        for n in 0..100 {
+            results.push(router.update_routes(0, patch(n % 2)).await);
+        }
+        assert!(results[..10].iter().all(|r| r.is_ok()));
+        for err in results[10..].iter().map(|r| r.as_ref().unwrap_err()) {
+            match err.downcast_ref::<RouterError>() {
+                Some(RouterError::RateLimited { retry_after }) => {
+                    assert!(*retry_after > Duration::ZERO);
+                    assert!(*retry_after <= Duration::from_millis(100));
+                }
+                other => panic!("unexpected error {:?}", other),
+            }
+        }
+        // Refused updates never reach the router.
+        assert_eq!(dummy.get_routes(0).await?[0].from_input, Some(1));
+        assert!(router.update_routes_atomic(0, patch(0)).await.is_err());
+        // Label updates count as well.
+        let label = RouterLabel {
+            id: 0,
+            name: "Program".into(),
+        };
+        assert!(router
+            .update_output_labels(0, vec![label.clone()])
+            .await
+            .is_err());
+        assert_eq!(
+            describe_router(&router)[0].counters,
+            vec![("limited".to_string(), 92), ("coalesced".to_string(), 0)]
+        );
+
+        // Reads pass through regardless.
+        assert_eq!(router.get_output_labels(0).await?[0].name, "Output 1");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delays_over_limit() -> Result<()> {
+        let limit = RateLimit {
+            max_updates_per_second: 50,
+            burst: 1,
+            policy: RateLimitPolicy::Delay,
+            ..Default::default()
+        };
+        let router = RateLimitedRouter::new(DummyRouter::with_config(1, 2, 2), limit)?;
+        let started = Instant::now();
+        router.update_routes(0, patch(1)).await?;
+        router.update_routes(0, patch(0)).await?;
+        let label = RouterLabel {
+            id: 1,
+            name: "Program".into(),
+        };
+        router.update_output_labels(0, vec![label]).await?;
+        // One update every 20ms after the first.
+        assert!(started.elapsed() >= Duration::from_millis(35));
+        assert_eq!(router.get_output_labels(0).await?[1].name, "Program");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn coalesces_patches_to_same_output() -> Result<()> {
+        let limit = RateLimit {
+            max_updates_per_second: 10,
+            burst: 1,
+            policy: RateLimitPolicy::Coalesce,
+            ..Default::default()
+        };
+        let dummy = DummyRouter::with_config(1, 100, 4);
+        let router = RateLimitedRouter::new(dummy.clone(), limit)?;
+        // Use up the budget.
+        router.update_routes(0, patch(1)).await?;
+
+        let mut events = dummy.event_stream().await?;
+        let updates = (0..100).map(|n| {
+            router.update_routes(
+                0,
+                vec![RouterPatch {
+                    from_input: Some(n),
+                    to_output: 3,
+                }],
+            )
+        });
+        for res in futures_util::future::join_all(updates).await {
+            res?;
+        }
+        assert_eq!(dummy.get_routes(0).await?[3].from_input, Some(99));
+        assert_eq!(dummy.get_routes(0).await?[0].from_input, Some(1));
+
+        // All of them reached the router at once.
+        let mut route_updates = 0;
+        while let Ok(Some(ev)) =
+            tokio::time::timeout(Duration::from_millis(50), events.next()).await
+        {
+            if matches!(ev, RouterEvent::RouteUpdate(..)) {
+                route_updates += 1;
+            }
+        }
+        assert_eq!(route_updates, 1);
+        assert_eq!(
+            describe_router(&router)[0].counters,
+            vec![("limited".to_string(), 100), ("coalesced".to_string(), 99)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_patches_fail_alone() -> Result<()> {
+        let limit = RateLimit {
+            max_updates_per_second: 10,
+            burst: 1,
+            policy: RateLimitPolicy::Coalesce,
+            ..Default::default()
+        };
+        let dummy = DummyRouter::with_config(1, 4, 4);
+        let router = RateLimitedRouter::new(dummy.clone(), limit)?;
+        router.update_routes(0, patch(1)).await?;
+
+        let valid = router.update_routes(
+            0,
+            vec![RouterPatch {
+                from_input: Some(2),
+                to_output: 3,
+            }],
+        );
+        let invalid = router.update_routes(
+            0,
+            vec![RouterPatch {
+                from_input: Some(9),
+                to_output: 2,
+            }],
+        );
+        let (valid, invalid) = tokio::join!(valid, invalid);
+        valid?;
+        assert!(matches!(
+            invalid.unwrap_err().downcast_ref::<RouterError>(),
+            Some(RouterError::OutOfRange { .. })
+        ));
+        assert_eq!(dummy.get_routes(0).await?[3].from_input, Some(2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn per_matrix_budgets() -> Result<()> {
+        let shared = RateLimitedRouter::new(DummyRouter::with_config(2, 2, 2), limit(false))?;
+        let separate = RateLimitedRouter::new(DummyRouter::with_config(2, 2, 2), limit(true))?;
+        for _ in 0..10 {
+            shared.update_routes(0, patch(1)).await?;
+            separate.update_routes(0, patch(1)).await?;
+        }
+        assert!(shared.update_routes(1, patch(1)).await.is_err());
+        separate.update_routes(1, patch(1)).await?;
+        assert!(separate.update_routes(0, patch(1)).await.is_err());
+        Ok(())
+    }
+}
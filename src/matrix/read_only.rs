@@ -0,0 +1,315 @@
+//! Read-only access to any router
+//!
+//! Wraps a [MatrixRouter], refusing every change with [RouterError::PermissionDenied] so it can
+//! be handed to monitoring clients safely. Reads and events pass through.
+
+use super::*;
+use anyhow::Result;
+use futures_core::stream::BoxStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Router wrapper refusing all changes to `R`.
+///
+/// Labels, routes, locks, staged takes and metadata can all be read but not changed.
+#[derive(Clone)]
+pub struct ReadOnlyRouter<R> {
+    inner: R,
+    /// Number of refused changes.
+    denied: Arc<AtomicU64>,
+}
+
+impl<R: MatrixRouter> ReadOnlyRouter<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            denied: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// The wrapped router.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    fn deny<T>(&self) -> Result<T> {
+        self.denied.fetch_add(1, Ordering::Relaxed);
+        Err(RouterError::PermissionDenied.into())
+    }
+}
+
+impl<R: MatrixRouter> MatrixRouter for ReadOnlyRouter<R> {
+    async fn is_alive(&self) -> Result<bool> {
+        self.inner.is_alive().await
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.inner.get_router_info().await
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        self.inner.get_matrix_info(index).await
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_input_labels(index).await
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_output_labels(index).await
+    }
+
+    async fn update_input_labels(&self, _index: u32, _changed: Vec<RouterLabel>) -> Result<()> {
+        self.deny()
+    }
+
+    async fn update_output_labels(&self, _index: u32, _changed: Vec<RouterLabel>) -> Result<()> {
+        self.deny()
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_input_descriptions(index).await
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_output_descriptions(index).await
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        _index: u32,
+        _changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.deny()
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        _index: u32,
+        _changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.deny()
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_input_status(index).await
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_output_status(index).await
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_routes(index).await
+    }
+
+    async fn get_route_for_output(&self, index: u32, output: u32) -> Result<Option<RouterPatch>> {
+        self.inner.get_route_for_output(index, output).await
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        self.inner.get_route(index, output).await
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        self.inner.get_route_for_input(index, input).await
+    }
+
+    async fn update_routes(&self, _index: u32, _changes: Vec<RouterPatch>) -> Result<()> {
+        self.deny()
+    }
+
+    async fn update_routes_atomic(&self, _index: u32, _changes: Vec<RouterPatch>) -> Result<()> {
+        self.deny()
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.inner.get_levels(index).await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_level_routes(index, level).await
+    }
+
+    async fn update_level_routes(
+        &self,
+        _index: u32,
+        _level: u32,
+        _changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        self.deny()
+    }
+
+    async fn stage_routes(&self, _index: u32, _changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        self.deny()
+    }
+
+    async fn commit(&self, _handle: TakeHandle) -> Result<()> {
+        self.deny()
+    }
+
+    async fn discard(&self, _handle: TakeHandle) -> Result<()> {
+        self.deny()
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_staged_routes(index).await
+    }
+
+    async fn get_frame_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_frame_labels(index).await
+    }
+
+    async fn update_frame_labels(&self, _index: u32, _changed: Vec<RouterLabel>) -> Result<()> {
+        self.deny()
+    }
+
+    async fn get_frame_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_frame_routes(index).await
+    }
+
+    async fn update_frame_routes(&self, _index: u32, _changes: Vec<RouterPatch>) -> Result<()> {
+        self.deny()
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_locks(index).await
+    }
+
+    async fn update_locks(&self, _index: u32, _changes: Vec<RouterLock>) -> Result<()> {
+        self.deny()
+    }
+
+    async fn get_frame_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_frame_locks(index).await
+    }
+
+    async fn update_frame_locks(&self, _index: u32, _changes: Vec<RouterLock>) -> Result<()> {
+        self.deny()
+    }
+
+    async fn get_processing_unit_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_processing_unit_locks(index).await
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        _index: u32,
+        _changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        self.deny()
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        self.inner.get_port_metadata(index, kind, id).await
+    }
+
+    async fn set_port_metadata(
+        &self,
+        _index: u32,
+        _kind: PortKind,
+        _id: u32,
+        _metadata: PortMetadata,
+    ) -> Result<()> {
+        self.deny()
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        self.inner.get_alarms().await
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        RouterCapabilities {
+            can_update_input_labels: false,
+            can_update_output_labels: false,
+            can_lock_outputs: false,
+            ..self.inner.capabilities()
+        }
+    }
+
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        self.inner.event_stream().await
+    }
+}
+
+impl<R: RouterIntrospect> RouterIntrospect for ReadOnlyRouter<R> {
+    fn name(&self) -> &'static str {
+        "ReadOnlyRouter"
+    }
+
+    fn counters(&self) -> Vec<(String, u64)> {
+        vec![("denied".into(), self.denied.load(Ordering::Relaxed))]
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        Some(&self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::StreamExt;
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    fn denied(result: Result<()>) -> bool {
+        matches!(
+            result.unwrap_err().downcast_ref::<RouterError>(),
+            Some(RouterError::PermissionDenied)
+        )
+    }
+
+    #[tokio::test]
+    async fn refuses_changes() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let router = ReadOnlyRouter::new(dummy.clone());
+        let caps = router.capabilities();
+        assert!(!caps.can_update_input_labels && !caps.can_update_output_labels);
+        assert!(!caps.can_lock_outputs);
+        let label = RouterLabel {
+            id: 0,
+            name: "Program".into(),
+        };
+        assert!(denied(router.update_routes(0, vec![patch(1, 0)]).await));
+        assert!(denied(
+            router.update_routes_atomic(0, vec![patch(1, 0)]).await
+        ));
+        assert!(denied(
+            router.update_input_labels(0, vec![label.clone()]).await
+        ));
+        assert!(denied(
+            router.update_output_labels(0, vec![label.clone()]).await
+        ));
+        assert!(router.stage_routes(0, vec![patch(1, 0)]).await.is_err());
+
+        // Nothing reached the router.
+        assert_eq!(router.get_routes(0).await?, dummy.get_routes(0).await?);
+        assert_eq!(router.get_route(0, 0).await?, patch(0, 0));
+        assert_eq!(router.get_output_labels(0).await?[0].name, "Output 1");
+        assert_eq!(
+            describe_router(&router)[0].counters,
+            vec![("denied".to_string(), 5)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn events_pass_through() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let router = ReadOnlyRouter::new(dummy.clone());
+        let mut events = router.event_stream().await?;
+        assert_eq!(events.next().await, Some(RouterEvent::Connected));
+        dummy.update_routes(0, vec![patch(1, 1)]).await?;
+        assert_eq!(
+            events.next().await,
+            Some(RouterEvent::RouteUpdate(0, vec![patch(1, 1)]))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,859 @@
+//! Renumbered router
+//!
+//! Wraps a [MatrixRouter], putting its inputs and outputs in another order. Physical wiring
+//! rarely matches the numbering panels and operators expect, the maps bridge the two without
+//! touching either side. Ports left out of the maps can't be seen or touched through it.
+//!
+//! Maps can be given as lists of `logical=physical` lines, one port per line:
+//!
+//! ```text
+//! # Cameras first, then the playout servers
+//! 0=4
+//! 1=5
+//! 2=0
+//! ```
+
+use super::*;
+use anyhow::{anyhow, Result};
+use futures_core::stream::BoxStream;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio_stream::StreamExt;
+
+/// Logical to physical numbering of ports of one kind, and back.
+#[derive(Clone, Debug)]
+struct PortMap {
+    to_physical: Vec<u32>,
+    to_logical: HashMap<u32, u32>,
+}
+
+impl PortMap {
+    /// Check `to_physical` maps no two ports to the same one.
+    fn new(kind: PortKind, to_physical: Vec<u32>) -> Result<Self> {
+        let mut to_logical = HashMap::new();
+        for (logical, &physical) in to_physical.iter().enumerate() {
+            if let Some(other) = to_logical.insert(physical, logical as u32) {
+                return Err(anyhow!(
+                    "{:?} ports {} and {} both map to physical port {}",
+                    kind,
+                    other,
+                    logical,
+                    physical
+                ));
+            }
+        }
+        Ok(Self {
+            to_physical,
+            to_logical,
+        })
+    }
+
+    fn len(&self) -> u32 {
+        self.to_physical.len() as u32
+    }
+
+    fn logical(&self, physical: u32) -> Option<u32> {
+        self.to_logical.get(&physical).copied()
+    }
+
+    /// Physical number of `logical`, which must have been checked to be mapped.
+    fn physical(&self, logical: u32) -> u32 {
+        self.to_physical[logical as usize]
+    }
+}
+
+/// Parse a list of `logical=physical` lines into a map, see the module docs.
+///
+/// Blank lines and lines starting with `#` are skipped. Every logical port from 0 up to the
+/// highest one must be given exactly once.
+fn parse_map(text: &str) -> Result<Vec<u32>> {
+    let mut entries = HashMap::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parse = |field: &str| {
+            field
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| anyhow!("line {}: invalid port {:?}", n + 1, field.trim()))
+        };
+        let (logical, physical) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("line {}: expected logical=physical", n + 1))?;
+        let logical = parse(logical)?;
+        if entries.insert(logical, parse(physical)?).is_some() {
+            return Err(anyhow!("line {}: port {} mapped twice", n + 1, logical));
+        }
+    }
+    (0..entries.len() as u32)
+        .map(|logical| {
+            entries
+                .get(&logical)
+                .copied()
+                .ok_or_else(|| anyhow!("port {} is not mapped", logical))
+        })
+        .collect()
+}
+
+/// Router wrapper putting the inputs and outputs of `R` in another order, see the module docs.
+///
+/// The same maps apply to every matrix. Labels, descriptions, port status, routes of every
+/// level, staged routes, output locks and port metadata are renumbered. Frame buffers and
+/// processing units are hidden, changing them fails with [RouterError::PermissionDenied].
+/// Outputs routed from an input left out of the map have no route.
+///
+/// Only routes staged through the remapped router can be committed or discarded through it.
+#[derive(Clone)]
+pub struct RemapRouter<R> {
+    inner: R,
+    inputs: PortMap,
+    outputs: PortMap,
+    staged: Arc<Mutex<HashSet<TakeHandle>>>,
+}
+
+impl<R: MatrixRouter> RemapRouter<R> {
+    /// Expose `inner` with logical input `i` being its input `input_map[i]`, and likewise
+    /// for outputs.
+    ///
+    /// Fails if a map sends two ports to the same one, or refers to a port some matrix of
+    /// `inner` doesn't have.
+    pub async fn new(inner: R, input_map: Vec<u32>, output_map: Vec<u32>) -> Result<Self> {
+        let inputs = PortMap::new(PortKind::Input, input_map)?;
+        let outputs = PortMap::new(PortKind::Output, output_map)?;
+        let matrix_count = inner.get_router_info().await?.matrix_count.unwrap_or(1);
+        for index in 0..matrix_count {
+            let mi = inner.get_matrix_info(index).await?;
+            for (kind, map, count) in [
+                (PortKind::Input, &inputs, mi.input_count),
+                (PortKind::Output, &outputs, mi.output_count),
+            ] {
+                if let Some((logical, physical)) = map
+                    .to_physical
+                    .iter()
+                    .enumerate()
+                    .find(|(_, &physical)| physical >= count)
+                {
+                    return Err(anyhow!(
+                        "{:?} port {} maps to physical port {}, matrix {} has {}",
+                        kind,
+                        logical,
+                        physical,
+                        index,
+                        count
+                    ));
+                }
+            }
+        }
+        Ok(Self {
+            inner,
+            inputs,
+            outputs,
+            staged: Arc::default(),
+        })
+    }
+
+    /// The wrapped router.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Matrix info of the remapped matrix, given that of the wrapped router.
+    fn remap_info(&self, mi: &RouterMatrixInfo) -> RouterMatrixInfo {
+        RouterMatrixInfo {
+            input_count: self.inputs.len(),
+            output_count: self.outputs.len(),
+            frame_count: 0,
+            name: mi.name.clone(),
+        }
+    }
+
+    fn map(&self, kind: PortKind) -> &PortMap {
+        match kind {
+            PortKind::Input => &self.inputs,
+            PortKind::Output => &self.outputs,
+        }
+    }
+
+    fn label_map(&self, kind: LabelKind) -> &PortMap {
+        match kind {
+            LabelKind::Input => &self.inputs,
+            _ => &self.outputs,
+        }
+    }
+
+    fn labels_to_logical(&self, kind: LabelKind, labels: Vec<RouterLabel>) -> Vec<RouterLabel> {
+        let map = self.label_map(kind);
+        let mut labels: Vec<_> = labels
+            .into_iter()
+            .filter_map(|l| {
+                Some(RouterLabel {
+                    id: map.logical(l.id)?,
+                    name: l.name,
+                })
+            })
+            .collect();
+        labels.sort_by_key(|l| l.id);
+        labels
+    }
+
+    fn descriptions_to_logical(
+        &self,
+        kind: LabelKind,
+        descriptions: Vec<RouterDescription>,
+    ) -> Vec<RouterDescription> {
+        let map = self.label_map(kind);
+        let mut descriptions: Vec<_> = descriptions
+            .into_iter()
+            .filter_map(|d| {
+                Some(RouterDescription {
+                    id: map.logical(d.id)?,
+                    text: d.text,
+                })
+            })
+            .collect();
+        descriptions.sort_by_key(|d| d.id);
+        descriptions
+    }
+
+    fn statuses_to_logical(&self, statuses: Vec<RouterPortStatus>) -> Vec<RouterPortStatus> {
+        let mut statuses: Vec<_> = statuses
+            .into_iter()
+            .filter_map(|st| {
+                Some(RouterPortStatus {
+                    id: self.map(st.kind).logical(st.id)?,
+                    ..st
+                })
+            })
+            .collect();
+        statuses.sort_by_key(|st| (st.kind, st.id));
+        statuses
+    }
+
+    fn routes_to_logical(&self, routes: Vec<RouterPatch>) -> Vec<RouterPatch> {
+        let mut routes: Vec<_> = routes
+            .into_iter()
+            .filter_map(|p| {
+                // Parked outputs stay parked, ones fed from unmapped inputs are left out.
+                let from_input = match p.from_input {
+                    Some(input) => Some(self.inputs.logical(input)?),
+                    None => None,
+                };
+                Some(RouterPatch {
+                    from_input,
+                    to_output: self.outputs.logical(p.to_output)?,
+                })
+            })
+            .collect();
+        routes.sort_by_key(|p| p.to_output);
+        routes
+    }
+
+    fn locks_to_logical(&self, locks: Vec<RouterLock>) -> Vec<RouterLock> {
+        let mut locks: Vec<_> = locks
+            .into_iter()
+            .filter_map(|l| {
+                Some(RouterLock {
+                    id: self.outputs.logical(l.id)?,
+                    locked: l.locked,
+                })
+            })
+            .collect();
+        locks.sort_by_key(|l| l.id);
+        locks
+    }
+
+    /// Check labels against the logical matrix, numbering them as in the wrapped router.
+    async fn labels_to_physical(
+        &self,
+        index: u32,
+        kind: LabelKind,
+        labels: Vec<RouterLabel>,
+    ) -> Result<Vec<RouterLabel>> {
+        let mi = self.get_matrix_info(index).await?;
+        mi.check_labels(index, kind, &labels)?;
+        let map = self.label_map(kind);
+        Ok(labels
+            .into_iter()
+            .map(|l| RouterLabel {
+                id: map.physical(l.id),
+                name: l.name,
+            })
+            .collect())
+    }
+
+    /// Check descriptions against the logical matrix, numbering them as in the wrapped router.
+    async fn descriptions_to_physical(
+        &self,
+        index: u32,
+        kind: LabelKind,
+        descriptions: Vec<RouterDescription>,
+    ) -> Result<Vec<RouterDescription>> {
+        let mi = self.get_matrix_info(index).await?;
+        mi.check_descriptions(index, kind, &descriptions)?;
+        let map = self.label_map(kind);
+        Ok(descriptions
+            .into_iter()
+            .map(|d| RouterDescription {
+                id: map.physical(d.id),
+                text: d.text,
+            })
+            .collect())
+    }
+
+    /// Check patches against the logical matrix, numbering them as in the wrapped router.
+    async fn routes_to_physical(
+        &self,
+        index: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<Vec<RouterPatch>> {
+        let mi = self.get_matrix_info(index).await?;
+        mi.check_patches(index, &changes)?;
+        Ok(changes
+            .into_iter()
+            .map(|p| RouterPatch {
+                from_input: p.from_input.map(|i| self.inputs.physical(i)),
+                to_output: self.outputs.physical(p.to_output),
+            })
+            .collect())
+    }
+
+    /// Number a logical port as in the wrapped router, if it's mapped.
+    async fn port_to_physical(&self, index: u32, kind: PortKind, id: u32) -> Result<u32> {
+        // Fails for matrices the wrapped router doesn't have.
+        self.get_matrix_info(index).await?;
+        let map = self.map(kind);
+        if id >= map.len() {
+            return Err(anyhow!("No {:?} port {} in matrix {}", kind, id, index));
+        }
+        Ok(map.physical(id))
+    }
+
+    /// Check `handle` was staged through the remapped router.
+    fn check_staged(&self, handle: TakeHandle) -> Result<()> {
+        if !self.staged.lock().unwrap().contains(&handle) {
+            return Err(anyhow!(
+                "No routes staged through the remap as {:?}",
+                handle
+            ));
+        }
+        Ok(())
+    }
+
+    /// The logical view of an event of the wrapped router, if it has any.
+    fn event_to_logical(&self, ev: RouterEvent) -> Option<RouterEvent> {
+        let ev = match ev {
+            RouterEvent::Connected
+            | RouterEvent::Disconnected
+            | RouterEvent::InfoUpdate(_)
+            | RouterEvent::AlarmUpdate(_) => ev,
+            RouterEvent::MatrixInfoUpdate(index, mi) => {
+                RouterEvent::MatrixInfoUpdate(index, self.remap_info(&mi))
+            }
+            RouterEvent::InputLabelUpdate(index, labels) => {
+                let labels = self.labels_to_logical(LabelKind::Input, labels);
+                (!labels.is_empty()).then_some(RouterEvent::InputLabelUpdate(index, labels))?
+            }
+            RouterEvent::OutputLabelUpdate(index, labels) => {
+                let labels = self.labels_to_logical(LabelKind::Output, labels);
+                (!labels.is_empty()).then_some(RouterEvent::OutputLabelUpdate(index, labels))?
+            }
+            RouterEvent::RouteUpdate(index, routes) => {
+                let routes = self.routes_to_logical(routes);
+                (!routes.is_empty()).then_some(RouterEvent::RouteUpdate(index, routes))?
+            }
+            RouterEvent::InputLabelSnapshot(index, labels) => RouterEvent::InputLabelSnapshot(
+                index,
+                self.labels_to_logical(LabelKind::Input, labels),
+            ),
+            RouterEvent::OutputLabelSnapshot(index, labels) => RouterEvent::OutputLabelSnapshot(
+                index,
+                self.labels_to_logical(LabelKind::Output, labels),
+            ),
+            RouterEvent::RouteSnapshot(index, routes) => {
+                RouterEvent::RouteSnapshot(index, self.routes_to_logical(routes))
+            }
+            RouterEvent::LevelRouteUpdate(index, level, routes) => {
+                RouterEvent::LevelRouteUpdate(index, level, self.routes_to_logical(routes))
+            }
+            RouterEvent::StagedRouteUpdate(index, routes) => {
+                RouterEvent::StagedRouteUpdate(index, self.routes_to_logical(routes))
+            }
+            RouterEvent::DescriptionUpdate(index, kind, descriptions) => {
+                let label_kind = match kind {
+                    PortKind::Input => LabelKind::Input,
+                    PortKind::Output => LabelKind::Output,
+                };
+                let descriptions = self.descriptions_to_logical(label_kind, descriptions);
+                (!descriptions.is_empty()).then_some(RouterEvent::DescriptionUpdate(
+                    index,
+                    kind,
+                    descriptions,
+                ))?
+            }
+            RouterEvent::PortStatusUpdate(index, statuses) => {
+                let statuses = self.statuses_to_logical(statuses);
+                (!statuses.is_empty()).then_some(RouterEvent::PortStatusUpdate(index, statuses))?
+            }
+            RouterEvent::LockUpdate(index, locks) => {
+                let locks = self.locks_to_logical(locks);
+                (!locks.is_empty()).then_some(RouterEvent::LockUpdate(index, locks))?
+            }
+            _ => return None,
+        };
+        Some(ev)
+    }
+}
+
+/// Builder of a [RemapRouter], with maps given directly or loaded from `logical=physical`
+/// lists.
+///
+/// Ports without a map are left in their order, all those of matrix 0 are exposed.
+#[derive(Clone, Debug, Default)]
+pub struct RemapBuilder {
+    inputs: Option<Vec<u32>>,
+    outputs: Option<Vec<u32>>,
+}
+
+impl RemapBuilder {
+    /// A builder leaving every port where it is, until given maps.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Logical input `i` is physical input `map[i]`.
+    pub fn inputs(mut self, map: Vec<u32>) -> Self {
+        self.inputs = Some(map);
+        self
+    }
+
+    /// Logical output `i` is physical output `map[i]`.
+    pub fn outputs(mut self, map: Vec<u32>) -> Self {
+        self.outputs = Some(map);
+        self
+    }
+
+    /// Load the input map from a list of `logical=physical` lines, see the module docs.
+    pub fn inputs_from_list(self, text: &str) -> Result<Self> {
+        Ok(self.inputs(parse_map(text).map_err(|e| e.context("Invalid input map"))?))
+    }
+
+    /// Load the output map from a list of `logical=physical` lines, see the module docs.
+    pub fn outputs_from_list(self, text: &str) -> Result<Self> {
+        Ok(self.outputs(parse_map(text).map_err(|e| e.context("Invalid output map"))?))
+    }
+
+    /// Wrap `inner`, failing like [RemapRouter::new] if the maps don't fit it.
+    pub async fn build<R: MatrixRouter>(self, inner: R) -> Result<RemapRouter<R>> {
+        let (inputs, outputs) = match (self.inputs, self.outputs) {
+            (Some(inputs), Some(outputs)) => (inputs, outputs),
+            (inputs, outputs) => {
+                let mi = inner.get_matrix_info(0).await?;
+                (
+                    inputs.unwrap_or_else(|| (0..mi.input_count).collect()),
+                    outputs.unwrap_or_else(|| (0..mi.output_count).collect()),
+                )
+            }
+        };
+        RemapRouter::new(inner, inputs, outputs).await
+    }
+}
+
+impl<R: MatrixRouter> MatrixRouter for RemapRouter<R> {
+    async fn is_alive(&self) -> Result<bool> {
+        self.inner.is_alive().await
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.inner.get_router_info().await
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        Ok(self.remap_info(&self.inner.get_matrix_info(index).await?))
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        let labels = self.inner.get_input_labels(index).await?;
+        Ok(self.labels_to_logical(LabelKind::Input, labels))
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        let labels = self.inner.get_output_labels(index).await?;
+        Ok(self.labels_to_logical(LabelKind::Output, labels))
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        let changed = self
+            .labels_to_physical(index, LabelKind::Input, changed)
+            .await?;
+        self.inner.update_input_labels(index, changed).await
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        let changed = self
+            .labels_to_physical(index, LabelKind::Output, changed)
+            .await?;
+        self.inner.update_output_labels(index, changed).await
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        let descriptions = self.inner.get_input_descriptions(index).await?;
+        Ok(self.descriptions_to_logical(LabelKind::Input, descriptions))
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        let descriptions = self.inner.get_output_descriptions(index).await?;
+        Ok(self.descriptions_to_logical(LabelKind::Output, descriptions))
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        let changed = self
+            .descriptions_to_physical(index, LabelKind::Input, changed)
+            .await?;
+        self.inner.update_input_descriptions(index, changed).await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        let changed = self
+            .descriptions_to_physical(index, LabelKind::Output, changed)
+            .await?;
+        self.inner.update_output_descriptions(index, changed).await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        Ok(self.statuses_to_logical(self.inner.get_input_status(index).await?))
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        Ok(self.statuses_to_logical(self.inner.get_output_status(index).await?))
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        Ok(self.routes_to_logical(self.inner.get_routes(index).await?))
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        let physical = self
+            .port_to_physical(index, PortKind::Output, output)
+            .await?;
+        let patch = self.inner.get_route(index, physical).await?;
+        self.routes_to_logical(vec![patch])
+            .pop()
+            .ok_or_else(|| anyhow!("No route for output {} in matrix {}", output, index))
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        let physical = self.port_to_physical(index, PortKind::Input, input).await?;
+        let outputs = self.inner.get_route_for_input(index, physical).await?;
+        let mut outputs: Vec<_> = outputs
+            .into_iter()
+            .filter_map(|output| self.outputs.logical(output))
+            .collect();
+        outputs.sort_unstable();
+        Ok(outputs)
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let changes = self.routes_to_physical(index, changes).await?;
+        self.inner.update_routes(index, changes).await
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let changes = self.routes_to_physical(index, changes).await?;
+        self.inner.update_routes_atomic(index, changes).await
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.inner.get_levels(index).await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        Ok(self.routes_to_logical(self.inner.get_level_routes(index, level).await?))
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        let changes = self.routes_to_physical(index, changes).await?;
+        self.inner.update_level_routes(index, level, changes).await
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        let changes = self.routes_to_physical(index, changes).await?;
+        let handle = self.inner.stage_routes(index, changes).await?;
+        self.staged.lock().unwrap().insert(handle);
+        Ok(handle)
+    }
+
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        self.check_staged(handle)?;
+        self.inner.commit(handle).await?;
+        self.staged.lock().unwrap().remove(&handle);
+        Ok(())
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        self.check_staged(handle)?;
+        self.inner.discard(handle).await?;
+        self.staged.lock().unwrap().remove(&handle);
+        Ok(())
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        Ok(self.routes_to_logical(self.inner.get_staged_routes(index).await?))
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        Ok(self.locks_to_logical(self.inner.get_locks(index).await?))
+    }
+
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        let mi = self.get_matrix_info(index).await?;
+        if let Some(l) = changes.iter().find(|l| l.id >= mi.output_count) {
+            return Err(anyhow!("Lock {} out of range", l.id));
+        }
+        let changes = changes
+            .into_iter()
+            .map(|l| RouterLock {
+                id: self.outputs.physical(l.id),
+                locked: l.locked,
+            })
+            .collect();
+        self.inner.update_locks(index, changes).await
+    }
+
+    /// Renumbered the same way, if `R` takes locks on behalf of owners.
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        Some(Self {
+            inner: self.inner.with_lock_owner(owner)?,
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            staged: Arc::clone(&self.staged),
+        })
+    }
+
+    async fn update_frame_labels(&self, _index: u32, _changed: Vec<RouterLabel>) -> Result<()> {
+        Err(RouterError::PermissionDenied.into())
+    }
+
+    async fn update_frame_routes(&self, _index: u32, _changes: Vec<RouterPatch>) -> Result<()> {
+        Err(RouterError::PermissionDenied.into())
+    }
+
+    async fn update_frame_locks(&self, _index: u32, _changes: Vec<RouterLock>) -> Result<()> {
+        Err(RouterError::PermissionDenied.into())
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        _index: u32,
+        _changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        Err(RouterError::PermissionDenied.into())
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        let id = self.port_to_physical(index, kind, id).await?;
+        self.inner.get_port_metadata(index, kind, id).await
+    }
+
+    async fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> Result<()> {
+        let id = self.port_to_physical(index, kind, id).await?;
+        self.inner
+            .set_port_metadata(index, kind, id, metadata)
+            .await
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        self.inner.get_alarms().await
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        RouterCapabilities {
+            has_frame_buffers: false,
+            has_processing_units: false,
+            ..self.inner.capabilities()
+        }
+    }
+
+    /// Starts with [RouterEvent::Connected] if the wrapped router is connected.
+    ///
+    /// Changes to unmapped ports are left out, events about anything not remapped are dropped.
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        let events = self.inner.event_stream().await?;
+        Ok(futures_util::StreamExt::boxed(
+            events.filter_map(move |ev| self.event_to_logical(ev)),
+        ))
+    }
+}
+
+impl<R: RouterIntrospect> RouterIntrospect for RemapRouter<R> {
+    fn name(&self) -> &'static str {
+        "RemapRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        vec![
+            ("inputs".into(), format!("{:?}", self.inputs.to_physical)),
+            ("outputs".into(), format!("{:?}", self.outputs.to_physical)),
+        ]
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        Some(&self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    /// Inputs and outputs of a 4x4 dummy reversed, with physical input 0 left out.
+    async fn remap() -> Result<(DummyRouter, RemapRouter<DummyRouter>)> {
+        let dummy = DummyRouter::with_config(1, 4, 4);
+        let remap = RemapRouter::new(dummy.clone(), vec![3, 2, 1], vec![3, 2, 1, 0]).await?;
+        Ok((dummy, remap))
+    }
+
+    #[tokio::test]
+    async fn renumbers() -> Result<()> {
+        let (dummy, remap) = remap().await?;
+        let mi = remap.get_matrix_info(0).await?;
+        assert_eq!((mi.input_count, mi.output_count), (3, 4));
+        assert_eq!(remap.get_input_labels(0).await?[0].name, "Input 4");
+        assert_eq!(remap.get_output_labels(0).await?[3].name, "Output 1");
+
+        remap.update_routes(0, vec![patch(0, 1)]).await?;
+        assert_eq!(dummy.get_route(0, 2).await?, patch(3, 2));
+        assert_eq!(remap.get_route(0, 1).await?, patch(0, 1));
+        // Output 3 is physical output 0, still routed from the unmapped input 0.
+        assert!(remap.get_route(0, 3).await.is_err());
+        assert!(remap
+            .get_routes(0)
+            .await?
+            .windows(2)
+            .all(|w| w[0].to_output < w[1].to_output));
+
+        let err = remap.update_routes(0, vec![patch(3, 0)]).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RouterError>(),
+            Some(RouterError::OutOfRange { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn patch_roundtrip() -> Result<()> {
+        let (dummy, remap) = remap().await?;
+        let mut events = remap.event_stream().await?;
+        assert_eq!(events.next().await, Some(RouterEvent::Connected));
+
+        // A logical patch goes out physical and comes back logical.
+        remap.update_routes(0, vec![patch(2, 0)]).await?;
+        assert_eq!(dummy.get_route(0, 3).await?, patch(1, 3));
+        match events.next().await {
+            Some(RouterEvent::RouteUpdate(0, routes)) => assert!(routes.contains(&patch(2, 0))),
+            ev => panic!("unexpected {:?}", ev),
+        }
+
+        // So does a physical one made behind its back, unless its input isn't mapped.
+        dummy.update_routes(0, vec![patch(0, 1)]).await?;
+        dummy.update_routes(0, vec![patch(3, 0)]).await?;
+        match events.next().await {
+            Some(RouterEvent::RouteUpdate(0, routes)) => {
+                assert!(routes.contains(&patch(0, 3)));
+                assert!(!routes.iter().any(|p| p.to_output == 2));
+            }
+            ev => panic!("unexpected {:?}", ev),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn levels_and_staging() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 4, 4)
+            .with_level_count(2)
+            .with_frame_count(2);
+        let remap = RemapRouter::new(dummy.clone(), vec![3, 2, 1], vec![3, 2, 1, 0]).await?;
+        assert_eq!(remap.get_levels(0).await?, dummy.get_levels(0).await?);
+        remap.update_level_routes(0, 1, vec![patch(0, 1)]).await?;
+        assert!(dummy.get_level_routes(0, 1).await?.contains(&patch(3, 2)));
+        assert!(remap.get_level_routes(0, 1).await?.contains(&patch(0, 1)));
+
+        let handle = remap.stage_routes(0, vec![patch(2, 0)]).await?;
+        assert_eq!(dummy.get_staged_routes(0).await?, vec![patch(1, 3)]);
+        assert_eq!(remap.get_staged_routes(0).await?, vec![patch(2, 0)]);
+        remap.commit(handle).await?;
+        assert_eq!(remap.get_route_for_input(0, 2).await?, vec![0]);
+
+        // Routes staged by others can't be taken through the remap.
+        let foreign = dummy.stage_routes(0, vec![patch(0, 0)]).await?;
+        assert!(remap.commit(foreign).await.is_err());
+        assert!(remap.discard(foreign).await.is_err());
+
+        // Frame buffers are hidden.
+        let err = remap
+            .update_frame_labels(0, vec![RouterLabel::default()])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::PermissionDenied)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_maps() {
+        let dummy = DummyRouter::with_config(1, 4, 4);
+        let err = RemapRouter::new(dummy.clone(), vec![0, 1, 1], vec![0])
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("both map to physical port 1"));
+        assert!(RemapRouter::new(dummy, vec![0], vec![0, 4]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn builder_from_list() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 4, 4);
+        let remap = RemapBuilder::new()
+            .inputs_from_list("# cameras\n1 = 0\n0=2\n\n")?
+            .build(dummy.clone())
+            .await?;
+        let mi = remap.get_matrix_info(0).await?;
+        assert_eq!((mi.input_count, mi.output_count), (2, 4));
+        remap.update_routes(0, vec![patch(0, 3)]).await?;
+        assert_eq!(dummy.get_route(0, 3).await?, patch(2, 3));
+
+        for bad in ["0=1\n0=2", "0=1\n2=3", "0:1", "0=x"] {
+            assert!(RemapBuilder::new().outputs_from_list(bad).is_err());
+        }
+        Ok(())
+    }
+}
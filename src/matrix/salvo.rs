@@ -0,0 +1,251 @@
+//! Salvos
+//!
+//! A salvo is the routing of a matrix captured under a name, to be recalled later on the same
+//! or another matrix. Recalling applies what fits the matrix and reports the rest.
+
+use super::*;
+use anyhow::{anyhow, Result};
+use futures_core::stream::BoxStream;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+#[cfg(feature = "serde")]
+use tracing::debug;
+
+/// Outcome of [SalvoStore::recall].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SalvoRecall {
+    /// Patches sent to the router.
+    pub applied: Vec<RouterPatch>,
+    /// Patches out of range for the matrix, left alone.
+    pub skipped: Vec<RouterPatch>,
+}
+
+/// Named [Salvo]s, optionally kept as one JSON file each in a directory.
+pub struct SalvoStore {
+    salvos: BTreeMap<String, Salvo>,
+    directory: Option<PathBuf>,
+    tx: broadcast::Sender<RouterEvent>,
+}
+
+/// Names end up as file names, so they may neither leave the directory nor hide in it.
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name
+            .chars()
+            .any(|c| c.is_control() || matches!(c, '/' | '\\' | ':'))
+    {
+        return Err(anyhow!("Invalid salvo name {:?}", name));
+    }
+    Ok(())
+}
+
+impl SalvoStore {
+    /// Create an empty store living in memory only.
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(16);
+        Self {
+            salvos: BTreeMap::new(),
+            directory: None,
+            tx,
+        }
+    }
+
+    /// Load all salvos from `directory`, creating it if needed, and keep them up to date there.
+    #[cfg(feature = "serde")]
+    pub fn with_directory(mut self, directory: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&directory)?;
+        for entry in std::fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let salvo: Salvo = serde_json::from_slice(&std::fs::read(&path)?)?;
+                self.salvos.insert(salvo.name.clone(), salvo);
+            }
+        }
+        debug!(?directory, count = self.salvos.len(), "Loaded salvos");
+        self.directory = Some(directory);
+        Ok(self)
+    }
+
+    /// Names of all salvos, sorted.
+    pub fn names(&self) -> Vec<String> {
+        self.salvos.keys().cloned().collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Salvo> {
+        self.salvos.get(name)
+    }
+
+    /// Store the current routing of matrix `index` as `name`, replacing any salvo of that name.
+    pub async fn capture<R: MatrixRouter>(
+        &mut self,
+        router: &R,
+        index: u32,
+        name: &str,
+    ) -> Result<&Salvo> {
+        check_name(name)?;
+        let mut patches = router.get_routes(index).await?;
+        patches.sort();
+        let salvo = Salvo {
+            name: name.into(),
+            patches,
+        };
+        self.persist(&salvo)?;
+        self.salvos.insert(name.into(), salvo);
+        Ok(&self.salvos[name])
+    }
+
+    /// Apply salvo `name` to matrix `index` in one go.
+    ///
+    /// Patches out of range for the matrix are skipped and reported, the remaining ones
+    /// are applied with [MatrixRouter::update_routes_atomic].
+    /// Successful recalls are announced as [RouterEvent::SalvoRecalled].
+    pub async fn recall<R: MatrixRouter>(
+        &self,
+        router: &R,
+        index: u32,
+        name: &str,
+    ) -> Result<SalvoRecall> {
+        let salvo = self
+            .salvos
+            .get(name)
+            .ok_or_else(|| anyhow!("No salvo named {:?}", name))?;
+        let mi = router.get_matrix_info(index).await?;
+        let (applied, skipped): (Vec<_>, Vec<_>) = salvo
+            .patches
+            .iter()
+            .partition(|p| mi.check_patches(index, std::slice::from_ref(p)).is_ok());
+        if !applied.is_empty() {
+            router.update_routes_atomic(index, applied.clone()).await?;
+        }
+        let _ = self
+            .tx
+            .send(RouterEvent::SalvoRecalled(index, name.to_string()));
+        Ok(SalvoRecall { applied, skipped })
+    }
+
+    /// Delete salvo `name`, returning whether it existed.
+    pub fn remove(&mut self, name: &str) -> Result<bool> {
+        if self.salvos.remove(name).is_none() {
+            return Ok(false);
+        }
+        if let Some(dir) = &self.directory {
+            std::fs::remove_file(dir.join(format!("{}.json", name)))?;
+        }
+        Ok(true)
+    }
+
+    /// Subscribe to [RouterEvent::SalvoRecalled] notifications.
+    pub fn event_stream(&self) -> BoxStream<'static, RouterEvent> {
+        let rx = self.tx.subscribe();
+        futures_util::StreamExt::boxed(BroadcastStream::new(rx).filter_map(|r| r.ok()))
+    }
+
+    /// Write `salvo` to the directory, if any, through a temporary file.
+    fn persist(&self, salvo: &Salvo) -> Result<()> {
+        #[cfg(feature = "serde")]
+        if let Some(dir) = &self.directory {
+            let path = dir.join(format!("{}.json", salvo.name));
+            let tmp = dir.join(format!(".{}.json.tmp", salvo.name));
+            std::fs::write(&tmp, serde_json::to_vec_pretty(salvo)?)?;
+            std::fs::rename(&tmp, path)?;
+        }
+        #[cfg(not(feature = "serde"))]
+        let _ = salvo;
+        Ok(())
+    }
+}
+
+impl Default for SalvoStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    #[tokio::test]
+    async fn capture_and_recall() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 4, 4);
+        let mut store = SalvoStore::new();
+        let mut events = store.event_stream();
+        dummy
+            .update_routes(0, vec![patch(3, 0), patch(2, 1)])
+            .await?;
+        let captured = store.capture(&dummy, 0, "Show open").await?.clone();
+        assert_eq!(captured.patches, dummy.get_routes(0).await?);
+
+        dummy
+            .update_routes(0, vec![patch(1, 0), patch(1, 3)])
+            .await?;
+        let recall = store.recall(&dummy, 0, "Show open").await?;
+        assert_eq!(recall.applied, captured.patches);
+        assert_eq!(recall.skipped, vec![]);
+        assert_eq!(dummy.get_routes(0).await?, captured.patches);
+        assert_eq!(
+            events.next().await,
+            Some(RouterEvent::SalvoRecalled(0, "Show open".into()))
+        );
+
+        assert!(store.recall(&dummy, 0, "Missing").await.is_err());
+        for bad in ["", "../escape", ".hidden", "a\nb"] {
+            assert!(store.capture(&dummy, 0, bad).await.is_err(), "{:?}", bad);
+        }
+        assert!(store.remove("Show open")?);
+        assert!(!store.remove("Show open")?);
+        assert_eq!(store.names(), Vec::<String>::new());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn recall_skips_out_of_range() -> Result<()> {
+        let big = DummyRouter::with_config(1, 4, 4);
+        big.update_routes(0, vec![patch(3, 0), patch(1, 1), patch(2, 3)])
+            .await?;
+        let mut store = SalvoStore::new();
+        store.capture(&big, 0, "Big").await?;
+
+        let small = DummyRouter::with_config(1, 3, 2);
+        let recall = store.recall(&small, 0, "Big").await?;
+        assert_eq!(recall.applied, vec![patch(1, 1)]);
+        assert_eq!(recall.skipped, vec![patch(3, 0), patch(0, 2), patch(2, 3)]);
+        assert_eq!(small.get_routes(0).await?, vec![patch(0, 0), patch(1, 1)]);
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn persistence_roundtrip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("salvos");
+        let dummy = DummyRouter::with_config(2, 2, 2);
+        dummy.update_routes(1, vec![patch(1, 0)]).await?;
+        {
+            let mut store = SalvoStore::new().with_directory(path.clone())?;
+            store.capture(&dummy, 0, "Default").await?;
+            store.capture(&dummy, 1, "Swapped").await?;
+            store.capture(&dummy, 1, "Gone").await?;
+            store.remove("Gone")?;
+        }
+
+        let store = SalvoStore::new().with_directory(path.clone())?;
+        assert_eq!(store.names(), vec!["Default", "Swapped"]);
+        assert_eq!(
+            store.get("Swapped").unwrap().patches,
+            dummy.get_routes(1).await?
+        );
+        assert_eq!(std::fs::read_dir(&path)?.count(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,728 @@
+//! Part of a router as a router of its own
+//!
+//! Wraps a [MatrixRouter], exposing only a range of its inputs and outputs, renumbered to
+//! start at 0. Anything outside the slice can't be seen or touched through it, which makes
+//! it fit for handing a panel just its share of a bigger router.
+
+use super::*;
+use anyhow::{anyhow, Result};
+use futures_core::stream::BoxStream;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use tokio_stream::StreamExt;
+
+/// Router wrapper exposing the inputs and outputs of `R` in the given ranges.
+///
+/// The same ranges apply to every matrix, clamped to the ports it has. Outputs routed from
+/// an input outside the slice have no route in it. Labels, descriptions, port status, routes
+/// of every level, staged routes, output locks and port metadata are sliced. Frame buffers
+/// and processing units are hidden, changing them fails with [RouterError::PermissionDenied].
+///
+/// Only routes staged through the slice can be committed or discarded through it.
+#[derive(Clone)]
+pub struct SliceRouter<R> {
+    inner: R,
+    inputs: Range<u32>,
+    outputs: Range<u32>,
+    staged: Arc<Mutex<HashSet<TakeHandle>>>,
+}
+
+/// The part of `range` below `count`.
+fn clamp(range: &Range<u32>, count: u32) -> Range<u32> {
+    range.start.min(count)..range.end.min(count)
+}
+
+/// Id of physical port `id` within `range`, if it's in there.
+fn to_local(range: &Range<u32>, id: u32) -> Option<u32> {
+    range.contains(&id).then(|| id - range.start)
+}
+
+impl<R: MatrixRouter> SliceRouter<R> {
+    /// Expose `inputs` and `outputs` of `inner`, numbered from 0.
+    pub fn new(inner: R, inputs: Range<u32>, outputs: Range<u32>) -> Self {
+        Self {
+            inner,
+            inputs,
+            outputs,
+            staged: Arc::default(),
+        }
+    }
+
+    /// The wrapped router.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Matrix info of the slice, given that of the wrapped router.
+    fn slice_info(&self, mi: &RouterMatrixInfo) -> RouterMatrixInfo {
+        RouterMatrixInfo {
+            input_count: clamp(&self.inputs, mi.input_count).len() as u32,
+            output_count: clamp(&self.outputs, mi.output_count).len() as u32,
+            frame_count: 0,
+            name: mi.name.clone(),
+        }
+    }
+
+    fn range(&self, kind: LabelKind) -> &Range<u32> {
+        match kind {
+            LabelKind::Input => &self.inputs,
+            _ => &self.outputs,
+        }
+    }
+
+    fn labels_to_local(&self, kind: LabelKind, labels: Vec<RouterLabel>) -> Vec<RouterLabel> {
+        let range = self.range(kind);
+        labels
+            .into_iter()
+            .filter_map(|l| {
+                Some(RouterLabel {
+                    id: to_local(range, l.id)?,
+                    name: l.name,
+                })
+            })
+            .collect()
+    }
+
+    fn descriptions_to_local(
+        &self,
+        kind: LabelKind,
+        descriptions: Vec<RouterDescription>,
+    ) -> Vec<RouterDescription> {
+        let range = self.range(kind);
+        descriptions
+            .into_iter()
+            .filter_map(|d| {
+                Some(RouterDescription {
+                    id: to_local(range, d.id)?,
+                    text: d.text,
+                })
+            })
+            .collect()
+    }
+
+    fn statuses_to_local(&self, statuses: Vec<RouterPortStatus>) -> Vec<RouterPortStatus> {
+        statuses
+            .into_iter()
+            .filter_map(|st| {
+                let range = match st.kind {
+                    PortKind::Input => &self.inputs,
+                    PortKind::Output => &self.outputs,
+                };
+                Some(RouterPortStatus {
+                    id: to_local(range, st.id)?,
+                    ..st
+                })
+            })
+            .collect()
+    }
+
+    fn routes_to_local(&self, routes: Vec<RouterPatch>) -> Vec<RouterPatch> {
+        routes
+            .into_iter()
+            .filter_map(|p| {
+                // Parked outputs stay parked, ones fed from outside the slice are left out.
+                let from_input = match p.from_input {
+                    Some(input) => Some(to_local(&self.inputs, input)?),
+                    None => None,
+                };
+                Some(RouterPatch {
+                    from_input,
+                    to_output: to_local(&self.outputs, p.to_output)?,
+                })
+            })
+            .collect()
+    }
+
+    fn locks_to_local(&self, locks: Vec<RouterLock>) -> Vec<RouterLock> {
+        locks
+            .into_iter()
+            .filter_map(|l| {
+                Some(RouterLock {
+                    id: to_local(&self.outputs, l.id)?,
+                    locked: l.locked,
+                })
+            })
+            .collect()
+    }
+
+    /// Check labels against the slice, numbering them as in the wrapped router.
+    async fn labels_to_physical(
+        &self,
+        index: u32,
+        kind: LabelKind,
+        labels: Vec<RouterLabel>,
+    ) -> Result<Vec<RouterLabel>> {
+        let mi = self.get_matrix_info(index).await?;
+        mi.check_labels(index, kind, &labels)?;
+        let start = self.range(kind).start;
+        Ok(labels
+            .into_iter()
+            .map(|l| RouterLabel {
+                id: l.id + start,
+                name: l.name,
+            })
+            .collect())
+    }
+
+    /// Check descriptions against the slice, numbering them as in the wrapped router.
+    async fn descriptions_to_physical(
+        &self,
+        index: u32,
+        kind: LabelKind,
+        descriptions: Vec<RouterDescription>,
+    ) -> Result<Vec<RouterDescription>> {
+        let mi = self.get_matrix_info(index).await?;
+        mi.check_descriptions(index, kind, &descriptions)?;
+        let start = self.range(kind).start;
+        Ok(descriptions
+            .into_iter()
+            .map(|d| RouterDescription {
+                id: d.id + start,
+                text: d.text,
+            })
+            .collect())
+    }
+
+    /// Check patches against the slice, numbering them as in the wrapped router.
+    async fn routes_to_physical(
+        &self,
+        index: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<Vec<RouterPatch>> {
+        let mi = self.get_matrix_info(index).await?;
+        mi.check_patches(index, &changes)?;
+        Ok(changes
+            .into_iter()
+            .map(|p| RouterPatch {
+                from_input: p.from_input.map(|i| i + self.inputs.start),
+                to_output: p.to_output + self.outputs.start,
+            })
+            .collect())
+    }
+
+    /// Number a port of the slice as in the wrapped router, if the slice has it.
+    async fn port_to_physical(&self, index: u32, kind: PortKind, id: u32) -> Result<u32> {
+        let mi = self.get_matrix_info(index).await?;
+        let (count, start) = match kind {
+            PortKind::Input => (mi.input_count, self.inputs.start),
+            PortKind::Output => (mi.output_count, self.outputs.start),
+        };
+        if id >= count {
+            return Err(anyhow!("No {:?} port {} in matrix {}", kind, id, index));
+        }
+        Ok(id + start)
+    }
+
+    /// Check `handle` was staged through the slice.
+    fn check_staged(&self, handle: TakeHandle) -> Result<()> {
+        if !self.staged.lock().unwrap().contains(&handle) {
+            return Err(anyhow!(
+                "No routes staged through the slice as {:?}",
+                handle
+            ));
+        }
+        Ok(())
+    }
+
+    /// The slice's view of an event of the wrapped router, if it has any.
+    fn event_to_local(&self, ev: RouterEvent) -> Option<RouterEvent> {
+        let ev = match ev {
+            RouterEvent::Connected
+            | RouterEvent::Disconnected
+            | RouterEvent::InfoUpdate(_)
+            | RouterEvent::AlarmUpdate(_) => ev,
+            RouterEvent::MatrixInfoUpdate(index, mi) => {
+                RouterEvent::MatrixInfoUpdate(index, self.slice_info(&mi))
+            }
+            RouterEvent::InputLabelUpdate(index, labels) => {
+                let labels = self.labels_to_local(LabelKind::Input, labels);
+                (!labels.is_empty()).then_some(RouterEvent::InputLabelUpdate(index, labels))?
+            }
+            RouterEvent::OutputLabelUpdate(index, labels) => {
+                let labels = self.labels_to_local(LabelKind::Output, labels);
+                (!labels.is_empty()).then_some(RouterEvent::OutputLabelUpdate(index, labels))?
+            }
+            RouterEvent::RouteUpdate(index, routes) => {
+                let routes = self.routes_to_local(routes);
+                (!routes.is_empty()).then_some(RouterEvent::RouteUpdate(index, routes))?
+            }
+            RouterEvent::InputLabelSnapshot(index, labels) => RouterEvent::InputLabelSnapshot(
+                index,
+                self.labels_to_local(LabelKind::Input, labels),
+            ),
+            RouterEvent::OutputLabelSnapshot(index, labels) => RouterEvent::OutputLabelSnapshot(
+                index,
+                self.labels_to_local(LabelKind::Output, labels),
+            ),
+            RouterEvent::RouteSnapshot(index, routes) => {
+                RouterEvent::RouteSnapshot(index, self.routes_to_local(routes))
+            }
+            RouterEvent::LevelRouteUpdate(index, level, routes) => {
+                RouterEvent::LevelRouteUpdate(index, level, self.routes_to_local(routes))
+            }
+            RouterEvent::StagedRouteUpdate(index, routes) => {
+                RouterEvent::StagedRouteUpdate(index, self.routes_to_local(routes))
+            }
+            RouterEvent::DescriptionUpdate(index, kind, descriptions) => {
+                let label_kind = match kind {
+                    PortKind::Input => LabelKind::Input,
+                    PortKind::Output => LabelKind::Output,
+                };
+                let descriptions = self.descriptions_to_local(label_kind, descriptions);
+                (!descriptions.is_empty()).then_some(RouterEvent::DescriptionUpdate(
+                    index,
+                    kind,
+                    descriptions,
+                ))?
+            }
+            RouterEvent::PortStatusUpdate(index, statuses) => {
+                let statuses = self.statuses_to_local(statuses);
+                (!statuses.is_empty()).then_some(RouterEvent::PortStatusUpdate(index, statuses))?
+            }
+            RouterEvent::LockUpdate(index, locks) => {
+                let locks = self.locks_to_local(locks);
+                (!locks.is_empty()).then_some(RouterEvent::LockUpdate(index, locks))?
+            }
+            _ => return None,
+        };
+        Some(ev)
+    }
+}
+
+impl<R: MatrixRouter> MatrixRouter for SliceRouter<R> {
+    async fn is_alive(&self) -> Result<bool> {
+        self.inner.is_alive().await
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.inner.get_router_info().await
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        Ok(self.slice_info(&self.inner.get_matrix_info(index).await?))
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        let labels = self.inner.get_input_labels(index).await?;
+        Ok(self.labels_to_local(LabelKind::Input, labels))
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        let labels = self.inner.get_output_labels(index).await?;
+        Ok(self.labels_to_local(LabelKind::Output, labels))
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        let changed = self
+            .labels_to_physical(index, LabelKind::Input, changed)
+            .await?;
+        self.inner.update_input_labels(index, changed).await
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        let changed = self
+            .labels_to_physical(index, LabelKind::Output, changed)
+            .await?;
+        self.inner.update_output_labels(index, changed).await
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        let descriptions = self.inner.get_input_descriptions(index).await?;
+        Ok(self.descriptions_to_local(LabelKind::Input, descriptions))
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        let descriptions = self.inner.get_output_descriptions(index).await?;
+        Ok(self.descriptions_to_local(LabelKind::Output, descriptions))
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        let changed = self
+            .descriptions_to_physical(index, LabelKind::Input, changed)
+            .await?;
+        self.inner.update_input_descriptions(index, changed).await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        let changed = self
+            .descriptions_to_physical(index, LabelKind::Output, changed)
+            .await?;
+        self.inner.update_output_descriptions(index, changed).await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        Ok(self.statuses_to_local(self.inner.get_input_status(index).await?))
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        Ok(self.statuses_to_local(self.inner.get_output_status(index).await?))
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        Ok(self.routes_to_local(self.inner.get_routes(index).await?))
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        let physical = self
+            .port_to_physical(index, PortKind::Output, output)
+            .await?;
+        let patch = self.inner.get_route(index, physical).await?;
+        self.routes_to_local(vec![patch])
+            .pop()
+            .ok_or_else(|| anyhow!("No route for output {} in matrix {}", output, index))
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        let physical = self.port_to_physical(index, PortKind::Input, input).await?;
+        let outputs = self.inner.get_route_for_input(index, physical).await?;
+        Ok(outputs
+            .into_iter()
+            .filter_map(|output| to_local(&self.outputs, output))
+            .collect())
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let changes = self.routes_to_physical(index, changes).await?;
+        self.inner.update_routes(index, changes).await
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        let changes = self.routes_to_physical(index, changes).await?;
+        self.inner.update_routes_atomic(index, changes).await
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.inner.get_levels(index).await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        Ok(self.routes_to_local(self.inner.get_level_routes(index, level).await?))
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        let changes = self.routes_to_physical(index, changes).await?;
+        self.inner.update_level_routes(index, level, changes).await
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        let changes = self.routes_to_physical(index, changes).await?;
+        let handle = self.inner.stage_routes(index, changes).await?;
+        self.staged.lock().unwrap().insert(handle);
+        Ok(handle)
+    }
+
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        self.check_staged(handle)?;
+        self.inner.commit(handle).await?;
+        self.staged.lock().unwrap().remove(&handle);
+        Ok(())
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        self.check_staged(handle)?;
+        self.inner.discard(handle).await?;
+        self.staged.lock().unwrap().remove(&handle);
+        Ok(())
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        Ok(self.routes_to_local(self.inner.get_staged_routes(index).await?))
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        Ok(self.locks_to_local(self.inner.get_locks(index).await?))
+    }
+
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        let mi = self.get_matrix_info(index).await?;
+        if let Some(l) = changes.iter().find(|l| l.id >= mi.output_count) {
+            return Err(anyhow!("Lock {} out of range", l.id));
+        }
+        let changes = changes
+            .into_iter()
+            .map(|l| RouterLock {
+                id: l.id + self.outputs.start,
+                locked: l.locked,
+            })
+            .collect();
+        self.inner.update_locks(index, changes).await
+    }
+
+    /// The same slice, if `R` takes locks on behalf of owners.
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        Some(Self {
+            inner: self.inner.with_lock_owner(owner)?,
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            staged: Arc::clone(&self.staged),
+        })
+    }
+
+    async fn update_frame_labels(&self, _index: u32, _changed: Vec<RouterLabel>) -> Result<()> {
+        Err(RouterError::PermissionDenied.into())
+    }
+
+    async fn update_frame_routes(&self, _index: u32, _changes: Vec<RouterPatch>) -> Result<()> {
+        Err(RouterError::PermissionDenied.into())
+    }
+
+    async fn update_frame_locks(&self, _index: u32, _changes: Vec<RouterLock>) -> Result<()> {
+        Err(RouterError::PermissionDenied.into())
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        _index: u32,
+        _changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        Err(RouterError::PermissionDenied.into())
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        let id = self.port_to_physical(index, kind, id).await?;
+        self.inner.get_port_metadata(index, kind, id).await
+    }
+
+    async fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> Result<()> {
+        let id = self.port_to_physical(index, kind, id).await?;
+        self.inner
+            .set_port_metadata(index, kind, id, metadata)
+            .await
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        self.inner.get_alarms().await
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        RouterCapabilities {
+            has_frame_buffers: false,
+            has_processing_units: false,
+            ..self.inner.capabilities()
+        }
+    }
+
+    /// Starts with [RouterEvent::Connected] if the wrapped router is connected.
+    ///
+    /// Changes outside the slice are left out, events about anything not sliced are dropped.
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        let events = self.inner.event_stream().await?;
+        Ok(futures_util::StreamExt::boxed(
+            events.filter_map(move |ev| self.event_to_local(ev)),
+        ))
+    }
+}
+
+impl<R: RouterIntrospect> RouterIntrospect for SliceRouter<R> {
+    fn name(&self) -> &'static str {
+        "SliceRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        vec![
+            ("inputs".into(), format!("{:?}", self.inputs)),
+            ("outputs".into(), format!("{:?}", self.outputs)),
+        ]
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        Some(&self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::VideohubFrontend;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use videohub::VideohubClient;
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    /// Inputs 4 to 11 and outputs 8 to 15 of a 32x16 dummy.
+    fn slice() -> (DummyRouter, SliceRouter<DummyRouter>) {
+        let dummy = DummyRouter::with_config(1, 32, 16);
+        let slice = SliceRouter::new(dummy.clone(), 4..12, 8..16);
+        (dummy, slice)
+    }
+
+    #[tokio::test]
+    async fn renumbers() -> Result<()> {
+        let (dummy, slice) = slice();
+        let mi = slice.get_matrix_info(0).await?;
+        assert_eq!((mi.input_count, mi.output_count), (8, 8));
+        assert_eq!(slice.get_input_labels(0).await?[0].name, "Input 5");
+        assert_eq!(slice.get_output_labels(0).await?[7].name, "Output 16");
+
+        slice.update_routes(0, vec![patch(2, 1)]).await?;
+        assert_eq!(dummy.get_route(0, 9).await?, patch(6, 9));
+        assert_eq!(slice.get_route(0, 1).await?, patch(2, 1));
+        // Output 0 is still routed from physical input 0, outside the slice.
+        assert!(slice.get_route(0, 0).await.is_err());
+        assert_eq!(slice.get_routes(0).await?, vec![patch(2, 1)]);
+
+        slice
+            .update_output_labels(
+                0,
+                vec![RouterLabel {
+                    id: 3,
+                    name: "Studio B".into(),
+                }],
+            )
+            .await?;
+        assert_eq!(dummy.get_output_labels(0).await?[11].name, "Studio B");
+
+        // Nothing outside the slice can be reached.
+        let err = slice.update_routes(0, vec![patch(8, 0)]).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RouterError>(),
+            Some(RouterError::OutOfRange { .. })
+        ));
+        assert!(slice.update_routes(0, vec![patch(0, 8)]).await.is_err());
+        let label = RouterLabel {
+            id: 8,
+            name: "Nope".into(),
+        };
+        assert!(slice.update_input_labels(0, vec![label]).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn levels_and_staging() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 32, 16)
+            .with_level_count(2)
+            .with_frame_count(2);
+        let slice = SliceRouter::new(dummy.clone(), 4..12, 8..16);
+        assert_eq!(slice.get_levels(0).await?, dummy.get_levels(0).await?);
+        slice.update_level_routes(0, 1, vec![patch(0, 2)]).await?;
+        assert!(dummy.get_level_routes(0, 1).await?.contains(&patch(4, 10)));
+        assert!(slice.get_level_routes(0, 1).await?.contains(&patch(0, 2)));
+        assert_eq!(slice.get_route_for_input(0, 0).await?, Vec::<u32>::new());
+
+        let handle = slice.stage_routes(0, vec![patch(1, 0)]).await?;
+        assert_eq!(dummy.get_staged_routes(0).await?, vec![patch(5, 8)]);
+        assert_eq!(slice.get_staged_routes(0).await?, vec![patch(1, 0)]);
+        slice.commit(handle).await?;
+        assert_eq!(slice.get_route_for_input(0, 1).await?, vec![0]);
+
+        // Routes staged by others can't be taken through the slice.
+        let foreign = dummy.stage_routes(0, vec![patch(0, 0)]).await?;
+        assert!(slice.commit(foreign).await.is_err());
+        assert!(slice.discard(foreign).await.is_err());
+        assert_eq!(dummy.get_staged_routes(0).await?, vec![patch(0, 0)]);
+
+        // Frame buffers are hidden.
+        assert_eq!(slice.get_matrix_info(0).await?.frame_count, 0);
+        let err = slice
+            .update_frame_routes(0, vec![patch(0, 0)])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RouterError>(),
+            Some(&RouterError::PermissionDenied)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn events_filtered() -> Result<()> {
+        let (dummy, slice) = slice();
+        let mut events = slice.event_stream().await?;
+        assert_eq!(events.next().await, Some(RouterEvent::Connected));
+
+        // Foreign outputs aren't announced, neither are inputs outside the slice.
+        dummy.update_routes(0, vec![patch(5, 2)]).await?;
+        dummy.update_routes(0, vec![patch(30, 8)]).await?;
+        dummy.update_routes(0, vec![patch(11, 15)]).await?;
+        match events.next().await {
+            Some(RouterEvent::RouteUpdate(0, routes)) => {
+                assert!(routes.contains(&patch(7, 7)));
+                assert!(routes
+                    .iter()
+                    .all(|p| p.to_output < 8 && p.from_input.is_some_and(|i| i < 8)));
+                assert!(!routes.iter().any(|p| p.to_output == 0));
+            }
+            ev => panic!("unexpected {:?}", ev),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn descriptions() -> Result<()> {
+        let (dummy, slice) = slice();
+        let mut events = slice.event_stream().await?;
+        assert_eq!(events.next().await, Some(RouterEvent::Connected));
+        let d = RouterDescription {
+            id: 2,
+            text: "Camera 7, balcony".into(),
+        };
+        slice.update_input_descriptions(0, vec![d.clone()]).await?;
+        assert_eq!(dummy.get_input_descriptions(0).await?[6].text, d.text);
+        assert_eq!(slice.get_input_descriptions(0).await?.len(), 8);
+        assert_eq!(slice.get_input_descriptions(0).await?[2], d);
+        assert_eq!(
+            events.next().await,
+            Some(RouterEvent::DescriptionUpdate(0, PortKind::Input, vec![d]))
+        );
+
+        // Descriptions of foreign ports are neither reachable nor announced.
+        let foreign = RouterDescription {
+            id: 0,
+            text: "Elsewhere".into(),
+        };
+        dummy
+            .update_output_descriptions(0, vec![foreign.clone()])
+            .await?;
+        let out = RouterDescription { id: 8, ..foreign };
+        assert!(slice
+            .update_output_descriptions(0, vec![out])
+            .await
+            .is_err());
+        slice.update_routes(0, vec![patch(0, 0)]).await?;
+        assert!(matches!(
+            events.next().await,
+            Some(RouterEvent::RouteUpdate(..))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serve_slice() -> Result<()> {
+        let (dummy, slice) = slice();
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(VideohubFrontend::new(Arc::new(slice), 0).serve(listener));
+
+        let (client, _events) = VideohubClient::connect(addr).await?;
+        client.set_route(3, 1).await?;
+        assert_eq!(dummy.get_route(0, 11).await?, patch(5, 11));
+        assert!(client.set_route(8, 0).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+//! Filtered event subscriptions
+//!
+//! Narrows down the events of any [MatrixRouter] to those of a single matrix or kind, as
+//! built with [MatrixRouter::events]:
+//!
+//! ```ignore
+//! let routes = router.events().matrix(0).routes_only().subscribe().await?;
+//! ```
+//!
+//! [RouterEvent::Connected] and [RouterEvent::Disconnected] always pass, whatever the filter.
+
+use super::*;
+use anyhow::Result;
+use futures_core::stream::BoxStream;
+use tokio_stream::StreamExt;
+
+/// Builder of a subscription to some of the events of `R`, see the module docs.
+pub struct EventSubscription<'a, R> {
+    router: &'a R,
+    matrix: Option<u32>,
+}
+
+impl<'a, R: MatrixRouter> EventSubscription<'a, R> {
+    /// Subscribe to everything `router` announces, until narrowed down.
+    pub fn new(router: &'a R) -> Self {
+        Self {
+            router,
+            matrix: None,
+        }
+    }
+
+    /// Only events about matrix `index`, besides those about the whole router.
+    pub fn matrix(mut self, index: u32) -> Self {
+        self.matrix = Some(index);
+        self
+    }
+
+    /// Only route changes, updates and snapshots alike.
+    pub fn routes_only(self) -> TypedSubscription<'a, R, Vec<RouterPatch>> {
+        self.typed(|ev| match ev {
+            RouterEvent::RouteUpdate(i, ps) | RouterEvent::RouteSnapshot(i, ps) => Some((i, ps)),
+            _ => None,
+        })
+    }
+
+    /// Only input label changes, updates and snapshots alike.
+    pub fn input_labels_only(self) -> TypedSubscription<'a, R, Vec<RouterLabel>> {
+        self.typed(|ev| match ev {
+            RouterEvent::InputLabelUpdate(i, ls) | RouterEvent::InputLabelSnapshot(i, ls) => {
+                Some((i, ls))
+            }
+            _ => None,
+        })
+    }
+
+    /// Only output label changes, updates and snapshots alike.
+    pub fn output_labels_only(self) -> TypedSubscription<'a, R, Vec<RouterLabel>> {
+        self.typed(|ev| match ev {
+            RouterEvent::OutputLabelUpdate(i, ls) | RouterEvent::OutputLabelSnapshot(i, ls) => {
+                Some((i, ls))
+            }
+            _ => None,
+        })
+    }
+
+    /// Only output lock changes.
+    pub fn locks_only(self) -> TypedSubscription<'a, R, Vec<RouterLock>> {
+        self.typed(|ev| match ev {
+            RouterEvent::LockUpdate(i, ls) => Some((i, ls)),
+            _ => None,
+        })
+    }
+
+    fn typed<T>(self, select: fn(RouterEvent) -> Option<(u32, T)>) -> TypedSubscription<'a, R, T> {
+        TypedSubscription {
+            router: self.router,
+            matrix: self.matrix,
+            select,
+        }
+    }
+
+    /// Subscribe, see [MatrixRouter::event_stream].
+    pub async fn subscribe(self) -> Result<BoxStream<'a, RouterEvent>> {
+        let matrix = self.matrix;
+        let events = self.router.event_stream().await?;
+        Ok(futures_util::StreamExt::boxed(events.filter(move |ev| {
+            matrix.is_none() || ev.matrix().is_none() || ev.matrix() == matrix
+        })))
+    }
+}
+
+/// What a [TypedSubscription] yields.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TypedEvent<T> {
+    /// See [RouterEvent::Connected].
+    Connected,
+    /// See [RouterEvent::Disconnected].
+    Disconnected,
+    /// A change of the subscribed kind on a matrix.
+    Changed(u32, T),
+}
+
+/// Builder of a subscription to a single kind of event, see [EventSubscription].
+pub struct TypedSubscription<'a, R, T> {
+    router: &'a R,
+    matrix: Option<u32>,
+    select: fn(RouterEvent) -> Option<(u32, T)>,
+}
+
+impl<'a, R: MatrixRouter, T: Send + 'a> TypedSubscription<'a, R, T> {
+    /// Only changes of matrix `index`.
+    pub fn matrix(mut self, index: u32) -> Self {
+        self.matrix = Some(index);
+        self
+    }
+
+    /// Subscribe, see [MatrixRouter::event_stream].
+    pub async fn subscribe(self) -> Result<BoxStream<'a, TypedEvent<T>>> {
+        let (matrix, select) = (self.matrix, self.select);
+        let events = self.router.event_stream().await?;
+        Ok(futures_util::StreamExt::boxed(events.filter_map(
+            move |ev| match ev {
+                RouterEvent::Connected => Some(TypedEvent::Connected),
+                RouterEvent::Disconnected => Some(TypedEvent::Disconnected),
+                ev => {
+                    let (index, change) = select(ev)?;
+                    matrix
+                        .is_none_or(|m| m == index)
+                        .then_some(TypedEvent::Changed(index, change))
+                }
+            },
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_by_matrix() -> Result<()> {
+        let dummy = DummyRouter::with_config(2, 4, 4);
+        let mut events = dummy.events().matrix(1).subscribe().await?;
+        assert_eq!(events.next().await, Some(RouterEvent::Connected));
+
+        dummy.update_routes(0, vec![patch(1, 1)]).await?;
+        dummy.push_event(RouterEvent::AlarmUpdate(vec![]));
+        dummy.update_routes(1, vec![patch(2, 2)]).await?;
+        dummy.set_alive(false);
+        assert_eq!(events.next().await, Some(RouterEvent::AlarmUpdate(vec![])));
+        assert_eq!(
+            events.next().await,
+            Some(RouterEvent::RouteUpdate(1, vec![patch(2, 2)]))
+        );
+        assert_eq!(events.next().await, Some(RouterEvent::Disconnected));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn filters_by_kind() -> Result<()> {
+        let dummy = DummyRouter::with_config(2, 4, 4);
+        let mut routes = dummy.events().matrix(0).routes_only().subscribe().await?;
+        let mut labels = dummy.events().input_labels_only().subscribe().await?;
+        assert_eq!(routes.next().await, Some(TypedEvent::Connected));
+        assert_eq!(labels.next().await, Some(TypedEvent::Connected));
+
+        let label = RouterLabel {
+            id: 2,
+            name: "Cam 3".into(),
+        };
+        dummy.update_input_labels(1, vec![label.clone()]).await?;
+        dummy.update_routes(1, vec![patch(3, 3)]).await?;
+        dummy.update_routes(0, vec![patch(1, 0)]).await?;
+        dummy.set_alive(false);
+        assert_eq!(
+            routes.next().await,
+            Some(TypedEvent::Changed(0, vec![patch(1, 0)]))
+        );
+        assert_eq!(routes.next().await, Some(TypedEvent::Disconnected));
+        assert_eq!(
+            labels.next().await,
+            Some(TypedEvent::Changed(1, vec![label]))
+        );
+        assert_eq!(labels.next().await, Some(TypedEvent::Disconnected));
+
+        // Snapshots on reconnect are changes like any other.
+        dummy.set_alive(true);
+        assert_eq!(routes.next().await, Some(TypedEvent::Connected));
+        match routes.next().await {
+            Some(TypedEvent::Changed(0, rs)) => assert_eq!(rs, dummy.get_routes(0).await?),
+            ev => panic!("expected routes, got {:?}", ev),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,502 @@
+//! Videohub protocol trace of any router
+//!
+//! Wraps a [MatrixRouter], appending a [TraceEntry] as a line of JSON for every Videohub
+//! message its changes amount to: changes made through it as outbound messages, changes its
+//! events announce as inbound ones. Only changes to one matrix are traced, like a Videohub
+//! frontend serving it would exchange them. Everything passes through.
+//!
+//! A [TraceLog] can also be handed to [crate::backend::VideohubRouter], tracing the messages
+//! actually exchanged with its peer. Named so to not be confused with the `tracing` crate.
+
+use super::*;
+use anyhow::{Context, Result};
+use futures_core::stream::BoxStream;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::StreamExt;
+use tracing::error;
+use videohub::VideohubMessage;
+
+/// Which way a traced message went.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceDirection {
+    /// Received from the peer, or announced by the events of the wrapped router.
+    Inbound,
+    /// Sent to the peer, or made through the [TracingRouter].
+    Outbound,
+}
+
+/// A line of the trace log.
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TraceEntry {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub direction: TraceDirection,
+    /// The message as it is put on the wire, block terminator included.
+    pub message: String,
+}
+
+impl TraceEntry {
+    fn now(direction: TraceDirection, msg: &VideohubMessage) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        let mut message = String::new();
+        // Writing into a String can't fail.
+        let _ = msg.write_serialized_fmt(&mut message);
+        Self {
+            timestamp,
+            direction,
+            message,
+        }
+    }
+}
+
+/// What the writer task is asked to do.
+enum Op {
+    Write(TraceEntry),
+    /// Report once everything before has been written.
+    Flush(oneshot::Sender<()>),
+}
+
+/// Handle of a trace log, written in a task of its own so recording never waits on it.
+///
+/// Clones share the log, which gets flushed and closed once the last of them is gone.
+#[derive(Clone)]
+pub struct TraceLog {
+    tx: mpsc::UnboundedSender<Op>,
+    written: Arc<AtomicU64>,
+}
+
+impl TraceLog {
+    /// Trace into `writer`, this must be called within a Tokio runtime.
+    pub fn new(writer: impl AsyncWrite + Send + Unpin + 'static) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let written = Arc::new(AtomicU64::new(0));
+        tokio::spawn(write_entries(writer, rx, Arc::clone(&written)));
+        Self { tx, written }
+    }
+
+    /// Trace into the file at `path`, replacing it if it exists.
+    pub async fn create(path: &Path) -> Result<Self> {
+        let file = tokio::fs::File::create(path)
+            .await
+            .with_context(|| format!("Failed to create trace log {}", path.display()))?;
+        Ok(Self::new(file))
+    }
+
+    /// Append `msg`, having gone `direction`.
+    pub fn record(&self, direction: TraceDirection, msg: &VideohubMessage) {
+        let _ = self.tx.send(Op::Write(TraceEntry::now(direction, msg)));
+    }
+
+    /// Wait until everything recorded so far has been written.
+    pub async fn flush(&self) {
+        let (tx, rx) = oneshot::channel();
+        if self.tx.send(Op::Flush(tx)).is_ok() {
+            let _ = rx.await;
+        }
+    }
+
+    /// Number of entries written.
+    pub fn written(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+}
+
+/// Write entries coming in on `rx` as lines, until every [TraceLog] handle is gone.
+async fn write_entries(
+    mut writer: impl AsyncWrite + Unpin,
+    mut rx: mpsc::UnboundedReceiver<Op>,
+    written: Arc<AtomicU64>,
+) {
+    let mut failed = false;
+    while let Some(op) = rx.recv().await {
+        match op {
+            Op::Write(entry) if !failed => {
+                let res = async {
+                    let mut line = serde_json::to_vec(&entry)?;
+                    line.push(b'\n');
+                    writer.write_all(&line).await?;
+                    // Flushed right away, so the trace is complete up to a crash.
+                    writer.flush().await?;
+                    anyhow::Ok(())
+                };
+                match res.await {
+                    Ok(()) => {
+                        written.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e) => {
+                        error!("Failed to write trace log, tracing stopped: {:#}", e);
+                        failed = true;
+                    }
+                }
+            }
+            Op::Write(_) => {}
+            Op::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+    let _ = writer.shutdown().await;
+}
+
+/// Whether `ev` is about matrix `index`, alarms being about all of them.
+fn is_about(ev: &RouterEvent, index: u32) -> bool {
+    match ev {
+        RouterEvent::InputLabelUpdate(i, _)
+        | RouterEvent::OutputLabelUpdate(i, _)
+        | RouterEvent::InputLabelSnapshot(i, _)
+        | RouterEvent::OutputLabelSnapshot(i, _)
+        | RouterEvent::FrameLabelUpdate(i, _)
+        | RouterEvent::RouteUpdate(i, _)
+        | RouterEvent::RouteSnapshot(i, _)
+        | RouterEvent::FrameRouteUpdate(i, _)
+        | RouterEvent::LockUpdate(i, _)
+        | RouterEvent::FrameLockUpdate(i, _)
+        | RouterEvent::ProcessingUnitLockUpdate(i, _) => *i == index,
+        RouterEvent::AlarmUpdate(_) => true,
+        _ => false,
+    }
+}
+
+/// Router wrapper tracing changes of `R` as Videohub messages, see the module docs.
+#[derive(Clone)]
+pub struct TracingRouter<R> {
+    inner: R,
+    index: u32,
+    log: TraceLog,
+}
+
+impl<R: MatrixRouter> TracingRouter<R> {
+    /// Trace changes to matrix `index` of `inner` into `log`.
+    pub fn new(inner: R, index: u32, log: TraceLog) -> Self {
+        Self { inner, index, log }
+    }
+
+    /// The wrapped router.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// The log traced into.
+    pub fn log(&self) -> &TraceLog {
+        &self.log
+    }
+
+    /// Trace the message `ev` amounts to, if it is about the traced matrix and has one.
+    fn trace(&self, direction: TraceDirection, ev: RouterEvent) {
+        if !is_about(&ev, self.index) {
+            return;
+        }
+        if let Ok(msg) = VideohubMessage::try_from(ev) {
+            self.log.record(direction, &msg);
+        }
+    }
+
+    /// Trace a change to matrix `index` made through this wrapper.
+    fn outbound(&self, index: u32, ev: impl FnOnce() -> RouterEvent) {
+        if index == self.index {
+            self.trace(TraceDirection::Outbound, ev());
+        }
+    }
+}
+
+impl<R: MatrixRouter> MatrixRouter for TracingRouter<R> {
+    async fn is_alive(&self) -> Result<bool> {
+        self.inner.is_alive().await
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.inner.get_router_info().await
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        self.inner.get_matrix_info(index).await
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_input_labels(index).await
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_output_labels(index).await
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.outbound(index, || {
+            RouterEvent::InputLabelUpdate(index, changed.clone())
+        });
+        self.inner.update_input_labels(index, changed).await
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.outbound(index, || {
+            RouterEvent::OutputLabelUpdate(index, changed.clone())
+        });
+        self.inner.update_output_labels(index, changed).await
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_input_descriptions(index).await
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_output_descriptions(index).await
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_input_descriptions(index, changed).await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_output_descriptions(index, changed).await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_input_status(index).await
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_output_status(index).await
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_routes(index).await
+    }
+
+    async fn get_route_for_output(&self, index: u32, output: u32) -> Result<Option<RouterPatch>> {
+        self.inner.get_route_for_output(index, output).await
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        self.inner.get_route(index, output).await
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        self.inner.get_route_for_input(index, input).await
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.outbound(index, || RouterEvent::RouteUpdate(index, changes.clone()));
+        self.inner.update_routes(index, changes).await
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.outbound(index, || RouterEvent::RouteUpdate(index, changes.clone()));
+        self.inner.update_routes_atomic(index, changes).await
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.inner.get_levels(index).await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_level_routes(index, level).await
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        self.inner.update_level_routes(index, level, changes).await
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        self.inner.stage_routes(index, changes).await
+    }
+
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.commit(handle).await
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.discard(handle).await
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_staged_routes(index).await
+    }
+
+    async fn get_frame_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_frame_labels(index).await
+    }
+
+    async fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.outbound(index, || {
+            RouterEvent::FrameLabelUpdate(index, changed.clone())
+        });
+        self.inner.update_frame_labels(index, changed).await
+    }
+
+    async fn get_frame_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_frame_routes(index).await
+    }
+
+    async fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.outbound(index, || {
+            RouterEvent::FrameRouteUpdate(index, changes.clone())
+        });
+        self.inner.update_frame_routes(index, changes).await
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_locks(index).await
+    }
+
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.outbound(index, || RouterEvent::LockUpdate(index, changes.clone()));
+        self.inner.update_locks(index, changes).await
+    }
+
+    /// Traced into the same log, if `R` takes locks on behalf of owners.
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        Some(Self::new(
+            self.inner.with_lock_owner(owner)?,
+            self.index,
+            self.log.clone(),
+        ))
+    }
+
+    async fn get_frame_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_frame_locks(index).await
+    }
+
+    async fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.outbound(index, || {
+            RouterEvent::FrameLockUpdate(index, changes.clone())
+        });
+        self.inner.update_frame_locks(index, changes).await
+    }
+
+    async fn get_processing_unit_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_processing_unit_locks(index).await
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        self.outbound(index, || {
+            RouterEvent::ProcessingUnitLockUpdate(index, changes.clone())
+        });
+        self.inner
+            .update_processing_unit_locks(index, changes)
+            .await
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        self.inner.get_port_metadata(index, kind, id).await
+    }
+
+    async fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> Result<()> {
+        self.inner
+            .set_port_metadata(index, kind, id, metadata)
+            .await
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        self.inner.get_alarms().await
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        self.inner.capabilities()
+    }
+
+    /// Events are traced as they are delivered, every stream opened traces them again.
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        let events = self.inner.event_stream().await?;
+        Ok(Box::pin(events.map(|ev| {
+            self.trace(TraceDirection::Inbound, ev.clone());
+            ev
+        })))
+    }
+}
+
+impl<R: RouterIntrospect> RouterIntrospect for TracingRouter<R> {
+    fn name(&self) -> &'static str {
+        "TracingRouter"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        vec![("index".into(), self.index.to_string())]
+    }
+
+    fn counters(&self) -> Vec<(String, u64)> {
+        vec![("traced".into(), self.log.written())]
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        Some(&self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Entries in the trace log at `path`.
+    fn read_trace(path: &Path) -> Result<Vec<TraceEntry>> {
+        std::fs::read_to_string(path)?
+            .lines()
+            .map(|l| Ok(serde_json::from_str(l)?))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn traces_changes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("trace.jsonl");
+        let dummy = DummyRouter::with_config(2, 4, 4);
+        let router = TracingRouter::new(dummy.clone(), 0, TraceLog::create(&path).await?);
+        let mut events = router.event_stream().await?;
+        assert_eq!(events.next().await, Some(RouterEvent::Connected));
+
+        let patch = RouterPatch {
+            from_input: Some(2),
+            to_output: 1,
+        };
+        router.update_routes(0, vec![patch]).await?;
+        assert_eq!(
+            events.next().await,
+            Some(RouterEvent::RouteUpdate(0, vec![patch]))
+        );
+        // Other matrices aren't traced.
+        router.update_routes(1, vec![patch]).await?;
+        events.next().await;
+        router.log().flush().await;
+        drop(events);
+        drop(router);
+
+        let entries = read_trace(&path)?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].direction, TraceDirection::Outbound);
+        assert_eq!(entries[0].message, "VIDEO OUTPUT ROUTING:\n1 2\n\n");
+        assert_eq!(entries[1].direction, TraceDirection::Inbound);
+        assert_eq!(entries[1].message, "VIDEO OUTPUT ROUTING:\n1 2\n\n");
+        assert!(entries[0].timestamp <= entries[1].timestamp);
+        Ok(())
+    }
+}
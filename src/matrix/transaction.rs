@@ -0,0 +1,219 @@
+//! Route transactions
+//!
+//! Applies a set of patches with any [MatrixRouter], one by one, rolling back what already
+//! applied once one fails. Unlike [MatrixRouter::update_routes], which may stop midway on
+//! some routers, the caller learns exactly which patches took effect and which were undone.
+
+use super::*;
+use anyhow::{anyhow, Result};
+
+/// Patches to apply all or not at all, see [apply_transaction].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RouteTransaction {
+    pub patches: Vec<RouterPatch>,
+}
+
+impl RouteTransaction {
+    pub fn new(patches: Vec<RouterPatch>) -> Self {
+        Self { patches }
+    }
+
+    /// Add a patch of `from_input` to `to_output`, applied after those added before.
+    pub fn patch(mut self, from_input: u32, to_output: u32) -> Self {
+        self.patches.push(RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        });
+        self
+    }
+
+    /// Add a patch parking `to_output`, applied after those added before.
+    pub fn park(mut self, to_output: u32) -> Self {
+        self.patches.push(RouterPatch::parked(to_output));
+        self
+    }
+}
+
+impl From<Vec<RouterPatch>> for RouteTransaction {
+    fn from(patches: Vec<RouterPatch>) -> Self {
+        Self::new(patches)
+    }
+}
+
+/// What became of a [RouteTransaction].
+#[derive(Debug, Default)]
+pub struct TransactionOutcome {
+    /// Patches of the transaction that applied, in order.
+    pub applied: Vec<RouterPatch>,
+    /// Patches restoring the routes from before the transaction, in the order they applied.
+    pub rolled_back: Vec<RouterPatch>,
+    /// The patch that failed, followed by any restoring patches that failed as well.
+    /// Applied patches to outputs the snapshot had no route for can't be undone, they're
+    /// listed here too.
+    pub failed: Vec<(RouterPatch, anyhow::Error)>,
+}
+
+impl TransactionOutcome {
+    /// Whether every patch of the transaction applied.
+    pub fn is_committed(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Whether a failed transaction was undone, the routes being back to where they were.
+    pub fn is_rolled_back(&self) -> bool {
+        self.failed.len() == 1
+    }
+}
+
+/// Apply `transaction` to matrix `index` of `router`, rolling back on failure.
+///
+/// The current routes are snapshot first, failing to get them fails before anything is
+/// applied. A failing patch doesn't fail the call, but is reported in the outcome along with
+/// the rollback. Changes others make to the same outputs meanwhile get overwritten by it.
+/// Outputs missing from the snapshot are left as they are and count as failed to roll back.
+pub async fn apply_transaction<R: MatrixRouter>(
+    router: &R,
+    index: u32,
+    transaction: RouteTransaction,
+) -> Result<TransactionOutcome> {
+    let snapshot = router.get_routes(index).await?;
+    let mut outcome = TransactionOutcome::default();
+    for p in transaction.patches {
+        if let Err(e) = router.update_routes(index, vec![p]).await {
+            outcome.failed.push((p, e));
+            break;
+        }
+        outcome.applied.push(p);
+    }
+    if outcome.is_committed() {
+        return Ok(outcome);
+    }
+
+    // Undo the latest patch of each output first, restoring what the snapshot had.
+    let mut undone = Vec::new();
+    for p in outcome.applied.iter().rev() {
+        if undone.contains(&p.to_output) {
+            continue;
+        }
+        undone.push(p.to_output);
+        let Some(before) = snapshot.iter().find(|s| s.to_output == p.to_output) else {
+            let e = anyhow!("No route of output {} to restore", p.to_output);
+            outcome.failed.push((*p, e));
+            continue;
+        };
+        match router.update_routes(index, vec![*before]).await {
+            Ok(()) => outcome.rolled_back.push(*before),
+            Err(e) => outcome.failed.push((*before, e)),
+        }
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn applies_everything() -> Result<()> {
+        let router = DummyRouter::with_config(1, 4, 4);
+        let outcome = apply_transaction(
+            &router,
+            0,
+            RouteTransaction::default().patch(1, 0).patch(2, 1),
+        )
+        .await?;
+        assert!(outcome.is_committed());
+        assert_eq!(outcome.applied.len(), 2);
+        assert!(outcome.rolled_back.is_empty());
+        let routes = router.get_routes(0).await?;
+        assert_eq!(
+            (routes[0].from_input, routes[1].from_input),
+            (Some(1), Some(2))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rolls_back_on_failure() -> Result<()> {
+        let router = DummyRouter::with_config(1, 4, 4);
+        apply_transaction(&router, 0, RouteTransaction::default().patch(0, 3)).await?;
+        let before = router.get_routes(0).await?;
+
+        // Output 0 gets patched twice, the out of range input fails midway.
+        let transaction = RouteTransaction::default()
+            .patch(1, 0)
+            .patch(2, 1)
+            .patch(2, 0)
+            .patch(9, 2)
+            .patch(3, 3);
+        let outcome = apply_transaction(&router, 0, transaction).await?;
+        assert!(!outcome.is_committed());
+        assert!(outcome.is_rolled_back());
+        assert_eq!(outcome.applied.len(), 3);
+        assert_eq!(outcome.failed.len(), 1);
+        assert_eq!(outcome.failed[0].0.from_input, Some(9));
+        assert_eq!(outcome.rolled_back.len(), 2);
+        assert_eq!(router.get_routes(0).await?, before);
+        Ok(())
+    }
+
+    /// A router leaving output 1 out of its routes.
+    struct SparseRouter {
+        inner: DummyRouter,
+    }
+
+    impl MatrixRouter for SparseRouter {
+        async fn is_alive(&self) -> Result<bool> {
+            self.inner.is_alive().await
+        }
+        async fn get_router_info(&self) -> Result<RouterInfo> {
+            self.inner.get_router_info().await
+        }
+        async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+            self.inner.get_matrix_info(index).await
+        }
+        async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+            self.inner.get_input_labels(index).await
+        }
+        async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+            self.inner.get_output_labels(index).await
+        }
+        async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+            self.inner.update_input_labels(index, changed).await
+        }
+        async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+            self.inner.update_output_labels(index, changed).await
+        }
+        async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+            let mut routes = self.inner.get_routes(index).await?;
+            routes.retain(|p| p.to_output != 1);
+            Ok(routes)
+        }
+        async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+            self.inner.update_routes(index, changes).await
+        }
+        async fn event_stream<'a>(
+            &'a self,
+        ) -> Result<futures_core::stream::BoxStream<'a, RouterEvent>> {
+            self.inner.event_stream().await
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_routes_fail_rollback() -> Result<()> {
+        let router = SparseRouter {
+            inner: DummyRouter::with_config(1, 4, 4),
+        };
+        let transaction = RouteTransaction::default()
+            .patch(1, 0)
+            .patch(2, 1)
+            .patch(9, 2);
+        let outcome = apply_transaction(&router, 0, transaction).await?;
+        assert!(!outcome.is_rolled_back());
+        assert_eq!(outcome.rolled_back.len(), 1);
+        assert_eq!(outcome.failed.len(), 2);
+        assert_eq!(outcome.failed[1].0.to_output, 1);
+        assert_eq!(router.inner.get_routes(0).await?[0].from_input, Some(0));
+        Ok(())
+    }
+}
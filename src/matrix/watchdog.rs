@@ -0,0 +1,584 @@
+//! Connection watchdog for any router
+//!
+//! Wraps a [MatrixRouter], polling [MatrixRouter::is_alive] on an interval and announcing
+//! [RouterEvent::Connected] and [RouterEvent::Disconnected] on its own, so clients get the same
+//! connectivity signals whichever backend sits below. Backends announcing these themselves are
+//! taken at their word, as are pings answered in time. A ping failing, erroring or not answered
+//! within the interval only counts against `R`, it's declared gone after a number of those in a
+//! row. Everything else passes through.
+
+use super::*;
+use anyhow::Result;
+use futures_core::stream::BoxStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+
+/// What the watchdog makes of `R`.
+struct Health {
+    alive: bool,
+    /// Pings failed in a row.
+    failures: u32,
+}
+
+/// State shared by the clones of a [Watchdog] and its poller.
+struct Shared {
+    health: Mutex<Health>,
+    transitions: broadcast::Sender<RouterEvent>,
+    /// Number of pings failed and transitions announced, respectively.
+    failed_pings: AtomicU64,
+    flips: AtomicU64,
+}
+
+impl Shared {
+    /// Announce `alive` unless it's the state already.
+    fn transition(&self, health: &mut Health, alive: bool) {
+        if health.alive == alive {
+            return;
+        }
+        health.alive = alive;
+        self.flips.fetch_add(1, Ordering::Relaxed);
+        let ev = if alive {
+            info!("Router is back");
+            RouterEvent::Connected
+        } else {
+            warn!("Router is gone");
+            RouterEvent::Disconnected
+        };
+        // Nobody subscribed is fine.
+        let _ = self.transitions.send(ev);
+    }
+
+    /// Account for a ping, declaring `R` gone after `threshold` failures in a row.
+    fn ping(&self, ok: bool, threshold: u32) {
+        let mut health = self.health.lock().unwrap();
+        if ok {
+            health.failures = 0;
+            self.transition(&mut health, true);
+        } else {
+            health.failures += 1;
+            self.failed_pings.fetch_add(1, Ordering::Relaxed);
+            if health.failures >= threshold {
+                self.transition(&mut health, false);
+            }
+        }
+    }
+
+    /// `R` announced coming or going itself.
+    fn announce(&self, alive: bool) {
+        let mut health = self.health.lock().unwrap();
+        health.failures = 0;
+        self.transition(&mut health, alive);
+    }
+}
+
+/// Aborts the poller once the last clone of a [Watchdog] is gone.
+struct Poller(JoinHandle<()>);
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Router wrapper watching over the liveness of `R`, see the module docs.
+///
+/// `R` counts as gone until the first ping is answered or it announces
+/// [RouterEvent::Connected].
+#[derive(Clone)]
+pub struct Watchdog<R> {
+    inner: R,
+    interval: Duration,
+    threshold: u32,
+    shared: Arc<Shared>,
+    _poller: Arc<Poller>,
+}
+
+impl<R> Watchdog<R>
+where
+    R: MatrixRouter + Clone + 'static,
+{
+    /// Ping `inner` every `interval`, declaring it gone after `threshold` failed pings in a row.
+    /// A `threshold` of `0` counts as `1`.
+    ///
+    /// Polls in a task of its own, so this must be called within a Tokio runtime.
+    pub fn new(inner: R, interval: Duration, threshold: u32) -> Self {
+        let threshold = threshold.max(1);
+        let shared = Arc::new(Shared {
+            health: Mutex::new(Health {
+                alive: false,
+                failures: 0,
+            }),
+            transitions: broadcast::channel(16).0,
+            failed_pings: AtomicU64::new(0),
+            flips: AtomicU64::new(0),
+        });
+        let poller = tokio::spawn(watch(
+            inner.clone(),
+            interval,
+            threshold,
+            Arc::clone(&shared),
+        ));
+        Self {
+            inner,
+            interval,
+            threshold,
+            shared,
+            _poller: Arc::new(Poller(poller)),
+        }
+    }
+}
+
+impl<R: MatrixRouter> Watchdog<R> {
+    /// The wrapped router.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+}
+
+/// Ping `inner` every `interval`, minding what it announces itself meanwhile.
+async fn watch(inner: impl MatrixRouter, interval: Duration, threshold: u32, shared: Arc<Shared>) {
+    let mut events = match inner.event_stream().await {
+        Ok(events) => Some(events),
+        Err(e) => {
+            warn!("No events to watch, pinging only: {:#}", e);
+            None
+        }
+    };
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                let ping = tokio::time::timeout(interval, inner.is_alive()).await;
+                shared.ping(matches!(ping, Ok(Ok(true))), threshold);
+            }
+            ev = next_event(&mut events) => match ev {
+                Some(RouterEvent::Connected) => shared.announce(true),
+                Some(RouterEvent::Disconnected) => shared.announce(false),
+                Some(_) => {}
+                None => events = None,
+            },
+        }
+    }
+}
+
+/// Next of `events`, never if there are none.
+async fn next_event(events: &mut Option<BoxStream<'_, RouterEvent>>) -> Option<RouterEvent> {
+    match events {
+        Some(events) => events.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+impl<R: MatrixRouter> MatrixRouter for Watchdog<R> {
+    /// Whether the watchdog considers `R` alive, without asking it.
+    async fn is_alive(&self) -> Result<bool> {
+        Ok(self.shared.health.lock().unwrap().alive)
+    }
+
+    async fn get_router_info(&self) -> Result<RouterInfo> {
+        self.inner.get_router_info().await
+    }
+
+    async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+        self.inner.get_matrix_info(index).await
+    }
+
+    async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_input_labels(index).await
+    }
+
+    async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_output_labels(index).await
+    }
+
+    async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_input_labels(index, changed).await
+    }
+
+    async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_output_labels(index, changed).await
+    }
+
+    async fn get_input_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_input_descriptions(index).await
+    }
+
+    async fn get_output_descriptions(&self, index: u32) -> Result<Vec<RouterDescription>> {
+        self.inner.get_output_descriptions(index).await
+    }
+
+    async fn update_input_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_input_descriptions(index, changed).await
+    }
+
+    async fn update_output_descriptions(
+        &self,
+        index: u32,
+        changed: Vec<RouterDescription>,
+    ) -> Result<()> {
+        self.inner.update_output_descriptions(index, changed).await
+    }
+
+    async fn get_input_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_input_status(index).await
+    }
+
+    async fn get_output_status(&self, index: u32) -> Result<Vec<RouterPortStatus>> {
+        self.inner.get_output_status(index).await
+    }
+
+    async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_routes(index).await
+    }
+
+    async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+        self.inner.get_route(index, output).await
+    }
+
+    async fn get_route_for_input(&self, index: u32, input: u32) -> Result<Vec<u32>> {
+        self.inner.get_route_for_input(index, input).await
+    }
+
+    async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.inner.update_routes(index, changes).await
+    }
+
+    async fn update_routes_atomic(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.inner.update_routes_atomic(index, changes).await
+    }
+
+    async fn get_levels(&self, index: u32) -> Result<Vec<RouterLevel>> {
+        self.inner.get_levels(index).await
+    }
+
+    async fn get_level_routes(&self, index: u32, level: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_level_routes(index, level).await
+    }
+
+    async fn update_level_routes(
+        &self,
+        index: u32,
+        level: u32,
+        changes: Vec<RouterPatch>,
+    ) -> Result<()> {
+        self.inner.update_level_routes(index, level, changes).await
+    }
+
+    async fn stage_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<TakeHandle> {
+        self.inner.stage_routes(index, changes).await
+    }
+
+    async fn commit(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.commit(handle).await
+    }
+
+    async fn discard(&self, handle: TakeHandle) -> Result<()> {
+        self.inner.discard(handle).await
+    }
+
+    async fn get_staged_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_staged_routes(index).await
+    }
+
+    async fn get_frame_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+        self.inner.get_frame_labels(index).await
+    }
+
+    async fn update_frame_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+        self.inner.update_frame_labels(index, changed).await
+    }
+
+    async fn get_frame_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+        self.inner.get_frame_routes(index).await
+    }
+
+    async fn update_frame_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+        self.inner.update_frame_routes(index, changes).await
+    }
+
+    async fn get_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_locks(index).await
+    }
+
+    async fn update_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.inner.update_locks(index, changes).await
+    }
+
+    /// Watched along with this handle, if `R` takes locks on behalf of owners.
+    fn with_lock_owner(&self, owner: &str) -> Option<Self> {
+        Some(Self {
+            inner: self.inner.with_lock_owner(owner)?,
+            interval: self.interval,
+            threshold: self.threshold,
+            shared: self.shared.clone(),
+            _poller: self._poller.clone(),
+        })
+    }
+
+    async fn get_frame_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_frame_locks(index).await
+    }
+
+    async fn update_frame_locks(&self, index: u32, changes: Vec<RouterLock>) -> Result<()> {
+        self.inner.update_frame_locks(index, changes).await
+    }
+
+    async fn get_processing_unit_locks(&self, index: u32) -> Result<Vec<RouterLock>> {
+        self.inner.get_processing_unit_locks(index).await
+    }
+
+    async fn update_processing_unit_locks(
+        &self,
+        index: u32,
+        changes: Vec<RouterLock>,
+    ) -> Result<()> {
+        self.inner
+            .update_processing_unit_locks(index, changes)
+            .await
+    }
+
+    async fn get_port_metadata(&self, index: u32, kind: PortKind, id: u32) -> Result<PortMetadata> {
+        self.inner.get_port_metadata(index, kind, id).await
+    }
+
+    async fn set_port_metadata(
+        &self,
+        index: u32,
+        kind: PortKind,
+        id: u32,
+        metadata: PortMetadata,
+    ) -> Result<()> {
+        self.inner
+            .set_port_metadata(index, kind, id, metadata)
+            .await
+    }
+
+    async fn get_alarms(&self) -> Result<Vec<RouterAlarm>> {
+        self.inner.get_alarms().await
+    }
+
+    fn capabilities(&self) -> RouterCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+        let events = self.inner.event_stream().await?;
+        // Subscribe and look at the state at once, so no transition is missed or doubled.
+        let (alive, transitions) = {
+            let health = self.shared.health.lock().unwrap();
+            (health.alive, self.shared.transitions.subscribe())
+        };
+        // The backend ending its stream ends ours, too.
+        let events = events
+            .filter(|ev| !matches!(ev, RouterEvent::Connected | RouterEvent::Disconnected))
+            .map(Some)
+            .chain(tokio_stream::once(None));
+        let transitions = BroadcastStream::new(transitions).filter_map(|ev| ev.ok().map(Some));
+        let merged = events.merge(transitions).map_while(|ev| ev);
+        Ok(Box::pin(
+            tokio_stream::iter(alive.then_some(RouterEvent::Connected)).chain(merged),
+        ))
+    }
+}
+
+impl<R: RouterIntrospect> RouterIntrospect for Watchdog<R> {
+    fn name(&self) -> &'static str {
+        "Watchdog"
+    }
+
+    fn config_summary(&self) -> Vec<(String, String)> {
+        vec![
+            ("interval".into(), format!("{:?}", self.interval)),
+            ("threshold".into(), self.threshold.to_string()),
+        ]
+    }
+
+    fn counters(&self) -> Vec<(String, u64)> {
+        vec![
+            (
+                "failed_pings".into(),
+                self.shared.failed_pings.load(Ordering::Relaxed),
+            ),
+            ("flips".into(), self.shared.flips.load(Ordering::Relaxed)),
+        ]
+    }
+
+    fn inner(&self) -> Option<&dyn RouterIntrospect> {
+        Some(&self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+
+    const INTERVAL: Duration = Duration::from_millis(20);
+
+    /// A [DummyRouter] keeping quiet about coming and going, answering the next `stalled` pings
+    /// only after a while.
+    #[derive(Clone)]
+    struct SilentRouter {
+        inner: DummyRouter,
+        stalled: Arc<AtomicU32>,
+    }
+
+    impl MatrixRouter for SilentRouter {
+        async fn is_alive(&self) -> Result<bool> {
+            let stall = self
+                .stalled
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            if stall {
+                tokio::time::sleep(INTERVAL * 2).await;
+            }
+            self.inner.is_alive().await
+        }
+        async fn get_router_info(&self) -> Result<RouterInfo> {
+            self.inner.get_router_info().await
+        }
+        async fn get_matrix_info(&self, index: u32) -> Result<RouterMatrixInfo> {
+            self.inner.get_matrix_info(index).await
+        }
+        async fn get_input_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+            self.inner.get_input_labels(index).await
+        }
+        async fn get_output_labels(&self, index: u32) -> Result<Vec<RouterLabel>> {
+            self.inner.get_output_labels(index).await
+        }
+        async fn update_input_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+            self.inner.update_input_labels(index, changed).await
+        }
+        async fn update_output_labels(&self, index: u32, changed: Vec<RouterLabel>) -> Result<()> {
+            self.inner.update_output_labels(index, changed).await
+        }
+        async fn get_routes(&self, index: u32) -> Result<Vec<RouterPatch>> {
+            self.inner.get_routes(index).await
+        }
+        async fn get_route(&self, index: u32, output: u32) -> Result<RouterPatch> {
+            self.inner.get_route(index, output).await
+        }
+        async fn update_routes(&self, index: u32, changes: Vec<RouterPatch>) -> Result<()> {
+            self.inner.update_routes(index, changes).await
+        }
+        async fn event_stream<'a>(&'a self) -> Result<BoxStream<'a, RouterEvent>> {
+            let events = self.inner.event_stream().await?;
+            Ok(Box::pin(events.filter(|ev| {
+                !matches!(ev, RouterEvent::Connected | RouterEvent::Disconnected)
+            })))
+        }
+    }
+
+    fn silent() -> (DummyRouter, Arc<AtomicU32>, SilentRouter) {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let stalled = Arc::new(AtomicU32::new(0));
+        let router = SilentRouter {
+            inner: dummy.clone(),
+            stalled: Arc::clone(&stalled),
+        };
+        (dummy, stalled, router)
+    }
+
+    fn patch(from_input: u32, to_output: u32) -> RouterPatch {
+        RouterPatch {
+            from_input: Some(from_input),
+            to_output,
+        }
+    }
+
+    /// Next event of `events`, `None` if there's none for a while.
+    async fn next(events: &mut BoxStream<'_, RouterEvent>) -> Option<RouterEvent> {
+        tokio::time::timeout(INTERVAL * 10, events.next())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    #[tokio::test]
+    async fn announces_silent_backends() -> Result<()> {
+        let (dummy, _, silent) = silent();
+        let router = Watchdog::new(silent, INTERVAL, 2);
+        let mut events = router.event_stream().await?;
+        assert_eq!(next(&mut events).await, Some(RouterEvent::Connected));
+        assert!(router.is_alive().await?);
+
+        dummy.set_alive(false);
+        assert_eq!(next(&mut events).await, Some(RouterEvent::Disconnected));
+        assert!(!router.is_alive().await?);
+        // Snapshots of the backend coming back may well beat the next ping.
+        dummy.set_alive(true);
+        let mut ev = next(&mut events).await;
+        while ev.as_ref().is_some_and(RouterEvent::is_snapshot) {
+            ev = next(&mut events).await;
+        }
+        assert_eq!(ev, Some(RouterEvent::Connected));
+
+        // Subscribing late starts off with the current state.
+        let mut late = router.event_stream().await?;
+        assert_eq!(next(&mut late).await, Some(RouterEvent::Connected));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn single_slow_ping_does_not_flap() -> Result<()> {
+        let (dummy, stalled, silent) = silent();
+        let router = Watchdog::new(silent, INTERVAL, 2);
+        let mut events = router.event_stream().await?;
+        assert_eq!(next(&mut events).await, Some(RouterEvent::Connected));
+
+        stalled.store(1, Ordering::SeqCst);
+        dummy.update_routes(0, vec![patch(1, 0)]).await?;
+        assert_eq!(
+            next(&mut events).await,
+            Some(RouterEvent::RouteUpdate(0, vec![patch(1, 0)]))
+        );
+        assert_eq!(next(&mut events).await, None);
+        assert_eq!(stalled.load(Ordering::SeqCst), 0);
+        assert!(router.is_alive().await?);
+
+        // Once in a row too often is gone, though.
+        stalled.store(2, Ordering::SeqCst);
+        assert_eq!(next(&mut events).await, Some(RouterEvent::Disconnected));
+        assert_eq!(next(&mut events).await, Some(RouterEvent::Connected));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dedupes_backend_announcements() -> Result<()> {
+        let dummy = DummyRouter::with_config(1, 2, 2);
+        let router = Watchdog::new(dummy.clone(), INTERVAL, 3);
+        let mut events = router.event_stream().await?;
+        assert_eq!(next(&mut events).await, Some(RouterEvent::Connected));
+
+        // Announced by the backend right away, no need to wait for pings to fail.
+        dummy.set_alive(false);
+        assert_eq!(
+            tokio::time::timeout(INTERVAL, events.next()).await?,
+            Some(RouterEvent::Disconnected)
+        );
+        assert_eq!(next(&mut events).await, None);
+
+        dummy.set_alive(true);
+        let mut connected = 0;
+        while let Some(ev) = next(&mut events).await {
+            match ev {
+                RouterEvent::Connected => connected += 1,
+                ev => assert!(ev.is_snapshot(), "unexpected {:?}", ev),
+            }
+        }
+        assert_eq!(connected, 1);
+        Ok(())
+    }
+}
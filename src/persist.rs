@@ -0,0 +1,77 @@
+//! JSON state files surviving restarts and crashes
+
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Read the JSON state at `path`, `None` if there is none yet.
+pub(crate) fn load<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    match std::fs::read(path) {
+        Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write `value` as JSON to `path`, so that it holds either the old or the new state even
+/// if the host goes down meanwhile.
+///
+/// The data goes to a temporary file next to `path` first, which is synced to disk before
+/// it's renamed over `path`. The directory is synced afterwards, so the rename sticks too.
+/// This blocks, see [store_async] for async code.
+pub(crate) fn store<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    write_synced(path, &serde_json::to_vec_pretty(value)?)
+}
+
+/// [store] without blocking the runtime.
+pub(crate) async fn store_async<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    let data = serde_json::to_vec_pretty(value)?;
+    let path = path.to_owned();
+    tokio::task::spawn_blocking(move || write_synced(&path, &data)).await?
+}
+
+fn write_synced(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp, path)?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn store_and_load() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("state.json");
+        assert_eq!(load::<Vec<u32>>(&path)?, None);
+
+        store(&path, &vec![1, 2, 3])?;
+        assert_eq!(load::<Vec<u32>>(&path)?, Some(vec![1, 2, 3]));
+        store_async(&path, &vec![4]).await?;
+        assert_eq!(load::<Vec<u32>>(&path)?, Some(vec![4]));
+
+        // Only the state is left behind, no temporary file.
+        let files: Vec<_> = std::fs::read_dir(dir.path())?
+            .map(|e| e.map(|e| e.file_name()))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(files, vec!["state.json"]);
+
+        std::fs::write(&path, "{")?;
+        assert!(load::<Vec<u32>>(&path).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use futures_util::{SinkExt, StreamExt};
+use std::net::SocketAddr;
+use tokio::{
+    net::TcpListener,
+    select,
+    sync::mpsc,
+    time::{timeout, Duration},
+};
+use tokio_util::codec::Framed;
+use videohub::{DeviceInfo, Preamble, Present, VideohubCodec, VideohubMessage};
+
+/// How long [MockVideohubServer::expect_received] waits.
+const EXPECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+enum Command {
+    Send(VideohubMessage),
+    SendRaw(Vec<u8>),
+    Close,
+}
+
+/// A scripted Videohub peer, for testing clients against exact message sequences.
+///
+/// Every accepted client gets the script played back first. Afterwards, the test drives
+/// the current connection through [Self::send], [Self::send_raw] and [Self::close],
+/// and checks what the client sent with [Self::expect_received]. Nothing gets answered
+/// on its own, not even pings.
+pub struct MockVideohubServer {
+    addr: SocketAddr,
+    cmd_tx: mpsc::UnboundedSender<Command>,
+    received_rx: mpsc::UnboundedReceiver<VideohubMessage>,
+}
+
+impl MockVideohubServer {
+    /// Listen on an ephemeral port, playing back `script` to each client.
+    pub async fn start(script: Vec<VideohubMessage>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (cmd_tx, mut cmd_rx) = mpsc::unbounded_channel();
+        let (received_tx, received_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let mut framed = Framed::new(socket, VideohubCodec::default());
+                for msg in &script {
+                    framed.send(msg.clone()).await.unwrap();
+                }
+                loop {
+                    select! {
+                        cmd = cmd_rx.recv() => match cmd {
+                            Some(Command::Send(msg)) => framed.send(msg).await.unwrap(),
+                            Some(Command::SendRaw(bytes)) => {
+                                framed.write_buffer_mut().extend_from_slice(&bytes);
+                                framed.flush().await.unwrap();
+                            }
+                            Some(Command::Close) => break,
+                            None => return,
+                        },
+                        msg = framed.next() => match msg {
+                            Some(Ok(msg)) => {
+                                let _ = received_tx.send(msg);
+                            }
+                            _ => break,
+                        },
+                    }
+                }
+            }
+        });
+        Self {
+            addr,
+            cmd_tx,
+            received_rx,
+        }
+    }
+
+    /// Script of a hub with the given size: preamble, device info and end of prelude.
+    pub fn handshake(inputs: u32, outputs: u32) -> Vec<VideohubMessage> {
+        vec![
+            VideohubMessage::Preamble(Preamble {
+                version: "2.7".into(),
+            }),
+            VideohubMessage::DeviceInfo(DeviceInfo {
+                present: Some(Present::Yes),
+                video_inputs: Some(inputs),
+                video_outputs: Some(outputs),
+                ..Default::default()
+            }),
+            VideohubMessage::EndPrelude,
+        ]
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Send `msg` to the connected client.
+    pub fn send(&self, msg: VideohubMessage) {
+        self.cmd_tx.send(Command::Send(msg)).unwrap();
+    }
+
+    /// Send bytes as is, e.g. a malformed block.
+    pub fn send_raw(&self, bytes: &[u8]) {
+        self.cmd_tx.send(Command::SendRaw(bytes.to_vec())).unwrap();
+    }
+
+    /// Drop the connected client without further ado, it sees EOF.
+    pub fn close(&self) {
+        self.cmd_tx.send(Command::Close).unwrap();
+    }
+
+    /// Wait for the next message the client sent.
+    ///
+    /// Panics if nothing arrives within a second.
+    pub async fn next_received(&mut self) -> VideohubMessage {
+        match timeout(EXPECT_TIMEOUT, self.received_rx.recv()).await {
+            Ok(Some(msg)) => msg,
+            _ => panic!("Expected a message from the client"),
+        }
+    }
+
+    /// Wait until the client sent `msg`, skipping everything it sent before.
+    ///
+    /// Panics if it doesn't arrive within a second.
+    pub async fn expect_received(&mut self, msg: VideohubMessage) {
+        let mut skipped = Vec::new();
+        loop {
+            match timeout(EXPECT_TIMEOUT, self.received_rx.recv()).await {
+                Ok(Some(got)) if got == msg => return,
+                Ok(Some(got)) => skipped.push(got),
+                _ => panic!("Expected {:?}, only got {:?}", msg, skipped),
+            }
+        }
+    }
+}
@@ -0,0 +1,5 @@
+//! Helpers shared by tests across modules.
+
+mod mock_server;
+
+pub use mock_server::MockVideohubServer;
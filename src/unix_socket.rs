@@ -0,0 +1,73 @@
+//! Listening UNIX sockets with restricted permissions
+
+use anyhow::{anyhow, Result};
+use std::{
+    fs::DirBuilder,
+    io::ErrorKind,
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::Path,
+};
+use tokio::net::UnixListener;
+
+/// Bind a UNIX socket at `path` with permissions `mode`, like `0o600` for the current user only.
+///
+/// The socket is created in a private directory next to `path` and only moved into place once
+/// its permissions are set, so nobody else can connect in between. A stale socket at `path` is
+/// replaced, anything else there is left alone and fails the bind.
+pub(crate) fn bind_with_mode(path: &Path, mode: u32) -> Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {}
+        Ok(_) => return Err(anyhow!("{} exists and is not a socket", path.display())),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} is not a file path", path.display()))?;
+    let dir = path.with_file_name(format!(
+        ".{}.{}.bind",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    DirBuilder::new().mode(0o700).create(&dir)?;
+    let tmp = dir.join("socket");
+    let bound = UnixListener::bind(&tmp).and_then(|listener| {
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(mode))?;
+        std::fs::rename(&tmp, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_file(&tmp);
+    std::fs::remove_dir(&dir)?;
+    Ok(bound?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn replaces_only_sockets() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("test.sock");
+
+        let first = bind_with_mode(&path, 0o600)?;
+        let mode = std::fs::metadata(&path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        drop(first);
+
+        // The stale socket gets replaced and the new one takes connections.
+        let listener = bind_with_mode(&path, 0o660)?;
+        let mode = std::fs::metadata(&path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        tokio::net::UnixStream::connect(&path).await?;
+        listener.accept().await?;
+
+        // Anything else is kept.
+        let file = dir.path().join("precious");
+        std::fs::write(&file, "data")?;
+        assert!(bind_with_mode(&file, 0o600).is_err());
+        assert_eq!(std::fs::read_to_string(&file)?, "data");
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 2);
+        Ok(())
+    }
+}
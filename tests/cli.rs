@@ -0,0 +1,133 @@
+#![cfg(feature = "cli")]
+
+use assert_cmd::{cargo::CommandCargoExt, Command};
+use omnimatrix::{
+    frontend::VideohubFrontend,
+    matrix::{DummyRouter, MatrixRouter, RouterEvent, RouterPatch},
+};
+use std::{
+    io::{BufRead, BufReader},
+    net::SocketAddr,
+    process::Stdio,
+    sync::Arc,
+};
+use tokio::{net::TcpListener, task::spawn_blocking};
+
+/// Serve a 4x2 dummy on an ephemeral port.
+async fn serve() -> (Arc<DummyRouter>, SocketAddr) {
+    let router = Arc::new(DummyRouter::with_config(1, 4, 2));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let frontend = VideohubFrontend::new(router.clone(), 0);
+    tokio::spawn(frontend.serve(listener));
+    (router, addr)
+}
+
+/// Run the CLI against `addr`, returning its stdout.
+async fn cli(addr: SocketAddr, args: &[&str]) -> String {
+    let mut cmd = Command::cargo_bin("omnimatrix-cli").unwrap();
+    cmd.arg("connect").arg(addr.to_string()).args(args);
+    let out = spawn_blocking(move || cmd.assert().success().get_output().stdout.clone())
+        .await
+        .unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[tokio::test]
+async fn list() {
+    let (_router, addr) = serve().await;
+    assert_eq!(
+        cli(addr, &["list-inputs"]).await,
+        "0\tInput 1\n1\tInput 2\n2\tInput 3\n3\tInput 4\n"
+    );
+    assert_eq!(
+        cli(addr, &["list-outputs"]).await,
+        "0\tOutput 1\n1\tOutput 2\n"
+    );
+    assert_eq!(cli(addr, &["list-routes"]).await, "0\t0\n1\t0\n");
+    assert!(cli(addr, &[]).await.contains("inputs\t4\noutputs\t2\n"));
+}
+
+#[tokio::test]
+async fn patch_and_rename() {
+    let (router, addr) = serve().await;
+    cli(addr, &["patch", "1", "3"]).await;
+    assert_eq!(
+        router.get_routes(0).await.unwrap()[1],
+        RouterPatch {
+            from_input: Some(3),
+            to_output: 1
+        }
+    );
+
+    cli(addr, &["rename-input", "2", "Camera 3"]).await;
+    cli(addr, &["rename-output", "0", "Program"]).await;
+    assert_eq!(
+        router.get_input_labels(0).await.unwrap()[2].name,
+        "Camera 3"
+    );
+    assert_eq!(
+        router.get_output_labels(0).await.unwrap()[0].name,
+        "Program"
+    );
+}
+
+#[tokio::test]
+async fn out_of_range_fails() {
+    let (_router, addr) = serve().await;
+    let mut cmd = Command::cargo_bin("omnimatrix-cli").unwrap();
+    cmd.arg("connect")
+        .arg(addr.to_string())
+        .args(["patch", "7", "0"]);
+    spawn_blocking(move || {
+        cmd.assert().failure();
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn watch_prints_json() {
+    let (router, addr) = serve().await;
+    let mut child = std::process::Command::cargo_bin("omnimatrix-cli")
+        .unwrap()
+        .arg("connect")
+        .arg(addr.to_string())
+        .arg("watch")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+
+    let first = spawn_blocking(move || {
+        let first = lines.next().unwrap().unwrap();
+        (first, lines)
+    });
+    let (first, mut lines) = first.await.unwrap();
+    assert_eq!(
+        serde_json::from_str::<RouterEvent>(&first).unwrap(),
+        RouterEvent::Connected
+    );
+
+    let patch = RouterPatch {
+        from_input: Some(2),
+        to_output: 0,
+    };
+    router.update_routes(0, vec![patch]).await.unwrap();
+    // Answers to the prefetch requests may still come in, restating the tables from before.
+    let ev = spawn_blocking(move || loop {
+        let line = lines.next().unwrap().unwrap();
+        match serde_json::from_str::<RouterEvent>(&line).unwrap() {
+            RouterEvent::InputLabelUpdate(..) | RouterEvent::OutputLabelUpdate(..) => {}
+            RouterEvent::RouteUpdate(_, patches) if !patches.contains(&patch) => {}
+            ev => return ev,
+        }
+    })
+    .await
+    .unwrap();
+    // The client reports just the change.
+    assert_eq!(ev, RouterEvent::RouteUpdate(0, vec![patch]));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
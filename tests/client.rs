@@ -0,0 +1,117 @@
+use omnimatrix::{
+    frontend::VideohubFrontend,
+    matrix::{DummyRouter, MatrixRouter, RouterPatch},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::{net::TcpListener, time::timeout};
+use videohub::{ClientError, Label, Route, VideohubClient, VideohubEvents, VideohubMessage};
+
+/// Serve a 4x2 dummy and connect a client to it.
+async fn connect() -> (Arc<DummyRouter>, VideohubClient, VideohubEvents) {
+    let router = Arc::new(DummyRouter::with_config(1, 4, 2));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let frontend = VideohubFrontend::new(router.clone(), 0);
+    tokio::spawn(frontend.serve(listener));
+    let (client, events) = VideohubClient::connect(addr).await.unwrap();
+    (router, client, events)
+}
+
+/// Next received message that `f` picks something out of.
+async fn next_matching<T>(
+    events: &mut VideohubEvents,
+    mut f: impl FnMut(VideohubMessage) -> Option<T>,
+) -> T {
+    timeout(Duration::from_secs(1), async {
+        loop {
+            let msg = events.recv().await.expect("connection closed");
+            if let Some(v) = f(msg) {
+                return v;
+            }
+        }
+    })
+    .await
+    .expect("no matching message")
+}
+
+/// Skip the initial dump.
+async fn skip_dump(events: &mut VideohubEvents) {
+    next_matching(events, |m| (m == VideohubMessage::EndPrelude).then_some(())).await;
+}
+
+#[tokio::test]
+async fn initial_dump() {
+    let (_router, _client, mut events) = connect().await;
+    let labels = next_matching(&mut events, |m| match m {
+        VideohubMessage::InputLabels(ls) => Some(ls),
+        _ => None,
+    })
+    .await;
+    assert_eq!(labels.len(), 4);
+    assert_eq!(labels[0], Label::from((0, "Input 1")));
+    skip_dump(&mut events).await;
+}
+
+#[tokio::test]
+async fn set_route() {
+    let (router, client, mut events) = connect().await;
+    skip_dump(&mut events).await;
+
+    client.set_route(1, 3).await.unwrap();
+    assert_eq!(
+        router.get_routes(0).await.unwrap()[1],
+        RouterPatch {
+            from_input: Some(3),
+            to_output: 1
+        }
+    );
+    let routes = next_matching(&mut events, |m| match m {
+        VideohubMessage::VideoOutputRouting(rs) => Some(rs),
+        _ => None,
+    })
+    .await;
+    assert!(routes.contains(&Route::from((1, 3))));
+
+    assert_eq!(client.set_route(7, 0).await, Err(ClientError::Nak));
+}
+
+#[tokio::test]
+async fn set_labels() {
+    let (router, client, mut events) = connect().await;
+    skip_dump(&mut events).await;
+
+    client.set_input_label(2, "Camera 3").await.unwrap();
+    client.set_output_label(0, "Program").await.unwrap();
+    assert_eq!(
+        router.get_input_labels(0).await.unwrap()[2].name,
+        "Camera 3"
+    );
+    assert_eq!(
+        router.get_output_labels(0).await.unwrap()[0].name,
+        "Program"
+    );
+}
+
+#[tokio::test]
+async fn ping_and_dump() {
+    let (_router, client, mut events) = connect().await;
+    skip_dump(&mut events).await;
+
+    client.ping().await.unwrap();
+    client.request_dump().unwrap();
+    let mut seen = Vec::new();
+    while seen.len() < 4 {
+        let msg = next_matching(&mut events, Some).await;
+        match msg {
+            VideohubMessage::InputLabels(_)
+            | VideohubMessage::OutputLabels(_)
+            | VideohubMessage::VideoOutputRouting(_)
+            | VideohubMessage::VideoOutputLocks(_) => seen.push(msg),
+            _ => {}
+        }
+    }
+    assert!(matches!(seen[0], VideohubMessage::InputLabels(ref ls) if ls.len() == 4));
+    assert!(matches!(seen[1], VideohubMessage::OutputLabels(ref ls) if ls.len() == 2));
+    assert!(matches!(seen[2], VideohubMessage::VideoOutputRouting(ref rs) if rs.len() == 2));
+    assert!(matches!(seen[3], VideohubMessage::VideoOutputLocks(_)));
+}
@@ -0,0 +1,51 @@
+use omnimatrix::{
+    backend::VideohubRouter,
+    frontend::{bind_dual_stack, VideohubFrontend},
+    matrix::{DummyRouter, MatrixRouter, RouterMatrixInfo},
+};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::net::TcpListener;
+
+fn frontend() -> VideohubFrontend<DummyRouter> {
+    VideohubFrontend::new(Arc::new(DummyRouter::with_config(1, 4, 2)), 0)
+}
+
+/// Connect a backend and check it got the initial dump.
+async fn handshake(addr: SocketAddr) {
+    let router = VideohubRouter::connect(addr).await.unwrap();
+    assert_eq!(
+        router.get_matrix_info(0).await.unwrap(),
+        RouterMatrixInfo {
+            input_count: 4,
+            output_count: 2,
+            frame_count: 0,
+            name: None,
+        }
+    );
+    assert_eq!(router.get_input_labels(0).await.unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn ipv4() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(frontend().serve(listener));
+    handshake(addr).await;
+}
+
+#[tokio::test]
+async fn ipv6() {
+    let listener = TcpListener::bind("[::1]:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(frontend().serve(listener));
+    handshake(addr).await;
+}
+
+#[tokio::test]
+async fn dual_stack() {
+    let listener = bind_dual_stack("[::]:0".parse().unwrap()).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(frontend().serve(listener));
+    handshake(SocketAddr::from(([127, 0, 0, 1], port))).await;
+    handshake(format!("[::1]:{}", port).parse().unwrap()).await;
+}
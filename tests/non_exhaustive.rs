@@ -0,0 +1,7 @@
+//! Public enums that may grow new variants can't be matched exhaustively downstream.
+
+#[test]
+fn exhaustive_matches_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,18 @@
+use omnimatrix::matrix::RouterEvent::{self, *};
+
+// Every variant is covered, only the missing wildcard arm is refused.
+fn is_lifecycle(ev: &RouterEvent) -> bool {
+    match ev {
+        Connected | Disconnected => true,
+        InfoUpdate(_) | MatrixInfoUpdate(..) | InputLabelUpdate(..) | OutputLabelUpdate(..)
+        | RouteUpdate(..) | InputLabelSnapshot(..) | OutputLabelSnapshot(..)
+        | RouteSnapshot(..) | StagedRouteUpdate(..) | LevelRouteUpdate(..) | LockUpdate(..)
+        | FrameLabelUpdate(..) | FrameRouteUpdate(..) | FrameLockUpdate(..)
+        | ProcessingUnitLockUpdate(..) | SalvoRecalled(..) | SourcesChanged(_)
+        | AlarmUpdate(_) | DescriptionUpdate(..) | PortStatusUpdate(..) => false,
+    }
+}
+
+fn main() {
+    assert!(is_lifecycle(&Connected));
+}
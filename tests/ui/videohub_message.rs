@@ -0,0 +1,20 @@
+use videohub::VideohubMessage::{self, *};
+
+// Every variant is covered, only the missing wildcard arm is refused.
+fn known(msg: &VideohubMessage) -> bool {
+    match msg {
+        Preamble(_) | DeviceInfo(_) | InputLabels(_) | OutputLabels(_)
+        | MonitorOutputLabels(_) | SerialPortLabels(_) | FrameLabels(_)
+        | VideoOutputRouting(_) | VideoMonitoringOutputRouting(_) | SerialPortRouting(_)
+        | ProcessingUnitRouting(_) | FrameBufferRouting(_) | VideoOutputLocks(_)
+        | MonitoringOutputLocks(_) | SerialPortLocks(_) | ProcessingUnitLocks(_)
+        | FrameBufferLocks(_) | VideoInputStatus(_) | VideoOutputStatus(_)
+        | SerialPortStatus(_) | AlarmStatus(_) | Configuration(_) | ACK | NAK | Ping
+        | EndPrelude => true,
+        UnknownMessage(..) => false,
+    }
+}
+
+fn main() {
+    assert!(known(&Ping));
+}
@@ -0,0 +1,88 @@
+#![cfg(unix)]
+
+use omnimatrix::{
+    backend::VideohubRouter,
+    frontend::VideohubFrontend,
+    matrix::{DummyRouter, MatrixRouter, RouterLabel, RouterPatch},
+};
+use std::{os::unix::fs::PermissionsExt, sync::Arc, time::Duration};
+use tokio::time::timeout;
+
+fn label(id: u32, name: &str) -> RouterLabel {
+    RouterLabel {
+        id,
+        name: name.into(),
+    }
+}
+
+#[tokio::test]
+async fn labels_and_routes_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("videohub.sock");
+    let dummy = Arc::new(DummyRouter::with_config(1, 4, 2));
+    let frontend = VideohubFrontend::new(dummy.clone(), 0);
+    let listen_path = path.clone();
+    tokio::spawn(async move { frontend.listen_unix(&listen_path).await.unwrap() });
+    while !path.exists() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let router = VideohubRouter::connect_unix(&path).await.unwrap();
+    assert_eq!(router.get_input_labels(0).await.unwrap().len(), 4);
+
+    // Changes from the client reach the router behind the frontend...
+    let patch = RouterPatch {
+        from_input: Some(3),
+        to_output: 1,
+    };
+    router.update_routes(0, vec![patch]).await.unwrap();
+    router
+        .update_output_labels(0, vec![label(1, "Program")])
+        .await
+        .unwrap();
+    assert_eq!(dummy.get_routes(0).await.unwrap()[1], patch);
+    assert_eq!(dummy.get_output_labels(0).await.unwrap()[1].name, "Program");
+
+    // ...and changes there make it back to the client.
+    dummy
+        .update_input_labels(0, vec![label(2, "Camera 3")])
+        .await
+        .unwrap();
+    timeout(Duration::from_secs(1), async {
+        while router.get_input_labels(0).await.unwrap()[2].name != "Camera 3" {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(router.get_routes(0).await.unwrap()[1], patch);
+}
+
+#[tokio::test]
+async fn socket_permissions() {
+    let dir = tempfile::tempdir().unwrap();
+    let dummy = Arc::new(DummyRouter::with_config(1, 4, 2));
+    for (name, mode) in [("private.sock", None), ("shared.sock", Some(0o660))] {
+        let path = dir.path().join(name);
+        let frontend = VideohubFrontend::new(dummy.clone(), 0);
+        let listen_path = path.clone();
+        tokio::spawn(async move {
+            match mode {
+                Some(mode) => frontend.listen_unix_with_mode(&listen_path, mode).await,
+                None => frontend.listen_unix(&listen_path).await,
+            }
+        });
+        while !path.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let perms = std::fs::metadata(&path).unwrap().permissions();
+        assert_eq!(perms.mode() & 0o777, mode.unwrap_or(0o600));
+    }
+
+    // Other files are left alone.
+    let path = dir.path().join("config");
+    std::fs::write(&path, "keep").unwrap();
+    let frontend = VideohubFrontend::new(dummy, 0);
+    assert!(frontend.listen_unix(&path).await.is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep");
+}